@@ -1,8 +1,187 @@
-use criterion::{criterion_group, criterion_main, Criterion};
+use bytes::Bytes;
+use colander_cache::fifo::FifoCache;
+use colander_cache::hotkeys::HotKeyTracker;
+use colander_cache::lru::LruCache;
+use colander_cache::sharded::ShardedCache;
+use colander_cache::sieve::SieveCache;
+use colander_cache::traits::{CachePolicy, CachedResponse};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rand_distr::{Distribution, Zipf};
+use std::time::{Duration, Instant};
 
-fn placeholder(_c: &mut Criterion) {
-    // Benchmarks will be added after sharded wrapper is complete
+/// Distinct keys in the stream.
+const ITEMS: usize = 100_000;
+/// Requests per pass over the stream.
+const REQUESTS: usize = 100_000;
+/// Zipf exponent, about what web and CDN traces show.
+const ALPHA: f64 = 1.0;
+/// Cache size as a share of `ITEMS`, small enough that misses and evictions
+/// are part of every pass.
+const CAPACITY: usize = ITEMS / 10;
+const THREADS: &[usize] = &[1, 2, 4, 8];
+
+/// A fixed Zipfian request stream, the same for every policy and run.
+fn zipf_keys(seed: u64) -> Vec<String> {
+    let zipf = Zipf::new(ITEMS as u64, ALPHA).expect("valid Zipf parameters");
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..REQUESTS)
+        .map(|_| format!("GET:/api/items/{}", zipf.sample(&mut rng) as u64))
+        .collect()
+}
+
+fn resp() -> CachedResponse {
+    CachedResponse {
+        status: 200,
+        headers: Default::default(),
+        body: Bytes::from_static(&[b'x'; 256]),
+        inserted_at: Instant::now(),
+        ttl: Duration::from_secs(3600),
+        hits: Default::default(),
+        source: Default::default(),
+        must_revalidate: false,
+    }
+}
+
+/// Look every key up, inserting it on a miss, as the proxy does.
+fn replay<T: CachePolicy>(cache: &mut T, keys: &[String]) {
+    for key in keys {
+        if black_box(cache.get(key)).is_none() {
+            cache.insert(key.clone(), resp());
+        }
+    }
+}
+
+fn replay_sharded<T: CachePolicy>(cache: &ShardedCache<T>, keys: &[String]) {
+    for key in keys {
+        if black_box(cache.get(key)).is_none() {
+            cache.insert(key.clone(), resp());
+        }
+    }
+}
+
+/// One policy without locks: hits on a cache holding every key, inserts
+/// that each evict, and the Zipfian mix of both.
+fn single_thread<T: CachePolicy>(c: &mut Criterion, name: &str, make: fn(usize) -> T) {
+    let keys = zipf_keys(1);
+    let mut group = c.benchmark_group("single_thread");
+    group.throughput(Throughput::Elements(REQUESTS as u64));
+
+    let mut everything = make(ITEMS);
+    replay(&mut everything, &keys);
+    group.bench_function(BenchmarkId::new("get_hit", name), |b| {
+        b.iter(|| {
+            for key in &keys {
+                black_box(everything.get(key));
+            }
+        })
+    });
+
+    let mut full = make(CAPACITY);
+    replay(&mut full, &keys);
+    let fresh: Vec<String> = (0..REQUESTS).map(|i| format!("new:{i}")).collect();
+    group.bench_function(BenchmarkId::new("insert_evict", name), |b| {
+        b.iter(|| {
+            for key in &fresh {
+                full.insert(key.clone(), resp());
+            }
+        })
+    });
+
+    let mut cache = make(CAPACITY);
+    replay(&mut cache, &keys);
+    group.bench_function(BenchmarkId::new("zipf_mixed", name), |b| {
+        b.iter(|| replay(&mut cache, &keys))
+    });
+    group.finish();
 }
 
-criterion_group!(benches, placeholder);
+fn single_thread_all(c: &mut Criterion) {
+    single_thread(c, "SIEVE", SieveCache::new);
+    single_thread(c, "LRU", LruCache::new);
+    single_thread(c, "FIFO", FifoCache::new);
+}
+
+/// Every thread replays its own Zipfian stream against one `ShardedCache`.
+/// SIEVE hits only flip a bit, so its throughput should scale with threads
+/// further than LRU's, whose hits reorder the list.
+fn sharded<T: CachePolicy>(c: &mut Criterion, name: &str, make: fn(usize) -> T) {
+    let streams: Vec<Vec<String>> = (0..*THREADS.iter().max().unwrap())
+        .map(|t| zipf_keys(t as u64 + 1))
+        .collect();
+    let cache = ShardedCache::new(CAPACITY, make);
+    replay_sharded(&cache, &streams[0]);
+
+    let mut group = c.benchmark_group(format!("sharded/{name}"));
+    for &threads in THREADS {
+        group.throughput(Throughput::Elements((threads * REQUESTS) as u64));
+        group.bench_with_input(
+            BenchmarkId::new("zipf_mixed", threads),
+            &threads,
+            |b, &n| {
+                b.iter_custom(|iters| {
+                    let start = Instant::now();
+                    std::thread::scope(|scope| {
+                        for keys in &streams[..n] {
+                            let cache = &cache;
+                            scope.spawn(move || {
+                                for _ in 0..iters {
+                                    replay_sharded(cache, keys);
+                                }
+                            });
+                        }
+                    });
+                    start.elapsed()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn sharded_all(c: &mut Criterion) {
+    sharded(c, "SIEVE", SieveCache::new);
+    sharded(c, "LRU", LruCache::new);
+    sharded(c, "FIFO", FifoCache::new);
+}
+
+/// Every thread recording into one tracker, as the proxy does per request.
+fn hot_keys(c: &mut Criterion) {
+    let streams: Vec<Vec<String>> = (0..*THREADS.iter().max().unwrap())
+        .map(|t| zipf_keys(t as u64 + 1))
+        .collect();
+    let tracker = HotKeyTracker::new(100);
+
+    let mut group = c.benchmark_group("hot_keys");
+    for &threads in THREADS {
+        group.throughput(Throughput::Elements((threads * REQUESTS) as u64));
+        group.bench_with_input(BenchmarkId::new("record", threads), &threads, |b, &n| {
+            b.iter_custom(|iters| {
+                let start = Instant::now();
+                std::thread::scope(|scope| {
+                    for keys in &streams[..n] {
+                        let tracker = &tracker;
+                        scope.spawn(move || {
+                            for _ in 0..iters {
+                                for key in keys {
+                                    tracker.record(key);
+                                }
+                            }
+                        });
+                    }
+                });
+                start.elapsed()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    // Each iteration is a full pass over the stream, so fewer samples will do
+    config = Criterion::default().sample_size(20);
+    targets = single_thread_all, sharded_all, hot_keys
+}
 criterion_main!(benches);
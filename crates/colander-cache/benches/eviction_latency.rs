@@ -0,0 +1,132 @@
+//! Per-operation latency of a full `ShardedCache` at several low
+//! watermarks, as percentiles rather than criterion's means: batched
+//! eviction trades many cheap inserts for the occasional expensive one, so
+//! the tails are what change.
+//!
+//! `cargo bench -p colander-cache --bench eviction_latency`
+
+use bytes::Bytes;
+use colander_cache::fifo::FifoCache;
+use colander_cache::lru::LruCache;
+use colander_cache::sharded::ShardedCache;
+use colander_cache::sieve::SieveCache;
+use colander_cache::traits::{CachePolicy, CachedResponse};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rand_distr::{Distribution, Zipf};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+/// Distinct keys in each thread's stream.
+const ITEMS: u64 = 1_000_000;
+/// Lookups per thread per run.
+const REQUESTS: usize = 400_000;
+const ALPHA: f64 = 1.0;
+/// Small enough that a good share of lookups miss and insert.
+const CAPACITY: usize = 64 * 1024;
+const THREADS: usize = 8;
+const WATERMARKS: &[f64] = &[1.0, 0.95, 0.9];
+
+fn zipf_keys(seed: u64) -> Vec<String> {
+    let zipf = Zipf::new(ITEMS, ALPHA).expect("valid Zipf parameters");
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..REQUESTS)
+        .map(|_| format!("GET:/api/items/{}", zipf.sample(&mut rng) as u64))
+        .collect()
+}
+
+fn resp() -> CachedResponse {
+    CachedResponse {
+        status: 200,
+        headers: Default::default(),
+        body: Bytes::from_static(&[b'x'; 256]),
+        inserted_at: Instant::now(),
+        ttl: Duration::from_secs(3600),
+        hits: Default::default(),
+        source: Default::default(),
+        must_revalidate: false,
+    }
+}
+
+/// Replay `keys` the way the proxy does, timing each lookup and each
+/// insert on a miss.
+fn replay<T: CachePolicy>(cache: &ShardedCache<T>, keys: &[String]) -> (Vec<u32>, Vec<u32>) {
+    let mut gets = Vec::with_capacity(keys.len());
+    let mut inserts = Vec::new();
+    for key in keys {
+        let start = Instant::now();
+        let found = black_box(cache.get(key));
+        gets.push(start.elapsed().as_nanos() as u32);
+        if found.is_none() {
+            let start = Instant::now();
+            cache.insert(key.clone(), resp());
+            inserts.push(start.elapsed().as_nanos() as u32);
+        }
+    }
+    (gets, inserts)
+}
+
+fn percentile(sorted: &[u32], q: f64) -> u32 {
+    sorted[((sorted.len() - 1) as f64 * q) as usize]
+}
+
+fn report(what: &str, mut nanos: Vec<u32>) {
+    nanos.sort_unstable();
+    println!(
+        "  {what:<7} {:>9} {:>9} {:>9} {:>9} {:>9}",
+        percentile(&nanos, 0.5),
+        percentile(&nanos, 0.99),
+        percentile(&nanos, 0.999),
+        percentile(&nanos, 0.9999),
+        nanos.last().copied().unwrap_or(0),
+    );
+}
+
+fn run<T: CachePolicy>(name: &str, make: fn(usize) -> T, streams: &[Vec<String>]) {
+    for &watermark in WATERMARKS {
+        let cache = ShardedCache::new(CAPACITY, make).with_low_watermark(watermark);
+        // Warm up until every shard is full
+        replay(&cache, &streams[0]);
+
+        let start = Instant::now();
+        let results: Vec<(Vec<u32>, Vec<u32>)> = std::thread::scope(|scope| {
+            let handles: Vec<_> = streams
+                .iter()
+                .map(|keys| {
+                    let cache = &cache;
+                    scope.spawn(move || replay(cache, keys))
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        let elapsed = start.elapsed();
+
+        let (gets, inserts): (Vec<Vec<u32>>, Vec<Vec<u32>>) = results.into_iter().unzip();
+        let ops = gets.iter().map(Vec::len).sum::<usize>();
+        println!(
+            "{name} low_watermark={watermark}: {:.2} Mops/s, {} inserts",
+            ops as f64 / elapsed.as_secs_f64() / 1e6,
+            inserts.iter().map(Vec::len).sum::<usize>(),
+        );
+        println!(
+            "  {:<7} {:>9} {:>9} {:>9} {:>9} {:>9}",
+            "ns", "p50", "p99", "p99.9", "p99.99", "max"
+        );
+        report("get", gets.concat());
+        report("insert", inserts.concat());
+    }
+}
+
+fn main() {
+    // `cargo test --benches` runs bench targets with `--bench`-less args;
+    // there's nothing to check, so don't spend a minute measuring
+    if !std::env::args().any(|a| a == "--bench") {
+        return;
+    }
+    let streams: Vec<Vec<String>> = (0..THREADS as u64).map(|t| zipf_keys(t + 1)).collect();
+    println!("{THREADS} threads, {REQUESTS} Zipf({ALPHA}) lookups each, capacity {CAPACITY}");
+    println!();
+    run("SIEVE", SieveCache::new, &streams);
+    run("LRU", LruCache::new, &streams);
+    run("FIFO", FifoCache::new, &streams);
+}
@@ -0,0 +1,67 @@
+//! Using colander's SIEVE cache in-process, without the proxy: several
+//! threads share one cache of rendered pages.
+//!
+//! Run with `cargo run -p colander-cache --example embedded`.
+
+use colander_cache::{Cache, Policy};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+fn render(page: u64) -> String {
+    format!("<h1>Page {page}</h1>")
+}
+
+fn main() {
+    let cache = Arc::new(
+        Cache::builder()
+            .policy(Policy::Sieve)
+            .capacity(1_024)
+            .ttl(Duration::from_secs(60))
+            .build(),
+    );
+
+    let workers: Vec<_> = (0..4)
+        .map(|worker| {
+            let cache = Arc::clone(&cache);
+            thread::spawn(move || {
+                for i in 0..10_000u64 {
+                    // A few hot pages and a long tail of cold ones
+                    let page = if i % 4 == 0 {
+                        i % 50
+                    } else {
+                        (i * 7 + worker) % 5_000
+                    };
+                    let key = format!("page:{page}");
+                    if cache.get(&key).is_none() {
+                        cache.insert(key, render(page));
+                    }
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    let stats = cache.stats();
+    println!("{cache:?}");
+    println!(
+        "hits {} misses {} evictions {} hit ratio {:.1}%",
+        stats.hits,
+        stats.misses,
+        stats.evictions,
+        100.0 * stats.hits as f64 / (stats.hits + stats.misses) as f64,
+    );
+
+    let hot = cache
+        .iter()
+        .filter_map(|(key, _)| key.strip_prefix("page:")?.parse::<u64>().ok())
+        .filter(|&page| page < 50)
+        .count();
+    println!("{hot} of the 50 hot pages are cached");
+
+    cache.remove("page:0");
+    cache.insert_with_ttl("banner", "maintenance at 02:00", Duration::from_secs(5));
+    println!("banner cached: {}", cache.contains_key("banner"));
+}
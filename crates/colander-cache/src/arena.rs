@@ -1,30 +1,83 @@
-use crate::traits::CachedResponse;
-use std::sync::atomic::{AtomicBool, Ordering};
+use crate::sync::AtomicBool;
+use crate::traits::{CachedResponse, EntryInfo};
+use http::{HeaderName, HeaderValue};
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 /// Sentinel value indicating "no node" (null pointer equivalent).
 pub const NIL: u32 = u32::MAX;
 
+/// Most nodes an arena can index: every `u32` but `NIL`.
+pub const MAX_CAPACITY: usize = NIL as usize;
+
+/// Fixed cost of an entry on top of its key, headers and body: its arena
+/// slot, the shared `CachedResponse` with its reference counts, and the
+/// policy's key-to-index map entry.
+const ENTRY_OVERHEAD: usize = size_of::<Option<Node>>()
+    + size_of::<CachedResponse>()
+    + 2 * size_of::<usize>()
+    + size_of::<(String, u32)>();
+
 /// A node in the arena-allocated doubly-linked list.
 pub struct Node {
     pub key: String,
     pub value: Arc<CachedResponse>,
     pub visited: AtomicBool,
+    /// Set on the first hit and never cleared, unlike `visited`.
+    pub hit: AtomicBool,
+    /// When this node entered the cache (not the response's own age).
+    pub cached_at: Instant,
     pub prev: u32,
     pub next: u32,
+    /// Approximate heap bytes held by this entry (see `footprint`).
+    footprint: usize,
 }
 
 impl Node {
     pub fn new(key: String, value: CachedResponse) -> Self {
+        let footprint = footprint(&key, &value);
         Self {
             key,
             value: Arc::new(value),
             visited: AtomicBool::new(false),
+            hit: AtomicBool::new(false),
+            cached_at: Instant::now(),
             prev: NIL,
             next: NIL,
+            footprint,
         }
     }
 
+    /// Approximate heap bytes this entry holds: key, headers and body plus
+    /// a fixed per-entry overhead. Allocator slack and spare `Vec`/`String`
+    /// capacity aren't counted, and a body shared with other `Bytes`
+    /// handles is counted in full.
+    #[inline]
+    pub fn footprint(&self) -> usize {
+        self.footprint
+    }
+
+    /// Remember that this node has served a hit (lock-free, for eviction stats).
+    #[inline]
+    pub fn mark_hit(&self) {
+        self.hit.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether this node has served a hit since it was inserted.
+    #[inline]
+    pub fn was_hit(&self) -> bool {
+        self.hit.load(Ordering::Relaxed)
+    }
+
+    /// How long this node has been in the cache.
+    #[inline]
+    pub fn resident_for(&self) -> Duration {
+        self.cached_at.elapsed()
+    }
+
     /// Mark this node as visited (lock-free on cache hit).
     #[inline]
     pub fn mark_visited(&self) {
@@ -42,6 +95,30 @@ impl Node {
     pub fn is_visited(&self) -> bool {
         self.visited.load(Ordering::Relaxed)
     }
+
+    /// What `CachePolicy::iter` reports about this node.
+    pub fn info(&self) -> EntryInfo {
+        EntryInfo {
+            key: self.key.clone(),
+            size: self.value.body.len(),
+            ttl_remaining: self.value.ttl.saturating_sub(self.value.age()),
+            visited: self.is_visited(),
+        }
+    }
+}
+
+/// Approximate heap bytes a node for `key` and `value` would hold (see
+/// `Node::footprint`).
+pub fn footprint(key: &str, value: &CachedResponse) -> usize {
+    let headers: usize = value
+        .headers
+        .iter()
+        .map(|(name, value)| {
+            size_of::<(HeaderName, HeaderValue)>() + name.as_str().len() + value.len()
+        })
+        .sum();
+    // The key is stored twice: in the node and in the policy's index
+    ENTRY_OVERHEAD + 2 * key.len() + headers + value.body.len()
 }
 
 /// Arena-allocated doubly-linked list.
@@ -54,6 +131,8 @@ pub struct Arena {
     pub head: u32,
     pub tail: u32,
     len: usize,
+    /// Sum of the linked nodes' `footprint`s.
+    bytes: usize,
 }
 
 impl Arena {
@@ -73,9 +152,37 @@ impl Arena {
             head: NIL,
             tail: NIL,
             len: 0,
+            bytes: 0,
         }
     }
 
+    /// Number of slots (occupied + free).
+    #[inline]
+    pub fn slot_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Grow the arena to at least `capacity` slots, adding the new slots to
+    /// the free list. Existing indices stay valid. Never shrinks: callers
+    /// bound occupancy themselves, so surplus slots just sit on the free list.
+    pub fn grow(&mut self, capacity: usize) {
+        let old = self.slots.len();
+        if capacity <= old {
+            return;
+        }
+        self.slots.resize_with(capacity, || None);
+        // New slots go under the existing free entries so reclaimed low
+        // indices are still reused first
+        let mut free_list: Vec<u32> = (old as u32..capacity as u32).rev().collect();
+        free_list.append(&mut self.free_list);
+        self.free_list = free_list;
+    }
+
+    /// Drop every node and put all slots back on the free list.
+    pub fn clear(&mut self) {
+        *self = Self::new(self.slots.len());
+    }
+
     /// Number of active (occupied) nodes.
     #[inline]
     pub fn len(&self) -> usize {
@@ -87,6 +194,12 @@ impl Arena {
         self.len == 0
     }
 
+    /// Approximate heap bytes held by the linked nodes (see `Node::footprint`).
+    #[inline]
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
     /// Get a reference to the node at `index`.
     #[inline]
     pub fn get(&self, index: u32) -> Option<&Node> {
@@ -103,6 +216,7 @@ impl Arena {
     /// Returns the index of the new node, or None if no free slots.
     pub fn push_head(&mut self, node: Node) -> Option<u32> {
         let index = self.free_list.pop()?;
+        self.bytes += node.footprint;
 
         let slot = &mut self.slots[index as usize];
         *slot = Some(node);
@@ -150,6 +264,7 @@ impl Arena {
 
         self.free_list.push(index);
         self.len -= 1;
+        self.bytes -= node.footprint;
         Some(node)
     }
 
@@ -196,6 +311,95 @@ impl Arena {
         let node = self.remove(index)?;
         Some((index, node))
     }
+
+    /// Walk the list from head to tail.
+    pub fn iter(&self) -> impl Iterator<Item = &Node> {
+        std::iter::successors(self.get(self.head), |node| self.get(node.next))
+    }
+
+    /// Verify the list structure: every slot is either linked or free, links
+    /// agree in both directions, walking from head reaches tail in exactly
+    /// `len` steps, and `bytes` adds up. O(slots); for tests and soak runs.
+    pub fn check(&self) -> Result<(), String> {
+        if self.len + self.free_list.len() != self.slots.len() {
+            return Err(format!(
+                "len {} + free {} != slots {}",
+                self.len,
+                self.free_list.len(),
+                self.slots.len()
+            ));
+        }
+        let occupied = self.slots.iter().filter(|s| s.is_some()).count();
+        if occupied != self.len {
+            return Err(format!("{occupied} occupied slots but len is {}", self.len));
+        }
+
+        let mut count = 0;
+        let mut bytes = 0;
+        let mut prev = NIL;
+        let mut index = self.head;
+        while index != NIL {
+            let node = self
+                .get(index)
+                .ok_or_else(|| format!("linked index {index} is a free slot"))?;
+            bytes += node.footprint;
+            if node.prev != prev {
+                return Err(format!(
+                    "node {index} has prev {}, expected {prev}",
+                    node.prev
+                ));
+            }
+            count += 1;
+            if count > self.len {
+                return Err(format!("list is longer than len {} (cycle?)", self.len));
+            }
+            prev = index;
+            index = node.next;
+        }
+        if prev != self.tail {
+            return Err(format!("walk ended at {prev}, tail is {}", self.tail));
+        }
+        if count != self.len {
+            return Err(format!("walked {count} nodes, len is {}", self.len));
+        }
+        if bytes != self.bytes {
+            return Err(format!(
+                "linked nodes hold {bytes} bytes, arena counts {}",
+                self.bytes
+            ));
+        }
+        Ok(())
+    }
+
+    /// `check`, plus: `map` indexes exactly the linked nodes under their own
+    /// keys, and no entry was inserted in the future (which would make every
+    /// TTL computation on it wrong).
+    pub fn check_map(&self, map: &HashMap<String, u32>) -> Result<(), String> {
+        self.check()?;
+        if map.len() != self.len {
+            return Err(format!(
+                "map has {} keys, arena has {}",
+                map.len(),
+                self.len
+            ));
+        }
+        let now = Instant::now();
+        for (key, &index) in map {
+            let node = self
+                .get(index)
+                .ok_or_else(|| format!("key {key:?} maps to free slot {index}"))?;
+            if node.key != *key {
+                return Err(format!(
+                    "key {key:?} maps to slot {index} holding {:?}",
+                    node.key
+                ));
+            }
+            if node.value.inserted_at > now {
+                return Err(format!("key {key:?} was inserted in the future"));
+            }
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -203,15 +407,21 @@ mod tests {
     use super::*;
     use crate::traits::CachedResponse;
     use bytes::Bytes;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+    use std::collections::VecDeque;
     use std::time::{Duration, Instant};
 
     fn test_response() -> CachedResponse {
         CachedResponse {
             status: 200,
-            headers: vec![],
+            headers: Default::default(),
             body: Bytes::from_static(b"test"),
             inserted_at: Instant::now(),
             ttl: Duration::from_secs(60),
+            hits: Default::default(),
+            source: Default::default(),
+            must_revalidate: false,
         }
     }
 
@@ -219,6 +429,55 @@ mod tests {
         Node::new(key.to_string(), test_response())
     }
 
+    #[test]
+    fn footprint_counts_key_headers_and_body() {
+        // No key, no headers, a 4-byte body
+        let base = test_node("").footprint();
+        assert_eq!(base, ENTRY_OVERHEAD + 4);
+        // The key is counted twice, for the node and the policy's index
+        assert_eq!(test_node("abc").footprint(), base + 6);
+
+        let mut response = test_response();
+        response
+            .headers
+            .insert(http::header::ETAG, HeaderValue::from_static("\"v1\""));
+        response.body = Bytes::from(vec![0; 1000]);
+        let node = Node::new(String::new(), response);
+        let big = node.footprint();
+        assert_eq!(big, base + size_of::<(HeaderName, HeaderValue)>() + 8 + 996);
+
+        let mut arena = Arena::new(2);
+        let index = arena.push_head(node).unwrap();
+        arena.push_head(test_node("abc")).unwrap();
+        assert_eq!(arena.bytes(), big + base + 6);
+        arena.remove(index);
+        assert_eq!(arena.bytes(), base + 6);
+        arena.clear();
+        assert_eq!(arena.bytes(), 0);
+    }
+
+    #[test]
+    fn check_accepts_valid_and_rejects_broken_lists() {
+        let mut arena = Arena::new(4);
+        let mut map = HashMap::new();
+        for key in ["a", "b", "c"] {
+            map.insert(key.to_string(), arena.push_head(test_node(key)).unwrap());
+        }
+        arena.remove(map.remove("b").unwrap());
+        assert_eq!(arena.check_map(&map), Ok(()));
+
+        // Index out of step with storage
+        map.insert("ghost".into(), 3);
+        assert!(arena.check_map(&map).is_err());
+        map.remove("ghost");
+
+        // Broken back-link
+        let head = arena.head;
+        arena.get_mut(arena.tail).unwrap().prev = head;
+        arena.get_mut(head).unwrap().prev = 0;
+        assert!(arena.check().is_err());
+    }
+
     #[test]
     fn empty_arena() {
         let arena = Arena::new(10);
@@ -348,6 +607,98 @@ mod tests {
         assert_eq!(arena.len(), 2);
     }
 
+    #[test]
+    fn grow_adds_free_slots() {
+        let mut arena = Arena::new(1);
+        let a = arena.push_head(test_node("a")).unwrap();
+        assert!(arena.push_head(test_node("b")).is_none());
+
+        arena.grow(3);
+        assert_eq!(arena.slot_count(), 3);
+        let b = arena.push_head(test_node("b")).unwrap();
+        let c = arena.push_head(test_node("c")).unwrap();
+        assert!(arena.push_head(test_node("d")).is_none());
+
+        // Existing node untouched, list order intact: c -> b -> a
+        assert_eq!(arena.get(a).unwrap().key, "a");
+        assert_eq!(arena.head, c);
+        assert_eq!(arena.get(c).unwrap().next, b);
+        assert_eq!(arena.tail, a);
+    }
+
+    #[test]
+    fn grow_never_shrinks() {
+        let mut arena = Arena::new(4);
+        arena.grow(2);
+        assert_eq!(arena.slot_count(), 4);
+    }
+
+    #[derive(Clone, Debug)]
+    enum ArenaOp {
+        Push,
+        Remove(usize),
+        MoveToHead(usize),
+        PopTail,
+        Grow(usize),
+    }
+
+    fn arena_op() -> impl Strategy<Value = ArenaOp> {
+        prop_oneof![
+            3 => Just(ArenaOp::Push),
+            2 => any::<usize>().prop_map(ArenaOp::Remove),
+            2 => any::<usize>().prop_map(ArenaOp::MoveToHead),
+            1 => Just(ArenaOp::PopTail),
+            1 => (0..4usize).prop_map(ArenaOp::Grow),
+        ]
+    }
+
+    proptest! {
+        /// Any sequence of list operations leaves the links, free list and
+        /// length consistent, in the order a `VecDeque` model predicts.
+        #[test]
+        fn list_matches_model(capacity in 0..8usize, ops in vec(arena_op(), 0..200)) {
+            let mut arena = Arena::new(capacity);
+            // (index, key), head first
+            let mut model: VecDeque<(u32, String)> = VecDeque::new();
+            for (i, op) in ops.into_iter().enumerate() {
+                match op {
+                    ArenaOp::Push => {
+                        let key = i.to_string();
+                        match arena.push_head(test_node(&key)) {
+                            Some(index) => model.push_front((index, key)),
+                            None => prop_assert_eq!(model.len(), arena.slot_count()),
+                        }
+                    }
+                    ArenaOp::Remove(n) if !model.is_empty() => {
+                        let (index, key) = model.remove(n % model.len()).unwrap();
+                        prop_assert_eq!(arena.remove(index).map(|node| node.key), Some(key));
+                        prop_assert!(arena.remove(index).is_none());
+                    }
+                    ArenaOp::MoveToHead(n) if !model.is_empty() => {
+                        let entry = model.remove(n % model.len()).unwrap();
+                        arena.move_to_head(entry.0);
+                        model.push_front(entry);
+                    }
+                    ArenaOp::PopTail => {
+                        let popped = arena.pop_tail().map(|(index, node)| (index, node.key));
+                        prop_assert_eq!(popped, model.pop_back());
+                    }
+                    ArenaOp::Grow(extra) => arena.grow(arena.slot_count() + extra),
+                    ArenaOp::Remove(_) | ArenaOp::MoveToHead(_) => {}
+                }
+                prop_assert_eq!(arena.check(), Ok(()));
+                prop_assert_eq!(arena.len(), model.len());
+                prop_assert_eq!(arena.bytes(), model.len() * test_node("").footprint()
+                    + 2 * model.iter().map(|e| e.1.len()).sum::<usize>());
+                prop_assert_eq!(arena.head, model.front().map_or(NIL, |e| e.0));
+                prop_assert_eq!(arena.tail, model.back().map_or(NIL, |e| e.0));
+                let keys: Vec<&str> = arena.iter().map(|node| node.key.as_str()).collect();
+                let expected: Vec<&str> = model.iter().map(|e| e.1.as_str()).collect();
+                prop_assert_eq!(keys, expected);
+            }
+        }
+    }
+
     #[test]
     fn visited_bit_operations() {
         let node = test_node("a");
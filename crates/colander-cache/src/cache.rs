@@ -0,0 +1,565 @@
+use crate::error::CacheError;
+use crate::fifo::FifoCache;
+use crate::lru::LruCache;
+use crate::sharded::check_total_capacity;
+use crate::sharded::{Entry, ShardedCache};
+use crate::sieve::SieveCache;
+use crate::traits::{CacheStats, CachedResponse};
+use bytes::Bytes;
+use http::HeaderMap;
+use std::convert::Infallible;
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Capacity of a `Cache` built without one.
+const DEFAULT_CAPACITY: usize = 10_000;
+/// Entries fetched per shard scan while iterating.
+const ITER_PAGE: usize = 256;
+
+/// Eviction algorithm of a `Cache`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Policy {
+    /// Evicts entries not hit since the hand last passed. Hits only mark
+    /// the entry and never reorder the queue, though like every policy they
+    /// take the shard's write lock (to count the hit and drop expired
+    /// entries).
+    #[default]
+    Sieve,
+    /// Evicts the least recently used entry.
+    Lru,
+    /// Evicts the oldest entry, hit or not.
+    Fifo,
+}
+
+impl Policy {
+    pub fn name(self) -> &'static str {
+        match self {
+            Policy::Sieve => "SIEVE",
+            Policy::Lru => "LRU",
+            Policy::Fifo => "FIFO",
+        }
+    }
+}
+
+/// Settings for a `Cache`, from `Cache::builder()`.
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct CacheBuilder {
+    policy: Policy,
+    capacity: usize,
+    ttl: Option<Duration>,
+    max_memory: usize,
+    low_watermark: f64,
+}
+
+impl CacheBuilder {
+    /// Eviction algorithm. SIEVE by default.
+    pub fn policy(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Maximum number of entries, 10,000 by default. It's split evenly over
+    /// 64 shards of at least one entry each, so the effective capacity is
+    /// rounded down to a multiple of 64 (and is at least 64).
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// How long entries stay fresh unless inserted with their own TTL. By
+    /// default they never expire and only leave by eviction or removal.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Evict to keep the approximate memory footprint of keys and values
+    /// under `bytes`, on top of the entry capacity. Unbounded by default.
+    pub fn max_memory(mut self, bytes: usize) -> Self {
+        self.max_memory = bytes;
+        self
+    }
+
+    /// When a shard fills up, evict down to this share of its capacity at
+    /// once rather than one entry per insert, e.g. 0.95. Fewer, longer
+    /// evictions under the shard lock; 1.0 (one at a time) by default.
+    /// `build` clamps it to [0.5, 1.0]; `try_build` refuses a share outside
+    /// that range.
+    pub fn low_watermark(mut self, share: f64) -> Self {
+        self.low_watermark = share;
+        self
+    }
+
+    /// The cache. A capacity of 0 is rounded up to one entry per shard;
+    /// use `try_build` to have it refused instead.
+    pub fn build(self) -> Cache {
+        let (capacity, max_memory, low) = (self.capacity, self.max_memory, self.low_watermark);
+        let shards = match self.policy {
+            Policy::Sieve => Shards::Sieve(
+                ShardedCache::new(capacity, SieveCache::new)
+                    .with_max_memory(max_memory)
+                    .with_low_watermark(low),
+            ),
+            Policy::Lru => Shards::Lru(
+                ShardedCache::new(capacity, LruCache::new)
+                    .with_max_memory(max_memory)
+                    .with_low_watermark(low),
+            ),
+            Policy::Fifo => Shards::Fifo(
+                ShardedCache::new(capacity, FifoCache::new)
+                    .with_max_memory(max_memory)
+                    .with_low_watermark(low),
+            ),
+        };
+        Cache {
+            shards,
+            policy: self.policy,
+            ttl: self.ttl,
+        }
+    }
+}
+
+impl CacheBuilder {
+    /// `build`, refusing a capacity of 0 or one too large for the shards
+    /// to index.
+    pub fn try_build(self) -> Result<Cache, CacheError> {
+        check_total_capacity(self.capacity)?;
+        if !(0.5..=1.0).contains(&self.low_watermark) {
+            return Err(CacheError::InvalidLowWatermark);
+        }
+        Ok(self.build())
+    }
+}
+
+enum Shards {
+    Sieve(ShardedCache<SieveCache>),
+    Lru(ShardedCache<LruCache>),
+    Fifo(ShardedCache<FifoCache>),
+}
+
+/// Run `$body` against whichever `ShardedCache` backs `$cache`.
+macro_rules! with_shards {
+    ($cache:expr, $shards:ident => $body:expr) => {
+        match &$cache.shards {
+            Shards::Sieve($shards) => $body,
+            Shards::Lru($shards) => $body,
+            Shards::Fifo($shards) => $body,
+        }
+    };
+}
+
+/// A thread-safe cache of byte values by string key, for embedding the
+/// eviction policies without the proxy.
+///
+/// Share it between threads behind an `Arc`: every method takes `&self`,
+/// and keys are spread over 64 independently locked shards. Values are
+/// `Bytes`, so reads hand out a reference-counted view rather than a copy.
+///
+/// ```
+/// use colander_cache::{Cache, Policy};
+/// use std::time::Duration;
+///
+/// let cache = Cache::builder()
+///     .policy(Policy::Sieve)
+///     .capacity(10_000)
+///     .ttl(Duration::from_secs(60))
+///     .build();
+///
+/// cache.insert("user:1", "alice");
+/// assert_eq!(cache.get("user:1").as_deref(), Some(&b"alice"[..]));
+/// assert!(cache.remove("user:1"));
+/// assert_eq!(cache.get("user:1"), None);
+/// ```
+pub struct Cache {
+    shards: Shards,
+    policy: Policy,
+    ttl: Option<Duration>,
+}
+
+impl Cache {
+    pub fn builder() -> CacheBuilder {
+        CacheBuilder {
+            policy: Policy::default(),
+            capacity: DEFAULT_CAPACITY,
+            ttl: None,
+            max_memory: 0,
+            low_watermark: 1.0,
+        }
+    }
+
+    /// A SIEVE cache of `capacity` entries that never expire.
+    pub fn new(capacity: usize) -> Self {
+        Self::builder().capacity(capacity).build()
+    }
+
+    /// The value for `key`, unless it's missing or expired. Counts as a hit
+    /// or miss, and as a use of the entry for eviction.
+    pub fn get(&self, key: &str) -> Option<Bytes> {
+        with_shards!(self, shards => shards.get(key)).map(|entry| entry.body.clone())
+    }
+
+    /// Whether `key` holds an unexpired value. Unlike `get`, this leaves
+    /// the statistics and eviction order alone.
+    pub fn contains_key(&self, key: &str) -> bool {
+        with_shards!(self, shards => shards.peek(key)).is_some_and(|entry| !entry.is_expired())
+    }
+
+    /// Store `value` under `key` with the cache's TTL, replacing any value
+    /// already there. May evict another entry.
+    pub fn insert(&self, key: impl Into<String>, value: impl Into<Bytes>) {
+        self.insert_entry(key.into(), value.into(), self.ttl);
+    }
+
+    /// `insert`, with a TTL of its own for this entry.
+    pub fn insert_with_ttl(&self, key: impl Into<String>, value: impl Into<Bytes>, ttl: Duration) {
+        self.insert_entry(key.into(), value.into(), Some(ttl));
+    }
+
+    fn insert_entry(&self, key: String, body: Bytes, ttl: Option<Duration>) {
+        let entry = stored(body, ttl);
+        with_shards!(self, shards => shards.insert(key, entry));
+    }
+
+    /// The value for `key`, computed by `fetch` and inserted on a miss.
+    /// Callers missing the same key while `fetch` runs wait for its value
+    /// instead of computing their own.
+    pub async fn get_or_insert_with<F, Fut, V>(&self, key: &str, fetch: F) -> Bytes
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+        V: Into<Bytes>,
+    {
+        let fetch = || async { Ok::<_, Infallible>(fetch().await) };
+        match self.try_get_or_insert_with(key, fetch).await {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    /// `get_or_insert_with` for fetches that can fail. The error goes to
+    /// this caller only and nothing is inserted; callers that were waiting
+    /// on the fetch run their own.
+    pub async fn try_get_or_insert_with<F, Fut, V, E>(
+        &self,
+        key: &str,
+        fetch: F,
+    ) -> Result<Bytes, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+        V: Into<Bytes>,
+    {
+        let fetch = || async { fetch().await.map(|value| stored(value.into(), self.ttl)) };
+        let entry = with_shards!(self, shards => shards.try_get_or_insert_with(key, fetch).await)?;
+        Ok(entry.body.clone())
+    }
+
+    /// `get_or_insert_with` for synchronous code: `fetch` is a plain
+    /// closure, and callers missing a key it's computing block until it's
+    /// done.
+    pub fn get_or_insert_with_blocking<V: Into<Bytes>>(
+        &self,
+        key: &str,
+        fetch: impl FnOnce() -> V,
+    ) -> Bytes {
+        with_shards!(self, shards => match shards.entry_blocking(key) {
+            Entry::Occupied(entry) => entry.body.clone(),
+            Entry::Vacant(vacant) => vacant.insert(stored(fetch().into(), self.ttl)).body.clone(),
+        })
+    }
+
+    /// Remove `key`. Returns whether it was there.
+    pub fn remove(&self, key: &str) -> bool {
+        with_shards!(self, shards => shards.remove(key))
+    }
+
+    /// Remove every entry, returning how many there were. Statistics are
+    /// kept.
+    pub fn clear(&self) -> usize {
+        with_shards!(self, shards => shards.clear())
+    }
+
+    /// Every unexpired entry, without counting hits or touching eviction
+    /// order. Entries are read a page at a time, so ones inserted or removed
+    /// during iteration may or may not show up, but none is seen twice.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            cache: self,
+            page: Vec::new().into_iter(),
+            after: None,
+            done: false,
+        }
+    }
+
+    /// Number of entries, including expired ones not yet evicted.
+    pub fn len(&self) -> usize {
+        with_shards!(self, shards => shards.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Maximum number of entries (see `CacheBuilder::capacity`).
+    pub fn capacity(&self) -> usize {
+        with_shards!(self, shards => shards.capacity())
+    }
+
+    /// Approximate heap footprint of the cached keys and values, in bytes.
+    pub fn memory_bytes(&self) -> usize {
+        with_shards!(self, shards => shards.memory_bytes())
+    }
+
+    pub fn policy(&self) -> Policy {
+        self.policy
+    }
+
+    /// `insert`, saying why the value wasn't kept when that happens: it's
+    /// over its shard's share of `max_memory` (and then nothing is evicted
+    /// for it), or there was no room for it.
+    pub fn try_insert(
+        &self,
+        key: impl Into<String>,
+        value: impl Into<Bytes>,
+    ) -> Result<(), CacheError> {
+        let entry = stored(value.into(), self.ttl);
+        with_shards!(self, shards => shards.try_insert(key.into(), entry))
+    }
+
+    /// `try_insert`, with a TTL of its own for this entry.
+    pub fn try_insert_with_ttl(
+        &self,
+        key: impl Into<String>,
+        value: impl Into<Bytes>,
+        ttl: Duration,
+    ) -> Result<(), CacheError> {
+        let entry = stored(value.into(), Some(ttl));
+        with_shards!(self, shards => shards.try_insert(key.into(), entry))
+    }
+
+    /// TTL given to entries inserted without one, if any.
+    pub fn ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+
+    /// Hits, misses, evictions and occupancy across all shards.
+    pub fn stats(&self) -> CacheStats {
+        with_shards!(self, shards => shards.stats())
+    }
+}
+
+/// `body` as a cache entry, with no expiry if `ttl` is `None`.
+fn stored(body: Bytes, ttl: Option<Duration>) -> CachedResponse {
+    CachedResponse {
+        status: 200,
+        headers: HeaderMap::new(),
+        body,
+        inserted_at: Instant::now(),
+        ttl: ttl.unwrap_or(Duration::MAX),
+        hits: Default::default(),
+        source: Default::default(),
+        must_revalidate: false,
+    }
+}
+
+impl fmt::Debug for Cache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cache")
+            .field("policy", &self.policy)
+            .field("len", &self.len())
+            .field("capacity", &self.capacity())
+            .field("ttl", &self.ttl)
+            .finish()
+    }
+}
+
+/// Iterator over a `Cache`'s unexpired entries, from `Cache::iter`.
+pub struct Iter<'a> {
+    cache: &'a Cache,
+    page: std::vec::IntoIter<(String, Arc<CachedResponse>)>,
+    after: Option<String>,
+    done: bool,
+}
+
+impl Iterator for Iter<'_> {
+    type Item = (String, Bytes);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            for (key, entry) in self.page.by_ref() {
+                if !entry.is_expired() {
+                    return Some((key, entry.body.clone()));
+                }
+            }
+            if self.done {
+                return None;
+            }
+            let page = with_shards!(self.cache, shards => {
+                shards.scan(self.after.as_deref(), ITER_PAGE, |_| true)
+            });
+            self.done = page.next.is_none();
+            self.after = page.next;
+            self.page = page.entries.into_iter();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn every_policy_stores_and_removes() {
+        for policy in [Policy::Sieve, Policy::Lru, Policy::Fifo] {
+            let cache = Cache::builder().policy(policy).capacity(640).build();
+            assert_eq!(cache.policy(), policy);
+            assert_eq!(cache.stats().capacity, 640);
+
+            cache.insert("a", "1");
+            cache.insert(String::from("b"), Bytes::from_static(b"2"));
+            assert_eq!(cache.get("a"), Some(Bytes::from_static(b"1")));
+            assert!(cache.contains_key("b"));
+            assert_eq!(cache.len(), 2);
+
+            assert!(cache.remove("a"));
+            assert!(!cache.remove("a"));
+            assert_eq!(cache.get("a"), None);
+            let stats = cache.stats();
+            assert_eq!((stats.hits, stats.misses), (1, 1), "{}", policy.name());
+        }
+    }
+
+    #[test]
+    fn capacity_is_enforced() {
+        let cache = Cache::new(64);
+        for i in 0..1000 {
+            cache.insert(format!("key:{i}"), "v");
+        }
+        assert!(cache.len() <= cache.capacity());
+        assert!(cache.stats().evictions > 0);
+    }
+
+    #[test]
+    fn max_memory_is_enforced() {
+        let cache = Cache::builder()
+            .capacity(64_000)
+            .max_memory(256 * 1024)
+            .build();
+        for i in 0..1000 {
+            cache.insert(format!("key:{i}"), vec![0; 1024]);
+        }
+        assert!(cache.memory_bytes() <= 256 * 1024);
+        assert!(cache.memory_bytes() > 1024 * cache.len());
+        assert!(cache.len() < 256);
+    }
+
+    #[test]
+    fn entries_expire_after_their_ttl() {
+        let cache = Cache::builder().ttl(Duration::from_millis(20)).build();
+        cache.insert("short", "x");
+        cache.insert_with_ttl("long", "y", Duration::from_secs(60));
+        assert!(cache.contains_key("short"));
+
+        std::thread::sleep(Duration::from_millis(40));
+        assert_eq!(cache.get("short"), None);
+        assert!(!cache.contains_key("short"));
+        assert_eq!(cache.get("long"), Some(Bytes::from_static(b"y")));
+        assert!(Cache::new(64).ttl().is_none());
+    }
+
+    #[test]
+    fn iter_visits_each_live_entry_once() {
+        let cache = Cache::new(6400);
+        for i in 0..1000 {
+            cache.insert(format!("key:{i}"), i.to_string());
+        }
+        cache.insert_with_ttl("gone", "x", Duration::ZERO);
+        std::thread::sleep(Duration::from_millis(1));
+
+        let keys: Vec<String> = cache.iter().map(|(key, _)| key).collect();
+        let unique: HashSet<&String> = keys.iter().collect();
+        assert_eq!(keys.len(), 1000);
+        assert_eq!(unique.len(), 1000);
+        assert!(!unique.contains(&"gone".to_string()));
+        // Iterating isn't a lookup
+        assert_eq!(cache.stats().hits, 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn get_or_insert_with_computes_once() {
+        let cache = Arc::new(Cache::new(640));
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let (cache, calls) = (Arc::clone(&cache), Arc::clone(&calls));
+                tokio::spawn(async move {
+                    cache
+                        .get_or_insert_with("k", || async {
+                            calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(20)).await;
+                            "computed"
+                        })
+                        .await
+                })
+            })
+            .collect();
+        for task in tasks {
+            assert_eq!(task.await.unwrap(), "computed");
+        }
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        let failed = cache
+            .try_get_or_insert_with("missing", || async { Err::<Bytes, _>("no such row") })
+            .await;
+        assert_eq!(failed, Err("no such row"));
+        assert!(!cache.contains_key("missing"));
+    }
+
+    #[test]
+    fn get_or_insert_with_blocking_keeps_cached_values() {
+        let cache = Cache::new(640);
+        cache.insert("k", "cached");
+        assert_eq!(cache.get_or_insert_with_blocking("k", || "fresh"), "cached");
+        assert_eq!(
+            cache.get_or_insert_with_blocking("new", || "fresh"),
+            "fresh"
+        );
+        assert_eq!(cache.get("new").as_deref(), Some(&b"fresh"[..]));
+    }
+
+    #[test]
+    fn clear_keeps_stats() {
+        let cache = Cache::new(640);
+        cache.insert("a", "1");
+        cache.get("a");
+        assert_eq!(cache.clear(), 1);
+        assert!(cache.is_empty());
+        assert_eq!(cache.stats().hits, 1);
+    }
+
+    #[test]
+    fn try_build_and_try_insert() {
+        let err = Cache::builder().capacity(0).try_build().err();
+        assert_eq!(err, Some(CacheError::ZeroCapacity));
+        for share in [0.2, 1.5, f64::NAN] {
+            let err = Cache::builder().low_watermark(share).try_build().err();
+            assert_eq!(err, Some(CacheError::InvalidLowWatermark));
+        }
+
+        let cache = Cache::builder()
+            .capacity(640)
+            .max_memory(640 * 1024)
+            .try_build()
+            .unwrap();
+        assert_eq!(cache.try_insert("a", "1"), Ok(()));
+        assert_eq!(cache.get("a"), Some(Bytes::from_static(b"1")));
+        let err = cache.try_insert("big", vec![0; 64 * 1024]).unwrap_err();
+        assert!(matches!(err, CacheError::EntryTooLarge { .. }));
+        assert!(!cache.contains_key("big"));
+    }
+}
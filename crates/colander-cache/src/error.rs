@@ -0,0 +1,57 @@
+use std::fmt;
+
+/// Why a cache couldn't be built, or why an insert stored nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CacheError {
+    /// A capacity of zero entries.
+    ZeroCapacity,
+    /// More entries than the cache's shards can index.
+    CapacityTooLarge { capacity: usize, max: usize },
+    /// The entry's approximate footprint is over its shard's share of the
+    /// memory bound, so keeping it would mean evicting everything else and
+    /// then the entry itself.
+    EntryTooLarge { size: usize, max: usize },
+    /// No slot was free for the entry and nothing could be evicted to make
+    /// one.
+    Full,
+    /// The entry was evicted as soon as it went in, to keep its quota group
+    /// within its share.
+    OverQuota,
+    /// A low watermark outside [0.5, 1.0].
+    InvalidLowWatermark,
+}
+
+impl CacheError {
+    /// `Ok` for a capacity one policy instance can hold.
+    pub(crate) fn check_capacity(capacity: usize) -> Result<(), CacheError> {
+        let max = crate::arena::MAX_CAPACITY;
+        match capacity {
+            0 => Err(CacheError::ZeroCapacity),
+            capacity if capacity > max => Err(CacheError::CapacityTooLarge { capacity, max }),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheError::ZeroCapacity => f.write_str("cache capacity must be > 0"),
+            CacheError::CapacityTooLarge { capacity, max } => {
+                write!(f, "cache capacity {capacity} is over the maximum of {max}")
+            }
+            CacheError::EntryTooLarge { size, max } => write!(
+                f,
+                "entry of about {size} bytes is over its shard's {max}-byte share of max memory"
+            ),
+            CacheError::Full => f.write_str("no room for the entry and nothing to evict"),
+            CacheError::OverQuota => f.write_str("entry evicted at once to keep its quota"),
+            CacheError::InvalidLowWatermark => {
+                f.write_str("low watermark must be between 0.5 and 1.0")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CacheError {}
@@ -0,0 +1,276 @@
+use crate::arena::{Arena, Node};
+use crate::error::CacheError;
+use crate::traits::{
+    CachePolicy, CacheStats, CachedResponse, EntryInfo, EvictionStats, RemovalLog, Removed,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// FIFO with lazy promotion (FIFO-Reinsertion, equivalent to CLOCK).
+///
+/// Hits only set the visited bit, like SIEVE. At eviction time a visited
+/// tail entry is cleared and moved back to the head instead of being evicted,
+/// so popular objects get promoted only when they'd otherwise leave. Unlike
+/// SIEVE, retained objects are mixed back in with new ones.
+pub struct ClockCache {
+    arena: Arena,
+    map: HashMap<String, u32>,
+    capacity: usize,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+    evicted: EvictionStats,
+    removals: RemovalLog,
+}
+
+impl ClockCache {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "cache capacity must be > 0");
+        Self {
+            arena: Arena::new(capacity),
+            map: HashMap::with_capacity(capacity),
+            capacity,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+            evicted: EvictionStats::default(),
+            removals: RemovalLog::default(),
+        }
+    }
+
+    /// Reinsert visited tail entries until an unvisited (or expired) one
+    /// comes up, then evict it. Each reinsertion clears a bit, so this ends
+    /// within one lap.
+    fn evict_one(&mut self) {
+        loop {
+            let index = self.arena.tail;
+            let Some(node) = self.arena.get(index) else {
+                return;
+            };
+            if !node.value.is_expired() && node.clear_visited() {
+                self.arena.move_to_head(index);
+                continue;
+            }
+            let evicted = self.arena.remove(index).unwrap();
+            self.map.remove(&evicted.key);
+            self.evictions += 1;
+            self.evicted
+                .record(evicted.resident_for(), evicted.was_hit());
+            self.removals.evicted(evicted.key, evicted.value);
+            return;
+        }
+    }
+}
+
+impl CachePolicy for ClockCache {
+    fn get(&mut self, key: &str) -> Option<Arc<CachedResponse>> {
+        let Some(&index) = self.map.get(key) else {
+            self.misses += 1;
+            return None;
+        };
+        let node = self.arena.get(index).unwrap();
+        if node.value.is_expired() {
+            self.misses += 1;
+            self.map.remove(key);
+            if let Some(node) = self.arena.remove(index) {
+                self.removals.expired(node.key, node.value);
+            }
+            return None;
+        }
+        self.hits += 1;
+        node.mark_visited();
+        node.mark_hit();
+        Some(Arc::clone(&node.value))
+    }
+
+    fn peek(&self, key: &str) -> Option<Arc<CachedResponse>> {
+        let index = *self.map.get(key)?;
+        self.arena.get(index).map(|node| Arc::clone(&node.value))
+    }
+
+    fn visited(&self, key: &str) -> bool {
+        self.map
+            .get(key)
+            .and_then(|&index| self.arena.get(index))
+            .is_some_and(Node::is_visited)
+    }
+
+    fn insert(&mut self, key: String, value: CachedResponse) {
+        let _ = self.try_insert(key, value);
+    }
+
+    fn try_insert(&mut self, key: String, value: CachedResponse) -> Result<(), CacheError> {
+        if let Some(old_index) = self.map.remove(&key) {
+            self.arena.remove(old_index);
+        }
+        while self.arena.len() >= self.capacity {
+            self.evict_one();
+        }
+        let node = Node::new(key.clone(), value);
+        let index = self.arena.push_head(node).ok_or(CacheError::Full)?;
+        self.map.insert(key, index);
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &str) -> bool {
+        match self.map.remove(key) {
+            Some(index) => {
+                self.arena.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.map.keys().cloned().collect()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = EntryInfo> + '_> {
+        Box::new(self.arena.iter().map(Node::info))
+    }
+
+    fn clear(&mut self) {
+        self.arena.clear();
+        self.map.clear();
+    }
+
+    fn len(&self) -> usize {
+        self.arena.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn resize(&mut self, capacity: usize) {
+        assert!(capacity > 0, "cache capacity must be > 0");
+        self.arena.grow(capacity);
+        self.capacity = capacity;
+        while self.arena.len() > self.capacity {
+            self.evict_one();
+        }
+    }
+
+    fn record_removals(&mut self, enabled: bool) {
+        self.removals.set_enabled(enabled);
+    }
+
+    fn take_removed(&mut self) -> Vec<Removed> {
+        self.removals.take()
+    }
+
+    fn memory_bytes(&self) -> usize {
+        self.arena.bytes()
+    }
+
+    fn evict(&mut self) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        self.evict_one();
+        true
+    }
+
+    fn name(&self) -> &'static str {
+        "CLOCK"
+    }
+
+    fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+            evicted: self.evicted.clone(),
+            current_size: self.arena.len(),
+            capacity: self.capacity,
+            memory_bytes: self.arena.bytes(),
+            scan: Default::default(),
+        }
+    }
+
+    fn reset_stats(&mut self) {
+        self.hits = 0;
+        self.misses = 0;
+        self.evictions = 0;
+        self.evicted = EvictionStats::default();
+    }
+
+    fn check_invariants(&self) -> Result<(), String> {
+        self.arena.check_map(&self.map)?;
+        if self.arena.len() > self.capacity {
+            return Err(format!(
+                "{} entries over capacity {}",
+                self.arena.len(),
+                self.capacity
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use std::time::{Duration, Instant};
+
+    fn resp() -> CachedResponse {
+        CachedResponse {
+            status: 200,
+            headers: Default::default(),
+            body: Bytes::from_static(b"test"),
+            inserted_at: Instant::now(),
+            ttl: Duration::from_secs(60),
+            hits: Default::default(),
+            source: Default::default(),
+            must_revalidate: false,
+        }
+    }
+
+    #[test]
+    fn evicts_in_insertion_order_without_hits() {
+        let mut cache = ClockCache::new(2);
+        cache.insert("a".into(), resp());
+        cache.insert("b".into(), resp());
+        cache.insert("c".into(), resp());
+
+        assert!(cache.peek("a").is_none());
+        assert!(cache.peek("b").is_some());
+        assert!(cache.peek("c").is_some());
+    }
+
+    #[test]
+    fn visited_tail_is_reinserted() {
+        let mut cache = ClockCache::new(3);
+        cache.insert("a".into(), resp());
+        cache.insert("b".into(), resp());
+        cache.insert("c".into(), resp());
+        cache.get("a");
+
+        // "a" is at the tail but visited: it moves to the head, "b" goes
+        cache.insert("d".into(), resp());
+        assert!(cache.peek("a").is_some());
+        assert!(cache.peek("b").is_none());
+
+        // The promotion cleared its bit, so it's evicted on the next lap
+        cache.insert("e".into(), resp());
+        cache.insert("f".into(), resp());
+        assert!(cache.peek("a").is_none());
+        cache.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn all_visited_still_evicts() {
+        let mut cache = ClockCache::new(3);
+        for key in ["a", "b", "c"] {
+            cache.insert(key.into(), resp());
+            cache.get(key);
+        }
+        cache.insert("d".into(), resp());
+
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.stats().evictions, 1);
+        cache.check_invariants().unwrap();
+    }
+}
@@ -0,0 +1,25 @@
+//! Eviction policies for experimentation, behind the `experimental` feature.
+//!
+//! They implement the same `CachePolicy` trait as SIEVE, LRU and FIFO, so they
+//! drop into `ShardedCache`, benchmarks and simulations unchanged, but the
+//! proxy doesn't offer them and their behavior may change between releases.
+
+pub mod clock;
+pub mod s3fifo;
+pub mod sieve2;
+
+use crate::traits::CachePolicy;
+
+/// Names accepted by `build`.
+pub const POLICIES: &[&str] = &["sieve2", "clock", "s3fifo"];
+
+/// Build an experimental policy by name, for tools that pick policies at
+/// runtime. `None` for names not in `POLICIES`.
+pub fn build(policy: &str, capacity: usize) -> Option<Box<dyn CachePolicy>> {
+    Some(match policy {
+        "sieve2" => Box::new(sieve2::Sieve2Cache::new(capacity)),
+        "clock" => Box::new(clock::ClockCache::new(capacity)),
+        "s3fifo" => Box::new(s3fifo::S3FifoCache::new(capacity)),
+        _ => return None,
+    })
+}
@@ -0,0 +1,419 @@
+use crate::arena::{Arena, Node};
+use crate::error::CacheError;
+use crate::traits::{
+    CachePolicy, CacheStats, CachedResponse, EntryInfo, EvictionStats, RemovalLog, Removed,
+};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+/// Which queue an entry lives in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Queue {
+    Small,
+    Main,
+}
+
+/// S3-FIFO (SOSP '23): quick demotion plus lazy promotion.
+///
+/// New entries land in a small FIFO holding ~10% of the cache. Ones that get
+/// no hit before reaching its tail are demoted right away and only their key
+/// hash is remembered in a ghost FIFO; ones that were hit move to the main
+/// FIFO, which evicts CLOCK-style (visited entries are reinserted). Keys found
+/// in the ghost on insert skip the small queue. One-hit wonders thus cost a
+/// slot only briefly. The paper's two-bit frequency is approximated with the
+/// arena's visited bit.
+pub struct S3FifoCache {
+    small: Arena,
+    main: Arena,
+    map: HashMap<String, (Queue, u32)>,
+    /// Hashes of keys recently demoted from `small`, oldest first, with a
+    /// count per hash for membership tests (a key can be demoted twice).
+    ghost: VecDeque<u64>,
+    ghost_counts: HashMap<u64, u32>,
+    hasher: ahash::RandomState,
+    capacity: usize,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+    evicted: EvictionStats,
+    removals: RemovalLog,
+}
+
+impl S3FifoCache {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "cache capacity must be > 0");
+        Self {
+            // Either queue may briefly hold everything (e.g. while filling)
+            small: Arena::new(capacity),
+            main: Arena::new(capacity),
+            map: HashMap::with_capacity(capacity),
+            ghost: VecDeque::new(),
+            ghost_counts: HashMap::new(),
+            hasher: ahash::RandomState::with_seeds(5, 6, 7, 8),
+            capacity,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+            evicted: EvictionStats::default(),
+            removals: RemovalLog::default(),
+        }
+    }
+
+    fn small_target(&self) -> usize {
+        (self.capacity / 10).max(1)
+    }
+
+    fn arena(&self, queue: Queue) -> &Arena {
+        match queue {
+            Queue::Small => &self.small,
+            Queue::Main => &self.main,
+        }
+    }
+
+    fn arena_mut(&mut self, queue: Queue) -> &mut Arena {
+        match queue {
+            Queue::Small => &mut self.small,
+            Queue::Main => &mut self.main,
+        }
+    }
+
+    fn evict_one(&mut self) {
+        if self.small.len() >= self.small_target() || self.main.is_empty() {
+            self.evict_small();
+        } else {
+            self.evict_main();
+        }
+    }
+
+    /// Promote hit entries from the small queue's tail until one without a
+    /// hit comes up, then demote it to the ghost. Falls through to the main
+    /// queue if everything in the small queue was promoted.
+    fn evict_small(&mut self) {
+        while let Some((_, node)) = self.small.pop_tail() {
+            if !node.value.is_expired() && node.clear_visited() {
+                let key = node.key.clone();
+                if let Some(index) = self.main.push_head(node) {
+                    self.map.insert(key, (Queue::Main, index));
+                }
+                continue;
+            }
+            self.map.remove(&node.key);
+            self.evictions += 1;
+            self.evicted.record(node.resident_for(), node.was_hit());
+            self.remember(&node.key);
+            self.removals.evicted(node.key, node.value);
+            return;
+        }
+        self.evict_main();
+    }
+
+    /// Reinsert visited entries at the main queue's head until an unvisited
+    /// (or expired) one reaches the tail, then evict it.
+    fn evict_main(&mut self) {
+        loop {
+            let index = self.main.tail;
+            let Some(node) = self.main.get(index) else {
+                return;
+            };
+            if !node.value.is_expired() && node.clear_visited() {
+                self.main.move_to_head(index);
+                continue;
+            }
+            let evicted = self.main.remove(index).unwrap();
+            self.map.remove(&evicted.key);
+            self.evictions += 1;
+            self.evicted
+                .record(evicted.resident_for(), evicted.was_hit());
+            self.removals.evicted(evicted.key, evicted.value);
+            return;
+        }
+    }
+
+    /// Add a demoted key to the ghost, which remembers as many keys as the
+    /// main queue can hold.
+    fn remember(&mut self, key: &str) {
+        let hash = self.hasher.hash_one(key);
+        self.ghost.push_back(hash);
+        *self.ghost_counts.entry(hash).or_default() += 1;
+        let limit = (self.capacity - self.small_target()).max(1);
+        while self.ghost.len() > limit {
+            let Some(old) = self.ghost.pop_front() else {
+                break;
+            };
+            self.forget(old);
+        }
+    }
+
+    fn forget(&mut self, hash: u64) {
+        if let Some(count) = self.ghost_counts.get_mut(&hash) {
+            *count -= 1;
+            if *count == 0 {
+                self.ghost_counts.remove(&hash);
+            }
+        }
+    }
+
+    /// Whether `key` was recently demoted. Ghost entries stay until they
+    /// age out of the FIFO; a stale hit only costs one skipped probation.
+    fn in_ghost(&self, key: &str) -> bool {
+        self.ghost_counts.contains_key(&self.hasher.hash_one(key))
+    }
+
+    fn unlink(&mut self, queue: Queue, index: u32) {
+        self.arena_mut(queue).remove(index);
+    }
+}
+
+impl CachePolicy for S3FifoCache {
+    fn get(&mut self, key: &str) -> Option<Arc<CachedResponse>> {
+        let Some(&(queue, index)) = self.map.get(key) else {
+            self.misses += 1;
+            return None;
+        };
+        if self.arena(queue).get(index).unwrap().value.is_expired() {
+            self.misses += 1;
+            self.map.remove(key);
+            if let Some(node) = self.arena_mut(queue).remove(index) {
+                self.removals.expired(node.key, node.value);
+            }
+            return None;
+        }
+        self.hits += 1;
+        let node = self.arena(queue).get(index).unwrap();
+        node.mark_visited();
+        node.mark_hit();
+        Some(Arc::clone(&node.value))
+    }
+
+    fn peek(&self, key: &str) -> Option<Arc<CachedResponse>> {
+        let &(queue, index) = self.map.get(key)?;
+        self.arena(queue)
+            .get(index)
+            .map(|node| Arc::clone(&node.value))
+    }
+
+    fn visited(&self, key: &str) -> bool {
+        self.map
+            .get(key)
+            .and_then(|&(queue, index)| self.arena(queue).get(index))
+            .is_some_and(Node::is_visited)
+    }
+
+    fn insert(&mut self, key: String, value: CachedResponse) {
+        let _ = self.try_insert(key, value);
+    }
+
+    fn try_insert(&mut self, key: String, value: CachedResponse) -> Result<(), CacheError> {
+        if let Some((queue, index)) = self.map.remove(&key) {
+            self.unlink(queue, index);
+        }
+        while self.len() >= self.capacity {
+            self.evict_one();
+        }
+        let queue = if self.in_ghost(&key) {
+            Queue::Main
+        } else {
+            Queue::Small
+        };
+        let node = Node::new(key.clone(), value);
+        let index = self
+            .arena_mut(queue)
+            .push_head(node)
+            .ok_or(CacheError::Full)?;
+        self.map.insert(key, (queue, index));
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &str) -> bool {
+        match self.map.remove(key) {
+            Some((queue, index)) => {
+                self.unlink(queue, index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.map.keys().cloned().collect()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = EntryInfo> + '_> {
+        Box::new(self.small.iter().chain(self.main.iter()).map(Node::info))
+    }
+
+    fn clear(&mut self) {
+        self.small.clear();
+        self.main.clear();
+        self.map.clear();
+        self.ghost.clear();
+        self.ghost_counts.clear();
+    }
+
+    fn len(&self) -> usize {
+        self.small.len() + self.main.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn resize(&mut self, capacity: usize) {
+        assert!(capacity > 0, "cache capacity must be > 0");
+        self.small.grow(capacity);
+        self.main.grow(capacity);
+        self.capacity = capacity;
+        while self.len() > self.capacity {
+            self.evict_one();
+        }
+    }
+
+    fn record_removals(&mut self, enabled: bool) {
+        self.removals.set_enabled(enabled);
+    }
+
+    fn take_removed(&mut self) -> Vec<Removed> {
+        self.removals.take()
+    }
+
+    fn memory_bytes(&self) -> usize {
+        self.small.bytes() + self.main.bytes()
+    }
+
+    fn evict(&mut self) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        self.evict_one();
+        true
+    }
+
+    fn name(&self) -> &'static str {
+        "S3-FIFO"
+    }
+
+    fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+            evicted: self.evicted.clone(),
+            current_size: self.len(),
+            capacity: self.capacity,
+            memory_bytes: self.small.bytes() + self.main.bytes(),
+            scan: Default::default(),
+        }
+    }
+
+    fn reset_stats(&mut self) {
+        self.hits = 0;
+        self.misses = 0;
+        self.evictions = 0;
+        self.evicted = EvictionStats::default();
+    }
+
+    fn check_invariants(&self) -> Result<(), String> {
+        self.small.check()?;
+        self.main.check()?;
+        if self.map.len() != self.len() {
+            return Err(format!(
+                "map has {} keys, queues hold {}",
+                self.map.len(),
+                self.len()
+            ));
+        }
+        for (key, &(queue, index)) in &self.map {
+            match self.arena(queue).get(index) {
+                Some(node) if node.key == *key => {}
+                Some(node) => {
+                    return Err(format!(
+                        "key {key:?} maps to {queue:?} slot {index} holding {:?}",
+                        node.key
+                    ))
+                }
+                None => return Err(format!("key {key:?} maps to free {queue:?} slot {index}")),
+            }
+        }
+        if self.len() > self.capacity {
+            return Err(format!(
+                "{} entries over capacity {}",
+                self.len(),
+                self.capacity
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use std::time::{Duration, Instant};
+
+    fn resp() -> CachedResponse {
+        CachedResponse {
+            status: 200,
+            headers: Default::default(),
+            body: Bytes::from_static(b"test"),
+            inserted_at: Instant::now(),
+            ttl: Duration::from_secs(60),
+            hits: Default::default(),
+            source: Default::default(),
+            must_revalidate: false,
+        }
+    }
+
+    #[test]
+    fn one_hit_wonders_are_demoted_first() {
+        let mut cache = S3FifoCache::new(10);
+        for i in 0..10 {
+            cache.insert(format!("k{i}"), resp());
+        }
+        // Hit everything but k0, then stream new keys through
+        for i in 1..10 {
+            cache.get(&format!("k{i}"));
+        }
+        for i in 10..15 {
+            cache.insert(format!("k{i}"), resp());
+        }
+
+        assert!(cache.peek("k0").is_none());
+        for i in 1..10 {
+            assert!(cache.peek(&format!("k{i}")).is_some(), "k{i} was hit");
+        }
+        cache.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn ghost_hit_goes_straight_to_main() {
+        let mut cache = S3FifoCache::new(10);
+        for i in 0..11 {
+            cache.insert(format!("k{i}"), resp());
+        }
+        // k0 was demoted; reinserting it skips the small queue
+        assert!(cache.peek("k0").is_none());
+        cache.insert("k0".into(), resp());
+        assert_eq!(cache.map["k0"].0, Queue::Main);
+        cache.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn stays_consistent_under_churn() {
+        let mut cache = S3FifoCache::new(20);
+        for round in 0..500u32 {
+            let key = format!("k{}", (round * 7919) % 60);
+            if cache.get(&key).is_none() {
+                cache.insert(key, resp());
+            }
+            if round % 11 == 0 {
+                cache.remove(&format!("k{}", round % 60));
+            }
+            if round == 250 {
+                cache.resize(10);
+            }
+            cache.check_invariants().unwrap();
+        }
+        assert!(cache.len() <= 10);
+    }
+}
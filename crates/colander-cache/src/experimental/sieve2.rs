@@ -0,0 +1,341 @@
+use crate::arena::{Arena, Node, NIL};
+use crate::error::CacheError;
+use crate::traits::{
+    CachePolicy, CacheStats, CachedResponse, EntryInfo, EvictionStats, RemovalLog, Removed,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// SIEVE with a second, leading hand (after two-handed CLOCK).
+///
+/// The eviction hand works exactly like SIEVE's. The leading hand runs
+/// `spread` entries ahead of it toward the head, clearing visited bits as it
+/// goes. An entry is therefore only retained if it was hit in the interval
+/// between the two hands passing it, rather than at any time since the last
+/// full lap. A smaller spread demotes one-off bursts faster; one less than
+/// the number of entries behaves like plain SIEVE, and zero degrades to FIFO.
+pub struct Sieve2Cache {
+    arena: Arena,
+    map: HashMap<String, u32>,
+    /// Eviction hand.
+    hand: u32,
+    /// Leading (clearing) hand; NIL until the first eviction places it.
+    lead: u32,
+    spread: usize,
+    capacity: usize,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+    evicted: EvictionStats,
+    removals: RemovalLog,
+}
+
+impl Sieve2Cache {
+    /// Hands half the cache apart.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_spread(capacity, capacity / 2)
+    }
+
+    /// Leading hand `spread` entries ahead of the eviction hand (capped at
+    /// one less than the number of entries, so it never laps it).
+    pub fn with_spread(capacity: usize, spread: usize) -> Self {
+        assert!(capacity > 0, "cache capacity must be > 0");
+        Self {
+            arena: Arena::new(capacity),
+            map: HashMap::with_capacity(capacity),
+            hand: NIL,
+            lead: NIL,
+            spread,
+            capacity,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+            evicted: EvictionStats::default(),
+            removals: RemovalLog::default(),
+        }
+    }
+
+    /// The entry after `index` in hand order (tail toward head, wrapping).
+    fn ahead(&self, index: u32) -> u32 {
+        match self.arena.get(index).map(|node| node.prev) {
+            Some(prev) if prev != NIL => prev,
+            _ => self.arena.tail,
+        }
+    }
+
+    /// Unlink `index`, stepping any hand that points at it back to its
+    /// predecessor first.
+    fn detach(&mut self, index: u32) -> Node {
+        let prev = self.arena.get(index).unwrap().prev;
+        if self.hand == index {
+            self.hand = prev;
+        }
+        if self.lead == index {
+            self.lead = prev;
+        }
+        self.arena.remove(index).unwrap()
+    }
+
+    fn evict_one(&mut self) {
+        if self.arena.is_empty() {
+            return;
+        }
+        if self.hand == NIL {
+            self.hand = self.arena.tail;
+        }
+        if self.lead == NIL {
+            self.lead = self.hand;
+            for _ in 0..self.spread.min(self.arena.len() - 1) {
+                self.lead = self.ahead(self.lead);
+            }
+        }
+
+        loop {
+            // Both hands move one entry per step, keeping their distance
+            if let Some(node) = self.arena.get(self.lead) {
+                node.clear_visited();
+            }
+            self.lead = self.ahead(self.lead);
+
+            let index = self.hand;
+            let node = self.arena.get(index).unwrap();
+            if !node.value.is_expired() && node.clear_visited() {
+                self.hand = self.ahead(index);
+                continue;
+            }
+            let evicted = self.detach(index);
+            self.map.remove(&evicted.key);
+            self.evictions += 1;
+            self.evicted
+                .record(evicted.resident_for(), evicted.was_hit());
+            self.removals.evicted(evicted.key, evicted.value);
+            if self.hand == NIL {
+                self.hand = self.arena.tail;
+            }
+            return;
+        }
+    }
+}
+
+impl CachePolicy for Sieve2Cache {
+    fn get(&mut self, key: &str) -> Option<Arc<CachedResponse>> {
+        let Some(&index) = self.map.get(key) else {
+            self.misses += 1;
+            return None;
+        };
+        if self.arena.get(index).unwrap().value.is_expired() {
+            self.misses += 1;
+            self.map.remove(key);
+            let node = self.detach(index);
+            self.removals.expired(node.key, node.value);
+            return None;
+        }
+        self.hits += 1;
+        let node = self.arena.get(index).unwrap();
+        node.mark_visited();
+        node.mark_hit();
+        Some(Arc::clone(&node.value))
+    }
+
+    fn peek(&self, key: &str) -> Option<Arc<CachedResponse>> {
+        let index = *self.map.get(key)?;
+        self.arena.get(index).map(|node| Arc::clone(&node.value))
+    }
+
+    fn visited(&self, key: &str) -> bool {
+        self.map
+            .get(key)
+            .and_then(|&index| self.arena.get(index))
+            .is_some_and(Node::is_visited)
+    }
+
+    fn insert(&mut self, key: String, value: CachedResponse) {
+        let _ = self.try_insert(key, value);
+    }
+
+    fn try_insert(&mut self, key: String, value: CachedResponse) -> Result<(), CacheError> {
+        if let Some(old_index) = self.map.remove(&key) {
+            self.detach(old_index);
+        }
+        while self.arena.len() >= self.capacity {
+            self.evict_one();
+        }
+        let node = Node::new(key.clone(), value);
+        let index = self.arena.push_head(node).ok_or(CacheError::Full)?;
+        self.map.insert(key, index);
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &str) -> bool {
+        match self.map.remove(key) {
+            Some(index) => {
+                self.detach(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.map.keys().cloned().collect()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = EntryInfo> + '_> {
+        Box::new(self.arena.iter().map(Node::info))
+    }
+
+    fn clear(&mut self) {
+        self.arena.clear();
+        self.map.clear();
+        self.hand = NIL;
+        self.lead = NIL;
+    }
+
+    fn len(&self) -> usize {
+        self.arena.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn resize(&mut self, capacity: usize) {
+        assert!(capacity > 0, "cache capacity must be > 0");
+        self.arena.grow(capacity);
+        self.capacity = capacity;
+        while self.arena.len() > self.capacity {
+            self.evict_one();
+        }
+    }
+
+    fn record_removals(&mut self, enabled: bool) {
+        self.removals.set_enabled(enabled);
+    }
+
+    fn take_removed(&mut self) -> Vec<Removed> {
+        self.removals.take()
+    }
+
+    fn memory_bytes(&self) -> usize {
+        self.arena.bytes()
+    }
+
+    fn evict(&mut self) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        self.evict_one();
+        true
+    }
+
+    fn name(&self) -> &'static str {
+        "SIEVE2"
+    }
+
+    fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+            evicted: self.evicted.clone(),
+            current_size: self.arena.len(),
+            capacity: self.capacity,
+            memory_bytes: self.arena.bytes(),
+            scan: Default::default(),
+        }
+    }
+
+    fn reset_stats(&mut self) {
+        self.hits = 0;
+        self.misses = 0;
+        self.evictions = 0;
+        self.evicted = EvictionStats::default();
+    }
+
+    fn check_invariants(&self) -> Result<(), String> {
+        self.arena.check_map(&self.map)?;
+        if self.arena.len() > self.capacity {
+            return Err(format!(
+                "{} entries over capacity {}",
+                self.arena.len(),
+                self.capacity
+            ));
+        }
+        for (name, index) in [("hand", self.hand), ("lead", self.lead)] {
+            if index != NIL && self.arena.get(index).is_none() {
+                return Err(format!("{name} points at free slot {index}"));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use std::time::{Duration, Instant};
+
+    fn resp() -> CachedResponse {
+        CachedResponse {
+            status: 200,
+            headers: Default::default(),
+            body: Bytes::from_static(b"test"),
+            inserted_at: Instant::now(),
+            ttl: Duration::from_secs(60),
+            hits: Default::default(),
+            source: Default::default(),
+            must_revalidate: false,
+        }
+    }
+
+    #[test]
+    fn retains_entries_hit_after_the_lead_hand() {
+        let mut cache = Sieve2Cache::with_spread(4, 1);
+        for key in ["a", "b", "c", "d"] {
+            cache.insert(key.into(), resp());
+        }
+        cache.get("a");
+        cache.get("b");
+
+        // The lead hand starts one ahead of the tail ("b") and clears it
+        // before the eviction hand reaches it; "a" keeps its bit
+        cache.insert("e".into(), resp());
+        assert!(cache.peek("a").is_some());
+        assert!(cache.peek("b").is_none());
+        cache.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn widest_spread_behaves_like_sieve() {
+        let mut cache = Sieve2Cache::with_spread(3, 10);
+        cache.insert("a".into(), resp());
+        cache.insert("b".into(), resp());
+        cache.insert("c".into(), resp());
+        cache.get("a");
+
+        cache.insert("d".into(), resp());
+        assert!(cache.peek("a").is_some());
+        assert!(cache.peek("b").is_none());
+        assert!(cache.peek("c").is_some());
+    }
+
+    #[test]
+    fn hands_survive_removals_and_resizes() {
+        let mut cache = Sieve2Cache::new(16);
+        for round in 0..200 {
+            let key = format!("k{}", round % 40);
+            if cache.get(&key).is_none() {
+                cache.insert(key.clone(), resp());
+            }
+            if round % 7 == 0 {
+                cache.remove(&format!("k{}", (round + 3) % 40));
+            }
+            if round == 100 {
+                cache.resize(8);
+            }
+            cache.check_invariants().unwrap();
+        }
+        assert!(cache.len() <= 8);
+    }
+}
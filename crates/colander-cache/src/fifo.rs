@@ -1,5 +1,8 @@
 use crate::arena::{Arena, Node};
-use crate::traits::{CachePolicy, CacheStats, CachedResponse};
+use crate::error::CacheError;
+use crate::traits::{
+    CachePolicy, CacheStats, CachedResponse, EntryInfo, EvictionStats, RemovalLog, Removed,
+};
 use std::collections::HashMap;
 use std::sync::Arc;
 
@@ -14,19 +17,43 @@ pub struct FifoCache {
     hits: u64,
     misses: u64,
     evictions: u64,
+    evicted: EvictionStats,
+    removals: RemovalLog,
 }
 
 impl FifoCache {
+    /// # Panics
+    ///
+    /// If `capacity` is 0 or over `arena::MAX_CAPACITY`; see `try_new`.
     pub fn new(capacity: usize) -> Self {
-        assert!(capacity > 0, "cache capacity must be > 0");
-        Self {
+        Self::try_new(capacity).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    pub fn try_new(capacity: usize) -> Result<Self, CacheError> {
+        CacheError::check_capacity(capacity)?;
+        Ok(Self {
             arena: Arena::new(capacity),
             map: HashMap::with_capacity(capacity),
             capacity,
             hits: 0,
             misses: 0,
             evictions: 0,
-        }
+            evicted: EvictionStats::default(),
+            removals: RemovalLog::default(),
+        })
+    }
+
+    /// Evict the oldest entry, at the tail. Returns `false` if empty.
+    fn evict_one(&mut self) -> bool {
+        let Some((_, evicted)) = self.arena.pop_tail() else {
+            return false;
+        };
+        self.map.remove(&evicted.key);
+        self.evictions += 1;
+        self.evicted
+            .record(evicted.resident_for(), evicted.was_hit());
+        self.removals.evicted(evicted.key, evicted.value);
+        true
     }
 }
 
@@ -39,11 +66,14 @@ impl CachePolicy for FifoCache {
                 self.misses += 1;
                 // Lazy expiration: remove expired entry
                 self.map.remove(key);
-                self.arena.remove(index);
+                if let Some(node) = self.arena.remove(index) {
+                    self.removals.expired(node.key, node.value);
+                }
                 return None;
             }
             self.hits += 1;
             // FIFO: no promotion on hit, just return the value
+            node.mark_hit();
             Some(Arc::clone(&node.value))
         } else {
             self.misses += 1;
@@ -51,7 +81,16 @@ impl CachePolicy for FifoCache {
         }
     }
 
+    fn peek(&self, key: &str) -> Option<Arc<CachedResponse>> {
+        let index = *self.map.get(key)?;
+        self.arena.get(index).map(|node| Arc::clone(&node.value))
+    }
+
     fn insert(&mut self, key: String, value: CachedResponse) {
+        let _ = self.try_insert(key, value);
+    }
+
+    fn try_insert(&mut self, key: String, value: CachedResponse) -> Result<(), CacheError> {
         // If key already exists, remove old entry first
         if let Some(&old_index) = self.map.get(&key) {
             self.arena.remove(old_index);
@@ -59,19 +98,12 @@ impl CachePolicy for FifoCache {
         }
 
         // Evict from tail if at capacity
-        while self.arena.len() >= self.capacity {
-            if let Some((_, evicted)) = self.arena.pop_tail() {
-                self.map.remove(&evicted.key);
-                self.evictions += 1;
-            } else {
-                break;
-            }
-        }
+        while self.arena.len() >= self.capacity && self.evict_one() {}
 
         let node = Node::new(key.clone(), value);
-        if let Some(index) = self.arena.push_head(node) {
-            self.map.insert(key, index);
-        }
+        let index = self.arena.push_head(node).ok_or(CacheError::Full)?;
+        self.map.insert(key, index);
+        Ok(())
     }
 
     fn remove(&mut self, key: &str) -> bool {
@@ -83,6 +115,19 @@ impl CachePolicy for FifoCache {
         }
     }
 
+    fn keys(&self) -> Vec<String> {
+        self.map.keys().cloned().collect()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = EntryInfo> + '_> {
+        Box::new(self.arena.iter().map(Node::info))
+    }
+
+    fn clear(&mut self) {
+        self.arena.clear();
+        self.map.clear();
+    }
+
     fn len(&self) -> usize {
         self.arena.len()
     }
@@ -91,6 +136,29 @@ impl CachePolicy for FifoCache {
         self.capacity
     }
 
+    fn resize(&mut self, capacity: usize) {
+        assert!(capacity > 0, "cache capacity must be > 0");
+        self.arena.grow(capacity);
+        self.capacity = capacity;
+        while self.arena.len() > self.capacity && self.evict_one() {}
+    }
+
+    fn record_removals(&mut self, enabled: bool) {
+        self.removals.set_enabled(enabled);
+    }
+
+    fn take_removed(&mut self) -> Vec<Removed> {
+        self.removals.take()
+    }
+
+    fn memory_bytes(&self) -> usize {
+        self.arena.bytes()
+    }
+
+    fn evict(&mut self) -> bool {
+        self.evict_one()
+    }
+
     fn name(&self) -> &'static str {
         "FIFO"
     }
@@ -100,9 +168,31 @@ impl CachePolicy for FifoCache {
             hits: self.hits,
             misses: self.misses,
             evictions: self.evictions,
+            evicted: self.evicted.clone(),
             current_size: self.arena.len(),
             capacity: self.capacity,
+            memory_bytes: self.arena.bytes(),
+            scan: Default::default(),
+        }
+    }
+
+    fn reset_stats(&mut self) {
+        self.hits = 0;
+        self.misses = 0;
+        self.evictions = 0;
+        self.evicted = EvictionStats::default();
+    }
+
+    fn check_invariants(&self) -> Result<(), String> {
+        self.arena.check_map(&self.map)?;
+        if self.arena.len() > self.capacity {
+            return Err(format!(
+                "{} entries over capacity {}",
+                self.arena.len(),
+                self.capacity
+            ));
         }
+        Ok(())
     }
 }
 
@@ -115,10 +205,13 @@ mod tests {
     fn resp(ttl_secs: u64) -> CachedResponse {
         CachedResponse {
             status: 200,
-            headers: vec![],
+            headers: Default::default(),
             body: Bytes::from_static(b"test"),
             inserted_at: Instant::now(),
             ttl: Duration::from_secs(ttl_secs),
+            hits: Default::default(),
+            source: Default::default(),
+            must_revalidate: false,
         }
     }
 
@@ -178,10 +271,13 @@ mod tests {
             "expired".into(),
             CachedResponse {
                 status: 200,
-                headers: vec![],
+                headers: Default::default(),
                 body: Bytes::from_static(b"old"),
                 inserted_at: Instant::now() - Duration::from_secs(120),
                 ttl: Duration::from_secs(60),
+                hits: Default::default(),
+                source: Default::default(),
+                must_revalidate: false,
             },
         );
 
@@ -0,0 +1,274 @@
+use crate::sharded::hasher;
+use crate::traits::CachedResponse;
+use parking_lot::{Condvar, Mutex};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+
+/// Number of independently locked key maps.
+const NUM_SHARDS: usize = 64;
+const SHARD_MASK: u64 = (NUM_SHARDS as u64) - 1;
+
+/// Keys being fetched after a miss, so that when several callers miss the
+/// same key at once only the first fetches it and the rest wait for its
+/// value (request coalescing, or "single flight").
+///
+/// Nothing here touches a cache: callers look the key up, `claim` it on a
+/// miss, and the leader inserts the value wherever it belongs before
+/// handing it to the followers with `FillGuard::complete`. A leader that
+/// drops its guard instead releases the followers empty-handed, to fetch
+/// for themselves; they don't queue up behind one another, so a key whose
+/// fetches keep failing is no slower than it would be without coalescing.
+pub struct Fills {
+    shards: Box<[Mutex<FillMap>]>,
+}
+
+type FillMap = HashMap<String, Arc<Fill>>;
+
+/// What a caller that missed `key` should do, from `Fills::claim`.
+pub enum Claim<'a> {
+    /// Nobody else is fetching the key: fetch it, then `complete` the guard.
+    Leader(FillGuard<'a>),
+    /// Another caller is: await (or `wait_blocking` on) its value.
+    Follower(FillWaiter),
+}
+
+/// One fetch in progress.
+#[derive(Default)]
+struct Fill {
+    state: Mutex<FillState>,
+    /// Signalled alongside the wakers, for `wait_blocking`.
+    done: Condvar,
+}
+
+#[derive(Default)]
+struct FillState {
+    outcome: Outcome,
+    wakers: Vec<Waker>,
+}
+
+#[derive(Default)]
+enum Outcome {
+    #[default]
+    Pending,
+    Filled(Arc<CachedResponse>),
+    Abandoned,
+}
+
+impl Fills {
+    pub fn new() -> Self {
+        Self {
+            shards: (0..NUM_SHARDS).map(|_| Mutex::default()).collect(),
+        }
+    }
+
+    /// Become the caller fetching `key`, or a follower of whoever already is.
+    pub fn claim(&self, key: &str) -> Claim<'_> {
+        let mut fills = self.shard(key).lock();
+        if let Some(fill) = fills.get(key) {
+            return Claim::Follower(FillWaiter {
+                fill: Arc::clone(fill),
+            });
+        }
+        let fill = Arc::new(Fill::default());
+        fills.insert(key.to_string(), Arc::clone(&fill));
+        Claim::Leader(FillGuard {
+            fills: self,
+            key: key.to_string(),
+            fill,
+        })
+    }
+
+    /// Number of keys being fetched.
+    pub fn in_flight(&self) -> usize {
+        self.shards.iter().map(|shard| shard.lock().len()).sum()
+    }
+
+    fn shard(&self, key: &str) -> &Mutex<FillMap> {
+        &self.shards[(hasher().hash_one(key) & SHARD_MASK) as usize]
+    }
+}
+
+impl Default for Fills {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The claim on a key of the caller fetching it. Dropping it without
+/// `complete` releases the followers to fetch for themselves.
+pub struct FillGuard<'a> {
+    fills: &'a Fills,
+    key: String,
+    fill: Arc<Fill>,
+}
+
+impl FillGuard<'_> {
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Hand `value` to every follower. Insert it into the cache first, so
+    /// callers arriving once the claim is gone find it there.
+    pub fn complete(self, value: Arc<CachedResponse>) {
+        self.finish(Outcome::Filled(value));
+    }
+
+    fn finish(&self, outcome: Outcome) {
+        {
+            let mut fills = self.fills.shard(&self.key).lock();
+            // A later leader may have claimed the key since we finished
+            if fills
+                .get(&self.key)
+                .is_some_and(|fill| Arc::ptr_eq(fill, &self.fill))
+            {
+                fills.remove(&self.key);
+            }
+        }
+        let wakers = {
+            let mut state = self.fill.state.lock();
+            if !matches!(state.outcome, Outcome::Pending) {
+                return;
+            }
+            state.outcome = outcome;
+            std::mem::take(&mut state.wakers)
+        };
+        self.fill.done.notify_all();
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+impl Drop for FillGuard<'_> {
+    fn drop(&mut self) {
+        self.finish(Outcome::Abandoned);
+    }
+}
+
+/// A follower's handle on another caller's fetch. Resolves to the fetched
+/// value, or `None` if the leader gave up without one.
+pub struct FillWaiter {
+    fill: Arc<Fill>,
+}
+
+impl FillWaiter {
+    /// Block the thread until the leader is done.
+    pub fn wait_blocking(self) -> Option<Arc<CachedResponse>> {
+        let mut state = self.fill.state.lock();
+        while matches!(state.outcome, Outcome::Pending) {
+            self.fill.done.wait(&mut state);
+        }
+        match &state.outcome {
+            Outcome::Filled(value) => Some(Arc::clone(value)),
+            _ => None,
+        }
+    }
+}
+
+impl Future for FillWaiter {
+    type Output = Option<Arc<CachedResponse>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.fill.state.lock();
+        match &state.outcome {
+            Outcome::Pending => {
+                if !state.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                    state.wakers.push(cx.waker().clone());
+                }
+                Poll::Pending
+            }
+            Outcome::Filled(value) => Poll::Ready(Some(Arc::clone(value))),
+            Outcome::Abandoned => Poll::Ready(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn resp() -> Arc<CachedResponse> {
+        Arc::new(CachedResponse {
+            status: 200,
+            headers: Default::default(),
+            body: bytes::Bytes::from_static(b"filled"),
+            inserted_at: Instant::now(),
+            ttl: Duration::from_secs(60),
+            hits: Default::default(),
+            source: Default::default(),
+            must_revalidate: false,
+        })
+    }
+
+    #[test]
+    fn second_claim_follows_the_first() {
+        let fills = Fills::new();
+        let Claim::Leader(guard) = fills.claim("k") else {
+            panic!("first claim should lead");
+        };
+        let Claim::Follower(waiter) = fills.claim("k") else {
+            panic!("second claim should follow");
+        };
+        assert!(matches!(fills.claim("other"), Claim::Leader(_)));
+        assert_eq!(fills.in_flight(), 1);
+
+        guard.complete(resp());
+        assert_eq!(waiter.wait_blocking().unwrap().body, "filled");
+        assert_eq!(fills.in_flight(), 0);
+        assert!(matches!(fills.claim("k"), Claim::Leader(_)));
+    }
+
+    #[test]
+    fn dropped_guard_releases_followers_empty_handed() {
+        let fills = Fills::new();
+        let guard = fills.claim("k");
+        let Claim::Follower(waiter) = fills.claim("k") else {
+            panic!("second claim should follow");
+        };
+        drop(guard);
+        assert!(waiter.wait_blocking().is_none());
+        assert_eq!(fills.in_flight(), 0);
+    }
+
+    #[test]
+    fn blocked_followers_wake_on_complete() {
+        let fills = Fills::new();
+        let Claim::Leader(guard) = fills.claim("k") else {
+            panic!("first claim should lead");
+        };
+        let followers: Vec<_> = (0..4)
+            .map(|_| {
+                let Claim::Follower(waiter) = fills.claim("k") else {
+                    panic!("later claims should follow");
+                };
+                std::thread::spawn(move || waiter.wait_blocking())
+            })
+            .collect();
+        std::thread::sleep(Duration::from_millis(10));
+        guard.complete(resp());
+        for follower in followers {
+            assert!(follower.join().unwrap().is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn awaiting_followers_wake_on_complete() {
+        let fills = Fills::new();
+        let Claim::Leader(guard) = fills.claim("k") else {
+            panic!("first claim should lead");
+        };
+        let Claim::Follower(waiter) = fills.claim("k") else {
+            panic!("second claim should follow");
+        };
+        let task = tokio::spawn(waiter);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!task.is_finished());
+
+        guard.complete(resp());
+        assert_eq!(task.await.unwrap().unwrap().body, "filled");
+    }
+}
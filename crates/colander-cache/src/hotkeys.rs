@@ -0,0 +1,303 @@
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+const DEFAULT_WIDTH: usize = 4096;
+const DEFAULT_DEPTH: usize = 4;
+
+/// Approximate top-K frequency tracker (count-min sketch + bounded candidate set).
+///
+/// The sketch is a `depth × width` grid of atomic counters, so recording an
+/// access is lock-free: hash once, bump one counter per row. Tracked keys'
+/// hashes are mirrored in a small atomic table, so their accesses stop there
+/// too; their counts are read back from the sketch by `top`. Only untracked
+/// keys whose estimated count beats the smallest tracked one take the
+/// candidate lock, which once the hot keys are in is a small fraction of
+/// accesses.
+///
+/// Counts are halved every `10 × width` records (TinyLFU-style aging) so the
+/// report follows shifts in popularity instead of freezing on early winners.
+pub struct HotKeyTracker {
+    counters: Vec<AtomicU32>,
+    width: usize,
+    depth: usize,
+    k: usize,
+    /// Tracked keys and their hashes.
+    candidates: Mutex<HashMap<String, u64>>,
+    /// The hashes in `candidates`, open-addressed with 0 for empty slots
+    /// and at most a quarter full (see `publish`).
+    tracked: Box<[AtomicU64]>,
+    /// Smallest estimate in `candidates` once full (0 while filling).
+    admit_threshold: AtomicU64,
+    records: AtomicU64,
+    aging_period: u64,
+}
+
+impl HotKeyTracker {
+    /// Track the `k` most frequent keys with default sketch dimensions.
+    pub fn new(k: usize) -> Self {
+        Self::with_dimensions(k, DEFAULT_WIDTH, DEFAULT_DEPTH)
+    }
+
+    pub fn with_dimensions(k: usize, width: usize, depth: usize) -> Self {
+        assert!(k > 0, "top-k size must be > 0");
+        assert!(width > 0 && depth > 0, "sketch dimensions must be > 0");
+        Self {
+            counters: (0..width * depth).map(|_| AtomicU32::new(0)).collect(),
+            width,
+            depth,
+            k,
+            candidates: Mutex::new(HashMap::with_capacity(k + 1)),
+            tracked: (0..(4 * k).next_power_of_two())
+                .map(|_| AtomicU64::new(0))
+                .collect(),
+            admit_threshold: AtomicU64::new(0),
+            records: AtomicU64::new(0),
+            aging_period: (width as u64) * 10,
+        }
+    }
+
+    /// Number of keys reported by `top`.
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// Record one access to `key`.
+    pub fn record(&self, key: &str) {
+        let hash = hash(key);
+        let mut estimate = u32::MAX;
+        for row in 0..self.depth {
+            let idx = self.slot(row, hash);
+            let prev = self.counters[idx].fetch_add(1, Ordering::Relaxed);
+            estimate = estimate.min(prev.saturating_add(1));
+        }
+        let estimate = estimate as u64;
+
+        if estimate > self.admit_threshold.load(Ordering::Relaxed) && !self.is_tracked(hash) {
+            self.offer(key, hash, estimate);
+        }
+
+        let records = self.records.fetch_add(1, Ordering::Relaxed) + 1;
+        if records.is_multiple_of(self.aging_period) {
+            self.age();
+        }
+    }
+
+    /// Estimated access count for `key` (never underestimates before aging).
+    pub fn estimate(&self, key: &str) -> u64 {
+        self.estimate_hash(hash(key))
+    }
+
+    /// The `n` hottest tracked keys, most frequent first.
+    pub fn top(&self, n: usize) -> Vec<(String, u64)> {
+        let mut keys: Vec<(String, u64)> = self
+            .candidates
+            .lock()
+            .iter()
+            .map(|(k, &hash)| (k.clone(), self.estimate_hash(hash)))
+            .collect();
+        keys.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        keys.truncate(n);
+        keys
+    }
+
+    /// Forget all counts and candidates.
+    pub fn clear(&self) {
+        let mut candidates = self.candidates.lock();
+        for c in &self.counters {
+            c.store(0, Ordering::Relaxed);
+        }
+        candidates.clear();
+        self.publish(&candidates);
+    }
+
+    fn offer(&self, key: &str, hash: u64, estimate: u64) {
+        let mut candidates = self.candidates.lock();
+        if candidates.contains_key(key) {
+            // Tracked since we checked
+            return;
+        }
+        if candidates.len() >= self.k {
+            // Full: replace the coldest candidate if we beat it
+            let coldest = candidates
+                .iter()
+                .map(|(k, &h)| (k, self.estimate_hash(h)))
+                .min_by_key(|&(_, count)| count);
+            let coldest = match coldest {
+                Some((k, count)) if count < estimate => k.clone(),
+                _ => return,
+            };
+            candidates.remove(&coldest);
+        }
+        candidates.insert(key.to_string(), hash);
+        self.publish(&candidates);
+    }
+
+    /// Halve every counter so old popularity decays, and drop the
+    /// candidates that decayed to nothing.
+    fn age(&self) {
+        let mut candidates = self.candidates.lock();
+        for c in &self.counters {
+            // Racing increments may be lost here; the sketch is approximate anyway
+            let v = c.load(Ordering::Relaxed);
+            c.store(v / 2, Ordering::Relaxed);
+        }
+        candidates.retain(|_, &mut hash| self.estimate_hash(hash) > 0);
+        self.publish(&candidates);
+    }
+
+    /// Mirror `candidates` into `tracked` and reset the admission threshold.
+    /// Lookups racing the rebuild may miss a tracked key, and then just take
+    /// the lock to find it tracked.
+    fn publish(&self, candidates: &HashMap<String, u64>) {
+        for slot in self.tracked.iter() {
+            slot.store(0, Ordering::Relaxed);
+        }
+        let mask = self.tracked.len() - 1;
+        for &hash in candidates.values() {
+            let mut i = hash as usize & mask;
+            while self.tracked[i].load(Ordering::Relaxed) != 0 {
+                i = (i + 1) & mask;
+            }
+            self.tracked[i].store(hash, Ordering::Relaxed);
+        }
+
+        let threshold = if candidates.len() < self.k {
+            0
+        } else {
+            candidates
+                .values()
+                .map(|&hash| self.estimate_hash(hash))
+                .min()
+                .unwrap_or(0)
+        };
+        self.admit_threshold.store(threshold, Ordering::Relaxed);
+    }
+
+    fn is_tracked(&self, hash: u64) -> bool {
+        let mask = self.tracked.len() - 1;
+        let mut i = hash as usize & mask;
+        loop {
+            match self.tracked[i].load(Ordering::Relaxed) {
+                0 => return false,
+                h if h == hash => return true,
+                _ => i = (i + 1) & mask,
+            }
+        }
+    }
+
+    fn estimate_hash(&self, hash: u64) -> u64 {
+        (0..self.depth)
+            .map(|row| self.counters[self.slot(row, hash)].load(Ordering::Relaxed))
+            .min()
+            .unwrap_or(0) as u64
+    }
+
+    #[inline]
+    fn slot(&self, row: usize, hash: u64) -> usize {
+        // Kirsch–Mitzenmacher double hashing: one hash, `depth` independent-enough
+        // rows. h2 is forced odd so rows never collapse onto the same column.
+        let (h1, h2) = (hash, (hash >> 32) | 1);
+        let h = h1.wrapping_add((row as u64).wrapping_mul(h2));
+        row * self.width + (h % self.width as u64) as usize
+    }
+}
+
+/// `key`'s hash, never 0 (the empty slot of `tracked`).
+fn hash(key: &str) -> u64 {
+    ahash::RandomState::with_seeds(5, 6, 7, 8)
+        .hash_one(key)
+        .max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_most_frequent_first() {
+        let tracker = HotKeyTracker::new(3);
+        for _ in 0..50 {
+            tracker.record("hot");
+        }
+        for _ in 0..20 {
+            tracker.record("warm");
+        }
+        for _ in 0..5 {
+            tracker.record("cool");
+        }
+
+        let top = tracker.top(3);
+        let keys: Vec<&str> = top.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(keys, vec!["hot", "warm", "cool"]);
+        assert_eq!(top[0].1, 50);
+    }
+
+    #[test]
+    fn tracked_keys_report_their_current_count() {
+        let tracker = HotKeyTracker::new(2);
+        tracker.record("a");
+        tracker.record("b");
+        // Both tracked: later accesses skip the candidate set entirely
+        for _ in 0..30 {
+            tracker.record("b");
+        }
+        assert_eq!(
+            tracker.top(2),
+            vec![("b".to_string(), 31), ("a".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn bounded_to_k_entries() {
+        let tracker = HotKeyTracker::new(2);
+        for i in 0..100 {
+            tracker.record(&format!("key-{i}"));
+        }
+        assert!(tracker.top(10).len() <= 2);
+    }
+
+    #[test]
+    fn hot_key_displaces_cold_candidates() {
+        let tracker = HotKeyTracker::new(2);
+        tracker.record("a");
+        tracker.record("b");
+        for _ in 0..10 {
+            tracker.record("c");
+        }
+
+        let top = tracker.top(2);
+        assert_eq!(top[0].0, "c");
+    }
+
+    #[test]
+    fn estimate_never_underestimates() {
+        let tracker = HotKeyTracker::with_dimensions(4, 64, 4);
+        for i in 0..500 {
+            tracker.record(&format!("noise-{i}"));
+        }
+        for _ in 0..30 {
+            tracker.record("target");
+        }
+        assert!(tracker.estimate("target") >= 30);
+    }
+
+    #[test]
+    fn aging_halves_counts() {
+        // width 8 → aging every 80 records
+        let tracker = HotKeyTracker::with_dimensions(2, 8, 2);
+        for _ in 0..80 {
+            tracker.record("a");
+        }
+        assert_eq!(tracker.top(1)[0].1, 40);
+    }
+
+    #[test]
+    fn clear_resets() {
+        let tracker = HotKeyTracker::new(4);
+        tracker.record("a");
+        tracker.clear();
+        assert!(tracker.top(4).is_empty());
+        assert_eq!(tracker.estimate("a"), 0);
+    }
+}
@@ -0,0 +1,128 @@
+use std::fmt::Write;
+
+/// Turns some request-like input into a cache key.
+///
+/// Builders write into a caller-supplied buffer so hot paths can reuse one
+/// allocation across lookups; `key` is the convenience owned form. The proxy's
+/// HTTP keys are built through this trait too, so embedders can reproduce
+/// them or swap in their own scheme.
+pub trait KeyBuilder<T: ?Sized>: Send + Sync {
+    /// Append the key for `input` to `out`.
+    fn write_key(&self, input: &T, out: &mut String);
+
+    /// The key for `input` as a new `String`.
+    fn key(&self, input: &T) -> String {
+        let mut out = String::new();
+        self.write_key(input, &mut out);
+        out
+    }
+}
+
+/// Uses the input string verbatim.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RawKey;
+
+impl KeyBuilder<str> for RawKey {
+    fn write_key(&self, input: &str, out: &mut String) {
+        out.push_str(input);
+    }
+}
+
+/// Joins parts with a separator, e.g. tenant + path → `"acme:/users/1"`.
+#[derive(Debug, Clone, Copy)]
+pub struct CompositeKey {
+    separator: char,
+}
+
+impl CompositeKey {
+    pub fn new(separator: char) -> Self {
+        Self { separator }
+    }
+}
+
+impl Default for CompositeKey {
+    /// `:`, the separator the proxy uses between method and URI.
+    fn default() -> Self {
+        Self::new(':')
+    }
+}
+
+impl<P: AsRef<str>> KeyBuilder<[P]> for CompositeKey {
+    fn write_key(&self, parts: &[P], out: &mut String) {
+        for (i, part) in parts.iter().enumerate() {
+            if i > 0 {
+                out.push(self.separator);
+            }
+            out.push_str(part.as_ref());
+        }
+    }
+}
+
+/// Replaces the key built by `B` with a fixed-length digest (32 hex chars of
+/// 128-bit FNV-1a), for inputs like long URLs that would bloat the index.
+///
+/// The digest is stable across processes and platforms. It is not
+/// cryptographic: don't use it where callers can pick inputs to collide
+/// with someone else's entries.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HashedKey<B>(pub B);
+
+impl<T: ?Sized, B: KeyBuilder<T>> KeyBuilder<T> for HashedKey<B> {
+    fn write_key(&self, input: &T, out: &mut String) {
+        let digest = fnv1a_128(self.0.key(input).as_bytes());
+        let _ = write!(out, "{digest:032x}"); // writing to a String can't fail
+    }
+}
+
+fn fnv1a_128(bytes: &[u8]) -> u128 {
+    const OFFSET: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013B;
+    bytes.iter().fold(OFFSET, |hash, &b| {
+        (hash ^ u128::from(b)).wrapping_mul(PRIME)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_key_is_verbatim() {
+        assert_eq!(RawKey.key("user:42"), "user:42");
+    }
+
+    #[test]
+    fn composite_joins_parts() {
+        let tenant_path = CompositeKey::default();
+        assert_eq!(tenant_path.key(&["acme", "/users/1"]), "acme:/users/1");
+        assert_eq!(CompositeKey::new('|').key(&["a", "b", "c"]), "a|b|c");
+        assert_eq!(tenant_path.key(&["solo"]), "solo");
+    }
+
+    #[test]
+    fn write_key_appends() {
+        let mut buf = String::from("prefix/");
+        RawKey.write_key("k", &mut buf);
+        assert_eq!(buf, "prefix/k");
+    }
+
+    #[test]
+    fn hashed_key_is_fixed_length_and_stable() {
+        let hashed = HashedKey(RawKey);
+        let a = hashed.key("/a/very/long/path?with=query");
+        assert_eq!(a.len(), 32);
+        assert_eq!(a, hashed.key("/a/very/long/path?with=query"));
+        assert_ne!(a, hashed.key("/a/very/long/path?with=querz"));
+        // Known FNV-1a 128 value for the empty input is the offset basis
+        assert_eq!(hashed.key(""), "6c62272e07bb014262b821756295c58d");
+    }
+
+    #[test]
+    fn hashed_wraps_composite() {
+        let hashed = HashedKey(CompositeKey::default());
+        assert_eq!(
+            hashed.key(&["acme", "/x"]),
+            HashedKey(RawKey).key("acme:/x")
+        );
+    }
+}
@@ -1,6 +1,44 @@
+//! Cache eviction policies (SIEVE, LRU, FIFO) behind a sharded, thread-safe
+//! front, as used by the colander proxy.
+//!
+//! To embed a cache in an application, use [`Cache`]:
+//!
+//! ```
+//! use colander_cache::{Cache, Policy};
+//! use std::time::Duration;
+//!
+//! let cache = Cache::builder()
+//!     .policy(Policy::Sieve)
+//!     .capacity(10_000)
+//!     .ttl(Duration::from_secs(300))
+//!     .build();
+//!
+//! cache.insert("greeting", "hello");
+//! assert_eq!(cache.get("greeting").as_deref(), Some(&b"hello"[..]));
+//! ```
+//!
+//! [`Cache`], [`CacheBuilder`], [`Policy`], [`Iter`], [`CacheStats`] and
+//! [`CacheError`] are the stable API and follow semver. The modules below
+//! them are the building blocks the proxy uses directly (`ShardedCache`,
+//! the per-policy caches, `CachedResponse`); they're public for that reason
+//! and may change in any release.
+
+mod cache;
+mod error;
+mod sync;
+
 pub mod arena;
+#[cfg(feature = "experimental")]
+pub mod experimental;
 pub mod fifo;
+pub mod fill;
+pub mod hotkeys;
+pub mod keys;
 pub mod lru;
 pub mod sharded;
 pub mod sieve;
 pub mod traits;
+
+pub use cache::{Cache, CacheBuilder, Iter, Policy};
+pub use error::CacheError;
+pub use traits::CacheStats;
@@ -1,5 +1,8 @@
 use crate::arena::{Arena, Node};
-use crate::traits::{CachePolicy, CacheStats, CachedResponse};
+use crate::error::CacheError;
+use crate::traits::{
+    CachePolicy, CacheStats, CachedResponse, EntryInfo, EvictionStats, RemovalLog, Removed,
+};
 use std::collections::HashMap;
 use std::sync::Arc;
 
@@ -17,19 +20,43 @@ pub struct LruCache {
     hits: u64,
     misses: u64,
     evictions: u64,
+    evicted: EvictionStats,
+    removals: RemovalLog,
 }
 
 impl LruCache {
+    /// # Panics
+    ///
+    /// If `capacity` is 0 or over `arena::MAX_CAPACITY`; see `try_new`.
     pub fn new(capacity: usize) -> Self {
-        assert!(capacity > 0, "cache capacity must be > 0");
-        Self {
+        Self::try_new(capacity).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    pub fn try_new(capacity: usize) -> Result<Self, CacheError> {
+        CacheError::check_capacity(capacity)?;
+        Ok(Self {
             arena: Arena::new(capacity),
             map: HashMap::with_capacity(capacity),
             capacity,
             hits: 0,
             misses: 0,
             evictions: 0,
-        }
+            evicted: EvictionStats::default(),
+            removals: RemovalLog::default(),
+        })
+    }
+
+    /// Evict the least recently used entry, at the tail. Returns `false` if empty.
+    fn evict_one(&mut self) -> bool {
+        let Some((_, evicted)) = self.arena.pop_tail() else {
+            return false;
+        };
+        self.map.remove(&evicted.key);
+        self.evictions += 1;
+        self.evicted
+            .record(evicted.resident_for(), evicted.was_hit());
+        self.removals.evicted(evicted.key, evicted.value);
+        true
     }
 }
 
@@ -41,13 +68,16 @@ impl CachePolicy for LruCache {
             if node.value.is_expired() {
                 self.misses += 1;
                 self.map.remove(key);
-                self.arena.remove(index);
+                if let Some(node) = self.arena.remove(index) {
+                    self.removals.expired(node.key, node.value);
+                }
                 return None;
             }
             self.hits += 1;
             // LRU: promote to head on every access (this requires a write lock)
             self.arena.move_to_head(index);
             let node = self.arena.get(index).unwrap();
+            node.mark_hit();
             Some(Arc::clone(&node.value))
         } else {
             self.misses += 1;
@@ -55,7 +85,16 @@ impl CachePolicy for LruCache {
         }
     }
 
+    fn peek(&self, key: &str) -> Option<Arc<CachedResponse>> {
+        let index = *self.map.get(key)?;
+        self.arena.get(index).map(|node| Arc::clone(&node.value))
+    }
+
     fn insert(&mut self, key: String, value: CachedResponse) {
+        let _ = self.try_insert(key, value);
+    }
+
+    fn try_insert(&mut self, key: String, value: CachedResponse) -> Result<(), CacheError> {
         // If key already exists, remove old entry first
         if let Some(&old_index) = self.map.get(&key) {
             self.arena.remove(old_index);
@@ -63,19 +102,12 @@ impl CachePolicy for LruCache {
         }
 
         // Evict LRU (tail) if at capacity
-        while self.arena.len() >= self.capacity {
-            if let Some((_, evicted)) = self.arena.pop_tail() {
-                self.map.remove(&evicted.key);
-                self.evictions += 1;
-            } else {
-                break;
-            }
-        }
+        while self.arena.len() >= self.capacity && self.evict_one() {}
 
         let node = Node::new(key.clone(), value);
-        if let Some(index) = self.arena.push_head(node) {
-            self.map.insert(key, index);
-        }
+        let index = self.arena.push_head(node).ok_or(CacheError::Full)?;
+        self.map.insert(key, index);
+        Ok(())
     }
 
     fn remove(&mut self, key: &str) -> bool {
@@ -87,6 +119,19 @@ impl CachePolicy for LruCache {
         }
     }
 
+    fn keys(&self) -> Vec<String> {
+        self.map.keys().cloned().collect()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = EntryInfo> + '_> {
+        Box::new(self.arena.iter().map(Node::info))
+    }
+
+    fn clear(&mut self) {
+        self.arena.clear();
+        self.map.clear();
+    }
+
     fn len(&self) -> usize {
         self.arena.len()
     }
@@ -95,6 +140,29 @@ impl CachePolicy for LruCache {
         self.capacity
     }
 
+    fn resize(&mut self, capacity: usize) {
+        assert!(capacity > 0, "cache capacity must be > 0");
+        self.arena.grow(capacity);
+        self.capacity = capacity;
+        while self.arena.len() > self.capacity && self.evict_one() {}
+    }
+
+    fn record_removals(&mut self, enabled: bool) {
+        self.removals.set_enabled(enabled);
+    }
+
+    fn take_removed(&mut self) -> Vec<Removed> {
+        self.removals.take()
+    }
+
+    fn memory_bytes(&self) -> usize {
+        self.arena.bytes()
+    }
+
+    fn evict(&mut self) -> bool {
+        self.evict_one()
+    }
+
     fn name(&self) -> &'static str {
         "LRU"
     }
@@ -104,10 +172,32 @@ impl CachePolicy for LruCache {
             hits: self.hits,
             misses: self.misses,
             evictions: self.evictions,
+            evicted: self.evicted.clone(),
             current_size: self.arena.len(),
             capacity: self.capacity,
+            memory_bytes: self.arena.bytes(),
+            scan: Default::default(),
         }
     }
+
+    fn reset_stats(&mut self) {
+        self.hits = 0;
+        self.misses = 0;
+        self.evictions = 0;
+        self.evicted = EvictionStats::default();
+    }
+
+    fn check_invariants(&self) -> Result<(), String> {
+        self.arena.check_map(&self.map)?;
+        if self.arena.len() > self.capacity {
+            return Err(format!(
+                "{} entries over capacity {}",
+                self.arena.len(),
+                self.capacity
+            ));
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -119,10 +209,13 @@ mod tests {
     fn resp(ttl_secs: u64) -> CachedResponse {
         CachedResponse {
             status: 200,
-            headers: vec![],
+            headers: Default::default(),
             body: Bytes::from_static(b"test"),
             inserted_at: Instant::now(),
             ttl: Duration::from_secs(ttl_secs),
+            hits: Default::default(),
+            source: Default::default(),
+            must_revalidate: false,
         }
     }
 
@@ -189,16 +282,52 @@ mod tests {
             "expired".into(),
             CachedResponse {
                 status: 200,
-                headers: vec![],
+                headers: Default::default(),
                 body: Bytes::from_static(b"old"),
                 inserted_at: Instant::now() - Duration::from_secs(120),
                 ttl: Duration::from_secs(60),
+                hits: Default::default(),
+                source: Default::default(),
+                must_revalidate: false,
             },
         );
 
         assert!(cache.get("expired").is_none());
     }
 
+    #[test]
+    fn resize_shrink_evicts_least_recent() {
+        let mut cache = LruCache::new(3);
+        cache.insert("a".into(), resp(60));
+        cache.insert("b".into(), resp(60));
+        cache.insert("c".into(), resp(60));
+        cache.get("a");
+
+        cache.resize(2);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("c").is_some());
+        assert!(cache.get("b").is_none());
+    }
+
+    #[test]
+    fn eviction_stats_count_never_hit_entries() {
+        let mut cache = LruCache::new(2);
+        cache.insert("a".into(), resp(60));
+        cache.get("a");
+        cache.insert("b".into(), resp(60));
+        cache.insert("c".into(), resp(60)); // evicts "a", which was hit
+        cache.insert("d".into(), resp(60)); // evicts "b", which wasn't
+
+        let evicted = cache.stats().evicted;
+        assert_eq!(evicted.count, 2);
+        assert_eq!(evicted.never_hit, 1);
+        assert_eq!(evicted.one_hit_wonder_ratio(), 0.5);
+
+        cache.reset_stats();
+        assert_eq!(cache.stats().evicted.count, 0);
+    }
+
     #[test]
     fn stats_tracking() {
         let mut cache = LruCache::new(2);
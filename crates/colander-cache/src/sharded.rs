@@ -1,26 +1,271 @@
-use crate::traits::{CachePolicy, CacheStats, CachedResponse};
-use parking_lot::RwLock;
+use crate::arena::{footprint, MAX_CAPACITY};
+use crate::error::CacheError;
+use crate::fill::{Claim, FillGuard, FillWaiter, Fills};
+use crate::sync::{AtomicU64, AtomicUsize, Mutex, RwLock};
+use crate::traits::{
+    CacheListener, CachePolicy, CacheStats, CachedResponse, EntryInfo, RemovalCause, Removed,
+};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
 /// Number of shards. Must be a power of two for fast modulo via bitmask.
+#[cfg(not(feature = "loom"))]
 const NUM_SHARDS: usize = 64;
+/// Few enough shards for loom to model them all.
+#[cfg(feature = "loom")]
+const NUM_SHARDS: usize = 4;
 const SHARD_MASK: u64 = (NUM_SHARDS as u64) - 1;
 
+/// `Ok` for a total capacity `ShardedCache::new` can give each shard
+/// without rounding it up.
+pub(crate) fn check_total_capacity(capacity: usize) -> Result<(), CacheError> {
+    let max = (MAX_CAPACITY + 1).saturating_mul(NUM_SHARDS) - 1;
+    match capacity {
+        0 => Err(CacheError::ZeroCapacity),
+        capacity if capacity > max => Err(CacheError::CapacityTooLarge { capacity, max }),
+        _ => Ok(()),
+    }
+}
+
+/// Keeps each keyspace partition (a tenant, a route, ...) on its own small
+/// group of shards instead of spreading it over all 64.
+///
+/// A key's partition is its `segment`-th field when split on `separator`,
+/// up to any `?`: with `/` and segment 1, `GET:/images/a.png` belongs to
+/// `images`, and so does `GET:/images?page=2`. The
+/// partition's name picks the first shard of a run of `shards` consecutive
+/// ones (wrapping), and the key's own hash picks within the run. Keys without
+/// that segment (or with it empty) are hashed over all shards as usual.
+///
+/// Runs of different partitions may overlap; operations on one partition
+/// then touch, but never remove, the other's entries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Partitioning {
+    separator: char,
+    segment: usize,
+    shards: usize,
+}
+
+impl Partitioning {
+    /// `shards` is clamped to 1..=64.
+    pub fn new(separator: char, segment: usize, shards: usize) -> Self {
+        Self {
+            separator,
+            segment,
+            shards: shards.clamp(1, NUM_SHARDS),
+        }
+    }
+
+    /// The partition `key` belongs to, if it has one.
+    pub fn partition_of<'a>(&self, key: &'a str) -> Option<&'a str> {
+        let field = key.split(self.separator).nth(self.segment)?;
+        let partition = field.split('?').next().unwrap_or(field);
+        (!partition.is_empty()).then_some(partition)
+    }
+
+    /// Shards per partition.
+    pub fn shards(&self) -> usize {
+        self.shards
+    }
+
+    /// Indices of the shards holding `partition`'s entries.
+    fn shard_run(&self, partition: &str) -> impl Iterator<Item = usize> {
+        let first = (hasher().hash_one(partition) & SHARD_MASK) as usize;
+        (0..self.shards).map(move |i| (first + i) & SHARD_MASK as usize)
+    }
+}
+
+/// One partition receiving far more than its share of lookups (see
+/// `ShardedCache::partition_skew`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartitionSkew {
+    pub partition: String,
+    /// Fraction of all lookups that went to the partition's shards.
+    pub share: f64,
+    /// The fraction its shards would get if load were even.
+    pub expected: f64,
+}
+
+/// The quota a key counts against (see `ShardedCache::with_quotas`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quota {
+    /// Keys naming the same group share one quota.
+    pub group: String,
+    /// The largest share of each shard's capacity the group may fill, in
+    /// (0, 1].
+    pub max_share: f64,
+}
+
+/// Decides which keys have a quota (see `ShardedCache::with_quotas`).
+pub trait KeyQuotas: Send + Sync {
+    /// The quota `key` counts against, if any. Called on the insert path
+    /// for every key that isn't already cached, so keep it quick; the answer
+    /// is kept until the entry leaves.
+    fn quota(&self, key: &str) -> Option<Quota>;
+}
+
+/// One quota group's entries, and how many of them went to keep it within
+/// its quota (see `ShardedCache::quota_usage`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QuotaUsage {
+    pub entries: usize,
+    pub evictions: u64,
+}
+
+/// A key looked up with `ShardedCache::entry`.
+pub enum Entry<'a, T: CachePolicy> {
+    /// The key's unexpired value, cached or just fetched by another caller.
+    Occupied(Arc<CachedResponse>),
+    /// The key is missing and this caller should fetch it.
+    Vacant(VacantEntry<'a, T>),
+}
+
+/// A missing key the caller has claimed, so concurrent `entry` calls for it
+/// wait for `insert`. Dropping it unfilled lets them fetch for themselves.
+pub struct VacantEntry<'a, T: CachePolicy> {
+    cache: &'a ShardedCache<T>,
+    key: String,
+    /// `None` when an earlier claim was abandoned: every caller that was
+    /// waiting on it fetches on its own.
+    fill: Option<FillGuard<'a>>,
+}
+
+impl<T: CachePolicy> VacantEntry<'_, T> {
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Insert `value` and hand it to the callers waiting on this key.
+    pub fn insert(self, value: CachedResponse) -> Arc<CachedResponse> {
+        let stored = self.cache.insert_and_get(self.key, value);
+        if let Some(fill) = self.fill {
+            fill.complete(Arc::clone(&stored));
+        }
+        stored
+    }
+}
+
+/// One page of entries from `ShardedCache::scan`.
+#[derive(Debug, Clone)]
+pub struct ScanPage {
+    pub entries: Vec<(String, Arc<CachedResponse>)>,
+    /// Pass as `after` to fetch the next page; `None` on the last one.
+    pub next: Option<String>,
+}
+
+/// Candidates `ShardedCache::quota_victim` looks at, oldest first.
+const QUOTA_SAMPLE: usize = 16;
+
+/// One shard's quota groups, and which group each of its keys with a quota
+/// belongs to. A key's group is resolved once, when it's inserted, so
+/// removals and evictions never call `KeyQuotas::quota`.
+#[derive(Default)]
+struct QuotaIndex {
+    groups: HashMap<Arc<str>, QuotaGroup>,
+    /// Key → its group and insertion number.
+    members: HashMap<String, (Arc<str>, u64)>,
+    next_seq: u64,
+}
+
+struct QuotaGroup {
+    max_share: f64,
+    usage: QuotaUsage,
+    /// Members by insertion number, oldest first. Entries whose number no
+    /// longer matches `members` (removed or since replaced) are stale and
+    /// skipped; they're dropped from the front as it's reached, and all at
+    /// once when they outnumber the members.
+    queue: VecDeque<(u64, String)>,
+}
+
+impl QuotaIndex {
+    /// Count `key` in `group`, or move it to the back of its group's queue
+    /// if it's already counted.
+    fn add(&mut self, key: &str, group: Arc<str>, max_share: f64) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let QuotaIndex {
+            groups, members, ..
+        } = self;
+        let group = match members.get_mut(key) {
+            Some(member) => {
+                member.1 = seq;
+                Arc::clone(&member.0)
+            }
+            None => {
+                members.insert(key.to_string(), (Arc::clone(&group), seq));
+                let counted = groups
+                    .entry(Arc::clone(&group))
+                    .or_insert_with(|| QuotaGroup {
+                        max_share,
+                        usage: QuotaUsage::default(),
+                        queue: VecDeque::new(),
+                    });
+                counted.usage.entries += 1;
+                group
+            }
+        };
+        let Some(counted) = groups.get_mut(&group) else {
+            return;
+        };
+        counted.queue.push_back((seq, key.to_string()));
+        if counted.queue.len() > 2 * counted.usage.entries + QUOTA_SAMPLE {
+            counted
+                .queue
+                .retain(|(seq, key)| members.get(key).is_some_and(|m| m.1 == *seq));
+        }
+    }
+
+    /// Take `key` off its group's count, forgetting groups with nothing
+    /// left to report. Returns the group's usage, if `key` had one.
+    fn remove(&mut self, key: &str) -> Option<&mut QuotaUsage> {
+        let (group, _) = self.members.remove(key)?;
+        let counted = self.groups.get_mut(&group)?;
+        counted.usage.entries = counted.usage.entries.saturating_sub(1);
+        if counted.usage == QuotaUsage::default() {
+            self.groups.remove(&group);
+            return None;
+        }
+        self.groups.get_mut(&group).map(|g| &mut g.usage)
+    }
+}
+
+pub(crate) fn hasher() -> ahash::RandomState {
+    ahash::RandomState::with_seeds(1, 2, 3, 4)
+}
+
 /// Thread-safe sharded cache wrapper.
 ///
 /// Distributes keys across 64 independent shards, each with its own `RwLock`
 /// and cache instance. This dramatically reduces lock contention:
 ///
-/// - **SIEVE hits**: `read lock` on one shard → flip visited bit → release.
-///   63 other shards remain uncontested.
-/// - **SIEVE misses**: `write lock` on one shard → evict + insert → release.
-/// - **LRU hits**: `write lock` on one shard (move-to-front). This is the
-///   scalability bottleneck that SIEVE avoids.
+/// - **Hits**: `write lock` on one shard → count the hit, drop the entry if
+///   expired → release. 63 other shards remain uncontested. SIEVE only sets
+///   a visited bit; LRU also moves the entry to the front of its list.
+/// - **Misses**: `write lock` on one shard → evict + insert → release.
 ///
-/// Shard selection uses `ahash` for fast, DoS-resistant hashing.
+/// Shard selection uses `ahash` for fast, DoS-resistant hashing, or a key
+/// prefix with `with_partitioning`.
 pub struct ShardedCache<T: CachePolicy> {
     shards: Box<[RwLock<T>; NUM_SHARDS]>,
     name: &'static str,
+    partitioning: Option<Partitioning>,
+    /// Misses being fetched through `entry`.
+    fills: Fills,
+    /// Told about inserts, hits, evictions and expiries (see `with_listener`).
+    listener: Option<Arc<dyn CacheListener>>,
+    /// Bound on `memory_bytes` across all shards, 0 for none (see
+    /// `set_max_memory`).
+    max_memory: AtomicUsize,
+    /// Share of a shard's capacity a full shard evicts down to, as `f64`
+    /// bits (see `set_low_watermark`).
+    low_watermark: AtomicU64,
+    /// Which keys have a quota (see `with_quotas`).
+    quotas: Option<Arc<dyn KeyQuotas>>,
+    /// Each shard's quota groups and their members, updated under the
+    /// shard's write lock. Empty without quotas.
+    quota_usage: Box<[Mutex<QuotaIndex>]>,
 }
 
 impl<T: CachePolicy> ShardedCache<T> {
@@ -42,38 +287,751 @@ impl<T: CachePolicy> ShardedCache<T> {
             .try_into()
             .unwrap_or_else(|_| unreachable!());
 
-        Self { shards, name }
+        Self {
+            shards,
+            name,
+            partitioning: None,
+            fills: Fills::new(),
+            listener: None,
+            max_memory: AtomicUsize::new(0),
+            low_watermark: AtomicU64::new(1.0f64.to_bits()),
+            quotas: None,
+            quota_usage: Box::new([]),
+        }
+    }
+
+    /// `new`, refusing a capacity of 0 (which `new` rounds up to one entry
+    /// per shard) or one too large for the shards to index.
+    pub fn try_new<F>(total_capacity: usize, make_shard: F) -> Result<Self, CacheError>
+    where
+        F: Fn(usize) -> T,
+    {
+        check_total_capacity(total_capacity)?;
+        Ok(Self::new(total_capacity, make_shard))
+    }
+
+    /// Bound the cache's approximate memory footprint to `bytes` (see
+    /// `set_max_memory`).
+    pub fn with_max_memory(self, bytes: usize) -> Self {
+        self.set_max_memory(bytes);
+        self
+    }
+
+    /// Bound `memory_bytes` to `bytes`, split evenly over the shards, on top
+    /// of the entry capacity; 0 removes the bound. An insert that takes its
+    /// shard over its share runs the policy's eviction until it's back
+    /// under, which may evict the new entry itself if it's too large to fit.
+    /// Shards already over are trimmed now, one at a time.
+    pub fn set_max_memory(&self, bytes: usize) {
+        self.max_memory.store(bytes, Ordering::Relaxed);
+        for (idx, shard) in self.shards.iter().enumerate() {
+            let removed = {
+                let mut shard = shard.write();
+                self.trim(&mut shard);
+                self.take_removed(idx, &mut shard)
+            };
+            self.notify_removed(removed);
+        }
+    }
+
+    /// The bound set by `set_max_memory`, 0 for none.
+    pub fn max_memory(&self) -> usize {
+        self.max_memory.load(Ordering::Relaxed)
+    }
+
+    /// Evict in batches (see `set_low_watermark`).
+    pub fn with_low_watermark(self, share: f64) -> Self {
+        self.set_low_watermark(share);
+        self
+    }
+
+    /// When an insert finds its shard full, evict down to `share` of the
+    /// shard's capacity in one go instead of one entry per insert, so the
+    /// inserts that follow go in without evicting. The eviction work is the
+    /// same, but it's done in fewer, longer write-lock holds: most inserts
+    /// get cheaper and one in every batch pays for the rest. 1.0 (the
+    /// default) evicts one entry at a time. Clamped to [0.5, 1.0]; shards
+    /// of fewer than two entries always evict one at a time.
+    pub fn set_low_watermark(&self, share: f64) {
+        let share = if share.is_nan() {
+            1.0
+        } else {
+            share.clamp(0.5, 1.0)
+        };
+        self.low_watermark.store(share.to_bits(), Ordering::Relaxed);
+    }
+
+    /// The share set by `set_low_watermark`.
+    pub fn low_watermark(&self) -> f64 {
+        f64::from_bits(self.low_watermark.load(Ordering::Relaxed))
+    }
+
+    /// Before inserting `key` into `shard`: if the shard is full and the
+    /// key is new, evict down to the low watermark.
+    fn make_room(&self, shard: &mut T, key: &str) {
+        let share = self.low_watermark();
+        let capacity = shard.capacity();
+        if share >= 1.0 || shard.len() < capacity || shard.peek(key).is_some() {
+            return;
+        }
+        let low = ((capacity as f64 * share) as usize).min(capacity - 1);
+        while shard.len() > low && shard.evict() {}
+    }
+
+    /// Evict from `shard` until it's within its share of `max_memory`.
+    fn trim(&self, shard: &mut T) {
+        let max = self.max_memory.load(Ordering::Relaxed);
+        if max == 0 {
+            return;
+        }
+        let budget = max / NUM_SHARDS;
+        while shard.memory_bytes() > budget && shard.evict() {}
+    }
+
+    /// Call `listener` as entries are inserted, hit, evicted or expire.
+    /// Explicit `remove`s and `clear`s aren't reported: the caller knows.
+    ///
+    /// Callbacks run after the shard lock is released, once the operation
+    /// that caused them is done, so an entry evicted by an insert is
+    /// reported before the insert itself.
+    pub fn with_listener(mut self, listener: Arc<dyn CacheListener>) -> Self {
+        for shard in self.shards.iter_mut() {
+            shard.get_mut().record_removals(true);
+        }
+        self.listener = Some(listener);
+        self
+    }
+
+    /// Cap the share of each shard's capacity a group of keys may fill (see
+    /// `KeyQuotas`), so one busy route or tenant can't push everyone else's
+    /// entries out. An insert that takes its group past `max_share` evicts
+    /// one of the group's own entries instead of leaving it to the policy.
+    /// The group's oldest few entries (by insertion) are the candidates: an
+    /// expired one if there is one, otherwise the oldest the policy hasn't
+    /// marked as visited, otherwise the oldest. Each shard keeps its groups'
+    /// members in insertion order, so this costs the same however large the
+    /// shard or group, and `KeyQuotas::quota` is called once per new key.
+    ///
+    /// Groups are counted per shard, which matches the whole cache as long
+    /// as a group's keys are hashed over every shard. Under partitioning,
+    /// the share applies within each of the partition's shards.
+    pub fn with_quotas(mut self, quotas: Arc<dyn KeyQuotas>) -> Self {
+        for shard in self.shards.iter_mut() {
+            shard.get_mut().record_removals(true);
+        }
+        self.quota_usage = (0..NUM_SHARDS).map(|_| Mutex::default()).collect();
+        self.quotas = Some(quotas);
+        for (idx, shard) in self.shards.iter().enumerate() {
+            self.recount_quotas(idx, &shard.read());
+        }
+        self
+    }
+
+    /// Entries and quota evictions of every quota group seen, across all
+    /// shards. Empty without quotas.
+    pub fn quota_usage(&self) -> HashMap<String, QuotaUsage> {
+        let mut total: HashMap<String, QuotaUsage> = HashMap::new();
+        for index in self.quota_usage.iter() {
+            for (group, counted) in index.lock().groups.iter() {
+                let usage = &counted.usage;
+                let sum = total.entry(group.to_string()).or_default();
+                sum.entries += usage.entries;
+                sum.evictions += usage.evictions;
+            }
+        }
+        total
+    }
+
+    /// `shard.take_removed()`, uncounting the entries from their quota
+    /// groups.
+    fn take_removed(&self, idx: usize, shard: &mut T) -> Vec<Removed> {
+        let removed = shard.take_removed();
+        if self.quotas.is_some() && !removed.is_empty() {
+            let mut index = self.quota_usage[idx].lock();
+            for Removed { key, .. } in &removed {
+                index.remove(key);
+            }
+        }
+        removed
+    }
+
+    /// Count `key`, just stored in shard `idx`, against its quota, unless
+    /// it replaced an entry already counted. While its group is over quota,
+    /// evict another of the group's entries; those are returned.
+    fn enforce_quota(&self, idx: usize, shard: &mut T, key: &str) -> Vec<Removed> {
+        let Some(quotas) = &self.quotas else {
+            return Vec::new();
+        };
+        if shard.peek(key).is_none() {
+            return Vec::new();
+        }
+        let mut index = self.quota_usage[idx].lock();
+        let (group, max_share) = match index.members.get(key) {
+            Some((group, _)) => {
+                let share = index.groups.get(group).map_or(1.0, |g| g.max_share);
+                (Arc::clone(group), share)
+            }
+            None => {
+                let Some(quota) = quotas.quota(key) else {
+                    return Vec::new();
+                };
+                let group = match index.groups.get_key_value(quota.group.as_str()) {
+                    Some((group, _)) => Arc::clone(group),
+                    None => Arc::from(quota.group),
+                };
+                (group, quota.max_share)
+            }
+        };
+        index.add(key, Arc::clone(&group), max_share);
+
+        let limit = ((max_share * shard.capacity() as f64) as usize).max(1);
+        let mut evicted = Vec::new();
+        while index
+            .groups
+            .get(&group)
+            .is_some_and(|g| g.usage.entries > limit)
+        {
+            let Some((victim, entry)) = Self::quota_victim(&mut index, shard, &group, key) else {
+                break;
+            };
+            shard.remove(&victim);
+            if let Some(usage) = index.remove(&victim) {
+                usage.evictions += 1;
+            }
+            let cause = if entry.is_expired() {
+                RemovalCause::Expired
+            } else {
+                RemovalCause::Evicted
+            };
+            evicted.push(Removed {
+                key: victim,
+                entry,
+                cause,
+            });
+        }
+        evicted
+    }
+
+    /// The entry of `group` to evict for its quota, never `inserted`,
+    /// chosen among the group's `QUOTA_SAMPLE` oldest entries.
+    fn quota_victim(
+        index: &mut QuotaIndex,
+        shard: &T,
+        group: &str,
+        inserted: &str,
+    ) -> Option<(String, Arc<CachedResponse>)> {
+        let QuotaIndex {
+            groups, members, ..
+        } = index;
+        let queue = &mut groups.get_mut(group)?.queue;
+        let current = |seq: u64, key: &str| members.get(key).is_some_and(|m| m.1 == seq);
+        while queue.front().is_some_and(|(seq, key)| !current(*seq, key)) {
+            queue.pop_front();
+        }
+
+        let (mut oldest, mut unvisited) = (None, None);
+        let mut sampled = 0;
+        for (seq, key) in queue.iter() {
+            if key == inserted || !current(*seq, key) {
+                continue;
+            }
+            let Some(entry) = shard.peek(key) else {
+                continue;
+            };
+            if entry.is_expired() {
+                return Some((key.clone(), entry));
+            }
+            if unvisited.is_none() && !shard.visited(key) {
+                unvisited = Some((key, Arc::clone(&entry)));
+            }
+            oldest.get_or_insert((key, entry));
+            sampled += 1;
+            if sampled == QUOTA_SAMPLE {
+                break;
+            }
+        }
+        let (key, entry) = unvisited.or(oldest)?;
+        Some((key.clone(), entry))
+    }
+
+    /// Count shard `idx`'s quota groups afresh, after entries left it
+    /// without being reported. Quota evictions so far are kept.
+    fn recount_quotas(&self, idx: usize, shard: &T) {
+        let Some(quotas) = &self.quotas else {
+            return;
+        };
+        let mut index = self.quota_usage[idx].lock();
+        let old = std::mem::take(&mut *index);
+        // `iter` is newest first
+        let mut keys: Vec<String> = shard.iter().map(|info| info.key).collect();
+        keys.reverse();
+        for key in keys {
+            if let Some(quota) = quotas.quota(&key) {
+                index.add(&key, Arc::from(quota.group), quota.max_share);
+            }
+        }
+        for (group, counted) in old.groups {
+            if counted.usage.evictions == 0 {
+                continue;
+            }
+            index
+                .groups
+                .entry(group)
+                .or_insert_with(|| QuotaGroup {
+                    max_share: counted.max_share,
+                    usage: QuotaUsage::default(),
+                    queue: VecDeque::new(),
+                })
+                .usage
+                .evictions = counted.usage.evictions;
+        }
+        index.next_seq = index.next_seq.max(old.next_seq);
+    }
+
+    /// Pass entries a shard dropped on its own to the listener.
+    fn notify_removed(&self, removed: Vec<Removed>) {
+        let Some(listener) = &self.listener else {
+            return;
+        };
+        for Removed { key, entry, cause } in removed {
+            match cause {
+                RemovalCause::Evicted => listener.on_evict(&key, &entry),
+                RemovalCause::Expired => listener.on_expire(&key, &entry),
+            }
+        }
+    }
+
+    /// Co-locate each partition's keys on a few shards (see `Partitioning`),
+    /// so `clear_partition` and `resize_partition` only lock those.
+    pub fn with_partitioning(mut self, partitioning: Partitioning) -> Self {
+        self.partitioning = Some(partitioning);
+        self
+    }
+
+    pub fn partitioning(&self) -> Option<&Partitioning> {
+        self.partitioning.as_ref()
     }
 
     /// Hash a key and return the shard index.
     #[inline]
-    fn shard_index(key: &str) -> usize {
-        let hash = ahash::RandomState::with_seeds(1, 2, 3, 4).hash_one(key);
-        (hash & SHARD_MASK) as usize
+    fn shard_index(&self, key: &str) -> usize {
+        let hash = hasher().hash_one(key);
+        let partition = self
+            .partitioning
+            .as_ref()
+            .and_then(|p| Some((p, p.partition_of(key)?)));
+        match partition {
+            Some((p, name)) => {
+                let first = hasher().hash_one(name);
+                (first.wrapping_add(hash % p.shards as u64) & SHARD_MASK) as usize
+            }
+            None => (hash & SHARD_MASK) as usize,
+        }
     }
 
-    /// Look up a key. For SIEVE, this only needs a read lock (visited bit
-    /// is AtomicBool). For LRU, the inner `get` does move-to-front which
-    /// needs `&mut self`, so we take a write lock regardless — the contention
-    /// difference shows up in benchmarks.
+    /// Look up a key. Every policy's `get` takes `&mut self` (hit and miss
+    /// counters, lazy expiry, LRU's move-to-front), so this takes the
+    /// shard's write lock; SIEVE just holds it for less time.
     pub fn get(&self, key: &str) -> Option<Arc<CachedResponse>> {
-        let idx = Self::shard_index(key);
-        let mut shard = self.shards[idx].write();
-        shard.get(key)
+        let idx = self.shard_index(key);
+        let (found, removed) = {
+            let mut shard = self.shards[idx].write();
+            (shard.get(key), self.take_removed(idx, &mut shard))
+        };
+        if let Some(listener) = &self.listener {
+            self.notify_removed(removed);
+            if let Some(entry) = &found {
+                listener.on_hit(key, entry);
+            }
+        }
+        found
+    }
+
+    /// Inspect a key without touching stats or eviction state. Read lock only.
+    pub fn peek(&self, key: &str) -> Option<Arc<CachedResponse>> {
+        let idx = self.shard_index(key);
+        self.shards[idx].read().peek(key)
     }
 
     /// Insert a key-value pair. Takes a write lock on one shard.
     pub fn insert(&self, key: String, value: CachedResponse) {
-        let idx = Self::shard_index(&key);
+        if self.listener.is_some() || self.quotas.is_some() {
+            self.insert_and_get(key, value);
+            return;
+        }
+        let idx = self.shard_index(&key);
         let mut shard = self.shards[idx].write();
+        self.make_room(&mut shard, &key);
         shard.insert(key, value);
+        self.trim(&mut shard);
+    }
+
+    /// `insert`, returning the entry as stored.
+    pub fn insert_and_get(&self, key: String, value: CachedResponse) -> Arc<CachedResponse> {
+        let idx = self.shard_index(&key);
+        let (stored, removed, trimmed) = {
+            let mut shard = self.shards[idx].write();
+            self.make_room(&mut shard, &key);
+            shard.insert(key.clone(), value);
+            let stored = shard
+                .peek(&key)
+                .expect("an entry is kept until the next insert");
+            let mut removed = self.take_removed(idx, &mut shard);
+            removed.extend(self.enforce_quota(idx, &mut shard, &key));
+            self.trim(&mut shard);
+            (stored, removed, self.take_removed(idx, &mut shard))
+        };
+        if let Some(listener) = &self.listener {
+            self.notify_removed(removed);
+            listener.on_insert(&key, &stored);
+            self.notify_removed(trimmed);
+        }
+        stored
+    }
+
+    /// `insert`, saying why the entry wasn't kept when that happens. An
+    /// entry over its shard's share of `max_memory` is turned away before
+    /// anything is evicted for it, where `insert` would empty the shard
+    /// trying to fit it.
+    pub fn try_insert(&self, key: String, value: CachedResponse) -> Result<(), CacheError> {
+        let max_memory = self.max_memory();
+        if max_memory > 0 {
+            let (size, max) = (footprint(&key, &value), max_memory / NUM_SHARDS);
+            if size > max {
+                return Err(CacheError::EntryTooLarge { size, max });
+            }
+        }
+        let idx = self.shard_index(&key);
+        let (inserted, kept, removed, trimmed) = {
+            let mut shard = self.shards[idx].write();
+            self.make_room(&mut shard, &key);
+            let inserted = shard.try_insert(key.clone(), value).map(|()| {
+                shard
+                    .peek(&key)
+                    .expect("an entry is kept until the next insert")
+            });
+            let mut removed = self.take_removed(idx, &mut shard);
+            let mut kept = inserted.is_ok();
+            if kept {
+                removed.extend(self.enforce_quota(idx, &mut shard, &key));
+                kept = shard.peek(&key).is_some();
+            }
+            self.trim(&mut shard);
+            (inserted, kept, removed, self.take_removed(idx, &mut shard))
+        };
+        if let Some(listener) = &self.listener {
+            self.notify_removed(removed);
+            if let Ok(stored) = &inserted {
+                listener.on_insert(&key, stored);
+            }
+            self.notify_removed(trimmed);
+        }
+        match inserted {
+            Err(e) => Err(e),
+            Ok(_) if !kept => Err(CacheError::OverQuota),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    /// Look `key` up, or claim it for fetching on a miss. If another caller
+    /// already claimed it, wait for the value it inserts instead: however
+    /// many callers miss a key at once, one fetches it.
+    ///
+    /// A miss counts toward the stats even when the wait ends with a value.
+    pub async fn entry(&self, key: &str) -> Entry<'_, T> {
+        match self.claim(key) {
+            Ok(entry) => entry,
+            Err(waiter) => self.vacant_unless_filled(key, waiter.await),
+        }
+    }
+
+    /// `entry`, blocking the thread while another caller fetches the key.
+    pub fn entry_blocking(&self, key: &str) -> Entry<'_, T> {
+        match self.claim(key) {
+            Ok(entry) => entry,
+            Err(waiter) => self.vacant_unless_filled(key, waiter.wait_blocking()),
+        }
+    }
+
+    /// The value for `key`, fetching it with `fetch` on a miss (see `entry`).
+    pub async fn get_or_insert_with<F, Fut>(&self, key: &str, fetch: F) -> Arc<CachedResponse>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = CachedResponse>,
+    {
+        match self.entry(key).await {
+            Entry::Occupied(value) => value,
+            Entry::Vacant(entry) => entry.insert(fetch().await),
+        }
+    }
+
+    /// `get_or_insert_with` for fetches that can fail. An error is returned
+    /// to this caller only, and nothing is cached; callers that were waiting
+    /// on the fetch make their own.
+    pub async fn try_get_or_insert_with<F, Fut, E>(
+        &self,
+        key: &str,
+        fetch: F,
+    ) -> Result<Arc<CachedResponse>, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<CachedResponse, E>>,
+    {
+        match self.entry(key).await {
+            Entry::Occupied(value) => Ok(value),
+            Entry::Vacant(entry) => Ok(entry.insert(fetch().await?)),
+        }
+    }
+
+    /// The entry for `key` if it's cached or claimable, or the claim to
+    /// wait on.
+    fn claim(&self, key: &str) -> Result<Entry<'_, T>, FillWaiter> {
+        if let Some(value) = self.get(key) {
+            return Ok(Entry::Occupied(value));
+        }
+        match self.fills.claim(key) {
+            Claim::Leader(fill) => {
+                // Another caller's fill may have landed since the miss
+                if let Some(value) = self.peek(key).filter(|v| !v.is_expired()) {
+                    fill.complete(Arc::clone(&value));
+                    return Ok(Entry::Occupied(value));
+                }
+                Ok(Entry::Vacant(VacantEntry {
+                    cache: self,
+                    key: key.to_string(),
+                    fill: Some(fill),
+                }))
+            }
+            Claim::Follower(waiter) => Err(waiter),
+        }
+    }
+
+    fn vacant_unless_filled(&self, key: &str, filled: Option<Arc<CachedResponse>>) -> Entry<'_, T> {
+        match filled {
+            Some(value) => Entry::Occupied(value),
+            None => Entry::Vacant(VacantEntry {
+                cache: self,
+                key: key.to_string(),
+                fill: None,
+            }),
+        }
+    }
+
+    /// Look up several keys, taking each shard's lock once however many of
+    /// them it holds. Results are in `keys` order.
+    pub fn get_many<K: AsRef<str>>(&self, keys: &[K]) -> Vec<Option<Arc<CachedResponse>>> {
+        let mut order: Vec<(usize, usize)> = keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| (self.shard_index(key.as_ref()), i))
+            .collect();
+        order.sort_unstable();
+
+        let mut found = vec![None; keys.len()];
+        let mut removed = Vec::new();
+        for group in order.chunk_by(|a, b| a.0 == b.0) {
+            let mut shard = self.shards[group[0].0].write();
+            for &(_, i) in group {
+                found[i] = shard.get(keys[i].as_ref());
+            }
+            removed.extend(self.take_removed(group[0].0, &mut shard));
+        }
+        if let Some(listener) = &self.listener {
+            self.notify_removed(removed);
+            for (key, entry) in keys.iter().zip(&found) {
+                if let Some(entry) = entry {
+                    listener.on_hit(key.as_ref(), entry);
+                }
+            }
+        }
+        found
+    }
+
+    /// Insert several entries, taking each shard's lock once. A key given
+    /// twice ends up with its last value.
+    pub fn insert_many(&self, entries: Vec<(String, CachedResponse)>) {
+        let mut entries: Vec<(usize, String, CachedResponse)> = entries
+            .into_iter()
+            .map(|(key, value)| (self.shard_index(&key), key, value))
+            .collect();
+        // Stable, so repeated keys keep their order within a shard
+        entries.sort_by_key(|&(idx, _, _)| idx);
+
+        let mut inserted = Vec::new();
+        let mut removed = Vec::new();
+        let mut trimmed = Vec::new();
+        let mut entries = entries.into_iter().peekable();
+        while let Some((idx, key, value)) = entries.next() {
+            let mut shard = self.shards[idx].write();
+            let mut next = Some((key, value));
+            while let Some((key, value)) = next {
+                self.make_room(&mut shard, &key);
+                if self.quotas.is_some() {
+                    shard.insert(key.clone(), value);
+                    removed.extend(self.take_removed(idx, &mut shard));
+                    removed.extend(self.enforce_quota(idx, &mut shard, &key));
+                    if self.listener.is_some() {
+                        inserted.extend(shard.peek(&key).map(|stored| (key, stored)));
+                    }
+                } else if self.listener.is_some() {
+                    shard.insert(key.clone(), value);
+                    inserted.extend(shard.peek(&key).map(|stored| (key, stored)));
+                } else {
+                    shard.insert(key, value);
+                }
+                next = entries.next_if(|e| e.0 == idx).map(|e| (e.1, e.2));
+            }
+            removed.extend(self.take_removed(idx, &mut shard));
+            self.trim(&mut shard);
+            trimmed.extend(self.take_removed(idx, &mut shard));
+        }
+        if let Some(listener) = &self.listener {
+            self.notify_removed(removed);
+            for (key, stored) in inserted {
+                listener.on_insert(&key, &stored);
+            }
+            self.notify_removed(trimmed);
+        }
+    }
+
+    /// Read-modify-write `key` under its shard's write lock, so concurrent
+    /// updates to the same key can't interleave. `f` sees the current entry,
+    /// expired or not, and returns its replacement (`None` leaves the entry
+    /// as it was) along with a result for the caller.
+    pub fn update<R, F>(&self, key: &str, f: F) -> R
+    where
+        F: FnOnce(Option<&CachedResponse>) -> (Option<CachedResponse>, R),
+    {
+        let idx = self.shard_index(key);
+        let (stored, removed, trimmed, result) = {
+            let mut shard = self.shards[idx].write();
+            let current = shard.peek(key);
+            let (replacement, result) = f(current.as_deref());
+            let inserted = replacement.is_some();
+            let stored = replacement.and_then(|replacement| {
+                self.make_room(&mut shard, key);
+                shard.insert(key.to_string(), replacement);
+                self.listener.as_ref().and_then(|_| shard.peek(key))
+            });
+            let mut removed = self.take_removed(idx, &mut shard);
+            if inserted {
+                removed.extend(self.enforce_quota(idx, &mut shard, key));
+            }
+            self.trim(&mut shard);
+            (stored, removed, self.take_removed(idx, &mut shard), result)
+        };
+        if let Some(listener) = &self.listener {
+            self.notify_removed(removed);
+            if let Some(stored) = stored {
+                listener.on_insert(key, &stored);
+            }
+            self.notify_removed(trimmed);
+        }
+        result
     }
 
     /// Remove a key explicitly.
     pub fn remove(&self, key: &str) -> bool {
-        let idx = Self::shard_index(key);
+        self.take(key).is_some()
+    }
+
+    /// Remove a key and return its entry, expired or not. Both happen under
+    /// the shard's write lock, so no other writer can replace the entry in
+    /// between.
+    pub fn take(&self, key: &str) -> Option<Arc<CachedResponse>> {
+        let idx = self.shard_index(key);
         let mut shard = self.shards[idx].write();
-        shard.remove(key)
+        let entry = shard.peek(key)?;
+        shard.remove(key);
+        if self.quotas.is_some() {
+            self.quota_usage[idx].lock().remove(key);
+        }
+        Some(entry)
+    }
+
+    /// Remove every entry, one shard at a time, so lookups on other shards
+    /// carry on meanwhile. Returns how many were removed.
+    pub fn clear(&self) -> usize {
+        self.shards
+            .iter()
+            .enumerate()
+            .map(|(idx, shard)| {
+                let mut shard = shard.write();
+                let removed = shard.len();
+                shard.clear();
+                self.recount_quotas(idx, &shard);
+                removed
+            })
+            .sum()
+    }
+
+    /// List up to `limit` entries whose keys pass `filter`, without side
+    /// effects. Entries come shard by shard, sorted by key within each, so
+    /// passing the previous page's `next` as `after` resumes where it left
+    /// off even if that key has since been evicted. Entries inserted or
+    /// removed meanwhile may or may not show up. Holds one shard's read
+    /// lock at a time.
+    pub fn scan<F>(&self, after: Option<&str>, limit: usize, filter: F) -> ScanPage
+    where
+        F: Fn(&str) -> bool,
+    {
+        let limit = limit.max(1);
+        let start = after.map_or(0, |key| self.shard_index(key));
+        let mut entries: Vec<(String, Arc<CachedResponse>)> = Vec::new();
+        for idx in start..NUM_SHARDS {
+            let mut found: Vec<_> = self.shards[idx]
+                .read()
+                .entries()
+                .into_iter()
+                .filter(|(key, _)| {
+                    filter(key) && (idx != start || after.is_none_or(|after| key.as_str() > after))
+                })
+                .collect();
+            found.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+            for entry in found {
+                if entries.len() == limit {
+                    let next = entries.last().map(|(key, _)| key.clone());
+                    return ScanPage { entries, next };
+                }
+                entries.push(entry);
+            }
+        }
+        ScanPage {
+            entries,
+            next: None,
+        }
+    }
+
+    /// Every entry, expired or not, shard by shard and in queue order within
+    /// each (see `CachePolicy::iter`), without side effects. A shard is read
+    /// under its lock when the iterator reaches it, so entries inserted or
+    /// removed meanwhile may or may not show up.
+    pub fn iter(&self) -> impl Iterator<Item = EntryInfo> + '_ {
+        self.shards
+            .iter()
+            .flat_map(|shard| shard.read().iter().collect::<Vec<_>>())
+    }
+
+    /// Remove every entry for which `keep` returns `false`, expired or not,
+    /// one shard at a time under its write lock. Returns how many went.
+    /// Like `remove`, this isn't reported to the listener.
+    pub fn retain<F>(&self, mut keep: F) -> usize
+    where
+        F: FnMut(&str, &CachedResponse) -> bool,
+    {
+        self.shards
+            .iter()
+            .enumerate()
+            .map(|(idx, shard)| {
+                let mut shard = shard.write();
+                let removed = shard.retain(&mut keep);
+                if removed > 0 {
+                    self.recount_quotas(idx, &shard);
+                }
+                removed
+            })
+            .sum()
     }
 
     /// Total number of entries across all shards.
@@ -86,16 +1044,138 @@ impl<T: CachePolicy> ShardedCache<T> {
         self.shards.iter().all(|s| s.read().is_empty())
     }
 
+    /// Approximate heap footprint of every entry, in bytes.
+    pub fn memory_bytes(&self) -> usize {
+        self.shards.iter().map(|s| s.read().memory_bytes()).sum()
+    }
+
     /// Total capacity across all shards.
     pub fn capacity(&self) -> usize {
         self.shards.iter().map(|s| s.read().capacity()).sum()
     }
 
+    /// Resize to `total_capacity` (split evenly, minimum 1 per shard, and
+    /// at most what a shard can index). Use `try_resize` to have a zero or
+    /// oversized capacity refused instead.
+    ///
+    /// Shards are resized one at a time, each under its own write lock, so a
+    /// large shrink never blocks more than one shard's traffic at once.
+    pub fn resize(&self, total_capacity: usize) {
+        let per_shard = (total_capacity / NUM_SHARDS).clamp(1, MAX_CAPACITY);
+        for (idx, shard) in self.shards.iter().enumerate() {
+            let removed = {
+                let mut shard = shard.write();
+                shard.resize(per_shard);
+                self.take_removed(idx, &mut shard)
+            };
+            self.notify_removed(removed);
+        }
+    }
+
+    /// `resize`, refusing the capacities `try_new` refuses. Nothing changes
+    /// on error.
+    pub fn try_resize(&self, total_capacity: usize) -> Result<(), CacheError> {
+        check_total_capacity(total_capacity)?;
+        self.resize(total_capacity);
+        Ok(())
+    }
+
+    /// Remove every entry in `partition`, locking only its shards (one at a
+    /// time). Returns how many were removed, or `None` without partitioning.
+    pub fn clear_partition(&self, partition: &str) -> Option<usize> {
+        let partitioning = self.partitioning.as_ref()?;
+        let mut outside =
+            |key: &str, _: &CachedResponse| partitioning.partition_of(key) != Some(partition);
+        let removed = partitioning
+            .shard_run(partition)
+            .map(|idx| {
+                let mut shard = self.shards[idx].write();
+                let removed = shard.retain(&mut outside);
+                self.recount_quotas(idx, &shard);
+                removed
+            })
+            .sum();
+        Some(removed)
+    }
+
+    /// Give `partition`'s shards `capacity` entries between them (minimum 1
+    /// each), leaving every other shard alone. Partitions sharing those
+    /// shards are resized with it, and a later `resize` evens everything out
+    /// again. Returns `false` without partitioning.
+    pub fn resize_partition(&self, partition: &str, capacity: usize) -> bool {
+        let Some(partitioning) = &self.partitioning else {
+            return false;
+        };
+        let per_shard = (capacity / partitioning.shards).clamp(1, MAX_CAPACITY);
+        for idx in partitioning.shard_run(partition) {
+            let removed = {
+                let mut shard = self.shards[idx].write();
+                shard.resize(per_shard);
+                self.take_removed(idx, &mut shard)
+            };
+            self.notify_removed(removed);
+        }
+        true
+    }
+
+    /// The partition on the busiest shard, if its shards have served more
+    /// than `threshold` of all lookups so far. Since partitions are pinned
+    /// to a few shards, one that dominates the traffic leaves the rest of
+    /// the cache idle while it evicts its own entries. Only the busiest
+    /// shard's keys are examined, so this is cheap enough to poll.
+    pub fn partition_skew(&self, threshold: f64) -> Option<PartitionSkew> {
+        let partitioning = self.partitioning.as_ref()?;
+        let lookups: Vec<u64> = self
+            .shards
+            .iter()
+            .map(|shard| {
+                let stats = shard.read().stats();
+                stats.hits + stats.misses
+            })
+            .collect();
+        let total: u64 = lookups.iter().sum();
+        let busiest = (0..NUM_SHARDS).max_by_key(|&i| lookups[i])?;
+        if total == 0 {
+            return None;
+        }
+
+        // Name the busiest shard's most common partition
+        let keys = self.shards[busiest].read().keys();
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for key in &keys {
+            if let Some(partition) = partitioning.partition_of(key) {
+                *counts.entry(partition).or_default() += 1;
+            }
+        }
+        let (partition, _) = counts.into_iter().max_by_key(|&(_, n)| n)?;
+
+        let share = partitioning
+            .shard_run(partition)
+            .map(|i| lookups[i])
+            .sum::<u64>() as f64
+            / total as f64;
+        (share > threshold).then(|| PartitionSkew {
+            partition: partition.to_string(),
+            share,
+            expected: partitioning.shards as f64 / NUM_SHARDS as f64,
+        })
+    }
+
     /// Name of the underlying eviction policy.
     pub fn name(&self) -> &'static str {
         self.name
     }
 
+    /// Run `CachePolicy::check_invariants` on every shard (one read lock at a
+    /// time) and return each violation with its shard index.
+    pub fn check_invariants(&self) -> Vec<(usize, String)> {
+        self.shards
+            .iter()
+            .enumerate()
+            .filter_map(|(i, shard)| shard.read().check_invariants().err().map(|e| (i, e)))
+            .collect()
+    }
+
     /// Aggregate statistics across all shards.
     pub fn stats(&self) -> CacheStats {
         let mut total = CacheStats::default();
@@ -106,9 +1186,20 @@ impl<T: CachePolicy> ShardedCache<T> {
             total.evictions += s.evictions;
             total.current_size += s.current_size;
             total.capacity += s.capacity;
+            total.evicted.merge(&s.evicted);
+            total.memory_bytes += s.memory_bytes;
+            total.scan.merge(&s.scan);
         }
         total
     }
+
+    /// Zero every shard's hit/miss/eviction counters (one write lock at a
+    /// time), keeping the entries, e.g. between benchmark runs.
+    pub fn reset_stats(&self) {
+        for shard in self.shards.iter() {
+            shard.write().reset_stats();
+        }
+    }
 }
 
 // ShardedCache is Send + Sync if the inner policy is Send
@@ -120,17 +1211,147 @@ mod tests {
     use crate::fifo::FifoCache;
     use crate::lru::LruCache;
     use crate::sieve::SieveCache;
+    use crate::traits::InsertSource;
     use bytes::Bytes;
     use std::time::{Duration, Instant};
 
     fn resp() -> CachedResponse {
         CachedResponse {
             status: 200,
-            headers: vec![],
+            headers: Default::default(),
             body: Bytes::from_static(b"test"),
             inserted_at: Instant::now(),
             ttl: Duration::from_secs(60),
+            hits: Default::default(),
+            source: Default::default(),
+            must_revalidate: false,
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_misses_fetch_once() {
+        let cache = Arc::new(ShardedCache::new(1024, SieveCache::new));
+        let fetches = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let tasks: Vec<_> = (0..16)
+            .map(|_| {
+                let cache = Arc::clone(&cache);
+                let fetches = Arc::clone(&fetches);
+                tokio::spawn(async move {
+                    let value = cache
+                        .get_or_insert_with("k", || async {
+                            fetches.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(20)).await;
+                            resp()
+                        })
+                        .await;
+                    assert_eq!(value.body, "test");
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(fetches.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert!(matches!(cache.entry("k").await, Entry::Occupied(_)));
+        assert_eq!(cache.fills.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn abandoned_fetch_lets_waiters_fetch_themselves() {
+        let cache = ShardedCache::new(1024, SieveCache::new);
+        let Entry::Vacant(leader) = cache.entry("k").await else {
+            panic!("empty cache should be vacant");
+        };
+        let waiting = cache.get_or_insert_with("k", || async { resp() });
+        let abandon = async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            drop(leader);
+        };
+        let (value, ()) = tokio::join!(waiting, abandon);
+        assert_eq!(value.body, "test");
+
+        let failed = cache
+            .try_get_or_insert_with("other", || async {
+                Err::<CachedResponse, _>("upstream down")
+            })
+            .await;
+        assert_eq!(failed.unwrap_err(), "upstream down");
+        assert!(cache.peek("other").is_none());
+        assert_eq!(cache.fills.in_flight(), 0);
+    }
+
+    #[test]
+    fn entry_blocking_waits_for_the_leader() {
+        let cache = Arc::new(ShardedCache::new(1024, LruCache::new));
+        let Entry::Vacant(leader) = cache.entry_blocking("k") else {
+            panic!("empty cache should be vacant");
+        };
+        let follower = {
+            let cache = Arc::clone(&cache);
+            std::thread::spawn(move || match cache.entry_blocking("k") {
+                Entry::Occupied(value) => value.body.clone(),
+                Entry::Vacant(_) => panic!("should have waited for the leader"),
+            })
+        };
+        std::thread::sleep(Duration::from_millis(10));
+        let stored = leader.insert(resp());
+        assert_eq!(follower.join().unwrap(), stored.body);
+    }
+
+    #[test]
+    fn update_is_atomic_per_key() {
+        let cache = Arc::new(ShardedCache::new(1024, SieveCache::new));
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let cache = Arc::clone(&cache);
+                std::thread::spawn(move || {
+                    for _ in 0..1000 {
+                        cache.update("counter", |current| {
+                            let n: u64 = current
+                                .map(|e| std::str::from_utf8(&e.body).unwrap().parse().unwrap())
+                                .unwrap_or(0);
+                            let next = CachedResponse {
+                                body: Bytes::from((n + 1).to_string()),
+                                ..resp()
+                            };
+                            (Some(next), ())
+                        });
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
         }
+        assert_eq!(&cache.peek("counter").unwrap().body[..], b"8000");
+    }
+
+    #[test]
+    fn update_can_leave_entry_alone() {
+        let cache = ShardedCache::new(1024, SieveCache::new);
+        let seen = cache.update("missing", |current| (None, current.is_some()));
+        assert!(!seen);
+        assert!(cache.peek("missing").is_none());
+    }
+
+    #[test]
+    fn take_hands_out_each_entry_once() {
+        let cache = Arc::new(ShardedCache::new(1024, SieveCache::new));
+        cache.insert("k".into(), resp());
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let cache = Arc::clone(&cache);
+                std::thread::spawn(move || cache.take("k").is_some())
+            })
+            .collect();
+        let taken = threads
+            .into_iter()
+            .map(|t| t.join().unwrap())
+            .filter(|&taken| taken)
+            .count();
+        assert_eq!(taken, 1);
+        assert!(cache.peek("k").is_none());
+        assert!(cache.take("k").is_none());
     }
 
     #[test]
@@ -182,6 +1403,50 @@ mod tests {
         );
     }
 
+    #[test]
+    fn peek_has_no_side_effects() {
+        let cache = ShardedCache::new(1024, SieveCache::new);
+
+        cache.insert("a".into(), resp());
+        assert!(cache.peek("a").is_some());
+        assert!(cache.peek("missing").is_none());
+
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 0);
+        assert_eq!(stats.misses, 0);
+    }
+
+    #[test]
+    fn scan_pages_through_every_entry_once() {
+        let cache = ShardedCache::new(6400, SieveCache::new);
+        for i in 0..300 {
+            cache.insert(format!("img:{i}"), resp());
+            cache.insert(format!("css:{i}"), resp());
+        }
+
+        let mut seen = Vec::new();
+        let mut after = None;
+        loop {
+            let page = cache.scan(after.as_deref(), 32, |key| key.starts_with("img:"));
+            assert!(page.entries.len() <= 32);
+            seen.extend(page.entries.into_iter().map(|(key, _)| key));
+            match page.next {
+                Some(next) => after = Some(next),
+                None => break,
+            }
+            // Evicting the cursor key mustn't lose our place
+            cache.remove(after.as_deref().unwrap());
+        }
+
+        seen.sort();
+        seen.dedup();
+        assert_eq!(seen.len(), 300);
+        assert!(seen.iter().all(|key| key.starts_with("img:")));
+
+        let stats = cache.stats();
+        assert_eq!(stats.hits + stats.misses, 0);
+    }
+
     #[test]
     fn remove_works() {
         let cache = ShardedCache::new(1024, SieveCache::new);
@@ -208,6 +1473,227 @@ mod tests {
         assert_eq!(stats.current_size, 2);
     }
 
+    #[test]
+    fn batch_get_and_insert() {
+        let cache = ShardedCache::new(1024, SieveCache::new);
+        let entries = (0..100).map(|i| (format!("k{i}"), resp())).collect();
+        cache.insert_many(entries);
+        assert_eq!(cache.len(), 100);
+
+        let keys = ["k5", "missing", "k99", "k5"];
+        let found = cache.get_many(&keys);
+        let hits: Vec<bool> = found.iter().map(Option::is_some).collect();
+        assert_eq!(hits, [true, false, true, true]);
+        assert_eq!(cache.stats().hits, 3);
+        assert_eq!(cache.stats().misses, 1);
+    }
+
+    #[test]
+    fn insert_many_last_value_wins() {
+        let cache = ShardedCache::new(1024, SieveCache::new);
+        let mut second = resp();
+        second.body = Bytes::from_static(b"second");
+        cache.insert_many(vec![("k".into(), resp()), ("k".into(), second)]);
+
+        assert_eq!(cache.len(), 1);
+        assert_eq!(&cache.peek("k").unwrap().body[..], b"second");
+    }
+
+    #[test]
+    fn reset_stats_keeps_entries() {
+        let cache = ShardedCache::new(64, SieveCache::new);
+        for i in 0..200 {
+            cache.insert(format!("k{i}"), resp());
+        }
+        cache.get("k199");
+        cache.get("missing");
+        assert!(cache.stats().evictions > 0);
+
+        cache.reset_stats();
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (0, 0, 0));
+        assert_eq!(stats.current_size, cache.len());
+        assert!(cache.get("k199").is_some());
+        assert_eq!(cache.stats().hits, 1);
+    }
+
+    #[test]
+    fn clear_empties_every_shard() {
+        let cache = ShardedCache::new(1024, SieveCache::new);
+        for i in 0..200 {
+            cache.insert(format!("k{i}"), resp());
+        }
+        cache.get("k1");
+
+        assert_eq!(cache.clear(), 200);
+        assert!(cache.is_empty());
+        assert!(cache.get("k1").is_none());
+        assert_eq!(cache.stats().hits, 1, "counters survive a clear");
+        assert!(cache.check_invariants().is_empty());
+
+        // Cleared slots are reusable
+        for i in 0..200 {
+            cache.insert(format!("k{i}"), resp());
+        }
+        assert_eq!(cache.len(), 200);
+    }
+
+    /// Every callback as `(event, key)`, in order.
+    #[derive(Default)]
+    struct Recorder(parking_lot::Mutex<Vec<(&'static str, String)>>);
+
+    impl CacheListener for Recorder {
+        fn on_insert(&self, key: &str, _: &CachedResponse) {
+            self.0.lock().push(("insert", key.to_string()));
+        }
+        fn on_hit(&self, key: &str, _: &CachedResponse) {
+            self.0.lock().push(("hit", key.to_string()));
+        }
+        fn on_evict(&self, key: &str, _: &CachedResponse) {
+            self.0.lock().push(("evict", key.to_string()));
+        }
+        fn on_expire(&self, key: &str, _: &CachedResponse) {
+            self.0.lock().push(("expire", key.to_string()));
+        }
+    }
+
+    impl Recorder {
+        fn take(&self) -> Vec<(&'static str, String)> {
+            std::mem::take(&mut self.0.lock())
+        }
+    }
+
+    fn listened<T: CachePolicy>(make_shard: fn(usize) -> T) -> (ShardedCache<T>, Arc<Recorder>) {
+        let recorder = Arc::new(Recorder::default());
+        let listener: Arc<dyn CacheListener> = recorder.clone();
+        // One entry per shard, so a second key on a shard evicts the first
+        (
+            ShardedCache::new(64, make_shard).with_listener(listener),
+            recorder,
+        )
+    }
+
+    /// Two keys that land on the same shard.
+    fn colliding<T: CachePolicy>(cache: &ShardedCache<T>) -> (String, String) {
+        let first = "k0".to_string();
+        let second = (1..)
+            .map(|i| format!("k{i}"))
+            .find(|key| cache.shard_index(key) == cache.shard_index(&first))
+            .unwrap();
+        (first, second)
+    }
+
+    fn event(name: &'static str, key: &str) -> (&'static str, String) {
+        (name, key.to_string())
+    }
+
+    #[test]
+    fn listener_hears_inserts_hits_and_evictions() {
+        fn check<T: CachePolicy>(make_shard: fn(usize) -> T) {
+            let (cache, recorder) = listened(make_shard);
+            let (a, b) = colliding(&cache);
+            cache.insert(a.clone(), resp());
+            assert!(cache.get(&a).is_some());
+            assert!(cache.get("missing").is_none());
+            cache.insert(b.clone(), resp());
+            assert_eq!(
+                recorder.take(),
+                [
+                    event("insert", &a),
+                    event("hit", &a),
+                    event("evict", &a),
+                    event("insert", &b)
+                ],
+                "{}",
+                cache.name()
+            );
+
+            // Explicit removals aren't reported
+            cache.remove(&b);
+            cache.clear();
+            assert!(recorder.take().is_empty(), "{}", cache.name());
+        }
+        check(SieveCache::new);
+        check(LruCache::new);
+        check(FifoCache::new);
+    }
+
+    #[test]
+    fn listener_hears_expiry() {
+        let (cache, recorder) = listened(SieveCache::new);
+        let (a, b) = colliding(&cache);
+        let expired = || CachedResponse {
+            ttl: Duration::ZERO,
+            ..resp()
+        };
+        cache.insert(a.clone(), expired());
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(cache.get(&a).is_none());
+        assert_eq!(recorder.take(), [event("insert", &a), event("expire", &a)]);
+
+        // Pushed out past its TTL counts as expired too
+        cache.insert(a.clone(), expired());
+        std::thread::sleep(Duration::from_millis(1));
+        cache.insert(b.clone(), resp());
+        assert_eq!(
+            recorder.take(),
+            [
+                event("insert", &a),
+                event("expire", &a),
+                event("insert", &b)
+            ]
+        );
+    }
+
+    #[test]
+    fn listener_hears_batches_updates_and_shrinks() {
+        let (cache, recorder) = listened(LruCache::new);
+        let (a, b) = colliding(&cache);
+        cache.insert_many(vec![(a.clone(), resp()), (b.clone(), resp())]);
+        assert_eq!(
+            recorder.take(),
+            [event("evict", &a), event("insert", &a), event("insert", &b)]
+        );
+
+        cache.get_many(&[&a, &b]);
+        cache.update(&b, |_| (None, ()));
+        cache.update(&b, |_| (Some(resp()), ()));
+        assert_eq!(recorder.take(), [event("hit", &b), event("insert", &b)]);
+
+        let cache = ShardedCache::new(6400, SieveCache::new)
+            .with_listener(Arc::clone(&recorder) as Arc<dyn CacheListener>);
+        for i in 0..6400 {
+            cache.insert(format!("k{i}"), resp());
+        }
+        recorder.take();
+        let before = cache.len();
+        cache.resize(64);
+        let evicted = recorder.take();
+        assert_eq!(evicted.len(), before - cache.len());
+        assert!(evicted.iter().all(|(name, _)| *name == "evict"));
+    }
+
+    #[test]
+    fn iter_and_retain_cover_every_shard() {
+        let cache = ShardedCache::new(6400, LruCache::new);
+        for i in 0..1000 {
+            cache.insert(
+                format!("{}:{i}", if i % 4 == 0 { "drop" } else { "keep" }),
+                resp(),
+            );
+        }
+        let mut keys: Vec<String> = cache.iter().map(|e| e.key).collect();
+        keys.sort();
+        keys.dedup();
+        assert_eq!(keys.len(), 1000);
+
+        assert_eq!(cache.retain(|key, _| !key.starts_with("drop:")), 250);
+        assert_eq!(cache.len(), 750);
+        assert!(cache.iter().all(|e| e.key.starts_with("keep:")));
+        assert_eq!(cache.stats().evictions, 0);
+        assert!(cache.check_invariants().is_empty());
+    }
+
     #[test]
     fn eviction_within_shard() {
         // Small total capacity — each shard gets very few slots
@@ -230,6 +1716,88 @@ mod tests {
         assert!(stats.evictions > 0, "expected evictions to occur");
     }
 
+    #[test]
+    fn max_memory_bounds_footprint() {
+        fn check<T: CachePolicy>(make_shard: fn(usize) -> T) {
+            let cache = ShardedCache::new(6400, make_shard);
+            for i in 0..1000 {
+                cache.insert(format!("key-{i}"), resp());
+            }
+            let full = cache.memory_bytes();
+            assert_eq!(cache.stats().memory_bytes, full);
+            assert_eq!(cache.len(), 1000);
+
+            // Shrinking the bound trims shards that are over it right away
+            cache.set_max_memory(full / 2);
+            assert!(cache.memory_bytes() <= full / 2);
+            let kept = cache.len();
+            assert!(kept < 1000 && kept > 0, "kept {kept}");
+
+            // Inserts evict to stay under it, well short of the capacity
+            for i in 1000..3000 {
+                cache.insert(format!("key-{i}"), resp());
+            }
+            assert!(cache.memory_bytes() <= full / 2);
+            assert!(cache.len() < 1000);
+            assert!(cache.check_invariants().is_empty());
+
+            // Lifting it lets the cache fill up to capacity again
+            cache.set_max_memory(0);
+            for i in 0..3000 {
+                cache.insert(format!("key-{i}"), resp());
+            }
+            assert_eq!(cache.len(), 3000);
+            cache.clear();
+            assert_eq!(cache.memory_bytes(), 0);
+        }
+        check(SieveCache::new);
+        check(LruCache::new);
+        check(FifoCache::new);
+    }
+
+    #[test]
+    fn oversized_entry_is_evicted_after_insert() {
+        let (cache, recorder) = listened(SieveCache::new);
+        let cache = cache.with_max_memory(64 * 1024);
+        let mut big = resp();
+        big.body = Bytes::from(vec![0; 4096]);
+        let stored = cache.insert_and_get("big".into(), big);
+        assert_eq!(stored.body.len(), 4096);
+        assert!(cache.peek("big").is_none());
+        assert_eq!(
+            recorder.take(),
+            [event("insert", "big"), event("evict", "big")]
+        );
+    }
+
+    #[test]
+    fn try_insert_turns_away_oversized_entry() {
+        let (cache, recorder) = listened(SieveCache::new);
+        let cache = cache.with_max_memory(64 * 1024);
+        cache.insert("small".into(), resp());
+        recorder.take();
+
+        let mut big = resp();
+        big.body = Bytes::from(vec![0; 4096]);
+        let err = cache.try_insert("big".into(), big).unwrap_err();
+        assert!(matches!(err, CacheError::EntryTooLarge { max: 1024, .. }));
+        assert!(cache.peek("small").is_some());
+        assert!(recorder.take().is_empty());
+
+        assert_eq!(cache.try_insert("other".into(), resp()), Ok(()));
+        assert_eq!(recorder.take(), [event("insert", "other")]);
+    }
+
+    #[test]
+    fn try_new_checks_capacity() {
+        let err = ShardedCache::try_new(0, SieveCache::new).err();
+        assert_eq!(err, Some(CacheError::ZeroCapacity));
+        let max = (MAX_CAPACITY + 1) * NUM_SHARDS - 1;
+        let err = ShardedCache::try_new(max + 1, SieveCache::new).err();
+        assert!(matches!(err, Some(CacheError::CapacityTooLarge { .. })));
+        assert!(ShardedCache::try_new(64, SieveCache::new).is_ok());
+    }
+
     #[test]
     fn ttl_expiration_through_sharded() {
         let cache = ShardedCache::new(1024, SieveCache::new);
@@ -238,16 +1806,51 @@ mod tests {
             "expired".into(),
             CachedResponse {
                 status: 200,
-                headers: vec![],
+                headers: Default::default(),
                 body: Bytes::from_static(b"old"),
                 inserted_at: Instant::now() - Duration::from_secs(120),
                 ttl: Duration::from_secs(60),
+                hits: Default::default(),
+                source: Default::default(),
+                must_revalidate: false,
             },
         );
 
         assert!(cache.get("expired").is_none());
     }
 
+    #[test]
+    fn resize_grow_and_shrink() {
+        let cache = ShardedCache::new(640, LruCache::new);
+        for i in 0..300 {
+            cache.insert(format!("key-{}", i), resp());
+        }
+        let before = cache.len();
+
+        cache.resize(1280);
+        assert_eq!(cache.capacity(), 1280);
+        assert_eq!(cache.len(), before);
+
+        cache.resize(64);
+        assert_eq!(cache.capacity(), 64);
+        assert!(cache.len() <= 64);
+    }
+
+    #[test]
+    fn try_resize_refuses_what_try_new_refuses() {
+        let cache = ShardedCache::new(640, SieveCache::new);
+        assert_eq!(cache.try_resize(0), Err(CacheError::ZeroCapacity));
+        let too_many = (MAX_CAPACITY + 1) * NUM_SHARDS;
+        assert!(matches!(
+            cache.try_resize(too_many),
+            Err(CacheError::CapacityTooLarge { .. })
+        ));
+        assert_eq!(cache.capacity(), 640);
+
+        assert_eq!(cache.try_resize(1280), Ok(()));
+        assert_eq!(cache.capacity(), 1280);
+    }
+
     #[test]
     fn concurrent_access() {
         use std::sync::Arc;
@@ -287,6 +1890,312 @@ mod tests {
         assert!(stats.hits + stats.misses > 0);
     }
 
+    #[test]
+    fn invariants_hold_under_mixed_workload() {
+        fn exercise<T: CachePolicy>(cache: ShardedCache<T>) {
+            for i in 0..5000 {
+                let key = format!("key-{}", i % 700);
+                match i % 5 {
+                    0 => {
+                        cache.remove(&key);
+                    }
+                    1 | 2 => cache.insert(key, resp()),
+                    _ => {
+                        cache.get(&key);
+                    }
+                }
+                if i == 2500 {
+                    cache.resize(128);
+                }
+            }
+            assert_eq!(cache.check_invariants(), vec![]);
+        }
+
+        exercise(ShardedCache::new(512, SieveCache::new));
+        exercise(ShardedCache::new(512, LruCache::new));
+        exercise(ShardedCache::new(512, FifoCache::new));
+
+        #[cfg(feature = "experimental")]
+        {
+            use crate::experimental::{clock, s3fifo, sieve2};
+            exercise(ShardedCache::new(512, sieve2::Sieve2Cache::new));
+            exercise(ShardedCache::new(512, clock::ClockCache::new));
+            exercise(ShardedCache::new(512, s3fifo::S3FifoCache::new));
+        }
+    }
+
+    #[test]
+    fn hit_count_is_shared_by_readers() {
+        let cache = ShardedCache::new(1024, SieveCache::new);
+        cache.insert("k".into(), resp());
+
+        assert_eq!(cache.get("k").unwrap().record_hit(), 1);
+        assert_eq!(cache.get("k").unwrap().record_hit(), 2);
+        assert_eq!(cache.peek("k").unwrap().hits.get(), 2);
+
+        // A copied entry carries its count over
+        let copy = (*cache.peek("k").unwrap()).clone();
+        assert_eq!(copy.hits.get(), 2);
+    }
+
+    #[test]
+    fn insert_source_is_kept() {
+        let cache = ShardedCache::new(1024, SieveCache::new);
+        cache.insert("fill".into(), resp());
+        cache.insert("set".into(), resp().with_source(InsertSource::Resp));
+
+        assert_eq!(cache.peek("fill").unwrap().source, InsertSource::Http);
+        assert_eq!(cache.get("set").unwrap().source, InsertSource::Resp);
+        assert_eq!(InsertSource::Resp.as_str(), "resp");
+    }
+
+    #[test]
+    fn partitions_stay_on_their_shards() {
+        let cache = ShardedCache::new(64_000, SieveCache::new)
+            .with_partitioning(Partitioning::new('/', 1, 4));
+        for i in 0..500 {
+            cache.insert(format!("GET:/images/{i}.png"), resp());
+            cache.insert(format!("GET:/api/users/{i}"), resp());
+        }
+        cache.insert("GET:/images?page=2".into(), resp());
+        cache.insert("no-partition".into(), resp());
+
+        let partitioning = cache.partitioning().unwrap();
+        let images: Vec<usize> = partitioning.shard_run("images").collect();
+        for (i, shard) in cache.shards.iter().enumerate() {
+            let shard = shard.read();
+            for key in shard.keys() {
+                if partitioning.partition_of(&key) == Some("images") {
+                    assert!(images.contains(&i), "{key} landed on shard {i}");
+                }
+            }
+        }
+        assert_eq!(
+            partitioning.partition_of("GET:/images?page=2"),
+            Some("images")
+        );
+        assert_eq!(partitioning.partition_of("GET:/"), None);
+        assert!(cache.get("GET:/images/7.png").is_some());
+        assert!(cache.get("no-partition").is_some());
+    }
+
+    #[test]
+    fn clear_partition_removes_only_that_partition() {
+        let cache =
+            ShardedCache::new(6400, LruCache::new).with_partitioning(Partitioning::new(':', 0, 2));
+        for i in 0..100 {
+            cache.insert(format!("acme:/u/{i}"), resp());
+            cache.insert(format!("globex:/u/{i}"), resp());
+        }
+
+        assert_eq!(cache.clear_partition("acme"), Some(100));
+        assert_eq!(cache.len(), 100);
+        assert!(cache.peek("acme:/u/1").is_none());
+        assert!(cache.peek("globex:/u/1").is_some());
+        assert_eq!(cache.clear_partition("acme"), Some(0));
+
+        let unpartitioned = ShardedCache::new(64, LruCache::new);
+        assert_eq!(unpartitioned.clear_partition("acme"), None);
+    }
+
+    #[test]
+    fn resize_partition_touches_only_its_shards() {
+        let cache =
+            ShardedCache::new(6400, FifoCache::new).with_partitioning(Partitioning::new(':', 0, 4));
+        for i in 0..400 {
+            cache.insert(format!("acme:{i}"), resp());
+        }
+
+        assert!(cache.resize_partition("acme", 40));
+        // 4 shards of 10 replace 4 of 100
+        assert_eq!(cache.capacity(), 6400 - 400 + 40);
+        assert!(cache.len() <= 40);
+        assert_eq!(cache.check_invariants(), vec![]);
+    }
+
+    #[test]
+    fn reports_a_dominant_partition() {
+        let cache = ShardedCache::new(6400, SieveCache::new)
+            .with_partitioning(Partitioning::new(':', 0, 2));
+        assert_eq!(cache.partition_skew(0.5), None);
+
+        for i in 0..50 {
+            cache.insert(format!("hot:{i}"), resp());
+            cache.insert(format!("cold-{i}:x"), resp());
+        }
+        for i in 0..1000 {
+            cache.get(&format!("hot:{}", i % 50));
+        }
+        for i in 0..50 {
+            cache.get(&format!("cold-{i}:x"));
+        }
+
+        let skew = cache.partition_skew(0.5).unwrap();
+        assert_eq!(skew.partition, "hot");
+        assert!(skew.share > 0.9, "share {}", skew.share);
+        assert_eq!(skew.expected, 2.0 / 64.0);
+        assert_eq!(cache.partition_skew(0.99), None);
+    }
+
+    /// Keys under `img/` may fill 40% of each shard.
+    struct Images;
+
+    impl KeyQuotas for Images {
+        fn quota(&self, key: &str) -> Option<Quota> {
+            key.starts_with("img/").then(|| Quota {
+                group: "img".into(),
+                max_share: 0.4,
+            })
+        }
+    }
+
+    #[test]
+    fn quota_keeps_a_group_from_evicting_the_rest() {
+        fn check<T: CachePolicy>(make_shard: fn(usize) -> T) {
+            let cache = ShardedCache::new(6400, make_shard).with_quotas(Arc::new(Images));
+            for i in 0..4000 {
+                cache.insert(format!("page/{i}"), resp());
+            }
+            for i in 0..6000 {
+                cache.insert(format!("img/{i}"), resp());
+            }
+
+            let images = cache.iter().filter(|e| e.key.starts_with("img/")).count();
+            assert!(images <= 40 * 64, "{images} images");
+            let pages = cache.len() - images;
+            assert!(pages > 3500, "only {pages} pages left");
+            let usage = cache.quota_usage()["img"];
+            assert_eq!(usage.entries, images);
+            assert!(usage.evictions >= 6000 - 40 * 64);
+            assert!(cache.check_invariants().is_empty());
+
+            cache.remove("img/5999");
+            cache.retain(|key, _| key != "img/5998");
+            assert_eq!(cache.quota_usage()["img"].entries, images - 2);
+            cache.clear();
+            assert_eq!(cache.quota_usage()["img"].entries, 0);
+        }
+        check(SieveCache::new);
+        check(LruCache::new);
+        check(FifoCache::new);
+    }
+
+    #[test]
+    fn quota_evicts_the_groups_unvisited_entries_first() {
+        // One shard's worth of keys is enough: every key lands on one shard
+        let cache = ShardedCache::new(640, SieveCache::new)
+            .with_partitioning(Partitioning::new('/', 5, 1))
+            .with_quotas(Arc::new(Images));
+        let shard = |i: usize| format!("img/{i}/a/b/c/x");
+        for i in 0..4 {
+            cache.insert(shard(i), resp());
+        }
+        cache.get(&shard(0));
+        // Limit is 4 (40% of 10): the fifth evicts the oldest unvisited
+        cache.insert(shard(4), resp());
+        assert!(cache.peek(&shard(0)).is_some());
+        assert!(cache.peek(&shard(1)).is_none());
+        assert_eq!(cache.quota_usage()["img"].entries, 4);
+        assert_eq!(cache.quota_usage()["img"].evictions, 1);
+    }
+
+    /// `Images`, counting how often it's asked.
+    #[derive(Default)]
+    struct CountedImages(std::sync::atomic::AtomicUsize);
+
+    impl KeyQuotas for CountedImages {
+        fn quota(&self, key: &str) -> Option<Quota> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Images.quota(key)
+        }
+    }
+
+    #[test]
+    fn quota_groups_are_resolved_once_per_key() {
+        let quotas = Arc::new(CountedImages::default());
+        let cache = ShardedCache::new(640, SieveCache::new)
+            .with_partitioning(Partitioning::new('/', 5, 1))
+            .with_quotas(Arc::clone(&quotas) as Arc<dyn KeyQuotas>);
+        let key = |i: usize| format!("img/{i}/a/b/c/x");
+        for i in 0..100 {
+            cache.insert(key(i), resp());
+        }
+        // Evicting 96 of them for the quota asks nothing more
+        assert_eq!(quotas.0.load(Ordering::Relaxed), 100);
+        assert_eq!(cache.quota_usage()["img"].entries, 4);
+
+        // Replacing a member neither asks nor counts it twice, and moves it
+        // to the back of the group's queue
+        for _ in 0..100 {
+            cache.insert(key(97), resp());
+        }
+        assert_eq!(quotas.0.load(Ordering::Relaxed), 100);
+        assert_eq!(cache.quota_usage()["img"].entries, 4);
+        let index = cache.quota_usage[cache.shard_index(&key(0))].lock();
+        let queue = &index.groups["img"].queue;
+        assert!(
+            queue.len() <= 2 * 4 + QUOTA_SAMPLE,
+            "{} queued",
+            queue.len()
+        );
+        drop(index);
+
+        cache.insert(key(100), resp());
+        assert!(cache.peek(&key(96)).is_none());
+        assert!(cache.peek(&key(97)).is_some());
+        cache.remove(&key(97));
+        assert_eq!(cache.quota_usage()["img"].entries, 3);
+    }
+
+    #[test]
+    fn quota_evicts_an_expired_member_first() {
+        let cache = ShardedCache::new(640, SieveCache::new)
+            .with_partitioning(Partitioning::new('/', 5, 1))
+            .with_quotas(Arc::new(Images));
+        let key = |i: usize| format!("img/{i}/a/b/c/x");
+        for i in 0..3 {
+            cache.insert(key(i), resp());
+        }
+        let mut stale = resp();
+        stale.inserted_at = Instant::now() - Duration::from_secs(120);
+        cache.insert(key(3), stale);
+        cache.insert(key(4), resp());
+        assert!(cache.peek(&key(3)).is_none());
+        assert!(cache.peek(&key(0)).is_some());
+        assert_eq!(cache.quota_usage()["img"].entries, 4);
+    }
+
+    #[test]
+    fn full_shard_evicts_down_to_the_low_watermark() {
+        fn check<T: CachePolicy>(make_shard: fn(usize) -> T) {
+            // Every key on one shard of 10
+            let cache = ShardedCache::new(640, make_shard)
+                .with_partitioning(Partitioning::new('/', 0, 1))
+                .with_low_watermark(0.7);
+            let key = |i: usize| format!("p/{i}");
+            for i in 0..10 {
+                cache.insert(key(i), resp());
+            }
+            assert_eq!(cache.len(), 10);
+            // Replacing an entry never evicts
+            cache.insert(key(9), resp());
+            assert_eq!(cache.len(), 10);
+
+            // Full: down to 7, then the new entry
+            cache.insert(key(10), resp());
+            assert_eq!(cache.len(), 8);
+            assert_eq!(cache.stats().evictions, 3);
+            cache.insert(key(11), resp());
+            cache.insert(key(12), resp());
+            assert_eq!(cache.len(), 10);
+            assert_eq!(cache.stats().evictions, 3);
+            assert!(cache.check_invariants().is_empty());
+        }
+        check(SieveCache::new);
+        check(LruCache::new);
+        check(FifoCache::new);
+    }
+
     #[test]
     fn is_send_and_sync() {
         fn assert_send_sync<T: Send + Sync>() {}
@@ -295,3 +2204,153 @@ mod tests {
         assert_send_sync::<ShardedCache<FifoCache>>();
     }
 }
+
+/// Every interleaving of a few threads sharing one shard, under loom's
+/// locks and atomics. Run with
+/// `cargo test -p colander-cache --features loom --release --lib loom_`;
+/// the other tests don't run under the `loom` feature.
+#[cfg(all(test, feature = "loom"))]
+mod loom_tests {
+    use super::*;
+    use crate::lru::LruCache;
+    use crate::sieve::SieveCache;
+    use bytes::Bytes;
+    use loom::thread;
+    use std::time::{Duration, Instant};
+
+    fn resp(body: &'static str) -> CachedResponse {
+        CachedResponse {
+            status: 200,
+            headers: Default::default(),
+            body: Bytes::from_static(body.as_bytes()),
+            inserted_at: Instant::now(),
+            ttl: Duration::from_secs(60),
+            hits: Default::default(),
+            source: Default::default(),
+            must_revalidate: false,
+        }
+    }
+
+    /// `n` keys that all land on shard 0.
+    fn same_shard<T: CachePolicy>(cache: &ShardedCache<T>, n: usize) -> Vec<String> {
+        (0..)
+            .map(|i| format!("key-{i}"))
+            .filter(|key| cache.shard_index(key) == 0)
+            .take(n)
+            .collect()
+    }
+
+    /// Two readers set the SIEVE visited bit under the read side of the
+    /// lock while a writer evicts from the same shard: the shard stays
+    /// consistent and a key is either found whole or not at all.
+    #[test]
+    fn loom_sieve_gets_race_evicting_insert() {
+        loom::model(|| {
+            let cache = Arc::new(ShardedCache::new(2 * NUM_SHARDS, SieveCache::new));
+            let keys = same_shard(&cache, 3);
+            cache.insert(keys[0].clone(), resp("a"));
+            cache.insert(keys[1].clone(), resp("b"));
+
+            let readers: Vec<_> = [0, 1]
+                .map(|i| {
+                    let (cache, key) = (Arc::clone(&cache), keys[i].clone());
+                    thread::spawn(move || {
+                        if let Some(entry) = cache.peek(&key) {
+                            assert_eq!(entry.body, ["a", "b"][i]);
+                        }
+                        cache.get(&key).map(|entry| entry.body.clone())
+                    })
+                })
+                .into();
+            cache.insert(keys[2].clone(), resp("c"));
+
+            for (i, reader) in readers.into_iter().enumerate() {
+                if let Some(body) = reader.join().unwrap() {
+                    assert_eq!(body, ["a", "b"][i]);
+                }
+            }
+            assert_eq!(cache.len(), 2);
+            assert!(cache.peek(&keys[2]).is_some());
+            assert!(cache.check_invariants().is_empty());
+        });
+    }
+
+    /// An insert and a remove of the same key, with a reader in between:
+    /// whichever lands last wins and the counts agree with it.
+    #[test]
+    fn loom_insert_and_remove_same_key() {
+        loom::model(|| {
+            let cache = Arc::new(ShardedCache::new(2 * NUM_SHARDS, SieveCache::new));
+            let key = same_shard(&cache, 1).remove(0);
+            cache.insert(key.clone(), resp("old"));
+
+            let writer = {
+                let (cache, key) = (Arc::clone(&cache), key.clone());
+                thread::spawn(move || cache.insert(key, resp("new")))
+            };
+            let remover = {
+                let (cache, key) = (Arc::clone(&cache), key.clone());
+                thread::spawn(move || cache.remove(&key))
+            };
+            if let Some(entry) = cache.get(&key) {
+                assert!(entry.body == "old" || entry.body == "new");
+            }
+            writer.join().unwrap();
+            remover.join().unwrap();
+
+            let left = cache.peek(&key);
+            assert_eq!(cache.len(), usize::from(left.is_some()));
+            if let Some(entry) = left {
+                assert_eq!(entry.body, "new");
+            }
+            assert!(cache.check_invariants().is_empty());
+        });
+    }
+
+    /// Hits recorded on one shared entry from two threads are both counted,
+    /// in the entry and in the shard's stats.
+    #[test]
+    fn loom_concurrent_hits_are_counted() {
+        loom::model(|| {
+            let cache = Arc::new(ShardedCache::new(2 * NUM_SHARDS, SieveCache::new));
+            let key = same_shard(&cache, 1).remove(0);
+            cache.insert(key.clone(), resp("a"));
+
+            let readers: Vec<_> = (0..2)
+                .map(|_| {
+                    let (cache, key) = (Arc::clone(&cache), key.clone());
+                    thread::spawn(move || cache.get(&key).unwrap().record_hit())
+                })
+                .collect();
+            let mut counts: Vec<u64> = readers.into_iter().map(|r| r.join().unwrap()).collect();
+            counts.sort_unstable();
+            assert_eq!(counts, [1, 2]);
+            assert_eq!(cache.peek(&key).unwrap().hits.get(), 2);
+            assert_eq!(cache.stats().hits, 2);
+        });
+    }
+
+    /// LRU's move-to-front on a hit against an insert that evicts the tail.
+    #[test]
+    fn loom_lru_get_races_evicting_insert() {
+        loom::model(|| {
+            let cache = Arc::new(ShardedCache::new(2 * NUM_SHARDS, LruCache::new));
+            let keys = same_shard(&cache, 3);
+            cache.insert(keys[0].clone(), resp("a"));
+            cache.insert(keys[1].clone(), resp("b"));
+
+            let reader = {
+                let (cache, key) = (Arc::clone(&cache), keys[0].clone());
+                thread::spawn(move || cache.get(&key).is_some())
+            };
+            cache.insert(keys[2].clone(), resp("c"));
+            let hit = reader.join().unwrap();
+
+            // A hit before the insert saves "a" and evicts "b"; after it,
+            // "a" was the LRU entry and is gone
+            assert_eq!(cache.peek(&keys[0]).is_some(), hit);
+            assert_eq!(cache.peek(&keys[1]).is_some(), !hit);
+            assert!(cache.check_invariants().is_empty());
+        });
+    }
+}
@@ -1,5 +1,9 @@
 use crate::arena::{Arena, Node, NIL};
-use crate::traits::{CachePolicy, CacheStats, CachedResponse};
+use crate::error::CacheError;
+use crate::traits::{
+    CachePolicy, CacheStats, CachedResponse, EntryInfo, EvictionStats, RemovalLog, Removed,
+    ScanStats,
+};
 use std::collections::HashMap;
 use std::sync::Arc;
 
@@ -14,7 +18,8 @@ use std::sync::Arc;
 /// from popular objects, enabling quick demotion of unpopular entries.
 ///
 /// Cache hits only flip a visited bit (AtomicBool) — no list mutation required.
-/// This means hits can be served under a read lock (or lock-free with sharding).
+/// `get` still takes `&mut self` to count hits and drop expired entries, so
+/// `ShardedCache` serves it under the shard's write lock.
 pub struct SieveCache {
     arena: Arena,
     map: HashMap<String, u32>,
@@ -23,12 +28,22 @@ pub struct SieveCache {
     hits: u64,
     misses: u64,
     evictions: u64,
+    evicted: EvictionStats,
+    scan: ScanStats,
+    removals: RemovalLog,
 }
 
 impl SieveCache {
+    /// # Panics
+    ///
+    /// If `capacity` is 0 or over `arena::MAX_CAPACITY`; see `try_new`.
     pub fn new(capacity: usize) -> Self {
-        assert!(capacity > 0, "cache capacity must be > 0");
-        Self {
+        Self::try_new(capacity).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    pub fn try_new(capacity: usize) -> Result<Self, CacheError> {
+        CacheError::check_capacity(capacity)?;
+        Ok(Self {
             arena: Arena::new(capacity),
             map: HashMap::with_capacity(capacity),
             hand: NIL,
@@ -36,7 +51,10 @@ impl SieveCache {
             hits: 0,
             misses: 0,
             evictions: 0,
-        }
+            evicted: EvictionStats::default(),
+            scan: ScanStats::default(),
+            removals: RemovalLog::default(),
+        })
     }
 
     /// The SIEVE eviction algorithm.
@@ -48,24 +66,20 @@ impl SieveCache {
     ///
     /// The hand wraps around to the tail when it reaches NIL (head).
     fn evict_one(&mut self) {
-        // If hand is NIL, start from tail
-        if self.hand == NIL {
-            self.hand = self.arena.tail;
-        }
-
         loop {
             if self.hand == NIL {
-                // Wrapped around — start from tail again
+                // Past the head (or not started yet) — start from tail again
                 self.hand = self.arena.tail;
-            }
-
-            if self.hand == NIL {
-                // Cache is empty, nothing to evict
-                return;
+                if self.hand == NIL {
+                    // Cache is empty, nothing to evict
+                    return;
+                }
+                self.scan.hand_wraps += 1;
             }
 
             let index = self.hand;
             let node = self.arena.get(index).unwrap();
+            self.scan.scanned += 1;
 
             // Always evict expired entries
             if node.value.is_expired() {
@@ -74,12 +88,17 @@ impl SieveCache {
                 let evicted = self.arena.remove(index).unwrap();
                 self.map.remove(&evicted.key);
                 self.evictions += 1;
+                self.scan.evictions += 1;
+                self.evicted
+                    .record(evicted.resident_for(), evicted.was_hit());
+                self.removals.evicted(evicted.key, evicted.value);
                 return;
             }
 
             if node.is_visited() {
                 // Retain: clear visited bit, move hand to prev
                 node.clear_visited();
+                self.scan.visited_clears += 1;
                 self.hand = node.prev;
                 // Keep scanning
             } else {
@@ -88,6 +107,10 @@ impl SieveCache {
                 let evicted = self.arena.remove(index).unwrap();
                 self.map.remove(&evicted.key);
                 self.evictions += 1;
+                self.scan.evictions += 1;
+                self.evicted
+                    .record(evicted.resident_for(), evicted.was_hit());
+                self.removals.evicted(evicted.key, evicted.value);
                 return;
             }
         }
@@ -106,13 +129,16 @@ impl CachePolicy for SieveCache {
                 if self.hand == index {
                     self.hand = node.prev;
                 }
-                self.arena.remove(index);
+                if let Some(node) = self.arena.remove(index) {
+                    self.removals.expired(node.key, node.value);
+                }
                 return None;
             }
             self.hits += 1;
             // SIEVE: just flip the visited bit. No list mutation!
             // In the sharded version, this is the only operation on the hot path.
             node.mark_visited();
+            node.mark_hit();
             Some(Arc::clone(&node.value))
         } else {
             self.misses += 1;
@@ -120,7 +146,23 @@ impl CachePolicy for SieveCache {
         }
     }
 
+    fn peek(&self, key: &str) -> Option<Arc<CachedResponse>> {
+        let index = *self.map.get(key)?;
+        self.arena.get(index).map(|node| Arc::clone(&node.value))
+    }
+
+    fn visited(&self, key: &str) -> bool {
+        self.map
+            .get(key)
+            .and_then(|&index| self.arena.get(index))
+            .is_some_and(Node::is_visited)
+    }
+
     fn insert(&mut self, key: String, value: CachedResponse) {
+        let _ = self.try_insert(key, value);
+    }
+
+    fn try_insert(&mut self, key: String, value: CachedResponse) -> Result<(), CacheError> {
         // If key already exists, remove old entry
         if let Some(&old_index) = self.map.get(&key) {
             // Fix hand if it points to the node we're about to remove
@@ -139,9 +181,9 @@ impl CachePolicy for SieveCache {
 
         // Insert new object at head (not visited initially)
         let node = Node::new(key.clone(), value);
-        if let Some(index) = self.arena.push_head(node) {
-            self.map.insert(key, index);
-        }
+        let index = self.arena.push_head(node).ok_or(CacheError::Full)?;
+        self.map.insert(key, index);
+        Ok(())
     }
 
     fn remove(&mut self, key: &str) -> bool {
@@ -157,6 +199,20 @@ impl CachePolicy for SieveCache {
         }
     }
 
+    fn keys(&self) -> Vec<String> {
+        self.map.keys().cloned().collect()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = EntryInfo> + '_> {
+        Box::new(self.arena.iter().map(Node::info))
+    }
+
+    fn clear(&mut self) {
+        self.arena.clear();
+        self.map.clear();
+        self.hand = NIL;
+    }
+
     fn len(&self) -> usize {
         self.arena.len()
     }
@@ -165,6 +221,35 @@ impl CachePolicy for SieveCache {
         self.capacity
     }
 
+    fn resize(&mut self, capacity: usize) {
+        assert!(capacity > 0, "cache capacity must be > 0");
+        self.arena.grow(capacity);
+        self.capacity = capacity;
+        while self.arena.len() > self.capacity {
+            self.evict_one();
+        }
+    }
+
+    fn record_removals(&mut self, enabled: bool) {
+        self.removals.set_enabled(enabled);
+    }
+
+    fn take_removed(&mut self) -> Vec<Removed> {
+        self.removals.take()
+    }
+
+    fn memory_bytes(&self) -> usize {
+        self.arena.bytes()
+    }
+
+    fn evict(&mut self) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        self.evict_one();
+        true
+    }
+
     fn name(&self) -> &'static str {
         "SIEVE"
     }
@@ -174,9 +259,35 @@ impl CachePolicy for SieveCache {
             hits: self.hits,
             misses: self.misses,
             evictions: self.evictions,
+            evicted: self.evicted.clone(),
             current_size: self.arena.len(),
             capacity: self.capacity,
+            memory_bytes: self.arena.bytes(),
+            scan: self.scan,
+        }
+    }
+
+    fn reset_stats(&mut self) {
+        self.hits = 0;
+        self.misses = 0;
+        self.evictions = 0;
+        self.evicted = EvictionStats::default();
+        self.scan = ScanStats::default();
+    }
+
+    fn check_invariants(&self) -> Result<(), String> {
+        self.arena.check_map(&self.map)?;
+        if self.arena.len() > self.capacity {
+            return Err(format!(
+                "{} entries over capacity {}",
+                self.arena.len(),
+                self.capacity
+            ));
         }
+        if self.hand != NIL && self.arena.get(self.hand).is_none() {
+            return Err(format!("hand points at free slot {}", self.hand));
+        }
+        Ok(())
     }
 }
 
@@ -189,10 +300,13 @@ mod tests {
     fn resp(ttl_secs: u64) -> CachedResponse {
         CachedResponse {
             status: 200,
-            headers: vec![],
+            headers: Default::default(),
             body: Bytes::from_static(b"test"),
             inserted_at: Instant::now(),
             ttl: Duration::from_secs(ttl_secs),
+            hits: Default::default(),
+            source: Default::default(),
+            must_revalidate: false,
         }
     }
 
@@ -209,6 +323,12 @@ mod tests {
         assert!(cache.get("d").is_none());
     }
 
+    #[test]
+    fn try_new_refuses_zero_capacity() {
+        assert_eq!(SieveCache::try_new(0).err(), Some(CacheError::ZeroCapacity));
+        assert!(SieveCache::try_new(1).is_ok());
+    }
+
     #[test]
     fn evicts_unvisited_from_tail() {
         let mut cache = SieveCache::new(3);
@@ -246,6 +366,34 @@ mod tests {
         assert!(cache.get("d").is_some());
     }
 
+    #[test]
+    fn scan_stats_count_the_hands_work() {
+        let mut cache = SieveCache::new(2);
+        cache.insert("a".into(), resp(60));
+        cache.insert("b".into(), resp(60));
+        cache.get("a");
+        cache.get("b");
+
+        // Both visited: the hand clears a and b, runs off the head, wraps
+        // to the tail and evicts a on its second pass
+        cache.insert("c".into(), resp(60));
+        let scan = cache.stats().scan;
+        assert_eq!(
+            scan,
+            ScanStats {
+                evictions: 1,
+                scanned: 3,
+                visited_clears: 2,
+                hand_wraps: 2,
+            }
+        );
+        assert_eq!(scan.mean_scan_length(), 3.0);
+        assert_eq!(scan.visited_clears_per_eviction(), 2.0);
+
+        cache.reset_stats();
+        assert_eq!(cache.stats().scan, ScanStats::default());
+    }
+
     #[test]
     fn hand_continues_from_last_position() {
         let mut cache = SieveCache::new(3);
@@ -326,6 +474,102 @@ mod tests {
         assert!(fifo.get("a").is_none(), "FIFO should evict oldest 'a'");
     }
 
+    #[test]
+    fn peek_does_not_mark_visited() {
+        let mut cache = SieveCache::new(3);
+        cache.insert("a".into(), resp(60));
+        cache.insert("b".into(), resp(60));
+        cache.insert("c".into(), resp(60));
+
+        // Peeking the tail must not protect it from eviction
+        assert!(cache.peek("a").is_some());
+        cache.insert("d".into(), resp(60));
+        assert!(cache.peek("a").is_none());
+        assert_eq!(cache.stats().hits, 0);
+    }
+
+    #[test]
+    fn iter_lists_queue_order_without_side_effects() {
+        let mut cache = SieveCache::new(3);
+        cache.insert("a".into(), resp(60));
+        cache.insert("b".into(), resp(0));
+        cache.insert("c".into(), resp(60));
+        cache.get("a");
+
+        let entries: Vec<EntryInfo> = cache.iter().collect();
+        let keys: Vec<&str> = entries.iter().map(|e| e.key.as_str()).collect();
+        assert_eq!(keys, ["c", "b", "a"]);
+        assert_eq!(
+            entries.iter().map(|e| e.visited).collect::<Vec<_>>(),
+            [false, false, true]
+        );
+        assert_eq!(entries[1].ttl_remaining, Duration::ZERO);
+        assert!(entries[2].ttl_remaining > Duration::from_secs(59));
+        assert_eq!(entries[0].size, 4);
+        assert_eq!(cache.stats().hits, 1);
+    }
+
+    #[test]
+    fn retain_removes_rejected_entries() {
+        let mut cache = SieveCache::new(4);
+        for k in ["user:1", "user:2", "page:1", "page:2"] {
+            cache.insert(k.into(), resp(60));
+        }
+        let removed = cache.retain(&mut |key, _| !key.starts_with("user:"));
+        assert_eq!(removed, 2);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.peek("page:1").is_some());
+        assert_eq!(cache.stats().evictions, 0);
+        cache.check_invariants().unwrap();
+
+        // The hand survives losing entries, and freed slots are reused
+        for k in ["a", "b", "c"] {
+            cache.insert(k.into(), resp(60));
+        }
+        assert_eq!(cache.len(), 4);
+        cache.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn resize_shrink_keeps_visited() {
+        let mut cache = SieveCache::new(4);
+        for k in ["a", "b", "c", "d"] {
+            cache.insert(k.into(), resp(60));
+        }
+        cache.get("a");
+        cache.get("b");
+
+        cache.resize(2);
+        assert_eq!(cache.capacity(), 2);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_some());
+        assert_eq!(cache.stats().evictions, 2);
+    }
+
+    #[test]
+    fn resize_grow_keeps_entries() {
+        let mut cache = SieveCache::new(2);
+        cache.insert("a".into(), resp(60));
+        cache.insert("b".into(), resp(60));
+
+        cache.resize(4);
+        cache.insert("c".into(), resp(60));
+        cache.insert("d".into(), resp(60));
+        assert_eq!(cache.len(), 4);
+        assert_eq!(cache.stats().evictions, 0);
+        assert!(cache.get("a").is_some());
+    }
+
+    #[test]
+    fn try_resize_refuses_zero() {
+        let mut cache = SieveCache::new(2);
+        cache.insert("a".into(), resp(60));
+        assert_eq!(cache.try_resize(0), Err(CacheError::ZeroCapacity));
+        assert_eq!(cache.capacity(), 2);
+        assert!(cache.get("a").is_some());
+    }
+
     #[test]
     fn explicit_remove() {
         let mut cache = SieveCache::new(3);
@@ -364,10 +608,13 @@ mod tests {
             "expired".into(),
             CachedResponse {
                 status: 200,
-                headers: vec![],
+                headers: Default::default(),
                 body: Bytes::from_static(b"old"),
                 inserted_at: Instant::now() - Duration::from_secs(120),
                 ttl: Duration::from_secs(60),
+                hits: Default::default(),
+                source: Default::default(),
+                must_revalidate: false,
             },
         );
 
@@ -381,10 +628,13 @@ mod tests {
             "will-expire".into(),
             CachedResponse {
                 status: 200,
-                headers: vec![],
+                headers: Default::default(),
                 body: Bytes::from_static(b"old"),
                 inserted_at: Instant::now() - Duration::from_secs(120),
                 ttl: Duration::from_secs(60),
+                hits: Default::default(),
+                source: Default::default(),
+                must_revalidate: false,
             },
         );
         // Visit it — would normally protect it
@@ -394,10 +644,13 @@ mod tests {
         let mut cache = SieveCache::new(2);
         let expired_resp = CachedResponse {
             status: 200,
-            headers: vec![],
+            headers: Default::default(),
             body: Bytes::from_static(b"old"),
             inserted_at: Instant::now() - Duration::from_secs(120),
             ttl: Duration::from_secs(60),
+            hits: Default::default(),
+            source: Default::default(),
+            must_revalidate: false,
         };
         cache.insert("a".into(), expired_resp);
         // Mark as visited by directly accessing the arena
@@ -412,6 +665,22 @@ mod tests {
         assert_eq!(cache.len(), 2);
     }
 
+    #[test]
+    fn evicts_one_hit_wonders_first() {
+        let mut cache = SieveCache::new(3);
+        cache.insert("a".into(), resp(60));
+        cache.insert("b".into(), resp(60));
+        cache.insert("c".into(), resp(60));
+        cache.get("a");
+        cache.insert("d".into(), resp(60)); // skips "a", evicts "b"
+        cache.insert("e".into(), resp(60)); // evicts "c"
+
+        assert!(cache.get("a").is_some());
+        let evicted = cache.stats().evicted;
+        assert_eq!((evicted.count, evicted.never_hit), (2, 2));
+        assert_eq!(evicted.one_hit_wonder_ratio(), 1.0);
+    }
+
     #[test]
     fn stats_tracking() {
         let mut cache = SieveCache::new(2);
@@ -1,4 +1,8 @@
+use crate::error::CacheError;
+use crate::sync::AtomicU64;
 use bytes::Bytes;
+use http::HeaderMap;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
@@ -6,26 +10,383 @@ use std::time::{Duration, Instant};
 #[derive(Clone, Debug)]
 pub struct CachedResponse {
     pub status: u16,
-    pub headers: Vec<(String, String)>,
+    /// Stored response headers, validated once at insert so a hit only
+    /// clones them: every value is a ref-counted `Bytes`.
+    pub headers: HeaderMap,
     pub body: Bytes,
+    /// When the response was generated, on this process's clock: insertion
+    /// time, backdated by any age it already had on arrival (see
+    /// `with_initial_age`).
     pub inserted_at: Instant,
+    /// Freshness lifetime, measured from `inserted_at`.
     pub ttl: Duration,
+    /// Times this entry has been served from cache.
+    pub hits: HitCounter,
+    /// What put this entry in the cache.
+    pub source: InsertSource,
+    /// The origin sent `must-revalidate` or `proxy-revalidate`: once expired,
+    /// the entry must not be served without revalidating it, not even when
+    /// the origin is down (RFC 9111 §5.2.2.2).
+    pub must_revalidate: bool,
 }
 
 impl CachedResponse {
     pub fn is_expired(&self) -> bool {
         self.inserted_at.elapsed() > self.ttl
     }
+
+    /// Current age: time since insertion plus any age it arrived with.
+    pub fn age(&self) -> Duration {
+        self.inserted_at.elapsed()
+    }
+
+    /// Account for a response that was already `age` old when it arrived
+    /// (RFC 9111 §4.2.3 corrected initial age), so it expires `age` earlier.
+    /// `Instant` can't always go back that far (e.g. shortly after boot);
+    /// whatever age doesn't fit is taken off `ttl` instead.
+    pub fn with_initial_age(mut self, age: Duration) -> Self {
+        let mut rest = age;
+        let mut step = age;
+        while !step.is_zero() {
+            match self.inserted_at.checked_sub(step) {
+                Some(born) => {
+                    self.inserted_at = born;
+                    rest -= step;
+                    step = step.min(rest);
+                }
+                None => step /= 2,
+            }
+        }
+        self.ttl = self.ttl.saturating_sub(rest);
+        self
+    }
+
+    /// Tag the entry with what inserted it (`Http` by default).
+    pub fn with_source(mut self, source: InsertSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Mark the entry as one never to serve stale (see `must_revalidate`).
+    pub fn with_must_revalidate(mut self, must_revalidate: bool) -> Self {
+        self.must_revalidate = must_revalidate;
+        self
+    }
+
+    /// Count a hit and return the new total.
+    pub fn record_hit(&self) -> u64 {
+        self.hits.increment()
+    }
+}
+
+/// How an entry got into the cache, so a surprising value can be traced back
+/// to whatever wrote it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum InsertSource {
+    /// Filled from upstream on an HTTP miss.
+    #[default]
+    Http,
+    /// Written with RESP `SET`.
+    Resp,
+    /// Written with memcached `set`.
+    Memcached,
+    /// Prefetched by the startup warmer.
+    Warmup,
+    /// Loaded from a snapshot.
+    Restore,
+    /// Refetched before it expired.
+    Refresh,
+}
+
+impl InsertSource {
+    pub const ALL: [InsertSource; 6] = [
+        InsertSource::Http,
+        InsertSource::Resp,
+        InsertSource::Memcached,
+        InsertSource::Warmup,
+        InsertSource::Restore,
+        InsertSource::Refresh,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            InsertSource::Http => "http",
+            InsertSource::Resp => "resp",
+            InsertSource::Memcached => "memcached",
+            InsertSource::Warmup => "warmup",
+            InsertSource::Restore => "restore",
+            InsertSource::Refresh => "refresh",
+        }
+    }
+}
+
+/// Per-entry hit counter, shared by every reader of the same `Arc<CachedResponse>`.
+///
+/// Cloning copies the current count, so an entry copied into another cache
+/// (e.g. a warm policy handoff) keeps its history.
+#[derive(Debug, Default)]
+pub struct HitCounter(AtomicU64);
+
+impl HitCounter {
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Add one and return the new count.
+    pub fn increment(&self) -> u64 {
+        self.0.fetch_add(1, Ordering::Relaxed) + 1
+    }
+}
+
+impl Clone for HitCounter {
+    fn clone(&self) -> Self {
+        Self(AtomicU64::new(self.get()))
+    }
 }
 
 /// Snapshot of cache statistics.
+///
+/// Non-exhaustive so new counters can be added; build one from
+/// `Default::default()` outside this crate.
 #[derive(Clone, Debug, Default)]
+#[non_exhaustive]
 pub struct CacheStats {
     pub hits: u64,
     pub misses: u64,
     pub evictions: u64,
     pub current_size: usize,
     pub capacity: usize,
+    /// How long evicted entries stayed cached, and how many were never hit.
+    pub evicted: EvictionStats,
+    /// Approximate heap footprint of the cached entries, in bytes.
+    pub memory_bytes: usize,
+    /// How far the eviction hand travelled (SIEVE only).
+    pub scan: ScanStats,
+}
+
+/// Number of power-of-two age buckets in `EvictionStats`.
+pub const AGE_BUCKETS: usize = 32;
+
+/// Lifetimes of evicted entries, from insertion to eviction.
+///
+/// A policy that demotes unpopular objects quickly (SIEVE) evicts its
+/// one-hit wonders young, and keeps popular ones longer than FIFO would.
+/// Ages are bucketed by powers of two, so the median is approximate.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EvictionStats {
+    pub count: u64,
+    /// Evicted without serving a single hit.
+    pub never_hit: u64,
+    pub total_age_ms: u64,
+    /// Bucket 0 counts ages under 1ms; bucket `i` ages in `[2^(i-1), 2^i)`ms.
+    /// The last bucket takes everything older.
+    pub age_buckets: [u64; AGE_BUCKETS],
+}
+
+impl EvictionStats {
+    /// Record one eviction of an entry that was cached for `age`.
+    pub fn record(&mut self, age: Duration, was_hit: bool) {
+        let ms = u64::try_from(age.as_millis()).unwrap_or(u64::MAX);
+        let bucket = (u64::BITS - ms.leading_zeros()) as usize;
+        self.age_buckets[bucket.min(AGE_BUCKETS - 1)] += 1;
+        self.count += 1;
+        self.total_age_ms = self.total_age_ms.saturating_add(ms);
+        if !was_hit {
+            self.never_hit += 1;
+        }
+    }
+
+    /// Fold in another shard's figures.
+    pub fn merge(&mut self, other: &EvictionStats) {
+        self.count += other.count;
+        self.never_hit += other.never_hit;
+        self.total_age_ms = self.total_age_ms.saturating_add(other.total_age_ms);
+        for (bucket, n) in self.age_buckets.iter_mut().zip(other.age_buckets) {
+            *bucket += n;
+        }
+    }
+
+    pub fn mean_age(&self) -> Duration {
+        match self.count {
+            0 => Duration::ZERO,
+            n => Duration::from_millis(self.total_age_ms / n),
+        }
+    }
+
+    /// Age below which fraction `q` of evictions fall, interpolated within
+    /// its bucket. Zero before the first eviction.
+    pub fn age_quantile(&self, q: f64) -> Duration {
+        let target = q.clamp(0.0, 1.0) * self.count as f64;
+        let mut below = 0u64;
+        for (i, &n) in self.age_buckets.iter().enumerate() {
+            if n > 0 && (below + n) as f64 >= target {
+                let (lo, hi) = match i {
+                    0 => (0.0, 1.0),
+                    _ => ((1u64 << (i - 1)) as f64, (1u64 << i) as f64),
+                };
+                let ms = lo + (hi - lo) * (target - below as f64) / n as f64;
+                return Duration::from_secs_f64(ms / 1000.0);
+            }
+            below += n;
+        }
+        Duration::ZERO
+    }
+
+    pub fn median_age(&self) -> Duration {
+        self.age_quantile(0.5)
+    }
+
+    /// Share of evicted entries that were never hit.
+    pub fn one_hit_wonder_ratio(&self) -> f64 {
+        match self.count {
+            0 => 0.0,
+            n => self.never_hit as f64 / n as f64,
+        }
+    }
+}
+
+/// The work SIEVE's hand does to find each victim, as reported in the SIEVE
+/// paper: how many entries it passes, how many of those it spares by
+/// clearing their visited bit, and how often it runs off the head and
+/// starts over from the tail. All zero for policies without a hand.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ScanStats {
+    /// Evictions the hand made, expired entries included.
+    pub evictions: u64,
+    /// Entries the hand examined, victims included.
+    pub scanned: u64,
+    /// Visited bits the hand cleared, each sparing an entry once.
+    pub visited_clears: u64,
+    /// Times the hand started over from the tail, the first start included.
+    pub hand_wraps: u64,
+}
+
+impl ScanStats {
+    /// Fold in another shard's figures.
+    pub fn merge(&mut self, other: &ScanStats) {
+        self.evictions += other.evictions;
+        self.scanned += other.scanned;
+        self.visited_clears += other.visited_clears;
+        self.hand_wraps += other.hand_wraps;
+    }
+
+    /// Entries examined per eviction; 1 means every victim was the first
+    /// entry the hand looked at.
+    pub fn mean_scan_length(&self) -> f64 {
+        match self.evictions {
+            0 => 0.0,
+            n => self.scanned as f64 / n as f64,
+        }
+    }
+
+    /// Visited bits cleared per eviction.
+    pub fn visited_clears_per_eviction(&self) -> f64 {
+        match self.evictions {
+            0 => 0.0,
+            n => self.visited_clears as f64 / n as f64,
+        }
+    }
+}
+
+/// One entry as listed by `CachePolicy::iter`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EntryInfo {
+    pub key: String,
+    /// Body size in bytes.
+    pub size: usize,
+    /// Time until the entry expires; zero once it has.
+    pub ttl_remaining: Duration,
+    /// Whether the entry has been hit since the eviction scan last passed
+    /// it, which spares it next time (SIEVE and the experimental policies).
+    /// Always `false` for LRU and FIFO, which don't keep the bit.
+    pub visited: bool,
+}
+
+/// Why a policy dropped an entry on its own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RemovalCause {
+    /// Pushed out to make room.
+    Evicted,
+    /// Past its TTL, found by a lookup or the eviction scan.
+    Expired,
+}
+
+/// An entry a policy dropped on its own (see `CachePolicy::take_removed`).
+#[derive(Clone, Debug)]
+pub struct Removed {
+    pub key: String,
+    pub entry: Arc<CachedResponse>,
+    pub cause: RemovalCause,
+}
+
+/// Where a policy keeps `Removed` entries until `take_removed`. Records
+/// nothing until enabled, so a policy used on its own doesn't pile them up.
+#[derive(Debug, Default)]
+pub struct RemovalLog {
+    enabled: bool,
+    removed: Vec<Removed>,
+}
+
+impl RemovalLog {
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.removed = Vec::new();
+        }
+    }
+
+    /// Note an evicted entry. One evicted after its TTL ran out counts as
+    /// expired.
+    pub fn evicted(&mut self, key: String, entry: Arc<CachedResponse>) {
+        let cause = if entry.is_expired() {
+            RemovalCause::Expired
+        } else {
+            RemovalCause::Evicted
+        };
+        self.push(key, entry, cause);
+    }
+
+    /// Note an entry dropped by a lookup for being past its TTL.
+    pub fn expired(&mut self, key: String, entry: Arc<CachedResponse>) {
+        self.push(key, entry, RemovalCause::Expired);
+    }
+
+    pub fn take(&mut self) -> Vec<Removed> {
+        std::mem::take(&mut self.removed)
+    }
+
+    fn push(&mut self, key: String, entry: Arc<CachedResponse>, cause: RemovalCause) {
+        if self.enabled {
+            self.removed.push(Removed { key, entry, cause });
+        }
+    }
+}
+
+/// Callbacks for what happens to a `ShardedCache`'s entries (see
+/// `ShardedCache::with_listener`). Every method defaults to doing nothing.
+///
+/// They run on the calling thread after the shard lock is released, so
+/// they may use the cache, but they're on the lookup path: keep them quick.
+pub trait CacheListener: Send + Sync {
+    /// `key` was stored, replacing any previous value.
+    fn on_insert(&self, key: &str, entry: &CachedResponse) {
+        let _ = (key, entry);
+    }
+
+    /// A lookup found `key`.
+    fn on_hit(&self, key: &str, entry: &CachedResponse) {
+        let _ = (key, entry);
+    }
+
+    /// `key` was evicted to make room.
+    fn on_evict(&self, key: &str, entry: &CachedResponse) {
+        let _ = (key, entry);
+    }
+
+    /// `key` was dropped for being past its TTL.
+    fn on_expire(&self, key: &str, entry: &CachedResponse) {
+        let _ = (key, entry);
+    }
 }
 
 /// Common interface for all cache eviction policies.
@@ -36,12 +397,73 @@ pub trait CachePolicy: Send {
     /// Look up a key. Returns the cached response if found and not expired.
     fn get(&mut self, key: &str) -> Option<Arc<CachedResponse>>;
 
+    /// Look up a key without side effects: no stats, no visited bit, no
+    /// promotion, and expired entries are left in place. For inspection only.
+    fn peek(&self, key: &str) -> Option<Arc<CachedResponse>>;
+
+    /// Whether `key`'s entry has been hit since the eviction scan last
+    /// passed it (see `EntryInfo::visited`). Like `peek`, this has no side
+    /// effects. Always `false` for policies that don't keep the bit.
+    fn visited(&self, key: &str) -> bool {
+        let _ = key;
+        false
+    }
+
     /// Insert a key-value pair. May trigger eviction if at capacity.
     fn insert(&mut self, key: String, value: CachedResponse);
 
+    /// `insert`, saying why nothing was stored when that happens. The
+    /// default inserts and reports `CacheError::Full` if the entry isn't
+    /// there afterwards; the built-in policies say more.
+    fn try_insert(&mut self, key: String, value: CachedResponse) -> Result<(), CacheError> {
+        let probe = key.clone();
+        self.insert(key, value);
+        match self.peek(&probe) {
+            Some(_) => Ok(()),
+            None => Err(CacheError::Full),
+        }
+    }
+
     /// Remove a key explicitly.
     fn remove(&mut self, key: &str) -> bool;
 
+    /// Every key currently stored, expired or not, in no particular order.
+    fn keys(&self) -> Vec<String>;
+
+    /// Every entry currently stored, expired or not, in no particular order.
+    /// Like `peek`, this has no side effects.
+    fn entries(&self) -> Vec<(String, Arc<CachedResponse>)> {
+        self.keys()
+            .into_iter()
+            .filter_map(|key| {
+                let value = self.peek(&key)?;
+                Some((key, value))
+            })
+            .collect()
+    }
+
+    /// Every entry currently stored, expired or not, in queue order (newest
+    /// or most recently promoted first). Like `peek`, this has no side
+    /// effects.
+    fn iter(&self) -> Box<dyn Iterator<Item = EntryInfo> + '_>;
+
+    /// Remove every entry for which `keep` returns `false`, expired or not,
+    /// returning how many went. Removals count as explicit, like `remove`:
+    /// not as evictions, and not reported by `take_removed`.
+    fn retain(&mut self, keep: &mut dyn FnMut(&str, &CachedResponse) -> bool) -> usize {
+        let mut removed = 0;
+        for (key, value) in self.entries() {
+            if !keep(&key, &value) && self.remove(&key) {
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    /// Remove every entry. Capacity and the hit/miss/eviction counters are
+    /// kept; cleared entries don't count as evictions.
+    fn clear(&mut self);
+
     /// Number of entries currently in the cache.
     fn len(&self) -> usize;
 
@@ -53,9 +475,264 @@ pub trait CachePolicy: Send {
     /// Maximum number of entries.
     fn capacity(&self) -> usize;
 
+    /// Change the maximum number of entries in place. Growing keeps every
+    /// entry; shrinking runs the policy's own eviction until the new bound is met.
+    /// Panics on a capacity of 0 or one over `arena::MAX_CAPACITY`; see
+    /// `try_resize`.
+    fn resize(&mut self, capacity: usize);
+
+    /// `resize`, refusing a capacity the policy can't hold instead of
+    /// panicking. Nothing changes on error.
+    fn try_resize(&mut self, capacity: usize) -> Result<(), CacheError> {
+        CacheError::check_capacity(capacity)?;
+        self.resize(capacity);
+        Ok(())
+    }
+
+    /// Approximate heap footprint of the cached entries, in bytes: keys,
+    /// headers, bodies and the bookkeeping around each. Meant to be cheap
+    /// enough to call after every insert.
+    fn memory_bytes(&self) -> usize {
+        self.stats().memory_bytes
+    }
+
+    /// Evict one entry, chosen the way the policy picks one to make room
+    /// when full. Returns `false` if nothing was evicted, because the cache
+    /// is empty or the policy can't evict on demand.
+    fn evict(&mut self) -> bool {
+        false
+    }
+
     /// Human-readable name of the eviction policy.
     fn name(&self) -> &'static str;
 
     /// Current statistics snapshot.
     fn stats(&self) -> CacheStats;
+
+    /// Zero the hit/miss/eviction counters. Entries are left alone.
+    fn reset_stats(&mut self);
+
+    /// Start (or stop) keeping the entries evicted or expired from here on
+    /// for `take_removed`. Off until turned on. Policies that can't report
+    /// removals ignore it.
+    fn record_removals(&mut self, enabled: bool) {
+        let _ = enabled;
+    }
+
+    /// The entries evicted or expired since the last call, in the order they
+    /// went, while `record_removals` is on.
+    fn take_removed(&mut self) -> Vec<Removed> {
+        Vec::new()
+    }
+
+    /// Verify internal consistency (index matches storage, occupancy within
+    /// capacity, ...), describing the first violation found. Walks the whole
+    /// cache, so keep it to tests and soak runs.
+    fn check_invariants(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fifo::FifoCache;
+    use crate::lru::LruCache;
+    use crate::sieve::SieveCache;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+    use std::collections::HashMap;
+
+    fn response(ttl: Duration) -> CachedResponse {
+        CachedResponse {
+            status: 200,
+            headers: Default::default(),
+            body: Bytes::new(),
+            inserted_at: Instant::now(),
+            ttl,
+            hits: Default::default(),
+            source: Default::default(),
+            must_revalidate: false,
+        }
+    }
+
+    #[test]
+    fn initial_age_backdates_insertion() {
+        let entry = response(Duration::from_secs(60)).with_initial_age(Duration::from_secs(10));
+        assert!(entry.age() >= Duration::from_secs(10));
+        assert_eq!(entry.ttl, Duration::from_secs(60));
+        assert!(!entry.is_expired());
+    }
+
+    #[test]
+    fn initial_age_past_the_clock_shortens_ttl() {
+        // Further back than any `Instant` goes: the age must still count
+        let entry =
+            response(Duration::from_secs(3600)).with_initial_age(Duration::from_secs(u64::MAX));
+        assert!(entry.is_expired());
+
+        let entry = response(Duration::MAX).with_initial_age(Duration::from_secs(u64::MAX));
+        assert!(entry.ttl < Duration::MAX);
+        assert!(!entry.is_expired());
+    }
+
+    #[test]
+    fn eviction_age_buckets() {
+        let mut stats = EvictionStats::default();
+        stats.record(Duration::from_micros(500), false);
+        stats.record(Duration::from_millis(1), true);
+        stats.record(Duration::from_millis(1500), true);
+        stats.record(Duration::from_secs(u64::MAX / 1000), false);
+
+        assert_eq!(stats.age_buckets[0], 1);
+        assert_eq!(stats.age_buckets[1], 1);
+        assert_eq!(stats.age_buckets[11], 1, "1500ms is in [1024, 2048)");
+        assert_eq!(stats.age_buckets[AGE_BUCKETS - 1], 1);
+        assert_eq!(stats.never_hit, 2);
+    }
+
+    #[test]
+    fn eviction_age_median_and_mean() {
+        let mut stats = EvictionStats::default();
+        assert_eq!(stats.median_age(), Duration::ZERO);
+        for _ in 0..10 {
+            stats.record(Duration::from_millis(100), true);
+        }
+        let mut other = EvictionStats::default();
+        other.record(Duration::from_secs(10), false);
+        stats.merge(&other);
+
+        assert_eq!(stats.count, 11);
+        // 100ms falls in [64, 128)
+        let median = stats.median_age();
+        assert!(median >= Duration::from_millis(64) && median < Duration::from_millis(128));
+        assert_eq!(stats.mean_age(), Duration::from_millis(1000));
+        assert!((stats.one_hit_wonder_ratio() - 1.0 / 11.0).abs() < 1e-9);
+    }
+
+    #[derive(Clone, Debug)]
+    enum Op {
+        Get(u8),
+        Insert(u8),
+        /// Insert an entry that has already expired.
+        InsertStale(u8),
+        Remove(u8),
+        Resize(usize),
+        /// Keep only the even keys.
+        RetainEven,
+        Clear,
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        let key = 0..24u8;
+        prop_oneof![
+            4 => key.clone().prop_map(Op::Get),
+            4 => key.clone().prop_map(Op::Insert),
+            1 => key.clone().prop_map(Op::InsertStale),
+            2 => key.prop_map(Op::Remove),
+            1 => (1..16usize).prop_map(Op::Resize),
+            1 => Just(Op::RetainEven),
+            1 => Just(Op::Clear),
+        ]
+    }
+
+    /// Body `version`, expired already if `stale`.
+    fn versioned(version: usize, stale: bool) -> CachedResponse {
+        let ttl = Duration::from_secs(60);
+        CachedResponse {
+            status: 200,
+            headers: Default::default(),
+            body: Bytes::from(version.to_string()),
+            inserted_at: if stale {
+                Instant::now() - ttl * 2
+            } else {
+                Instant::now()
+            },
+            ttl,
+            hits: Default::default(),
+            source: Default::default(),
+            must_revalidate: false,
+        }
+    }
+
+    /// Run `ops` against `cache`, checking after each one that its list and
+    /// index agree, the SIEVE hand (if any) is on a live entry, it's within
+    /// capacity, and it only ever returns the latest value of a key that
+    /// hasn't been removed since.
+    fn check_ops(mut cache: Box<dyn CachePolicy>, ops: &[Op]) -> Result<(), TestCaseError> {
+        // Latest version inserted for each key that may still be cached
+        let mut model: HashMap<String, usize> = HashMap::new();
+        let mut lookups = 0;
+        for (version, op) in ops.iter().enumerate() {
+            match *op {
+                Op::Get(k) => {
+                    lookups += 1;
+                    let key = k.to_string();
+                    if let Some(found) = cache.get(&key) {
+                        let latest = model.get(&key).map(|v| v.to_string());
+                        prop_assert_eq!(
+                            Some(found.body.as_ref()),
+                            latest.as_deref().map(str::as_bytes)
+                        );
+                    }
+                }
+                Op::Insert(k) => {
+                    cache.insert(k.to_string(), versioned(version, false));
+                    model.insert(k.to_string(), version);
+                    let found = cache.peek(&k.to_string());
+                    prop_assert_eq!(
+                        found.map(|e| e.body.clone()),
+                        Some(Bytes::from(version.to_string())),
+                        "fresh insert not found"
+                    );
+                }
+                Op::InsertStale(k) => {
+                    lookups += 1;
+                    cache.insert(k.to_string(), versioned(version, true));
+                    model.insert(k.to_string(), version);
+                    prop_assert!(cache.get(&k.to_string()).is_none(), "expired entry served");
+                }
+                Op::Remove(k) => {
+                    let removed = cache.remove(&k.to_string());
+                    prop_assert!(!removed || model.remove(&k.to_string()).is_some());
+                    model.remove(&k.to_string());
+                    prop_assert!(cache.peek(&k.to_string()).is_none());
+                }
+                Op::Resize(capacity) => cache.resize(capacity),
+                Op::RetainEven => {
+                    let before = cache.len();
+                    let dropped = cache.retain(&mut |key, _| key.parse::<u8>().unwrap() % 2 == 0);
+                    prop_assert_eq!(cache.len(), before - dropped);
+                    model.retain(|key, _| key.parse::<u8>().unwrap() % 2 == 0);
+                }
+                Op::Clear => {
+                    cache.clear();
+                    model.clear();
+                    prop_assert!(cache.is_empty());
+                }
+            }
+            prop_assert_eq!(cache.check_invariants(), Ok(()), "after {:?}", op);
+            prop_assert!(cache.len() <= cache.capacity());
+            prop_assert_eq!(cache.iter().count(), cache.len());
+            for key in cache.keys() {
+                prop_assert!(model.contains_key(&key), "{} came back after removal", key);
+            }
+        }
+        let stats = cache.stats();
+        prop_assert_eq!(stats.hits + stats.misses, lookups);
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn policies_keep_invariants(capacity in 1..16usize, ops in vec(op(), 0..300)) {
+            check_ops(Box::new(SieveCache::new(capacity)), &ops)?;
+            check_ops(Box::new(LruCache::new(capacity)), &ops)?;
+            check_ops(Box::new(FifoCache::new(capacity)), &ops)?;
+            #[cfg(feature = "experimental")]
+            for name in crate::experimental::POLICIES {
+                check_ops(crate::experimental::build(name, capacity).unwrap(), &ops)?;
+            }
+        }
+    }
 }
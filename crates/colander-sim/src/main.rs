@@ -0,0 +1,288 @@
+mod trace;
+
+use bytes::Bytes;
+use clap::{Parser, ValueEnum};
+use colander_cache::fifo::FifoCache;
+use colander_cache::lru::LruCache;
+use colander_cache::sieve::SieveCache;
+use colander_cache::traits::{CachePolicy, CacheStats, CachedResponse};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rand_distr::{Distribution, Zipf};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use trace::TraceFormat;
+
+/// Policies every build can simulate.
+const POLICIES: &[&str] = &["sieve", "lru", "fifo"];
+
+/// Colander cache simulator — replays a trace (or Zipfian traffic) against
+/// every eviction policy at several cache sizes and reports their hit rates.
+#[derive(Parser)]
+#[command(name = "colander-sim")]
+struct Args {
+    /// Request trace to replay instead of generated traffic
+    #[arg(long)]
+    trace: Option<PathBuf>,
+
+    /// Layout of the `--trace` file
+    #[arg(long, value_enum, default_value = "csv")]
+    trace_format: TraceFormat,
+
+    /// Number of unique items in generated traffic
+    #[arg(long, default_value_t = 100_000)]
+    num_items: u64,
+
+    /// Number of generated requests
+    #[arg(long, default_value_t = 1_000_000)]
+    requests: usize,
+
+    /// Zipfian alpha (skewness) of generated traffic
+    #[arg(long, default_value_t = 0.8)]
+    alpha: f64,
+
+    /// Seed for generated traffic, so runs can be repeated
+    #[arg(long, default_value_t = 1)]
+    seed: u64,
+
+    /// Comma-separated policies to compare (`sieve`, `lru`, `fifo`, plus
+    /// `sieve2`, `clock` and `s3fifo` when built with `experimental`)
+    #[arg(long, value_delimiter = ',', default_value = "sieve,lru,fifo")]
+    policies: Vec<String>,
+
+    /// Comma-separated cache sizes, in entries or as a percentage of the
+    /// distinct keys requested (`1000`, `5%`)
+    #[arg(long, value_delimiter = ',', default_value = "1%,2%,5%,10%,20%")]
+    capacities: Vec<Capacity>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "table")]
+    format: Format,
+}
+
+/// A cache size from `--capacities`.
+#[derive(Clone, Copy, Debug)]
+enum Capacity {
+    Entries(usize),
+    Percent(f64),
+}
+
+impl Capacity {
+    fn entries(self, distinct: usize) -> usize {
+        match self {
+            Capacity::Entries(n) => n,
+            Capacity::Percent(p) => ((distinct as f64 * p / 100.0).round() as usize).max(1),
+        }
+    }
+}
+
+impl FromStr for Capacity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let capacity = match s.trim().strip_suffix('%') {
+            Some(percent) => match percent.trim().parse::<f64>() {
+                Ok(p) if p > 0.0 && p <= 100.0 => Capacity::Percent(p),
+                _ => return Err(format!("{s:?} isn't a percentage in (0, 100]")),
+            },
+            None => match s.trim().parse::<usize>() {
+                Ok(n) if n > 0 => Capacity::Entries(n),
+                _ => return Err(format!("{s:?} isn't a positive number of entries")),
+            },
+        };
+        Ok(capacity)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// Hit rates with a row per cache size and a column per policy
+    Table,
+    /// `policy,capacity,requests,hits,misses,hit_rate,evictions`, a line per run
+    Csv,
+}
+
+/// Build a policy by name: the proxy's three, or an experimental one when
+/// the feature is on.
+fn build(policy: &str, capacity: usize) -> Option<Box<dyn CachePolicy>> {
+    Some(match policy {
+        "sieve" => Box::new(SieveCache::new(capacity)),
+        "lru" => Box::new(LruCache::new(capacity)),
+        "fifo" => Box::new(FifoCache::new(capacity)),
+        #[cfg(feature = "experimental")]
+        other => return colander_cache::experimental::build(other, capacity),
+        #[cfg(not(feature = "experimental"))]
+        _ => return None,
+    })
+}
+
+fn known_policies() -> Vec<&'static str> {
+    #[allow(unused_mut)]
+    let mut names = POLICIES.to_vec();
+    #[cfg(feature = "experimental")]
+    names.extend_from_slice(colander_cache::experimental::POLICIES);
+    names
+}
+
+/// A request stream of `requests` keys drawn from `num_items` items by
+/// popularity rank, named the way the proxy keys `GET /api/items/{id}`.
+fn zipf_keys(num_items: u64, alpha: f64, requests: usize, seed: u64) -> Vec<String> {
+    let zipf = Zipf::new(num_items, alpha).expect("checked in main");
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..requests)
+        .map(|_| format!("GET:/api/items/{}", zipf.sample(&mut rng) as u64))
+        .collect()
+}
+
+/// Look every key up, inserting it on a miss, as the proxy does. Entries
+/// never expire, so only eviction decides what's cached.
+fn simulate(mut cache: Box<dyn CachePolicy>, keys: &[String]) -> CacheStats {
+    let now = Instant::now();
+    for key in keys {
+        if cache.get(key).is_none() {
+            cache.insert(
+                key.clone(),
+                CachedResponse {
+                    status: 200,
+                    headers: Default::default(),
+                    body: Bytes::new(),
+                    inserted_at: now,
+                    ttl: Duration::MAX,
+                    hits: Default::default(),
+                    source: Default::default(),
+                    must_revalidate: false,
+                },
+            );
+        }
+    }
+    cache.stats()
+}
+
+fn hit_rate(stats: &CacheStats) -> f64 {
+    let total = stats.hits + stats.misses;
+    if total == 0 {
+        0.0
+    } else {
+        stats.hits as f64 / total as f64
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    let known = known_policies();
+    if let Some(unknown) = args.policies.iter().find(|p| !known.contains(&p.as_str())) {
+        eprintln!(
+            "unknown policy {unknown:?}, expected one of {}",
+            known.join(", ")
+        );
+        std::process::exit(2);
+    }
+    if args.trace.is_none()
+        && (args.num_items == 0 || !(args.alpha > 0.0 && args.alpha.is_finite()))
+    {
+        eprintln!("--num-items must be positive and --alpha a finite number greater than 0");
+        std::process::exit(2);
+    }
+
+    let keys = match &args.trace {
+        Some(path) => match trace::load(path, args.trace_format) {
+            Ok((keys, skipped)) => {
+                if skipped > 0 {
+                    eprintln!("skipped {skipped} lines that weren't requests");
+                }
+                keys
+            }
+            Err(e) => {
+                eprintln!("reading {}: {e}", path.display());
+                std::process::exit(1);
+            }
+        },
+        None => zipf_keys(args.num_items, args.alpha, args.requests, args.seed),
+    };
+    if keys.is_empty() {
+        eprintln!("no requests to simulate");
+        std::process::exit(1);
+    }
+    let distinct = keys
+        .iter()
+        .map(String::as_str)
+        .collect::<HashSet<_>>()
+        .len();
+
+    let mut capacities: Vec<usize> = args
+        .capacities
+        .iter()
+        .map(|c| c.entries(distinct))
+        .collect();
+    capacities.sort_unstable();
+    capacities.dedup();
+
+    // Every (capacity, policy) pair is independent; spread them over the cores
+    let jobs: Vec<(usize, &str)> = capacities
+        .iter()
+        .flat_map(|&c| args.policies.iter().map(move |p| (c, p.as_str())))
+        .collect();
+    let results: Mutex<Vec<Option<CacheStats>>> = Mutex::new(vec![None; jobs.len()]);
+    let next = AtomicUsize::new(0);
+    let workers = std::thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(jobs.len());
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(&(capacity, policy)) = jobs.get(i) else {
+                    break;
+                };
+                let cache = build(policy, capacity).expect("policy names checked in main");
+                let stats = simulate(cache, &keys);
+                results.lock().unwrap()[i] = Some(stats);
+            });
+        }
+    });
+    let results: Vec<CacheStats> = results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|stats| stats.expect("every job ran"))
+        .collect();
+
+    match args.format {
+        Format::Csv => {
+            println!("policy,capacity,requests,hits,misses,hit_rate,evictions");
+            for (&(capacity, policy), stats) in jobs.iter().zip(&results) {
+                println!(
+                    "{policy},{capacity},{},{},{},{:.6},{}",
+                    stats.hits + stats.misses,
+                    stats.hits,
+                    stats.misses,
+                    hit_rate(stats),
+                    stats.evictions,
+                );
+            }
+        }
+        Format::Table => {
+            println!("{} requests, {distinct} distinct keys", keys.len());
+            println!();
+            print!("{:>10} {:>7}", "capacity", "% keys");
+            for policy in &args.policies {
+                print!(" {:>8}", policy.to_uppercase());
+            }
+            println!();
+            for (row, &capacity) in results.chunks(args.policies.len()).zip(&capacities) {
+                print!(
+                    "{capacity:>10} {:>6.1}%",
+                    capacity as f64 * 100.0 / distinct as f64
+                );
+                for stats in row {
+                    print!(" {:>7.2}%", hit_rate(stats) * 100.0);
+                }
+                println!();
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use clap::ValueEnum;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// Layout of a request trace, the same ones `loadgen --trace` replays.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum TraceFormat {
+    /// `key,timestamp[,size]`. A header line is skipped.
+    Csv,
+    /// Twitter's cache traces: `timestamp,key,key_size,value_size,client_id,operation,ttl`.
+    /// Only `get` and `gets` are simulated.
+    Twitter,
+    /// Wikimedia CDN traces: whitespace-separated `timestamp key [type] size ...`.
+    Wiki,
+}
+
+impl TraceFormat {
+    /// The key requested on `line`, or `None` for lines that aren't
+    /// requests: malformed ones, headers, and operations other than reads.
+    fn key(self, line: &str) -> Option<&str> {
+        let (timestamp, key) = match self {
+            TraceFormat::Csv => {
+                let mut fields = line.split(',').map(str::trim);
+                let key = fields.next()?;
+                (fields.next()?, key)
+            }
+            TraceFormat::Twitter => {
+                let fields: Vec<&str> = line.split(',').collect();
+                if fields.len() < 6 || !matches!(fields[5], "get" | "gets") {
+                    return None;
+                }
+                (fields[0], fields[1])
+            }
+            TraceFormat::Wiki => {
+                let mut fields = line.split_whitespace();
+                (fields.next()?, fields.next()?)
+            }
+        };
+        // A timestamp that isn't a number marks a header
+        if key.is_empty() || timestamp.parse::<f64>().is_err() {
+            return None;
+        }
+        Some(key)
+    }
+}
+
+/// Every key requested in the trace at `path`, in order, and the number of
+/// lines skipped. Sizes are ignored: the policies count entries, not bytes.
+pub fn load(path: &Path, format: TraceFormat) -> std::io::Result<(Vec<String>, u64)> {
+    let mut keys = Vec::new();
+    let mut skipped = 0;
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match format.key(&line) {
+            Some(key) => keys.push(key.to_string()),
+            None => skipped += 1,
+        }
+    }
+    Ok((keys, skipped))
+}
@@ -1,26 +1,320 @@
-use axum::extract::Path;
+mod spec;
+
+use axum::extract::{Path, Query, State};
+use axum::http::header::{CACHE_CONTROL, ETAG, IF_NONE_MATCH};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
 use axum::routing::get;
 use axum::{Json, Router};
+use clap::Parser;
 use rand::Rng;
-use serde_json::{json, Value};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use spec::{Latency, PayloadSize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tracing_subscriber::EnvFilter;
 
-async fn get_item(Path(id): Path<u64>) -> Json<Value> {
-    // Simulate upstream latency (5-20ms)
-    let delay = rand::thread_rng().gen_range(5..=20);
-    tokio::time::sleep(Duration::from_millis(delay)).await;
+/// Largest `?size=` honored, so a stray query can't exhaust memory.
+const MAX_QUERY_SIZE: usize = 16 * 1024 * 1024;
+/// Version of an item nobody has written.
+const INITIAL_VERSION: u64 = 1;
+
+/// Fake origin API for trying colander locally, with configurable latency,
+/// item sizes and failures.
+#[derive(Parser)]
+#[command(name = "demo-backend")]
+struct Args {
+    /// Listen address
+    #[arg(long, default_value = "0.0.0.0:3000")]
+    addr: String,
+
+    /// Response time in milliseconds: `fixed:MS`, `uniform:MIN-MAX` or `lognormal:MEDIAN,SIGMA`
+    #[arg(long, default_value = "uniform:5-20")]
+    latency: Latency,
+
+    /// Size of each item's `data` in bytes: `N`, or `MIN-MAX` to vary it by item
+    #[arg(long, default_value = "256")]
+    payload_bytes: PayloadSize,
+
+    /// Share of item requests answered with a 500
+    #[arg(long, default_value_t = 0.0)]
+    error_rate: f64,
+
+    /// Share of item requests that take `--slow-ms` longer
+    #[arg(long, default_value_t = 0.0)]
+    slow_rate: f64,
+
+    /// Extra time taken by slow requests, and by `/api/slow/{id}`
+    #[arg(long, default_value_t = 2000)]
+    slow_ms: u64,
+
+    /// `Cache-Control: max-age` of `/api/items/{id}`, in seconds
+    #[arg(long, default_value_t = 60)]
+    max_age: u64,
+
+    /// `Cache-Control: max-age` of `/api/slow/{id}`, in seconds
+    #[arg(long, default_value_t = 0)]
+    slow_max_age: u64,
+}
+
+struct Backend {
+    latency: Latency,
+    payload: PayloadSize,
+    error_rate: f64,
+    slow_rate: f64,
+    slow: Duration,
+    max_age: u64,
+    slow_max_age: u64,
+    /// Items that have been written or deleted. The rest have their
+    /// generated content at `INITIAL_VERSION`.
+    items: Mutex<HashMap<u64, Stored>>,
+}
+
+enum Stored {
+    Live {
+        version: u64,
+        fields: Map<String, Value>,
+    },
+    Deleted {
+        version: u64,
+    },
+}
+
+impl Stored {
+    fn version(&self) -> u64 {
+        match self {
+            Stored::Live { version, .. } | Stored::Deleted { version } => *version,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ItemQuery {
+    /// Overrides the item's `data` size, as trace replay asks for.
+    size: Option<usize>,
+    /// For `/api/slow/{id}`: how long to take instead of `--slow-ms`.
+    ms: Option<u64>,
+}
+
+/// Weak, since `latency_ms` differs between otherwise equal responses.
+/// Generated items include their size, which `?size=` can change.
+fn etag(id: u64, version: u64, size: Option<usize>) -> String {
+    match size {
+        Some(size) => format!("W/\"{id}-{version}-{size}\""),
+        None => format!("W/\"{id}-{version}\""),
+    }
+}
+
+/// Whether `If-None-Match` lists `etag`, by the weak comparison RFC 9110
+/// §13.1.2 asks for.
+fn none_match(headers: &HeaderMap, etag: &str) -> bool {
+    let Some(value) = headers.get(IF_NONE_MATCH).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    value
+        .split(',')
+        .any(|tag| tag.trim() == "*" || opaque(tag) == opaque(etag))
+}
+
+/// The fields of an item nobody has written.
+fn generated(backend: &Backend, id: u64, size: Option<usize>) -> Map<String, Value> {
+    let size = size
+        .map(|s| s.min(MAX_QUERY_SIZE))
+        .unwrap_or_else(|| backend.payload.for_item(id));
+    let mut fields = Map::new();
+    fields.insert("name".to_string(), json!(format!("Item {}", id)));
+    fields.insert("data".to_string(), json!("x".repeat(size)));
+    fields
+}
+
+/// An item's JSON body, from its stored or generated fields.
+fn body(id: u64, version: u64, mut fields: Map<String, Value>, delay: Duration) -> Json<Value> {
+    fields.insert("id".to_string(), json!(id));
+    fields.insert("version".to_string(), json!(version));
+    fields.insert("latency_ms".to_string(), json!(delay.as_millis() as u64));
+    Json(Value::Object(fields))
+}
+
+/// Sample this request's delay and whether it fails, then wait it out.
+/// Returns the delay, or the injected failure to answer with.
+async fn delay_or_fail(backend: &Backend, id: u64) -> Result<Duration, Response> {
+    let (delay, failed) = {
+        let mut rng = rand::thread_rng();
+        let mut delay = backend.latency.sample();
+        if backend.slow_rate > 0.0 && rng.gen_bool(backend.slow_rate) {
+            delay += backend.slow;
+        }
+        (
+            delay,
+            backend.error_rate > 0.0 && rng.gen_bool(backend.error_rate),
+        )
+    };
+    tokio::time::sleep(delay).await;
+
+    if failed {
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            [(CACHE_CONTROL, "no-store")],
+            Json(json!({ "error": "injected failure", "id": id })),
+        )
+            .into_response());
+    }
+    Ok(delay)
+}
+
+fn not_found(id: u64) -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        [(CACHE_CONTROL, "no-store")],
+        Json(json!({ "error": "item deleted", "id": id })),
+    )
+        .into_response()
+}
+
+/// The item, or `304 Not Modified` if the client already has this version.
+fn item(
+    backend: &Backend,
+    headers: &HeaderMap,
+    id: u64,
+    size: Option<usize>,
+    delay: Duration,
+    max_age: u64,
+) -> Response {
+    let (version, fields, size) = match backend.items.lock().unwrap().get(&id) {
+        Some(Stored::Live { version, fields }) => (*version, fields.clone(), None),
+        Some(Stored::Deleted { .. }) => return not_found(id),
+        None => {
+            let fields = generated(backend, id, size);
+            let size = fields["data"].as_str().map(str::len);
+            (INITIAL_VERSION, fields, size)
+        }
+    };
+    let etag = etag(id, version, size);
+    let cache_control = format!("public, max-age={max_age}");
+    let caching = [(ETAG, etag.clone()), (CACHE_CONTROL, cache_control)];
+    if none_match(headers, &etag) {
+        return (StatusCode::NOT_MODIFIED, caching).into_response();
+    }
+    (caching, body(id, version, fields, delay)).into_response()
+}
+
+enum Update {
+    Replace(Map<String, Value>),
+    Merge(Map<String, Value>),
+}
+
+/// Store the item's next version and answer with it: `201` if the item had
+/// been deleted, `200` otherwise.
+fn store(backend: &Backend, id: u64, delay: Duration, update: Update) -> Response {
+    let mut items = backend.items.lock().unwrap();
+    let (previous, mut fields, created) = match items.remove(&id) {
+        Some(Stored::Live { version, fields }) => (version, fields, false),
+        Some(Stored::Deleted { version }) => (version, Map::new(), true),
+        None => (INITIAL_VERSION, generated(backend, id, None), false),
+    };
+    match update {
+        Update::Replace(new) => fields = new,
+        Update::Merge(new) => fields.extend(new),
+    }
+    let version = previous + 1;
+    items.insert(
+        id,
+        Stored::Live {
+            version,
+            fields: fields.clone(),
+        },
+    );
+    drop(items);
+
+    let status = if created {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+    (
+        status,
+        [(ETAG, etag(id, version, None))],
+        body(id, version, fields, delay),
+    )
+        .into_response()
+}
+
+async fn get_item(
+    State(backend): State<Arc<Backend>>,
+    Path(id): Path<u64>,
+    Query(query): Query<ItemQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let delay = match delay_or_fail(&backend, id).await {
+        Ok(delay) => delay,
+        Err(failure) => return failure,
+    };
+    item(&backend, &headers, id, query.size, delay, backend.max_age)
+}
+
+/// Replace the item with the request body.
+async fn put_item(
+    State(backend): State<Arc<Backend>>,
+    Path(id): Path<u64>,
+    Json(fields): Json<Map<String, Value>>,
+) -> Response {
+    match delay_or_fail(&backend, id).await {
+        Ok(delay) => store(&backend, id, delay, Update::Replace(fields)),
+        Err(failure) => failure,
+    }
+}
+
+/// Update the fields of the item that the request body names, keeping the
+/// rest.
+async fn post_item(
+    State(backend): State<Arc<Backend>>,
+    Path(id): Path<u64>,
+    Json(fields): Json<Map<String, Value>>,
+) -> Response {
+    match delay_or_fail(&backend, id).await {
+        Ok(delay) => store(&backend, id, delay, Update::Merge(fields)),
+        Err(failure) => failure,
+    }
+}
 
-    Json(json!({
-        "id": id,
-        "name": format!("Item {}", id),
-        "data": "x".repeat(256),
-        "latency_ms": delay,
-    }))
+async fn delete_item(State(backend): State<Arc<Backend>>, Path(id): Path<u64>) -> Response {
+    if let Err(failure) = delay_or_fail(&backend, id).await {
+        return failure;
+    }
+    let mut items = backend.items.lock().unwrap();
+    let version = match items.get(&id) {
+        Some(Stored::Deleted { .. }) => return not_found(id),
+        Some(stored) => stored.version() + 1,
+        None => INITIAL_VERSION + 1,
+    };
+    items.insert(id, Stored::Deleted { version });
+    StatusCode::NO_CONTENT.into_response()
 }
 
-async fn health() -> &'static str {
-    "ok"
+/// Always slow and never failing, for exercising timeouts.
+async fn get_slow_item(
+    State(backend): State<Arc<Backend>>,
+    Path(id): Path<u64>,
+    Query(query): Query<ItemQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let delay = query.ms.map(Duration::from_millis).unwrap_or(backend.slow);
+    tokio::time::sleep(delay).await;
+    item(
+        &backend,
+        &headers,
+        id,
+        query.size,
+        delay,
+        backend.slow_max_age,
+    )
+}
+
+async fn health() -> impl IntoResponse {
+    ([(CACHE_CONTROL, "no-store")], "ok")
 }
 
 #[tokio::main]
@@ -31,13 +325,51 @@ async fn main() {
         )
         .init();
 
-    let app = Router::new()
-        .route("/api/items/{id}", get(get_item))
-        .route("/health", get(health));
+    let args = Args::parse();
+    for (name, rate) in [
+        ("--error-rate", args.error_rate),
+        ("--slow-rate", args.slow_rate),
+    ] {
+        if !(0.0..=1.0).contains(&rate) {
+            eprintln!("{name} must be in [0, 1]");
+            std::process::exit(2);
+        }
+    }
 
-    let addr = "0.0.0.0:3000";
-    tracing::info!(addr, "demo backend starting");
+    tracing::info!(
+        addr = %args.addr,
+        latency = %args.latency,
+        payload_bytes = %args.payload_bytes,
+        error_rate = args.error_rate,
+        slow_rate = args.slow_rate,
+        slow_ms = args.slow_ms,
+        max_age = args.max_age,
+        slow_max_age = args.slow_max_age,
+        "demo backend starting"
+    );
+    let backend = Arc::new(Backend {
+        latency: args.latency,
+        payload: args.payload_bytes,
+        error_rate: args.error_rate,
+        slow_rate: args.slow_rate,
+        slow: Duration::from_millis(args.slow_ms),
+        max_age: args.max_age,
+        slow_max_age: args.slow_max_age,
+        items: Mutex::new(HashMap::new()),
+    });
+
+    let app = Router::new()
+        .route(
+            "/api/items/{id}",
+            get(get_item)
+                .put(put_item)
+                .post(post_item)
+                .delete(delete_item),
+        )
+        .route("/api/slow/{id}", get(get_slow_item))
+        .route("/health", get(health))
+        .with_state(backend);
 
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+    let listener = tokio::net::TcpListener::bind(&args.addr).await.unwrap();
     axum::serve(listener, app).await.unwrap();
 }
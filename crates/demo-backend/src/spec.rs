@@ -0,0 +1,150 @@
+use rand::Rng;
+use rand_distr::{Distribution, LogNormal};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How long the backend takes to answer, from `--latency`.
+#[derive(Clone, Copy, Debug)]
+pub enum Latency {
+    /// `fixed:10`
+    Fixed(f64),
+    /// `uniform:5-20`
+    Uniform(f64, f64),
+    /// `lognormal:10,0.8`: median and sigma. A long right tail, like real
+    /// services have.
+    LogNormal {
+        median: f64,
+        sigma: f64,
+        dist: LogNormal<f64>,
+    },
+}
+
+impl Latency {
+    pub fn sample(&self) -> Duration {
+        let ms = match *self {
+            Latency::Fixed(ms) => ms,
+            Latency::Uniform(min, max) => rand::thread_rng().gen_range(min..=max),
+            Latency::LogNormal { dist, .. } => dist.sample(&mut rand::thread_rng()),
+        };
+        Duration::from_secs_f64(ms.max(0.0) / 1000.0)
+    }
+}
+
+impl FromStr for Latency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (kind, params) = s.split_once(':').unwrap_or((s, ""));
+        let ms = |v: &str| -> Result<f64, String> {
+            let ms: f64 = v
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid milliseconds `{v}`"))?;
+            if ms >= 0.0 && ms.is_finite() {
+                Ok(ms)
+            } else {
+                Err(format!("milliseconds must be >= 0, got `{v}`"))
+            }
+        };
+        match kind {
+            "fixed" => Ok(Latency::Fixed(ms(params)?)),
+            "uniform" => {
+                let (min, max) = params
+                    .split_once('-')
+                    .ok_or("uniform latency is `uniform:MIN-MAX`")?;
+                let (min, max) = (ms(min)?, ms(max)?);
+                if min > max {
+                    return Err("uniform latency needs MIN <= MAX".to_string());
+                }
+                Ok(Latency::Uniform(min, max))
+            }
+            "lognormal" => {
+                let (median, sigma) = params
+                    .split_once(',')
+                    .ok_or("lognormal latency is `lognormal:MEDIAN,SIGMA`")?;
+                let median = ms(median)?;
+                let sigma: f64 = sigma
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("invalid sigma `{sigma}`"))?;
+                if median <= 0.0 {
+                    return Err("lognormal median must be > 0".to_string());
+                }
+                LogNormal::new(median.ln(), sigma)
+                    .map(|dist| Latency::LogNormal {
+                        median,
+                        sigma,
+                        dist,
+                    })
+                    .map_err(|e| format!("invalid lognormal latency: {e}"))
+            }
+            _ => Err(format!(
+                "unknown latency `{s}` (use fixed:MS, uniform:MIN-MAX or lognormal:MEDIAN,SIGMA)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Latency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Latency::Fixed(ms) => write!(f, "fixed:{ms}"),
+            Latency::Uniform(min, max) => write!(f, "uniform:{min}-{max}"),
+            Latency::LogNormal { median, sigma, .. } => write!(f, "lognormal:{median},{sigma}"),
+        }
+    }
+}
+
+/// Size of an item's `data` field, from `--payload-bytes`: one size for
+/// every item (`256`) or a range (`128-65536`) that each item falls in.
+#[derive(Clone, Copy, Debug)]
+pub struct PayloadSize {
+    min: usize,
+    max: usize,
+}
+
+impl PayloadSize {
+    /// The size for item `id`. It's derived from the ID, so an item is the
+    /// same size on every request.
+    pub fn for_item(&self, id: u64) -> usize {
+        let span = (self.max - self.min) as u64 + 1;
+        self.min + (splitmix(id) % span) as usize
+    }
+}
+
+impl FromStr for PayloadSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let bytes = |v: &str| -> Result<usize, String> {
+            v.trim().parse().map_err(|_| format!("invalid size `{v}`"))
+        };
+        let (min, max) = match s.split_once('-') {
+            Some((min, max)) => (bytes(min)?, bytes(max)?),
+            None => (bytes(s)?, bytes(s)?),
+        };
+        if min > max {
+            return Err("payload size range needs MIN <= MAX".to_string());
+        }
+        Ok(PayloadSize { min, max })
+    }
+}
+
+impl fmt::Display for PayloadSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.min == self.max {
+            write!(f, "{}", self.min)
+        } else {
+            write!(f, "{}-{}", self.min, self.max)
+        }
+    }
+}
+
+/// splitmix64, to spread consecutive IDs over the size range.
+fn splitmix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
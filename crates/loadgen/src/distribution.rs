@@ -0,0 +1,151 @@
+use crate::zipfian::ZipfianGenerator;
+use clap::ValueEnum;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::time::Instant;
+
+/// How item IDs are picked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[value(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum Distribution {
+    /// Zipf(α) over item IDs: item 1 is the most popular.
+    Zipfian,
+    /// Zipf(α) popularity, with the popular items scattered over the ID space.
+    ScrambledZipfian,
+    /// Every item equally likely.
+    Uniform,
+    /// A fixed share of requests to a fixed share of items, uniform within each.
+    Hotspot,
+    /// Zipf(α) skewed toward the newest (highest) IDs.
+    Latest,
+    /// Every item in order, then around again: a scan no cache can help.
+    Sequential,
+}
+
+impl Distribution {
+    /// Inverse of `self as u8`, for keeping the distribution in an atomic.
+    pub fn from_index(index: u8) -> Self {
+        Self::value_variants()[index as usize]
+    }
+}
+
+/// Shape of the `hotspot` distribution.
+#[derive(Clone, Copy, Debug)]
+pub struct Hotspot {
+    /// Share of the items that are hot, in (0, 1].
+    pub fraction: f64,
+    /// Share of the requests that go to hot items, in [0, 1].
+    pub traffic: f64,
+}
+
+/// Picks item IDs (1-based) from one distribution.
+pub struct KeyGenerator {
+    distribution: Distribution,
+    num_items: u64,
+    zipf: ZipfianGenerator,
+    hotspot: Hotspot,
+    /// Next position of the `sequential` scan, shared by every worker so
+    /// together they walk the keyspace once per pass.
+    cursor: Arc<AtomicU64>,
+}
+
+impl KeyGenerator {
+    pub fn new(
+        distribution: Distribution,
+        num_items: u64,
+        alpha: f64,
+        hotspot: Hotspot,
+        cursor: Arc<AtomicU64>,
+    ) -> Self {
+        Self {
+            distribution,
+            num_items,
+            zipf: ZipfianGenerator::new(num_items, alpha),
+            hotspot,
+            cursor,
+        }
+    }
+
+    pub fn distribution(&self) -> Distribution {
+        self.distribution
+    }
+
+    pub fn alpha(&self) -> f64 {
+        self.zipf.alpha()
+    }
+
+    pub fn num_items(&self) -> u64 {
+        self.num_items
+    }
+
+    pub fn next_id(&mut self) -> u64 {
+        let n = self.num_items;
+        match self.distribution {
+            Distribution::Zipfian => self.zipf.next_id(),
+            Distribution::ScrambledZipfian => fnv1a(&self.zipf.next_id().to_le_bytes()) % n + 1,
+            Distribution::Uniform => rand::thread_rng().gen_range(1..=n),
+            Distribution::Hotspot => {
+                let mut rng = rand::thread_rng();
+                let hot = ((n as f64 * self.hotspot.fraction) as u64).clamp(1, n);
+                if hot == n || rng.gen_bool(self.hotspot.traffic) {
+                    rng.gen_range(1..=hot)
+                } else {
+                    rng.gen_range(hot + 1..=n)
+                }
+            }
+            Distribution::Latest => n + 1 - self.zipf.next_id(),
+            Distribution::Sequential => self.cursor.fetch_add(1, Ordering::Relaxed) % n + 1,
+        }
+    }
+}
+
+/// The item IDs in play: `--num-items` of them to begin with, joined by
+/// `--churn-rate` new ones a second. With `--retire`, the oldest item drops
+/// out for each new one, so the live set slides along the ID space instead
+/// of growing.
+pub struct Keyspace {
+    initial: u64,
+    churn_rate: f64,
+    retire: bool,
+    started: Instant,
+}
+
+impl Keyspace {
+    pub fn new(initial: u64, churn_rate: f64, retire: bool) -> Self {
+        Self {
+            initial,
+            churn_rate,
+            retire,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn churn_rate(&self) -> f64 {
+        self.churn_rate
+    }
+
+    pub fn retire(&self) -> bool {
+        self.retire
+    }
+
+    /// The oldest live ID, and how many are live. IDs run consecutively
+    /// from the oldest; the newest is the highest.
+    pub fn window(&self) -> (u64, u64) {
+        let added = (self.started.elapsed().as_secs_f64() * self.churn_rate) as u64;
+        if self.retire {
+            (1 + added, self.initial)
+        } else {
+            (1, self.initial + added)
+        }
+    }
+}
+
+/// 64-bit FNV-1a, as YCSB uses to scramble Zipfian ranks.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
@@ -0,0 +1,168 @@
+use hdrhistogram::Histogram;
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Longest latency tracked exactly; anything slower is recorded as this.
+const MAX_LATENCY_US: u64 = 60_000_000;
+
+/// How the proxy answered, from its `X-Cache` header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheOutcome {
+    Hit,
+    Miss,
+    /// `STALE`, `BYPASS`, no header at all, ...
+    Other,
+    /// Not a read: a write or delete.
+    Write,
+}
+
+impl CacheOutcome {
+    pub fn from_header(value: Option<&str>) -> Self {
+        match value {
+            Some(v) if v.eq_ignore_ascii_case("HIT") => CacheOutcome::Hit,
+            Some(v) if v.eq_ignore_ascii_case("MISS") => CacheOutcome::Miss,
+            _ => CacheOutcome::Other,
+        }
+    }
+}
+
+/// Latency percentiles of one set of requests, in microseconds.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct Percentiles {
+    pub count: u64,
+    pub p50_us: u64,
+    pub p95_us: u64,
+    pub p99_us: u64,
+    pub p999_us: u64,
+    pub max_us: u64,
+}
+
+impl Percentiles {
+    fn of(histogram: &Histogram<u64>) -> Self {
+        Self {
+            count: histogram.len(),
+            p50_us: histogram.value_at_quantile(0.5),
+            p95_us: histogram.value_at_quantile(0.95),
+            p99_us: histogram.value_at_quantile(0.99),
+            p999_us: histogram.value_at_quantile(0.999),
+            max_us: histogram.max(),
+        }
+    }
+}
+
+/// Percentiles for every request, and for hits, misses and writes
+/// separately.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct LatencyReport {
+    pub all: Percentiles,
+    pub hit: Percentiles,
+    pub miss: Percentiles,
+    pub write: Percentiles,
+}
+
+struct Histograms {
+    all: Histogram<u64>,
+    hit: Histogram<u64>,
+    miss: Histogram<u64>,
+    write: Histogram<u64>,
+}
+
+impl Histograms {
+    fn new() -> Self {
+        let histogram =
+            || Histogram::new_with_bounds(1, MAX_LATENCY_US, 3).expect("valid histogram bounds");
+        Self {
+            all: histogram(),
+            hit: histogram(),
+            miss: histogram(),
+            write: histogram(),
+        }
+    }
+
+    fn report(&self) -> LatencyReport {
+        LatencyReport {
+            all: Percentiles::of(&self.all),
+            hit: Percentiles::of(&self.hit),
+            miss: Percentiles::of(&self.miss),
+            write: Percentiles::of(&self.write),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.all.reset();
+        self.hit.reset();
+        self.miss.reset();
+        self.write.reset();
+    }
+}
+
+/// Interval and run histograms, behind one lock so a request is recorded
+/// into both at once.
+struct Windows {
+    interval: Histograms,
+    run: Histograms,
+}
+
+/// Client-side response times, collected in HDR histograms (3 significant
+/// digits, 1µs to 60s) per reporting interval and over the whole run.
+pub struct LatencyRecorder {
+    current: Mutex<Windows>,
+    /// What `rotate` last returned.
+    last: Mutex<LatencyReport>,
+}
+
+impl LatencyRecorder {
+    pub fn new() -> Self {
+        Self {
+            current: Mutex::new(Windows {
+                interval: Histograms::new(),
+                run: Histograms::new(),
+            }),
+            last: Mutex::new(LatencyReport::default()),
+        }
+    }
+
+    pub fn record(&self, latency: Duration, outcome: CacheOutcome) {
+        let us = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+        let mut guard = self.current.lock().unwrap();
+        let windows = &mut *guard;
+        for histograms in [&mut windows.interval, &mut windows.run] {
+            histograms.all.saturating_record(us);
+            match outcome {
+                CacheOutcome::Hit => histograms.hit.saturating_record(us),
+                CacheOutcome::Miss => histograms.miss.saturating_record(us),
+                CacheOutcome::Write => histograms.write.saturating_record(us),
+                CacheOutcome::Other => {}
+            }
+        }
+    }
+
+    /// Percentiles of everything recorded since the previous call, then
+    /// start a new interval.
+    pub fn rotate(&self) -> LatencyReport {
+        let report = {
+            let mut windows = self.current.lock().unwrap();
+            let report = windows.interval.report();
+            windows.interval.reset();
+            report
+        };
+        *self.last.lock().unwrap() = report;
+        report
+    }
+
+    /// Forget the run so far, e.g. the warmup.
+    pub fn start_run(&self) {
+        self.current.lock().unwrap().run.reset();
+    }
+
+    /// Percentiles of everything recorded since `start_run`.
+    pub fn run_report(&self) -> LatencyReport {
+        self.current.lock().unwrap().run.report()
+    }
+
+    /// The most recent completed interval's percentiles.
+    pub fn last(&self) -> LatencyReport {
+        *self.last.lock().unwrap()
+    }
+}
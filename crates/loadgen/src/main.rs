@@ -1,18 +1,38 @@
+mod distribution;
+mod latency;
+mod open_loop;
+mod ops;
+mod pacer;
+mod profile;
+mod report;
+mod resp;
+mod trace;
 mod zipfian;
 
 use axum::extract::State;
+use axum::http::StatusCode;
 use axum::routing::{get, post};
 use axum::{Json, Router};
+use bytes::Bytes;
 use clap::Parser;
+use distribution::{Distribution, Hotspot, KeyGenerator, Keyspace};
+use latency::{CacheOutcome, LatencyRecorder, LatencyReport};
+use ops::{Mix, Op, Protocol};
+use pacer::Pacer;
+use profile::Profile;
+use report::RunOptions;
 use reqwest::Client;
+use resp::RespDriver;
 use serde::{Deserialize, Serialize};
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
+use tokio::time::Instant;
+use trace::TraceFormat;
 use tracing_subscriber::EnvFilter;
-use zipfian::ZipfianGenerator;
 
-/// Colander load generator — Zipfian traffic for cache benchmarking.
+/// Colander load generator — Zipfian (and other) traffic for cache benchmarking.
 #[derive(Parser)]
 #[command(name = "loadgen")]
 struct Args {
@@ -28,14 +48,99 @@ struct Args {
     #[arg(long, default_value_t = 16)]
     concurrency: u64,
 
-    /// Target requests per second (0 = unlimited)
+    /// Target requests per second across all workers (0 = unlimited)
     #[arg(long, default_value_t = 0)]
     rps: u64,
 
+    /// Send `--rps` requests per second on a fixed schedule, with up to
+    /// `--concurrency` in flight, instead of one request per worker at a time
+    #[arg(long)]
+    open_loop: bool,
+
+    /// New items added to the keyspace per second
+    #[arg(long, default_value_t = 0.0)]
+    churn_rate: f64,
+
+    /// Retire the oldest item for each new one, keeping `--num-items` live
+    #[arg(long)]
+    retire: bool,
+
     /// Initial Zipfian alpha (skewness)
     #[arg(long, default_value_t = 0.8)]
     alpha: f64,
 
+    /// Initial key distribution
+    #[arg(long, value_enum, default_value_t = Distribution::Zipfian)]
+    distribution: Distribution,
+
+    /// Share of the items that are hot, for `--distribution hotspot`
+    #[arg(long, default_value_t = 0.2)]
+    hot_fraction: f64,
+
+    /// Share of the requests that go to hot items, for `--distribution hotspot`
+    #[arg(long, default_value_t = 0.8)]
+    hot_traffic: f64,
+
+    /// How requests reach the cache
+    #[arg(long, value_enum, default_value_t = Protocol::Http)]
+    protocol: Protocol,
+
+    /// RESP listener, for `--protocol resp`
+    #[arg(long, default_value = "127.0.0.1:6379")]
+    resp_addr: String,
+
+    /// Password to `AUTH` with on the RESP listener
+    #[arg(long)]
+    resp_password: Option<String>,
+
+    /// Share of requests that write an item (HTTP `PUT`, RESP `SET`)
+    #[arg(long, default_value_t = 0.0)]
+    write_ratio: f64,
+
+    /// Share of requests that delete an item (HTTP `DELETE`, RESP `DEL`)
+    #[arg(long, default_value_t = 0.0)]
+    delete_ratio: f64,
+
+    /// Size in bytes of the `data` field in written items
+    #[arg(long, default_value_t = 256)]
+    value_size: usize,
+
+    /// Change rps, alpha and distribution over time following this TOML schedule
+    #[arg(long)]
+    profile: Option<PathBuf>,
+
+    /// Replay this request trace instead of generating traffic
+    #[arg(long)]
+    trace: Option<PathBuf>,
+
+    /// Layout of the `--trace` file
+    #[arg(long, value_enum, default_value_t = TraceFormat::Csv)]
+    trace_format: TraceFormat,
+
+    /// Replay speed relative to the trace's timestamps (2 = twice as fast, 0 = as fast as possible)
+    #[arg(long, default_value_t = 1.0)]
+    trace_speed: f64,
+
+    /// Measure for this long after the warmup, then stop (e.g. `120s`, `5m`)
+    #[arg(long, value_parser = report::parse_duration)]
+    duration: Option<Duration>,
+
+    /// Send traffic for this long before measuring starts
+    #[arg(long, value_parser = report::parse_duration, default_value = "0s")]
+    warmup: Duration,
+
+    /// Write a summary of the measured period here when the run ends (`.csv` for CSV, otherwise JSON)
+    #[arg(long)]
+    report: Option<PathBuf>,
+
+    /// Proxy admin URL, for the hit rates in the report
+    #[arg(long, default_value = "http://127.0.0.1:9090")]
+    admin_url: String,
+
+    /// Bearer token for the proxy's admin API
+    #[arg(long)]
+    admin_token: Option<String>,
+
     /// Control server listen address
     #[arg(long, default_value = "0.0.0.0:9091")]
     control_addr: String,
@@ -45,13 +150,33 @@ struct Args {
 struct LoadGenState {
     /// Zipfian alpha stored as fixed-point (alpha * 1000) for lock-free updates.
     alpha_fp: AtomicU64,
+    /// `Distribution` as its index, for lock-free updates.
+    distribution: AtomicU8,
+    hotspot: Hotspot,
+    /// Position of the `sequential` scan.
+    scan_cursor: Arc<AtomicU64>,
     num_items: u64,
+    /// Which item IDs are live, as `--churn-rate` moves them along.
+    keyspace: Keyspace,
     running: AtomicBool,
     proxy_url: String,
-    rps: u64,
+    /// Keeps the workers to `--rps`, and holds the current target.
+    pacer: Pacer,
     concurrency: u64,
+    open_loop: bool,
+    protocol: Protocol,
+    resp_addr: String,
+    resp_password: Option<String>,
+    mix: Mix,
+    /// Body of every write: `{"data": "xxx..."}`.
+    write_value: Bytes,
     /// Total requests sent (atomic counter).
     total_requests: AtomicU64,
+    /// Requests that got no response, or whose body couldn't be read.
+    failed_requests: AtomicU64,
+    trace: Option<PathBuf>,
+    profile: Option<PathBuf>,
+    latency: LatencyRecorder,
 }
 
 impl LoadGenState {
@@ -63,6 +188,61 @@ impl LoadGenState {
         let fp = (alpha * 1000.0) as u64;
         self.alpha_fp.store(fp, Ordering::Relaxed);
     }
+
+    fn rps(&self) -> u64 {
+        self.pacer.rps()
+    }
+
+    fn set_rps(&self, rps: u64) {
+        self.pacer.set_rps(rps);
+    }
+
+    fn distribution(&self) -> Distribution {
+        Distribution::from_index(self.distribution.load(Ordering::Relaxed))
+    }
+
+    fn set_distribution(&self, distribution: Distribution) {
+        self.distribution
+            .store(distribution as u8, Ordering::Relaxed);
+    }
+
+    /// The item to request next. `gen` picks within the live keyspace, and
+    /// is rebuilt first if alpha, the distribution or the keyspace's size
+    /// have changed.
+    fn next_item(&self, gen: &mut KeyGenerator) -> u64 {
+        let (first, count) = self.keyspace.window();
+        if (self.alpha() - gen.alpha()).abs() > 0.001
+            || self.distribution() != gen.distribution()
+            || gen.num_items() != count
+        {
+            *gen = self.key_generator();
+        }
+        first - 1 + gen.next_id()
+    }
+
+    /// Count a finished request, and record its latency from `scheduled`
+    /// if it succeeded.
+    fn record(&self, scheduled: Instant, outcome: Option<CacheOutcome>) {
+        match outcome {
+            Some(outcome) => {
+                self.latency.record(scheduled.elapsed(), outcome);
+                self.total_requests.fetch_add(1, Ordering::Relaxed);
+            }
+            None => {
+                self.failed_requests.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn key_generator(&self) -> KeyGenerator {
+        KeyGenerator::new(
+            self.distribution(),
+            self.keyspace.window().1,
+            self.alpha(),
+            self.hotspot,
+            Arc::clone(&self.scan_cursor),
+        )
+    }
 }
 
 #[derive(Deserialize)]
@@ -70,12 +250,18 @@ struct ControlRequest {
     #[serde(default)]
     alpha: Option<f64>,
     #[serde(default)]
+    distribution: Option<Distribution>,
+    #[serde(default)]
+    rps: Option<u64>,
+    #[serde(default)]
     running: Option<bool>,
 }
 
 #[derive(Serialize)]
 struct ControlResponse {
     alpha: f64,
+    distribution: Distribution,
+    rps: u64,
     running: bool,
     total_requests: u64,
 }
@@ -83,60 +269,133 @@ struct ControlResponse {
 #[derive(Serialize)]
 struct StatusResponse {
     alpha: f64,
+    distribution: Distribution,
     running: bool,
     total_requests: u64,
     num_items: u64,
+    churn_rate: f64,
+    retire: bool,
+    /// Oldest live item, and how many are live.
+    first_item: u64,
+    live_items: u64,
     concurrency: u64,
     rps: u64,
+    open_loop: bool,
+    protocol: Protocol,
+    write_ratio: f64,
+    delete_ratio: f64,
+    /// Percentiles over the last reporting interval.
+    latency: LatencyReport,
+    /// Trace being replayed, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace: Option<PathBuf>,
+    /// Load profile being followed, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    profile: Option<PathBuf>,
 }
 
 async fn control_handler(
     State(state): State<Arc<LoadGenState>>,
     Json(body): Json<ControlRequest>,
-) -> Json<ControlResponse> {
+) -> Result<Json<ControlResponse>, (StatusCode, &'static str)> {
+    if body.rps == Some(0) && state.open_loop {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "open-loop mode needs an rps above 0",
+        ));
+    }
     if let Some(alpha) = body.alpha {
         let clamped = alpha.clamp(0.01, 3.0);
         state.set_alpha(clamped);
         tracing::info!(alpha = clamped, "alpha updated");
     }
+    if let Some(distribution) = body.distribution {
+        state.set_distribution(distribution);
+        tracing::info!(?distribution, "distribution updated");
+    }
+    if let Some(rps) = body.rps {
+        state.set_rps(rps);
+        tracing::info!(rps, "rps updated");
+    }
     if let Some(running) = body.running {
         state.running.store(running, Ordering::Relaxed);
         tracing::info!(running, "running state updated");
     }
 
-    Json(ControlResponse {
+    Ok(Json(ControlResponse {
         alpha: state.alpha(),
+        distribution: state.distribution(),
+        rps: state.rps(),
         running: state.running.load(Ordering::Relaxed),
         total_requests: state.total_requests.load(Ordering::Relaxed),
-    })
+    }))
 }
 
 async fn status_handler(State(state): State<Arc<LoadGenState>>) -> Json<StatusResponse> {
     Json(StatusResponse {
         alpha: state.alpha(),
+        distribution: state.distribution(),
         running: state.running.load(Ordering::Relaxed),
         total_requests: state.total_requests.load(Ordering::Relaxed),
         num_items: state.num_items,
+        churn_rate: state.keyspace.churn_rate(),
+        retire: state.keyspace.retire(),
+        first_item: state.keyspace.window().0,
+        live_items: state.keyspace.window().1,
         concurrency: state.concurrency,
-        rps: state.rps,
+        rps: state.rps(),
+        open_loop: state.open_loop,
+        protocol: state.protocol,
+        write_ratio: state.mix.write,
+        delete_ratio: state.mix.delete,
+        latency: state.latency.last(),
+        trace: state.trace.clone(),
+        profile: state.profile.clone(),
     })
 }
 
-/// Worker task that sends requests to the proxy using a Zipfian distribution.
-async fn worker(state: Arc<LoadGenState>, client: Client, worker_id: u64) {
-    let delay = if state.rps > 0 {
-        let per_worker_rps = state.rps / state.concurrency.max(1);
-        if per_worker_rps > 0 {
-            Some(Duration::from_micros(1_000_000 / per_worker_rps))
-        } else {
-            None
-        }
-    } else {
-        None
-    };
+/// Send one request for `path` to the proxy and record its latency, timed
+/// from `scheduled` (when the request was meant to go out) to the end of
+/// the body.
+async fn fetch(
+    state: &LoadGenState,
+    client: &Client,
+    op: Op,
+    path: &str,
+    scheduled: Instant,
+) -> Result<(), reqwest::Error> {
+    let url = format!("{}{}", state.proxy_url, path);
+    let result = async {
+        let req = match op {
+            Op::Read => client.get(&url),
+            Op::Write => client
+                .put(&url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(state.write_value.clone()),
+            Op::Delete => client.delete(&url),
+        };
+        let resp = req.send().await?;
+        let outcome = match op {
+            Op::Read => CacheOutcome::from_header(
+                resp.headers().get("x-cache").and_then(|v| v.to_str().ok()),
+            ),
+            Op::Write | Op::Delete => CacheOutcome::Write,
+        };
+        resp.bytes().await?;
+        Ok(outcome)
+    }
+    .await;
+    state.record(scheduled, result.as_ref().ok().copied());
+    result.map(|_| ())
+}
 
+/// Worker task that sends requests to the proxy, picking items from the
+/// current distribution.
+async fn worker(state: Arc<LoadGenState>, client: Client, worker_id: u64) {
     // Each worker gets its own generator (rand is not Send-safe across awaits with thread_rng)
-    let mut gen = ZipfianGenerator::new(state.num_items, state.alpha());
+    let mut gen = state.key_generator();
+    let mut resp = (state.protocol == Protocol::Resp)
+        .then(|| RespDriver::new(state.resp_addr.clone(), state.resp_password.clone()));
 
     loop {
         if !state.running.load(Ordering::Relaxed) {
@@ -144,29 +403,29 @@ async fn worker(state: Arc<LoadGenState>, client: Client, worker_id: u64) {
             continue;
         }
 
-        // Check if alpha changed and rebuild generator
-        let current_alpha = state.alpha();
-        if (current_alpha - gen.alpha()).abs() > 0.001 {
-            gen = ZipfianGenerator::new(state.num_items, current_alpha);
-        }
-
-        let item_id = gen.next_id();
-        let url = format!("{}/api/items/{}", state.proxy_url, item_id);
-
-        match client.get(&url).send().await {
-            Ok(_resp) => {
-                state.total_requests.fetch_add(1, Ordering::Relaxed);
+        state.pacer.wait().await;
+        let item_id = state.next_item(&mut gen);
+        let op = state.mix.next_op();
+        let result = match &mut resp {
+            Some(resp) => resp
+                .send(&state, op, item_id, Instant::now())
+                .await
+                .map_err(|e| e.to_string()),
+            None => fetch(
+                &state,
+                &client,
+                op,
+                &format!("/api/items/{item_id}"),
+                Instant::now(),
+            )
+            .await
+            .map_err(|e| e.to_string()),
+        };
+        if let Err(e) = result {
+            if worker_id == 0 {
+                tracing::warn!(error = %e, "request failed");
             }
-            Err(e) => {
-                if worker_id == 0 {
-                    tracing::warn!(error = %e, "request failed");
-                }
-                tokio::time::sleep(Duration::from_millis(100)).await;
-            }
-        }
-
-        if let Some(d) = delay {
-            tokio::time::sleep(d).await;
+            tokio::time::sleep(Duration::from_millis(100)).await;
         }
     }
 }
@@ -180,15 +439,102 @@ async fn main() {
         .init();
 
     let args = Args::parse();
+    if !(args.hot_fraction > 0.0 && args.hot_fraction <= 1.0) {
+        eprintln!("--hot-fraction must be in (0, 1]");
+        std::process::exit(2);
+    }
+    if !(0.0..=1.0).contains(&args.hot_traffic) {
+        eprintln!("--hot-traffic must be in [0, 1]");
+        std::process::exit(2);
+    }
+    if !(args.trace_speed >= 0.0 && args.trace_speed.is_finite()) {
+        eprintln!("--trace-speed must be >= 0");
+        std::process::exit(2);
+    }
+    if args.open_loop && args.rps == 0 {
+        eprintln!("--open-loop needs --rps");
+        std::process::exit(2);
+    }
+    if args.open_loop && args.trace.is_some() {
+        eprintln!("--open-loop doesn't apply to --trace, which already keeps the trace's schedule");
+        std::process::exit(2);
+    }
+    let ratio = 0.0..=1.0;
+    if !ratio.contains(&args.write_ratio)
+        || !ratio.contains(&args.delete_ratio)
+        || args.write_ratio + args.delete_ratio > 1.0
+    {
+        eprintln!("--write-ratio and --delete-ratio must be in [0, 1] and add up to at most 1");
+        std::process::exit(2);
+    }
+    if args.protocol == Protocol::Resp && (args.open_loop || args.trace.is_some()) {
+        eprintln!("--protocol resp works with the closed-loop workers only");
+        std::process::exit(2);
+    }
+    if args.trace.is_some() && args.write_ratio + args.delete_ratio > 0.0 {
+        eprintln!("--trace replays reads only; drop --write-ratio and --delete-ratio");
+        std::process::exit(2);
+    }
+    if !(args.churn_rate >= 0.0 && args.churn_rate.is_finite()) {
+        eprintln!("--churn-rate must be >= 0");
+        std::process::exit(2);
+    }
+    if args.retire && args.churn_rate == 0.0 {
+        eprintln!("--retire needs --churn-rate");
+        std::process::exit(2);
+    }
+    if args.trace.is_some() && args.churn_rate > 0.0 {
+        eprintln!("--churn-rate doesn't apply to --trace");
+        std::process::exit(2);
+    }
+    if args.profile.is_some() && args.trace.is_some() {
+        eprintln!("--profile doesn't apply to --trace");
+        std::process::exit(2);
+    }
+    let profile = args.profile.as_ref().map(|path| {
+        Profile::load(path, args.open_loop).unwrap_or_else(|e| {
+            eprintln!("invalid profile {}: {e}", path.display());
+            std::process::exit(2);
+        })
+    });
+    // A profile that ends, like a trace, ends the run
+    let finite_profile = profile.as_ref().is_some_and(|p| !p.repeat);
+    let timed = args.duration.is_some() || args.trace.is_some() || finite_profile;
+    if !timed && (args.report.is_some() || !args.warmup.is_zero()) {
+        eprintln!("--report and --warmup need --duration (or --trace, or a --profile that ends)");
+        std::process::exit(2);
+    }
 
     let state = Arc::new(LoadGenState {
         alpha_fp: AtomicU64::new((args.alpha * 1000.0) as u64),
+        distribution: AtomicU8::new(args.distribution as u8),
+        hotspot: Hotspot {
+            fraction: args.hot_fraction,
+            traffic: args.hot_traffic,
+        },
+        scan_cursor: Arc::new(AtomicU64::new(0)),
         num_items: args.num_items,
+        keyspace: Keyspace::new(args.num_items, args.churn_rate, args.retire),
         running: AtomicBool::new(true),
         proxy_url: args.proxy_url.clone(),
-        rps: args.rps,
+        pacer: Pacer::new(args.rps),
         concurrency: args.concurrency,
+        open_loop: args.open_loop,
+        protocol: args.protocol,
+        resp_addr: args.resp_addr.clone(),
+        resp_password: args.resp_password.clone(),
+        mix: Mix {
+            write: args.write_ratio,
+            delete: args.delete_ratio,
+        },
+        write_value: Bytes::from(
+            serde_json::json!({ "data": "x".repeat(args.value_size) }).to_string(),
+        ),
         total_requests: AtomicU64::new(0),
+        failed_requests: AtomicU64::new(0),
+        trace: args.trace.clone(),
+        profile: args.profile.clone(),
+        latency: LatencyRecorder::new(),
     });
 
     // Build control server
@@ -202,9 +548,20 @@ async fn main() {
     tracing::info!(
         proxy = %args.proxy_url,
         alpha = args.alpha,
+        distribution = ?args.distribution,
+        trace = ?args.trace,
+        profile = ?args.profile,
         num_items = args.num_items,
+        churn_rate = args.churn_rate,
+        retire = args.retire,
         concurrency = args.concurrency,
         rps = args.rps,
+        open_loop = args.open_loop,
+        protocol = ?args.protocol,
+        write_ratio = args.write_ratio,
+        delete_ratio = args.delete_ratio,
+        duration = ?args.duration,
+        warmup = ?args.warmup,
         control = %control_addr,
         "loadgen starting"
     );
@@ -227,15 +584,7 @@ async fn main() {
         .build()
         .expect("failed to build HTTP client");
 
-    // Spawn workers
-    let mut handles = Vec::new();
-    for i in 0..args.concurrency {
-        let s = Arc::clone(&state);
-        let c = client.clone();
-        handles.push(tokio::spawn(worker(s, c, i)));
-    }
-
-    // Log throughput every 5 seconds
+    // Log throughput and latency every 5 seconds
     let stats_state = Arc::clone(&state);
     tokio::spawn(async move {
         let mut prev = 0u64;
@@ -246,17 +595,94 @@ async fn main() {
             let delta = current - prev;
             let rps = delta as f64 / 5.0;
             prev = current;
+            let latency = stats_state.latency.rotate();
             tracing::info!(
                 total = current,
                 rps = format!("{:.0}", rps),
                 alpha = format!("{:.2}", stats_state.alpha()),
+                distribution = ?stats_state.distribution(),
                 "throughput"
             );
+            for (class, p) in [
+                ("all", latency.all),
+                ("hit", latency.hit),
+                ("miss", latency.miss),
+                ("write", latency.write),
+            ] {
+                if p.count > 0 {
+                    tracing::info!(
+                        class,
+                        count = p.count,
+                        p50_us = p.p50_us,
+                        p95_us = p.p95_us,
+                        p99_us = p.p99_us,
+                        p999_us = p.p999_us,
+                        max_us = p.max_us,
+                        "latency"
+                    );
+                }
+            }
         }
     });
 
-    // Wait for all workers (runs forever)
-    for h in handles {
-        let _ = h.await;
+    let mut workload = match args.trace.clone() {
+        Some(path) => {
+            let (state, client) = (Arc::clone(&state), client.clone());
+            tokio::spawn(async move {
+                if let Err(e) =
+                    trace::replay(state, client, &path, args.trace_format, args.trace_speed).await
+                {
+                    eprintln!("failed to read trace {}: {e}", path.display());
+                    std::process::exit(1);
+                }
+            })
+        }
+        None => {
+            let traffic = if args.open_loop {
+                tokio::spawn(open_loop::run(Arc::clone(&state), client.clone()))
+            } else {
+                let handles: Vec<_> = (0..args.concurrency)
+                    .map(|i| tokio::spawn(worker(Arc::clone(&state), client.clone(), i)))
+                    .collect();
+                // Workers run forever
+                tokio::spawn(async move {
+                    for h in handles {
+                        let _ = h.await;
+                    }
+                })
+            };
+            match profile {
+                Some(profile) => {
+                    let state = Arc::clone(&state);
+                    tokio::spawn(async move {
+                        profile::run(state, profile).await;
+                        traffic.abort();
+                    })
+                }
+                None => traffic,
+            }
+        }
+    };
+
+    if !timed {
+        let _ = workload.await;
+        return;
+    }
+
+    let options = RunOptions {
+        warmup: args.warmup,
+        duration: args.duration,
+        admin_url: args.admin_url.clone(),
+        admin_token: args.admin_token.clone(),
+    };
+    let report = report::measure(&state, &client, &options, &mut workload).await;
+    workload.abort();
+    report.log();
+    if let Some(path) = &args.report {
+        if let Err(e) = report.write(path) {
+            eprintln!("failed to write report {}: {e}", path.display());
+            std::process::exit(1);
+        }
+        tracing::info!(path = %path.display(), "report written");
     }
 }
@@ -0,0 +1,76 @@
+use crate::{fetch, LoadGenState};
+use reqwest::Client;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::time::Instant;
+
+/// How far behind schedule sending can fall before it's logged.
+const LAG_WARNING: Duration = Duration::from_secs(1);
+
+/// Send requests at a constant `--rps`, each at a fixed time whether or not
+/// earlier responses have arrived, up to `concurrency` in flight.
+///
+/// When every slot is taken, the schedule keeps running and the requests it
+/// owes go out as soon as slots free up. Their latency is counted from when
+/// they were due, not when they were sent, so a stalled proxy shows up in
+/// the percentiles instead of quietly slowing the loadgen down (coordinated
+/// omission).
+pub async fn run(state: Arc<LoadGenState>, client: Client) {
+    let mut rps = state.rps();
+    let slots = Arc::new(Semaphore::new(state.concurrency.max(1) as usize));
+    let mut gen = state.key_generator();
+    // When the schedule started, and how many requests it has sent since
+    let mut origin = Instant::now();
+    let mut sent = 0u64;
+    let mut lagging = false;
+
+    loop {
+        if !state.running.load(Ordering::Relaxed) {
+            while !state.running.load(Ordering::Relaxed) {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            // Requests owed from the pause are never sent
+            origin = Instant::now();
+            sent = 0;
+        }
+        if state.rps() != rps {
+            // A new rate starts a new schedule from here
+            rps = state.rps();
+            origin = Instant::now();
+            sent = 0;
+        }
+
+        let due = origin + Duration::from_secs_f64(sent as f64 / rps.max(1) as f64);
+        let now = Instant::now();
+        if due > now {
+            tokio::time::sleep_until(due).await;
+            lagging = false;
+        }
+        let permit = Arc::clone(&slots)
+            .acquire_owned()
+            .await
+            .expect("semaphore never closed");
+        let behind = Instant::now().saturating_duration_since(due);
+        if behind > LAG_WARNING && !lagging {
+            tracing::warn!(
+                behind_ms = behind.as_millis() as u64,
+                "open-loop schedule falling behind; raise --concurrency"
+            );
+            lagging = true;
+        }
+
+        let path = format!("/api/items/{}", state.next_item(&mut gen));
+        let op = state.mix.next_op();
+        let state = Arc::clone(&state);
+        let client = client.clone();
+        tokio::spawn(async move {
+            if let Err(e) = fetch(&state, &client, op, &path, due).await {
+                tracing::debug!(error = %e, "request failed");
+            }
+            drop(permit);
+        });
+        sent += 1;
+    }
+}
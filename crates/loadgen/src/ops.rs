@@ -0,0 +1,45 @@
+use clap::ValueEnum;
+use rand::Rng;
+use serde::Serialize;
+
+/// How requests reach the cache.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Protocol {
+    /// `GET`, `PUT` and `DELETE /api/items/{id}` through the proxy.
+    Http,
+    /// `GET`, `SET` and `DEL item:{id}` on the RESP listener, using the
+    /// cache as a key-value store.
+    Resp,
+}
+
+/// What one request does to an item.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    Read,
+    Write,
+    Delete,
+}
+
+/// Share of requests that write or delete instead of read.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Mix {
+    pub write: f64,
+    pub delete: f64,
+}
+
+impl Mix {
+    pub fn next_op(&self) -> Op {
+        if self.write == 0.0 && self.delete == 0.0 {
+            return Op::Read;
+        }
+        let roll: f64 = rand::thread_rng().gen();
+        if roll < self.write {
+            Op::Write
+        } else if roll < self.write + self.delete {
+            Op::Delete
+        } else {
+            Op::Read
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+/// Spaces requests `1/rps` apart across every worker, so the total rate
+/// holds at any concurrency.
+///
+/// Each caller takes the next free send slot on a shared schedule and
+/// sleeps until it. Slots nobody was ready for are forfeited rather than
+/// saved up, so workers that fall behind (a slow proxy) never catch up in a
+/// burst.
+pub struct Pacer {
+    /// Target requests per second (0 = unlimited).
+    rps: AtomicU64,
+    /// Earliest time the next slot can be handed out.
+    next: Mutex<Instant>,
+    /// Wakes callers waiting on slots from the old rate when it changes.
+    changed: Notify,
+}
+
+impl Pacer {
+    pub fn new(rps: u64) -> Self {
+        Self {
+            rps: AtomicU64::new(rps),
+            next: Mutex::new(Instant::now()),
+            changed: Notify::new(),
+        }
+    }
+
+    pub fn rps(&self) -> u64 {
+        self.rps.load(Ordering::Relaxed)
+    }
+
+    /// Change the rate. Slots handed out at the old rate are given up, and
+    /// the new schedule starts now.
+    pub fn set_rps(&self, rps: u64) {
+        if self.rps.swap(rps, Ordering::Relaxed) != rps {
+            *self.next.lock().unwrap() = Instant::now();
+            self.changed.notify_waiters();
+        }
+    }
+
+    /// Wait for this caller's turn to send.
+    pub async fn wait(&self) {
+        loop {
+            let changed = self.changed.notified();
+            let rps = self.rps();
+            if rps == 0 {
+                return;
+            }
+            let interval = Duration::from_secs_f64(1.0 / rps as f64);
+            let slot = {
+                let mut next = self.next.lock().unwrap();
+                let slot = (*next).max(Instant::now());
+                *next = slot + interval;
+                slot
+            };
+            tokio::select! {
+                _ = tokio::time::sleep_until(slot) => return,
+                _ = changed => {}
+            }
+        }
+    }
+}
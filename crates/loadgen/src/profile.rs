@@ -0,0 +1,130 @@
+use crate::distribution::Distribution;
+use crate::report::parse_duration;
+use crate::LoadGenState;
+use serde::{Deserialize, Deserializer};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// How often a ramping phase moves its settings along.
+const RAMP_STEP: Duration = Duration::from_millis(100);
+
+/// A load schedule read from TOML: phases applied one after another, each
+/// setting the rate, skew and distribution for its duration.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// Start over after the last phase instead of ending the run.
+    #[serde(default)]
+    pub repeat: bool,
+    #[serde(rename = "phase")]
+    pub phases: Vec<Phase>,
+}
+
+/// One step of a `Profile`. Settings left out carry over from the phase
+/// before (or the command line, for the first).
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Phase {
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub duration: Duration,
+    #[serde(default)]
+    pub rps: Option<u64>,
+    #[serde(default)]
+    pub alpha: Option<f64>,
+    #[serde(default)]
+    pub distribution: Option<Distribution>,
+    /// Move `rps` and `alpha` gradually from the previous phase's values to
+    /// this one's over the phase, rather than switch at its start.
+    #[serde(default)]
+    pub ramp: bool,
+}
+
+impl Profile {
+    /// Read and check the profile at `path`. `open_loop` profiles need a
+    /// rate in every phase, since the open-loop schedule can't run
+    /// unlimited.
+    pub fn load(path: &Path, open_loop: bool) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let profile: Profile = toml::from_str(&text).map_err(|e| e.to_string())?;
+        if profile.phases.is_empty() {
+            return Err("no [[phase]] entries".to_string());
+        }
+        for (i, phase) in profile.phases.iter().enumerate() {
+            let n = i + 1;
+            if phase.duration.is_zero() {
+                return Err(format!("phase {n}: duration must be > 0"));
+            }
+            if phase.alpha.is_some_and(|a| !(0.01..=3.0).contains(&a)) {
+                return Err(format!("phase {n}: alpha must be in [0.01, 3]"));
+            }
+            if open_loop && phase.rps == Some(0) {
+                return Err(format!("phase {n}: --open-loop needs rps > 0"));
+            }
+        }
+        Ok(profile)
+    }
+}
+
+/// Apply `profile` to `state` phase by phase. Returns after the last phase,
+/// or never if the profile repeats.
+pub async fn run(state: Arc<LoadGenState>, profile: Profile) {
+    loop {
+        for (i, phase) in profile.phases.iter().enumerate() {
+            let (from_rps, from_alpha) = (state.rps(), state.alpha());
+            let to_rps = phase.rps.unwrap_or(from_rps);
+            let to_alpha = phase.alpha.unwrap_or(from_alpha);
+            if let Some(distribution) = phase.distribution {
+                state.set_distribution(distribution);
+            }
+            tracing::info!(
+                phase = i + 1,
+                secs = phase.duration.as_secs_f64(),
+                rps = to_rps,
+                alpha = to_alpha,
+                distribution = ?state.distribution(),
+                ramp = phase.ramp,
+                "profile phase"
+            );
+
+            if !phase.ramp {
+                state.set_rps(to_rps);
+                state.set_alpha(to_alpha);
+                tokio::time::sleep(phase.duration).await;
+                continue;
+            }
+            let start = Instant::now();
+            let end = start + phase.duration;
+            loop {
+                let progress =
+                    (start.elapsed().as_secs_f64() / phase.duration.as_secs_f64()).min(1.0);
+                let rps = from_rps as f64 + (to_rps as f64 - from_rps as f64) * progress;
+                state.set_rps(rps.round() as u64);
+                state.set_alpha(from_alpha + (to_alpha - from_alpha) * progress);
+                if progress >= 1.0 {
+                    break;
+                }
+                tokio::time::sleep_until((Instant::now() + RAMP_STEP).min(end)).await;
+            }
+        }
+        if !profile.repeat {
+            tracing::info!("profile finished");
+            return;
+        }
+    }
+}
+
+/// `"90s"`-style strings as on the command line, or a number of seconds.
+fn deserialize_duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Seconds(f64),
+        Text(String),
+    }
+    match Raw::deserialize(deserializer)? {
+        Raw::Seconds(secs) => Duration::try_from_secs_f64(secs).map_err(serde::de::Error::custom),
+        Raw::Text(text) => parse_duration(&text).map_err(serde::de::Error::custom),
+    }
+}
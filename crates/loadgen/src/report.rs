@@ -0,0 +1,376 @@
+use crate::distribution::Distribution;
+use crate::latency::{LatencyReport, Percentiles};
+use crate::ops::Protocol;
+use crate::LoadGenState;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+/// Parse a duration from the command line: `90s`, `2m`, `1h`, `500ms`, or
+/// a bare number of seconds. Fractions (`1.5m`) are fine.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration `{s}`"))?;
+    let scale = match unit.trim() {
+        "" | "s" => 1.0,
+        "ms" => 0.001,
+        "m" => 60.0,
+        "h" => 3600.0,
+        other => return Err(format!("unknown unit `{other}` (use ms, s, m or h)")),
+    };
+    Duration::try_from_secs_f64(number * scale).map_err(|_| format!("invalid duration `{s}`"))
+}
+
+/// How a timed run is measured.
+pub struct RunOptions {
+    /// Traffic sent before measuring starts, to fill the cache.
+    pub warmup: Duration,
+    /// How long to measure for; `None` measures until the workload ends.
+    pub duration: Option<Duration>,
+    /// Proxy admin URL for `GET /api/stats`.
+    pub admin_url: String,
+    pub admin_token: Option<String>,
+}
+
+/// Outcome of a timed run, covering the measured period only.
+#[derive(Serialize)]
+pub struct Report {
+    pub warmup_secs: f64,
+    pub duration_secs: f64,
+    pub requests: u64,
+    pub errors: u64,
+    pub throughput_rps: f64,
+    /// Share of reads answered from the cache: `X-Cache: HIT` over HTTP,
+    /// an existing key over RESP.
+    pub client_hit_rate: f64,
+    /// Hit rates the proxy counted over the same period, primary policy
+    /// first. Empty when its admin API couldn't be reached.
+    pub proxy: Vec<PolicyHitRate>,
+    pub latency: LatencyReport,
+    pub distribution: Distribution,
+    pub alpha: f64,
+    pub num_items: u64,
+    pub churn_rate: f64,
+    pub retire: bool,
+    pub concurrency: u64,
+    pub rps: u64,
+    pub open_loop: bool,
+    pub protocol: Protocol,
+    pub write_ratio: f64,
+    pub delete_ratio: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PolicyHitRate {
+    pub name: String,
+    pub hits: u64,
+    pub misses: u64,
+    #[serde(default)]
+    pub hit_rate: f64,
+}
+
+/// The part of the proxy's `GET /api/stats` the report needs.
+#[derive(Deserialize)]
+struct ProxyStats {
+    primary: PolicyHitRate,
+    #[serde(default)]
+    comparisons: Vec<PolicyHitRate>,
+}
+
+impl ProxyStats {
+    fn policies(self) -> Vec<PolicyHitRate> {
+        std::iter::once(self.primary)
+            .chain(self.comparisons)
+            .collect()
+    }
+}
+
+/// Let `workload` run through the warmup and then for `duration` (or until
+/// it ends), and report on the part after the warmup. Traffic is paused
+/// once the report is taken.
+pub async fn measure(
+    state: &LoadGenState,
+    client: &Client,
+    options: &RunOptions,
+    workload: &mut JoinHandle<()>,
+) -> Report {
+    if !options.warmup.is_zero() {
+        tracing::info!(secs = options.warmup.as_secs_f64(), "warming up");
+        tokio::select! {
+            _ = tokio::time::sleep(options.warmup) => {}
+            _ = &mut *workload => tracing::warn!("workload ended during warmup"),
+        }
+    }
+
+    state.latency.start_run();
+    let requests = state.total_requests.load(Ordering::Relaxed);
+    let errors = state.failed_requests.load(Ordering::Relaxed);
+    let proxy_before = proxy_stats(client, options).await;
+    let started = Instant::now();
+    tracing::info!(duration = ?options.duration, "measuring");
+
+    if !workload.is_finished() {
+        match options.duration {
+            Some(duration) => {
+                tokio::select! {
+                    _ = tokio::time::sleep(duration) => {}
+                    _ = &mut *workload => {}
+                }
+            }
+            None => {
+                let _ = (&mut *workload).await;
+            }
+        }
+    }
+
+    state.running.store(false, Ordering::Relaxed);
+    let elapsed = started.elapsed().as_secs_f64();
+    let latency = state.latency.run_report();
+    let requests = state.total_requests.load(Ordering::Relaxed) - requests;
+    let proxy = match (proxy_before, proxy_stats(client, options).await) {
+        (Some(before), Some(after)) => hit_rates_between(before, after),
+        _ => Vec::new(),
+    };
+
+    Report {
+        warmup_secs: options.warmup.as_secs_f64(),
+        duration_secs: elapsed,
+        requests,
+        errors: state.failed_requests.load(Ordering::Relaxed) - errors,
+        throughput_rps: if elapsed > 0.0 {
+            requests as f64 / elapsed
+        } else {
+            0.0
+        },
+        client_hit_rate: ratio(latency.hit.count, latency.all.count - latency.write.count),
+        proxy,
+        latency,
+        distribution: state.distribution(),
+        alpha: state.alpha(),
+        num_items: state.num_items,
+        churn_rate: state.keyspace.churn_rate(),
+        retire: state.keyspace.retire(),
+        concurrency: state.concurrency,
+        rps: state.rps(),
+        open_loop: state.open_loop,
+        protocol: state.protocol,
+        write_ratio: state.mix.write,
+        delete_ratio: state.mix.delete,
+        trace: state.trace.clone(),
+        profile: state.profile.clone(),
+    }
+}
+
+async fn proxy_stats(client: &Client, options: &RunOptions) -> Option<Vec<PolicyHitRate>> {
+    let url = format!("{}/api/stats", options.admin_url.trim_end_matches('/'));
+    let mut req = client.get(&url);
+    if let Some(token) = &options.admin_token {
+        req = req.bearer_auth(token);
+    }
+    let result = async {
+        req.send()
+            .await?
+            .error_for_status()?
+            .json::<ProxyStats>()
+            .await
+    }
+    .await;
+    match result {
+        Ok(stats) => Some(stats.policies()),
+        Err(e) => {
+            tracing::warn!(url, error = %e, "couldn't read proxy stats; report will leave them out");
+            None
+        }
+    }
+}
+
+/// Hits and misses each policy counted between two snapshots. Policies
+/// missing from either are left out.
+fn hit_rates_between(before: Vec<PolicyHitRate>, after: Vec<PolicyHitRate>) -> Vec<PolicyHitRate> {
+    after
+        .into_iter()
+        .filter_map(|end| {
+            let start = before.iter().find(|p| p.name == end.name)?;
+            // A stats reset in between makes the counts go backwards
+            let hits = end.hits.saturating_sub(start.hits);
+            let misses = end.misses.saturating_sub(start.misses);
+            Some(PolicyHitRate {
+                name: end.name,
+                hits,
+                misses,
+                hit_rate: ratio(hits, hits + misses),
+            })
+        })
+        .collect()
+}
+
+fn ratio(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 / whole as f64
+    }
+}
+
+impl Report {
+    pub fn log(&self) {
+        let primary = self.proxy.first();
+        tracing::info!(
+            requests = self.requests,
+            errors = self.errors,
+            throughput_rps = format!("{:.0}", self.throughput_rps),
+            client_hit_rate = format!("{:.4}", self.client_hit_rate),
+            proxy_policy = primary.map(|p| p.name.as_str()),
+            proxy_hit_rate = primary.map(|p| format!("{:.4}", p.hit_rate)),
+            p50_us = self.latency.all.p50_us,
+            p99_us = self.latency.all.p99_us,
+            p999_us = self.latency.all.p999_us,
+            "run finished"
+        );
+    }
+
+    /// Write the report to `path`: CSV when it ends in `.csv`, JSON
+    /// otherwise. CSV reports are appended as one row, with a header only
+    /// when the file is new, so repeated runs build up one table.
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let is_csv = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+        if !is_csv {
+            let json = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+            return std::fs::write(path, json + "\n");
+        }
+
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        if file.metadata()?.len() == 0 {
+            writeln!(file, "{}", Self::csv_header())?;
+        }
+        writeln!(file, "{}", self.csv_row())
+    }
+
+    fn csv_header() -> String {
+        let mut columns: Vec<String> = [
+            "warmup_secs",
+            "duration_secs",
+            "requests",
+            "errors",
+            "throughput_rps",
+            "client_hit_rate",
+            "proxy_policy",
+            "proxy_hit_rate",
+        ]
+        .map(String::from)
+        .to_vec();
+        for class in ["all", "hit", "miss", "write"] {
+            for field in ["count", "p50_us", "p95_us", "p99_us", "p999_us", "max_us"] {
+                columns.push(format!("{class}_{field}"));
+            }
+        }
+        columns.extend(
+            [
+                "distribution",
+                "alpha",
+                "num_items",
+                "churn_rate",
+                "retire",
+                "concurrency",
+                "rps",
+                "open_loop",
+                "protocol",
+                "write_ratio",
+                "delete_ratio",
+                "trace",
+                "profile",
+            ]
+            .map(String::from),
+        );
+        columns.join(",")
+    }
+
+    fn csv_row(&self) -> String {
+        let primary = self.proxy.first();
+        let mut fields = vec![
+            format!("{:.3}", self.warmup_secs),
+            format!("{:.3}", self.duration_secs),
+            self.requests.to_string(),
+            self.errors.to_string(),
+            format!("{:.1}", self.throughput_rps),
+            format!("{:.6}", self.client_hit_rate),
+            primary.map(|p| csv_field(&p.name)).unwrap_or_default(),
+            primary
+                .map(|p| format!("{:.6}", p.hit_rate))
+                .unwrap_or_default(),
+        ];
+        for p in [
+            self.latency.all,
+            self.latency.hit,
+            self.latency.miss,
+            self.latency.write,
+        ] {
+            let Percentiles {
+                count,
+                p50_us,
+                p95_us,
+                p99_us,
+                p999_us,
+                max_us,
+            } = p;
+            fields.extend([count, p50_us, p95_us, p99_us, p999_us, max_us].map(|v| v.to_string()));
+        }
+        let distribution = enum_name(self.distribution);
+        fields.extend([
+            distribution,
+            format!("{:.3}", self.alpha),
+            self.num_items.to_string(),
+            self.churn_rate.to_string(),
+            self.retire.to_string(),
+            self.concurrency.to_string(),
+            self.rps.to_string(),
+            self.open_loop.to_string(),
+            enum_name(self.protocol),
+            self.write_ratio.to_string(),
+            self.delete_ratio.to_string(),
+        ]);
+        fields.extend([&self.trace, &self.profile].map(|path| {
+            path.as_deref()
+                .map(|p| csv_field(&p.display().to_string()))
+                .unwrap_or_default()
+        }));
+        fields.join(",")
+    }
+}
+
+/// How `value` is spelled in the JSON report.
+fn enum_name(value: impl Serialize) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|v| v.as_str().map(String::from))
+        .unwrap_or_default()
+}
+
+/// Quote a CSV field if it needs it.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,134 @@
+use crate::latency::CacheOutcome;
+use crate::ops::Op;
+use crate::LoadGenState;
+use bytes::{Bytes, BytesMut};
+use redis_protocol::resp2::decode::decode_bytes_mut;
+use redis_protocol::resp2::encode::extend_encode;
+use redis_protocol::resp2::types::BytesFrame;
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::Instant;
+
+/// Longest wait for a reply, matching the HTTP client's timeout.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// One worker's RESP connection, opened on first use and again after any
+/// I/O error.
+pub struct RespDriver {
+    addr: String,
+    password: Option<String>,
+    conn: Option<Connection>,
+}
+
+impl RespDriver {
+    pub fn new(addr: String, password: Option<String>) -> Self {
+        Self {
+            addr,
+            password,
+            conn: None,
+        }
+    }
+
+    /// Run `op` on `item:{item_id}` and record its latency from `scheduled`.
+    /// `GET` counts as a hit when the key exists.
+    pub async fn send(
+        &mut self,
+        state: &LoadGenState,
+        op: Op,
+        item_id: u64,
+        scheduled: Instant,
+    ) -> io::Result<()> {
+        let result = self.run(state, op, item_id).await;
+        state.record(scheduled, result.as_ref().ok().copied());
+        result.map(|_| ())
+    }
+
+    async fn run(
+        &mut self,
+        state: &LoadGenState,
+        op: Op,
+        item_id: u64,
+    ) -> io::Result<CacheOutcome> {
+        let key = Bytes::from(format!("item:{item_id}"));
+        let args = match op {
+            Op::Read => vec![Bytes::from_static(b"GET"), key],
+            Op::Write => vec![Bytes::from_static(b"SET"), key, state.write_value.clone()],
+            Op::Delete => vec![Bytes::from_static(b"DEL"), key],
+        };
+        let conn = match &mut self.conn {
+            Some(conn) => conn,
+            None => self
+                .conn
+                .insert(Connection::open(&self.addr, self.password.as_deref()).await?),
+        };
+        let reply = match tokio::time::timeout(TIMEOUT, conn.call(args)).await {
+            Ok(Ok(reply)) => reply,
+            Ok(Err(e)) => {
+                self.conn = None;
+                return Err(e);
+            }
+            Err(_) => {
+                // The reply may still arrive, out of step with the next command
+                self.conn = None;
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "RESP reply timed out",
+                ));
+            }
+        };
+        match (op, reply) {
+            (_, BytesFrame::Error(message)) => Err(io::Error::other(message.to_string())),
+            (Op::Read, BytesFrame::Null) => Ok(CacheOutcome::Miss),
+            (Op::Read, _) => Ok(CacheOutcome::Hit),
+            _ => Ok(CacheOutcome::Write),
+        }
+    }
+}
+
+struct Connection {
+    stream: TcpStream,
+    buf: BytesMut,
+}
+
+impl Connection {
+    async fn open(addr: &str, password: Option<&str>) -> io::Result<Self> {
+        let stream = tokio::time::timeout(TIMEOUT, TcpStream::connect(addr))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "RESP connect timed out"))??;
+        stream.set_nodelay(true)?;
+        let mut conn = Self {
+            stream,
+            buf: BytesMut::with_capacity(4096),
+        };
+        if let Some(password) = password {
+            let args = vec![
+                Bytes::from_static(b"AUTH"),
+                Bytes::from(password.to_string()),
+            ];
+            if let BytesFrame::Error(message) = conn.call(args).await? {
+                return Err(io::Error::other(format!("AUTH failed: {message}")));
+            }
+        }
+        Ok(conn)
+    }
+
+    /// Send one command and wait for its reply.
+    async fn call(&mut self, args: Vec<Bytes>) -> io::Result<BytesFrame> {
+        let command = BytesFrame::Array(args.into_iter().map(BytesFrame::BulkString).collect());
+        let mut out = BytesMut::new();
+        extend_encode(&mut out, &command, false).map_err(io::Error::other)?;
+        self.stream.write_all(&out).await?;
+        loop {
+            if let Some((frame, _, _)) =
+                decode_bytes_mut(&mut self.buf).map_err(io::Error::other)?
+            {
+                return Ok(frame);
+            }
+            if self.stream.read_buf(&mut self.buf).await? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+    }
+}
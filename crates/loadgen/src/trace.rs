@@ -0,0 +1,178 @@
+use crate::distribution::fnv1a;
+use crate::ops::Op;
+use crate::{fetch, LoadGenState};
+use clap::ValueEnum;
+use reqwest::Client;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::Semaphore;
+use tokio::time::Instant;
+
+/// Malformed lines logged before the rest are only counted.
+const MAX_LOGGED_SKIPS: u64 = 5;
+/// How far behind schedule replay can fall before it's logged.
+const LAG_WARNING: Duration = Duration::from_secs(1);
+
+/// Layout of a request trace.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum TraceFormat {
+    /// `key,timestamp[,size]` with the timestamp in seconds. A header line
+    /// is skipped.
+    Csv,
+    /// Twitter's cache traces: `timestamp,key,key_size,value_size,client_id,operation,ttl`.
+    /// Only `get` and `gets` are replayed.
+    Twitter,
+    /// Wikimedia CDN traces: whitespace-separated `timestamp key [type] size ...`.
+    Wiki,
+}
+
+/// One request from a trace.
+#[derive(Debug, PartialEq)]
+struct TraceRecord {
+    /// Seconds, on the trace's own clock.
+    timestamp: f64,
+    /// Numeric keys are used as item IDs as they are; others are hashed.
+    item_id: u64,
+    size: Option<u64>,
+}
+
+impl TraceFormat {
+    /// `None` for lines that aren't requests: malformed ones, headers, and
+    /// operations other than reads.
+    fn parse(self, line: &str) -> Option<TraceRecord> {
+        let (timestamp, key, size) = match self {
+            TraceFormat::Csv => {
+                let mut fields = line.split(',').map(str::trim);
+                let key = fields.next()?;
+                let timestamp = fields.next()?;
+                (timestamp, key, fields.next())
+            }
+            TraceFormat::Twitter => {
+                let fields: Vec<&str> = line.split(',').collect();
+                if fields.len() < 6 || !matches!(fields[5], "get" | "gets") {
+                    return None;
+                }
+                (fields[0], fields[1], Some(fields[3]))
+            }
+            TraceFormat::Wiki => {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                // The upload traces have an image type column before the size
+                let size = match fields.len() {
+                    0..=1 => return None,
+                    2 => None,
+                    3 | 4 => Some(fields[2]),
+                    _ => Some(fields[3]),
+                };
+                (fields[0], fields[1], size)
+            }
+        };
+        if key.is_empty() {
+            return None;
+        }
+        Some(TraceRecord {
+            timestamp: timestamp.parse().ok()?,
+            item_id: key.parse().unwrap_or_else(|_| fnv1a(key.as_bytes())),
+            size: size.and_then(|s| s.parse().ok()),
+        })
+    }
+}
+
+/// Replay the trace at `path`, keeping the gaps between its timestamps
+/// divided by `speed` (0 sends as fast as `concurrency` allows). Each record
+/// becomes `GET /api/items/{id}`, with `?size=` when the trace has one.
+///
+/// Requests go out on schedule whether or not earlier ones have finished,
+/// up to `concurrency` at a time; beyond that, replay falls behind the
+/// trace rather than drop requests. Latency counts from when a request was
+/// due, as in the open-loop mode. Pausing through `/control` stops the
+/// trace clock too.
+pub async fn replay(
+    state: Arc<LoadGenState>,
+    client: Client,
+    path: &Path,
+    format: TraceFormat,
+    speed: f64,
+) -> std::io::Result<()> {
+    let file = tokio::fs::File::open(path).await?;
+    let mut lines = BufReader::new(file).lines();
+    let slots = Arc::new(Semaphore::new(state.concurrency.max(1) as usize));
+
+    let started = Instant::now();
+    // Trace time of the first record, and when it was (re)aligned to the wall clock
+    let mut origin: Option<(f64, Instant)> = None;
+    let (mut replayed, mut skipped) = (0u64, 0u64);
+    let mut lagging = false;
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some(record) = format.parse(&line) else {
+            skipped += 1;
+            if skipped <= MAX_LOGGED_SKIPS {
+                tracing::debug!(line, "skipping trace line");
+            }
+            continue;
+        };
+
+        if !state.running.load(Ordering::Relaxed) {
+            while !state.running.load(Ordering::Relaxed) {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            // Resume where we left off rather than rush to catch up
+            origin = None;
+        }
+        let due = if speed > 0.0 {
+            let (first, at) = *origin.get_or_insert((record.timestamp, Instant::now()));
+            let offset = ((record.timestamp - first) / speed).max(0.0);
+            let due = at + Duration::from_secs_f64(offset);
+            let now = Instant::now();
+            if due > now {
+                tokio::time::sleep_until(due).await;
+                lagging = false;
+            } else if now - due > LAG_WARNING && !lagging {
+                tracing::warn!(
+                    behind_ms = (now - due).as_millis() as u64,
+                    "trace replay falling behind; raise --concurrency"
+                );
+                lagging = true;
+            }
+            Some(due)
+        } else {
+            None
+        };
+
+        let permit = Arc::clone(&slots)
+            .acquire_owned()
+            .await
+            .expect("semaphore never closed");
+        // With no schedule to keep, latency counts from when a slot was free
+        let due = due.unwrap_or_else(Instant::now);
+        let path = match record.size {
+            Some(size) => format!("/api/items/{}?size={size}", record.item_id),
+            None => format!("/api/items/{}", record.item_id),
+        };
+        let state = Arc::clone(&state);
+        let client = client.clone();
+        tokio::spawn(async move {
+            if let Err(e) = fetch(&state, &client, Op::Read, &path, due).await {
+                tracing::debug!(error = %e, "trace request failed");
+            }
+            drop(permit);
+        });
+        replayed += 1;
+    }
+
+    // Let the last requests finish
+    let _ = slots.acquire_many(state.concurrency.max(1) as u32).await;
+    tracing::info!(
+        replayed,
+        skipped,
+        elapsed_secs = started.elapsed().as_secs(),
+        "trace finished"
+    );
+    Ok(())
+}
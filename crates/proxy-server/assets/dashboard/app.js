@@ -0,0 +1,166 @@
+"use strict";
+
+// Built-in dashboard: live charts from /ws/metrics, back-filled from
+// /api/history when the admin API shares this listener.
+
+const MAX_POINTS = 600; // 5 minutes at 500ms
+const RECONNECT_DELAY = 2000;
+// Primary first, then one color per comparison policy
+const COLORS = ["#22d3ee", "#f472b6", "#a3e635", "#fbbf24", "#c084fc"];
+
+let history = [];
+
+function merge(snapshots) {
+  const byTime = new Map();
+  for (const s of history.concat(snapshots)) byTime.set(s.timestamp_ms, s);
+  history = [...byTime.values()]
+    .sort((a, b) => a.timestamp_ms - b.timestamp_ms)
+    .slice(-MAX_POINTS);
+  render();
+}
+
+const policies = (s) => [s.primary, ...(s.comparisons || [])];
+const recentRate = (p) => (p.windows ? p.windows["1m"] : p.hit_rate);
+const pct = (rate) => `${(rate * 100).toFixed(1)}%`;
+const num = (n) => n.toLocaleString();
+
+function drawChart(canvas, series, max) {
+  const dpr = window.devicePixelRatio || 1;
+  const w = canvas.clientWidth;
+  const h = canvas.clientHeight;
+  canvas.width = w * dpr;
+  canvas.height = h * dpr;
+  const ctx = canvas.getContext("2d");
+  ctx.scale(dpr, dpr);
+
+  const pad = { left: 48, right: 8, top: 8, bottom: 8 };
+  const plotW = w - pad.left - pad.right;
+  const plotH = h - pad.top - pad.bottom;
+  const values = series.flatMap((s) => s.values).filter((v) => v != null);
+  const top = max ?? Math.max(1, ...values) * 1.1;
+
+  ctx.font = "11px system-ui, sans-serif";
+  ctx.textAlign = "right";
+  ctx.textBaseline = "middle";
+  for (let i = 0; i <= 4; i++) {
+    const y = pad.top + (plotH * i) / 4;
+    ctx.strokeStyle = "#334155";
+    ctx.beginPath();
+    ctx.moveTo(pad.left, y);
+    ctx.lineTo(w - pad.right, y);
+    ctx.stroke();
+    ctx.fillStyle = "#64748b";
+    ctx.fillText(Math.round(top * (1 - i / 4)).toLocaleString(), pad.left - 6, y);
+  }
+
+  for (const { values, color } of series) {
+    ctx.strokeStyle = color;
+    ctx.lineWidth = 2;
+    ctx.beginPath();
+    let drawing = false;
+    values.forEach((v, i) => {
+      if (v == null) {
+        drawing = false;
+        return;
+      }
+      const x = pad.left + (plotW * i) / Math.max(1, values.length - 1);
+      const y = pad.top + plotH * (1 - Math.min(v, top) / top);
+      if (drawing) ctx.lineTo(x, y);
+      else ctx.moveTo(x, y);
+      drawing = true;
+    });
+    ctx.stroke();
+  }
+}
+
+function render() {
+  const latest = history[history.length - 1];
+  if (!latest) return;
+  const current = policies(latest);
+
+  drawChart(
+    document.getElementById("hit-rate"),
+    current.map((p, j) => ({
+      color: COLORS[j % COLORS.length],
+      values: history.map((s) => {
+        const q = policies(s)[j];
+        return q && q.name === p.name ? recentRate(q) * 100 : null;
+      }),
+    })),
+    100,
+  );
+  drawChart(document.getElementById("throughput"), [
+    { color: COLORS[0], values: history.map((s) => s.throughput_rps) },
+  ]);
+
+  const legend = document.getElementById("legend");
+  legend.replaceChildren(
+    ...current.map((p, j) => {
+      const item = document.createElement("span");
+      item.style.setProperty("--c", COLORS[j % COLORS.length]);
+      item.textContent = p.name;
+      return item;
+    }),
+  );
+
+  const rows = document.getElementById("policies");
+  rows.replaceChildren(
+    ...current.map((p) => {
+      const row = document.createElement("tr");
+      const w = p.windows || {};
+      for (const cell of [
+        p.name,
+        pct(p.hit_rate),
+        pct(w["10s"] ?? 0),
+        pct(w["1m"] ?? 0),
+        pct(w["5m"] ?? 0),
+        num(p.hits),
+        num(p.misses),
+        num(p.evictions),
+        `${num(p.size)} / ${num(p.capacity)}`,
+      ]) {
+        const td = document.createElement("td");
+        td.textContent = cell;
+        row.appendChild(td);
+      }
+      return row;
+    }),
+  );
+
+  document.getElementById("meta").textContent =
+    `${latest.mode} mode · up ${latest.uptime_seconds}s · ${Math.round(latest.throughput_rps)} rps`;
+}
+
+function setStatus(live) {
+  const status = document.getElementById("status");
+  status.textContent = live ? "live" : "disconnected, retrying…";
+  status.className = live ? "live" : "";
+}
+
+function connect() {
+  const protocol = location.protocol === "https:" ? "wss:" : "ws:";
+  const ws = new WebSocket(`${protocol}//${location.host}/ws/metrics`);
+
+  ws.onopen = () => {
+    setStatus(true);
+    fetch("/api/history?window=300")
+      .then((res) => (res.ok ? res.json() : { snapshots: [] }))
+      .then(({ snapshots }) => merge(snapshots))
+      .catch(() => {});
+  };
+  ws.onmessage = (event) => {
+    try {
+      merge([JSON.parse(event.data)]);
+    } catch {
+      // ignore malformed snapshots
+    }
+  };
+  ws.onclose = () => {
+    setStatus(false);
+    setTimeout(connect, RECONNECT_DELAY);
+  };
+  ws.onerror = () => ws.close();
+}
+
+window.addEventListener("resize", render);
+connect();
@@ -0,0 +1,147 @@
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, Uri};
+use bytes::Bytes;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use proxy_server::cache_layer::CacheLayer;
+use proxy_server::config::KeyConfig;
+use proxy_server::http_key::HttpKey;
+use proxy_server::proxy::{build_cached_response, cache_key, with_cache_key};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Counts heap allocations, so the hit path's allocation budget is checked
+/// on every run rather than only read off a flamegraph.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// What building a hit's response may allocate: the cloned `HeaderMap` and
+/// the room reserved in it for colander's headers, the boxed body, and the
+/// `Age`, `Date` and `X-Cache-Hits` values. Stored header names, values and
+/// the body are shared, not copied.
+const RESPONSE_ALLOCATIONS: usize = 11;
+
+/// Heap allocations `f` makes per call, averaged over a few calls after a
+/// warm-up one (which fills the thread-local key buffer).
+fn allocations_per_call<R>(mut f: impl FnMut() -> R) -> usize {
+    const CALLS: usize = 100;
+    black_box(f());
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..CALLS {
+        black_box(f());
+    }
+    (ALLOCATIONS.load(Ordering::Relaxed) - before) / CALLS
+}
+
+fn typical_headers() -> HeaderMap {
+    [
+        ("content-type", "application/json"),
+        ("content-length", "312"),
+        ("cache-control", "public, max-age=60"),
+        ("etag", "\"v1-42\""),
+        ("date", "Thu, 15 Oct 2026 10:00:00 GMT"),
+        ("server", "demo-backend"),
+        ("vary", "accept-encoding"),
+        ("x-request-id", "4f1c2a9e-7d2b-4c8e-9a51-0e6b3f2d1c77"),
+    ]
+    .iter()
+    .map(|(k, v)| (HeaderName::from_static(k), HeaderValue::from_static(v)))
+    .collect()
+}
+
+fn key_building(c: &mut Criterion) {
+    let uri: Uri = "/api/items/42?fields=name,data".parse().unwrap();
+    let plain = KeyConfig::default();
+    let keys = HttpKey::new(&plain);
+    let mut group = c.benchmark_group("cache_key");
+
+    // Baseline: the per-request format! the handler used to do
+    group.bench_function("format_alloc", |b| {
+        b.iter(|| black_box(format!("{}:{}", Method::GET, black_box(&uri))))
+    });
+    group.bench_function("thread_local_buffer", |b| {
+        b.iter(|| with_cache_key(&keys, &Method::GET, black_box(&uri), |k| black_box(k.len())))
+    });
+
+    // Sorting + stripping allocates a small Vec per key
+    let normalizing = KeyConfig {
+        sort_query: true,
+        strip_params: vec!["utm_*".into(), "gclid".into()],
+        ..KeyConfig::default()
+    };
+    let normalized = HttpKey::new(&normalizing);
+    let tracked: Uri = "/api/items/42?utm_source=x&page=2&fields=name&gclid=abc"
+        .parse()
+        .unwrap();
+    group.bench_function("normalized", |b| {
+        b.iter(|| {
+            with_cache_key(&normalized, &Method::GET, black_box(&tracked), |k| {
+                black_box(k.len())
+            })
+        })
+    });
+    group.finish();
+}
+
+fn hit_path(c: &mut Criterion) {
+    let cache = CacheLayer::new("sieve", &[], 10_000, Duration::from_secs(60), 1_048_576);
+    let plain = KeyConfig::default();
+    let keys = HttpKey::new(&plain);
+    let uri: Uri = "/api/items/42".parse().unwrap();
+    let body = Bytes::from(vec![b'x'; 312]);
+    cache.insert(
+        cache_key(&keys, &Method::GET, &uri),
+        cache.build_response(200, typical_headers(), body, None),
+    );
+
+    // Fails the run (`cargo bench -p proxy-server -- --test` included) if
+    // the hit path starts allocating again
+    let lookup = || {
+        with_cache_key(&keys, &Method::GET, black_box(&uri), |k| cache.get(k).value)
+            .expect("entry is cached")
+    };
+    let format_key = || format!("{}:{}", Method::GET, black_box(&uri));
+    assert!(allocations_per_call(format_key) > 0);
+    assert_eq!(
+        allocations_per_call(lookup),
+        0,
+        "key build and lookup allocate"
+    );
+    let cached = lookup();
+    let response = allocations_per_call(|| build_cached_response(&cached, &cache));
+    assert!(
+        response <= RESPONSE_ALLOCATIONS,
+        "building a hit's response made {response} allocations, over {RESPONSE_ALLOCATIONS}"
+    );
+
+    c.bench_function("hit_path/lookup_and_build_response", |b| {
+        b.iter(|| {
+            let cached =
+                with_cache_key(&keys, &Method::GET, black_box(&uri), |k| cache.get(k).value)
+                    .expect("entry is cached");
+            black_box(build_cached_response(&cached, &cache))
+        })
+    });
+}
+
+criterion_group!(benches, key_building, hit_path);
+criterion_main!(benches);
@@ -0,0 +1,28 @@
+//! Embeds the git commit into the binary for `GET /api/version`.
+//!
+//! `COLANDER_GIT_HASH` in the build environment wins (Docker builds have no
+//! `.git`); otherwise `git rev-parse` is tried, falling back to "unknown".
+
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=COLANDER_GIT_HASH");
+    println!("cargo:rerun-if-changed=../../.git/HEAD");
+    println!("cargo:rerun-if-changed=../../.git/refs/heads");
+
+    let hash = std::env::var("COLANDER_GIT_HASH")
+        .ok()
+        .filter(|h| !h.is_empty())
+        .or_else(|| {
+            let out = Command::new("git")
+                .args(["rev-parse", "--short=12", "HEAD"])
+                .output()
+                .ok()?;
+            out.status
+                .success()
+                .then(|| String::from_utf8_lossy(&out.stdout).trim().to_string())
+        })
+        .unwrap_or_else(|| "unknown".to_string());
+
+    println!("cargo:rustc-env=COLANDER_GIT_HASH={hash}");
+}
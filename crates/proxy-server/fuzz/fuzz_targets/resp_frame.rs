@@ -0,0 +1,44 @@
+//! Feed arbitrary bytes to the RESP server's frame decoder, arriving in
+//! chunks as they would off a socket, and check it never panics, always
+//! makes progress on a complete frame, and only returns frames that encode.
+
+#![no_main]
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use proxy_server::resp::{decode_frame, Decoded};
+use redis_protocol::resp2::encode::extend_encode;
+
+/// Small enough that the fuzzer reaches the too-large path.
+const MAX_FRAME: usize = 4096;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&chunk, data)) = data.split_first() else {
+        return;
+    };
+    let chunk = usize::from(chunk).max(1);
+    let mut buf = BytesMut::new();
+    for piece in data.chunks(chunk) {
+        buf.extend_from_slice(piece);
+        loop {
+            let before = buf.len();
+            match decode_frame(&mut buf, MAX_FRAME) {
+                Decoded::Frame(frame) => {
+                    assert!(buf.len() < before, "frame decoded from nothing");
+                    let mut out = BytesMut::new();
+                    extend_encode(&mut out, &frame, false).expect("decoded frame encodes");
+                }
+                Decoded::Blank => assert!(buf.len() < before, "blank line not consumed"),
+                Decoded::Incomplete { too_large } => {
+                    assert_eq!(buf.len(), before, "incomplete frame consumed");
+                    if too_large {
+                        return;
+                    }
+                    break;
+                }
+                // The connection closes here
+                Decoded::Invalid(_) => return,
+            }
+        }
+    }
+});
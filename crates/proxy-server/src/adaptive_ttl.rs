@@ -0,0 +1,112 @@
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+
+const NUM_SHARDS: usize = 16;
+
+/// What we've learned about one key's change rate.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct TtlHistory {
+    #[serde(skip)]
+    fingerprint: u64,
+    /// TTL that will be applied on the next fill.
+    pub ttl_seconds: u64,
+    /// How many times the object has been fetched from upstream.
+    pub observations: u64,
+    /// How many of those fetches returned a different version than the last.
+    pub changes: u64,
+}
+
+/// Self-tuning per-key TTLs.
+///
+/// Every upstream fill is compared against the previous version of the same
+/// key (ETag when present, otherwise a body hash). Unchanged objects get their
+/// TTL doubled, changed objects get it halved, always within `[min, max]`.
+/// Stable objects drift toward long TTLs; churny ones toward short.
+///
+/// History outlives cache entries (the point is to learn across refetches), so
+/// it is kept in its own bounded map: once a shard is full, an arbitrary old
+/// key is forgotten to make room.
+pub struct AdaptiveTtl {
+    shards: Vec<Mutex<HashMap<String, TtlHistory>>>,
+    per_shard: usize,
+    min: Duration,
+    max: Duration,
+}
+
+impl AdaptiveTtl {
+    pub fn new(capacity: usize, min: Duration, max: Duration) -> Self {
+        let per_shard = (capacity / NUM_SHARDS).max(1);
+        Self {
+            shards: (0..NUM_SHARDS)
+                .map(|_| Mutex::new(HashMap::with_capacity(per_shard)))
+                .collect(),
+            per_shard,
+            min,
+            max: max.max(min),
+        }
+    }
+
+    /// Record a fresh upstream fill and return the TTL to cache it with.
+    pub fn observe(&self, key: &str, fingerprint: u64, default_ttl: Duration) -> Duration {
+        let mut shard = self.shards[shard_index(key)].lock();
+
+        if let Some(history) = shard.get_mut(key) {
+            history.observations += 1;
+            let ttl = Duration::from_secs(history.ttl_seconds);
+            let next = if history.fingerprint == fingerprint {
+                ttl.saturating_mul(2)
+            } else {
+                history.changes += 1;
+                ttl / 2
+            };
+            let next = next.clamp(self.min, self.max);
+            history.fingerprint = fingerprint;
+            history.ttl_seconds = next.as_secs();
+            return next;
+        }
+
+        if shard.len() >= self.per_shard {
+            if let Some(victim) = shard.keys().next().cloned() {
+                shard.remove(&victim);
+            }
+        }
+
+        let ttl = default_ttl.clamp(self.min, self.max);
+        shard.insert(
+            key.to_string(),
+            TtlHistory {
+                fingerprint,
+                ttl_seconds: ttl.as_secs(),
+                observations: 1,
+                changes: 0,
+            },
+        );
+        ttl
+    }
+
+    /// Learned history for a key, if we've seen it.
+    pub fn history(&self, key: &str) -> Option<TtlHistory> {
+        self.shards[shard_index(key)].lock().get(key).copied()
+    }
+}
+
+/// Fingerprint a response version: the ETag if the origin sent one, otherwise
+/// a hash of the body.
+pub fn fingerprint(etag: Option<&str>, body: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    match etag {
+        Some(tag) => tag.hash(&mut hasher),
+        None => body.hash(&mut hasher),
+    }
+    hasher.finish()
+}
+
+fn shard_index(key: &str) -> usize {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    (hasher.finish() as usize) % NUM_SHARDS
+}
@@ -0,0 +1,167 @@
+use crate::cache_layer::POLICIES;
+use crate::config::CONFIG_SCHEMA_VERSION;
+use serde::Serialize;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Short commit hash, or "unknown" (see `build.rs`).
+pub const GIT_HASH: &str = env!("COLANDER_GIT_HASH");
+
+/// Optional capabilities compiled into this binary. Clients should probe for a
+/// name here rather than compare version numbers; anything not listed (e.g. a
+/// disk tier) isn't available. Add the name here when adding a capability;
+/// the tests below fail for a new module that isn't accounted for.
+pub const FEATURES: &[&str] = &[
+    "tls",
+    "resp",
+    "memcached",
+    "hot_keys",
+    "adaptive_ttl",
+    "upstream_failover",
+    "outlier_detection",
+    "warmup",
+    "drain",
+    "read_only",
+    "cluster_stats",
+    "http2",
+    "load_shedding",
+    "circuit_breaker",
+    "metrics_export",
+    "upstream_retries",
+    "stats_reset",
+    "sse_metrics",
+    "builtin_dashboard",
+    "cluster_routing",
+    "invalidation_broadcast",
+    "write_invalidation",
+    "refresh_ahead",
+    "request_coalescing",
+    "memory_limit",
+    "tenants",
+    "quotas",
+    "partitioning",
+    "client_directives",
+    "keyspace_notifications",
+    "range_requests",
+    "cache_dump",
+    "upgrade_passthrough",
+    "forwarded_headers",
+    "auth_bypass",
+    "tower_layer",
+    #[cfg(feature = "soak")]
+    "soak",
+    #[cfg(feature = "wasm")]
+    "wasm_plugins",
+];
+
+/// Everything `GET /api/version` reports.
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_hash: &'static str,
+    pub features: &'static [&'static str],
+    pub policies: &'static [&'static str],
+    pub config_schema_version: u32,
+}
+
+pub fn build_info() -> BuildInfo {
+    BuildInfo {
+        version: VERSION,
+        git_hash: GIT_HASH,
+        features: FEATURES,
+        policies: POLICIES,
+        config_schema_version: CONFIG_SCHEMA_VERSION,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FEATURES;
+    use std::collections::BTreeSet;
+
+    /// The module implementing each feature.
+    const IMPLEMENTED_IN: &[(&str, &str)] = &[
+        ("tls", "tls"),
+        ("resp", "resp"),
+        ("memcached", "memcached"),
+        ("hot_keys", "cache_layer"),
+        ("adaptive_ttl", "adaptive_ttl"),
+        ("upstream_failover", "upstream"),
+        ("outlier_detection", "upstream"),
+        ("warmup", "warmup"),
+        ("drain", "drain"),
+        ("read_only", "cache_layer"),
+        ("cluster_stats", "cluster"),
+        ("http2", "listeners"),
+        ("load_shedding", "shed"),
+        ("circuit_breaker", "circuit"),
+        ("metrics_export", "export"),
+        ("upstream_retries", "upstream"),
+        ("stats_reset", "metrics"),
+        ("sse_metrics", "metrics"),
+        ("builtin_dashboard", "dashboard"),
+        ("cluster_routing", "cluster"),
+        ("invalidation_broadcast", "invalidation"),
+        ("write_invalidation", "writes"),
+        ("refresh_ahead", "refresh"),
+        ("request_coalescing", "cache_layer"),
+        ("memory_limit", "cache_layer"),
+        ("tenants", "tenant"),
+        ("quotas", "quota"),
+        ("partitioning", "cache_layer"),
+        ("client_directives", "directives"),
+        ("keyspace_notifications", "pubsub"),
+        ("range_requests", "range"),
+        ("cache_dump", "dump"),
+        ("upgrade_passthrough", "tunnel"),
+        ("forwarded_headers", "forwarded"),
+        ("auth_bypass", "bypass"),
+        ("tower_layer", "layer"),
+        #[cfg(feature = "soak")]
+        ("soak", "soak"),
+        #[cfg(feature = "wasm")]
+        ("wasm_plugins", "plugin"),
+    ];
+
+    /// Modules that only support the features above.
+    const SUPPORT: &[&str] = &[
+        "build_info",
+        "config",
+        "hop_by_hop",
+        "http_key",
+        "plugin",
+        "proxy",
+        "server",
+        "soak",
+    ];
+
+    fn modules() -> BTreeSet<&'static str> {
+        include_str!("lib.rs")
+            .lines()
+            .filter_map(|line| line.strip_prefix("pub mod ")?.strip_suffix(';'))
+            .collect()
+    }
+
+    #[test]
+    fn every_feature_names_its_module() {
+        let listed: BTreeSet<_> = FEATURES.iter().copied().collect();
+        let mapped: BTreeSet<_> = IMPLEMENTED_IN.iter().map(|&(f, _)| f).collect();
+        assert_eq!(listed, mapped, "FEATURES and IMPLEMENTED_IN disagree");
+        assert_eq!(listed.len(), FEATURES.len(), "a feature is listed twice");
+
+        let modules = modules();
+        for (feature, module) in IMPLEMENTED_IN {
+            assert!(modules.contains(module), "{feature}: no module {module}");
+        }
+    }
+
+    #[test]
+    fn every_module_is_accounted_for() {
+        for module in modules() {
+            assert!(
+                SUPPORT.contains(&module) || IMPLEMENTED_IN.iter().any(|&(_, m)| m == module),
+                "module {module} is neither a feature in FEATURES nor listed in SUPPORT"
+            );
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use crate::config::BypassConfig;
+use axum::http::header::COOKIE;
+use axum::http::{HeaderMap, Uri};
+use colander_cache::keys::{HashedKey, KeyBuilder, RawKey};
+
+/// The credentials `[cache.bypass]` says make this request private, as
+/// `name=value` lines, or `None` for a request that may share the cache.
+///
+/// Only the names configured are inspected, so anonymous traffic costs a
+/// couple of header lookups; cookies and the query string are only parsed
+/// when cookie or query-param names are configured.
+pub fn private_credentials(
+    config: &BypassConfig,
+    headers: &HeaderMap,
+    uri: &Uri,
+) -> Option<String> {
+    let mut found = String::new();
+
+    for name in &config.headers {
+        for value in headers.get_all(name.as_str()) {
+            push(&mut found, name, &String::from_utf8_lossy(value.as_bytes()));
+        }
+    }
+
+    if !config.cookies.is_empty() {
+        for header in headers.get_all(COOKIE) {
+            let Ok(header) = header.to_str() else {
+                continue;
+            };
+            for (name, value) in pairs(header, ';') {
+                if config.cookies.iter().any(|c| c == name) {
+                    push(&mut found, name, value);
+                }
+            }
+        }
+    }
+
+    if !config.query_params.is_empty() {
+        for (name, value) in pairs(uri.query().unwrap_or(""), '&') {
+            if config.query_params.iter().any(|p| p == name) {
+                push(&mut found, name, value);
+            }
+        }
+    }
+
+    (!found.is_empty()).then_some(found)
+}
+
+/// Scope `key` to one user: appends a digest of their credentials, so the
+/// credentials themselves never show up in keys, logs or `/api/hotkeys`.
+pub fn per_user_key(key: &str, credentials: &str) -> String {
+    format!("{key}#user={}", HashedKey(RawKey).key(credentials))
+}
+
+fn pairs(s: &str, separator: char) -> impl Iterator<Item = (&str, &str)> {
+    s.split(separator).filter_map(|pair| {
+        let pair = pair.trim();
+        if pair.is_empty() {
+            return None;
+        }
+        Some(pair.split_once('=').unwrap_or((pair, "")))
+    })
+}
+
+fn push(out: &mut String, name: &str, value: &str) {
+    out.push_str(name);
+    out.push('=');
+    out.push_str(value);
+    out.push('\n');
+}
@@ -1,16 +1,28 @@
+use crate::adaptive_ttl::{AdaptiveTtl, TtlHistory};
+use crate::config::CacheConfig;
+use crate::invalidation::InvalidationBus;
+use crate::pubsub::PubSub;
+use crate::quota::{HttpQuotas, QuotaReport};
 use colander_cache::fifo::FifoCache;
+use colander_cache::fill::{Claim, FillGuard, Fills};
+use colander_cache::hotkeys::HotKeyTracker;
 use colander_cache::lru::LruCache;
-use colander_cache::sharded::ShardedCache;
+use colander_cache::sharded::{PartitionSkew, Partitioning, QuotaUsage, ScanPage, ShardedCache};
 use colander_cache::sieve::SieveCache;
-use colander_cache::traits::{CacheStats, CachedResponse};
+use colander_cache::traits::{CacheListener, CacheStats, CachedResponse, InsertSource};
+use colander_cache::CacheError;
 
+use axum::http::HeaderMap;
 use bytes::Bytes;
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use parking_lot::Mutex;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 /// Runtime mode for the dual-cache system.
-/// - Demo: updates both caches, serves from primary (fair hit-rate comparison)
+/// - Demo: updates every cache, serves from primary (fair hit-rate comparison)
 /// - Bench: updates only primary cache (true latency/throughput)
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CacheMode {
@@ -34,6 +46,23 @@ impl CacheInner {
         }
     }
 
+    fn peek(&self, key: &str) -> Option<Arc<CachedResponse>> {
+        match self {
+            CacheInner::Sieve(c) => c.peek(key),
+            CacheInner::Lru(c) => c.peek(key),
+            CacheInner::Fifo(c) => c.peek(key),
+        }
+    }
+
+    fn scan(&self, after: Option<&str>, limit: usize, prefix: &str) -> ScanPage {
+        let filter = |key: &str| key.starts_with(prefix);
+        match self {
+            CacheInner::Sieve(c) => c.scan(after, limit, filter),
+            CacheInner::Lru(c) => c.scan(after, limit, filter),
+            CacheInner::Fifo(c) => c.scan(after, limit, filter),
+        }
+    }
+
     fn insert(&self, key: String, value: CachedResponse) {
         match self {
             CacheInner::Sieve(c) => c.insert(key, value),
@@ -42,6 +71,42 @@ impl CacheInner {
         }
     }
 
+    fn insert_and_get(&self, key: String, value: CachedResponse) -> Arc<CachedResponse> {
+        match self {
+            CacheInner::Sieve(c) => c.insert_and_get(key, value),
+            CacheInner::Lru(c) => c.insert_and_get(key, value),
+            CacheInner::Fifo(c) => c.insert_and_get(key, value),
+        }
+    }
+
+    fn get_many(&self, keys: &[String]) -> Vec<Option<Arc<CachedResponse>>> {
+        match self {
+            CacheInner::Sieve(c) => c.get_many(keys),
+            CacheInner::Lru(c) => c.get_many(keys),
+            CacheInner::Fifo(c) => c.get_many(keys),
+        }
+    }
+
+    fn insert_many(&self, entries: Vec<(String, CachedResponse)>) {
+        match self {
+            CacheInner::Sieve(c) => c.insert_many(entries),
+            CacheInner::Lru(c) => c.insert_many(entries),
+            CacheInner::Fifo(c) => c.insert_many(entries),
+        }
+    }
+
+    fn update<R>(
+        &self,
+        key: &str,
+        f: impl FnOnce(Option<&CachedResponse>) -> (Option<CachedResponse>, R),
+    ) -> R {
+        match self {
+            CacheInner::Sieve(c) => c.update(key, f),
+            CacheInner::Lru(c) => c.update(key, f),
+            CacheInner::Fifo(c) => c.update(key, f),
+        }
+    }
+
     fn remove(&self, key: &str) -> bool {
         match self {
             CacheInner::Sieve(c) => c.remove(key),
@@ -50,6 +115,22 @@ impl CacheInner {
         }
     }
 
+    fn take(&self, key: &str) -> Option<Arc<CachedResponse>> {
+        match self {
+            CacheInner::Sieve(c) => c.take(key),
+            CacheInner::Lru(c) => c.take(key),
+            CacheInner::Fifo(c) => c.take(key),
+        }
+    }
+
+    fn clear(&self) -> usize {
+        match self {
+            CacheInner::Sieve(c) => c.clear(),
+            CacheInner::Lru(c) => c.clear(),
+            CacheInner::Fifo(c) => c.clear(),
+        }
+    }
+
     fn stats(&self) -> CacheStats {
         match self {
             CacheInner::Sieve(c) => c.stats(),
@@ -58,6 +139,54 @@ impl CacheInner {
         }
     }
 
+    fn reset_stats(&self) {
+        match self {
+            CacheInner::Sieve(c) => c.reset_stats(),
+            CacheInner::Lru(c) => c.reset_stats(),
+            CacheInner::Fifo(c) => c.reset_stats(),
+        }
+    }
+
+    fn try_resize(&self, capacity: usize) -> Result<(), CacheError> {
+        match self {
+            CacheInner::Sieve(c) => c.try_resize(capacity),
+            CacheInner::Lru(c) => c.try_resize(capacity),
+            CacheInner::Fifo(c) => c.try_resize(capacity),
+        }
+    }
+
+    fn set_max_memory(&self, bytes: usize) {
+        match self {
+            CacheInner::Sieve(c) => c.set_max_memory(bytes),
+            CacheInner::Lru(c) => c.set_max_memory(bytes),
+            CacheInner::Fifo(c) => c.set_max_memory(bytes),
+        }
+    }
+
+    fn set_low_watermark(&self, share: f64) {
+        match self {
+            CacheInner::Sieve(c) => c.set_low_watermark(share),
+            CacheInner::Lru(c) => c.set_low_watermark(share),
+            CacheInner::Fifo(c) => c.set_low_watermark(share),
+        }
+    }
+
+    fn memory_bytes(&self) -> usize {
+        match self {
+            CacheInner::Sieve(c) => c.memory_bytes(),
+            CacheInner::Lru(c) => c.memory_bytes(),
+            CacheInner::Fifo(c) => c.memory_bytes(),
+        }
+    }
+
+    fn check_invariants(&self) -> Vec<(usize, String)> {
+        match self {
+            CacheInner::Sieve(c) => c.check_invariants(),
+            CacheInner::Lru(c) => c.check_invariants(),
+            CacheInner::Fifo(c) => c.check_invariants(),
+        }
+    }
+
     fn name(&self) -> &'static str {
         match self {
             CacheInner::Sieve(c) => c.name(),
@@ -65,8 +194,75 @@ impl CacheInner {
             CacheInner::Fifo(c) => c.name(),
         }
     }
+
+    fn retain(&self, keep: impl FnMut(&str, &CachedResponse) -> bool) -> usize {
+        match self {
+            CacheInner::Sieve(c) => c.retain(keep),
+            CacheInner::Lru(c) => c.retain(keep),
+            CacheInner::Fifo(c) => c.retain(keep),
+        }
+    }
+
+    fn with_listener(self, listener: Arc<dyn CacheListener>) -> Self {
+        match self {
+            CacheInner::Sieve(c) => CacheInner::Sieve(c.with_listener(listener)),
+            CacheInner::Lru(c) => CacheInner::Lru(c.with_listener(listener)),
+            CacheInner::Fifo(c) => CacheInner::Fifo(c.with_listener(listener)),
+        }
+    }
+
+    fn with_partitioning(self, partitioning: Partitioning) -> Self {
+        match self {
+            CacheInner::Sieve(c) => CacheInner::Sieve(c.with_partitioning(partitioning)),
+            CacheInner::Lru(c) => CacheInner::Lru(c.with_partitioning(partitioning)),
+            CacheInner::Fifo(c) => CacheInner::Fifo(c.with_partitioning(partitioning)),
+        }
+    }
+
+    fn with_quotas(self, quotas: Arc<HttpQuotas>) -> Self {
+        match self {
+            CacheInner::Sieve(c) => CacheInner::Sieve(c.with_quotas(quotas)),
+            CacheInner::Lru(c) => CacheInner::Lru(c.with_quotas(quotas)),
+            CacheInner::Fifo(c) => CacheInner::Fifo(c.with_quotas(quotas)),
+        }
+    }
+
+    fn quota_usage(&self) -> HashMap<String, QuotaUsage> {
+        match self {
+            CacheInner::Sieve(c) => c.quota_usage(),
+            CacheInner::Lru(c) => c.quota_usage(),
+            CacheInner::Fifo(c) => c.quota_usage(),
+        }
+    }
+
+    fn clear_partition(&self, partition: &str) -> Option<usize> {
+        match self {
+            CacheInner::Sieve(c) => c.clear_partition(partition),
+            CacheInner::Lru(c) => c.clear_partition(partition),
+            CacheInner::Fifo(c) => c.clear_partition(partition),
+        }
+    }
+
+    fn resize_partition(&self, partition: &str, capacity: usize) -> bool {
+        match self {
+            CacheInner::Sieve(c) => c.resize_partition(partition, capacity),
+            CacheInner::Lru(c) => c.resize_partition(partition, capacity),
+            CacheInner::Fifo(c) => c.resize_partition(partition, capacity),
+        }
+    }
+
+    fn partition_skew(&self, threshold: f64) -> Option<PartitionSkew> {
+        match self {
+            CacheInner::Sieve(c) => c.partition_skew(threshold),
+            CacheInner::Lru(c) => c.partition_skew(threshold),
+            CacheInner::Fifo(c) => c.partition_skew(threshold),
+        }
+    }
 }
 
+/// Eviction policy names accepted by `build_cache`.
+pub const POLICIES: &[&str] = &["sieve", "lru", "fifo"];
+
 fn build_cache(policy: &str, capacity: usize) -> CacheInner {
     match policy {
         "sieve" => CacheInner::Sieve(ShardedCache::new(capacity, SieveCache::new)),
@@ -76,45 +272,494 @@ fn build_cache(policy: &str, capacity: usize) -> CacheInner {
     }
 }
 
+/// Publishes the primary cache's evictions and expiries as `evicted` and
+/// `expired` keyspace events, and counts the expiries.
+struct RemovalEvents {
+    pubsub: Arc<PubSub>,
+    policy: &'static str,
+}
+
+impl CacheListener for RemovalEvents {
+    fn on_evict(&self, key: &str, _: &CachedResponse) {
+        self.pubsub.keyevent("evicted", key);
+    }
+
+    fn on_expire(&self, key: &str, _: &CachedResponse) {
+        ::metrics::counter!("colander_cache_expirations_total", "policy" => self.policy)
+            .increment(1);
+        self.pubsub.keyevent("expired", key);
+    }
+}
+
+/// Primary-cache inserts per `InsertSource`, indexed by discriminant.
+#[derive(Debug, Default)]
+struct InsertCounts([AtomicU64; InsertSource::ALL.len()]);
+
+impl InsertCounts {
+    fn record(&self, source: InsertSource) {
+        self.0[source as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn get(&self, source: InsertSource) -> u64 {
+        self.0[source as usize].load(Ordering::Relaxed)
+    }
+}
+
+/// Why `insert_raw` stored nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertError {
+    /// The value is larger than `max_body_size`.
+    TooLarge,
+    /// The cache is read-only.
+    ReadOnly,
+}
+
+/// Why `incr_by` left a counter unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IncrError {
+    /// The stored value isn't a base-10 `i64`.
+    NotAnInteger,
+    /// The result wouldn't fit in an `i64`.
+    Overflow,
+    /// The cache is read-only.
+    ReadOnly,
+}
+
 /// Dual-cache layer for the proxy.
 ///
 /// Primary cache serves responses. Comparison cache (optional) runs in shadow
 /// mode for metrics only. Toggle between demo and bench mode at runtime.
 pub struct CacheLayer {
     primary: CacheInner,
-    comparison: Option<CacheInner>,
+    /// Shadow caches, kept up to date in demo mode only to compare hit rates.
+    comparisons: Vec<CacheInner>,
     demo_mode: AtomicBool,
+    /// Serve existing entries but never insert, evict or expire (see `set_read_only`).
+    read_only: AtomicBool,
     default_ttl_secs: AtomicU64,
-    pub max_body_size: usize,
+    /// Requested total capacity (shards round it down to a multiple of 64).
+    capacity: AtomicUsize,
+    /// Largest response body worth caching (hot-reloadable).
+    max_body_size: AtomicUsize,
+    /// Bound on `memory_bytes`, 0 for none (see `set_max_memory`).
+    max_memory: AtomicUsize,
+    /// `[cache.low_watermark]`, by lowercase policy name (see
+    /// `set_low_watermarks`).
+    low_watermarks: Mutex<BTreeMap<String, f64>>,
+    // Shared with layers rebuilt by `with_policies`: what we've learned about
+    // the workload doesn't depend on the eviction policy
+    hot_keys: Option<Arc<HotKeyTracker>>,
+    adaptive_ttl: Option<Arc<AdaptiveTtl>>,
+    inserts: Arc<InsertCounts>,
+    /// Values refused for being larger than `max_body_size`.
+    oversize_rejections: Arc<AtomicU64>,
+    partitioning: Option<Partitioning>,
+    /// `[[cache.quotas]]`, applied to every cache (see `with_quotas`).
+    quotas: Option<Arc<HttpQuotas>>,
+    /// Where keyspace events go (see `with_keyspace_events`).
+    pubsub: Option<Arc<PubSub>>,
+    /// Where removals are queued for other instances (see `with_invalidation_bus`).
+    invalidations: Option<Arc<InvalidationBus>>,
+    /// Bumped by every `reset_stats`.
+    stats_generation: AtomicU64,
+    /// Misses being fetched, so concurrent ones for a key wait for one fetch.
+    fills: Fills,
 }
 
 impl CacheLayer {
     pub fn new(
         primary_policy: &str,
-        comparison_policy: Option<&str>,
+        comparison_policies: &[&str],
         capacity: usize,
         default_ttl: Duration,
         max_body_size: usize,
     ) -> Self {
         let primary = build_cache(primary_policy, capacity);
-        let comparison = comparison_policy.map(|p| build_cache(p, capacity));
+        let comparisons: Vec<CacheInner> = comparison_policies
+            .iter()
+            .map(|p| build_cache(p, capacity))
+            .collect();
 
         tracing::info!(
             primary = primary.name(),
-            comparison = comparison.as_ref().map(|c| c.name()),
+            comparisons = ?comparisons.iter().map(|c| c.name()).collect::<Vec<_>>(),
             capacity,
             "cache layer initialized"
         );
 
         Self {
             primary,
-            comparison,
+            comparisons,
             demo_mode: AtomicBool::new(true),
+            read_only: AtomicBool::new(false),
             default_ttl_secs: AtomicU64::new(default_ttl.as_secs()),
-            max_body_size,
+            capacity: AtomicUsize::new(capacity),
+            max_body_size: AtomicUsize::new(max_body_size),
+            max_memory: AtomicUsize::new(0),
+            low_watermarks: Mutex::default(),
+            hot_keys: None,
+            adaptive_ttl: None,
+            inserts: Default::default(),
+            oversize_rejections: Default::default(),
+            partitioning: None,
+            quotas: None,
+            pubsub: None,
+            invalidations: None,
+            stats_generation: AtomicU64::new(0),
+            fills: Fills::new(),
+        }
+    }
+
+    /// Build a cache layer from `[cache]` config. `capacity` is passed
+    /// separately because a rebuild keeps the live capacity, which may have
+    /// been changed at runtime since the config was written.
+    pub fn from_config(config: &CacheConfig, capacity: usize) -> Self {
+        let comparisons: Vec<&str> = config
+            .comparison_policy
+            .iter()
+            .map(String::as_str)
+            .collect();
+        let mut layer = Self::new(
+            &config.eviction_policy,
+            &comparisons,
+            capacity,
+            Duration::from_secs(config.default_ttl_seconds),
+            config.max_body_size_bytes,
+        );
+        if config.max_memory_bytes > 0 {
+            layer.set_max_memory(config.max_memory_bytes);
+        }
+        if !config.low_watermark.is_empty() {
+            layer.set_low_watermarks(&config.low_watermark);
+        }
+        if config.hot_keys_top_k > 0 {
+            layer = layer.with_hot_keys(config.hot_keys_top_k);
+        }
+        if config.adaptive_ttl.enabled {
+            layer = layer.with_adaptive_ttl(AdaptiveTtl::new(
+                capacity,
+                Duration::from_secs(config.adaptive_ttl.min_ttl_seconds),
+                Duration::from_secs(config.adaptive_ttl.max_ttl_seconds),
+            ));
+        }
+        let partitioning = &config.partitioning;
+        if partitioning.enabled {
+            layer = layer.with_partitioning(Partitioning::new(
+                partitioning.separator,
+                partitioning.segment,
+                partitioning.shards_per_partition,
+            ));
+        }
+        if !config.quotas.is_empty() {
+            layer = layer.with_quotas(Arc::new(HttpQuotas::new(config.quotas.clone())));
+        }
+        layer
+    }
+
+    /// Track the `k` most requested keys (see `hot_keys`).
+    pub fn with_hot_keys(mut self, k: usize) -> Self {
+        self.hot_keys = Some(Arc::new(HotKeyTracker::new(k)));
+        self
+    }
+
+    /// Learn per-key TTLs from observed change rates (see `learn_ttl`).
+    pub fn with_adaptive_ttl(mut self, adaptive: AdaptiveTtl) -> Self {
+        self.adaptive_ttl = Some(Arc::new(adaptive));
+        self
+    }
+
+    /// Publish `set`, `del`, `evicted` and `expired` keyspace events to
+    /// `pubsub` as primary-cache entries are inserted, removed, evicted, or
+    /// dropped past their TTL. Costs nothing while no one is subscribed.
+    pub fn with_keyspace_events(mut self, pubsub: Arc<PubSub>) -> Self {
+        let policy = self.primary.name();
+        self.primary = self.primary.with_listener(Arc::new(RemovalEvents {
+            pubsub: Arc::clone(&pubsub),
+            policy,
+        }));
+        self.pubsub = Some(pubsub);
+        self
+    }
+
+    pub fn keyspace_events(&self) -> Option<&Arc<PubSub>> {
+        self.pubsub.as_ref()
+    }
+
+    /// Pass every `remove` on to the other instances of a cluster (see
+    /// `invalidation::run`).
+    pub fn with_invalidation_bus(mut self, bus: Arc<InvalidationBus>) -> Self {
+        self.invalidations = Some(bus);
+        self
+    }
+
+    pub fn invalidation_bus(&self) -> Option<&Arc<InvalidationBus>> {
+        self.invalidations.as_ref()
+    }
+
+    fn keyevent(&self, event: &str, key: &str) {
+        if let Some(pubsub) = &self.pubsub {
+            pubsub.keyevent(event, key);
+        }
+    }
+
+    /// Map keys to shards by partition (see `clear_partition`). Only call
+    /// on a new, empty layer: entries already stored would be unreachable.
+    pub fn with_partitioning(mut self, partitioning: Partitioning) -> Self {
+        self.primary = self.primary.with_partitioning(partitioning.clone());
+        self.comparisons = self
+            .comparisons
+            .into_iter()
+            .map(|c| c.with_partitioning(partitioning.clone()))
+            .collect();
+        self.partitioning = Some(partitioning);
+        self
+    }
+
+    /// Give each cache the low watermark configured for its policy (see
+    /// `ShardedCache::set_low_watermark`); policies not in `by_policy`
+    /// evict one entry at a time.
+    pub fn set_low_watermarks(&self, by_policy: &BTreeMap<String, f64>) {
+        for cache in std::iter::once(&self.primary).chain(&self.comparisons) {
+            let policy = cache.name().to_lowercase();
+            cache.set_low_watermark(by_policy.get(&policy).copied().unwrap_or(1.0));
+        }
+        *self.low_watermarks.lock() = by_policy.clone();
+    }
+
+    /// Cap the share of every cache a route or tenant may fill (see
+    /// `ShardedCache::with_quotas`). The comparison caches get the same
+    /// quotas, so hit rates stay comparable.
+    pub fn with_quotas(mut self, quotas: Arc<HttpQuotas>) -> Self {
+        self.primary = self.primary.with_quotas(Arc::clone(&quotas));
+        self.comparisons = self
+            .comparisons
+            .into_iter()
+            .map(|c| c.with_quotas(Arc::clone(&quotas)))
+            .collect();
+        self.quotas = Some(quotas);
+        self
+    }
+
+    /// Entries and quota evictions of each of the primary cache's quota
+    /// groups; empty without quotas.
+    pub fn quota_report(&self) -> BTreeMap<String, QuotaReport> {
+        match &self.quotas {
+            Some(quotas) => quotas.report(self.primary.quota_usage()),
+            None => BTreeMap::new(),
         }
     }
 
+    /// Publish the primary cache's quota usage to Prometheus.
+    pub fn export_quotas(&self) {
+        if let Some(quotas) = &self.quotas {
+            quotas.export(&self.primary.quota_usage());
+        }
+    }
+
+    /// Build a replacement layer with different eviction policies, keeping
+    /// capacity, memory bound, TTL, mode, read-only flag, hot-key counts,
+    /// insert counts, oversize rejections, learned TTLs, partitioning,
+    /// quotas, keyspace event publishing and the invalidation bus.
+    ///
+    /// With `warm_keys > 0`, the hottest tracked keys still cached here are
+    /// copied into the new caches (original insertion time and TTL preserved)
+    /// so the swap doesn't start completely cold.
+    pub fn with_policies(&self, primary: &str, comparisons: &[&str], warm_keys: usize) -> Self {
+        let layer = self.carry_over(
+            primary,
+            comparisons,
+            self.partitioning.clone(),
+            self.quotas.clone(),
+        );
+
+        if warm_keys > 0 {
+            let mut warmed = 0usize;
+            for (key, _) in self.hot_keys(warm_keys) {
+                let Some(entry) = self.primary.peek(&key) else {
+                    continue;
+                };
+                if entry.is_expired() {
+                    continue;
+                }
+                for comparison in &layer.comparisons {
+                    comparison.insert(key.clone(), (*entry).clone());
+                }
+                layer.primary.insert(key, (*entry).clone());
+                warmed += 1;
+            }
+            tracing::info!(warmed, "warm handoff to new cache layer");
+        }
+
+        layer
+    }
+
+    /// Build the replacement for a hot reload that changed the eviction
+    /// policies from `old` to `new`: `with_policies` without a warm handoff,
+    /// except that hot-key tracking, adaptive TTL, partitioning and quotas
+    /// edited in the config are rebuilt from `new`. Unedited ones carry over
+    /// with everything they've learned.
+    pub fn rebuild(&self, old: &CacheConfig, new: &CacheConfig) -> Self {
+        let comparisons: Vec<&str> = new.comparison_policy.iter().map(String::as_str).collect();
+        let partitioning = if old.partitioning.same_layout(&new.partitioning) {
+            self.partitioning.clone()
+        } else {
+            let p = &new.partitioning;
+            p.enabled
+                .then(|| Partitioning::new(p.separator, p.segment, p.shards_per_partition))
+        };
+        let quotas = if old.quotas == new.quotas {
+            self.quotas.clone()
+        } else {
+            (!new.quotas.is_empty()).then(|| Arc::new(HttpQuotas::new(new.quotas.clone())))
+        };
+        let mut layer = self.carry_over(&new.eviction_policy, &comparisons, partitioning, quotas);
+        if old.hot_keys_top_k != new.hot_keys_top_k {
+            layer.hot_keys =
+                (new.hot_keys_top_k > 0).then(|| Arc::new(HotKeyTracker::new(new.hot_keys_top_k)));
+        }
+        if old.adaptive_ttl != new.adaptive_ttl {
+            let adaptive = &new.adaptive_ttl;
+            layer.adaptive_ttl = adaptive.enabled.then(|| {
+                Arc::new(AdaptiveTtl::new(
+                    layer.capacity(),
+                    Duration::from_secs(adaptive.min_ttl_seconds),
+                    Duration::from_secs(adaptive.max_ttl_seconds),
+                ))
+            });
+        }
+        layer
+    }
+
+    /// An empty layer with new policies and everything else of this one
+    /// (see `with_policies`), partitioned and quota'd as given.
+    fn carry_over(
+        &self,
+        primary: &str,
+        comparisons: &[&str],
+        partitioning: Option<Partitioning>,
+        quotas: Option<Arc<HttpQuotas>>,
+    ) -> Self {
+        let mut layer = Self::new(
+            primary,
+            comparisons,
+            self.capacity(),
+            self.default_ttl(),
+            self.max_body_size(),
+        );
+        layer.set_max_memory(self.max_memory());
+        layer.set_low_watermarks(&self.low_watermarks.lock());
+        layer.demo_mode = AtomicBool::new(self.is_demo_mode());
+        layer.read_only = AtomicBool::new(self.is_read_only());
+        layer.hot_keys = self.hot_keys.clone();
+        layer.adaptive_ttl = self.adaptive_ttl.clone();
+        layer.inserts = self.inserts.clone();
+        layer.oversize_rejections = self.oversize_rejections.clone();
+        if let Some(pubsub) = &self.pubsub {
+            layer = layer.with_keyspace_events(Arc::clone(pubsub));
+        }
+        layer.invalidations = self.invalidations.clone();
+        if let Some(partitioning) = partitioning {
+            layer = layer.with_partitioning(partitioning);
+        }
+        if let Some(quotas) = quotas {
+            layer = layer.with_quotas(quotas);
+        }
+        layer
+    }
+
+    /// Requested total capacity.
+    pub fn capacity(&self) -> usize {
+        self.capacity.load(Ordering::Relaxed)
+    }
+
+    /// Resize the primary and comparison caches in place, keeping their contents.
+    ///
+    /// Shrinking evicts with each policy's own algorithm, one shard at a time,
+    /// so it's proportional to the number of surplus entries — run it off the
+    /// async executor when the shrink is large. A zero or oversized capacity
+    /// is refused and nothing changes.
+    pub fn resize(&self, capacity: usize) -> Result<(), CacheError> {
+        self.primary.try_resize(capacity)?;
+        for comparison in &self.comparisons {
+            comparison.try_resize(capacity)?;
+        }
+        let old = self.capacity.swap(capacity, Ordering::Relaxed);
+        tracing::info!(old, new = capacity, "cache resized");
+        Ok(())
+    }
+
+    /// Bound on `memory_bytes`, 0 for none.
+    pub fn max_memory(&self) -> usize {
+        self.max_memory.load(Ordering::Relaxed)
+    }
+
+    /// Bound the memory held by every cache together to `bytes` (0 lifts
+    /// the bound). The primary and each comparison cache get an equal share,
+    /// so shadow caches don't crowd out the primary and hit rates stay
+    /// comparable. Caches over their share evict right away.
+    pub fn set_max_memory(&self, bytes: usize) {
+        let old = self.max_memory.swap(bytes, Ordering::Relaxed);
+        let share = bytes / (1 + self.comparisons.len());
+        self.primary.set_max_memory(share);
+        for comparison in &self.comparisons {
+            comparison.set_max_memory(share);
+        }
+        if old != bytes {
+            tracing::info!(old, new = bytes, "cache memory bound changed");
+        }
+    }
+
+    /// Approximate heap footprint of every cache's entries, in bytes.
+    pub fn memory_bytes(&self) -> usize {
+        std::iter::once(&self.primary)
+            .chain(&self.comparisons)
+            .map(CacheInner::memory_bytes)
+            .sum()
+    }
+
+    /// Drop every entry from every cache, one shard at a time. Returns the
+    /// number removed from the primary. Stats counters, hot keys and learned
+    /// TTLs are kept.
+    pub fn clear(&self) -> usize {
+        let removed = self.primary.clear();
+        for comparison in &self.comparisons {
+            comparison.clear();
+        }
+        tracing::info!(removed, "cache cleared");
+        removed
+    }
+
+    /// Drop every entry in `partition` from every cache, locking only that
+    /// partition's shards. Returns the number removed from the primary, or
+    /// `None` if partitioning is off.
+    pub fn clear_partition(&self, partition: &str) -> Option<usize> {
+        let removed = self.primary.clear_partition(partition)?;
+        for comparison in &self.comparisons {
+            comparison.clear_partition(partition);
+        }
+        tracing::info!(partition, removed, "cache partition cleared");
+        Some(removed)
+    }
+
+    /// Give `partition`'s shards `capacity` entries between them in every
+    /// cache. Returns `false` if partitioning is off.
+    pub fn resize_partition(&self, partition: &str, capacity: usize) -> bool {
+        if !self.primary.resize_partition(partition, capacity) {
+            return false;
+        }
+        for comparison in &self.comparisons {
+            comparison.resize_partition(partition, capacity);
+        }
+        tracing::info!(partition, capacity, "cache partition resized");
+        true
+    }
+
+    /// The primary cache's dominant partition, if one takes more than
+    /// `threshold` of lookups (see `ShardedCache::partition_skew`).
+    pub fn partition_skew(&self, threshold: f64) -> Option<PartitionSkew> {
+        self.primary.partition_skew(threshold)
+    }
+
     /// Current default TTL (read atomically for hot-reload support).
     pub fn default_ttl(&self) -> Duration {
         Duration::from_secs(self.default_ttl_secs.load(Ordering::Relaxed))
@@ -125,58 +770,522 @@ impl CacheLayer {
         self.default_ttl_secs.store(secs, Ordering::Relaxed);
     }
 
+    /// Largest body `insert` callers should cache, in bytes.
+    pub fn max_body_size(&self) -> usize {
+        self.max_body_size.load(Ordering::Relaxed)
+    }
+
+    /// Update the body size limit atomically; entries already cached stay.
+    pub fn set_max_body_size(&self, bytes: usize) {
+        self.max_body_size.store(bytes, Ordering::Relaxed);
+    }
+
+    /// Whether a value of `len` bytes is within `max_body_size`, counting it
+    /// as an oversize rejection if not.
+    pub fn admit_size(&self, key: &str, len: usize) -> bool {
+        let max = self.max_body_size();
+        if len <= max {
+            return true;
+        }
+        self.oversize_rejections.fetch_add(1, Ordering::Relaxed);
+        ::metrics::counter!("colander_cache_oversize_rejections_total").increment(1);
+        tracing::debug!(key, len, max, "value larger than max body size, not cached");
+        false
+    }
+
+    /// Values refused since startup for being larger than `max_body_size`,
+    /// by any interface.
+    pub fn oversize_rejections(&self) -> u64 {
+        self.oversize_rejections.load(Ordering::Relaxed)
+    }
+
     /// Look up a key in the primary cache. In demo mode, also checks the
-    /// comparison cache (for metrics only — result is discarded).
+    /// comparison caches (for metrics only — results are discarded).
+    ///
+    /// In read-only mode this only peeks: no hit/miss counting, no promotion,
+    /// and expired entries are reported missing but left in place.
     pub fn get(&self, key: &str) -> CacheLookup {
+        if let Some(tracker) = &self.hot_keys {
+            tracker.record(key);
+        }
+        if self.is_read_only() {
+            return CacheLookup {
+                value: self.primary.peek(key).filter(|e| !e.is_expired()),
+                comparison_hits: 0,
+            };
+        }
         let primary_result = self.primary.get(key);
 
-        let comparison_hit = if self.is_demo_mode() {
-            if let Some(comp) = &self.comparison {
-                comp.get(key).is_some()
-            } else {
-                false
-            }
+        let comparison_hits = if self.is_demo_mode() {
+            self.comparisons
+                .iter()
+                .filter(|comp| comp.get(key).is_some())
+                .count()
         } else {
-            false
+            0
         };
 
         CacheLookup {
             value: primary_result,
-            comparison_hit,
+            comparison_hits,
         }
     }
 
-    /// Insert into primary cache. In demo mode, also inserts into comparison.
+    /// Insert into primary cache. In demo mode, also inserts into the
+    /// comparison caches.
+    /// No-op in read-only mode, and for bodies larger than `max_body_size`
+    /// (counted in `oversize_rejections`).
     pub fn insert(&self, key: String, value: CachedResponse) {
+        self.store(key, value);
+    }
+
+    /// `insert`, returning the entry as stored (or, if it wasn't, as it
+    /// would have been).
+    fn store(&self, key: String, value: CachedResponse) -> Arc<CachedResponse> {
+        if self.is_read_only() || !self.admit_size(&key, value.body.len()) {
+            return Arc::new(value);
+        }
         if self.is_demo_mode() {
-            if let Some(comp) = &self.comparison {
+            for comp in &self.comparisons {
                 comp.insert(key.clone(), value.clone());
             }
         }
-        self.primary.insert(key, value);
+        self.inserts.record(value.source);
+        self.keyevent("set", &key);
+        self.primary.insert_and_get(key, value)
+    }
+
+    /// Claim a key that just missed for fetching, or get a handle on the
+    /// fetch another request already claimed it for (see
+    /// `colander_cache::fill::Fills`). The leader stores its value with
+    /// `insert_fill`.
+    pub fn claim_fill(&self, key: &str) -> Claim<'_> {
+        self.fills.claim(key)
+    }
+
+    /// `insert` the value fetched for a claimed key and hand it to the
+    /// requests waiting on the claim (even when it isn't stored).
+    pub fn insert_fill(&self, fill: FillGuard<'_>, value: CachedResponse) -> Arc<CachedResponse> {
+        let stored = self.store(fill.key().to_string(), value);
+        fill.complete(Arc::clone(&stored));
+        stored
+    }
+
+    /// The entry for `key`, fetched with `fetch` and inserted on a miss.
+    /// Concurrent misses for the key wait for one fetch instead of each
+    /// making their own; if it fails, the error goes to its caller and the
+    /// others fetch for themselves.
+    pub async fn try_get_or_insert_with<F, Fut, E>(
+        &self,
+        key: &str,
+        fetch: F,
+    ) -> Result<Arc<CachedResponse>, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<CachedResponse, E>>,
+    {
+        if let Some(value) = self.get(key).value {
+            return Ok(value);
+        }
+        let fill = match self.claim_fill(key) {
+            Claim::Leader(fill) => Some(fill),
+            Claim::Follower(waiter) => match waiter.await {
+                Some(value) => return Ok(value),
+                None => None,
+            },
+        };
+        let value = fetch().await?;
+        Ok(match fill {
+            Some(fill) => self.insert_fill(fill, value),
+            None => self.store(key.to_string(), value),
+        })
+    }
+
+    /// `try_get_or_insert_with` for fetches that can't fail.
+    pub async fn get_or_insert_with<F, Fut>(&self, key: &str, fetch: F) -> Arc<CachedResponse>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = CachedResponse>,
+    {
+        let fetch = || async { Ok::<_, std::convert::Infallible>(fetch().await) };
+        match self.try_get_or_insert_with(key, fetch).await {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
     }
 
     /// Remove a key from the primary cache. Returns true if the key existed.
+    /// Always false in read-only mode, where nothing is removed.
+    ///
+    /// With an invalidation bus, the key is also queued for the other
+    /// instances, whether or not this one had it.
     pub fn remove(&self, key: &str) -> bool {
-        self.primary.remove(key)
+        if self.is_read_only() {
+            return false;
+        }
+        if let Some(bus) = &self.invalidations {
+            bus.publish(key);
+        }
+        self.remove_local(key)
     }
 
-    /// Insert raw bytes (for RESP SET — bypasses HTTP response wrapping).
-    /// Only inserts into primary (RESP ops don't participate in demo comparison).
-    pub fn insert_raw(&self, key: String, value: Bytes, ttl: Option<Duration>) {
-        let response = CachedResponse {
+    /// `remove` without telling other instances, for keys they told us about.
+    pub fn remove_local(&self, key: &str) -> bool {
+        let removed = !self.is_read_only() && self.primary.remove(key);
+        if removed {
+            self.keyevent("del", key);
+        }
+        removed
+    }
+
+    /// Remove `key` and return its entry if it hadn't expired, reading and
+    /// removing under the key's shard lock so two callers can't both get it
+    /// (RESP `GETDEL`). Like `remove`, nothing happens in read-only mode and
+    /// the key is queued for other instances.
+    pub fn take(&self, key: &str) -> Option<Arc<CachedResponse>> {
+        if self.is_read_only() {
+            return None;
+        }
+        if let Some(bus) = &self.invalidations {
+            bus.publish(key);
+        }
+        let entry = self.primary.take(key)?;
+        self.keyevent("del", key);
+        Some(entry).filter(|e| !e.is_expired())
+    }
+
+    /// `remove` every key starting with `prefix`, from the comparison
+    /// caches too, returning how many the primary cache had. Walks the
+    /// whole keyspace one shard lock at a time, so keep it off the request
+    /// path.
+    ///
+    /// With an invalidation bus, the prefix itself is queued for the other
+    /// instances, which drop whatever keys they have under it.
+    pub fn remove_prefix(&self, prefix: &str) -> usize {
+        if self.is_read_only() {
+            return 0;
+        }
+        if let Some(bus) = &self.invalidations {
+            bus.publish_prefix(prefix);
+        }
+        self.remove_prefix_local(prefix)
+    }
+
+    /// `remove_prefix` without telling other instances, for prefixes they
+    /// told us about.
+    pub fn remove_prefix_local(&self, prefix: &str) -> usize {
+        if self.is_read_only() {
+            return 0;
+        }
+        let mut removed = Vec::new();
+        self.primary.retain(|key, _| {
+            let matched = key.starts_with(prefix);
+            if matched {
+                removed.push(key.to_string());
+            }
+            !matched
+        });
+        for comp in &self.comparisons {
+            comp.retain(|key, _| !key.starts_with(prefix));
+        }
+        for key in &removed {
+            self.keyevent("del", key);
+        }
+        removed.len()
+    }
+
+    /// `get` for several keys at once, taking each shard lock once per
+    /// cache rather than once per key. Results are in `keys` order.
+    pub fn get_many(&self, keys: &[String]) -> Vec<Option<Arc<CachedResponse>>> {
+        if let Some(tracker) = &self.hot_keys {
+            for key in keys {
+                tracker.record(key);
+            }
+        }
+        if self.is_read_only() {
+            return keys
+                .iter()
+                .map(|key| self.primary.peek(key).filter(|e| !e.is_expired()))
+                .collect();
+        }
+        let found = self.primary.get_many(keys);
+        if self.is_demo_mode() {
+            for comp in &self.comparisons {
+                comp.get_many(keys);
+            }
+        }
+        found
+    }
+
+    /// `insert_raw` for several entries at once, taking each shard lock
+    /// once. All or nothing: if any value is too large, none are stored.
+    pub fn insert_raw_many(
+        &self,
+        entries: Vec<(String, Bytes)>,
+        ttl: Option<Duration>,
+        source: InsertSource,
+    ) -> Result<(), InsertError> {
+        if self.is_read_only() {
+            return Err(InsertError::ReadOnly);
+        }
+        let oversize = entries
+            .iter()
+            .filter(|(key, body)| !self.admit_size(key, body.len()))
+            .count();
+        if oversize > 0 {
+            return Err(InsertError::TooLarge);
+        }
+        let ttl = ttl.unwrap_or(self.default_ttl());
+        let inserted_at = Instant::now();
+        let entries = entries
+            .into_iter()
+            .map(|(key, body)| {
+                self.inserts.record(source);
+                self.keyevent("set", &key);
+                let response = CachedResponse {
+                    status: 0,
+                    headers: HeaderMap::new(),
+                    body,
+                    inserted_at,
+                    ttl,
+                    hits: Default::default(),
+                    source,
+                    must_revalidate: false,
+                };
+                (key, response)
+            })
+            .collect();
+        self.primary.insert_many(entries);
+        Ok(())
+    }
+
+    /// Insert raw bytes (for RESP/memcached SET — bypasses HTTP response
+    /// wrapping). Only inserts into primary (these ops don't participate in
+    /// demo comparison). Values larger than `max_body_size` are refused and
+    /// counted in `oversize_rejections`, as in `insert`.
+    pub fn insert_raw(
+        &self,
+        key: String,
+        value: Bytes,
+        ttl: Option<Duration>,
+        source: InsertSource,
+    ) -> Result<(), InsertError> {
+        if self.is_read_only() {
+            return Err(InsertError::ReadOnly);
+        }
+        if !self.admit_size(&key, value.len()) {
+            return Err(InsertError::TooLarge);
+        }
+        let response = self.raw_response(value, ttl, source);
+        self.inserts.record(source);
+        self.keyevent("set", &key);
+        self.primary.insert(key, response);
+        Ok(())
+    }
+
+    /// `insert_raw` with the default TTL, but only if `key` has no unexpired
+    /// entry, checking and inserting under the key's shard lock so only one
+    /// of several concurrent callers stores (RESP `SETNX`). Returns whether
+    /// this one did.
+    pub fn insert_raw_if_absent(
+        &self,
+        key: &str,
+        value: Bytes,
+        source: InsertSource,
+    ) -> Result<bool, InsertError> {
+        if self.is_read_only() {
+            return Err(InsertError::ReadOnly);
+        }
+        if !self.admit_size(key, value.len()) {
+            return Err(InsertError::TooLarge);
+        }
+        let stored = self.primary.update(key, |current| {
+            if current.is_some_and(|e| !e.is_expired()) {
+                return (None, false);
+            }
+            (Some(self.raw_response(value, None, source)), true)
+        });
+        if stored {
+            self.inserts.record(source);
+            self.keyevent("set", key);
+        }
+        Ok(stored)
+    }
+
+    /// `insert_raw` with the default TTL, returning the body it replaced if
+    /// that hadn't expired. Both happen under the key's shard lock, so the
+    /// old value returned is the one this write overwrote (RESP `GETSET`).
+    pub fn replace_raw(
+        &self,
+        key: &str,
+        value: Bytes,
+        source: InsertSource,
+    ) -> Result<Option<Bytes>, InsertError> {
+        if self.is_read_only() {
+            return Err(InsertError::ReadOnly);
+        }
+        if !self.admit_size(key, value.len()) {
+            return Err(InsertError::TooLarge);
+        }
+        let old = self.primary.update(key, |current| {
+            let old = current.filter(|e| !e.is_expired()).map(|e| {
+                e.record_hit();
+                e.body.clone()
+            });
+            (Some(self.raw_response(value, None, source)), old)
+        });
+        self.inserts.record(source);
+        self.keyevent("set", key);
+        Ok(old)
+    }
+
+    /// Append `suffix` to the value at `key` and return the new length,
+    /// reading and writing under the key's shard lock so concurrent appends
+    /// are never lost (RESP `APPEND`). An existing value keeps its remaining
+    /// TTL; a missing or expired one starts from empty with the default TTL.
+    pub fn append_raw(
+        &self,
+        key: &str,
+        suffix: &[u8],
+        source: InsertSource,
+    ) -> Result<usize, InsertError> {
+        if self.is_read_only() {
+            return Err(InsertError::ReadOnly);
+        }
+        let result = self.primary.update(key, |current| {
+            let current = current.filter(|e| !e.is_expired());
+            let len = current.map_or(0, |e| e.body.len()) + suffix.len();
+            if !self.admit_size(key, len) {
+                return (None, Err(InsertError::TooLarge));
+            }
+            let mut value = Vec::with_capacity(len);
+            value.extend_from_slice(current.map_or(&[][..], |e| &e.body));
+            value.extend_from_slice(suffix);
+            let body = Bytes::from(value);
+            let entry = match current {
+                Some(entry) => CachedResponse {
+                    body,
+                    hits: entry.hits.clone(),
+                    headers: entry.headers.clone(),
+                    source,
+                    ..*entry
+                },
+                None => self.raw_response(body, None, source),
+            };
+            (Some(entry), Ok(len))
+        });
+        if result.is_ok() {
+            self.inserts.record(source);
+            self.keyevent("set", key);
+        }
+        result
+    }
+
+    /// A RESP/memcached value as an entry, with the default TTL unless
+    /// `ttl` is given.
+    fn raw_response(
+        &self,
+        body: Bytes,
+        ttl: Option<Duration>,
+        source: InsertSource,
+    ) -> CachedResponse {
+        CachedResponse {
             status: 0,
-            headers: vec![],
-            body: value,
+            headers: HeaderMap::new(),
+            body,
             inserted_at: Instant::now(),
             ttl: ttl.unwrap_or(self.default_ttl()),
-        };
-        self.primary.insert(key, response);
+            hits: Default::default(),
+            source,
+            must_revalidate: false,
+        }
+    }
+
+    /// Add `delta` to the integer stored at `key` and return the result,
+    /// reading and writing under the key's shard lock so concurrent
+    /// increments are never lost. A missing or expired key counts as 0 and
+    /// gets the default TTL; an existing one keeps its remaining TTL.
+    pub fn incr_by(&self, key: &str, delta: i64, source: InsertSource) -> Result<i64, IncrError> {
+        if self.is_read_only() {
+            return Err(IncrError::ReadOnly);
+        }
+        let result = self.primary.update(key, |current| {
+            let current = current.filter(|e| !e.is_expired());
+            let value = match current {
+                Some(entry) => match std::str::from_utf8(&entry.body).map(str::parse::<i64>) {
+                    Ok(Ok(n)) => n,
+                    _ => return (None, Err(IncrError::NotAnInteger)),
+                },
+                None => 0,
+            };
+            let Some(next) = value.checked_add(delta) else {
+                return (None, Err(IncrError::Overflow));
+            };
+            let body = Bytes::from(next.to_string());
+            let entry = match current {
+                Some(entry) => CachedResponse {
+                    body,
+                    hits: entry.hits.clone(),
+                    headers: entry.headers.clone(),
+                    source,
+                    ..*entry
+                },
+                None => CachedResponse {
+                    status: 0,
+                    headers: HeaderMap::new(),
+                    body,
+                    inserted_at: Instant::now(),
+                    ttl: self.default_ttl(),
+                    hits: Default::default(),
+                    source,
+                    must_revalidate: false,
+                },
+            };
+            (Some(entry), Ok(next))
+        });
+        if result.is_ok() {
+            self.inserts.record(source);
+            self.keyevent("incrby", key);
+        }
+        result
+    }
+
+    /// Inspect a primary-cache entry without counting a hit/miss or affecting
+    /// eviction order.
+    pub fn peek(&self, key: &str) -> Option<Arc<CachedResponse>> {
+        self.primary.peek(key)
+    }
+
+    /// One page of primary-cache entries whose keys start with `prefix`,
+    /// resuming after the previous page's `next` key. See `ShardedCache::scan`.
+    pub fn scan(&self, prefix: &str, after: Option<&str>, limit: usize) -> ScanPage {
+        self.primary.scan(after, limit, prefix)
+    }
+
+    /// Feed an upstream fill into adaptive TTL learning and return the TTL to
+    /// use for it. `None` when adaptive TTL is disabled.
+    pub fn learn_ttl(&self, key: &str, fingerprint: u64) -> Option<Duration> {
+        self.adaptive_ttl
+            .as_ref()
+            .map(|a| a.observe(key, fingerprint, self.default_ttl()))
+    }
+
+    pub fn is_adaptive_ttl(&self) -> bool {
+        self.adaptive_ttl.is_some()
+    }
+
+    /// What adaptive TTL has learned about a key, if anything.
+    pub fn learned_ttl(&self, key: &str) -> Option<TtlHistory> {
+        self.adaptive_ttl.as_ref()?.history(key)
     }
 
     /// Get TTL remaining for a key. Returns None if key missing/expired.
     pub fn ttl_remaining(&self, key: &str) -> Option<Duration> {
-        let entry = self.primary.get(key)?;
+        let entry = if self.is_read_only() {
+            self.primary.peek(key)?
+        } else {
+            self.primary.get(key)?
+        };
         entry.ttl.checked_sub(entry.inserted_at.elapsed())
     }
 
@@ -184,7 +1293,7 @@ impl CacheLayer {
     pub fn build_response(
         &self,
         status: u16,
-        headers: Vec<(String, String)>,
+        headers: HeaderMap,
         body: Bytes,
         ttl: Option<Duration>,
     ) -> CachedResponse {
@@ -194,23 +1303,78 @@ impl CacheLayer {
             body,
             inserted_at: Instant::now(),
             ttl: ttl.unwrap_or(self.default_ttl()),
+            hits: Default::default(),
+            source: Default::default(),
+            must_revalidate: false,
         }
     }
 
+    /// Primary-cache inserts since startup by what made them, e.g.
+    /// `("http", 120)`, in `InsertSource::ALL` order.
+    pub fn inserts_by_source(&self) -> Vec<(&'static str, u64)> {
+        InsertSource::ALL
+            .iter()
+            .map(|&source| (source.as_str(), self.inserts.get(source)))
+            .collect()
+    }
+
+    /// The `n` most frequently requested keys (hits and misses alike), hottest
+    /// first. Empty when hot-key tracking is disabled.
+    pub fn hot_keys(&self, n: usize) -> Vec<(String, u64)> {
+        self.hot_keys
+            .as_ref()
+            .map(|t| t.top(n.min(t.k())))
+            .unwrap_or_default()
+    }
+
+    /// Structural violations in any cache, as `"<policy> shard <n>: <what>"`.
+    /// Walks every entry under per-shard read locks (see `soak`).
+    pub fn check_invariants(&self) -> Vec<String> {
+        std::iter::once(&self.primary)
+            .chain(&self.comparisons)
+            .flat_map(|cache| {
+                cache
+                    .check_invariants()
+                    .into_iter()
+                    .map(|(shard, e)| format!("{} shard {shard}: {e}", cache.name()))
+            })
+            .collect()
+    }
+
+    /// Zero the hit/miss/eviction counters of every cache, keeping their
+    /// entries, so benchmark runs start from a clean slate. Insert counts by
+    /// source and the Prometheus counters keep counting.
+    pub fn reset_stats(&self) {
+        for cache in std::iter::once(&self.primary).chain(&self.comparisons) {
+            cache.reset_stats();
+        }
+        self.stats_generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// How many times `reset_stats` has been called, so observers can tell
+    /// a reset from counters that merely went backwards.
+    pub fn stats_generation(&self) -> u64 {
+        self.stats_generation.load(Ordering::Relaxed)
+    }
+
     pub fn primary_stats(&self) -> CacheStats {
         self.primary.stats()
     }
 
-    pub fn comparison_stats(&self) -> Option<CacheStats> {
-        self.comparison.as_ref().map(|c| c.stats())
+    /// Each comparison cache's policy name and stats, in configured order.
+    pub fn comparison_stats(&self) -> Vec<(&'static str, CacheStats)> {
+        self.comparisons
+            .iter()
+            .map(|c| (c.name(), c.stats()))
+            .collect()
     }
 
     pub fn primary_name(&self) -> &'static str {
         self.primary.name()
     }
 
-    pub fn comparison_name(&self) -> Option<&'static str> {
-        self.comparison.as_ref().map(|c| c.name())
+    pub fn comparison_names(&self) -> Vec<&'static str> {
+        self.comparisons.iter().map(|c| c.name()).collect()
     }
 
     pub fn is_demo_mode(&self) -> bool {
@@ -223,6 +1387,20 @@ impl CacheLayer {
         tracing::info!(?mode, "cache mode changed");
     }
 
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed)
+    }
+
+    /// Freeze the cache contents: lookups keep serving live entries, while
+    /// inserts, removals, expiry cleanup and eviction all stop. Meant for
+    /// incident mitigation or taking a consistent snapshot.
+    pub fn set_read_only(&self, enabled: bool) {
+        let was = self.read_only.swap(enabled, Ordering::Relaxed);
+        if was != enabled {
+            tracing::info!(read_only = enabled, "cache read-only mode changed");
+        }
+    }
+
     pub fn mode(&self) -> CacheMode {
         if self.is_demo_mode() {
             CacheMode::Demo
@@ -235,7 +1413,8 @@ impl CacheLayer {
 /// Result of a cache lookup, including comparison cache info.
 pub struct CacheLookup {
     pub value: Option<Arc<CachedResponse>>,
-    pub comparison_hit: bool,
+    /// How many comparison caches also had the key (demo mode only).
+    pub comparison_hits: usize,
 }
 
 impl CacheLookup {
@@ -249,12 +1428,18 @@ pub fn parse_cache_control(value: &str) -> CacheControl {
     let mut result = CacheControl {
         cacheable: true,
         max_age: None,
+        must_revalidate: false,
     };
+    let mut s_maxage = None;
 
     for directive in value.split(',').map(|s| s.trim().to_lowercase()) {
         if directive == "no-store" || directive == "no-cache" || directive == "private" {
             result.cacheable = false;
         }
+        // proxy-revalidate is must-revalidate for shared caches
+        if directive == "must-revalidate" || directive == "proxy-revalidate" {
+            result.must_revalidate = true;
+        }
         if let Some(age) = directive.strip_prefix("max-age=") {
             if let Ok(secs) = age.trim().parse::<u64>() {
                 result.max_age = Some(Duration::from_secs(secs));
@@ -262,16 +1447,24 @@ pub fn parse_cache_control(value: &str) -> CacheControl {
         }
         if let Some(age) = directive.strip_prefix("s-maxage=") {
             if let Ok(secs) = age.trim().parse::<u64>() {
-                // s-maxage takes precedence for shared caches
-                result.max_age = Some(Duration::from_secs(secs));
+                s_maxage = Some(Duration::from_secs(secs));
             }
         }
     }
 
+    // s-maxage takes precedence for shared caches, wherever it appears
+    if s_maxage.is_some() {
+        result.max_age = s_maxage;
+    }
+
     result
 }
 
 pub struct CacheControl {
     pub cacheable: bool,
+    /// Explicit freshness lifetime (`s-maxage`, else `max-age`).
     pub max_age: Option<Duration>,
+    /// Stale copies must not be served without revalidation. Colander never
+    /// revalidates, so such responses only get their explicit lifetime.
+    pub must_revalidate: bool,
 }
@@ -0,0 +1,215 @@
+use crate::config::CircuitBreakerConfig;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Where the breaker stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Requests flow; error rates are being measured.
+    Closed,
+    /// Upstream is failing; requests fail fast until the cool-down ends.
+    Open,
+    /// Cool-down over; a few trial requests decide whether to close again.
+    HalfOpen,
+}
+
+impl CircuitState {
+    /// Value of the `colander_upstream_circuit_state` gauge.
+    fn gauge(self) -> f64 {
+        match self {
+            CircuitState::Closed => 0.0,
+            CircuitState::Open => 1.0,
+            CircuitState::HalfOpen => 2.0,
+        }
+    }
+}
+
+/// Point-in-time view of the breaker for `/api/stats`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CircuitStatus {
+    pub state: CircuitState,
+    /// Times the circuit has opened since startup.
+    pub opens: u64,
+    /// Upstream requests and failures in the current window.
+    pub window_requests: u64,
+    pub window_errors: u64,
+}
+
+/// Circuit breaker around upstream requests, shared by every backend.
+///
+/// While closed, outcomes are counted in fixed windows of `window_ms`. A
+/// window with at least `min_requests` whose error rate exceeds
+/// `error_rate_threshold` opens the circuit: for `cool_down_ms` no request
+/// reaches upstream. After that it's half-open and lets up to
+/// `half_open_requests` trials through at once; a successful trial closes
+/// the circuit, a failed one opens it for another cool-down.
+pub struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    /// Mirrors `state == Closed` so the common case never takes the lock.
+    closed: AtomicBool,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    state: CircuitState,
+    window_start: Instant,
+    requests: u64,
+    errors: u64,
+    /// When the circuit opened, or when the current batch of trials started.
+    since: Instant,
+    trials: u32,
+    opens: u64,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        let now = Instant::now();
+        Self {
+            config,
+            closed: AtomicBool::new(true),
+            inner: Mutex::new(Inner {
+                state: CircuitState::Closed,
+                window_start: now,
+                requests: 0,
+                errors: 0,
+                since: now,
+                trials: 0,
+                opens: 0,
+            }),
+        }
+    }
+
+    /// Whether expired entries should be kept and served when upstream is
+    /// refused (see `allow`).
+    pub fn serves_stale(&self) -> bool {
+        self.config.serve_stale && !self.closed.load(Ordering::Relaxed)
+    }
+
+    /// Whether the next upstream request may go out. Every `true` must be
+    /// followed by a `record` of its outcome.
+    pub fn allow(&self) -> bool {
+        if self.closed.load(Ordering::Relaxed) {
+            return true;
+        }
+        let cool_down = Duration::from_millis(self.config.cool_down_ms);
+        let now = Instant::now();
+        let mut inner = self.inner.lock();
+        match inner.state {
+            CircuitState::Closed => true,
+            CircuitState::Open => {
+                if now < inner.since + cool_down {
+                    return false;
+                }
+                self.transition(&mut inner, CircuitState::HalfOpen, now);
+                inner.trials = 1;
+                true
+            }
+            CircuitState::HalfOpen => {
+                let limit = self.config.half_open_requests.max(1);
+                // Trials that never reported back (the client went away
+                // mid-request) mustn't wedge the breaker half-open
+                if inner.trials >= limit && now >= inner.since + cool_down {
+                    inner.trials = 0;
+                    inner.since = now;
+                }
+                if inner.trials < limit {
+                    inner.trials += 1;
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    /// Feed the outcome of one upstream request. `ok` is false for connect
+    /// errors and 5xx responses.
+    pub fn record(&self, ok: bool) {
+        let now = Instant::now();
+        let mut inner = self.inner.lock();
+        match inner.state {
+            CircuitState::Closed => {
+                if now.duration_since(inner.window_start)
+                    >= Duration::from_millis(self.config.window_ms)
+                {
+                    inner.window_start = now;
+                    inner.requests = 0;
+                    inner.errors = 0;
+                }
+                inner.requests += 1;
+                if !ok {
+                    inner.errors += 1;
+                }
+                let error_rate = inner.errors as f64 / inner.requests as f64;
+                if inner.requests >= self.config.min_requests
+                    && error_rate > self.config.error_rate_threshold
+                {
+                    self.transition(&mut inner, CircuitState::Open, now);
+                }
+            }
+            CircuitState::HalfOpen if ok => {
+                self.transition(&mut inner, CircuitState::Closed, now);
+            }
+            CircuitState::HalfOpen => {
+                self.transition(&mut inner, CircuitState::Open, now);
+            }
+            // Requests admitted before the circuit opened; nothing to learn
+            CircuitState::Open => {}
+        }
+    }
+
+    /// How long until trial requests will be let through, for `Retry-After`.
+    pub fn retry_after(&self) -> Duration {
+        let inner = self.inner.lock();
+        let cool_down = Duration::from_millis(self.config.cool_down_ms);
+        (inner.since + cool_down).saturating_duration_since(Instant::now())
+    }
+
+    pub fn status(&self) -> CircuitStatus {
+        let inner = self.inner.lock();
+        CircuitStatus {
+            state: inner.state,
+            opens: inner.opens,
+            window_requests: inner.requests,
+            window_errors: inner.errors,
+        }
+    }
+
+    fn transition(&self, inner: &mut Inner, to: CircuitState, now: Instant) {
+        let from = inner.state;
+        inner.state = to;
+        inner.since = now;
+        inner.trials = 0;
+        match to {
+            CircuitState::Open => {
+                if from == CircuitState::Closed {
+                    tracing::warn!(
+                        requests = inner.requests,
+                        errors = inner.errors,
+                        cool_down_ms = self.config.cool_down_ms,
+                        "upstream circuit opened"
+                    );
+                } else {
+                    tracing::warn!("upstream trial request failed, circuit re-opened");
+                }
+                inner.opens += 1;
+                ::metrics::counter!("colander_upstream_circuit_opens_total").increment(1);
+            }
+            CircuitState::HalfOpen => {
+                tracing::info!("upstream circuit half-open, sending trial requests");
+            }
+            CircuitState::Closed => {
+                inner.window_start = now;
+                inner.requests = 0;
+                inner.errors = 0;
+                tracing::info!("upstream circuit closed");
+            }
+        }
+        self.closed
+            .store(to == CircuitState::Closed, Ordering::Relaxed);
+        ::metrics::gauge!("colander_upstream_circuit_state").set(to.gauge());
+    }
+}
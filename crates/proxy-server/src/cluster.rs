@@ -0,0 +1,269 @@
+use crate::config::{ClusterConfig, HostHeader};
+use crate::forwarded;
+use crate::hop_by_hop;
+use crate::metrics::{InstanceStats, MetricsState};
+use crate::proxy::{AppState, HttpClient};
+use axum::body::Body;
+use axum::http::request::Parts;
+use axum::http::{header, HeaderName, HeaderValue, Request, Response};
+use http_body_util::BodyExt;
+use serde::Serialize;
+use std::time::Duration;
+
+/// Set on requests one instance forwards to another. The receiver serves
+/// them itself whoever it thinks owns the key, so a request takes at most
+/// one hop even while instances disagree about `nodes` (e.g. mid-rollout).
+pub const FORWARDED_BY: HeaderName = HeaderName::from_static("x-colander-forwarded-by");
+
+/// Instance in `nodes` that owns `key`, by rendezvous hashing: every node
+/// scores the key and the highest score wins. All instances agree on the
+/// owner given the same node list in any order, and adding or removing a
+/// node only moves the keys that node gains or loses.
+pub fn owner<'a>(nodes: &'a [String], key: &str) -> Option<&'a str> {
+    nodes
+        .iter()
+        .map(|node| node.trim_end_matches('/'))
+        .max_by_key(|node| (score(node, key), *node))
+}
+
+/// FNV-1a over node and key, finished with the splitmix64 mixer. Unlike
+/// std's and ahash's hashers it's the same in every process and build,
+/// which the instances need to agree on owners.
+fn score(node: &str, key: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &byte in node.as_bytes().iter().chain(&[0]).chain(key.as_bytes()) {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+/// Send a cacheable request on to the instance that owns its key and relay
+/// the response as it streams in. An error means the owner couldn't be
+/// reached or didn't start answering within the request timeout; the caller
+/// then serves the request itself.
+pub async fn forward(
+    state: &AppState,
+    owner: &str,
+    node_url: &str,
+    parts: &Parts,
+) -> Result<Response<Body>, String> {
+    let config = state.config.load();
+    let path = parts.uri.path_and_query().map_or("/", |pq| pq.as_str());
+    // Host is filled in from the owner's URL, unless the owner is to
+    // preserve the client's
+    let mut headers = parts.headers.clone();
+    hop_by_hop::strip(&mut headers);
+    headers.remove(header::HOST);
+    if config.upstream.host_header == HostHeader::Preserve {
+        if let Some(host) =
+            forwarded::client_host(parts).and_then(|h| HeaderValue::from_str(h).ok())
+        {
+            headers.insert(header::HOST, host);
+        }
+    }
+    // The owner appends this instance to them if it trusts it
+    forwarded::set_headers(&config.upstream.forwarded, parts, &mut headers);
+    let mut req = Request::builder()
+        .method(&parts.method)
+        .uri(format!("{owner}{path}"));
+    if let Some(out) = req.headers_mut() {
+        *out = headers;
+    }
+    let node = HeaderValue::from_str(node_url).map_err(|e| e.to_string())?;
+    let req = req
+        .header(FORWARDED_BY, node)
+        .body(Body::empty())
+        .map_err(|e| e.to_string())?;
+
+    let send = state.client.request(req);
+    let resp = match state.upstreams.request_timeout() {
+        Some(timeout) => tokio::time::timeout(timeout, send)
+            .await
+            .map_err(|_| format!("timed out after {}ms", timeout.as_millis()))?,
+        None => send.await,
+    }
+    .map_err(|e| e.to_string())?;
+    let mut resp = resp.map(Body::new);
+    hop_by_hop::strip(resp.headers_mut());
+    Ok(resp)
+}
+
+/// Fleet-wide view returned by `GET /api/cluster/stats`.
+///
+/// Totals cover this instance plus every peer that answered; unreachable
+/// peers are listed with their error but don't count toward them.
+#[derive(Debug, Serialize)]
+pub struct ClusterStats {
+    pub instances: usize,
+    pub reachable: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub hit_rate: f64,
+    pub evictions: u64,
+    pub size: usize,
+    pub capacity: usize,
+    pub throughput_rps: f64,
+    pub members: Vec<Member>,
+}
+
+/// One instance's `/api/stats`, or why it couldn't be fetched.
+#[derive(Debug, Serialize)]
+pub struct Member {
+    /// `"self"` for this instance, otherwise the peer's configured URL.
+    pub instance: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<InstanceStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Collect local stats and query every peer's `/api/stats` concurrently,
+/// then sum the primary caches' counters.
+pub async fn aggregate(state: &MetricsState, config: &ClusterConfig) -> ClusterStats {
+    let timeout = Duration::from_millis(config.timeout_ms);
+    let token = config.token.as_deref();
+    let fetches = config.peers.iter().map(|peer| {
+        let client = state.app.client.clone();
+        async move {
+            let result = fetch_stats(client, peer, token, timeout).await;
+            if let Err(e) = &result {
+                tracing::debug!(peer = %peer, error = %e, "cluster peer stats failed");
+            }
+            Member {
+                instance: peer.clone(),
+                error: result.as_ref().err().cloned(),
+                stats: result.ok(),
+            }
+        }
+    });
+    let peers = futures_util::future::join_all(fetches).await;
+
+    let local = Member {
+        instance: "self".into(),
+        stats: Some(InstanceStats::collect(state)),
+        error: None,
+    };
+    let members: Vec<Member> = std::iter::once(local).chain(peers).collect();
+
+    let mut totals = ClusterStats {
+        instances: members.len(),
+        reachable: 0,
+        hits: 0,
+        misses: 0,
+        hit_rate: 0.0,
+        evictions: 0,
+        size: 0,
+        capacity: 0,
+        throughput_rps: 0.0,
+        members: Vec::new(),
+    };
+    for stats in members.iter().filter_map(|m| m.stats.as_ref()) {
+        totals.reachable += 1;
+        totals.hits += stats.primary.hits;
+        totals.misses += stats.primary.misses;
+        totals.evictions += stats.primary.evictions;
+        totals.size += stats.primary.size;
+        totals.capacity += stats.primary.capacity;
+        totals.throughput_rps += stats.throughput_rps;
+    }
+    let lookups = totals.hits + totals.misses;
+    if lookups > 0 {
+        totals.hit_rate = totals.hits as f64 / lookups as f64;
+    }
+    totals.members = members;
+    totals
+}
+
+async fn fetch_stats(
+    client: HttpClient,
+    peer: &str,
+    token: Option<&str>,
+    timeout: Duration,
+) -> Result<InstanceStats, String> {
+    let uri = format!("{}/api/stats", peer.trim_end_matches('/'));
+    let mut req = Request::get(&uri);
+    if let Some(token) = token {
+        req = req.header(header::AUTHORIZATION, format!("Bearer {token}"));
+    }
+    let req = req.body(Body::empty()).map_err(|e| e.to_string())?;
+
+    let fetch = async {
+        let resp = client.request(req).await.map_err(|e| e.to_string())?;
+        if !resp.status().is_success() {
+            return Err(format!("status {}", resp.status()));
+        }
+        let body = resp
+            .into_body()
+            .collect()
+            .await
+            .map_err(|e| e.to_string())?
+            .to_bytes();
+        serde_json::from_slice(&body).map_err(|e| format!("bad stats body: {e}"))
+    };
+    tokio::time::timeout(timeout, fetch)
+        .await
+        .unwrap_or_else(|_| Err(format!("timed out after {}ms", timeout.as_millis())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Instances of different builds must agree on owners, so these values
+    // may never change: a new hash would move keys during a rolling deploy.
+    const NODES: [&str; 3] = [
+        "http://10.0.0.1:8080",
+        "http://10.0.0.2:8080",
+        "http://10.0.0.3:8080",
+    ];
+    const OWNERS: [(&str, usize, usize); 8] = [
+        // key, owner among all three nodes, owner without the third
+        ("GET:/api/items/1", 2, 1),
+        ("GET:/api/items/2", 1, 1),
+        ("GET:/api/items/3", 1, 1),
+        ("GET:/api/items/4", 0, 0),
+        ("GET:/api/items/5", 0, 0),
+        ("GET:/api/items/6", 2, 1),
+        ("GET:/api/items/7", 0, 0),
+        ("GET:/api/items/8", 0, 0),
+    ];
+
+    fn nodes(urls: &[&str]) -> Vec<String> {
+        urls.iter().map(|url| url.to_string()).collect()
+    }
+
+    #[test]
+    fn score_is_stable() {
+        assert_eq!(score("", ""), 0x25fc_6dd3_6ce0_4b20);
+        assert_eq!(
+            score("http://10.0.0.1:8080", "GET:/api/items/1"),
+            0x3cc3_738a_0b48_9970
+        );
+    }
+
+    #[test]
+    fn owners_are_stable() {
+        let all = nodes(&NODES);
+        let two = nodes(&NODES[..2]);
+        for (key, owner_of_all, owner_of_two) in OWNERS {
+            assert_eq!(owner(&all, key), Some(NODES[owner_of_all]), "{key}");
+            assert_eq!(owner(&two, key), Some(NODES[owner_of_two]), "{key}");
+        }
+    }
+
+    #[test]
+    fn owner_ignores_order_and_trailing_slashes() {
+        let shuffled = nodes(&[
+            "http://10.0.0.3:8080/",
+            "http://10.0.0.1:8080",
+            "http://10.0.0.2:8080/",
+        ]);
+        for (key, owner_of_all, _) in OWNERS {
+            assert_eq!(owner(&shuffled, key), Some(NODES[owner_of_all]), "{key}");
+        }
+        assert_eq!(owner(&[], "GET:/api/items/1"), None);
+    }
+}
@@ -1,11 +1,23 @@
-use crate::cache_layer::CacheLayer;
+use crate::cache_layer::{CacheLayer, POLICIES};
+use crate::listeners::validate_roles;
+use crate::tls::TlsReloader;
+use crate::upstream::UpstreamPool;
+use crate::writes;
 use arc_swap::ArcSwap;
-use serde::Deserialize;
+use axum::http::{HeaderName, HeaderValue};
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
 use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
 
-#[derive(Debug, Clone, Deserialize)]
+/// Version of the `config.toml` format, reported by `/api/version`. Bumped
+/// when a field is removed or changes meaning; new optional fields don't.
+pub const CONFIG_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct Config {
     #[serde(default)]
     pub server: ServerConfig,
@@ -14,24 +26,241 @@ pub struct Config {
     pub cache: CacheConfig,
     #[serde(default)]
     pub resp: RespConfig,
+    #[serde(default)]
+    pub warmup: WarmupConfig,
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
+    #[serde(default)]
+    pub soak: SoakConfig,
+    #[serde(default)]
+    pub cluster: ClusterConfig,
+    #[serde(default)]
+    pub load_shedding: LoadSheddingConfig,
+    #[serde(default)]
+    pub metrics_export: MetricsExportConfig,
+    /// Explicit listener layout. When empty, the legacy `[server]`/`[resp]`
+    /// addresses are used instead (see `Config::effective_listeners`).
+    #[serde(default)]
+    pub listeners: Vec<ListenerConfig>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct ServerConfig {
     #[serde(default = "default_listen_addr")]
     pub listen_addr: String,
     #[serde(default = "default_metrics_addr")]
     pub metrics_addr: String,
+    /// Accept cleartext HTTP/2 on `listen_addr` (see `ListenerConfig::h2c`).
+    #[serde(default)]
+    pub h2c: bool,
+    /// Terminate TLS on both `listen_addr` and `metrics_addr`.
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    /// How much metrics history `/api/history` can serve (0 keeps none).
+    #[serde(default = "default_metrics_history_secs")]
+    pub metrics_history_secs: u64,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct UpstreamConfig {
+    /// Single origin. Ignored when `backends` is set.
+    #[serde(default)]
     pub url: String,
+    /// Fixed `Host` for `url`, like a backend's `host`.
+    #[serde(default)]
+    pub host: Option<String>,
+    /// `Host` sent to backends without a fixed `host` of their own.
+    #[serde(default)]
+    pub host_header: HostHeader,
     #[serde(default = "default_timeout_ms")]
     pub timeout_ms: u64,
+    /// Origins with failover priority (see `UpstreamConfig::effective_backends`).
+    #[serde(default)]
+    pub backends: Vec<BackendConfig>,
+    #[serde(default)]
+    pub health_check: HealthCheckConfig,
+    #[serde(default)]
+    pub outlier_detection: OutlierDetectionConfig,
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    #[serde(default)]
+    pub http2: UpstreamHttp2,
+    #[serde(default)]
+    pub concurrency: UpstreamConcurrencyConfig,
+    #[serde(default)]
+    pub forwarded: ForwardedConfig,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+/// Where the `Host` of upstream requests comes from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HostHeader {
+    /// The backend URL's authority.
+    #[default]
+    Upstream,
+    /// Whatever the client sent, for origins that serve several virtual hosts.
+    Preserve,
+}
+
+/// Which HTTP version the proxy speaks to backends.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpstreamHttp2 {
+    /// `https://` backends negotiate HTTP/2 via ALPN; `http://` ones use HTTP/1.1.
+    #[default]
+    Auto,
+    /// HTTP/2 for every backend, including cleartext h2c with prior knowledge.
+    Always,
+    /// HTTP/1.1 only.
+    Never,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackendConfig {
+    pub url: String,
+    /// Priority tier: 0 is primary, higher tiers are backups used only while
+    /// every backend in all lower tiers is unhealthy.
+    #[serde(default)]
+    pub tier: u32,
+    /// `Host` sent to this backend whatever the client asked for, overriding
+    /// `host_header`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+}
+
+/// Active health checks against every backend.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct HealthCheckConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Path probed with `GET`; any 2xx counts as a pass.
+    #[serde(default = "default_health_path")]
+    pub path: String,
+    #[serde(default = "default_health_interval_ms")]
+    pub interval_ms: u64,
+    #[serde(default = "default_health_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Consecutive failures before a backend is marked unhealthy.
+    #[serde(default = "default_unhealthy_threshold")]
+    pub unhealthy_threshold: u32,
+    /// Consecutive passes before an unhealthy backend is trusted again.
+    #[serde(default = "default_healthy_threshold")]
+    pub healthy_threshold: u32,
+}
+
+/// Stop sending requests upstream while most of them are failing, instead of
+/// piling more load on an origin that's already down (see `circuit`).
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CircuitBreakerConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Length of the window error rates are measured over.
+    #[serde(default = "default_circuit_window_ms")]
+    pub window_ms: u64,
+    /// Error rate (connect errors and 5xx) within a window that opens the circuit.
+    #[serde(default = "default_error_rate_threshold")]
+    pub error_rate_threshold: f64,
+    /// Requests a window needs before its error rate is judged.
+    #[serde(default = "default_circuit_min_requests")]
+    pub min_requests: u64,
+    /// How long the circuit stays open before trial requests are let through.
+    #[serde(default = "default_cool_down_ms")]
+    pub cool_down_ms: u64,
+    /// Trial requests allowed at once while half-open.
+    #[serde(default = "default_half_open_requests")]
+    pub half_open_requests: u32,
+    /// While open, answer with an expired cached copy when there is one
+    /// rather than `503`.
+    #[serde(default = "default_serve_stale")]
+    pub serve_stale: bool,
+}
+
+/// Retry idempotent upstream requests that fail to connect, time out or come
+/// back with a retryable status. Read per request, so hot reload applies
+/// immediately.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RetryConfig {
+    /// Retries after the first attempt. 0 disables retrying.
+    #[serde(default)]
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for each one after it.
+    #[serde(default = "default_retry_backoff_ms")]
+    pub backoff_ms: u64,
+    /// Upper bound on the delay between attempts.
+    #[serde(default = "default_retry_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+    /// Response statuses worth another attempt.
+    #[serde(default = "default_retry_on")]
+    pub retry_on: Vec<u16>,
+    /// Time limit for each attempt, including reading the body. 0 means none.
+    #[serde(default)]
+    pub per_try_timeout_ms: u64,
+}
+
+/// `X-Forwarded-*` (and optionally `Forwarded`) headers on upstream
+/// requests, so the origin sees who the client is. Read per request, so hot
+/// reload applies immediately.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ForwardedConfig {
+    /// Off leaves the client's forwarding headers as they were.
+    #[serde(default = "default_forwarded_enabled")]
+    pub enabled: bool,
+    /// Also send the RFC 7239 `Forwarded` header.
+    #[serde(default)]
+    pub rfc7239: bool,
+    /// Peers (addresses or CIDR ranges) whose own forwarding headers are
+    /// extended; anyone else's are replaced, since a client can claim any
+    /// address it likes.
+    #[serde(default, deserialize_with = "ip_nets")]
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+/// Cap on simultaneous upstream requests, so a cold cache or a flood of
+/// unique keys can't open thousands of connections to the origin at once.
+/// Requests over the cap queue for a free slot and get `503` if none frees up
+/// within `queue_timeout_ms`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct UpstreamConcurrencyConfig {
+    /// Upstream requests in flight at once, across all backends. 0 means no limit.
+    #[serde(default)]
+    pub max_in_flight: usize,
+    /// How long a request may wait for a slot.
+    #[serde(default = "default_queue_timeout_ms")]
+    pub queue_timeout_ms: u64,
+}
+
+/// Passive outlier detection: temporarily eject backends whose live traffic
+/// shows errors or latency far worse than their tier peers.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct OutlierDetectionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// How often error rates and latencies are analysed (and windows reset).
+    #[serde(default = "default_outlier_interval_ms")]
+    pub interval_ms: u64,
+    /// Consecutive failed requests (connect errors or 5xx) that eject at once.
+    #[serde(default = "default_consecutive_errors")]
+    pub consecutive_errors: u32,
+    /// Error rate within one interval above which a backend is ejected.
+    #[serde(default = "default_error_rate_threshold")]
+    pub error_rate_threshold: f64,
+    /// Eject when latency EWMA exceeds this multiple of the tier median.
+    #[serde(default = "default_latency_factor")]
+    pub latency_factor: f64,
+    /// Requests a backend needs in an interval before rates are judged.
+    #[serde(default = "default_outlier_min_requests")]
+    pub min_requests: u64,
+    /// Ejection length; multiplied by how many times the backend was ejected.
+    #[serde(default = "default_base_ejection_ms")]
+    pub base_ejection_ms: u64,
+    /// Never eject more than this share of a tier at once.
+    #[serde(default = "default_max_ejection_percent")]
+    pub max_ejection_percent: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct CacheConfig {
     #[serde(default = "default_capacity")]
     pub capacity: usize,
@@ -39,37 +268,827 @@ pub struct CacheConfig {
     pub default_ttl_seconds: u64,
     #[serde(default = "default_max_body_size")]
     pub max_body_size_bytes: usize,
+    /// Bound on the approximate memory held by cached entries, shared
+    /// evenly by the primary and comparison caches. 0 for no bound.
+    #[serde(default)]
+    pub max_memory_bytes: usize,
     #[serde(default = "default_eviction_policy")]
     pub eviction_policy: String,
+    /// Per policy (`sieve = 0.95`), the share of a full shard's capacity to
+    /// evict down to at once. Policies left out evict one entry per insert.
+    #[serde(default)]
+    pub low_watermark: BTreeMap<String, f64>,
+    /// Policies to shadow the primary with in demo mode, for hit-rate
+    /// comparison. A single name or a list (`["lru", "fifo"]`).
+    #[serde(default, deserialize_with = "string_or_list")]
+    pub comparison_policy: Vec<String>,
+    /// Number of hottest keys to track for `/api/hotkeys` (0 disables tracking).
+    #[serde(default = "default_hot_keys_top_k")]
+    pub hot_keys_top_k: usize,
+    /// Make concurrent misses for a key wait for one upstream fetch instead
+    /// of each sending their own. Read per request.
+    #[serde(default = "default_coalesce_misses")]
+    pub coalesce_misses: bool,
+    #[serde(default)]
+    pub adaptive_ttl: AdaptiveTtlConfig,
+    #[serde(default)]
+    pub bypass: BypassConfig,
+    #[serde(default)]
+    pub key: KeyConfig,
+    #[serde(default)]
+    pub headers: StoredHeadersConfig,
     #[serde(default)]
-    pub comparison_policy: Option<String>,
+    pub partitioning: PartitioningConfig,
+    #[serde(default)]
+    pub writes: WritesConfig,
+    #[serde(default)]
+    pub refresh_ahead: RefreshAheadConfig,
+    #[serde(default)]
+    pub tenant: TenantConfig,
+    #[serde(default)]
+    pub directives: DirectivesConfig,
+    /// Caps on the share of the cache one route or tenant may fill. A key
+    /// counts against the first that matches it.
+    #[serde(default)]
+    pub quotas: Vec<QuotaConfig>,
+    /// WebAssembly hooks run on requests to matching paths (see `plugin`).
+    /// The first match wins. Needs the `wasm` feature.
+    #[serde(default)]
+    pub plugins: Vec<PluginConfig>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+/// Keep each keyspace partition on its own few shards, so clearing or
+/// resizing one only locks those (see `colander_cache::sharded::Partitioning`).
+/// HTTP keys look like `GET:/images/a.png`, so the defaults partition by
+/// first path segment.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct PartitioningConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_partition_separator")]
+    pub separator: char,
+    /// Which `separator`-delimited field names the partition.
+    #[serde(default = "default_partition_segment")]
+    pub segment: usize,
+    #[serde(default = "default_shards_per_partition")]
+    pub shards_per_partition: usize,
+    /// Warn when one partition's shards serve more than this fraction of
+    /// lookups. Read on every check, so hot reload applies immediately.
+    #[serde(default = "default_skew_warn_share")]
+    pub skew_warn_share: f64,
+}
+
+/// Which upstream response headers are stored with an entry and replayed on
+/// hits. Names are case-insensitive. `Date` and `Age` are never stored: hits
+/// get a fresh `Date` and colander's own `Age`. Read per request; entries
+/// already cached keep the headers they were stored with.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct StoredHeadersConfig {
+    /// When non-empty, only these headers (and `keep`) are stored.
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Headers never stored, e.g. `["set-cookie", "server"]`.
+    #[serde(default = "default_denied_headers")]
+    pub deny: Vec<String>,
+    /// Headers always stored, whatever `allow` and `deny` say.
+    #[serde(default = "default_kept_headers")]
+    pub keep: Vec<String>,
+}
+
+/// How request URIs are normalized into cache keys, so near-identical URLs
+/// share one entry. Everything is off by default. Read per request; entries
+/// stored under the old scheme simply age out after a change.
+///
+/// Patterns are exact, or a prefix when they end in `*` (`utm_*`, `/static/*`).
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct KeyConfig {
+    /// Order query parameters by name (repeated names keep their order).
+    #[serde(default)]
+    pub sort_query: bool,
+    /// Query parameters to drop, e.g. `["utm_*", "gclid", "fbclid"]`.
+    #[serde(default)]
+    pub strip_params: Vec<String>,
+    /// Lowercase the path. Only safe if the origin treats paths case-insensitively.
+    #[serde(default)]
+    pub lowercase_path: bool,
+    /// Paths whose query string is ignored entirely.
+    #[serde(default)]
+    pub ignore_query: Vec<String>,
+}
+
+/// What a successful `POST`, `PUT`, `PATCH` or `DELETE` does to the cached
+/// `GET`s it made stale (see `writes`). Read per request.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct WritesConfig {
+    #[serde(default)]
+    pub mode: WriteMode,
+    /// More `GET`s to drop, as paths built from the written one: `{path}` is
+    /// its path and `{parent}` that path without the last segment. A
+    /// trailing `*` makes a prefix, e.g. `["{parent}", "{path}?*"]`.
+    #[serde(default)]
+    pub invalidate: Vec<String>,
+}
+
+/// How `[cache.writes]` treats the written path's own `GET` entry.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WriteMode {
+    /// Leave the cache alone; entries live out their TTL.
+    Off,
+    /// Drop the entry (and the `invalidate` patterns).
+    #[default]
+    Invalidate,
+    /// Drop them, then refetch the entry in the background so the next
+    /// read is a hit. Deletes are only invalidated.
+    Refresh,
+}
+
+/// Refetch hot entries shortly before they expire, so popular objects never
+/// miss (see `refresh`). Read per request.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RefreshAheadConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Only entries served at least this many times are refreshed.
+    #[serde(default = "default_refresh_min_hits")]
+    pub min_hits: u64,
+    /// Refresh once less than this percentage of the TTL is left.
+    #[serde(default = "default_refresh_window_percent")]
+    pub window_percent: f64,
+    /// Overrides for matching paths (exact, or a prefix ending in `*`). The
+    /// first match wins; settings it leaves out come from above.
+    #[serde(default)]
+    pub routes: Vec<RefreshRouteConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RefreshRouteConfig {
+    pub path: String,
+    #[serde(default)]
+    pub enabled: Option<bool>,
+    #[serde(default)]
+    pub min_hits: Option<u64>,
+    #[serde(default)]
+    pub window_percent: Option<f64>,
+}
+
+/// Tenants named by a request header, each with cache entries and hit-rate
+/// stats of its own (see `tenant`). Read per request, so changes apply on
+/// hot reload without a rebuild.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TenantConfig {
+    /// Header naming the tenant, e.g. `X-Tenant-ID`. Empty turns tenants off.
+    #[serde(default)]
+    pub header: String,
+    /// Requests without the header bypass the cache instead of sharing the
+    /// entries of other tenantless requests.
+    #[serde(default)]
+    pub required: bool,
+    /// Tenants with stats of their own; any beyond are counted together.
+    #[serde(default = "default_max_tracked_tenants")]
+    pub max_tracked: usize,
+}
+
+/// What clients may ask of the cache per request (see `directives`). Read
+/// per request, so changes apply on hot reload without a rebuild.
+///
+/// `no-store` and `only-if-cached` are honored by default: they can only
+/// keep a response out of the cache or a request away from the origin.
+/// `no-cache` and the bypass header are off by default: any client could
+/// otherwise send requests past the cache to the origin, and browsers send
+/// `no-cache` on every hard reload.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct DirectivesConfig {
+    /// Honor `no-cache` in the request's `Cache-Control`.
+    #[serde(default)]
+    pub no_cache: bool,
+    /// Honor `no-store` in the request's `Cache-Control`.
+    #[serde(default = "default_honor_no_store")]
+    pub no_store: bool,
+    /// Honor `only-if-cached` in the request's `Cache-Control`.
+    #[serde(default = "default_honor_only_if_cached")]
+    pub only_if_cached: bool,
+    /// A request with this header set to `1` skips the cache entirely.
+    /// Empty (the default) turns it off.
+    #[serde(default)]
+    pub bypass_header: String,
+}
+
+/// A cap on the share of the cache some keys may fill, enforced at insert
+/// (see `quota`). Exactly one of `path` and `tenant` is set. Applied when
+/// the cache layer is rebuilt.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct QuotaConfig {
+    /// Request paths sharing the quota, exact or a prefix ending in `*`.
+    #[serde(default)]
+    pub path: Option<String>,
+    /// `[cache.tenant]` tenants, exact or a prefix ending in `*`; each
+    /// matching tenant gets a quota of its own.
+    #[serde(default)]
+    pub tenant: Option<String>,
+    /// Largest share of the capacity the group may fill, in (0, 1].
+    pub max_share: f64,
+}
+
+/// A WebAssembly module whose `on_request` and `on_response` exports see,
+/// and may change, the requests to `path` (see `plugin`). Loaded at
+/// startup; changes need a restart.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct PluginConfig {
+    /// Request paths the plugin runs on, exact or a prefix ending in `*`.
+    pub path: String,
+    /// Path to the compiled `.wasm` module.
+    pub module: String,
+    /// Instructions (roughly) one hook call may run before it's stopped.
+    #[serde(default = "default_plugin_fuel")]
+    pub fuel: u64,
+    /// Most linear memory one of the plugin's instances may grow to. They
+    /// are reused across calls (see `plugin`).
+    #[serde(default = "default_plugin_max_memory_bytes")]
+    pub max_memory_bytes: usize,
+}
+
+/// Requests carrying credentials that mustn't share cache entries across users.
+/// Read per request, so changes apply on hot reload without a rebuild.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct BypassConfig {
+    /// Request headers that mark a request as private (case-insensitive).
+    #[serde(default = "default_bypass_headers")]
+    pub headers: Vec<String>,
+    /// Cookie names that mark a request as private.
+    #[serde(default)]
+    pub cookies: Vec<String>,
+    /// Query parameters that mark a request as private (e.g. `token`).
+    #[serde(default)]
+    pub query_params: Vec<String>,
+    #[serde(default)]
+    pub mode: BypassMode,
+}
+
+/// What to do with a request that matches `[cache.bypass]`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BypassMode {
+    /// Go straight to upstream; never read or write the cache.
+    #[default]
+    Bypass,
+    /// Cache under a key scoped to a hash of the matched credentials.
+    PerUser,
+}
+
+/// Learn per-key TTLs from how often refetches find the object changed.
+/// Only applies when the origin doesn't send `max-age`/`s-maxage`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AdaptiveTtlConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_min_ttl")]
+    pub min_ttl_seconds: u64,
+    #[serde(default = "default_max_ttl")]
+    pub max_ttl_seconds: u64,
+}
+
+/// Prefetch a list of hot URLs at startup before reporting ready.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct WarmupConfig {
+    /// Paths, URLs, cache keys or access-log lines (see `warmup::parse_warm_file`).
+    #[serde(default)]
+    pub file: Option<String>,
+    /// Entries listed inline, in the same formats as `file`'s lines. Warmed
+    /// along with the file's.
+    #[serde(default)]
+    pub urls: Vec<String>,
+    #[serde(default = "default_warmup_concurrency")]
+    pub concurrency: usize,
+    /// Report ready after this long even if warmup hasn't finished.
+    #[serde(default = "default_warmup_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+/// How the process drains on SIGTERM or `POST /api/drain`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct ShutdownConfig {
+    /// Keep serving (with `/readyz` failing) this long before closing listeners.
+    #[serde(default)]
+    pub grace_period_secs: u64,
+    /// Write the hot, still-cached keys here before exiting, in a format
+    /// `[warmup] file` can read back.
+    #[serde(default)]
+    pub snapshot_file: Option<String>,
+}
+
+/// Invariant checking for soak runs. Only used by builds with the `soak`
+/// feature; other builds warn at startup if it's changed.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SoakConfig {
+    #[serde(default = "default_soak_interval_ms")]
+    pub interval_ms: u64,
+}
+
+/// Peers whose stats `GET /api/cluster/stats` aggregates with this
+/// instance's, and the instances that share one keyspace by consistent
+/// hashing (see `cluster::owner`). Read per request, so hot reload applies
+/// immediately.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ClusterConfig {
+    /// Admin base URLs of the other instances, e.g. `"http://10.0.0.2:9090"`
+    /// (`https://` for peers terminating TLS, verified like upstreams).
+    /// Don't list this instance; it's always included.
+    #[serde(default)]
+    pub peers: Vec<String>,
+    /// Per-peer timeout; slower peers are reported as unreachable.
+    #[serde(default = "default_cluster_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Sent as `Authorization: Bearer <token>` to peers whose admin
+    /// listener requires auth.
+    #[serde(default)]
+    pub token: Option<String>,
+    /// Proxy base URLs of every instance sharing the keyspace, this one
+    /// included. Cacheable requests for keys another instance owns are
+    /// forwarded to it. Empty (default) disables routing.
+    #[serde(default)]
+    pub nodes: Vec<String>,
+    /// This instance's entry in `nodes`.
+    #[serde(default)]
+    pub node_url: Option<String>,
+    /// Pass keys removed here (RESP `DEL`, memcached `delete`, ...) on to
+    /// every peer, so they drop their copies too (see `invalidation`).
+    #[serde(default)]
+    pub broadcast_invalidations: bool,
+    /// `host:port` whose addresses are more admin endpoints to broadcast
+    /// invalidations to, e.g. a headless service's DNS name. Re-resolved
+    /// every 30s.
+    #[serde(default)]
+    pub discover: Option<String>,
+}
+
+/// Overload protection for the proxy role. Read per request, so hot reload
+/// applies immediately.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct LoadSheddingConfig {
+    /// Proxy requests served at once before new ones get `503`. 0 disables.
+    #[serde(default)]
+    pub max_in_flight: usize,
+    /// `Retry-After` sent with shed responses.
+    #[serde(default = "default_retry_after_secs")]
+    pub retry_after_secs: u64,
+}
+
+/// Append every metrics snapshot to a newline-delimited JSON file, for
+/// offline analysis of benchmark runs.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MetricsExportConfig {
+    /// File to write. Unset disables the export.
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Rotate once the file would grow past this size. 0 never rotates.
+    #[serde(default = "default_export_max_bytes")]
+    pub max_bytes: u64,
+    /// Rotated files to keep (`<path>.1` is the newest). 0 keeps none.
+    #[serde(default = "default_export_max_files")]
+    pub max_files: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct RespConfig {
     #[serde(default = "default_resp_enabled")]
     pub enabled: bool,
     #[serde(default = "default_resp_addr")]
     pub listen_addr: String,
+    /// Like Redis's `requirepass`: connections must `AUTH` with it before
+    /// running any other command. Checked when a connection opens.
+    #[serde(default)]
+    pub password: Option<String>,
+    /// A second password that authenticates read-only connections, which
+    /// are refused writes (`SET`, `DEL`, `FLUSHALL`, ...).
+    #[serde(default)]
+    pub read_only_password: Option<String>,
+    /// Open connections allowed per RESP listener; clients past it get an
+    /// error and are disconnected. Checked on accept, so it's hot.
+    #[serde(default = "default_resp_max_connections")]
+    pub max_connections: usize,
+    /// Largest command a client may send, in bytes. A connection whose
+    /// unfinished command outgrows it gets an error and is closed.
+    #[serde(default = "default_resp_max_frame_bytes")]
+    pub max_frame_bytes: usize,
+    /// Close connections that send nothing for this long (0 = never).
+    /// Subscribed connections are exempt.
+    #[serde(default)]
+    pub idle_timeout_secs: u64,
+}
+
+/// What a listener serves. HTTP roles (proxy, admin, metrics) may be combined
+/// on one socket; wire-protocol roles (resp, memcached) must stand alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ListenerRole {
+    /// Caching reverse proxy (catch-all route).
+    Proxy,
+    /// Admin API under `/api/*`.
+    Admin,
+    /// Prometheus `/metrics` and the `/ws/metrics` stream.
+    Metrics,
+    /// Redis RESP2 interface.
+    Resp,
+    /// Memcached text protocol interface.
+    Memcached,
+}
+
+impl ListenerRole {
+    pub fn is_http(self) -> bool {
+        matches!(
+            self,
+            ListenerRole::Proxy | ListenerRole::Admin | ListenerRole::Metrics
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ListenerConfig {
+    pub addr: String,
+    pub roles: Vec<ListenerRole>,
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    #[serde(default)]
+    pub auth: Option<AuthConfig>,
+    /// Offer HTTP/2 to TLS clients via ALPN. HTTP roles only.
+    #[serde(default = "default_http2")]
+    pub http2: bool,
+    /// Accept cleartext HTTP/2 (prior knowledge) alongside HTTP/1.1 on a
+    /// listener without TLS. HTTP roles only.
+    #[serde(default)]
+    pub h2c: bool,
+}
+
+/// Certificate files are re-read on every config reload, so rotating them
+/// in place only needs a touch of the config file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TlsConfig {
+    pub cert_path: String,
+    pub key_path: String,
+    /// PEM CA bundle; when set, clients must present a certificate it signed (mTLS).
+    #[serde(default)]
+    pub client_ca_path: Option<String>,
+}
+
+/// Per-listener authentication. HTTP listeners require
+/// `Authorization: Bearer <token>` on every request.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AuthConfig {
+    pub token: String,
+}
+
+impl ListenerConfig {
+    pub fn has_role(&self, role: ListenerRole) -> bool {
+        self.roles.contains(&role)
+    }
+}
+
+/// Everything `Config::validate` found wrong with a config.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigErrors(pub Vec<String>);
+
+impl std::fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0.as_slice() {
+            [only] => f.write_str(only),
+            errors => write!(f, "{} config errors: {}", errors.len(), errors.join("; ")),
+        }
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+/// Why `url` can't be used as an upstream or peer base URL.
+fn check_url(url: &str) -> Result<(), &'static str> {
+    let uri: hyper::Uri = url.parse().map_err(|_| "is not a valid URL")?;
+    if !matches!(uri.scheme_str(), Some("http" | "https")) {
+        return Err("must be an http:// or https:// URL");
+    }
+    if uri.host().is_none_or(str::is_empty) {
+        return Err("has no host");
+    }
+    Ok(())
+}
+
+/// Whether a listener could bind `addr`: an IP socket address, or a host
+/// name with a port.
+fn is_host_port(addr: &str) -> bool {
+    addr.parse::<SocketAddr>().is_ok()
+        || addr.rsplit_once(':').is_some_and(|(host, port)| {
+            !host.is_empty() && !host.contains(':') && port.parse::<u16>().is_ok()
+        })
 }
 
 impl Config {
     pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
         let contents = std::fs::read_to_string(path)?;
-        let config: Config = toml::from_str(&contents)?;
+        Self::parse(&contents)
+    }
+
+    /// Parse a config from TOML text and `validate` it.
+    pub fn parse(contents: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let config: Config = toml::from_str(contents)?;
+        config.validate()?;
         Ok(config)
     }
 
+    /// Check what the types can't: policy names, listener addresses, URLs,
+    /// capacity and TTL ranges. Every problem is reported, not just the
+    /// first, so a broken file can be fixed in one pass.
+    pub fn validate(&self) -> Result<(), ConfigErrors> {
+        let mut errors = Vec::new();
+
+        if self.upstream.url.is_empty() && self.upstream.backends.is_empty() {
+            errors.push("[upstream] needs `url` or at least one [[upstream.backends]]".to_string());
+        } else {
+            for backend in self.upstream.effective_backends() {
+                if let Err(e) = check_url(&backend.url) {
+                    errors.push(format!("[upstream] {:?} {e}", backend.url));
+                }
+                if let Some(host) = &backend.host {
+                    if host.is_empty() || HeaderValue::from_str(host).is_err() {
+                        errors.push(format!(
+                            "[upstream] {:?} host {host:?} isn't a valid Host header",
+                            backend.url
+                        ));
+                    }
+                }
+            }
+        }
+
+        let listeners = self.effective_listeners();
+        let mut addrs = HashSet::new();
+        for listener in &listeners {
+            if let Err(e) = validate_roles(listener) {
+                errors.push(e);
+            }
+            if !is_host_port(&listener.addr) {
+                errors.push(format!(
+                    "listener address {:?} must be host:port",
+                    listener.addr
+                ));
+            } else if !addrs.insert(listener.addr.as_str()) {
+                errors.push(format!("listener address {} is used twice", listener.addr));
+            }
+        }
+
+        let cache = &self.cache;
+        if cache.capacity == 0 {
+            errors.push("[cache] capacity must be > 0".to_string());
+        }
+        for policy in std::iter::once(&cache.eviction_policy).chain(&cache.comparison_policy) {
+            if !POLICIES.contains(&policy.as_str()) {
+                errors.push(format!(
+                    "[cache] unknown policy {policy:?}, use one of {POLICIES:?}"
+                ));
+            }
+        }
+        if cache.default_ttl_seconds == 0 {
+            errors.push("[cache] default_ttl_seconds must be > 0".to_string());
+        }
+        for (policy, share) in &cache.low_watermark {
+            if !POLICIES.contains(&policy.as_str()) {
+                errors.push(format!(
+                    "[cache.low_watermark] unknown policy {policy:?}, use one of {POLICIES:?}"
+                ));
+            }
+            if !(0.5..=1.0).contains(share) {
+                errors.push(format!(
+                    "[cache.low_watermark] {policy} must be in [0.5, 1], got {share}"
+                ));
+            }
+        }
+        for pattern in &cache.writes.invalidate {
+            if let Err(e) = writes::expand(pattern, "/") {
+                errors.push(format!("[cache.writes] invalidate {pattern:?} {e}"));
+            }
+        }
+        let refresh = &cache.refresh_ahead;
+        let windows = std::iter::once(refresh.window_percent)
+            .chain(refresh.routes.iter().filter_map(|r| r.window_percent));
+        for window in windows {
+            if !(window > 0.0 && window <= 100.0) {
+                errors.push(format!(
+                    "[cache.refresh_ahead] window_percent must be in (0, 100], got {window}"
+                ));
+            }
+        }
+        let tenant = &cache.tenant;
+        if !tenant.header.is_empty() && HeaderName::from_bytes(tenant.header.as_bytes()).is_err() {
+            errors.push(format!(
+                "[cache.tenant] header {:?} isn't a valid header name",
+                tenant.header
+            ));
+        }
+        let bypass_header = &cache.directives.bypass_header;
+        if !bypass_header.is_empty() && HeaderName::from_bytes(bypass_header.as_bytes()).is_err() {
+            errors.push(format!(
+                "[cache.directives] bypass_header {bypass_header:?} isn't a valid header name"
+            ));
+        }
+        for quota in &cache.quotas {
+            match (&quota.path, &quota.tenant) {
+                (Some(path), None) if !path.starts_with('/') => {
+                    errors.push(format!("[[cache.quotas]] path {path:?} must start with /"));
+                }
+                (Some(_), None) | (None, Some(_)) => {}
+                _ => errors.push("[[cache.quotas]] needs one of path or tenant".to_string()),
+            }
+            if !(quota.max_share > 0.0 && quota.max_share <= 1.0) {
+                errors.push(format!(
+                    "[[cache.quotas]] max_share must be in (0, 1], got {}",
+                    quota.max_share
+                ));
+            }
+        }
+        for plugin in &cache.plugins {
+            if !plugin.path.starts_with('/') {
+                errors.push(format!(
+                    "[[cache.plugins]] path {:?} must start with /",
+                    plugin.path
+                ));
+            }
+            if plugin.module.is_empty() {
+                errors.push("[[cache.plugins]] needs a module".to_string());
+            }
+            if plugin.fuel == 0 {
+                errors.push("[[cache.plugins]] fuel must be > 0".to_string());
+            }
+        }
+        if !cache.plugins.is_empty() && !cfg!(feature = "wasm") {
+            errors.push("[[cache.plugins]] needs a build with the `wasm` feature".to_string());
+        }
+        let adaptive = &cache.adaptive_ttl;
+        if adaptive.enabled
+            && (adaptive.min_ttl_seconds == 0
+                || adaptive.min_ttl_seconds > adaptive.max_ttl_seconds)
+        {
+            errors.push(format!(
+                "[cache.adaptive_ttl] needs 0 < min_ttl_seconds <= max_ttl_seconds, got {} and {}",
+                adaptive.min_ttl_seconds, adaptive.max_ttl_seconds
+            ));
+        }
+
+        for peer in &self.cluster.peers {
+            if let Err(e) = check_url(peer) {
+                errors.push(format!("[cluster] peer {peer:?} {e}"));
+            }
+        }
+        let cluster = &self.cluster;
+        if let Some(name) = cluster
+            .discover
+            .as_deref()
+            .filter(|name| !is_host_port(name))
+        {
+            errors.push(format!("[cluster] discover {name:?} must be host:port"));
+        }
+        let mut nodes = HashSet::new();
+        for node in &cluster.nodes {
+            if let Err(e) = check_url(node) {
+                errors.push(format!("[cluster] node {node:?} {e}"));
+            } else if !nodes.insert(node.trim_end_matches('/')) {
+                errors.push(format!("[cluster] node {node:?} is listed twice"));
+            }
+        }
+        if !cluster.nodes.is_empty() {
+            match &cluster.node_url {
+                None => errors.push("[cluster] node_url is required with nodes".to_string()),
+                Some(url) if !nodes.contains(url.trim_end_matches('/')) => errors.push(format!(
+                    "[cluster] node_url {url:?} must be one of the nodes"
+                )),
+                Some(_) => {}
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigErrors(errors))
+        }
+    }
+
     pub fn default_config() -> Self {
         Config {
             server: ServerConfig::default(),
             upstream: UpstreamConfig {
                 url: "http://127.0.0.1:3000".to_string(),
+                host: None,
+                host_header: HostHeader::default(),
                 timeout_ms: 5000,
+                backends: Vec::new(),
+                health_check: HealthCheckConfig::default(),
+                outlier_detection: OutlierDetectionConfig::default(),
+                circuit_breaker: CircuitBreakerConfig::default(),
+                retry: RetryConfig::default(),
+                http2: UpstreamHttp2::default(),
+                concurrency: UpstreamConcurrencyConfig::default(),
+                forwarded: ForwardedConfig::default(),
             },
             cache: CacheConfig::default(),
             resp: RespConfig::default(),
+            warmup: WarmupConfig::default(),
+            shutdown: ShutdownConfig::default(),
+            soak: SoakConfig::default(),
+            cluster: ClusterConfig::default(),
+            load_shedding: LoadSheddingConfig::default(),
+            metrics_export: MetricsExportConfig::default(),
+            listeners: Vec::new(),
+        }
+    }
+
+    /// Listeners to bind at startup.
+    ///
+    /// If `[[listeners]]` is declared it is used verbatim. Otherwise the legacy
+    /// layout is synthesized: proxy on `server.listen_addr`, admin + metrics on
+    /// `server.metrics_addr`, and RESP on `resp.listen_addr` when enabled.
+    pub fn effective_listeners(&self) -> Vec<ListenerConfig> {
+        if !self.listeners.is_empty() {
+            return self.listeners.clone();
+        }
+
+        let mut listeners = vec![
+            ListenerConfig {
+                addr: self.server.listen_addr.clone(),
+                roles: vec![ListenerRole::Proxy],
+                tls: self.server.tls.clone(),
+                auth: None,
+                http2: default_http2(),
+                h2c: self.server.h2c,
+            },
+            ListenerConfig {
+                addr: self.server.metrics_addr.clone(),
+                roles: vec![ListenerRole::Admin, ListenerRole::Metrics],
+                tls: self.server.tls.clone(),
+                auth: None,
+                http2: default_http2(),
+                h2c: false,
+            },
+        ];
+        if self.resp.enabled {
+            listeners.push(ListenerConfig {
+                addr: self.resp.listen_addr.clone(),
+                roles: vec![ListenerRole::Resp],
+                tls: None,
+                auth: None,
+                http2: false,
+                h2c: false,
+            });
+        }
+        listeners
+    }
+}
+
+/// Settings given on the command line or as `COLANDER_*` environment
+/// variables. They win over `config.toml` and are applied again after every
+/// reload, so editing the file can't undo them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Overrides {
+    /// `[server] listen_addr`.
+    pub listen_addr: Option<String>,
+    /// `[server] metrics_addr`.
+    pub metrics_addr: Option<String>,
+    /// `[upstream] url`, replacing any `[[upstream.backends]]`.
+    pub upstream_url: Option<String>,
+    /// `[cache] eviction_policy`.
+    pub eviction_policy: Option<String>,
+    /// `[cache] capacity`.
+    pub capacity: Option<usize>,
+}
+
+impl Overrides {
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(addr) = &self.listen_addr {
+            config.server.listen_addr = addr.clone();
+        }
+        if let Some(addr) = &self.metrics_addr {
+            config.server.metrics_addr = addr.clone();
+        }
+        if let Some(url) = &self.upstream_url {
+            config.upstream.url = url.clone();
+            config.upstream.backends.clear();
+        }
+        if let Some(policy) = &self.eviction_policy {
+            config.cache.eviction_policy = policy.clone();
         }
+        if let Some(capacity) = self.capacity {
+            config.cache.capacity = capacity;
+        }
+    }
+}
+
+impl UpstreamConfig {
+    /// Backends to proxy to: `[[upstream.backends]]` if declared, otherwise
+    /// `url` as a single tier-0 backend.
+    pub fn effective_backends(&self) -> Vec<BackendConfig> {
+        if !self.backends.is_empty() {
+            return self.backends.clone();
+        }
+        vec![BackendConfig {
+            url: self.url.clone(),
+            tier: 0,
+            host: self.host.clone(),
+        }]
     }
 }
 
@@ -78,6 +1097,9 @@ impl Default for ServerConfig {
         Self {
             listen_addr: default_listen_addr(),
             metrics_addr: default_metrics_addr(),
+            h2c: false,
+            tls: None,
+            metrics_history_secs: default_metrics_history_secs(),
         }
     }
 }
@@ -88,8 +1110,241 @@ impl Default for CacheConfig {
             capacity: default_capacity(),
             default_ttl_seconds: default_ttl(),
             max_body_size_bytes: default_max_body_size(),
+            max_memory_bytes: 0,
             eviction_policy: default_eviction_policy(),
-            comparison_policy: Some("lru".to_string()),
+            low_watermark: BTreeMap::new(),
+            comparison_policy: vec!["lru".to_string()],
+            hot_keys_top_k: default_hot_keys_top_k(),
+            coalesce_misses: default_coalesce_misses(),
+            adaptive_ttl: AdaptiveTtlConfig::default(),
+            bypass: BypassConfig::default(),
+            key: KeyConfig::default(),
+            headers: StoredHeadersConfig::default(),
+            partitioning: PartitioningConfig::default(),
+            writes: WritesConfig::default(),
+            refresh_ahead: RefreshAheadConfig::default(),
+            tenant: TenantConfig::default(),
+            directives: DirectivesConfig::default(),
+            quotas: Vec::new(),
+            plugins: Vec::new(),
+        }
+    }
+}
+
+impl Default for PartitioningConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            separator: default_partition_separator(),
+            segment: default_partition_segment(),
+            shards_per_partition: default_shards_per_partition(),
+            skew_warn_share: default_skew_warn_share(),
+        }
+    }
+}
+
+impl PartitioningConfig {
+    /// Whether `other` places keys in the same shards, i.e. differs at most
+    /// in `skew_warn_share`.
+    pub fn same_layout(&self, other: &Self) -> bool {
+        (
+            self.enabled,
+            self.separator,
+            self.segment,
+            self.shards_per_partition,
+        ) == (
+            other.enabled,
+            other.separator,
+            other.segment,
+            other.shards_per_partition,
+        )
+    }
+}
+
+impl Default for TenantConfig {
+    fn default() -> Self {
+        Self {
+            header: String::new(),
+            required: false,
+            max_tracked: default_max_tracked_tenants(),
+        }
+    }
+}
+
+impl Default for DirectivesConfig {
+    fn default() -> Self {
+        Self {
+            no_cache: false,
+            no_store: default_honor_no_store(),
+            only_if_cached: default_honor_only_if_cached(),
+            bypass_header: String::new(),
+        }
+    }
+}
+
+impl Default for BypassConfig {
+    fn default() -> Self {
+        Self {
+            headers: default_bypass_headers(),
+            cookies: Vec::new(),
+            query_params: Vec::new(),
+            mode: BypassMode::default(),
+        }
+    }
+}
+
+impl Default for RefreshAheadConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_hits: default_refresh_min_hits(),
+            window_percent: default_refresh_window_percent(),
+            routes: Vec::new(),
+        }
+    }
+}
+
+impl Default for StoredHeadersConfig {
+    fn default() -> Self {
+        Self {
+            allow: Vec::new(),
+            deny: default_denied_headers(),
+            keep: default_kept_headers(),
+        }
+    }
+}
+
+impl Default for AdaptiveTtlConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_ttl_seconds: default_min_ttl(),
+            max_ttl_seconds: default_max_ttl(),
+        }
+    }
+}
+
+impl Default for HealthCheckConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: default_health_path(),
+            interval_ms: default_health_interval_ms(),
+            timeout_ms: default_health_timeout_ms(),
+            unhealthy_threshold: default_unhealthy_threshold(),
+            healthy_threshold: default_healthy_threshold(),
+        }
+    }
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window_ms: default_circuit_window_ms(),
+            error_rate_threshold: default_error_rate_threshold(),
+            min_requests: default_circuit_min_requests(),
+            cool_down_ms: default_cool_down_ms(),
+            half_open_requests: default_half_open_requests(),
+            serve_stale: default_serve_stale(),
+        }
+    }
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 0,
+            backoff_ms: default_retry_backoff_ms(),
+            max_backoff_ms: default_retry_max_backoff_ms(),
+            retry_on: default_retry_on(),
+            per_try_timeout_ms: 0,
+        }
+    }
+}
+
+impl Default for ForwardedConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_forwarded_enabled(),
+            rfc7239: false,
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+impl Default for UpstreamConcurrencyConfig {
+    fn default() -> Self {
+        Self {
+            max_in_flight: 0,
+            queue_timeout_ms: default_queue_timeout_ms(),
+        }
+    }
+}
+
+impl Default for OutlierDetectionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_ms: default_outlier_interval_ms(),
+            consecutive_errors: default_consecutive_errors(),
+            error_rate_threshold: default_error_rate_threshold(),
+            latency_factor: default_latency_factor(),
+            min_requests: default_outlier_min_requests(),
+            base_ejection_ms: default_base_ejection_ms(),
+            max_ejection_percent: default_max_ejection_percent(),
+        }
+    }
+}
+
+impl Default for WarmupConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            urls: Vec::new(),
+            concurrency: default_warmup_concurrency(),
+            timeout_secs: default_warmup_timeout_secs(),
+        }
+    }
+}
+
+impl Default for SoakConfig {
+    fn default() -> Self {
+        Self {
+            interval_ms: default_soak_interval_ms(),
+        }
+    }
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        Self {
+            peers: Vec::new(),
+            timeout_ms: default_cluster_timeout_ms(),
+            token: None,
+            nodes: Vec::new(),
+            node_url: None,
+            broadcast_invalidations: false,
+            discover: None,
+        }
+    }
+}
+
+impl Default for LoadSheddingConfig {
+    fn default() -> Self {
+        Self {
+            max_in_flight: 0,
+            retry_after_secs: default_retry_after_secs(),
+        }
+    }
+}
+
+impl Default for MetricsExportConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            max_bytes: default_export_max_bytes(),
+            max_files: default_export_max_files(),
         }
     }
 }
@@ -99,55 +1354,484 @@ impl Default for RespConfig {
         Self {
             enabled: default_resp_enabled(),
             listen_addr: default_resp_addr(),
+            password: None,
+            read_only_password: None,
+            max_connections: default_resp_max_connections(),
+            max_frame_bytes: default_resp_max_frame_bytes(),
+            idle_timeout_secs: 0,
         }
     }
 }
 
-/// Compare old and new config, apply safe changes, reject unsafe ones.
+/// One effect of moving the running process from one config to another.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum ConfigChange {
+    /// Default TTL swapped atomically; cached entries kept.
+    TtlUpdate { old: u64, new: u64 },
+    /// Body size limit swapped atomically; cached entries kept.
+    MaxBodySizeUpdate { old: usize, new: usize },
+    /// Memory bound swapped atomically; caches over the new one evict.
+    MaxMemoryUpdate { old: usize, new: usize },
+    /// Eviction low watermarks swapped; used from the next full shard on.
+    LowWatermarkUpdate {
+        old: BTreeMap<String, f64>,
+        new: BTreeMap<String, f64>,
+    },
+    /// Cache resized in place; entries kept, shrinks evict.
+    Resize { old: usize, new: usize },
+    /// A listener's certificate, key or client CA paths changed; the files
+    /// are loaded for new connections.
+    TlsReload { listener: String },
+    /// Upstream backends swapped for new requests; ones under way finish
+    /// where they started.
+    UpstreamUpdate {
+        old: Vec<BackendConfig>,
+        new: Vec<BackendConfig>,
+    },
+    /// Upstream request timeout swapped for new requests. The connect
+    /// timeout of new connections keeps its startup value.
+    UpstreamTimeoutUpdate { old: u64, new: u64 },
+    /// Cache rebuilt with new policies; all entries dropped.
+    PolicyRebuild {
+        old_primary: String,
+        new_primary: String,
+        old_comparison: Vec<String>,
+        new_comparison: Vec<String>,
+    },
+    /// A change that would be unsafe to apply and is skipped.
+    Rejected { field: String, reason: String },
+    /// A change that hot reload doesn't pick up.
+    Ignored { field: String, reason: String },
+}
+
+/// Work out what `diff_and_apply` would do for `old` → `new`, without
+/// touching anything. Used by hot reload and by `/api/config/validate`.
 ///
-/// - TTL changed → atomic update (no cache data loss)
+/// - TTL or max body size changed → atomic update (no cache data loss)
+/// - Max memory changed → atomic update (a lower bound evicts)
+/// - Low watermarks changed → atomic update
+/// - Upstream URLs or timeout changed → swapped for new requests
+/// - Capacity changed → resize in place (entries kept; shrinks evict)
 /// - Eviction policy changed → rebuild cache (data cleared)
-/// - Capacity changed → WARN log, ignore (restart required)
-pub fn diff_and_apply(old: &Config, new: &Config, cache_swap: &ArcSwap<CacheLayer>) {
-    // Capacity changed → WARN, ignore
+/// - `[shutdown]`, `coalesce_misses`, `[cache.bypass]`, `[cache.key]`,
+///   `[cache.writes]`, `[cache.refresh_ahead]`, `[cache.tenant]`,
+///   `[cache.directives]` → nothing
+///   to do, they're read on use
+/// - Anything else → reported as ignored until restart
+pub fn plan_changes(old: &Config, new: &Config) -> Vec<ConfigChange> {
+    let mut changes = Vec::new();
+
     if old.cache.capacity != new.cache.capacity {
-        tracing::warn!(
-            old = old.cache.capacity,
-            new = new.cache.capacity,
-            "capacity change detected — ignoring. Restart to resize cache safely"
-        );
+        if new.cache.capacity == 0 {
+            changes.push(ConfigChange::Rejected {
+                field: "cache.capacity".into(),
+                reason: "capacity must be > 0".into(),
+            });
+        } else {
+            changes.push(ConfigChange::Resize {
+                old: old.cache.capacity,
+                new: new.cache.capacity,
+            });
+        }
     }
 
-    // TTL changed → atomic update (no cache loss)
     if old.cache.default_ttl_seconds != new.cache.default_ttl_seconds {
-        cache_swap
-            .load()
-            .set_default_ttl(new.cache.default_ttl_seconds);
-        tracing::info!(
-            old = old.cache.default_ttl_seconds,
-            new = new.cache.default_ttl_seconds,
-            "config reloaded: TTL changed"
-        );
+        changes.push(ConfigChange::TtlUpdate {
+            old: old.cache.default_ttl_seconds,
+            new: new.cache.default_ttl_seconds,
+        });
     }
 
-    // Eviction policy changed → rebuild cache (data cleared)
-    if old.cache.eviction_policy != new.cache.eviction_policy
-        || old.cache.comparison_policy != new.cache.comparison_policy
-    {
-        let new_cache = CacheLayer::new(
-            &new.cache.eviction_policy,
-            new.cache.comparison_policy.as_deref(),
-            old.cache.capacity, // Use OLD capacity (immutable)
-            Duration::from_secs(new.cache.default_ttl_seconds),
-            new.cache.max_body_size_bytes,
-        );
-        cache_swap.store(Arc::new(new_cache));
-        tracing::info!(
-            old_policy = %old.cache.eviction_policy,
-            new_policy = %new.cache.eviction_policy,
-            "config reloaded: eviction policy changed. Cache cleared."
-        );
+    if old.cache.max_body_size_bytes != new.cache.max_body_size_bytes {
+        changes.push(ConfigChange::MaxBodySizeUpdate {
+            old: old.cache.max_body_size_bytes,
+            new: new.cache.max_body_size_bytes,
+        });
+    }
+
+    if old.cache.max_memory_bytes != new.cache.max_memory_bytes {
+        changes.push(ConfigChange::MaxMemoryUpdate {
+            old: old.cache.max_memory_bytes,
+            new: new.cache.max_memory_bytes,
+        });
+    }
+
+    if old.cache.low_watermark != new.cache.low_watermark {
+        changes.push(ConfigChange::LowWatermarkUpdate {
+            old: old.cache.low_watermark.clone(),
+            new: new.cache.low_watermark.clone(),
+        });
+    }
+
+    let policy_changed = old.cache.eviction_policy != new.cache.eviction_policy
+        || old.cache.comparison_policy != new.cache.comparison_policy;
+    let unknown = std::iter::once(&new.cache.eviction_policy)
+        .chain(&new.cache.comparison_policy)
+        .find(|p| !POLICIES.contains(&p.as_str()));
+    if policy_changed {
+        match unknown {
+            Some(policy) => {
+                let field = if *policy == new.cache.eviction_policy {
+                    "cache.eviction_policy"
+                } else {
+                    "cache.comparison_policy"
+                };
+                changes.push(ConfigChange::Rejected {
+                    field: field.into(),
+                    reason: format!("unknown policy {policy:?}, use one of {POLICIES:?}"),
+                });
+            }
+            None => changes.push(ConfigChange::PolicyRebuild {
+                old_primary: old.cache.eviction_policy.clone(),
+                new_primary: new.cache.eviction_policy.clone(),
+                old_comparison: old.cache.comparison_policy.clone(),
+                new_comparison: new.cache.comparison_policy.clone(),
+            }),
+        }
+    }
+    let rebuild = policy_changed && unknown.is_none();
+
+    // These only take effect when the cache layer is rebuilt
+    if !rebuild {
+        let rebuild_only = [
+            (
+                "cache.hot_keys_top_k",
+                old.cache.hot_keys_top_k != new.cache.hot_keys_top_k,
+            ),
+            (
+                "cache.adaptive_ttl",
+                old.cache.adaptive_ttl != new.cache.adaptive_ttl,
+            ),
+            ("cache.quotas", old.cache.quotas != new.cache.quotas),
+            (
+                "cache.partitioning",
+                !old.cache.partitioning.same_layout(&new.cache.partitioning),
+            ),
+        ];
+        for (field, changed) in rebuild_only {
+            if changed {
+                changes.push(ConfigChange::Ignored {
+                    field: field.into(),
+                    reason: "applied on the next policy change or restart".into(),
+                });
+            }
+        }
+    }
+
+    let (old_backends, new_backends) = (
+        old.upstream.effective_backends(),
+        new.upstream.effective_backends(),
+    );
+    if old_backends != new_backends {
+        changes.push(ConfigChange::UpstreamUpdate {
+            old: old_backends,
+            new: new_backends,
+        });
+    }
+    if old.upstream.timeout_ms != new.upstream.timeout_ms {
+        changes.push(ConfigChange::UpstreamTimeoutUpdate {
+            old: old.upstream.timeout_ms,
+            new: new.upstream.timeout_ms,
+        });
+    }
+
+    // TLS files can be swapped live; adding or removing TLS needs a restart
+    let (old_listeners, new_listeners) = (old.effective_listeners(), new.effective_listeners());
+    for listener in &new_listeners {
+        let Some(before) = old_listeners.iter().find(|l| l.addr == listener.addr) else {
+            continue;
+        };
+        if let (Some(old_tls), Some(new_tls)) = (&before.tls, &listener.tls) {
+            if old_tls != new_tls {
+                changes.push(ConfigChange::TlsReload {
+                    listener: listener.addr.clone(),
+                });
+            }
+        }
+    }
+    let tls_present = |l: &ListenerConfig| ListenerConfig {
+        tls: l.tls.as_ref().map(|_| TlsConfig {
+            cert_path: String::new(),
+            key_path: String::new(),
+            client_ca_path: None,
+        }),
+        ..l.clone()
+    };
+    let restart_only = [
+        (
+            "server",
+            ServerConfig {
+                tls: None,
+                ..old.server.clone()
+            } != ServerConfig {
+                tls: None,
+                ..new.server.clone()
+            } || old.server.tls.is_some() != new.server.tls.is_some(),
+        ),
+        (
+            "listeners",
+            !old.listeners
+                .iter()
+                .map(tls_present)
+                .eq(new.listeners.iter().map(tls_present)),
+        ),
+        (
+            "upstream",
+            UpstreamConfig {
+                url: String::new(),
+                host: None,
+                host_header: HostHeader::default(),
+                timeout_ms: 0,
+                backends: Vec::new(),
+                retry: RetryConfig::default(),
+                forwarded: ForwardedConfig::default(),
+                ..old.upstream.clone()
+            } != UpstreamConfig {
+                url: String::new(),
+                host: None,
+                host_header: HostHeader::default(),
+                timeout_ms: 0,
+                backends: Vec::new(),
+                retry: RetryConfig::default(),
+                forwarded: ForwardedConfig::default(),
+                ..new.upstream.clone()
+            },
+        ),
+        (
+            "resp",
+            old.resp.enabled != new.resp.enabled || old.resp.listen_addr != new.resp.listen_addr,
+        ),
+        ("cache.plugins", old.cache.plugins != new.cache.plugins),
+        ("warmup", old.warmup != new.warmup),
+        ("soak", old.soak != new.soak),
+        ("metrics_export", old.metrics_export != new.metrics_export),
+    ];
+    for (field, changed) in restart_only {
+        if changed {
+            changes.push(ConfigChange::Ignored {
+                field: field.into(),
+                reason: "restart required".into(),
+            });
+        }
+    }
+
+    changes
+}
+
+/// `plan_changes` against the running `cache`. While it's read-only, the
+/// changes that would evict or drop entries (capacity, memory bound, low
+/// watermarks, policies) are rejected, as `POST /api/cache/resize` and
+/// `POST /api/policy` refuse them.
+pub fn plan_changes_for(old: &Config, new: &Config, cache: &CacheLayer) -> Vec<ConfigChange> {
+    let mut changes = plan_changes(old, new);
+    if !cache.is_read_only() {
+        return changes;
+    }
+    for change in &mut changes {
+        let field = match change {
+            ConfigChange::Resize { .. } => "cache.capacity",
+            ConfigChange::MaxMemoryUpdate { .. } => "cache.max_memory_bytes",
+            ConfigChange::LowWatermarkUpdate { .. } => "cache.low_watermark",
+            ConfigChange::PolicyRebuild {
+                old_primary,
+                new_primary,
+                ..
+            } if old_primary == new_primary => "cache.comparison_policy",
+            ConfigChange::PolicyRebuild { .. } => "cache.eviction_policy",
+            _ => continue,
+        };
+        *change = ConfigChange::Rejected {
+            field: field.into(),
+            reason: "cache is read-only".into(),
+        };
     }
+    changes
+}
+
+/// Compare old and new config and apply every safe change (see
+/// `plan_changes_for`). Returns the config now in effect (see
+/// `apply_changes`).
+///
+/// TLS files are re-read for every TLS listener on each reload, changed or
+/// not, so certificates rotated in place take effect too.
+pub fn diff_and_apply(
+    old: &Config,
+    new: &Config,
+    cache_swap: &ArcSwap<CacheLayer>,
+    upstreams: &UpstreamPool,
+    tls: &TlsReloader,
+) -> Config {
+    let applied = apply_changes(old, new, cache_swap, upstreams);
+    tls.reload(&new.effective_listeners());
+    applied
+}
+
+/// Apply every safe change in `old` → `new` to the cache and upstreams,
+/// leaving TLS alone. `diff_and_apply` without the certificate reload, for
+/// callers that don't touch listeners (e.g. RESP `CONFIG SET`).
+///
+/// Returns `new` with the cache settings that weren't applied (rejected,
+/// or waiting for a rebuild) put back to `old`'s values. Store that as the
+/// current config, so the next reload diffs against what is running and
+/// tries them again.
+pub fn apply_changes(
+    old: &Config,
+    new: &Config,
+    cache_swap: &ArcSwap<CacheLayer>,
+    upstreams: &UpstreamPool,
+) -> Config {
+    let mut applied = new.clone();
+    for change in plan_changes_for(old, new, &cache_swap.load()) {
+        apply_change(&change, old, new, cache_swap, upstreams);
+        if let ConfigChange::Rejected { field, .. } | ConfigChange::Ignored { field, .. } = &change
+        {
+            keep_old(field, &old.cache, &mut applied.cache);
+        }
+    }
+    applied
+}
+
+/// Undo `applied`'s edit of a cache setting that wasn't applied.
+fn keep_old(field: &str, old: &CacheConfig, applied: &mut CacheConfig) {
+    match field {
+        "cache.capacity" => applied.capacity = old.capacity,
+        "cache.max_memory_bytes" => applied.max_memory_bytes = old.max_memory_bytes,
+        "cache.low_watermark" => applied.low_watermark = old.low_watermark.clone(),
+        "cache.eviction_policy" | "cache.comparison_policy" => {
+            applied.eviction_policy = old.eviction_policy.clone();
+            applied.comparison_policy = old.comparison_policy.clone();
+            // No rebuild, so its settings wait for the next one too
+            for field in [
+                "cache.hot_keys_top_k",
+                "cache.adaptive_ttl",
+                "cache.quotas",
+                "cache.partitioning",
+            ] {
+                keep_old(field, old, applied);
+            }
+        }
+        "cache.hot_keys_top_k" => applied.hot_keys_top_k = old.hot_keys_top_k,
+        "cache.adaptive_ttl" => applied.adaptive_ttl = old.adaptive_ttl.clone(),
+        "cache.quotas" => applied.quotas = old.quotas.clone(),
+        "cache.partitioning" => {
+            applied.partitioning = PartitioningConfig {
+                skew_warn_share: applied.partitioning.skew_warn_share,
+                ..old.partitioning.clone()
+            }
+        }
+        _ => {}
+    }
+}
+
+fn apply_change(
+    change: &ConfigChange,
+    old: &Config,
+    new: &Config,
+    cache_swap: &ArcSwap<CacheLayer>,
+    upstreams: &UpstreamPool,
+) {
+    match change {
+        ConfigChange::Resize { old, new } => match cache_swap.load().resize(*new) {
+            Ok(()) => tracing::info!(old, new, "config reloaded: capacity changed"),
+            Err(e) => tracing::warn!(old, new, error = %e, "config reload: capacity not changed"),
+        },
+        ConfigChange::TtlUpdate { old, new } => {
+            cache_swap.load().set_default_ttl(*new);
+            tracing::info!(old, new, "config reloaded: TTL changed");
+        }
+        ConfigChange::MaxBodySizeUpdate { old, new } => {
+            cache_swap.load().set_max_body_size(*new);
+            tracing::info!(old, new, "config reloaded: max body size changed");
+        }
+        ConfigChange::MaxMemoryUpdate { old, new } => {
+            cache_swap.load().set_max_memory(*new);
+            tracing::info!(old, new, "config reloaded: max memory changed");
+        }
+        ConfigChange::LowWatermarkUpdate { old, new } => {
+            cache_swap.load().set_low_watermarks(new);
+            tracing::info!(
+                ?old,
+                ?new,
+                "config reloaded: eviction low watermarks changed"
+            );
+        }
+        ConfigChange::PolicyRebuild {
+            old_primary,
+            new_primary,
+            ..
+        } => {
+            // Everything the live layer has learned carries over (see
+            // `CacheLayer::rebuild`), as do its capacity, which may have been
+            // resized via the admin API, and its read-only flag.
+            let new_cache = cache_swap.load().rebuild(&old.cache, &new.cache);
+            cache_swap.store(Arc::new(new_cache));
+            tracing::info!(
+                old_policy = %old_primary,
+                new_policy = %new_primary,
+                "config reloaded: eviction policy changed. Cache cleared."
+            );
+        }
+        ConfigChange::UpstreamUpdate { new, .. } => {
+            upstreams.set_backends(new);
+            let urls: Vec<&str> = new.iter().map(|b| b.url.as_str()).collect();
+            tracing::info!(upstreams = ?urls, "config reloaded: upstreams changed");
+        }
+        ConfigChange::UpstreamTimeoutUpdate { old, new } => {
+            upstreams.set_request_timeout((*new > 0).then(|| Duration::from_millis(*new)));
+            tracing::info!(old, new, "config reloaded: upstream timeout changed");
+        }
+        ConfigChange::TlsReload { listener } => {
+            tracing::info!(listener = %listener, "config reloaded: TLS files changed");
+        }
+        ConfigChange::Rejected { field, reason } => {
+            tracing::warn!(field = %field, reason = %reason, "config change rejected");
+        }
+        ConfigChange::Ignored { field, reason } => {
+            tracing::warn!(field = %field, reason = %reason, "config change ignored");
+        }
+    }
+}
+
+/// A single name or a list of them, e.g. for `comparison_policy`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl From<OneOrMany> for Vec<String> {
+    fn from(value: OneOrMany) -> Self {
+        match value {
+            OneOrMany::One(name) => vec![name],
+            OneOrMany::Many(names) => names,
+        }
+    }
+}
+
+fn string_or_list<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    OneOrMany::deserialize(deserializer).map(Vec::from)
+}
+
+/// Addresses or CIDR ranges; a bare address is a range of one.
+fn ip_nets<'de, D>(deserializer: D) -> Result<Vec<IpNet>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|s| {
+            s.parse::<IpNet>()
+                .or_else(|_| s.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| {
+                    serde::de::Error::custom(format!("{s:?} isn't an IP address or CIDR range"))
+                })
+        })
+        .collect()
 }
 
 fn default_listen_addr() -> String {
@@ -156,9 +1840,88 @@ fn default_listen_addr() -> String {
 fn default_metrics_addr() -> String {
     "0.0.0.0:9090".to_string()
 }
+fn default_metrics_history_secs() -> u64 {
+    600
+}
+fn default_forwarded_enabled() -> bool {
+    true
+}
+fn default_http2() -> bool {
+    true
+}
 fn default_timeout_ms() -> u64 {
     5000
 }
+fn default_health_path() -> String {
+    "/".to_string()
+}
+fn default_health_interval_ms() -> u64 {
+    2000
+}
+fn default_health_timeout_ms() -> u64 {
+    1000
+}
+fn default_unhealthy_threshold() -> u32 {
+    3
+}
+fn default_healthy_threshold() -> u32 {
+    2
+}
+fn default_outlier_interval_ms() -> u64 {
+    10_000
+}
+fn default_consecutive_errors() -> u32 {
+    5
+}
+fn default_error_rate_threshold() -> f64 {
+    0.5
+}
+fn default_latency_factor() -> f64 {
+    3.0
+}
+fn default_outlier_min_requests() -> u64 {
+    20
+}
+fn default_base_ejection_ms() -> u64 {
+    30_000
+}
+fn default_max_ejection_percent() -> u32 {
+    50
+}
+fn default_circuit_window_ms() -> u64 {
+    10_000
+}
+fn default_circuit_min_requests() -> u64 {
+    20
+}
+fn default_cool_down_ms() -> u64 {
+    5_000
+}
+fn default_half_open_requests() -> u32 {
+    1
+}
+fn default_serve_stale() -> bool {
+    true
+}
+fn default_queue_timeout_ms() -> u64 {
+    1000
+}
+
+fn default_retry_backoff_ms() -> u64 {
+    25
+}
+fn default_retry_max_backoff_ms() -> u64 {
+    250
+}
+fn default_retry_on() -> Vec<u16> {
+    vec![502, 503, 504]
+}
+fn default_warmup_concurrency() -> usize {
+    16
+}
+fn default_warmup_timeout_secs() -> u64 {
+    60
+}
 fn default_capacity() -> usize {
     10000
 }
@@ -171,9 +1934,216 @@ fn default_max_body_size() -> usize {
 fn default_eviction_policy() -> String {
     "sieve".to_string()
 }
+fn default_min_ttl() -> u64 {
+    10
+}
+fn default_max_ttl() -> u64 {
+    3600
+}
+fn default_bypass_headers() -> Vec<String> {
+    vec!["authorization".to_string()]
+}
+fn default_denied_headers() -> Vec<String> {
+    vec!["set-cookie".to_string()]
+}
+fn default_kept_headers() -> Vec<String> {
+    vec!["content-type".to_string(), "etag".to_string()]
+}
+fn default_partition_separator() -> char {
+    '/'
+}
+fn default_partition_segment() -> usize {
+    1
+}
+fn default_shards_per_partition() -> usize {
+    4
+}
+fn default_skew_warn_share() -> f64 {
+    0.5
+}
+fn default_refresh_min_hits() -> u64 {
+    10
+}
+fn default_refresh_window_percent() -> f64 {
+    20.0
+}
+fn default_hot_keys_top_k() -> usize {
+    100
+}
+fn default_plugin_fuel() -> u64 {
+    1_000_000
+}
+fn default_plugin_max_memory_bytes() -> usize {
+    16 << 20
+}
+fn default_max_tracked_tenants() -> usize {
+    100
+}
+fn default_coalesce_misses() -> bool {
+    true
+}
+
+fn default_honor_no_store() -> bool {
+    true
+}
+
+fn default_honor_only_if_cached() -> bool {
+    true
+}
+fn default_soak_interval_ms() -> u64 {
+    10_000
+}
+fn default_cluster_timeout_ms() -> u64 {
+    1000
+}
+fn default_retry_after_secs() -> u64 {
+    1
+}
+fn default_export_max_bytes() -> u64 {
+    100 * 1024 * 1024
+}
+fn default_export_max_files() -> u32 {
+    5
+}
 fn default_resp_enabled() -> bool {
     true
 }
 fn default_resp_addr() -> String {
     "0.0.0.0:6379".to_string()
 }
+fn default_resp_max_connections() -> usize {
+    10_000
+}
+fn default_resp_max_frame_bytes() -> usize {
+    16 * 1024 * 1024 // 16 MB
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache_layer::CacheMode;
+    use bytes::Bytes;
+    use colander_cache::traits::InsertSource;
+
+    fn config(cache: &str) -> Config {
+        Config::parse(&format!(
+            "[upstream]\nurl = \"http://127.0.0.1:3000\"\n[cache]\n{cache}"
+        ))
+        .unwrap()
+    }
+
+    fn running(config: &Config) -> (ArcSwap<CacheLayer>, UpstreamPool) {
+        let cache = CacheLayer::from_config(&config.cache, config.cache.capacity);
+        (
+            ArcSwap::from_pointee(cache),
+            UpstreamPool::new(&config.upstream.effective_backends()),
+        )
+    }
+
+    #[test]
+    fn only_directives_that_reach_the_origin_are_opt_in() {
+        use crate::directives::Directives;
+        use axum::http::{HeaderMap, HeaderValue};
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "cache-control",
+            HeaderValue::from_static("no-cache, no-store, only-if-cached"),
+        );
+        headers.insert("x-bypass", HeaderValue::from_static("1"));
+
+        let defaults = config("[cache.directives]").cache.directives;
+        assert_eq!(defaults, DirectivesConfig::default());
+        let honored = Directives::from_request(&defaults, &headers);
+        assert_eq!(
+            honored,
+            Directives {
+                no_store: true,
+                only_if_cached: true,
+                ..Directives::default()
+            }
+        );
+
+        let all = config(
+            "[cache.directives]
+no_cache = true
+bypass_header = \"x-bypass\"",
+        )
+        .cache
+        .directives;
+        let honored = Directives::from_request(&all, &headers);
+        assert!(honored.no_cache && honored.no_store && honored.only_if_cached && honored.bypass);
+    }
+
+    #[test]
+    fn read_only_cache_keeps_its_shape_across_a_reload() {
+        let old = config("capacity = 6400\neviction_policy = \"sieve\"");
+        let new = config(
+            "capacity = 640\neviction_policy = \"lru\"\nmax_memory_bytes = 4096\n\
+             default_ttl_seconds = 5",
+        );
+        let (cache, upstreams) = running(&old);
+        cache.load().set_read_only(true);
+        let before = Arc::clone(&cache.load());
+
+        let applied = apply_changes(&old, &new, &cache, &upstreams);
+
+        let after = cache.load();
+        assert!(Arc::ptr_eq(&before, &after));
+        assert_eq!(after.capacity(), 6400);
+        assert_eq!(after.max_memory(), 0);
+        assert_eq!(after.default_ttl(), Duration::from_secs(5));
+        assert_eq!(applied.cache.capacity, 6400);
+        assert_eq!(applied.cache.eviction_policy, "sieve");
+        assert_eq!(applied.cache.max_memory_bytes, 0);
+        assert_eq!(applied.cache.default_ttl_seconds, 5);
+
+        // Writable again, the same file applies what was refused
+        after.set_read_only(false);
+        let applied = apply_changes(&applied, &new, &cache, &upstreams);
+        assert_eq!(cache.load().capacity(), 640);
+        assert_eq!(applied.cache, new.cache);
+    }
+
+    #[test]
+    fn policy_rebuild_keeps_what_the_layer_learned() {
+        let old = config("eviction_policy = \"sieve\"\nhot_keys_top_k = 4");
+        let new = config("eviction_policy = \"lru\"\nhot_keys_top_k = 4");
+        let (cache, upstreams) = running(&old);
+        let before = cache.load_full();
+        before
+            .insert_raw(
+                "GET:/a".into(),
+                Bytes::from_static(b"x"),
+                None,
+                InsertSource::Http,
+            )
+            .unwrap();
+        before.get("GET:/a");
+        before.set_mode(CacheMode::Bench);
+
+        apply_changes(&old, &new, &cache, &upstreams);
+
+        let after = cache.load();
+        assert!(!Arc::ptr_eq(&before, &after));
+        assert_eq!(after.primary_name(), "LRU");
+        assert_eq!(after.hot_keys(4), before.hot_keys(4));
+        assert_eq!(after.inserts_by_source(), before.inserts_by_source());
+        assert!(!after.is_demo_mode());
+    }
+
+    #[test]
+    fn rebuild_only_settings_wait_for_the_next_rebuild() {
+        let old = config("eviction_policy = \"sieve\"\nhot_keys_top_k = 0");
+        let edited = config("eviction_policy = \"sieve\"\nhot_keys_top_k = 4");
+        let (cache, upstreams) = running(&old);
+
+        let applied = apply_changes(&old, &edited, &cache, &upstreams);
+        assert_eq!(applied.cache.hot_keys_top_k, 0);
+
+        let new = config("eviction_policy = \"lru\"\nhot_keys_top_k = 4");
+        apply_changes(&applied, &new, &cache, &upstreams);
+        cache.load().get("GET:/a");
+        assert_eq!(cache.load().hot_keys(4), [("GET:/a".to_string(), 1)]);
+    }
+}
@@ -0,0 +1,33 @@
+use axum::http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+
+/// The built-in dashboard, compiled into the binary so it needs no
+/// separate frontend build. The React app in `dashboard/` has more controls.
+const INDEX_HTML: &[u8] = include_bytes!("../assets/dashboard/index.html");
+const APP_JS: &[u8] = include_bytes!("../assets/dashboard/app.js");
+
+/// `/` and its script. Only mounted on listeners that don't also proxy,
+/// where `/` belongs to the upstream.
+pub fn router<S: Clone + Send + Sync + 'static>() -> Router<S> {
+    Router::new()
+        .route("/", get(index))
+        .route("/dashboard/app.js", get(app_js))
+}
+
+async fn index() -> impl IntoResponse {
+    asset("text/html; charset=utf-8", INDEX_HTML)
+}
+
+async fn app_js() -> impl IntoResponse {
+    asset("text/javascript; charset=utf-8", APP_JS)
+}
+
+/// Revalidate every time, so an upgraded binary's dashboard shows up at once.
+fn asset(content_type: &'static str, body: &'static [u8]) -> impl IntoResponse {
+    (
+        [(CONTENT_TYPE, content_type), (CACHE_CONTROL, "no-cache")],
+        body,
+    )
+}
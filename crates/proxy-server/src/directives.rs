@@ -0,0 +1,66 @@
+use crate::config::DirectivesConfig;
+use axum::http::header::CACHE_CONTROL;
+use axum::http::HeaderMap;
+
+/// What a client asked of the cache for one request, per
+/// `[cache.directives]`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Directives {
+    /// `Cache-Control: no-cache`: don't answer from the cache, fetch from
+    /// upstream and store what comes back. Colander never revalidates, so
+    /// this is a full refetch.
+    pub no_cache: bool,
+    /// `Cache-Control: no-store`: a hit may still be served, but a miss
+    /// isn't stored.
+    pub no_store: bool,
+    /// `Cache-Control: only-if-cached`: a miss is answered `504` instead of
+    /// going upstream.
+    pub only_if_cached: bool,
+    /// The bypass header was `1`: neither read nor write the cache.
+    pub bypass: bool,
+}
+
+impl Directives {
+    /// The directives in `headers` that `config` honors. Requests without
+    /// a `Cache-Control` or bypass header cost two lookups.
+    pub fn from_request(config: &DirectivesConfig, headers: &HeaderMap) -> Self {
+        let mut directives = Directives::default();
+        if config.no_cache || config.no_store || config.only_if_cached {
+            let values = headers
+                .get_all(CACHE_CONTROL)
+                .iter()
+                .filter_map(|v| v.to_str().ok());
+            for directive in values.flat_map(|v| v.split(',')) {
+                // Only argument-less directives matter here
+                let name = directive.trim();
+                if name.eq_ignore_ascii_case("no-cache") {
+                    directives.no_cache = config.no_cache;
+                } else if name.eq_ignore_ascii_case("no-store") {
+                    directives.no_store = config.no_store;
+                } else if name.eq_ignore_ascii_case("only-if-cached") {
+                    directives.only_if_cached = config.only_if_cached;
+                }
+            }
+        }
+        if !config.bypass_header.is_empty() {
+            directives.bypass = headers
+                .get(config.bypass_header.as_str())
+                .is_some_and(|v| v.as_bytes().trim_ascii() == b"1");
+        }
+        directives
+    }
+
+    /// Count each directive present in `colander_request_directives_total`.
+    pub fn record(&self) {
+        let present = [
+            (self.no_cache, "no-cache"),
+            (self.no_store, "no-store"),
+            (self.only_if_cached, "only-if-cached"),
+            (self.bypass, "bypass"),
+        ];
+        for (_, name) in present.iter().filter(|(set, _)| *set) {
+            ::metrics::counter!("colander_request_directives_total", "directive" => *name)
+                .increment(1);
+        }
+    }
+}
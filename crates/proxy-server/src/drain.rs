@@ -0,0 +1,162 @@
+use crate::cache_layer::CacheLayer;
+use crate::config::ShutdownConfig;
+use crate::proxy::AppState;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How a drain should proceed.
+#[derive(Debug, Clone)]
+pub struct DrainOptions {
+    /// How long to keep serving after readiness is withdrawn.
+    pub grace: Duration,
+    /// Where to write the key snapshot, if anywhere.
+    pub snapshot_file: Option<PathBuf>,
+}
+
+impl DrainOptions {
+    pub fn from_config(config: &ShutdownConfig) -> Self {
+        Self {
+            grace: Duration::from_secs(config.grace_period_secs),
+            snapshot_file: config.snapshot_file.as_ref().map(PathBuf::from),
+        }
+    }
+}
+
+/// Mark the instance as draining. Returns false if a drain is already under way.
+///
+/// From here on `/readyz` fails, so load balancers stop sending new traffic,
+/// but every listener keeps serving until `drain` closes them.
+pub fn begin(state: &AppState) -> bool {
+    !state.draining.swap(true, Ordering::SeqCst)
+}
+
+/// Run a drain started with `begin`: wait out the grace period, write the
+/// snapshot, then fire the shutdown token. Listeners stop accepting and
+/// finish their in-flight requests; the process exits once they're done.
+///
+/// The grace period is cut short if something else fires the token first
+/// (e.g. a second SIGTERM).
+pub async fn drain(state: Arc<AppState>, options: DrainOptions) {
+    tracing::info!(
+        grace_secs = options.grace.as_secs(),
+        snapshot = ?options.snapshot_file,
+        "draining: readiness withdrawn"
+    );
+
+    tokio::select! {
+        _ = tokio::time::sleep(options.grace) => {}
+        _ = state.shutdown.cancelled() => {}
+    }
+
+    if let Some(path) = &options.snapshot_file {
+        let cache = state.cache.load_full();
+        match write_snapshot(cache, path).await {
+            Ok(keys) => tracing::info!(file = %path.display(), keys, "cache snapshot written"),
+            Err(e) => tracing::error!(error = %e, file = %path.display(), "cache snapshot failed"),
+        }
+    }
+
+    tracing::info!("draining: closing listeners");
+    state.shutdown.cancel();
+}
+
+/// Write every key still cached in the primary to `path` as a warm file
+/// (see `warmup::parse_warm_file`): the tracked hot keys first, hottest
+/// first, then the rest in no particular order. Returns how many keys were
+/// written.
+///
+/// Only HTTP `GET` keys are kept: they're the ones warmup can refetch.
+/// Per-user (`#user=`) and per-tenant (`#tenant=`) keys are left out too:
+/// warmup can't send their credentials or tenant header, so refetching
+/// them would fill the shared key instead.
+/// Bodies aren't stored; the next instance refills them from upstream, up
+/// to its `[warmup]` limits. The walk takes one shard lock at a time, off
+/// the async workers.
+pub async fn write_snapshot(cache: Arc<CacheLayer>, path: &Path) -> std::io::Result<usize> {
+    let (out, written) = tokio::task::spawn_blocking(move || snapshot_keys(&cache))
+        .await
+        .map_err(std::io::Error::other)?;
+    tokio::fs::write(path, out).await?;
+    Ok(written)
+}
+
+/// The warm file `write_snapshot` writes, and how many keys it lists.
+fn snapshot_keys(cache: &CacheLayer) -> (String, usize) {
+    const PAGE: usize = 1024;
+    let mut out = String::from("# colander key snapshot, hottest first\n");
+    let mut written = HashSet::new();
+    for (key, _) in cache.hot_keys(usize::MAX) {
+        if warmable(&key) && cache.peek(&key).is_some_and(|entry| !entry.is_expired()) {
+            let _ = writeln!(out, "{key}"); // writing to a String can't fail
+            written.insert(key);
+        }
+    }
+
+    let mut after = None;
+    loop {
+        let page = cache.scan("GET:", after.as_deref(), PAGE);
+        for (key, entry) in page.entries {
+            if warmable(&key) && !entry.is_expired() && !written.contains(&key) {
+                let _ = writeln!(out, "{key}");
+                written.insert(key);
+            }
+        }
+        match page.next {
+            Some(next) => after = Some(next),
+            None => break,
+        }
+    }
+    (out, written.len())
+}
+
+/// A plain `GET` key: warmup refetching its path fills this very key.
+fn warmable(key: &str) -> bool {
+    key.starts_with("GET:") && !key.contains('#')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::warmup::parse_warm_file;
+    use bytes::Bytes;
+    use colander_cache::traits::InsertSource;
+
+    #[test]
+    fn snapshot_lists_every_cached_get_key_hottest_first() {
+        let cache =
+            CacheLayer::new("sieve", &[], 6400, Duration::from_secs(60), 1 << 20).with_hot_keys(4);
+        for i in 0..500 {
+            let key = format!("GET:/items/{i}");
+            cache
+                .insert_raw(key, Bytes::from_static(b"x"), None, InsertSource::Http)
+                .unwrap();
+        }
+        cache
+            .insert_raw("session:1".into(), Bytes::new(), None, InsertSource::Resp)
+            .unwrap();
+        for key in ["GET:/items/1#user=abc", "GET:/items/2#tenant=acme"] {
+            cache
+                .insert_raw(key.into(), Bytes::new(), None, InsertSource::Http)
+                .unwrap();
+            for _ in 0..5 {
+                cache.get(key);
+            }
+        }
+        for _ in 0..3 {
+            cache.get("GET:/items/42");
+        }
+        cache.get("GET:/items/7");
+
+        let (out, written) = snapshot_keys(&cache);
+        let paths = parse_warm_file(&out);
+        assert_eq!(written, 500);
+        assert_eq!(paths.len(), 500);
+        assert_eq!(paths[..2], ["/items/42", "/items/7"]);
+        assert!(!out.contains("session:1"));
+        assert!(!out.contains("#user=") && !out.contains("#tenant="));
+    }
+}
@@ -0,0 +1,244 @@
+use crate::cache_layer::CacheLayer;
+use crate::metrics::{read_only_conflict, MetricsState};
+use axum::body::Body;
+use axum::extract::{Query, State};
+use axum::http::header::CONTENT_TYPE;
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use bytes::{Bytes, BytesMut};
+use colander_cache::traits::{CachedResponse, InsertSource};
+use futures_util::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Entries read from the cache per chunk of a dump.
+const DUMP_PAGE: usize = 256;
+
+/// Room a restore line gets beyond the base64 of the largest cacheable body,
+/// for its key, headers and the rest.
+const LINE_SLACK: usize = 1 << 20;
+
+/// One cache entry, one line of `/api/cache/dump` NDJSON.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DumpRecord {
+    pub key: String,
+    /// 0 for values written over RESP or memcached.
+    pub status: u16,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    /// The body, base64 with padding.
+    pub body: String,
+    /// Freshness lifetime, counted from when the response was generated.
+    pub ttl_seconds: u64,
+    /// Age at the time of the dump.
+    #[serde(default)]
+    pub age_seconds: u64,
+    /// Never to be served stale (see `CachedResponse::must_revalidate`).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub must_revalidate: bool,
+}
+
+impl DumpRecord {
+    fn new(key: String, entry: &CachedResponse) -> Self {
+        Self {
+            key,
+            status: entry.status,
+            headers: entry
+                .headers
+                .iter()
+                .map(|(k, v)| {
+                    let value = String::from_utf8_lossy(v.as_bytes()).into_owned();
+                    (k.as_str().to_string(), value)
+                })
+                .collect(),
+            body: BASE64.encode(&entry.body),
+            ttl_seconds: entry.ttl.as_secs(),
+            age_seconds: entry.age().as_secs(),
+            must_revalidate: entry.must_revalidate,
+        }
+    }
+
+    /// The entry to insert, tagged as restored, with the age it was dumped
+    /// at so it expires when it would have.
+    fn into_entry(self) -> Result<(String, CachedResponse), String> {
+        let body = BASE64
+            .decode(&self.body)
+            .map_err(|e| format!("body isn't base64: {e}"))?;
+        let mut headers = HeaderMap::with_capacity(self.headers.len());
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| format!("invalid header name {name:?}"))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|_| format!("invalid value for header {name}"))?;
+            headers.append(name, value);
+        }
+        let entry = CachedResponse {
+            status: self.status,
+            headers,
+            body: Bytes::from(body),
+            inserted_at: Instant::now(),
+            ttl: Duration::from_secs(self.ttl_seconds),
+            hits: Default::default(),
+            source: InsertSource::Restore,
+            must_revalidate: self.must_revalidate,
+        }
+        .with_initial_age(Duration::from_secs(self.age_seconds));
+        Ok((self.key, entry))
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DumpQuery {
+    #[serde(default)]
+    pub prefix: String,
+}
+
+/// GET /api/cache/dump?prefix=... — stream the primary cache's unexpired
+/// entries as NDJSON `DumpRecord`s, a page at a time, without counting hits
+/// or affecting eviction. Entries written while the dump runs may or may
+/// not be in it.
+pub async fn dump_handler(
+    State(state): State<MetricsState>,
+    Query(query): Query<DumpQuery>,
+) -> impl IntoResponse {
+    let cache = state.app.cache.load_full();
+    let pages = stream::unfold(Some(None), move |cursor: Option<Option<String>>| {
+        let cache = Arc::clone(&cache);
+        let prefix = query.prefix.clone();
+        async move {
+            let after = cursor?;
+            let page = cache.scan(&prefix, after.as_deref(), DUMP_PAGE);
+            let chunk = dump_page(&page.entries);
+            let next = page.next.map(Some);
+            Some((Ok::<_, Infallible>(chunk), next))
+        }
+    });
+    (
+        [(CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(pages),
+    )
+}
+
+/// `entries` as NDJSON lines, leaving out the expired.
+fn dump_page(entries: &[(String, Arc<CachedResponse>)]) -> Bytes {
+    let mut out = Vec::new();
+    for (key, entry) in entries.iter().filter(|(_, e)| !e.is_expired()) {
+        let record = DumpRecord::new(key.clone(), entry);
+        if serde_json::to_writer(&mut out, &record).is_ok() {
+            out.push(b'\n');
+        }
+    }
+    Bytes::from(out)
+}
+
+/// What `/api/cache/restore` did with the lines it was sent.
+#[derive(Debug, Default, Serialize)]
+pub struct RestoreReport {
+    pub restored: u64,
+    /// Entries already past their lifetime.
+    pub expired: u64,
+    /// Bodies over `max_body_size_bytes`, also counted as oversize
+    /// rejections.
+    pub too_large: u64,
+    /// Lines that aren't a `DumpRecord`.
+    pub invalid: u64,
+    /// The first invalid line and what was wrong with it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_error: Option<String>,
+}
+
+impl RestoreReport {
+    fn invalid(&mut self, line_no: usize, error: String) {
+        self.invalid += 1;
+        self.first_error
+            .get_or_insert_with(|| format!("line {line_no}: {error}"));
+    }
+}
+
+/// POST /api/cache/restore — insert the entries of a `/api/cache/dump`
+/// body. The body is read a line at a time, so a dump of any size can be
+/// sent. Entries keep their remaining lifetime but not their hit counts or
+/// place in eviction order.
+pub async fn restore_handler(State(state): State<MetricsState>, body: Body) -> Response {
+    let cache = state.app.cache.load_full();
+    if cache.is_read_only() {
+        return read_only_conflict().into_response();
+    }
+    let max_line = cache.max_body_size().saturating_mul(4) / 3 + LINE_SLACK;
+
+    let mut report = RestoreReport::default();
+    let mut buf = BytesMut::new();
+    let mut line_no = 0;
+    let mut body = body.into_data_stream();
+    loop {
+        let chunk = match body.next().await {
+            Some(Ok(chunk)) => chunk,
+            Some(Err(e)) => return bad_request(format!("reading body: {e}")),
+            None => break,
+        };
+        buf.extend_from_slice(&chunk);
+        while let Some(end) = buf.iter().position(|&b| b == b'\n') {
+            let line = buf.split_to(end + 1);
+            line_no += 1;
+            restore_line(&cache, &line[..end], line_no, &mut report);
+        }
+        if buf.len() > max_line {
+            return bad_request(format!(
+                "line {} is longer than {max_line} bytes",
+                line_no + 1
+            ));
+        }
+    }
+    if !buf.is_empty() {
+        restore_line(&cache, &buf, line_no + 1, &mut report);
+    }
+
+    tracing::info!(
+        restored = report.restored,
+        expired = report.expired,
+        too_large = report.too_large,
+        invalid = report.invalid,
+        "cache restored from dump"
+    );
+    Json(report).into_response()
+}
+
+fn restore_line(cache: &CacheLayer, line: &[u8], line_no: usize, report: &mut RestoreReport) {
+    if line.trim_ascii().is_empty() {
+        return;
+    }
+    let record = match serde_json::from_slice::<DumpRecord>(line) {
+        Ok(record) => record,
+        Err(e) => return report.invalid(line_no, e.to_string()),
+    };
+    // Checked here rather than on the entry: a process up for less than
+    // the age can't backdate it that far
+    if record.age_seconds >= record.ttl_seconds {
+        report.expired += 1;
+        return;
+    }
+    let (key, entry) = match record.into_entry() {
+        Ok(parsed) => parsed,
+        Err(e) => return report.invalid(line_no, e),
+    };
+    if entry.body.len() > cache.max_body_size() {
+        report.too_large += 1;
+    } else {
+        report.restored += 1;
+    }
+    // Oversize bodies are turned away (and counted) by the cache layer
+    cache.insert(key, entry);
+}
+
+fn bad_request(error: String) -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(serde_json::json!({ "error": error })),
+    )
+        .into_response()
+}
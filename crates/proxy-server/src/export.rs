@@ -0,0 +1,123 @@
+use crate::config::MetricsExportConfig;
+use crate::metrics::MetricsSnapshot;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+/// Appends lines to a file, rotating it by size like logrotate: `<path>.1`
+/// is the newest rotated file and `<path>.<max_files>` the oldest kept.
+pub struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    written: u64,
+    max_bytes: u64,
+    max_files: u32,
+}
+
+impl RotatingFile {
+    /// Open `path` for appending, creating it and its directory if needed.
+    pub fn open(path: &Path, max_bytes: u64, max_files: u32) -> io::Result<Self> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            written,
+            max_bytes,
+            max_files,
+        })
+    }
+
+    /// Write one line (including its `\n`), rotating first if it would take
+    /// the file past `max_bytes`. Lines are never split across files.
+    pub fn append(&mut self, line: &[u8]) -> io::Result<()> {
+        if self.max_bytes > 0
+            && self.written > 0
+            && self.written + line.len() as u64 > self.max_bytes
+        {
+            self.rotate()?;
+        }
+        self.file.write_all(line)?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let numbered = |n: u32| {
+            let mut name = self.path.clone().into_os_string();
+            name.push(format!(".{n}"));
+            PathBuf::from(name)
+        };
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..self.max_files).rev() {
+                match fs::rename(numbered(n), numbered(n + 1)) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => {}
+                }
+            }
+            fs::rename(&self.path, numbered(1))?;
+        }
+        *self = Self::open(&self.path, self.max_bytes, self.max_files)?;
+        Ok(())
+    }
+}
+
+/// Write every metrics snapshot broadcast on `tx` to `[metrics_export] path`
+/// as one JSON object per line, on a thread of its own. Does nothing when no
+/// path is configured.
+///
+/// Fails if the file can't be opened, so a benchmark run doesn't go ahead
+/// without the artifact it was meant to produce. The thread stops once
+/// `shutdown` fires.
+pub fn spawn_metrics_export(
+    config: &MetricsExportConfig,
+    tx: &broadcast::Sender<MetricsSnapshot>,
+    shutdown: CancellationToken,
+) -> Result<(), String> {
+    let Some(path) = &config.path else {
+        return Ok(());
+    };
+    let mut out = RotatingFile::open(Path::new(path), config.max_bytes, config.max_files)
+        .map_err(|e| format!("failed to open metrics export file {path}: {e}"))?;
+    let mut rx = tx.subscribe();
+    let path = path.clone();
+    tracing::info!(path = %path, max_bytes = config.max_bytes, max_files = config.max_files, "metrics export enabled");
+
+    // Writes stay on this thread; the runtime only wakes it up
+    let runtime = tokio::runtime::Handle::current();
+    std::thread::Builder::new()
+        .name("metrics-export".into())
+        .spawn(move || loop {
+            let received = runtime.block_on(async {
+                tokio::select! {
+                    _ = shutdown.cancelled() => None,
+                    received = rx.recv() => Some(received),
+                }
+            });
+            let snapshot = match received {
+                Some(Ok(snapshot)) => snapshot,
+                Some(Err(broadcast::error::RecvError::Lagged(missed))) => {
+                    tracing::warn!(missed, "metrics export fell behind, snapshots dropped");
+                    continue;
+                }
+                Some(Err(broadcast::error::RecvError::Closed)) | None => return,
+            };
+            let mut line = match serde_json::to_vec(&snapshot) {
+                Ok(line) => line,
+                Err(_) => continue,
+            };
+            line.push(b'\n');
+            if let Err(e) = out.append(&line) {
+                tracing::error!(path = %path, error = %e, "metrics export write failed");
+            }
+        })
+        .map_err(|e| format!("failed to spawn metrics export thread: {e}"))?;
+    Ok(())
+}
@@ -0,0 +1,256 @@
+use crate::config::ForwardedConfig;
+use axum::http::header::{FORWARDED, HOST};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use std::net::{IpAddr, SocketAddr};
+
+pub const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+pub const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+pub const X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+
+/// The connection a request arrived on, added to its extensions by the
+/// HTTP listeners. Requests the proxy makes up itself (warmup, refresh)
+/// have none.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientConn {
+    pub addr: SocketAddr,
+    pub tls: bool,
+}
+
+/// Set the forwarding headers of an upstream request for the client
+/// request `client`, replacing any copied from it.
+///
+/// A peer in `trusted_proxies` is taken at its word: its address is
+/// appended to the `X-Forwarded-For` and `Forwarded` it sent, and its
+/// `X-Forwarded-Proto` and `X-Forwarded-Host` are kept. Anyone else's are
+/// dropped, and the peer itself becomes the client.
+pub fn set_headers(config: &ForwardedConfig, client: &Parts, upstream: &mut HeaderMap) {
+    if !config.enabled {
+        return;
+    }
+    for name in [
+        X_FORWARDED_FOR,
+        X_FORWARDED_PROTO,
+        X_FORWARDED_HOST,
+        FORWARDED,
+    ] {
+        upstream.remove(name);
+    }
+    let Some(conn) = client.extensions.get::<ClientConn>() else {
+        return;
+    };
+    // Dual-stack listeners see IPv4 clients as `::ffff:a.b.c.d`
+    let ip = conn.addr.ip().to_canonical();
+    let trusted = config.trusted_proxies.iter().any(|net| net.contains(&ip));
+    let headers = &client.headers;
+    let proto = if conn.tls { "https" } else { "http" };
+    let host = client_host(client);
+
+    let mut insert = |name: HeaderName, value: String| {
+        if let Ok(value) = HeaderValue::try_from(value) {
+            upstream.insert(name, value);
+        }
+    };
+    insert(
+        X_FORWARDED_FOR,
+        appended(trusted, headers, &X_FORWARDED_FOR, ip.to_string()),
+    );
+    let kept = |name: &HeaderName| {
+        headers
+            .get(name)
+            .filter(|_| trusted)
+            .and_then(|v| v.to_str().ok())
+    };
+    insert(
+        X_FORWARDED_PROTO,
+        kept(&X_FORWARDED_PROTO).unwrap_or(proto).to_string(),
+    );
+    if let Some(host) = kept(&X_FORWARDED_HOST).or(host) {
+        insert(X_FORWARDED_HOST, host.to_string());
+    }
+    if config.rfc7239 {
+        let mut element = format!("for={}", node(ip));
+        if let Some(host) = host {
+            element.push_str(";host=");
+            element.push_str(&quoted(host));
+        }
+        element.push_str(";proto=");
+        element.push_str(proto);
+        insert(FORWARDED, appended(trusted, headers, &FORWARDED, element));
+    }
+}
+
+/// The host the client asked for: its `Host` header, or for HTTP/2 the
+/// `:authority` of the URI.
+pub fn client_host(client: &Parts) -> Option<&str> {
+    client
+        .headers
+        .get(HOST)
+        .and_then(|v| v.to_str().ok())
+        .or_else(|| client.uri.authority().map(|a| a.as_str()))
+}
+
+/// `last` after every `name` value the peer sent, if it's trusted.
+fn appended(trusted: bool, headers: &HeaderMap, name: &HeaderName, last: String) -> String {
+    if !trusted {
+        return last;
+    }
+    let mut list: Vec<&str> = headers
+        .get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .collect();
+    list.push(&last);
+    list.join(", ")
+}
+
+/// An RFC 7239 node: IPv6 addresses are bracketed, which needs quotes.
+fn node(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => ip.to_string(),
+        IpAddr::V6(ip) => format!("\"[{ip}]\""),
+    }
+}
+
+/// `value` as an RFC 7239 value: a token if it can be one, otherwise a
+/// quoted string.
+fn quoted(value: &str) -> String {
+    let token = !value.is_empty()
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+    if token {
+        value.to_string()
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Request;
+
+    const SPOOFED: [(&str, &str); 4] = [
+        ("x-forwarded-for", "1.2.3.4"),
+        ("x-forwarded-proto", "https"),
+        ("x-forwarded-host", "cdn.example"),
+        ("forwarded", "for=1.2.3.4;proto=https"),
+    ];
+
+    fn config(trusted: &[&str]) -> ForwardedConfig {
+        ForwardedConfig {
+            enabled: true,
+            rfc7239: true,
+            trusted_proxies: trusted.iter().map(|net| net.parse().unwrap()).collect(),
+        }
+    }
+
+    /// The upstream headers for a request from `peer` with `headers`, which
+    /// start out copied from the client's, as the proxy does.
+    fn forwarded(
+        config: &ForwardedConfig,
+        peer: &str,
+        headers: &[(&'static str, &'static str)],
+    ) -> HeaderMap {
+        let mut request = Request::builder()
+            .uri("/items/1")
+            .extension(ClientConn {
+                addr: peer.parse().unwrap(),
+                tls: false,
+            })
+            .body(())
+            .unwrap();
+        for &(name, value) in headers {
+            request.headers_mut().append(
+                HeaderName::from_static(name),
+                HeaderValue::from_static(value),
+            );
+        }
+        let (client, ()) = request.into_parts();
+        let mut upstream = client.headers.clone();
+        set_headers(config, &client, &mut upstream);
+        upstream
+    }
+
+    fn get<'a>(headers: &'a HeaderMap, name: &str) -> Vec<&'a str> {
+        headers
+            .get_all(name)
+            .iter()
+            .map(|v| v.to_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn untrusted_peers_headers_are_replaced() {
+        let mut headers = vec![("host", "example.com")];
+        headers.extend(SPOOFED);
+        let upstream = forwarded(&config(&["10.0.0.0/8"]), "203.0.113.7:5000", &headers);
+
+        assert_eq!(get(&upstream, "x-forwarded-for"), ["203.0.113.7"]);
+        assert_eq!(get(&upstream, "x-forwarded-proto"), ["http"]);
+        assert_eq!(get(&upstream, "x-forwarded-host"), ["example.com"]);
+        assert_eq!(
+            get(&upstream, "forwarded"),
+            ["for=203.0.113.7;host=example.com;proto=http"]
+        );
+    }
+
+    #[test]
+    fn trusted_peers_headers_are_appended_to() {
+        let mut headers = vec![("host", "example.com"), ("x-forwarded-for", "5.6.7.8")];
+        headers.extend(SPOOFED);
+        let upstream = forwarded(&config(&["10.0.0.0/8"]), "10.1.2.3:5000", &headers);
+
+        assert_eq!(
+            get(&upstream, "x-forwarded-for"),
+            ["5.6.7.8, 1.2.3.4, 10.1.2.3"]
+        );
+        assert_eq!(get(&upstream, "x-forwarded-proto"), ["https"]);
+        assert_eq!(get(&upstream, "x-forwarded-host"), ["cdn.example"]);
+        assert_eq!(
+            get(&upstream, "forwarded"),
+            ["for=1.2.3.4;proto=https, for=10.1.2.3;host=example.com;proto=http"]
+        );
+    }
+
+    #[test]
+    fn ipv4_mapped_peers_are_canonicalized() {
+        let upstream = forwarded(
+            &config(&["10.0.0.0/8"]),
+            "[::ffff:10.1.2.3]:5000",
+            &[("x-forwarded-for", "1.2.3.4")],
+        );
+        // Matched against the IPv4 range, so trusted, and written as IPv4
+        assert_eq!(get(&upstream, "x-forwarded-for"), ["1.2.3.4, 10.1.2.3"]);
+        assert_eq!(get(&upstream, "forwarded"), ["for=10.1.2.3;proto=http"]);
+    }
+
+    #[test]
+    fn ipv6_nodes_are_bracketed_and_quoted() {
+        let upstream = forwarded(
+            &config(&[]),
+            "[2001:db8::1]:5000",
+            &[("host", "example.com:8080")],
+        );
+        assert_eq!(get(&upstream, "x-forwarded-for"), ["2001:db8::1"]);
+        assert_eq!(
+            get(&upstream, "forwarded"),
+            ["for=\"[2001:db8::1]\";host=\"example.com:8080\";proto=http"]
+        );
+    }
+
+    #[test]
+    fn disabled_leaves_headers_alone() {
+        let config = ForwardedConfig {
+            enabled: false,
+            ..config(&[])
+        };
+        let upstream = forwarded(&config, "203.0.113.7:5000", &SPOOFED);
+        for (name, value) in SPOOFED {
+            assert_eq!(get(&upstream, name), [value]);
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use crate::config::KeyConfig;
+use axum::http::{Method, Uri};
+use colander_cache::keys::KeyBuilder;
+use std::fmt::Write;
+
+/// The proxy's cache key scheme: `"METHOD:uri"`, e.g. `"GET:/path?query"`,
+/// with the URI normalized per `[cache.key]`.
+///
+/// The authority (absolute-form requests only) is always lowercased, since
+/// hosts are case-insensitive. With the default config nothing else changes,
+/// so keys match the raw request URI.
+///
+/// Requests of a `[cache.tenant]` tenant get `#tenant=` and its name
+/// appended, so tenants never share entries.
+#[derive(Debug, Clone, Copy)]
+pub struct HttpKey<'a> {
+    config: &'a KeyConfig,
+    tenant: Option<&'a str>,
+}
+
+impl<'a> HttpKey<'a> {
+    pub fn new(config: &'a KeyConfig) -> Self {
+        Self {
+            config,
+            tenant: None,
+        }
+    }
+
+    /// Scope keys to `tenant`, if any.
+    pub fn with_tenant(self, tenant: Option<&'a str>) -> Self {
+        Self { tenant, ..self }
+    }
+
+    /// The start of every key of `method` requests for `uri`'s scheme and
+    /// authority whose URI begins with `prefix` (a path, optionally with the
+    /// start of a query). The path is normalized as in a key; the query is
+    /// kept as written, since a partial one can't be sorted or stripped.
+    /// Tenants come last in a key, so the prefix covers all of theirs.
+    pub fn prefix(&self, method: &Method, uri: &Uri, prefix: &str) -> String {
+        let (path, query) = match prefix.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (prefix, None),
+        };
+        let mut out = String::with_capacity(method.as_str().len() + 1 + prefix.len());
+        self.write_path(method, uri, path, &mut out);
+        if let Some(query) = query {
+            out.push('?');
+            out.push_str(query);
+        }
+        out
+    }
+
+    /// `METHOD:`, then `uri`'s scheme and authority, then `path`.
+    fn write_path(&self, method: &Method, uri: &Uri, path: &str, out: &mut String) {
+        out.push_str(method.as_str());
+        out.push(':');
+        if let Some(scheme) = uri.scheme() {
+            let _ = write!(out, "{scheme}://"); // writing to a String can't fail
+        }
+        if let Some(authority) = uri.authority() {
+            out.extend(authority.as_str().chars().map(|c| c.to_ascii_lowercase()));
+        }
+        if self.config.lowercase_path {
+            out.extend(path.chars().map(|c| c.to_ascii_lowercase()));
+        } else {
+            out.push_str(path);
+        }
+    }
+
+    fn write_query(&self, path: &str, query: &str, out: &mut String) {
+        let config = self.config;
+        if config.ignore_query.iter().any(|p| matches(p, path)) {
+            return;
+        }
+        if !config.sort_query && config.strip_params.is_empty() {
+            if !query.is_empty() {
+                out.push('?');
+                out.push_str(query);
+            }
+            return;
+        }
+
+        let mut params: Vec<&str> = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .filter(|pair| {
+                let name = pair.split_once('=').map_or(*pair, |(name, _)| name);
+                !config.strip_params.iter().any(|p| matches(p, name))
+            })
+            .collect();
+        if config.sort_query {
+            params.sort_by_key(|pair| pair.split_once('=').map_or(*pair, |(name, _)| name));
+        }
+
+        for (i, pair) in params.iter().enumerate() {
+            out.push(if i == 0 { '?' } else { '&' });
+            out.push_str(pair);
+        }
+    }
+}
+
+impl<'a> KeyBuilder<(&'a Method, &'a Uri)> for HttpKey<'_> {
+    fn write_key(&self, (method, uri): &(&'a Method, &'a Uri), out: &mut String) {
+        let path = uri.path();
+        self.write_path(method, uri, path, out);
+        if let Some(query) = uri.query() {
+            self.write_query(path, query, out);
+        }
+        if let Some(tenant) = self.tenant {
+            out.push_str("#tenant=");
+            out.push_str(tenant);
+        }
+    }
+}
+
+/// `pattern` equals `value`, or is a prefix of it when ending in `*`.
+pub(crate) fn matches(pattern: &str, value: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => value.starts_with(prefix),
+        None => pattern == value,
+    }
+}
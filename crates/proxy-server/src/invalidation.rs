@@ -0,0 +1,195 @@
+use crate::proxy::{AppState, HttpClient};
+use axum::body::Body;
+use axum::http::{header, Request};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio_util::sync::CancellationToken;
+
+/// Removals waiting for the broadcaster beyond this are dropped.
+const QUEUE_LEN: usize = 10_000;
+/// Most keys sent to a peer in one request.
+const MAX_BATCH: usize = 500;
+/// How long peers found through `[cluster] discover` are reused before the
+/// name is resolved again.
+const DISCOVERY_TTL: Duration = Duration::from_secs(30);
+
+/// Keys removed on this instance (RESP `DEL`, memcached `delete`, ...),
+/// queued for `run` to pass on to every peer.
+///
+/// Removals are always queued; `run` drops them unless
+/// `[cluster] broadcast_invalidations` is on, so the setting hot-reloads.
+pub struct InvalidationBus {
+    tx: mpsc::Sender<Removal>,
+}
+
+/// One queued removal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Removal {
+    Key(String),
+    /// Every key starting with this, whichever ones a peer holds.
+    Prefix(String),
+}
+
+impl InvalidationBus {
+    /// The bus, and the receiving end to hand to `run`.
+    pub fn new() -> (Self, mpsc::Receiver<Removal>) {
+        let (tx, rx) = mpsc::channel(QUEUE_LEN);
+        (Self { tx }, rx)
+    }
+
+    /// Queue `key` for the peers. Never blocks: if the broadcaster has
+    /// fallen this far behind, the key is dropped (and counted) instead.
+    pub fn publish(&self, key: &str) {
+        self.send(Removal::Key(key.to_string()));
+    }
+
+    /// Queue the removal of every key under `prefix`, like `publish`.
+    pub fn publish_prefix(&self, prefix: &str) {
+        self.send(Removal::Prefix(prefix.to_string()));
+    }
+
+    fn send(&self, removal: Removal) {
+        if let Err(TrySendError::Full(_)) = self.tx.try_send(removal) {
+            ::metrics::counter!("colander_cluster_invalidations_dropped_total").increment(1);
+        }
+    }
+}
+
+/// Send queued removals to every peer's `POST /api/cluster/invalidate`.
+///
+/// Keys that pile up while a batch is in flight go out together in the next
+/// one. Delivery is best effort: a peer that's down or slow misses the batch
+/// and keeps its copies until they expire.
+pub async fn run(
+    state: Arc<AppState>,
+    mut rx: mpsc::Receiver<Removal>,
+    shutdown: CancellationToken,
+) {
+    let mut discovery = Discovery::default();
+    loop {
+        let first = tokio::select! {
+            _ = shutdown.cancelled() => return,
+            removal = rx.recv() => match removal {
+                Some(removal) => removal,
+                None => return,
+            },
+        };
+        let mut batch = vec![first];
+        while batch.len() < MAX_BATCH {
+            match rx.try_recv() {
+                Ok(removal) => batch.push(removal),
+                Err(_) => break,
+            }
+        }
+
+        let config = state.config.load();
+        let cluster = &config.cluster;
+        if !cluster.broadcast_invalidations {
+            continue;
+        }
+        let (mut keys, mut prefixes) = (Vec::new(), Vec::new());
+        for removal in batch {
+            match removal {
+                Removal::Key(key) => keys.push(key),
+                Removal::Prefix(prefix) => prefixes.push(prefix),
+            }
+        }
+        keys.sort_unstable();
+        keys.dedup();
+        prefixes.sort_unstable();
+        prefixes.dedup();
+
+        let mut peers: Vec<String> = cluster
+            .peers
+            .iter()
+            .map(|peer| peer.trim_end_matches('/').to_string())
+            .collect();
+        if let Some(name) = &cluster.discover {
+            peers.extend_from_slice(discovery.peers(name).await);
+            peers.sort_unstable();
+            peers.dedup();
+        }
+
+        let timeout = Duration::from_millis(cluster.timeout_ms);
+        let token = cluster.token.as_deref();
+        let body = serde_json::json!({ "keys": keys, "prefixes": prefixes }).to_string();
+        let count = keys.len() + prefixes.len();
+        let sends = peers.iter().map(|peer| {
+            let client = state.client.clone();
+            let body = body.clone();
+            async move {
+                match send(client, peer, token, timeout, body).await {
+                    Ok(()) => {
+                        ::metrics::counter!("colander_cluster_invalidations_sent_total", "peer" => peer.clone())
+                            .increment(count as u64);
+                    }
+                    Err(e) => {
+                        tracing::warn!(peer = %peer, removals = count, error = %e, "invalidation broadcast failed");
+                        ::metrics::counter!("colander_cluster_invalidation_errors_total", "peer" => peer.clone())
+                            .increment(1);
+                    }
+                }
+            }
+        });
+        futures_util::future::join_all(sends).await;
+    }
+}
+
+async fn send(
+    client: HttpClient,
+    peer: &str,
+    token: Option<&str>,
+    timeout: Duration,
+    body: String,
+) -> Result<(), String> {
+    let mut req = Request::post(format!("{peer}/api/cluster/invalidate"))
+        .header(header::CONTENT_TYPE, "application/json");
+    if let Some(token) = token {
+        req = req.header(header::AUTHORIZATION, format!("Bearer {token}"));
+    }
+    let req = req.body(Body::from(body)).map_err(|e| e.to_string())?;
+
+    let resp = tokio::time::timeout(timeout, client.request(req))
+        .await
+        .map_err(|_| format!("timed out after {}ms", timeout.as_millis()))?
+        .map_err(|e| e.to_string())?;
+    if !resp.status().is_success() {
+        return Err(format!("status {}", resp.status()));
+    }
+    Ok(())
+}
+
+/// Admin URLs behind `[cluster] discover`: one `http://` peer per address
+/// the name resolves to.
+#[derive(Default)]
+struct Discovery {
+    name: String,
+    peers: Vec<String>,
+    resolved_at: Option<Instant>,
+}
+
+impl Discovery {
+    async fn peers(&mut self, name: &str) -> &[String] {
+        let stale = self
+            .resolved_at
+            .is_none_or(|at| at.elapsed() >= DISCOVERY_TTL);
+        if self.name != name {
+            self.peers.clear();
+        }
+        if stale || self.name != name {
+            match tokio::net::lookup_host(name).await {
+                Ok(addrs) => {
+                    self.peers = addrs.map(|addr| format!("http://{addr}")).collect();
+                    tracing::debug!(name, peers = ?self.peers, "discovered cluster peers");
+                }
+                // Keep the last answer rather than stop broadcasting
+                Err(e) => tracing::warn!(name, error = %e, "cluster peer discovery failed"),
+            }
+            self.name = name.to_string();
+            self.resolved_at = Some(Instant::now());
+        }
+        &self.peers
+    }
+}
@@ -0,0 +1,302 @@
+use crate::bypass;
+use crate::config::{BypassConfig, BypassMode, DirectivesConfig, KeyConfig, StoredHeadersConfig};
+use crate::directives::Directives;
+use crate::http_key::HttpKey;
+use crate::proxy::{
+    cache_control, cache_key, entry_response, initial_age, serve_cached, stored_headers,
+};
+use axum::body::{Body, HttpBody};
+use axum::http::header::CONTENT_LENGTH;
+use axum::http::{HeaderName, HeaderValue, Method, Request, Response, StatusCode};
+use bytes::Bytes;
+use colander_cache::sharded::{Entry, ShardedCache};
+use colander_cache::sieve::SieveCache;
+use colander_cache::traits::CachedResponse;
+use futures_util::future::BoxFuture;
+use http_body_util::BodyExt;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant, SystemTime};
+use tower::{BoxError, Layer, Service};
+
+/// Colander's response caching as tower middleware, for an axum or hyper
+/// service that wants a SIEVE cache in-process instead of a proxy in front:
+///
+/// ```no_run
+/// use axum::{routing::get, Router};
+/// use proxy_server::layer::ColanderCacheLayer;
+/// use std::time::Duration;
+///
+/// let cache = ColanderCacheLayer::new(10_000).default_ttl(Duration::from_secs(30));
+/// let app: Router = Router::new()
+///     .route("/", get(|| async { "hello" }))
+///     .layer(cache.clone());
+/// // `cache.cache()` is the ShardedCache behind it, for stats and removals
+/// ```
+///
+/// It caches as the proxy does: `GET` responses with status 200 that
+/// `Cache-Control` lets a shared cache keep, for their `s-maxage` or
+/// `max-age` and otherwise `default_ttl`, keyed on the request URI. `HEAD`
+/// is answered from the `GET` entry, and concurrent misses on one key call
+/// the inner service once. Requests with credentials (`Authorization` by
+/// default) skip the cache. A client's `no-store` and `only-if-cached` are
+/// honored; its `no-cache`, and a bypass header, only once enabled with
+/// `directives`. Responses that may be cached are buffered whole; the
+/// rest stream through untouched.
+///
+/// Clones share one cache, so a layer applied to several routers caches
+/// for all of them.
+#[derive(Clone)]
+pub struct ColanderCacheLayer {
+    cache: Arc<ShardedCache<SieveCache>>,
+    config: Arc<LayerConfig>,
+}
+
+/// The parts of `[cache]` the layer follows.
+#[derive(Clone)]
+struct LayerConfig {
+    default_ttl: Duration,
+    max_body_size: usize,
+    key: KeyConfig,
+    headers: StoredHeadersConfig,
+    bypass: BypassConfig,
+    directives: DirectivesConfig,
+}
+
+impl ColanderCacheLayer {
+    /// A layer over a new SIEVE cache of `capacity` entries, with the
+    /// proxy's defaults: a 60 s TTL and 1 MiB bodies.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            cache: Arc::new(ShardedCache::new(capacity, SieveCache::new)),
+            config: Arc::new(LayerConfig {
+                default_ttl: Duration::from_secs(60),
+                max_body_size: 1_048_576,
+                key: KeyConfig::default(),
+                headers: StoredHeadersConfig::default(),
+                bypass: BypassConfig::default(),
+                directives: DirectivesConfig::default(),
+            }),
+        }
+    }
+
+    /// Lifetime of responses that don't give one (`default_ttl_seconds`).
+    pub fn default_ttl(mut self, ttl: Duration) -> Self {
+        Arc::make_mut(&mut self.config).default_ttl = ttl;
+        self
+    }
+
+    /// Bodies larger than this are served but not cached
+    /// (`max_body_size_bytes`).
+    pub fn max_body_size(mut self, bytes: usize) -> Self {
+        Arc::make_mut(&mut self.config).max_body_size = bytes;
+        self
+    }
+
+    /// How URIs are normalized into keys (`[cache.key]`).
+    pub fn key(mut self, key: KeyConfig) -> Self {
+        Arc::make_mut(&mut self.config).key = key;
+        self
+    }
+
+    /// Which response headers are stored with an entry (`[cache.headers]`).
+    pub fn stored_headers(mut self, headers: StoredHeadersConfig) -> Self {
+        Arc::make_mut(&mut self.config).headers = headers;
+        self
+    }
+
+    /// Which requests carry credentials, and whether they skip the cache or
+    /// get entries of their own (`[cache.bypass]`).
+    pub fn bypass(mut self, bypass: BypassConfig) -> Self {
+        Arc::make_mut(&mut self.config).bypass = bypass;
+        self
+    }
+
+    /// What clients may ask of the cache (`[cache.directives]`).
+    pub fn directives(mut self, directives: DirectivesConfig) -> Self {
+        Arc::make_mut(&mut self.config).directives = directives;
+        self
+    }
+
+    /// The cache behind the layer, shared by every service it wraps.
+    pub fn cache(&self) -> &Arc<ShardedCache<SieveCache>> {
+        &self.cache
+    }
+}
+
+impl<S> Layer<S> for ColanderCacheLayer {
+    type Service = ColanderCache<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ColanderCache {
+            inner,
+            cache: Arc::clone(&self.cache),
+            config: Arc::clone(&self.config),
+        }
+    }
+}
+
+/// A service wrapped by `ColanderCacheLayer`.
+#[derive(Clone)]
+pub struct ColanderCache<S> {
+    inner: S,
+    cache: Arc<ShardedCache<SieveCache>>,
+    config: Arc<LayerConfig>,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ColanderCache<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    ReqBody: Send + 'static,
+    ResBody: HttpBody<Data = Bytes> + Send + 'static,
+    ResBody::Error: Into<BoxError>,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Response<Body>, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        // The clone may not be ready; the instance `poll_ready` was called
+        // on is
+        let clone = self.inner.clone();
+        let ready = std::mem::replace(&mut self.inner, clone);
+        let (cache, config) = (Arc::clone(&self.cache), Arc::clone(&self.config));
+        Box::pin(async move { serve(&cache, &config, ready, req).await })
+    }
+}
+
+async fn serve<S, ReqBody, ResBody>(
+    cache: &ShardedCache<SieveCache>,
+    config: &LayerConfig,
+    mut inner: S,
+    req: Request<ReqBody>,
+) -> Result<Response<Body>, S::Error>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: HttpBody<Data = Bytes> + Send + 'static,
+    ResBody::Error: Into<BoxError>,
+{
+    let method = req.method().clone();
+    let head = method == Method::HEAD;
+    let directives = Directives::from_request(&config.directives, req.headers());
+    let credentials = bypass::private_credentials(&config.bypass, req.headers(), req.uri());
+    let bypassed =
+        directives.bypass || (credentials.is_some() && config.bypass.mode == BypassMode::Bypass);
+    if bypassed || !(head || method == Method::GET) {
+        let response = inner.call(req).await?;
+        return Ok(labeled(
+            response.map(Body::new),
+            if bypassed { "BYPASS" } else { "MISS" },
+        ));
+    }
+
+    // HEAD is answered from the GET entry, so it's keyed like one
+    let mut key = cache_key(&HttpKey::new(&config.key), &Method::GET, req.uri());
+    if let Some(creds) = &credentials {
+        key = bypass::per_user_key(&key, creds);
+    }
+
+    // Only a GET that may store its response claims the key; HEAD and
+    // `no-store` requests read it without making others wait on them
+    let mut fill = None;
+    if !directives.no_cache {
+        let hit = if head || directives.no_store {
+            cache.get(&key)
+        } else {
+            match cache.entry(&key).await {
+                Entry::Occupied(hit) => Some(hit),
+                Entry::Vacant(vacant) => {
+                    fill = Some(vacant);
+                    None
+                }
+            }
+        };
+        if let Some(hit) = hit {
+            let response = labeled(entry_response(&hit), "HIT");
+            return Ok(serve_cached(&method, req.headers(), &hit, response));
+        }
+    }
+
+    if directives.only_if_cached {
+        let mut response = Response::new(Body::from("Gateway Timeout"));
+        *response.status_mut() = StatusCode::GATEWAY_TIMEOUT;
+        return Ok(labeled(response, "MISS"));
+    }
+
+    let request_time = SystemTime::now();
+    let response = inner.call(req).await?;
+    let response_time = SystemTime::now();
+
+    let (parts, body) = response.into_parts();
+    let cc = cache_control(&parts.headers);
+    // must-revalidate without a lifetime isn't cached, as in the proxy
+    let lifetime = cc
+        .max_age
+        .or((!cc.must_revalidate).then_some(config.default_ttl));
+    let declared_len = parts
+        .headers
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    let cacheable = parts.status == StatusCode::OK
+        && cc.cacheable
+        && declared_len.is_none_or(|len| len <= config.max_body_size);
+    let (Some(fill), Some(lifetime), true) = (fill, lifetime, cacheable) else {
+        return Ok(labeled(
+            Response::from_parts(parts, Body::new(body)),
+            "MISS",
+        ));
+    };
+
+    let body = match body.collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(e) => {
+            let e: BoxError = e.into();
+            tracing::error!(error = %e, key = %fill.key(), "failed to read response body");
+            let mut response = Response::new(Body::from("Bad Gateway"));
+            *response.status_mut() = StatusCode::BAD_GATEWAY;
+            return Ok(labeled(response, "MISS"));
+        }
+    };
+    // Time the response already spent in upstream caches counts against it
+    let age = initial_age(&parts.headers, request_time, response_time);
+    if body.len() <= config.max_body_size && age < lifetime {
+        let entry = CachedResponse {
+            status: parts.status.as_u16(),
+            headers: stored_headers(&config.headers, &parts.headers),
+            body: body.clone(),
+            inserted_at: Instant::now(),
+            ttl: lifetime,
+            hits: Default::default(),
+            source: Default::default(),
+            must_revalidate: cc.must_revalidate,
+        };
+        fill.insert(entry.with_initial_age(age));
+    }
+    Ok(labeled(
+        Response::from_parts(parts, Body::from(body)),
+        "MISS",
+    ))
+}
+
+/// `response` with `X-Cache` set to `status`, and `X-Cache-Hits: 0` unless
+/// it came from the cache.
+fn labeled(mut response: Response<Body>, status: &'static str) -> Response<Body> {
+    let headers = response.headers_mut();
+    if status != "HIT" {
+        headers.insert(
+            HeaderName::from_static("x-cache-hits"),
+            HeaderValue::from_static("0"),
+        );
+    }
+    headers.insert(
+        HeaderName::from_static("x-cache"),
+        HeaderValue::from_static(status),
+    );
+    response
+}
@@ -0,0 +1,42 @@
+//! Colander proxy internals: cache layer, HTTP proxy, admin/metrics endpoints,
+//! and the RESP/memcached wire-protocol servers. `server::Server` wires
+//! these together for the `proxy-server` binary and for applications that
+//! embed colander in-process; the parts are also exposed for benches.
+
+pub mod adaptive_ttl;
+pub mod build_info;
+pub mod bypass;
+pub mod cache_layer;
+pub mod circuit;
+pub mod cluster;
+pub mod config;
+pub mod dashboard;
+pub mod directives;
+pub mod drain;
+pub mod dump;
+pub mod export;
+pub mod forwarded;
+pub mod hop_by_hop;
+pub mod http_key;
+pub mod invalidation;
+pub mod layer;
+pub mod listeners;
+pub mod memcached;
+pub mod metrics;
+pub mod plugin;
+pub mod proxy;
+pub mod pubsub;
+pub mod quota;
+pub mod range;
+pub mod refresh;
+pub mod resp;
+pub mod server;
+pub mod shed;
+#[cfg(feature = "soak")]
+pub mod soak;
+pub mod tenant;
+pub mod tls;
+pub mod tunnel;
+pub mod upstream;
+pub mod warmup;
+pub mod writes;
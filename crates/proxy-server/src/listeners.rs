@@ -0,0 +1,481 @@
+use crate::config::{ListenerConfig, ListenerRole};
+use crate::dashboard;
+use crate::dump::{dump_handler, restore_handler};
+use crate::forwarded::ClientConn;
+use crate::memcached;
+use crate::metrics::{
+    cache_entry_handler, cache_keys_handler, clear_cache_handler, clear_partition_handler,
+    cluster_stats_handler, drain_handler, history_handler, hot_keys_handler, invalidate_handler,
+    read_only_handler, ready_handler, reset_stats_handler, resize_handler,
+    resize_partition_handler, set_mode_handler, set_policy_handler, sse_metrics_handler,
+    stats_handler, upstreams_handler, validate_config_handler, version_handler, ws_metrics_handler,
+    MetricsState,
+};
+use crate::proxy::{proxy_handler, AppState};
+use crate::resp;
+use crate::shed;
+use crate::tls::{self, ReloadableTls, TlsListener};
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{any, get, post};
+use axum::Router;
+use hyper::body::Incoming;
+use hyper::server::conn::{http1, http2};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::service::TowerToHyperService;
+use metrics_exporter_prometheus::PrometheusHandle;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::{pin, Pin};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use tower::ServiceExt;
+
+/// Shared handles every listener draws from when building its routers.
+#[derive(Clone)]
+pub struct ListenerContext {
+    pub state: Arc<AppState>,
+    pub metrics: MetricsState,
+    pub prom_handle: PrometheusHandle,
+    pub shutdown: CancellationToken,
+}
+
+/// A listener whose socket is bound (and TLS loaded) but not yet serving.
+pub struct BoundListener {
+    config: ListenerConfig,
+    tcp: TcpListener,
+    tls: Option<ReloadableTls>,
+}
+
+impl BoundListener {
+    pub fn addr(&self) -> &str {
+        &self.config.addr
+    }
+
+    pub fn roles(&self) -> &[ListenerRole] {
+        &self.config.roles
+    }
+
+    /// The address actually bound, with any port 0 resolved.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.tcp
+            .local_addr()
+            .expect("bound listener has a local address")
+    }
+
+    /// Handle for swapping this listener's certificate (see `TlsReloader`).
+    pub fn tls(&self) -> Option<&ReloadableTls> {
+        self.tls.as_ref()
+    }
+}
+
+/// Check that a listener's role set can actually be served on one socket.
+pub fn validate_roles(listener: &ListenerConfig) -> Result<(), String> {
+    if listener.roles.is_empty() {
+        return Err(format!("listener {} has no roles", listener.addr));
+    }
+    let wire_roles = listener.roles.iter().filter(|r| !r.is_http()).count();
+    if wire_roles > 0 && listener.roles.len() > 1 {
+        return Err(format!(
+            "listener {} mixes {:?}: resp and memcached roles must be alone on their socket",
+            listener.addr, listener.roles
+        ));
+    }
+    // Neither protocol would check it, leaving the port open to anyone
+    if wire_roles > 0 && listener.auth.is_some() {
+        return Err(format!(
+            "listener {} sets auth, which only HTTP roles enforce: use [resp] password for RESP; memcached has no authentication",
+            listener.addr
+        ));
+    }
+    Ok(())
+}
+
+/// Bind a listener's socket and load its TLS material. The error names the
+/// listener, since a half-started proxy is worse than a loud startup error.
+pub async fn bind(config: ListenerConfig) -> Result<BoundListener, String> {
+    validate_roles(&config).map_err(|e| format!("invalid listener config: {e}"))?;
+
+    let alpn = match (config.roles.iter().all(|r| r.is_http()), config.http2) {
+        (true, true) => tls::ALPN_H2_HTTP1,
+        (true, false) => tls::ALPN_HTTP1,
+        (false, _) => &[],
+    };
+    let tls = match &config.tls {
+        Some(t) => Some(
+            ReloadableTls::load(t, alpn)
+                .map_err(|e| format!("failed to load TLS for listener {}: {e}", config.addr))?,
+        ),
+        None => None,
+    };
+
+    let tcp = TcpListener::bind(&config.addr).await.map_err(|e| {
+        format!(
+            "failed to bind listener {:?} to {}: {e}",
+            config.roles, config.addr
+        )
+    })?;
+
+    Ok(BoundListener { config, tcp, tls })
+}
+
+/// Start serving a bound listener on the given join set.
+pub fn spawn(bound: BoundListener, ctx: &ListenerContext, tasks: &mut JoinSet<()>) {
+    let BoundListener { config, tcp, tls } = bound;
+
+    tracing::info!(
+        addr = %config.addr,
+        roles = ?config.roles,
+        tls = tls.is_some(),
+        auth = config.auth.is_some(),
+        http2 = tls.is_some() && config.http2,
+        h2c = tls.is_none() && config.h2c,
+        "listener starting"
+    );
+
+    if config.has_role(ListenerRole::Resp) || config.has_role(ListenerRole::Memcached) {
+        let state = Arc::clone(&ctx.state);
+        let shutdown = ctx.shutdown.clone();
+        if config.has_role(ListenerRole::Resp) {
+            tasks.spawn(resp::run_resp_server(tcp, tls, state, shutdown));
+        } else {
+            tasks.spawn(memcached::run_memcached_server(tcp, tls, state, shutdown));
+        }
+        return;
+    }
+
+    let router = build_router(&config, ctx);
+    let shutdown = ctx.shutdown.clone();
+    let addr = config.addr.clone();
+    let h2c = config.h2c;
+
+    tasks.spawn(async move {
+        match tls {
+            // ALPN already settled the version; don't sniff for the other one
+            Some(tls) => match TlsListener::new(tcp, tls) {
+                Ok(listener) => {
+                    let versions = |io: &_| {
+                        if tls::negotiated_h2(io) {
+                            HttpVersions::Http2
+                        } else {
+                            HttpVersions::Http1
+                        }
+                    };
+                    serve_http(listener, true, router, versions, shutdown).await;
+                }
+                Err(e) => tracing::error!(error = %e, addr = %addr, "listener error"),
+            },
+            None => {
+                let versions = if h2c {
+                    HttpVersions::Auto
+                } else {
+                    HttpVersions::Http1
+                };
+                serve_http(tcp, false, router, move |_: &_| versions, shutdown).await;
+            }
+        }
+    });
+}
+
+/// HTTP versions a connection may speak.
+#[derive(Debug, Clone, Copy)]
+enum HttpVersions {
+    Http1,
+    Http2,
+    /// Either, told apart by the HTTP/2 connection preface.
+    Auto,
+}
+
+/// Serve `router` on every connection `listener` accepts until `shutdown`
+/// fires, then let in-flight requests finish. Each request carries its
+/// connection's `ClientConn`; `tls` says whether `listener` terminates TLS.
+///
+/// Like `axum::serve`, but `versions` decides per connection which protocols
+/// are allowed, so h2c can stay off and TLS connections follow ALPN.
+async fn serve_http<L>(
+    mut listener: L,
+    tls: bool,
+    router: Router,
+    versions: impl Fn(&L::Io) -> HttpVersions,
+    shutdown: CancellationToken,
+) where
+    L: axum::serve::Listener<Addr = SocketAddr>,
+{
+    // Each connection task holds a receiver; the sender sees `closed()`
+    // once the last one is dropped
+    let (done_tx, done_rx) = watch::channel(());
+
+    loop {
+        let (io, addr) = tokio::select! {
+            conn = listener.accept() => conn,
+            _ = shutdown.cancelled() => break,
+        };
+        let versions = versions(&io);
+        let conn = ClientConn { addr, tls };
+        let service = TowerToHyperService::new(router.clone().map_request(
+            move |mut req: Request<Incoming>| {
+                req.extensions_mut().insert(conn);
+                req.map(Body::new)
+            },
+        ));
+        let shutdown = shutdown.clone();
+        let done_rx = done_rx.clone();
+
+        tokio::spawn(async move {
+            // auto::Builder's upgrade-capable connections ignore
+            // http1_only/http2_only, so pinned versions use hyper directly
+            let io = TokioIo::new(io);
+            match versions {
+                HttpVersions::Http1 => {
+                    let conn = http1::Builder::new()
+                        .serve_connection(io, service)
+                        .with_upgrades();
+                    drive(conn, |c| c.graceful_shutdown(), shutdown).await
+                }
+                HttpVersions::Http2 => {
+                    let conn = http2::Builder::new(TokioExecutor::new())
+                        // CONNECT protocol needed for HTTP/2 websockets
+                        .enable_connect_protocol()
+                        .serve_connection(io, service);
+                    drive(conn, |c| c.graceful_shutdown(), shutdown).await
+                }
+                HttpVersions::Auto => {
+                    let mut builder = auto::Builder::new(TokioExecutor::new());
+                    builder.http2().enable_connect_protocol();
+                    let conn = builder.serve_connection_with_upgrades(io, service);
+                    drive(conn, |c| c.graceful_shutdown(), shutdown).await
+                }
+            }
+            drop(done_rx);
+        });
+    }
+
+    drop(done_rx);
+    done_tx.closed().await;
+}
+
+/// Run one connection to completion, asking it to finish gracefully (stop
+/// taking new requests, complete in-flight ones) once `shutdown` fires.
+async fn drive<C, E>(conn: C, graceful: impl FnOnce(Pin<&mut C>), shutdown: CancellationToken)
+where
+    C: Future<Output = Result<(), E>>,
+    E: std::fmt::Display,
+{
+    let mut conn = pin!(conn);
+    let result = tokio::select! {
+        result = conn.as_mut() => result,
+        _ = shutdown.cancelled() => {
+            graceful(conn.as_mut());
+            conn.await
+        }
+    };
+    if let Err(e) = result {
+        tracing::trace!(error = %e, "connection closed with error");
+    }
+}
+
+/// Compose the HTTP router for a listener from its roles.
+///
+/// Explicit admin/metrics routes take precedence over the proxy's catch-all,
+/// so combining `proxy` with `admin` shadows those paths on the upstream.
+/// The built-in dashboard at `/` is left out there, so `/` stays proxied.
+fn build_router(config: &ListenerConfig, ctx: &ListenerContext) -> Router {
+    let mut router = Router::new();
+
+    if config.has_role(ListenerRole::Admin) {
+        router = router.merge(admin_router(ctx));
+    }
+    if config.has_role(ListenerRole::Metrics) {
+        router = router.merge(metrics_router(ctx));
+    }
+    if config.has_role(ListenerRole::Proxy) {
+        router = router.merge(proxy_router(ctx));
+    } else if config.has_role(ListenerRole::Metrics) {
+        router = router.merge(dashboard::router());
+    }
+
+    if let Some(auth) = &config.auth {
+        let token: Arc<str> = Arc::from(auth.token.as_str());
+        router = router.layer(middleware::from_fn_with_state(token, require_bearer));
+    }
+
+    router
+}
+
+fn proxy_router(ctx: &ListenerContext) -> Router {
+    // Shedding wraps only the proxy, so admin and metrics stay reachable
+    // while it's overloaded
+    Router::new()
+        .route("/{*path}", any(proxy_handler))
+        .route("/", any(proxy_handler))
+        .layer(middleware::from_fn_with_state(
+            Arc::clone(&ctx.state),
+            shed::shed_load,
+        ))
+        .with_state(Arc::clone(&ctx.state))
+}
+
+fn admin_router(ctx: &ListenerContext) -> Router {
+    Router::new()
+        .route("/api/mode", post(set_mode_handler))
+        .route("/api/stats", get(stats_handler))
+        .route("/api/stats/reset", post(reset_stats_handler))
+        .route("/api/history", get(history_handler))
+        .route("/api/hotkeys", get(hot_keys_handler))
+        .route("/api/cache/keys", get(cache_keys_handler))
+        .route("/api/cache/entry", get(cache_entry_handler))
+        .route("/api/cache/dump", get(dump_handler))
+        .route("/api/cache/restore", post(restore_handler))
+        .route("/api/cache/resize", post(resize_handler))
+        .route("/api/cache/read-only", post(read_only_handler))
+        .route("/api/cache/clear", post(clear_cache_handler))
+        .route("/api/cache/partition/clear", post(clear_partition_handler))
+        .route(
+            "/api/cache/partition/resize",
+            post(resize_partition_handler),
+        )
+        .route("/api/policy", post(set_policy_handler))
+        .route("/api/cluster/stats", get(cluster_stats_handler))
+        .route("/api/cluster/invalidate", post(invalidate_handler))
+        .route("/api/upstreams", get(upstreams_handler))
+        .route("/api/config/validate", post(validate_config_handler))
+        .route("/api/drain", post(drain_handler))
+        .route("/api/version", get(version_handler))
+        .route("/readyz", get(ready_handler))
+        .with_state(ctx.metrics.clone())
+}
+
+fn metrics_router(ctx: &ListenerContext) -> Router {
+    let prom_handle = ctx.prom_handle.clone();
+    Router::new()
+        .route("/ws/metrics", get(ws_metrics_handler))
+        .route("/sse/metrics", get(sse_metrics_handler))
+        .route(
+            "/metrics",
+            get(move || {
+                let h = prom_handle.clone();
+                async move { h.render() }
+            }),
+        )
+        .with_state(ctx.metrics.clone())
+}
+
+/// Reject requests without a matching `Authorization: Bearer <token>` header.
+/// The header is removed from those let through: it's the listener's
+/// credential, not the client's, so it must neither make requests private
+/// (see `bypass`) nor reach the origin.
+async fn require_bearer(State(token): State<Arc<str>>, mut req: Request, next: Next) -> Response {
+    let authorized = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|presented| constant_time_eq(presented.as_bytes(), token.as_bytes()));
+
+    if authorized {
+        req.headers_mut().remove(header::AUTHORIZATION);
+        next.run(req).await
+    } else {
+        (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            "Unauthorized",
+        )
+            .into_response()
+    }
+}
+
+/// Compare secrets without short-circuiting on the first differing byte.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AuthConfig, Config};
+    use crate::server::Server;
+    use axum::http::HeaderMap;
+    use hyper_util::client::legacy::Client;
+    use parking_lot::Mutex;
+
+    fn listener(roles: Vec<ListenerRole>, token: Option<&str>) -> ListenerConfig {
+        ListenerConfig {
+            addr: "127.0.0.1:0".into(),
+            roles,
+            tls: None,
+            auth: token.map(|token| AuthConfig {
+                token: token.into(),
+            }),
+            http2: false,
+            h2c: false,
+        }
+    }
+
+    #[test]
+    fn wire_listeners_refuse_auth_they_cant_enforce() {
+        for role in [ListenerRole::Resp, ListenerRole::Memcached] {
+            assert!(validate_roles(&listener(vec![role], None)).is_ok());
+            let err = validate_roles(&listener(vec![role], Some("s3cret"))).unwrap_err();
+            assert!(err.contains("only HTTP roles"), "{err}");
+        }
+        let http = listener(
+            vec![ListenerRole::Proxy, ListenerRole::Admin],
+            Some("s3cret"),
+        );
+        assert!(validate_roles(&http).is_ok());
+    }
+
+    #[tokio::test]
+    async fn authenticated_proxy_listener_caches_and_keeps_its_token() {
+        // An origin that records what `Authorization` it's sent
+        let seen: Arc<Mutex<Vec<Option<String>>>> = Arc::default();
+        let origin = {
+            let seen = Arc::clone(&seen);
+            Router::new().route(
+                "/item",
+                get(move |headers: HeaderMap| async move {
+                    let auth = headers.get(header::AUTHORIZATION);
+                    seen.lock()
+                        .push(auth.map(|v| v.to_str().unwrap().to_string()));
+                    ([(header::CACHE_CONTROL, "max-age=60")], "ok")
+                }),
+            )
+        };
+        let tcp = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let origin_addr = tcp.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(tcp, origin).await });
+
+        let mut config = Config::default_config();
+        config.upstream.url = format!("http://{origin_addr}");
+        config.listeners = vec![listener(vec![ListenerRole::Proxy], Some("s3cret"))];
+        let server = Server::start(config).await.unwrap();
+        let addr = server.handle().addr(ListenerRole::Proxy).unwrap();
+
+        let client = Client::builder(TokioExecutor::new()).build_http::<Body>();
+        let mut cache_status = Vec::new();
+        for _ in 0..2 {
+            let req = Request::get(format!("http://{addr}/item"))
+                .header(header::AUTHORIZATION, "Bearer s3cret")
+                .body(Body::empty())
+                .unwrap();
+            let resp = client.request(req).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+            cache_status.push(resp.headers()["x-cache"].to_str().unwrap().to_string());
+        }
+
+        assert_eq!(cache_status, ["MISS", "HIT"]);
+        assert_eq!(*seen.lock(), [None]);
+        server.handle().shutdown();
+    }
+}
@@ -1,31 +1,59 @@
-mod cache_layer;
-mod config;
-mod metrics;
-mod proxy;
-mod resp;
-
-use arc_swap::ArcSwap;
-use axum::routing::{any, get, post};
-use axum::Router;
-use cache_layer::CacheLayer;
-use config::Config;
-use hyper_util::client::legacy::Client;
-use hyper_util::rt::TokioExecutor;
-use metrics::{
-    metrics_broadcaster, set_mode_handler, stats_handler, ws_metrics_handler, MetricsState,
-};
+use clap::Parser;
 use notify::{Event, EventKind, RecursiveMode, Watcher};
-use parking_lot::Mutex;
-use proxy::{proxy_handler, AppState};
+use proxy_server::config::{Config, Overrides};
+use proxy_server::drain::DrainOptions;
+use proxy_server::server::{Server, ServerHandle};
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::broadcast;
-use tokio_util::sync::CancellationToken;
 use tracing_subscriber::EnvFilter;
 
+/// Colander — a caching reverse proxy with SIEVE eviction.
+///
+/// Flags and their `COLANDER_*` environment variables override the config
+/// file; a flag wins over its variable.
+#[derive(Parser)]
+#[command(name = "proxy-server", version)]
+struct Args {
+    /// Config file [default: config.toml, used only if it exists]
+    #[arg(long, env = "COLANDER_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Proxy listen address (`[server] listen_addr`)
+    #[arg(long, env = "COLANDER_LISTEN")]
+    listen: Option<String>,
+
+    /// Admin and metrics listen address (`[server] metrics_addr`)
+    #[arg(long, env = "COLANDER_METRICS_LISTEN")]
+    metrics_listen: Option<String>,
+
+    /// Upstream origin URL (`[upstream] url`); replaces any `[[upstream.backends]]`
+    #[arg(long, env = "COLANDER_UPSTREAM")]
+    upstream: Option<String>,
+
+    /// Eviction policy (`[cache] eviction_policy`)
+    #[arg(long, env = "COLANDER_POLICY")]
+    policy: Option<String>,
+
+    /// Cache capacity in entries (`[cache] capacity`)
+    #[arg(long, env = "COLANDER_CAPACITY")]
+    capacity: Option<usize>,
+}
+
+impl Args {
+    fn overrides(&self) -> Overrides {
+        Overrides {
+            listen_addr: self.listen.clone(),
+            metrics_addr: self.metrics_listen.clone(),
+            upstream_url: self.upstream.clone(),
+            eviction_policy: self.policy.clone(),
+            capacity: self.capacity,
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() {
+    let args = Args::parse();
+
     // Initialize tracing
     tracing_subscriber::fmt()
         .with_env_filter(
@@ -34,158 +62,73 @@ async fn main() {
         .json()
         .init();
 
-    // Load config
-    let config = if Path::new("config.toml").exists() {
-        match Config::load(Path::new("config.toml")) {
+    // Load config. An explicit --config must exist; the default is optional.
+    let config_path = args
+        .config
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("config.toml"));
+    let mut config = if args.config.is_some() || config_path.exists() {
+        match Config::load(&config_path) {
             Ok(c) => {
-                tracing::info!("loaded config from config.toml");
+                tracing::info!(path = %config_path.display(), "loaded config");
                 c
             }
             Err(e) => {
-                tracing::error!(error = %e, "failed to load config.toml, using defaults");
-                Config::default_config()
+                tracing::error!(path = %config_path.display(), error = %e, "failed to load config, refusing to start");
+                std::process::exit(1);
             }
         }
     } else {
         tracing::info!("no config.toml found, using defaults");
         Config::default_config()
     };
+    let overrides = args.overrides();
+    overrides.apply(&mut config);
+    if let Err(e) = config.validate() {
+        tracing::error!(error = %e, "invalid command-line or COLANDER_* override, refusing to start");
+        std::process::exit(1);
+    }
 
-    // Install Prometheus metrics recorder
-    let prom_handle = metrics_exporter_prometheus::PrometheusBuilder::new()
-        .install_recorder()
-        .expect("prometheus recorder");
-
-    // Build cache layer (wrapped in ArcSwap for hot-reload)
-    let cache = Arc::new(CacheLayer::new(
-        &config.cache.eviction_policy,
-        config.cache.comparison_policy.as_deref(),
-        config.cache.capacity,
-        Duration::from_secs(config.cache.default_ttl_seconds),
-        config.cache.max_body_size_bytes,
-    ));
-
-    let cache_swap = Arc::new(ArcSwap::from(cache));
-
-    // Build HTTP client for upstream requests
-    let client = Client::builder(TokioExecutor::new()).build_http();
-
-    let state = Arc::new(AppState {
-        cache: ArcSwap::from(cache_swap.load_full()),
-        client,
-        upstream_url: config.upstream.url.clone(),
-    });
-
-    // Shutdown token for graceful shutdown
-    let shutdown = CancellationToken::new();
-
-    // Metrics broadcast channel
-    let (metrics_tx, _) = broadcast::channel::<metrics::MetricsSnapshot>(64);
-
-    // Start metrics broadcaster
-    let start_time = std::time::Instant::now();
-    tokio::spawn(metrics_broadcaster(
-        Arc::clone(&state),
-        metrics_tx.clone(),
-        start_time,
-    ));
-
-    // Combined metrics state
-    let metrics_state = MetricsState {
-        app: Arc::clone(&state),
-        tx: metrics_tx,
+    let server = match Server::start(config).await {
+        Ok(server) => server,
+        Err(e) => {
+            tracing::error!(error = %e, "failed to start");
+            std::process::exit(1);
+        }
     };
 
-    // Build metrics/admin router (separate port)
-    let metrics_router = Router::new()
-        .route("/ws/metrics", get(ws_metrics_handler))
-        .route("/api/mode", post(set_mode_handler))
-        .route("/api/stats", get(stats_handler))
-        .route(
-            "/metrics",
-            get(move || {
-                let h = prom_handle.clone();
-                async move { h.render() }
-            }),
-        )
-        .with_state(metrics_state);
-
-    // Build proxy router (main port)
-    let proxy_router = Router::new()
-        .route("/{*path}", any(proxy_handler))
-        .route("/", any(proxy_handler))
-        .with_state(Arc::clone(&state));
-
-    // Start both servers
-    let proxy_addr = config.server.listen_addr.clone();
-    let metrics_addr = config.server.metrics_addr.clone();
-
-    tracing::info!(
-        proxy = %proxy_addr,
-        metrics = %metrics_addr,
-        upstream = %config.upstream.url,
-        policy = %config.cache.eviction_policy,
-        comparison = ?config.cache.comparison_policy,
-        capacity = config.cache.capacity,
-        resp_enabled = config.resp.enabled,
-        "colander proxy starting"
-    );
-
-    let proxy_listener = tokio::net::TcpListener::bind(&proxy_addr)
-        .await
-        .unwrap_or_else(|e| panic!("failed to bind proxy to {proxy_addr}: {e}"));
-
-    let metrics_listener = tokio::net::TcpListener::bind(&metrics_addr)
-        .await
-        .unwrap_or_else(|e| panic!("failed to bind metrics to {metrics_addr}: {e}"));
-
-    // Spawn RESP server if enabled
-    if config.resp.enabled {
-        let resp_addr = config.resp.listen_addr.clone();
-        let resp_cache = Arc::clone(&state);
-        let resp_shutdown = shutdown.clone();
-        tokio::spawn(async move {
-            resp::run_resp_server(&resp_addr, resp_cache, resp_shutdown).await;
-        });
-    }
-
-    // Spawn config file watcher
-    spawn_config_watcher(PathBuf::from("config.toml"), config, Arc::clone(&state));
+    // Reload on config file changes, and on SIGHUP
+    #[cfg(unix)]
+    tokio::spawn(reload_on_sighup(
+        config_path.clone(),
+        overrides.clone(),
+        server.handle().clone(),
+    ));
+    spawn_config_watcher(config_path, overrides, server.handle().clone());
 
     // Spawn shutdown signal handler
-    let shutdown_clone = shutdown.clone();
-    tokio::spawn(async move {
-        shutdown_signal(shutdown_clone).await;
-    });
-
-    // Run both servers with graceful shutdown
-    let proxy_shutdown = shutdown.clone();
-    let metrics_shutdown = shutdown.clone();
+    tokio::spawn(shutdown_signal(server.handle().clone()));
 
-    let proxy_future = axum::serve(proxy_listener, proxy_router)
-        .with_graceful_shutdown(proxy_shutdown.cancelled_owned());
-
-    let metrics_future = axum::serve(metrics_listener, metrics_router)
-        .with_graceful_shutdown(metrics_shutdown.cancelled_owned());
+    // Each listener drains and exits once the shutdown token fires
+    server.join().await;
+}
 
-    tokio::select! {
-        result = proxy_future => {
-            if let Err(e) = result {
-                tracing::error!(error = %e, "proxy server error");
-            }
-        }
-        result = metrics_future => {
-            if let Err(e) = result {
-                tracing::error!(error = %e, "metrics server error");
-            }
-        }
+/// Listen for SIGINT (Ctrl+C) or SIGTERM and drain per `[shutdown]`.
+///
+/// A second signal, or a signal during a drain started over the admin API,
+/// skips the rest of the grace period.
+async fn shutdown_signal(server: ServerHandle) {
+    wait_for_signal().await;
+    let options = DrainOptions::from_config(&server.state().config.load().shutdown);
+    if server.drain(options) {
+        tracing::info!("shutdown signal received, draining connections...");
+        wait_for_signal().await;
     }
-
-    tracing::info!("colander proxy shut down");
+    tracing::info!("shutdown signal received, skipping grace period");
+    server.shutdown();
 }
 
-/// Listen for SIGINT (Ctrl+C) or SIGTERM and cancel the shutdown token.
-async fn shutdown_signal(token: CancellationToken) {
+async fn wait_for_signal() {
     let ctrl_c = tokio::signal::ctrl_c();
 
     #[cfg(unix)]
@@ -202,29 +145,16 @@ async fn shutdown_signal(token: CancellationToken) {
     {
         ctrl_c.await.ok();
     }
-
-    tracing::info!("shutdown signal received, draining connections...");
-    token.cancel();
 }
 
-/// Spawn a filesystem watcher on config.toml that applies safe config changes at runtime.
-fn spawn_config_watcher(config_path: PathBuf, initial_config: Config, state: Arc<AppState>) {
-    let current_config = Arc::new(Mutex::new(initial_config));
-
+/// Spawn a filesystem watcher on the config file that applies safe config
+/// changes at runtime, with `overrides` still on top.
+fn spawn_config_watcher(config_path: PathBuf, overrides: Overrides, server: ServerHandle) {
     let config_path_clone = config_path.clone();
     let mut watcher = match notify::recommended_watcher(move |res: Result<Event, notify::Error>| {
         if let Ok(event) = res {
             if matches!(event.kind, EventKind::Modify(_) | EventKind::Create(_)) {
-                match Config::load(&config_path_clone) {
-                    Ok(new_config) => {
-                        let mut old = current_config.lock();
-                        config::diff_and_apply(&old, &new_config, &state.cache);
-                        *old = new_config;
-                    }
-                    Err(e) => {
-                        tracing::error!(error = %e, "failed to reload config.toml");
-                    }
-                }
+                reload_config(&config_path_clone, &overrides, &server);
             }
         }
     }) {
@@ -236,7 +166,7 @@ fn spawn_config_watcher(config_path: PathBuf, initial_config: Config, state: Arc
     };
 
     if let Err(e) = watcher.watch(&config_path, RecursiveMode::NonRecursive) {
-        tracing::warn!(error = %e, "failed to watch config.toml");
+        tracing::warn!(path = %config_path.display(), error = %e, "failed to watch config file");
         return;
     }
 
@@ -244,3 +174,36 @@ fn spawn_config_watcher(config_path: PathBuf, initial_config: Config, state: Arc
     std::mem::forget(watcher);
     tracing::info!("config file watcher started");
 }
+
+/// Reload the config file on every SIGHUP. File events don't arrive for
+/// every kind of update (editors that rename over the file, Kubernetes
+/// ConfigMap symlink swaps), so this gives operators a way to force one.
+#[cfg(unix)]
+async fn reload_on_sighup(config_path: PathBuf, overrides: Overrides, server: ServerHandle) {
+    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+        .expect("SIGHUP handler");
+    while hangup.recv().await.is_some() {
+        tracing::info!(path = %config_path.display(), "SIGHUP received, reloading config");
+        let (path, overrides, server) = (config_path.clone(), overrides.clone(), server.clone());
+        // A shrink or policy rebuild can take a while; keep it off the runtime
+        tokio::task::spawn_blocking(move || reload_config(&path, &overrides, &server))
+            .await
+            .ok();
+    }
+}
+
+/// Re-read the config file and apply it with `overrides` on top. A file
+/// that fails to load or validate is logged and the running config kept.
+fn reload_config(config_path: &Path, overrides: &Overrides, server: &ServerHandle) {
+    match Config::load(config_path) {
+        Ok(mut new_config) => {
+            overrides.apply(&mut new_config);
+            if let Err(e) = server.reload(new_config) {
+                tracing::error!(path = %config_path.display(), error = %e, "invalid config, not reloaded");
+            }
+        }
+        Err(e) => {
+            tracing::error!(path = %config_path.display(), error = %e, "failed to reload config");
+        }
+    }
+}
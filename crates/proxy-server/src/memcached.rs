@@ -0,0 +1,296 @@
+use crate::cache_layer::InsertError;
+use crate::proxy::AppState;
+use crate::tls::{self, ReloadableTls};
+use bytes::{Bytes, BytesMut};
+use colander_cache::traits::InsertSource;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+
+/// Memcached treats exptimes larger than 30 days as absolute unix timestamps.
+const RELATIVE_EXPTIME_LIMIT: i64 = 60 * 60 * 24 * 30;
+
+/// Longest command line accepted before the connection is dropped.
+const MAX_LINE_LEN: usize = 2048;
+
+/// Run the memcached text-protocol server on a bound listener.
+///
+/// Supports the subset that look-aside clients need: `get`/`gets`, `set`,
+/// `delete`, `version`, and `quit`. Values share the cache with HTTP and RESP.
+pub async fn run_memcached_server(
+    listener: TcpListener,
+    tls: Option<ReloadableTls>,
+    state: Arc<AppState>,
+    shutdown: CancellationToken,
+) {
+    if let Ok(addr) = listener.local_addr() {
+        tracing::info!(addr = %addr, tls = tls.is_some(), "memcached server listening");
+    }
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => {
+                tracing::info!("memcached server shutting down");
+                break;
+            }
+            result = listener.accept() => {
+                match result {
+                    Ok((stream, peer)) => {
+                        let state = Arc::clone(&state);
+                        let tls = tls.as_ref().map(ReloadableTls::acceptor);
+                        tokio::spawn(async move {
+                            tracing::debug!(peer = %peer, "memcached client connected");
+                            match tls {
+                                Some(acceptor) => match tls::handshake(&acceptor, stream).await {
+                                    Ok(stream) => handle_connection(stream, &state).await,
+                                    Err(e) => tracing::debug!(error = %e, peer = %peer, "memcached TLS handshake failed"),
+                                },
+                                None => handle_connection(stream, &state).await,
+                            }
+                            tracing::debug!(peer = %peer, "memcached client disconnected");
+                        });
+                    }
+                    Err(e) => {
+                        tracing::error!(error = %e, "memcached accept error");
+                    }
+                }
+            }
+        }
+    }
+}
+
+enum Command {
+    Get(Vec<String>),
+    Set {
+        key: String,
+        exptime: i64,
+        value: Bytes,
+        noreply: bool,
+    },
+    Delete {
+        key: String,
+        noreply: bool,
+    },
+    Version,
+    Quit,
+    /// A malformed or unknown command; the reply is sent and the line skipped.
+    Error(&'static str),
+}
+
+/// Outcome of trying to parse one command from the buffer.
+enum Parsed {
+    Complete(Command, usize),
+    Incomplete,
+    /// The stream can't be resynchronized (bad data block, oversized value).
+    Fatal(&'static str),
+}
+
+async fn handle_connection<S>(mut stream: S, state: &AppState)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut buf = BytesMut::with_capacity(4096);
+
+    loop {
+        match stream.read_buf(&mut buf).await {
+            Ok(0) => break, // EOF
+            Ok(_) => {}
+            Err(e) => {
+                tracing::debug!(error = %e, "memcached read error");
+                break;
+            }
+        }
+
+        loop {
+            let max_value = state.cache.load().max_body_size();
+            let (command, consumed) = match parse(&buf, max_value) {
+                Parsed::Complete(command, consumed) => (command, consumed),
+                Parsed::Incomplete => break,
+                Parsed::Fatal(reply) => {
+                    let _ = stream.write_all(reply.as_bytes()).await;
+                    return;
+                }
+            };
+            let _ = buf.split_to(consumed);
+
+            let out = match execute(command, state) {
+                Some(out) => out,
+                None => return, // quit
+            };
+            if out.is_empty() {
+                continue;
+            }
+            if let Err(e) = stream.write_all(&out).await {
+                tracing::debug!(error = %e, "memcached write error");
+                return;
+            }
+        }
+    }
+}
+
+/// Run a command against the cache. Returns `None` when the client asked to quit.
+fn execute(command: Command, state: &AppState) -> Option<Vec<u8>> {
+    let cache = state.cache.load();
+    let mut out = Vec::new();
+
+    match command {
+        // Like memcached's own SERVER_ERROR replies, sent even with noreply
+        Command::Set { .. } | Command::Delete { .. } if cache.is_read_only() => {
+            out.extend_from_slice(b"SERVER_ERROR cache is read-only\r\n");
+        }
+        Command::Get(keys) => {
+            for key in keys {
+                if let Some(cached) = cache.get(&key).value {
+                    cached.record_hit();
+                    out.extend_from_slice(
+                        format!("VALUE {key} 0 {}\r\n", cached.body.len()).as_bytes(),
+                    );
+                    out.extend_from_slice(&cached.body);
+                    out.extend_from_slice(b"\r\n");
+                }
+            }
+            out.extend_from_slice(b"END\r\n");
+        }
+        Command::Set {
+            key,
+            exptime,
+            value,
+            noreply,
+        } => {
+            let stored = match exptime_to_ttl(exptime) {
+                Some(ttl) => cache.insert_raw(key, value, ttl, InsertSource::Memcached),
+                // Already expired — memcached stores nothing and drops any old value
+                None => {
+                    cache.remove(&key);
+                    Ok(())
+                }
+            };
+            match stored {
+                Ok(()) if noreply => {}
+                Ok(()) => out.extend_from_slice(b"STORED\r\n"),
+                // The limit or the mode changed since the command was parsed
+                Err(InsertError::TooLarge) => {
+                    out.extend_from_slice(b"SERVER_ERROR object too large for cache\r\n")
+                }
+                Err(InsertError::ReadOnly) => {
+                    out.extend_from_slice(b"SERVER_ERROR cache is read-only\r\n")
+                }
+            }
+        }
+        Command::Delete { key, noreply } => {
+            let removed = cache.remove(&key);
+            if !noreply {
+                out.extend_from_slice(if removed {
+                    b"DELETED\r\n"
+                } else {
+                    b"NOT_FOUND\r\n"
+                });
+            }
+        }
+        Command::Version => {
+            out.extend_from_slice(
+                format!("VERSION colander-{}\r\n", env!("CARGO_PKG_VERSION")).as_bytes(),
+            );
+        }
+        Command::Quit => return None,
+        Command::Error(reply) => out.extend_from_slice(reply.as_bytes()),
+    }
+
+    Some(out)
+}
+
+fn parse(buf: &[u8], max_value: usize) -> Parsed {
+    let line_end = match buf.windows(2).position(|w| w == b"\r\n") {
+        Some(pos) => pos,
+        None if buf.len() > MAX_LINE_LEN => return Parsed::Fatal("CLIENT_ERROR line too long\r\n"),
+        None => return Parsed::Incomplete,
+    };
+    let line_len = line_end + 2;
+
+    let line = match std::str::from_utf8(&buf[..line_end]) {
+        Ok(l) => l,
+        Err(_) => {
+            return Parsed::Complete(
+                Command::Error("CLIENT_ERROR bad command line format\r\n"),
+                line_len,
+            )
+        }
+    };
+    let parts: Vec<&str> = line.split_ascii_whitespace().collect();
+    let Some(&name) = parts.first() else {
+        return Parsed::Complete(Command::Error("ERROR\r\n"), line_len);
+    };
+
+    let command = match name {
+        "get" | "gets" if parts.len() >= 2 => {
+            Command::Get(parts[1..].iter().map(|k| k.to_string()).collect())
+        }
+        "set" if parts.len() == 5 || parts.len() == 6 => {
+            let (Ok(exptime), Ok(len)) = (parts[3].parse::<i64>(), parts[4].parse::<usize>())
+            else {
+                return Parsed::Complete(
+                    Command::Error("CLIENT_ERROR bad command line format\r\n"),
+                    line_len,
+                );
+            };
+            if len > max_value {
+                return Parsed::Fatal("SERVER_ERROR object too large for cache\r\n");
+            }
+            let total = line_len + len + 2;
+            if buf.len() < total {
+                return Parsed::Incomplete;
+            }
+            if &buf[line_len + len..total] != b"\r\n" {
+                return Parsed::Fatal("CLIENT_ERROR bad data chunk\r\n");
+            }
+            let value = Bytes::copy_from_slice(&buf[line_len..line_len + len]);
+            return Parsed::Complete(
+                Command::Set {
+                    key: parts[1].to_string(),
+                    exptime,
+                    value,
+                    noreply: parts.get(5) == Some(&"noreply"),
+                },
+                total,
+            );
+        }
+        "delete" if parts.len() == 2 || parts.len() == 3 => Command::Delete {
+            key: parts[1].to_string(),
+            noreply: parts.get(2) == Some(&"noreply"),
+        },
+        "version" => Command::Version,
+        "quit" => Command::Quit,
+        _ => Command::Error("ERROR\r\n"),
+    };
+
+    Parsed::Complete(command, line_len)
+}
+
+/// Convert a memcached exptime into a cache TTL.
+///
+/// `Some(None)` means "use the default TTL"; `None` means the item is
+/// already expired and must not be stored.
+fn exptime_to_ttl(exptime: i64) -> Option<Option<Duration>> {
+    if exptime == 0 {
+        return Some(None);
+    }
+    if exptime < 0 {
+        return None;
+    }
+    if exptime <= RELATIVE_EXPTIME_LIMIT {
+        return Some(Some(Duration::from_secs(exptime as u64)));
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default() // clock before 1970 — treat as epoch
+        .as_secs() as i64;
+    let remaining = exptime - now;
+    if remaining <= 0 {
+        None
+    } else {
+        Some(Some(Duration::from_secs(remaining as u64)))
+    }
+}
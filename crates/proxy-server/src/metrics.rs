@@ -1,19 +1,102 @@
-use crate::cache_layer::{CacheLayer, CacheMode};
+use crate::build_info;
+use crate::cache_layer::{CacheLayer, CacheMode, POLICIES};
+use crate::circuit::CircuitStatus;
+use crate::cluster;
+use crate::config::{self, Config, ConfigChange, ConfigErrors, OneOrMany};
+use crate::drain;
 use crate::proxy::AppState;
+use crate::quota::QuotaReport;
+use crate::tenant::TenantReport;
+use crate::warmup::WarmupStatus;
+use arc_swap::ArcSwapOption;
 use axum::extract::ws::{Message, WebSocket};
-use axum::extract::{State, WebSocketUpgrade};
+use axum::extract::{Query, State, WebSocketUpgrade};
+use axum::response::sse::{Event, KeepAlive, Sse};
 use axum::response::IntoResponse;
 use axum::Json;
+use colander_cache::traits::CacheStats;
+use futures_util::stream::{self, Stream, StreamExt};
+use hdrhistogram::Histogram;
+use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::convert::Infallible;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+/// Highest latency the histograms track (60s, in microseconds). Slower
+/// samples are clamped rather than dropped.
+const MAX_LATENCY_US: u64 = 60_000_000;
 
 /// Combined state for the metrics router (holds both AppState and broadcast sender).
 #[derive(Clone)]
 pub struct MetricsState {
     pub app: Arc<AppState>,
     pub tx: broadcast::Sender<MetricsSnapshot>,
+    /// The broadcaster's most recent snapshot, for one-shot endpoints that
+    /// need windowed figures like throughput.
+    pub latest: Arc<ArcSwapOption<MetricsSnapshot>>,
+    /// Recent snapshots for `/api/history`.
+    pub history: Arc<MetricsHistory>,
+}
+
+impl MetricsState {
+    pub fn new(app: Arc<AppState>, tx: broadcast::Sender<MetricsSnapshot>) -> Self {
+        let history_secs = app.config.load().server.metrics_history_secs;
+        Self {
+            app,
+            tx,
+            latest: Arc::new(ArcSwapOption::empty()),
+            history: Arc::new(MetricsHistory::new(Duration::from_secs(history_secs))),
+        }
+    }
+}
+
+/// Ring buffer of the most recent broadcast snapshots, so a dashboard that
+/// connects late can fill in its charts.
+pub struct MetricsHistory {
+    snapshots: Mutex<VecDeque<MetricsSnapshot>>,
+    capacity: usize,
+}
+
+impl MetricsHistory {
+    /// Room for `retention` worth of 500ms snapshots.
+    pub fn new(retention: Duration) -> Self {
+        let capacity = (retention.as_millis() / 500) as usize;
+        Self {
+            snapshots: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    pub fn push(&self, snapshot: MetricsSnapshot) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut snapshots = self.snapshots.lock();
+        if snapshots.len() == self.capacity {
+            snapshots.pop_front();
+        }
+        snapshots.push_back(snapshot);
+    }
+
+    /// Snapshots from the last `window`, oldest first.
+    pub fn last(&self, window: Duration) -> Vec<MetricsSnapshot> {
+        let snapshots = self.snapshots.lock();
+        let Some(newest) = snapshots.back() else {
+            return Vec::new();
+        };
+        let since = newest.timestamp_ms.saturating_sub(window.as_millis());
+        let start = snapshots.partition_point(|s| s.timestamp_ms <= since);
+        snapshots.range(start..).cloned().collect()
+    }
+
+    /// How far back the buffer reaches when full.
+    pub fn retention(&self) -> Duration {
+        Duration::from_millis(self.capacity as u64 * 500)
+    }
 }
 
 /// Metrics snapshot broadcast to WebSocket clients every 500ms.
@@ -22,13 +105,111 @@ pub struct MetricsSnapshot {
     pub timestamp_ms: u128,
     pub window_ms: u64,
     pub primary: PolicyMetrics,
+    /// The first of `comparisons`, for clients that expect a single one.
     pub comparison: Option<PolicyMetrics>,
+    /// Every shadow cache, in configured order.
+    pub comparisons: Vec<PolicyMetrics>,
     pub throughput_rps: f64,
     pub uptime_seconds: u64,
     pub mode: String,
+    pub latency: LatencyMetrics,
 }
 
-#[derive(Debug, Clone, Serialize)]
+/// Latency percentiles over the last broadcast window, in microseconds.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LatencyPercentiles {
+    pub count: u64,
+    pub p50_us: u64,
+    pub p90_us: u64,
+    pub p99_us: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LatencyMetrics {
+    /// End-to-end latency of requests served from cache.
+    pub hit: LatencyPercentiles,
+    /// End-to-end latency of requests that went upstream.
+    pub miss: LatencyPercentiles,
+    /// Time spent waiting on the origin (request + body) for misses.
+    pub upstream: LatencyPercentiles,
+}
+
+/// Per-request latency recorder feeding both Prometheus and the dashboard.
+///
+/// Every sample goes to the global `metrics` recorder (rendered as quantiles
+/// on `/metrics`) and to a windowed HDR histogram that the broadcaster drains
+/// every tick, so dashboard percentiles reflect recent traffic only.
+pub struct LatencyRecorder {
+    hit: Mutex<Histogram<u64>>,
+    miss: Mutex<Histogram<u64>>,
+    upstream: Mutex<Histogram<u64>>,
+}
+
+impl LatencyRecorder {
+    pub fn new() -> Self {
+        Self {
+            hit: Mutex::new(new_histogram()),
+            miss: Mutex::new(new_histogram()),
+            upstream: Mutex::new(new_histogram()),
+        }
+    }
+
+    pub fn record_hit(&self, elapsed: Duration) {
+        ::metrics::histogram!("colander_request_duration_seconds", "cache" => "hit")
+            .record(elapsed.as_secs_f64());
+        record_us(&self.hit, elapsed);
+    }
+
+    pub fn record_miss(&self, elapsed: Duration) {
+        ::metrics::histogram!("colander_request_duration_seconds", "cache" => "miss")
+            .record(elapsed.as_secs_f64());
+        record_us(&self.miss, elapsed);
+    }
+
+    pub fn record_upstream(&self, elapsed: Duration) {
+        ::metrics::histogram!("colander_upstream_duration_seconds").record(elapsed.as_secs_f64());
+        record_us(&self.upstream, elapsed);
+    }
+
+    /// Read percentiles for the current window and start a new one.
+    pub fn take_window(&self) -> LatencyMetrics {
+        LatencyMetrics {
+            hit: drain(&self.hit),
+            miss: drain(&self.miss),
+            upstream: drain(&self.upstream),
+        }
+    }
+}
+
+impl Default for LatencyRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn new_histogram() -> Histogram<u64> {
+    // 3 significant figures keeps per-histogram memory small (~tens of KB)
+    Histogram::new_with_bounds(1, MAX_LATENCY_US, 3).expect("valid histogram bounds")
+}
+
+fn record_us(hist: &Mutex<Histogram<u64>>, elapsed: Duration) {
+    let us = (elapsed.as_micros() as u64).clamp(1, MAX_LATENCY_US);
+    hist.lock().saturating_record(us);
+}
+
+fn drain(hist: &Mutex<Histogram<u64>>) -> LatencyPercentiles {
+    let mut hist = hist.lock();
+    let percentiles = LatencyPercentiles {
+        count: hist.len(),
+        p50_us: hist.value_at_quantile(0.50),
+        p90_us: hist.value_at_quantile(0.90),
+        p99_us: hist.value_at_quantile(0.99),
+    };
+    hist.reset();
+    percentiles
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PolicyMetrics {
     pub name: String,
     pub hit_rate: f64,
@@ -37,62 +218,227 @@ pub struct PolicyMetrics {
     pub evictions: u64,
     pub size: usize,
     pub capacity: usize,
+    /// Approximate heap footprint of the cached entries.
+    #[serde(default)]
+    pub memory_bytes: usize,
+    /// Hit rates over the last few seconds and minutes. `hit_rate` is since
+    /// startup, so it hardly moves once the cache is warm.
+    #[serde(default)]
+    pub windows: WindowedHitRates,
+    #[serde(default)]
+    pub eviction: EvictionMetrics,
+    /// How hard SIEVE's hand works per eviction; only SIEVE has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scan: Option<ScanMetrics>,
+}
+
+/// The eviction-scan figures the SIEVE paper reports, since startup or the
+/// last stats reset.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ScanMetrics {
+    /// Entries the hand examined per eviction, the victim included.
+    pub mean_scan_length: f64,
+    /// Visited bits cleared per eviction.
+    pub visited_clears_per_eviction: f64,
+    /// Times the hand ran off the head and started over from the tail.
+    pub hand_wraps: u64,
+}
+
+/// How long evicted entries stayed cached, and how many never served a hit.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct EvictionMetrics {
+    pub mean_age_ms: u64,
+    /// Approximate (power-of-two buckets).
+    pub median_age_ms: u64,
+    /// Share of evicted entries that were never hit.
+    pub one_hit_wonder_ratio: f64,
+}
+
+/// Sliding-window hit rates, filled in by the metrics broadcaster. Zero
+/// for a window without lookups, like `hit_rate`.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct WindowedHitRates {
+    #[serde(rename = "10s")]
+    pub last_10s: f64,
+    #[serde(rename = "1m")]
+    pub last_1m: f64,
+    #[serde(rename = "5m")]
+    pub last_5m: f64,
 }
 
 impl PolicyMetrics {
-    fn from_cache(cache: &CacheLayer, primary: bool) -> Option<Self> {
-        if primary {
-            let stats = cache.primary_stats();
-            let total = stats.hits + stats.misses;
-            Some(PolicyMetrics {
-                name: cache.primary_name().to_string(),
-                hit_rate: if total > 0 {
-                    stats.hits as f64 / total as f64
-                } else {
-                    0.0
-                },
-                hits: stats.hits,
-                misses: stats.misses,
-                evictions: stats.evictions,
-                size: stats.current_size,
-                capacity: stats.capacity,
-            })
+    fn from_stats(name: &str, stats: CacheStats) -> Self {
+        let total = stats.hits + stats.misses;
+        PolicyMetrics {
+            name: name.to_string(),
+            hit_rate: if total > 0 {
+                stats.hits as f64 / total as f64
+            } else {
+                0.0
+            },
+            hits: stats.hits,
+            misses: stats.misses,
+            evictions: stats.evictions,
+            size: stats.current_size,
+            capacity: stats.capacity,
+            memory_bytes: stats.memory_bytes,
+            windows: WindowedHitRates::default(),
+            eviction: EvictionMetrics {
+                mean_age_ms: stats.evicted.mean_age().as_millis() as u64,
+                median_age_ms: stats.evicted.median_age().as_millis() as u64,
+                one_hit_wonder_ratio: stats.evicted.one_hit_wonder_ratio(),
+            },
+            scan: (name == "SIEVE").then(|| ScanMetrics {
+                mean_scan_length: stats.scan.mean_scan_length(),
+                visited_clears_per_eviction: stats.scan.visited_clears_per_eviction(),
+                hand_wraps: stats.scan.hand_wraps,
+            }),
+        }
+    }
+
+    /// Publish the eviction-age figures, memory footprint and SIEVE scan
+    /// figures as Prometheus gauges.
+    fn export_gauges(&self) {
+        let policy = self.name.clone();
+        let e = &self.eviction;
+        ::metrics::gauge!("colander_cache_eviction_age_seconds", "policy" => policy.clone(), "stat" => "mean")
+            .set(e.mean_age_ms as f64 / 1000.0);
+        ::metrics::gauge!("colander_cache_eviction_age_seconds", "policy" => policy.clone(), "stat" => "median")
+            .set(e.median_age_ms as f64 / 1000.0);
+        ::metrics::gauge!("colander_cache_one_hit_wonder_ratio", "policy" => policy.clone())
+            .set(e.one_hit_wonder_ratio);
+        ::metrics::gauge!("colander_cache_memory_bytes", "policy" => policy.clone())
+            .set(self.memory_bytes as f64);
+        if let Some(scan) = &self.scan {
+            ::metrics::gauge!("colander_sieve_scan_length", "policy" => policy.clone())
+                .set(scan.mean_scan_length);
+            ::metrics::gauge!("colander_sieve_visited_clears_per_eviction", "policy" => policy.clone())
+                .set(scan.visited_clears_per_eviction);
+            ::metrics::gauge!("colander_sieve_hand_wraps", "policy" => policy)
+                .set(scan.hand_wraps as f64);
+        }
+    }
+
+    fn primary(cache: &CacheLayer) -> Self {
+        Self::from_stats(cache.primary_name(), cache.primary_stats())
+    }
+
+    fn comparisons(cache: &CacheLayer) -> Vec<Self> {
+        cache
+            .comparison_stats()
+            .into_iter()
+            .map(|(name, stats)| Self::from_stats(name, stats))
+            .collect()
+    }
+}
+
+/// Broadcast ticks of hit/miss history kept per cache: 5 minutes' worth.
+const HISTORY_TICKS: usize = 600;
+
+/// Per-tick hit and miss deltas for one cache, oldest first, from which
+/// the broadcaster derives sliding-window hit rates.
+struct HitRateHistory {
+    name: String,
+    deltas: VecDeque<(u64, u64)>,
+    /// Cumulative hits and misses at the last tick.
+    last: (u64, u64),
+}
+
+impl HitRateHistory {
+    fn new(metrics: &PolicyMetrics) -> Self {
+        Self {
+            name: metrics.name.clone(),
+            deltas: VecDeque::with_capacity(HISTORY_TICKS),
+            last: (metrics.hits, metrics.misses),
+        }
+    }
+
+    /// Record this tick's counters and fill in `metrics.windows`.
+    fn record(&mut self, metrics: &mut PolicyMetrics) {
+        if self.deltas.len() == HISTORY_TICKS {
+            self.deltas.pop_front();
+        }
+        // Counters only go backwards when a rebuild zeroes them
+        let since = |now: u64, last: u64| now.checked_sub(last).unwrap_or(now);
+        self.deltas.push_back((
+            since(metrics.hits, self.last.0),
+            since(metrics.misses, self.last.1),
+        ));
+        self.last = (metrics.hits, metrics.misses);
+        metrics.windows = WindowedHitRates {
+            last_10s: self.hit_rate(20),
+            last_1m: self.hit_rate(120),
+            last_5m: self.hit_rate(HISTORY_TICKS),
+        };
+    }
+
+    /// Hit rate over the newest `ticks` entries.
+    fn hit_rate(&self, ticks: usize) -> f64 {
+        let (hits, misses) = self
+            .deltas
+            .iter()
+            .rev()
+            .take(ticks)
+            .fold((0, 0), |(h, m), &(dh, dm)| (h + dh, m + dm));
+        if hits + misses > 0 {
+            hits as f64 / (hits + misses) as f64
         } else {
-            let stats = cache.comparison_stats()?;
-            let name = cache.comparison_name()?;
-            let total = stats.hits + stats.misses;
-            Some(PolicyMetrics {
-                name: name.to_string(),
-                hit_rate: if total > 0 {
-                    stats.hits as f64 / total as f64
-                } else {
-                    0.0
-                },
-                hits: stats.hits,
-                misses: stats.misses,
-                evictions: stats.evictions,
-                size: stats.current_size,
-                capacity: stats.capacity,
-            })
+            0.0
         }
     }
 }
 
-/// Background task that snapshots metrics every 500ms and broadcasts to clients.
+/// Background task that snapshots metrics every 500ms and broadcasts to
+/// clients, until `shutdown` fires.
 pub async fn metrics_broadcaster(
-    state: Arc<AppState>,
-    tx: broadcast::Sender<MetricsSnapshot>,
+    metrics: MetricsState,
     start_time: Instant,
+    shutdown: CancellationToken,
 ) {
+    let state = metrics.app;
     let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(500));
     let mut prev_total_requests: u64 = 0;
+    let mut ticks: u64 = 0;
+    let mut skewed: Option<String> = None;
+    // Primary then comparisons, since the last stats reset
+    let mut history: Vec<HitRateHistory> = Vec::new();
+    let mut stats_generation = 0;
 
     loop {
-        interval.tick().await;
+        tokio::select! {
+            _ = shutdown.cancelled() => return,
+            _ = interval.tick() => {}
+        }
+        ticks += 1;
 
         let cache = state.cache.load();
-        let primary = PolicyMetrics::from_cache(&cache, true).unwrap(); // primary always Some
-        let comparison = PolicyMetrics::from_cache(&cache, false);
+        // Skew is measured over all lookups so far; every 30s is plenty
+        if ticks.is_multiple_of(60) {
+            check_partition_skew(&cache, &state.config.load(), &mut skewed);
+        }
+        let mut primary = PolicyMetrics::primary(&cache);
+        let mut comparisons = PolicyMetrics::comparisons(&cache);
+
+        // Start over when the set of policies changes or stats are reset
+        let all: Vec<&mut PolicyMetrics> = std::iter::once(&mut primary)
+            .chain(&mut comparisons)
+            .collect();
+        let same_policies = history
+            .iter()
+            .map(|h| &h.name)
+            .eq(all.iter().map(|m| &m.name));
+        if !same_policies || stats_generation != cache.stats_generation() {
+            stats_generation = cache.stats_generation();
+            history = all.iter().map(|m| HitRateHistory::new(m)).collect();
+        }
+        for (h, m) in history.iter_mut().zip(all) {
+            h.record(m);
+            m.export_gauges();
+        }
+
+        ::metrics::gauge!("colander_requests_in_flight").set(state.shedder.in_flight() as f64);
+        state.tenants.export();
+        cache.export_quotas();
 
         let current_total = primary.hits + primary.misses;
         let delta = current_total.saturating_sub(prev_total_requests);
@@ -106,14 +452,43 @@ pub async fn metrics_broadcaster(
                 .as_millis(),
             window_ms: 500,
             primary,
-            comparison,
+            comparison: comparisons.first().cloned(),
+            comparisons,
             throughput_rps: throughput,
             uptime_seconds: start_time.elapsed().as_secs(),
             mode: format!("{:?}", cache.mode()).to_lowercase(),
+            latency: state.latency.take_window(),
         };
 
+        metrics.latest.store(Some(Arc::new(snapshot.clone())));
+        metrics.history.push(snapshot.clone());
         // Ignore send errors (no subscribers)
-        let _ = tx.send(snapshot);
+        let _ = metrics.tx.send(snapshot);
+    }
+}
+
+/// Warn when one partition starts dominating lookups, and again once it
+/// stops. `skewed` remembers the last partition warned about.
+fn check_partition_skew(cache: &CacheLayer, config: &Config, skewed: &mut Option<String>) {
+    let threshold = config.cache.partitioning.skew_warn_share;
+    let skew = cache.partition_skew(threshold);
+    ::metrics::gauge!("colander_partition_skew_share").set(skew.as_ref().map_or(0.0, |s| s.share));
+    match skew {
+        Some(skew) if skewed.as_deref() != Some(skew.partition.as_str()) => {
+            tracing::warn!(
+                partition = %skew.partition,
+                share = skew.share,
+                expected = skew.expected,
+                "cache partition dominates lookups"
+            );
+            *skewed = Some(skew.partition);
+        }
+        Some(_) => {}
+        None => {
+            if let Some(partition) = skewed.take() {
+                tracing::info!(partition = %partition, "cache partition skew resolved");
+            }
+        }
     }
 }
 
@@ -145,6 +520,45 @@ async fn handle_ws_client(mut socket: WebSocket, tx: broadcast::Sender<MetricsSn
     }
 }
 
+#[derive(Deserialize)]
+pub struct SseQuery {
+    /// Minimum time between events; snapshots in between are skipped. The
+    /// default (0) sends every one, i.e. every 500ms.
+    #[serde(default)]
+    pub interval_ms: u64,
+}
+
+/// GET /sse/metrics — the `/ws/metrics` snapshots as Server-Sent Events,
+/// for clients that can't speak WebSocket (`curl -N`, `EventSource`).
+pub async fn sse_metrics_handler(
+    State(state): State<MetricsState>,
+    Query(query): Query<SseQuery>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let rx = state.tx.subscribe();
+    let interval = query.interval_ms as u128;
+    let events = stream::unfold((rx, None::<u128>), move |(mut rx, last_sent)| async move {
+        loop {
+            let snapshot = match rx.recv().await {
+                Ok(snapshot) => snapshot,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            };
+            // Half a window of slack, so 1000ms means every other snapshot
+            // rather than every third when ticks arrive a little early
+            let slack = snapshot.window_ms as u128 / 2;
+            if last_sent.is_some_and(|t| snapshot.timestamp_ms + slack < t + interval) {
+                continue;
+            }
+            let Ok(event) = Event::default().json_data(&snapshot) else {
+                continue;
+            };
+            return Some((Ok(event), (rx, Some(snapshot.timestamp_ms))));
+        }
+    });
+    let shutdown = state.app.shutdown.clone().cancelled_owned();
+    Sse::new(events.take_until(shutdown)).keep_alive(KeepAlive::default())
+}
+
 /// POST /api/mode — toggle between demo and bench mode.
 #[derive(Deserialize)]
 pub struct ModeRequest {
@@ -176,15 +590,640 @@ pub async fn set_mode_handler(
     )
 }
 
+/// POST /api/cache/read-only — freeze or unfreeze the cache contents.
+///
+/// While enabled, hits keep being served but nothing is inserted, evicted or
+/// removed; proxy misses go straight to upstream and are tagged BYPASS.
+#[derive(Deserialize)]
+pub struct ReadOnlyRequest {
+    pub enabled: bool,
+}
+
+pub async fn read_only_handler(
+    State(state): State<MetricsState>,
+    Json(body): Json<ReadOnlyRequest>,
+) -> impl IntoResponse {
+    let cache = state.app.cache.load();
+    cache.set_read_only(body.enabled);
+    ::metrics::gauge!("colander_cache_read_only").set(if body.enabled { 1.0 } else { 0.0 });
+
+    Json(serde_json::json!({"read_only": cache.is_read_only()}))
+}
+
+/// Resizing and policy swaps would evict, so they wait until read-only is off.
+pub(crate) fn read_only_conflict() -> (axum::http::StatusCode, Json<serde_json::Value>) {
+    (
+        axum::http::StatusCode::CONFLICT,
+        Json(serde_json::json!({"error": "cache is read-only"})),
+    )
+}
+
+/// POST /api/cache/resize — change cache capacity without dropping entries.
+#[derive(Deserialize)]
+pub struct ResizeRequest {
+    pub capacity: usize,
+}
+
+pub async fn resize_handler(
+    State(state): State<MetricsState>,
+    Json(body): Json<ResizeRequest>,
+) -> impl IntoResponse {
+    if state.app.cache.load().is_read_only() {
+        return read_only_conflict();
+    }
+
+    // A big shrink evicts a lot of entries; keep it off the async workers
+    let cache = state.app.cache.load_full();
+    let old = cache.capacity();
+    let (cache, resized) = tokio::task::spawn_blocking(move || {
+        let resized = cache.resize(body.capacity);
+        (cache, resized)
+    })
+    .await
+    .expect("resize task panicked");
+    if let Err(e) = resized {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({ "error": e.to_string() })),
+        );
+    }
+
+    (
+        axum::http::StatusCode::OK,
+        Json(serde_json::json!({
+            "old_capacity": old,
+            "capacity": cache.capacity(),
+            "size": cache.primary_stats().current_size,
+        })),
+    )
+}
+
+/// POST /api/cache/clear — drop every entry from every cache, keeping the
+/// stats.
+pub async fn clear_cache_handler(State(state): State<MetricsState>) -> impl IntoResponse {
+    if state.app.cache.load().is_read_only() {
+        return read_only_conflict();
+    }
+
+    let cache = state.app.cache.load_full();
+    let removed = tokio::task::spawn_blocking(move || cache.clear())
+        .await
+        .expect("clear task panicked");
+
+    (
+        axum::http::StatusCode::OK,
+        Json(serde_json::json!({ "removed": removed })),
+    )
+}
+
+/// POST /api/cache/partition/clear — drop one partition's entries.
+#[derive(Deserialize)]
+pub struct ClearPartitionRequest {
+    pub partition: String,
+}
+
+pub async fn clear_partition_handler(
+    State(state): State<MetricsState>,
+    Json(body): Json<ClearPartitionRequest>,
+) -> impl IntoResponse {
+    if state.app.cache.load().is_read_only() {
+        return read_only_conflict();
+    }
+
+    let cache = state.app.cache.load_full();
+    let partition = body.partition.clone();
+    let removed = tokio::task::spawn_blocking(move || cache.clear_partition(&partition))
+        .await
+        .expect("clear task panicked");
+
+    match removed {
+        Some(removed) => (
+            axum::http::StatusCode::OK,
+            Json(serde_json::json!({"partition": body.partition, "removed": removed})),
+        ),
+        None => partitioning_disabled(),
+    }
+}
+
+/// POST /api/cluster/invalidate — drop keys another instance removed, and
+/// every key under the prefixes it removed (see `invalidation`). They
+/// aren't broadcast any further.
+#[derive(Deserialize)]
+pub struct InvalidateRequest {
+    pub keys: Vec<String>,
+    #[serde(default)]
+    pub prefixes: Vec<String>,
+}
+
+pub async fn invalidate_handler(
+    State(state): State<MetricsState>,
+    Json(body): Json<InvalidateRequest>,
+) -> impl IntoResponse {
+    let cache = state.app.cache.load_full();
+    let received = body.keys.len() + body.prefixes.len();
+    // Prefixes walk the whole cache
+    let removed = tokio::task::spawn_blocking(move || {
+        let keys = body
+            .keys
+            .iter()
+            .filter(|key| cache.remove_local(key))
+            .count();
+        let prefixes: usize = body
+            .prefixes
+            .iter()
+            .map(|prefix| cache.remove_prefix_local(prefix))
+            .sum();
+        keys + prefixes
+    })
+    .await
+    .expect("invalidation task panicked");
+    ::metrics::counter!("colander_cluster_invalidations_received_total").increment(received as u64);
+    Json(serde_json::json!({"received": received, "removed": removed}))
+}
+
+/// POST /api/cache/partition/resize — change the capacity of one
+/// partition's shards.
+#[derive(Deserialize)]
+pub struct ResizePartitionRequest {
+    pub partition: String,
+    pub capacity: usize,
+}
+
+pub async fn resize_partition_handler(
+    State(state): State<MetricsState>,
+    Json(body): Json<ResizePartitionRequest>,
+) -> impl IntoResponse {
+    if body.capacity == 0 {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": "capacity must be > 0"})),
+        );
+    }
+    if state.app.cache.load().is_read_only() {
+        return read_only_conflict();
+    }
+
+    let cache = state.app.cache.load_full();
+    let partition = body.partition.clone();
+    let (resized, cache) = tokio::task::spawn_blocking(move || {
+        let resized = cache.resize_partition(&partition, body.capacity);
+        (resized, cache)
+    })
+    .await
+    .expect("resize task panicked");
+
+    if !resized {
+        return partitioning_disabled();
+    }
+    let stats = cache.primary_stats();
+    (
+        axum::http::StatusCode::OK,
+        Json(serde_json::json!({
+            "partition": body.partition,
+            "capacity": body.capacity,
+            "total_capacity": stats.capacity,
+            "size": stats.current_size,
+        })),
+    )
+}
+
+fn partitioning_disabled() -> (axum::http::StatusCode, Json<serde_json::Value>) {
+    (
+        axum::http::StatusCode::BAD_REQUEST,
+        Json(serde_json::json!({"error": "cache partitioning is not enabled"})),
+    )
+}
+
+/// POST /api/policy — rebuild the cache with different eviction policies.
+///
+/// Omitted fields keep the current policies. `comparison` takes one policy
+/// or a list; `"none"` or `[]` turns the shadow caches off. `warm_keys` replays that many of the hottest cached keys
+/// into the new cache before it's swapped in.
+#[derive(Deserialize)]
+pub struct PolicyRequest {
+    pub primary: Option<String>,
+    pub comparison: Option<OneOrMany>,
+    #[serde(default)]
+    pub warm_keys: usize,
+}
+
+pub async fn set_policy_handler(
+    State(state): State<MetricsState>,
+    Json(body): Json<PolicyRequest>,
+) -> impl IntoResponse {
+    if state.app.cache.load().is_read_only() {
+        return read_only_conflict();
+    }
+    let current = state.app.cache.load_full();
+
+    let primary = body
+        .primary
+        .map(|p| p.to_lowercase())
+        .unwrap_or_else(|| current.primary_name().to_lowercase());
+    let comparison: Vec<String> = match body.comparison.map(Vec::from) {
+        Some(names) if names.len() == 1 && names[0].eq_ignore_ascii_case("none") => vec![],
+        Some(names) => names.iter().map(|c| c.to_lowercase()).collect(),
+        None => current
+            .comparison_names()
+            .into_iter()
+            .map(str::to_lowercase)
+            .collect(),
+    };
+
+    for policy in std::iter::once(&primary).chain(&comparison) {
+        if !POLICIES.contains(&policy.as_str()) {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "error": format!("unknown policy: {policy}, use one of {POLICIES:?}")
+                })),
+            );
+        }
+    }
+
+    // Allocating arenas and replaying keys is synchronous work
+    let (primary_in, comparison_in) = (primary.clone(), comparison.clone());
+    let rebuilt = tokio::task::spawn_blocking(move || {
+        let comparison_in: Vec<&str> = comparison_in.iter().map(String::as_str).collect();
+        current.with_policies(&primary_in, &comparison_in, body.warm_keys)
+    })
+    .await
+    .expect("policy rebuild task panicked");
+    let size = rebuilt.primary_stats().current_size;
+    state.app.cache.store(Arc::new(rebuilt));
+
+    tracing::info!(%primary, ?comparison, warmed = size, "eviction policy changed via admin API");
+
+    (
+        axum::http::StatusCode::OK,
+        Json(serde_json::json!({
+            "primary": primary,
+            "comparison": comparison,
+            "warmed": size,
+        })),
+    )
+}
+
+/// GET /api/upstreams — backend health and which failover tier is live.
+pub async fn upstreams_handler(State(state): State<MetricsState>) -> impl IntoResponse {
+    let pool = &state.app.upstreams;
+    Json(serde_json::json!({
+        "active_tier": pool.active_tier(),
+        "backends": pool.status(),
+    }))
+}
+
+/// GET /api/version — build and capability info for clients to negotiate with.
+pub async fn version_handler() -> impl IntoResponse {
+    Json(build_info::build_info())
+}
+
+/// GET /readyz — 200 once startup warmup is done, 503 before and while draining.
+pub async fn ready_handler(State(state): State<MetricsState>) -> impl IntoResponse {
+    if state
+        .app
+        .draining
+        .load(std::sync::atomic::Ordering::Relaxed)
+    {
+        (axum::http::StatusCode::SERVICE_UNAVAILABLE, "draining")
+    } else if state.app.ready.load(std::sync::atomic::Ordering::Relaxed) {
+        (axum::http::StatusCode::OK, "ready")
+    } else {
+        (axum::http::StatusCode::SERVICE_UNAVAILABLE, "warming")
+    }
+}
+
+/// POST /api/drain — withdraw readiness, wait, snapshot, then shut down.
+///
+/// Omitted fields fall back to `[shutdown]`; `"snapshot": false` skips the
+/// configured snapshot file.
+#[derive(Deserialize, Default)]
+pub struct DrainRequest {
+    pub grace_period_secs: Option<u64>,
+    pub snapshot: Option<bool>,
+}
+
+pub async fn drain_handler(
+    State(state): State<MetricsState>,
+    body: Option<Json<DrainRequest>>,
+) -> impl IntoResponse {
+    let body = body.map(|Json(b)| b).unwrap_or_default();
+    let mut options = drain::DrainOptions::from_config(&state.app.config.load().shutdown);
+    if let Some(secs) = body.grace_period_secs {
+        options.grace = Duration::from_secs(secs);
+    }
+    match body.snapshot {
+        Some(false) => options.snapshot_file = None,
+        Some(true) if options.snapshot_file.is_none() => {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({"error": "no [shutdown] snapshot_file configured"})),
+            );
+        }
+        _ => {}
+    }
+
+    if !drain::begin(&state.app) {
+        return (
+            axum::http::StatusCode::CONFLICT,
+            Json(serde_json::json!({"error": "already draining"})),
+        );
+    }
+
+    let response = serde_json::json!({
+        "draining": true,
+        "grace_period_secs": options.grace.as_secs(),
+        "snapshot_file": options.snapshot_file,
+    });
+    tokio::spawn(drain::drain(Arc::clone(&state.app), options));
+
+    (axum::http::StatusCode::ACCEPTED, Json(response))
+}
+
+/// POST /api/config/validate — dry-run a candidate config.
+///
+/// The body is TOML text. Returns what hot reload would do with it (see
+/// `config::plan_changes_for`) without applying anything.
+pub async fn validate_config_handler(
+    State(state): State<MetricsState>,
+    body: String,
+) -> impl IntoResponse {
+    let candidate = match Config::parse(&body) {
+        Ok(c) => c,
+        Err(e) => {
+            let errors = e.downcast_ref::<ConfigErrors>().map(|e| &e.0);
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({"valid": false, "error": e.to_string(), "errors": errors})),
+            );
+        }
+    };
+
+    let current = state.app.config.load();
+    let changes = config::plan_changes_for(&current, &candidate, &state.app.cache.load());
+    let valid = !changes
+        .iter()
+        .any(|c| matches!(c, ConfigChange::Rejected { .. }));
+
+    (
+        axum::http::StatusCode::OK,
+        Json(serde_json::json!({"valid": valid, "changes": changes})),
+    )
+}
+
+/// Body of `GET /api/stats`, also what `cluster` reads back from peers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstanceStats {
+    pub primary: PolicyMetrics,
+    /// The first of `comparisons`, for clients that expect a single one.
+    pub comparison: Option<PolicyMetrics>,
+    #[serde(default)]
+    pub comparisons: Vec<PolicyMetrics>,
+    pub mode: String,
+    #[serde(default)]
+    pub read_only: bool,
+    /// Requests per second over the last broadcast window.
+    #[serde(default)]
+    pub throughput_rps: f64,
+    #[serde(default)]
+    pub uptime_seconds: u64,
+    /// Proxy requests being served right now.
+    #[serde(default)]
+    pub in_flight: usize,
+    /// Proxy requests shed by `[load_shedding]` since startup.
+    #[serde(default)]
+    pub shed: u64,
+    /// Primary-cache inserts since startup by source (`http`, `resp`, ...).
+    #[serde(default)]
+    pub inserts: BTreeMap<String, u64>,
+    /// Upstream circuit breaker, when `[upstream.circuit_breaker]` is enabled.
+    #[serde(default)]
+    pub circuit: Option<CircuitStatus>,
+    /// Startup warmup, when `[warmup]` had anything to fetch.
+    #[serde(default)]
+    pub warmup: Option<WarmupStatus>,
+    /// `[cache.refresh_ahead]` fetches started since startup.
+    #[serde(default)]
+    pub refreshes: u64,
+    /// Values not cached for being larger than `max_body_size_bytes`.
+    #[serde(default)]
+    pub oversize_rejections: u64,
+    /// Hits and misses per tenant, when `[cache.tenant]` names a header.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenants: Option<TenantReport>,
+    /// Entries and evictions per `[[cache.quotas]]` group.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub quotas: BTreeMap<String, QuotaReport>,
+}
+
+impl InstanceStats {
+    pub fn collect(state: &MetricsState) -> Self {
+        let cache = state.app.cache.load();
+        let latest = state.latest.load();
+        let mut primary = PolicyMetrics::primary(&cache);
+        let mut comparisons = PolicyMetrics::comparisons(&cache);
+        // Windows only exist in the broadcaster's history
+        if let Some(snapshot) = latest.as_ref() {
+            let windowed = std::iter::once(&snapshot.primary).chain(&snapshot.comparisons);
+            for (m, w) in std::iter::once(&mut primary)
+                .chain(&mut comparisons)
+                .zip(windowed)
+            {
+                if m.name == w.name {
+                    m.windows = w.windows;
+                }
+            }
+        }
+        Self {
+            primary,
+            comparison: comparisons.first().cloned(),
+            comparisons,
+            mode: format!("{:?}", cache.mode()).to_lowercase(),
+            read_only: cache.is_read_only(),
+            throughput_rps: latest.as_ref().map_or(0.0, |s| s.throughput_rps),
+            uptime_seconds: latest.as_ref().map_or(0, |s| s.uptime_seconds),
+            in_flight: state.app.shedder.in_flight(),
+            shed: state.app.shedder.shed_count(),
+            inserts: cache
+                .inserts_by_source()
+                .into_iter()
+                .map(|(source, n)| (source.to_string(), n))
+                .collect(),
+            circuit: state.app.upstreams.circuit().map(|c| c.status()),
+            warmup: state.app.warmup.status(),
+            refreshes: state.app.refresh.count(),
+            oversize_rejections: cache.oversize_rejections(),
+            tenants: (!state.app.config.load().cache.tenant.header.is_empty())
+                .then(|| state.app.tenants.report()),
+            quotas: cache.quota_report(),
+        }
+    }
+}
+
 /// GET /api/stats — one-shot stats endpoint.
 pub async fn stats_handler(State(state): State<MetricsState>) -> impl IntoResponse {
+    Json(InstanceStats::collect(&state))
+}
+
+/// POST /api/stats/reset — zero the hit/miss/eviction counters of every
+/// cache without touching the cached entries.
+pub async fn reset_stats_handler(State(state): State<MetricsState>) -> impl IntoResponse {
     let cache = state.app.cache.load();
-    let primary = PolicyMetrics::from_cache(&cache, true);
-    let comparison = PolicyMetrics::from_cache(&cache, false);
+    cache.reset_stats();
+    state.app.tenants.reset();
+    tracing::info!("cache stats reset");
+    Json(serde_json::json!({"reset": true}))
+}
 
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    /// Seconds of history to return, up to `[server] metrics_history_secs`.
+    #[serde(default = "default_history_window")]
+    pub window: u64,
+}
+
+fn default_history_window() -> u64 {
+    300
+}
+
+/// GET /api/history?window=300 — the broadcast snapshots from the last
+/// `window` seconds, oldest first, to back-fill charts.
+pub async fn history_handler(
+    State(state): State<MetricsState>,
+    Query(query): Query<HistoryQuery>,
+) -> impl IntoResponse {
+    let window = Duration::from_secs(query.window).min(state.history.retention());
+    let snapshots = state.history.last(window);
     Json(serde_json::json!({
-        "primary": primary,
-        "comparison": comparison,
-        "mode": format!("{:?}", cache.mode()).to_lowercase(),
+        "window_secs": window.as_secs(),
+        "snapshots": snapshots,
     }))
 }
+
+/// GET /api/cluster/stats — this instance's stats aggregated with every
+/// `[cluster]` peer's (see `cluster::aggregate`).
+pub async fn cluster_stats_handler(State(state): State<MetricsState>) -> impl IntoResponse {
+    let config = state.app.config.load_full();
+    Json(cluster::aggregate(&state, &config.cluster).await)
+}
+
+#[derive(Deserialize)]
+pub struct HotKeysQuery {
+    #[serde(default = "default_hot_keys_limit")]
+    pub limit: usize,
+}
+
+fn default_hot_keys_limit() -> usize {
+    20
+}
+
+/// GET /api/hotkeys?limit=N — most frequently requested keys, hottest first.
+pub async fn hot_keys_handler(
+    State(state): State<MetricsState>,
+    Query(query): Query<HotKeysQuery>,
+) -> impl IntoResponse {
+    let cache = state.app.cache.load();
+    let keys: Vec<_> = cache
+        .hot_keys(query.limit)
+        .into_iter()
+        .map(|(key, count)| serde_json::json!({"key": key, "count": count}))
+        .collect();
+
+    Json(serde_json::json!({ "keys": keys }))
+}
+
+/// Most keys one `/api/cache/keys` page returns, whatever `limit` asks for.
+const MAX_KEYS_PAGE: usize = 1000;
+
+#[derive(Deserialize)]
+pub struct KeysQuery {
+    #[serde(default)]
+    pub prefix: String,
+    #[serde(default = "default_keys_limit")]
+    pub limit: usize,
+    /// `next_cursor` from the previous page.
+    pub cursor: Option<String>,
+}
+
+fn default_keys_limit() -> usize {
+    100
+}
+
+/// GET /api/cache/keys?prefix=...&limit=N&cursor=... — page through the
+/// primary cache's keys without counting hits or affecting eviction.
+pub async fn cache_keys_handler(
+    State(state): State<MetricsState>,
+    Query(query): Query<KeysQuery>,
+) -> impl IntoResponse {
+    let cache = state.app.cache.load();
+    let page = cache.scan(
+        &query.prefix,
+        query.cursor.as_deref(),
+        query.limit.min(MAX_KEYS_PAGE),
+    );
+    let keys: Vec<_> = page
+        .entries
+        .iter()
+        .map(|(key, entry)| {
+            let age = entry.age();
+            serde_json::json!({
+                "key": key,
+                "size_bytes": entry.body.len(),
+                "age_seconds": age.as_secs(),
+                "ttl_remaining_seconds": entry.ttl.saturating_sub(age).as_secs(),
+                "expired": entry.is_expired(),
+                "hits": entry.hits.get(),
+            })
+        })
+        .collect();
+
+    Json(serde_json::json!({ "keys": keys, "next_cursor": page.next }))
+}
+
+#[derive(Deserialize)]
+pub struct EntryQuery {
+    pub key: String,
+}
+
+/// GET /api/cache/entry?key=... — inspect one primary-cache entry without
+/// counting a hit or affecting eviction.
+pub async fn cache_entry_handler(
+    State(state): State<MetricsState>,
+    Query(query): Query<EntryQuery>,
+) -> impl IntoResponse {
+    let cache = state.app.cache.load();
+    let learned_ttl = cache.learned_ttl(&query.key);
+
+    let Some(entry) = cache.peek(&query.key) else {
+        return (
+            axum::http::StatusCode::NOT_FOUND,
+            Json(serde_json::json!({
+                "error": "key not cached",
+                "key": query.key,
+                "learned_ttl": learned_ttl,
+            })),
+        );
+    };
+
+    let age = entry.age();
+    (
+        axum::http::StatusCode::OK,
+        Json(serde_json::json!({
+            "key": query.key,
+            "status": entry.status,
+            "size_bytes": entry.body.len(),
+            "headers": entry
+                .headers
+                .iter()
+                .map(|(k, v)| (k.as_str(), String::from_utf8_lossy(v.as_bytes())))
+                .collect::<Vec<_>>(),
+            "age_seconds": age.as_secs(),
+            "ttl_seconds": entry.ttl.as_secs(),
+            "ttl_remaining_seconds": entry.ttl.saturating_sub(age).as_secs(),
+            "expired": entry.is_expired(),
+            "hits": entry.hits.get(),
+            "source": entry.source.as_str(),
+            "learned_ttl": learned_ttl,
+        })),
+    )
+}
@@ -0,0 +1,526 @@
+//! `[[cache.plugins]]`: WebAssembly modules hooked into the proxy handler,
+//! for rules too specific to configure, like VCL in Varnish.
+//!
+//! A plugin is a core WebAssembly module with no imports. It exports its
+//! `memory`, `colander_alloc(len: i32) -> i32` (returning where the host
+//! may write `len` bytes), and one or both hooks:
+//!
+//! - `on_request(ptr: i32, len: i32) -> i64` gets a `RequestView` and
+//!   answers with a `RequestAction`: a new cache key, a status to deny the
+//!   request with, or a bypass of the cache.
+//! - `on_response(ptr: i32, len: i32) -> i64` gets a `ResponseView` of the
+//!   origin's response and answers with a `ResponseAction`: headers to set
+//!   or remove, a TTL, or whether to cache it at all.
+//!
+//! Views and actions are JSON. A hook returns `(ptr << 32) | len` of its
+//! action in its memory, or 0 to change nothing. Calls run on tokio's
+//! blocking pool, each bounded by the plugin's `fuel` and in an instance
+//! capped at `max_memory_bytes`. Instances are kept between calls, so a
+//! module's globals and memory outlive a call; one that traps, runs out or
+//! answers with something unreadable is dropped, logged, counted in
+//! `colander_plugin_errors_total` and treated as having changed nothing.
+
+use crate::config::PluginConfig;
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
+#[cfg(feature = "wasm")]
+use std::sync::Arc;
+
+/// What `on_request` sees.
+#[derive(Debug, Serialize)]
+pub struct RequestView<'a> {
+    pub method: &'a str,
+    pub uri: String,
+    pub headers: Vec<(&'a str, String)>,
+    /// The key the request would be cached under without the plugin.
+    pub key: String,
+    pub tenant: Option<&'a str>,
+}
+
+/// What `on_request` wants done; every field is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RequestAction {
+    /// Cache the request under this key instead. With `[cache.bypass]` in
+    /// `per_user` mode, private requests still get their user suffix.
+    pub key: Option<String>,
+    /// Answer with this status without consulting cache or origin.
+    pub deny: Option<u16>,
+    /// Send the request to the origin and don't cache the response.
+    pub bypass: bool,
+}
+
+/// What `on_response` sees.
+#[derive(Debug, Serialize)]
+pub struct ResponseView<'a> {
+    pub method: &'a str,
+    pub uri: String,
+    pub key: &'a str,
+    pub status: u16,
+    pub headers: Vec<(&'a str, String)>,
+}
+
+/// What `on_response` wants done; every field is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ResponseAction {
+    /// Headers to add or replace, on the response and the cached entry.
+    pub set_headers: Vec<(String, String)>,
+    pub remove_headers: Vec<String>,
+    /// Cache for this long, whatever `Cache-Control` says.
+    pub ttl_seconds: Option<u64>,
+    /// Cache (or not) regardless of `Cache-Control`. Only status 200
+    /// responses to `GET` are ever cached.
+    pub cache: Option<bool>,
+}
+
+impl ResponseAction {
+    /// Apply the header changes to `headers`, skipping invalid ones.
+    pub fn apply_headers(&self, headers: &mut HeaderMap) {
+        for name in &self.remove_headers {
+            headers.remove(name.as_str());
+        }
+        for (name, value) in &self.set_headers {
+            match (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(value),
+            ) {
+                (Ok(name), Ok(value)) => {
+                    headers.insert(name, value);
+                }
+                _ => tracing::warn!(header = %name, "plugin set an invalid header, skipped"),
+            }
+        }
+    }
+}
+
+/// `headers` as a view's `(name, value)` pairs.
+pub fn header_pairs(headers: &HeaderMap) -> Vec<(&str, String)> {
+    headers
+        .iter()
+        .map(|(k, v)| {
+            (
+                k.as_str(),
+                String::from_utf8_lossy(v.as_bytes()).into_owned(),
+            )
+        })
+        .collect()
+}
+
+/// A hook call from `Plugins::on_request` or `on_response`, holding its
+/// serialized view; `finish` runs it.
+pub struct HookCall<A> {
+    #[cfg(feature = "wasm")]
+    call: Option<wasm::Pending>,
+    action: PhantomData<fn() -> A>,
+}
+
+impl<A: DeserializeOwned + Send + 'static> HookCall<A> {
+    /// What the hook wants done, or `None` if there was no hook to call or
+    /// it failed.
+    pub async fn finish(self) -> Option<A> {
+        #[cfg(feature = "wasm")]
+        if let Some(call) = self.call {
+            return call.run().await;
+        }
+        None
+    }
+}
+
+/// The loaded `[[cache.plugins]]`, by route.
+#[derive(Default)]
+pub struct Plugins {
+    #[cfg(feature = "wasm")]
+    routes: Vec<(String, Arc<wasm::Plugin>)>,
+}
+
+impl Plugins {
+    /// Compile every configured module. Without the `wasm` feature only an
+    /// empty list loads.
+    pub fn load(configs: &[PluginConfig]) -> Result<Self, String> {
+        #[cfg(feature = "wasm")]
+        {
+            let engine = wasm::engine()?;
+            let routes = configs
+                .iter()
+                .map(|config| {
+                    let plugin = wasm::Plugin::load(&engine, config)?;
+                    Ok((config.path.clone(), Arc::new(plugin)))
+                })
+                .collect::<Result<_, String>>()?;
+            Ok(Self { routes })
+        }
+        #[cfg(not(feature = "wasm"))]
+        match configs {
+            [] => Ok(Self::default()),
+            _ => Err("[[cache.plugins]] needs a build with the `wasm` feature".to_string()),
+        }
+    }
+
+    /// Start the `on_request` hook of the plugin for `path`, if it has
+    /// one. `view` is only built when it does, and is serialized before this
+    /// returns, so the call doesn't borrow the request.
+    pub fn on_request<'a>(
+        &self,
+        path: &str,
+        view: impl FnOnce() -> RequestView<'a>,
+    ) -> HookCall<RequestAction> {
+        self.hook(path, "on_request", view)
+    }
+
+    /// Start the `on_response` hook of the plugin for `path`, if it has one.
+    pub fn on_response<'a>(
+        &self,
+        path: &str,
+        view: impl FnOnce() -> ResponseView<'a>,
+    ) -> HookCall<ResponseAction> {
+        self.hook(path, "on_response", view)
+    }
+
+    fn hook<V: Serialize, A>(
+        &self,
+        path: &str,
+        hook: &'static str,
+        view: impl FnOnce() -> V,
+    ) -> HookCall<A> {
+        #[cfg(feature = "wasm")]
+        let call = self
+            .route(path)
+            .filter(|plugin| plugin.has(hook))
+            .map(|plugin| wasm::Pending {
+                plugin: Arc::clone(plugin),
+                hook,
+                input: serde_json::to_vec(&view()),
+            });
+        #[cfg(not(feature = "wasm"))]
+        let _ = (path, hook, view);
+        HookCall {
+            #[cfg(feature = "wasm")]
+            call,
+            action: PhantomData,
+        }
+    }
+
+    #[cfg(feature = "wasm")]
+    fn route(&self, path: &str) -> Option<&Arc<wasm::Plugin>> {
+        self.routes
+            .iter()
+            .find(|(pattern, _)| crate::http_key::matches(pattern, path))
+            .map(|(_, plugin)| plugin)
+    }
+}
+
+#[cfg(feature = "wasm")]
+mod wasm {
+    use crate::config::PluginConfig;
+    use parking_lot::Mutex;
+    use serde::de::DeserializeOwned;
+    use std::path::Path;
+    use std::sync::Arc;
+    use wasmtime::{
+        Config, Engine, Instance, InstancePre, Linker, Module, Store, StoreLimits,
+        StoreLimitsBuilder,
+    };
+
+    const ALLOC: &str = "colander_alloc";
+    const HOOKS: [&str; 2] = ["on_request", "on_response"];
+    /// Instances each plugin keeps between calls. More calls than this at
+    /// once instantiate their own, dropped when they're done.
+    const IDLE_INSTANCES: usize = 16;
+
+    pub(super) fn engine() -> Result<Engine, String> {
+        Engine::new(Config::new().consume_fuel(true))
+            .map_err(|e| format!("[[cache.plugins]] engine: {e:#}"))
+    }
+
+    pub(super) struct Plugin {
+        /// The module's file stem, for logs and metrics.
+        name: String,
+        instance: InstancePre<StoreLimits>,
+        hooks: Vec<&'static str>,
+        fuel: u64,
+        max_memory_bytes: usize,
+        /// Instances whose last call succeeded, ready for the next one.
+        idle: Mutex<Vec<(Store<StoreLimits>, Instance)>>,
+    }
+
+    /// A hook call that hasn't run yet.
+    pub(super) struct Pending {
+        pub(super) plugin: Arc<Plugin>,
+        pub(super) hook: &'static str,
+        /// The serialized view.
+        pub(super) input: serde_json::Result<Vec<u8>>,
+    }
+
+    impl Pending {
+        /// Call the hook on the blocking pool, so a slow one holds up its own
+        /// request only. Failures are logged and counted, and change nothing.
+        pub(super) async fn run<A: DeserializeOwned + Send + 'static>(self) -> Option<A> {
+            let Self {
+                plugin,
+                hook,
+                input,
+            } = self;
+            let result = match input {
+                Ok(input) => {
+                    let runner = Arc::clone(&plugin);
+                    tokio::task::spawn_blocking(move || runner.run(hook, &input))
+                        .await
+                        .unwrap_or_else(|e| Err(e.into()))
+                }
+                Err(e) => Err(e.into()),
+            };
+            match result {
+                Ok(action) => action,
+                Err(e) => {
+                    tracing::warn!(plugin = %plugin.name, hook, error = %format_args!("{e:#}"), "plugin call failed");
+                    ::metrics::counter!(
+                        "colander_plugin_errors_total",
+                        "plugin" => plugin.name.clone(),
+                        "hook" => hook
+                    )
+                    .increment(1);
+                    None
+                }
+            }
+        }
+    }
+
+    impl Plugin {
+        pub(super) fn load(engine: &Engine, config: &PluginConfig) -> Result<Self, String> {
+            let error = |e: String| format!("[[cache.plugins]] {:?}: {e}", config.module);
+            let module =
+                Module::from_file(engine, &config.module).map_err(|e| error(format!("{e:#}")))?;
+            let exports: Vec<&str> = module.exports().map(|e| e.name()).collect();
+            for needed in ["memory", ALLOC] {
+                if !exports.contains(&needed) {
+                    return Err(error(format!("doesn't export `{needed}`")));
+                }
+            }
+            let hooks: Vec<_> = HOOKS.into_iter().filter(|h| exports.contains(h)).collect();
+            if hooks.is_empty() {
+                return Err(error(
+                    "exports neither `on_request` nor `on_response`".into(),
+                ));
+            }
+            // Plugins get nothing from the host but their input
+            let instance = Linker::new(engine)
+                .instantiate_pre(&module)
+                .map_err(|e| error(format!("{e:#}")))?;
+            let name = Path::new(&config.module).file_stem().map_or_else(
+                || config.module.clone(),
+                |s| s.to_string_lossy().into_owned(),
+            );
+            tracing::info!(plugin = %name, path = %config.path, ?hooks, "plugin loaded");
+            Ok(Self {
+                name,
+                instance,
+                hooks,
+                fuel: config.fuel,
+                max_memory_bytes: config.max_memory_bytes,
+                idle: Mutex::default(),
+            })
+        }
+
+        pub(super) fn has(&self, hook: &str) -> bool {
+            self.hooks.contains(&hook)
+        }
+
+        /// Run `hook` on an idle instance, or a new one if none is. The
+        /// instance goes back to the pool only if the call succeeded: one
+        /// that failed may be left in any state.
+        fn run<A: DeserializeOwned>(
+            &self,
+            hook: &str,
+            input: &[u8],
+        ) -> wasmtime::Result<Option<A>> {
+            let (mut store, instance) = match self.idle.lock().pop() {
+                Some(idle) => idle,
+                None => self.instantiate()?,
+            };
+            store.set_fuel(self.fuel)?;
+            let action = Self::invoke(&mut store, &instance, hook, input)?;
+            let mut idle = self.idle.lock();
+            if idle.len() < IDLE_INSTANCES {
+                idle.push((store, instance));
+            }
+            Ok(action)
+        }
+
+        fn instantiate(&self) -> wasmtime::Result<(Store<StoreLimits>, Instance)> {
+            let limits = StoreLimitsBuilder::new()
+                .memory_size(self.max_memory_bytes)
+                .build();
+            let mut store = Store::new(self.instance.module().engine(), limits);
+            store.limiter(|limits| limits);
+            // Start-up code runs on the same budget as a call
+            store.set_fuel(self.fuel)?;
+            let instance = self.instance.instantiate(&mut store)?;
+            Ok((store, instance))
+        }
+
+        fn invoke<A: DeserializeOwned>(
+            store: &mut Store<StoreLimits>,
+            instance: &Instance,
+            hook: &str,
+            input: &[u8],
+        ) -> wasmtime::Result<Option<A>> {
+            let memory = instance
+                .get_memory(&mut *store, "memory")
+                .ok_or_else(|| wasmtime::Error::msg("`memory` isn't a memory"))?;
+
+            let alloc = instance.get_typed_func::<i32, i32>(&mut *store, ALLOC)?;
+            let ptr = alloc.call(&mut *store, i32::try_from(input.len())?)?;
+            memory.write(&mut *store, ptr as u32 as usize, input)?;
+
+            let hook = instance.get_typed_func::<(i32, i32), i64>(&mut *store, hook)?;
+            let packed = hook.call(&mut *store, (ptr, input.len() as i32))? as u64;
+            if packed == 0 {
+                return Ok(None);
+            }
+            let (ptr, len) = ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize);
+            let output = memory
+                .data(&*store)
+                .get(ptr..ptr.saturating_add(len))
+                .ok_or_else(|| {
+                    wasmtime::Error::msg(format!("action at {ptr}+{len} is out of bounds"))
+                })?;
+            Ok(Some(serde_json::from_slice(output)?))
+        }
+    }
+}
+
+#[cfg(all(test, feature = "wasm"))]
+mod tests {
+    use super::*;
+
+    // `on_request` denies with 401 on an instance's first call and 402
+    // after; `on_response` never returns
+    const MODULE: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (global $calls (mut i32) (i32.const 0))
+          (data (i32.const 0) "{\"deny\":401}{\"deny\":402}")
+          (func (export "colander_alloc") (param i32) (result i32)
+            i32.const 1024)
+          (func (export "on_request") (param i32 i32) (result i64)
+            (global.set $calls (i32.add (global.get $calls) (i32.const 1)))
+            (i64.or
+              (i64.shl
+                (i64.extend_i32_u
+                  (select (i32.const 0) (i32.const 12)
+                    (i32.eq (global.get $calls) (i32.const 1))))
+                (i64.const 32))
+              (i64.const 12)))
+          (func (export "on_response") (param i32 i32) (result i64)
+            (loop $spin (br $spin))
+            i64.const 0))
+    "#;
+
+    /// Write `MODULE` for `test` and configure it for `/api/*`.
+    fn plugin_config(test: &str) -> PluginConfig {
+        let path = std::env::temp_dir().join(format!(
+            "colander-plugin-{test}-{}.wasm",
+            std::process::id()
+        ));
+        std::fs::write(&path, wat::parse_str(MODULE).unwrap()).unwrap();
+        PluginConfig {
+            path: "/api/*".into(),
+            module: path.to_string_lossy().into_owned(),
+            fuel: 100_000,
+            max_memory_bytes: 1 << 20,
+        }
+    }
+
+    fn load() -> Plugins {
+        let config = plugin_config("load");
+        let plugins = Plugins::load(std::slice::from_ref(&config));
+        std::fs::remove_file(&config.module).unwrap();
+        plugins.unwrap()
+    }
+
+    fn request() -> RequestView<'static> {
+        RequestView {
+            method: "GET",
+            uri: "/api/items".into(),
+            headers: Vec::new(),
+            key: "GET:/api/items".into(),
+            tenant: None,
+        }
+    }
+
+    fn response() -> ResponseView<'static> {
+        ResponseView {
+            method: "GET",
+            uri: "/api/items".into(),
+            key: "GET:/api/items",
+            status: 200,
+            headers: Vec::new(),
+        }
+    }
+
+    async fn deny(plugins: &Plugins) -> Option<u16> {
+        plugins
+            .on_request("/api/items", request)
+            .finish()
+            .await
+            .and_then(|action| action.deny)
+    }
+
+    #[tokio::test]
+    async fn instances_are_reused_until_a_call_fails() {
+        let plugins = load();
+        assert_eq!(deny(&plugins).await, Some(401));
+        assert_eq!(deny(&plugins).await, Some(402));
+
+        // Runs out of fuel on the same instance, which is dropped
+        let action = plugins.on_response("/api/items", response).finish().await;
+        assert!(action.is_none());
+        assert_eq!(deny(&plugins).await, Some(401));
+
+        assert!(plugins
+            .on_request("/other", request)
+            .finish()
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn tunnel_requests_are_denied_too() {
+        use crate::config::{Config, ListenerRole};
+        use crate::server::Server;
+        use axum::body::Body;
+        use axum::http::{header, Request};
+        use hyper_util::client::legacy::Client;
+        use hyper_util::rt::TokioExecutor;
+
+        let mut config = Config::default_config();
+        config.server.listen_addr = "127.0.0.1:0".into();
+        config.server.metrics_addr = "127.0.0.1:0".into();
+        config.cache.plugins = vec![plugin_config("tunnel")];
+        let server = Server::start(config.clone()).await.unwrap();
+        std::fs::remove_file(&config.cache.plugins[0].module).unwrap();
+        let addr = server.handle().addr(ListenerRole::Proxy).unwrap();
+
+        let client = Client::builder(TokioExecutor::new()).build_http::<Body>();
+        for (name, value) in [
+            (header::ACCEPT, "text/event-stream"),
+            (header::UPGRADE, "websocket"),
+        ] {
+            let req = Request::get(format!("http://{addr}/api/events"))
+                .header(header::CONNECTION, "upgrade")
+                .header(name, value)
+                .body(Body::empty())
+                .unwrap();
+            let resp = client.request(req).await.unwrap();
+            assert!(
+                resp.status().is_client_error(),
+                "{value}: {}",
+                resp.status()
+            );
+        }
+        server.handle().shutdown();
+    }
+}
@@ -1,122 +1,651 @@
-use crate::cache_layer::{parse_cache_control, CacheLayer};
+use crate::adaptive_ttl;
+use crate::bypass;
+use crate::cache_layer::{parse_cache_control, CacheControl, CacheLayer};
+use crate::circuit::CircuitBreaker;
+use crate::cluster;
+use crate::config::{BypassMode, Config, StoredHeadersConfig};
+use crate::directives::Directives;
+use crate::forwarded;
+use crate::hop_by_hop;
+use crate::http_key::HttpKey;
+use crate::metrics::LatencyRecorder;
+use crate::plugin::{self, Plugins, RequestView, ResponseView};
+use crate::pubsub::PubSub;
+use crate::range;
+use crate::refresh::{self, RefreshAhead};
+use crate::shed::LoadShedder;
+use crate::tenant::{self, TenantStats};
+use crate::tunnel;
+use crate::upstream::{Backend, UpstreamPool};
+use crate::warmup::WarmupProgress;
+use crate::writes;
 use arc_swap::ArcSwap;
 use axum::body::Body;
 use axum::extract::State;
-use axum::http::{HeaderMap, HeaderValue, Request, Response, StatusCode};
+use axum::http::header::{
+    AGE, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, DATE, HOST, IF_RANGE, RANGE, RETRY_AFTER,
+};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode, Uri};
+use bytes::Bytes;
+use colander_cache::fill::Claim;
+use colander_cache::keys::KeyBuilder;
+use colander_cache::traits::{CachedResponse, InsertSource};
+use futures_util::future::BoxFuture;
 use http_body_util::BodyExt;
 use hyper_util::client::legacy::Client;
+use parking_lot::Mutex;
+use std::cell::RefCell;
+use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant, SystemTime};
+use tokio_util::sync::CancellationToken;
 
-pub type HttpClient = Client<hyper_util::client::legacy::connect::HttpConnector, Body>;
+/// Key buffers that grew past this (huge URIs) are released instead of kept.
+const KEY_BUF_RETAIN: usize = 4096;
+
+thread_local! {
+    /// Reused buffer for building lookup keys so cache hits don't allocate one.
+    static KEY_BUF: RefCell<String> = RefCell::new(String::with_capacity(256));
+}
+
+/// Upstream client: plain HTTP or TLS per backend URL (see `upstream::build_client`).
+pub type HttpClient =
+    Client<hyper_rustls::HttpsConnector<hyper_util::client::legacy::connect::HttpConnector>, Body>;
 
 /// Shared application state passed to all handlers.
 pub struct AppState {
     pub cache: ArcSwap<CacheLayer>,
     pub client: HttpClient,
-    pub upstream_url: String,
+    pub upstreams: Arc<UpstreamPool>,
+    pub latency: LatencyRecorder,
+    /// False until startup warmup has finished (see `/readyz`).
+    pub ready: AtomicBool,
+    /// Config currently in effect, updated by hot reload.
+    pub config: ArcSwap<Config>,
+    /// Serializes config changes (hot reload, RESP `CONFIG SET`) so two in
+    /// quick succession can't diff against the same old config.
+    pub config_lock: Mutex<()>,
+    /// Set once a drain has started; `/readyz` fails from then on.
+    pub draining: AtomicBool,
+    /// Fired to close every listener (see `drain`).
+    pub shutdown: CancellationToken,
+    /// In-flight proxy requests and overload shedding (see `shed`).
+    pub shedder: LoadShedder,
+    /// When the server started, for uptime reporting.
+    pub started_at: Instant,
+    /// RESP publish/subscribe, including keyspace events from the cache.
+    pub pubsub: Arc<PubSub>,
+    /// Startup warmup progress, for `/api/stats`.
+    pub warmup: WarmupProgress,
+    /// `[cache.refresh_ahead]` fetches under way (see `refresh`).
+    pub refresh: RefreshAhead,
+    /// Cache hits and misses per `[cache.tenant]` tenant.
+    pub tenants: TenantStats,
+    /// Fills misses in place of the `[upstream]` backends, if set (see
+    /// `OriginFetcher`).
+    pub origin: Option<Arc<dyn OriginFetcher>>,
+    /// `[[cache.plugins]]`, loaded at startup.
+    pub plugins: Plugins,
+}
+
+/// Where misses are filled from when that isn't an HTTP server: a gRPC
+/// service, object storage, a database query. Plugged in with
+/// `Server::start_with_origin`, it's called once per request that goes to
+/// the origin, in place of `[upstream]`'s backends, retries and circuit
+/// breaker. Its responses are cached by the same rules as an upstream's:
+/// status 200, with `Cache-Control` deciding whether and for how long.
+///
+/// ```no_run
+/// use axum::http::{HeaderMap, HeaderValue, StatusCode};
+/// use futures_util::future::BoxFuture;
+/// use proxy_server::proxy::{OriginError, OriginFetcher, OriginRequest, OriginResponse};
+///
+/// /// Serves `/users/<id>` from a table, fresh for five minutes.
+/// struct Users(std::collections::HashMap<String, String>);
+///
+/// impl OriginFetcher for Users {
+///     fn fetch(&self, req: OriginRequest) -> BoxFuture<'_, Result<OriginResponse, OriginError>> {
+///         Box::pin(async move {
+///             let id = req.uri.path().trim_start_matches("/users/");
+///             let mut headers = HeaderMap::new();
+///             headers.insert("cache-control", HeaderValue::from_static("max-age=300"));
+///             Ok(match self.0.get(id) {
+///                 Some(row) => OriginResponse::new(StatusCode::OK, headers, row.clone()),
+///                 None => OriginResponse::new(StatusCode::NOT_FOUND, HeaderMap::new(), ""),
+///             })
+///         })
+///     }
+/// }
+/// ```
+pub trait OriginFetcher: Send + Sync {
+    /// Fetch the response to `req`, whole. `[upstream] timeout_ms` and
+    /// `[upstream.concurrency]` bound the call as they would an HTTP one.
+    fn fetch(&self, req: OriginRequest) -> BoxFuture<'_, Result<OriginResponse, OriginError>>;
+}
+
+/// A request for an `OriginFetcher`, as the client sent it.
+#[derive(Debug, Clone)]
+pub struct OriginRequest {
+    pub method: Method,
+    pub uri: Uri,
+    /// The client's headers, unfiltered.
+    pub headers: HeaderMap,
+    pub body: Bytes,
+    /// The key a cacheable response will be stored under.
+    pub key: String,
+    /// The request's `[cache.tenant]` tenant, if any.
+    pub tenant: Option<String>,
+}
+
+/// An origin's answer to an `OriginRequest`.
+#[derive(Debug, Clone)]
+pub struct OriginResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl OriginResponse {
+    pub fn new(status: StatusCode, headers: HeaderMap, body: impl Into<Bytes>) -> Self {
+        Self {
+            status,
+            headers,
+            body: body.into(),
+        }
+    }
+}
+
+/// Why an `OriginFetcher` has no response. Clients get a `502` or a `504`.
+#[derive(Debug)]
+pub enum OriginError {
+    Failed(Box<dyn std::error::Error + Send + Sync>),
+    Timeout,
+}
+
+impl std::fmt::Display for OriginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OriginError::Failed(e) => write!(f, "origin fetch failed: {e}"),
+            OriginError::Timeout => f.write_str("origin fetch timed out"),
+        }
+    }
 }
 
+impl std::error::Error for OriginError {}
+
 /// Main proxy handler. Checks cache, forwards to upstream on miss, caches response.
 pub async fn proxy_handler(
     State(state): State<Arc<AppState>>,
-    req: Request<Body>,
+    mut req: Request<Body>,
 ) -> Response<Body> {
     let start = Instant::now();
     let method = req.method().clone();
     let uri = req.uri().clone();
+    // Internal callers (the warmer) tag their fills with an extension
+    let source = req
+        .extensions()
+        .get::<InsertSource>()
+        .copied()
+        .unwrap_or_default();
+
+    let config = state.config.load();
+    let bypass = &config.cache.bypass;
+    // Each tenant gets keys of its own. Owned, since `req` is taken apart
+    // before the keys are last needed.
+    let tenant_config = &config.cache.tenant;
+    let tenant = tenant::tenant(tenant_config, req.headers()).map(str::to_string);
+    let keys = HttpKey::new(&config.cache.key).with_tenant(tenant.as_deref());
+    // HEAD is answered from the GET entry, so it's keyed like one
+    let head = method == Method::HEAD;
+    let key_method = if head { Method::GET } else { method.clone() };
+
+    // Requests carrying credentials never share entries with other users:
+    // they either skip the cache or get keys of their own
+    let credentials = bypass::private_credentials(bypass, req.headers(), &uri);
+    let untenanted = tenant.is_none() && tenant_config.required && !tenant_config.header.is_empty();
+    // A `[[cache.plugins]]` hook may rekey the request, turn it away or
+    // send it past the cache. It runs before the tunnel check, so no
+    // request header gets around its `deny`.
+    let action = state
+        .plugins
+        .on_request(uri.path(), || RequestView {
+            method: method.as_str(),
+            uri: uri.to_string(),
+            headers: plugin::header_pairs(req.headers()),
+            key: cache_key(&keys, &key_method, &uri),
+            tenant: tenant.as_deref(),
+        })
+        .finish()
+        .await
+        .unwrap_or_default();
+    if let Some(status) = action.deny {
+        let status = StatusCode::from_u16(status).unwrap_or(StatusCode::FORBIDDEN);
+        return Response::builder()
+            .status(status)
+            .body(Body::from(status.canonical_reason().unwrap_or_default()))
+            .unwrap();
+    }
+
+    // WebSockets and event streams can't be buffered, let alone cached
+    if let Some(kind) = tunnel::classify(req.headers()) {
+        // Tunnels can stay open for hours; don't pin this config for them
+        drop(config);
+        return tunnel::forward(&state, req, kind).await;
+    }
+
+    // The client's own say: its Cache-Control and the debugging bypass header
+    let directives = Directives::from_request(&config.cache.directives, req.headers());
+    directives.record();
+    let bypassed = untenanted
+        || directives.bypass
+        || action.bypass
+        || (credentials.is_some() && bypass.mode == BypassMode::Bypass);
+    // The key to use instead of the usual one, if the plugin or a per-user
+    // credential calls for it
+    let user_key = match &credentials {
+        Some(creds) if bypass.mode == BypassMode::PerUser => Some(bypass::per_user_key(
+            &action
+                .key
+                .unwrap_or_else(|| cache_key(&keys, &key_method, &uri)),
+            creds,
+        )),
+        _ => action.key,
+    };
+    if bypassed {
+        ::metrics::counter!("colander_cache_bypass_total").increment(1);
+    }
 
-    // Only cache GET requests
-    let cacheable_method = method == axum::http::Method::GET;
+    // Only cache GET requests. HEAD reads their entries but never fills
+    // them: its response has no body to store.
+    let cacheable_method = method == Method::GET && !bypassed;
+    let readable_method = cacheable_method || (head && !bypassed);
 
-    let cache_key = format!("{}:{}", method, uri);
+    // With cluster routing, each cacheable key is cached by one instance;
+    // others forward to it rather than keep a copy of their own
+    let node_url = config.cluster.node_url.as_deref().filter(|_| {
+        readable_method
+            && !config.cluster.nodes.is_empty()
+            && !req.headers().contains_key(cluster::FORWARDED_BY)
+    });
+    if let Some(node_url) = node_url {
+        let nodes = &config.cluster.nodes;
+        let owner = match &user_key {
+            Some(key) => cluster::owner(nodes, key),
+            None => with_cache_key(&keys, &key_method, &uri, |key| cluster::owner(nodes, key)),
+        };
+        if let Some(owner) = owner.filter(|&owner| owner != node_url.trim_end_matches('/')) {
+            let (parts, body) = req.into_parts();
+            match cluster::forward(&state, owner, node_url, &parts).await {
+                Ok(response) => {
+                    ::metrics::counter!("colander_cluster_forwarded_total", "node" => owner.to_string())
+                        .increment(1);
+                    return response;
+                }
+                Err(e) => {
+                    // Better to cache a second copy than fail the request
+                    tracing::warn!(node = owner, error = %e, "cluster owner unreachable, serving locally");
+                    ::metrics::counter!("colander_cluster_forward_errors_total", "node" => owner.to_string())
+                        .increment(1);
+                    req = Request::from_parts(parts, body);
+                }
+            }
+        }
+    }
 
     let cache = state.cache.load();
+    // Read-only caches still serve hits, but misses pass through uncached
+    let read_only = cache.is_read_only();
 
-    // Check cache for GET requests
-    if cacheable_method {
-        let lookup = cache.get(&cache_key);
-        if lookup.is_hit() {
-            let cached = lookup.value.unwrap(); // safe: guarded by is_hit()
+    let circuit = state.upstreams.circuit();
+    // While the circuit is open an expired entry beats an error, so don't let
+    // `get` drop it. Unless the origin said it must be revalidated: then it
+    // gets the error like any other miss.
+    let keep_stale = circuit.is_some_and(|c| c.serves_stale());
+    let mut stale = None;
+
+    // Check cache for GET requests. Refreshes and `no-cache` requests are
+    // after a newer copy than the one cached, so they always go upstream.
+    if readable_method && source != InsertSource::Refresh && !directives.no_cache {
+        let lookup = |key: &str| {
+            if keep_stale {
+                let servable = |e: &Arc<CachedResponse>| e.is_expired() && !e.must_revalidate;
+                if let Some(entry) = cache.peek(key).filter(servable) {
+                    return (None, Some(entry));
+                }
+            }
+            let lookup = cache.get(key);
+            if lookup.is_hit() {
+                tracing::debug!(key, comparison_hits = lookup.comparison_hits, "cache HIT");
+            }
+            (lookup.value, None)
+        };
+        let (fresh, expired) = match &user_key {
+            Some(key) => lookup(key),
+            None => with_cache_key(&keys, &key_method, &uri, lookup),
+        };
+        if let Some(tenant) = &tenant {
+            state
+                .tenants
+                .record(tenant, fresh.is_some(), tenant_config.max_tracked);
+        }
+        if let Some(cached) = fresh {
             let elapsed = start.elapsed();
+            state.latency.record_hit(elapsed);
+            let response = build_cached_response(&cached, &cache);
+            // Per-user entries aren't refreshed: the refetch has no credentials
+            if user_key.is_none() && !read_only {
+                refresh::maybe_refresh(&state, &config, &uri, tenant.as_deref(), &cached);
+            }
+            return serve_cached(&method, req.headers(), &cached, response);
+        }
+        stale = expired;
+    }
 
-            tracing::debug!(
-                key = %cache_key,
-                latency_us = elapsed.as_micros(),
-                "cache HIT"
-            );
+    if directives.only_if_cached {
+        let mut response = Response::builder()
+            .status(StatusCode::GATEWAY_TIMEOUT)
+            .body(Body::from("Gateway Timeout"))
+            .unwrap();
+        append_cache_headers(response.headers_mut(), "MISS", &cache);
+        return response;
+    }
 
-            return build_cached_response(&cached, &cache, true);
+    if let Some(circuit) = circuit.filter(|c| !c.allow()) {
+        ::metrics::counter!("colander_upstream_circuit_rejected_total").increment(1);
+        if let Some(cached) = stale {
+            tracing::debug!(uri = %uri, "circuit open, serving stale");
+            state.latency.record_hit(start.elapsed());
+            let mut response = build_cached_response(&cached, &cache);
+            append_cache_headers(response.headers_mut(), "STALE", &cache);
+            return serve_cached(&method, req.headers(), &cached, response);
         }
+        return circuit_open_response(circuit);
     }
 
-    // Cache miss — forward to upstream
-    let upstream_uri = format!(
-        "{}{}",
-        state.upstream_url.trim_end_matches('/'),
-        uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("/")
-    );
+    // Misses need an owned key for insertion and logging
+    let cache_key = user_key.unwrap_or_else(|| cache_key(&keys, &key_method, &uri));
 
-    let upstream_req = match Request::builder()
-        .method(&method)
-        .uri(&upstream_uri)
-        .body(req.into_body())
+    // Concurrent misses for a key share the first one's upstream fetch. If
+    // it isn't cacheable, the rest are let go to fetch their own.
+    let mut fill = None;
+    if cacheable_method
+        && !read_only
+        && !directives.no_store
+        && source != InsertSource::Refresh
+        && config.cache.coalesce_misses
     {
-        Ok(r) => r,
-        Err(e) => {
-            tracing::error!(error = %e, "failed to build upstream request");
-            return Response::builder()
-                .status(StatusCode::BAD_GATEWAY)
-                .body(Body::from("Bad Gateway"))
-                .unwrap();
+        match cache.claim_fill(&cache_key) {
+            Claim::Leader(guard) => fill = Some(guard),
+            Claim::Follower(waiter) => {
+                if let Some(cached) = waiter.await {
+                    ::metrics::counter!("colander_coalesced_requests_total").increment(1);
+                    state.latency.record_miss(start.elapsed());
+                    let response = build_cached_response(&cached, &cache);
+                    return serve_cached(&method, req.headers(), &cached, response);
+                }
+            }
         }
+    }
+
+    // Cache miss — forward to upstream. Retried requests need their body
+    // again, so it's buffered up front.
+    let retry = &config.upstream.retry;
+    let attempts = if retry.max_retries > 0 && method.is_idempotent() {
+        1 + retry.max_retries
+    } else {
+        1
     };
+    let (parts, body) = req.into_parts();
+    // An origin fetcher takes the body whole
+    let (mut body, replay) = if attempts > 1 || state.origin.is_some() {
+        match body.collect().await {
+            Ok(collected) => (None, collected.to_bytes()),
+            Err(e) => {
+                tracing::debug!(error = %e, "failed to read request body");
+                return Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(Body::from("Bad Request"))
+                    .unwrap();
+            }
+        }
+    } else {
+        (Some(body), Bytes::new())
+    };
+    let per_try_timeout =
+        (retry.per_try_timeout_ms > 0).then(|| Duration::from_millis(retry.per_try_timeout_ms));
+
+    let deadline = state
+        .upstreams
+        .request_timeout()
+        .map(|t| Instant::now() + t);
 
-    let upstream_resp = match state.client.request(upstream_req).await {
+    let mut forwarding = HeaderMap::new();
+    forwarded::set_headers(&config.upstream.forwarded, &parts, &mut forwarding);
+
+    let mut attempt = 0;
+    let upstream = loop {
+        // A fetcher of the user's own replaces the HTTP backends entirely
+        if let Some(origin) = &state.origin {
+            let request = OriginRequest {
+                method: method.clone(),
+                uri: uri.clone(),
+                headers: parts.headers.clone(),
+                body: replay.clone(),
+                key: cache_key.clone(),
+                tenant: tenant.clone(),
+            };
+            let Some(slot) = state.upstreams.acquire(deadline).await else {
+                tracing::debug!(uri = %uri, "upstream concurrency limit reached");
+                return upstream_busy_response();
+            };
+            let result = fetch_origin(&state, origin.as_ref(), request, deadline).await;
+            drop(slot);
+            break result;
+        }
+
+        let backend = state.upstreams.select();
+        let upstream_uri = format!(
+            "{}{}",
+            backend.url,
+            uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("/")
+        );
+
+        let mut upstream_req = Request::builder().method(&method).uri(&upstream_uri);
+        if let Some(host) = backend.host_header(config.upstream.host_header, &parts) {
+            upstream_req = upstream_req.header(HOST, host);
+        }
+        if credentials.is_some() {
+            // Pass the credentials on so the origin can authenticate the request
+            let cookie = (!bypass.cookies.is_empty()).then_some("cookie");
+            for name in bypass.headers.iter().map(String::as_str).chain(cookie) {
+                for value in parts.headers.get_all(name) {
+                    upstream_req = upstream_req.header(name, value.clone());
+                }
+            }
+        }
+        if let Some(tenant) = &tenant {
+            upstream_req = upstream_req.header(tenant_config.header.as_str(), tenant.as_str());
+        }
+        // Ranges the cache couldn't answer go to the origin (its 206 isn't
+        // cached since only whole 200s are), and request bodies need their
+        // content headers to mean anything to it
+        for name in [RANGE, IF_RANGE, CONTENT_TYPE, CONTENT_ENCODING] {
+            for value in parts.headers.get_all(&name) {
+                upstream_req = upstream_req.header(&name, value.clone());
+            }
+        }
+        for (name, value) in &forwarding {
+            upstream_req = upstream_req.header(name, value.clone());
+        }
+        let body = body.take().unwrap_or_else(|| Body::from(replay.clone()));
+        let upstream_req = match upstream_req.body(body) {
+            Ok(r) => r,
+            Err(e) => {
+                tracing::error!(error = %e, "failed to build upstream request");
+                return Response::builder()
+                    .status(StatusCode::BAD_GATEWAY)
+                    .body(Body::from("Bad Gateway"))
+                    .unwrap();
+            }
+        };
+
+        // Time spent queueing for a slot comes out of the request timeout
+        let Some(slot) = state.upstreams.acquire(deadline).await else {
+            tracing::debug!(uri = %uri, "upstream concurrency limit reached");
+            return upstream_busy_response();
+        };
+        // Each attempt gets what's left of the request timeout, at most
+        let remaining = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+        let limit = match (remaining, per_try_timeout) {
+            (Some(remaining), Some(per_try)) => Some(remaining.min(per_try)),
+            (remaining, per_try) => remaining.or(per_try),
+        };
+        let result = send_upstream(&state, &backend, upstream_req, limit).await;
+        drop(slot);
+        attempt += 1;
+        let retryable = match &result {
+            Ok(resp) => retry.retry_on.contains(&resp.status.as_u16()),
+            Err(_) => true,
+        };
+        let backoff = retry
+            .backoff_ms
+            .saturating_mul(1 << (attempt - 1).min(16))
+            .min(retry.max_backoff_ms);
+        let out_of_time =
+            deadline.is_some_and(|d| Instant::now() + Duration::from_millis(backoff) >= d);
+        // Don't keep knocking once the breaker has given up on upstream
+        if !retryable || attempt >= attempts || out_of_time || circuit.is_some_and(|c| !c.allow()) {
+            break result;
+        }
+
+        tracing::debug!(
+            upstream = %upstream_uri,
+            attempt,
+            backoff_ms = backoff,
+            status = result.as_ref().ok().map(|r| r.status.as_u16()),
+            "retrying upstream request"
+        );
+        ::metrics::counter!("colander_upstream_retries_total").increment(1);
+        tokio::time::sleep(Duration::from_millis(backoff)).await;
+    };
+    let UpstreamResponse {
+        status,
+        mut headers,
+        body: body_bytes,
+        request_time,
+        response_time,
+    } = match upstream {
         Ok(resp) => resp,
-        Err(e) => {
-            tracing::error!(error = %e, upstream = %upstream_uri, "upstream request failed");
+        Err(UpstreamError::Timeout) => {
             return Response::builder()
-                .status(StatusCode::BAD_GATEWAY)
-                .body(Body::from("Bad Gateway"))
+                .status(StatusCode::GATEWAY_TIMEOUT)
+                .body(Body::from("Gateway Timeout"))
                 .unwrap();
         }
-    };
-
-    let status = upstream_resp.status();
-    let headers = upstream_resp.headers().clone();
-
-    // Read the full response body
-    let body_bytes = match upstream_resp.into_body().collect().await {
-        Ok(collected) => collected.to_bytes(),
-        Err(e) => {
-            tracing::error!(error = %e, "failed to read upstream response body");
+        Err(UpstreamError::Failed) => {
             return Response::builder()
                 .status(StatusCode::BAD_GATEWAY)
                 .body(Body::from("Bad Gateway"))
                 .unwrap();
         }
     };
+    // Neither stored nor passed on: they were about our connection to upstream
+    hop_by_hop::strip(&mut headers);
+
+    // Header changes apply before Cache-Control is read, so a plugin can
+    // rewrite that too
+    let action = state
+        .plugins
+        .on_response(uri.path(), || ResponseView {
+            method: method.as_str(),
+            uri: uri.to_string(),
+            key: &cache_key,
+            status: status.as_u16(),
+            headers: plugin::header_pairs(&headers),
+        })
+        .finish()
+        .await;
+    if let Some(action) = &action {
+        action.apply_headers(&mut headers);
+    }
+
+    // Writes leave cached reads of what they changed stale
+    if !method.is_safe() && status.is_success() {
+        writes::after_write(
+            &state,
+            &cache,
+            &config,
+            &method,
+            &uri,
+            tenant.as_deref(),
+            credentials.as_deref(),
+        );
+    }
 
     // Determine if we should cache this response
-    let should_cache = cacheable_method
+    let cc = cache_control(&headers);
+    let mut should_cache = cacheable_method
+        && !read_only
+        && !directives.no_store
         && status == StatusCode::OK
-        && body_bytes.len() <= cache.max_body_size
-        && is_cacheable_headers(&headers);
+        && action
+            .as_ref()
+            .and_then(|a| a.cache)
+            .unwrap_or(cc.cacheable);
 
-    let ttl = extract_ttl(&headers);
+    // Origin-provided max-age wins; otherwise let adaptive TTL pick one.
+    // must-revalidate without one isn't cached: we never revalidate, so any
+    // lifetime we picked could end up serving it stale.
+    let mut ttl = action
+        .as_ref()
+        .and_then(|a| a.ttl_seconds)
+        .map(Duration::from_secs)
+        .or(cc.max_age);
+    if ttl.is_none() && cc.must_revalidate {
+        should_cache = false;
+    }
+    // Checked before adaptive TTL hashes the body; counted as a rejection
+    if should_cache && !cache.admit_size(&cache_key, body_bytes.len()) {
+        should_cache = false;
+    }
 
     if should_cache {
-        let response_headers: Vec<(String, String)> = headers
-            .iter()
-            .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
-            .collect();
+        if ttl.is_none() && cache.is_adaptive_ttl() {
+            let etag = headers.get("etag").and_then(|v| v.to_str().ok());
+            ttl = cache.learn_ttl(&cache_key, adaptive_ttl::fingerprint(etag, &body_bytes));
+        }
+
+        // Time the response already spent in upstream caches counts against it
+        let age = initial_age(&headers, request_time, response_time);
+        let lifetime = ttl.unwrap_or_else(|| cache.default_ttl());
 
-        let cached_response =
-            cache.build_response(status.as_u16(), response_headers, body_bytes.clone(), ttl);
+        if age < lifetime {
+            let response_headers = stored_headers(&config.cache.headers, &headers);
 
-        cache.insert(cache_key.clone(), cached_response);
+            let cached_response = cache
+                .build_response(
+                    status.as_u16(),
+                    response_headers,
+                    body_bytes.clone(),
+                    Some(lifetime),
+                )
+                .with_initial_age(age)
+                .with_source(source)
+                .with_must_revalidate(cc.must_revalidate);
+
+            match fill.take() {
+                Some(fill) => {
+                    cache.insert_fill(fill, cached_response);
+                }
+                None => cache.insert(cache_key.clone(), cached_response),
+            }
+        } else {
+            tracing::debug!(key = %cache_key, age_secs = age.as_secs(), "stale on arrival, not cached");
+            should_cache = false;
+        }
     }
+    // Unless it was just inserted, release requests waiting on this fetch
+    drop(fill);
 
     let elapsed = start.elapsed();
     tracing::debug!(
@@ -126,82 +655,441 @@ pub async fn proxy_handler(
         latency_us = elapsed.as_micros(),
         "cache MISS → upstream"
     );
+    state.latency.record_miss(elapsed);
 
     // Build response from upstream
-    let mut response = Response::builder().status(status);
+    let mut response = Response::new(Body::from(body_bytes));
+    *response.status_mut() = status;
 
-    // Copy upstream headers
+    let out = response.headers_mut();
+    out.reserve(headers.len() + 4);
     for (key, value) in headers.iter() {
-        // Skip hop-by-hop headers
-        let k = key.as_str();
-        if k == "transfer-encoding" || k == "connection" {
-            continue;
-        }
-        response = response.header(key, value);
-    }
-
-    // Add cache status headers
-    response = response
-        .header("X-Cache", "MISS")
-        .header("X-Cache-Policy", cache.primary_name())
-        .header(
-            "X-Mode",
-            if cache.is_demo_mode() {
-                "demo"
-            } else {
-                "bench"
-            },
-        );
+        out.append(key.clone(), value.clone());
+    }
+    out.insert(
+        HeaderName::from_static("x-cache-hits"),
+        HeaderValue::from_static("0"),
+    );
+    let label = if bypassed || (readable_method && read_only) {
+        "BYPASS"
+    } else {
+        "MISS"
+    };
+    append_cache_headers(out, label, &cache);
 
-    response.body(Body::from(body_bytes)).unwrap()
+    response
 }
 
-/// Build an HTTP response from a cached entry.
-fn build_cached_response(
-    cached: &colander_cache::traits::CachedResponse,
-    cache: &CacheLayer,
-    _hit: bool,
-) -> Response<Body> {
-    let mut response = Response::builder().status(cached.status);
+/// An upstream response, read in full.
+struct UpstreamResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    /// When the request went out and when the response head came back, for
+    /// `Age` calculation.
+    request_time: SystemTime,
+    response_time: SystemTime,
+}
 
-    for (key, value) in &cached.headers {
-        if let Ok(v) = HeaderValue::from_str(value) {
-            response = response.header(key.as_str(), v);
+/// Why an upstream attempt produced no response. Details are logged where
+/// it happens.
+#[derive(Debug)]
+enum UpstreamError {
+    /// Connect error, or the connection failed mid-response.
+    Failed,
+    /// Connecting, or the whole attempt, took too long.
+    Timeout,
+}
+
+/// Send one attempt to `backend` and read the whole response within
+/// `timeout`, recording the outcome with the pool.
+async fn send_upstream(
+    state: &AppState,
+    backend: &Backend,
+    req: Request<Body>,
+    timeout: Option<Duration>,
+) -> Result<UpstreamResponse, UpstreamError> {
+    let upstream_uri = req.uri().clone();
+    let upstream_start = Instant::now();
+    let request_time = SystemTime::now();
+    let exchange = async {
+        let resp = state.client.request(req).await.map_err(|e| {
+            if is_timeout(&e) {
+                tracing::warn!(upstream = %upstream_uri, "upstream connect timed out");
+                return UpstreamError::Timeout;
+            }
+            tracing::error!(error = %e, upstream = %upstream_uri, "upstream request failed");
+            UpstreamError::Failed
+        })?;
+        let response_time = SystemTime::now();
+        let status = resp.status();
+        let headers = resp.headers().clone();
+        let body = match resp.into_body().collect().await {
+            Ok(collected) => collected.to_bytes(),
+            Err(e) => {
+                tracing::error!(error = %e, "failed to read upstream response body");
+                return Err(UpstreamError::Failed);
+            }
+        };
+        Ok(UpstreamResponse {
+            status,
+            headers,
+            body,
+            request_time,
+            response_time,
+        })
+    };
+    let result = match timeout {
+        Some(limit) => tokio::time::timeout(limit, exchange)
+            .await
+            .unwrap_or_else(|_| {
+                tracing::warn!(upstream = %upstream_uri, timeout_ms = limit.as_millis() as u64, "upstream attempt timed out");
+                Err(UpstreamError::Timeout)
+            }),
+        None => exchange.await,
+    };
+
+    let elapsed = upstream_start.elapsed();
+    match &result {
+        Ok(resp) => {
+            state.latency.record_upstream(elapsed);
+            state
+                .upstreams
+                .record(backend, elapsed, !resp.status.is_server_error());
+        }
+        Err(e) => {
+            if matches!(e, UpstreamError::Timeout) {
+                ::metrics::counter!("colander_upstream_timeouts_total").increment(1);
+            }
+            state.upstreams.record(backend, elapsed, false);
         }
     }
+    result
+}
 
-    response = response
-        .header("X-Cache", "HIT")
-        .header("X-Cache-Policy", cache.primary_name())
-        .header(
-            "X-Mode",
-            if cache.is_demo_mode() {
-                "demo"
-            } else {
-                "bench"
-            },
-        );
+/// Call `origin` for `req` within what's left of the request timeout.
+async fn fetch_origin(
+    state: &AppState,
+    origin: &dyn OriginFetcher,
+    req: OriginRequest,
+    deadline: Option<Instant>,
+) -> Result<UpstreamResponse, UpstreamError> {
+    let start = Instant::now();
+    let request_time = SystemTime::now();
+    let fetch = origin.fetch(req);
+    let result = match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline.into(), fetch)
+            .await
+            .unwrap_or(Err(OriginError::Timeout)),
+        None => fetch.await,
+    };
+    match result {
+        Ok(resp) => {
+            state.latency.record_upstream(start.elapsed());
+            Ok(UpstreamResponse {
+                status: resp.status,
+                headers: resp.headers,
+                body: resp.body,
+                request_time,
+                response_time: SystemTime::now(),
+            })
+        }
+        Err(OriginError::Timeout) => {
+            tracing::warn!("origin fetch timed out");
+            ::metrics::counter!("colander_upstream_timeouts_total").increment(1);
+            Err(UpstreamError::Timeout)
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "origin fetch failed");
+            Err(UpstreamError::Failed)
+        }
+    }
+}
 
-    response.body(Body::from(cached.body.clone())).unwrap()
+/// Whether a client error was the connect timeout firing.
+fn is_timeout(error: &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(error);
+    while let Some(e) = source {
+        if e.downcast_ref::<std::io::Error>()
+            .is_some_and(|e| e.kind() == std::io::ErrorKind::TimedOut)
+        {
+            return true;
+        }
+        source = e.source();
+    }
+    false
 }
 
-/// Check if response headers allow caching.
-fn is_cacheable_headers(headers: &HeaderMap) -> bool {
-    if let Some(cc) = headers.get("cache-control") {
-        if let Ok(cc_str) = cc.to_str() {
-            return parse_cache_control(cc_str).cacheable;
+/// Build the cache key for `method` + `uri` into a thread-local buffer and
+/// hand it to `f`. Hits never need an owned key, so lookups through here stay
+/// allocation-free; `f` must not call back into `with_cache_key`.
+pub fn with_cache_key<R>(
+    keys: &HttpKey,
+    method: &Method,
+    uri: &Uri,
+    f: impl FnOnce(&str) -> R,
+) -> R {
+    KEY_BUF.with(|buf| {
+        let mut buf = buf.borrow_mut();
+        buf.clear();
+        keys.write_key(&(method, uri), &mut buf);
+        let result = f(&buf);
+        if buf.capacity() > KEY_BUF_RETAIN {
+            *buf = String::with_capacity(256);
         }
+        result
+    })
+}
+
+/// Owned cache key for `method` + `uri` (`"GET:/path?query"`).
+pub fn cache_key(keys: &HttpKey, method: &Method, uri: &Uri) -> String {
+    let mut key = String::with_capacity(method.as_str().len() + 1 + uri.path().len() + 16);
+    keys.write_key(&(method, uri), &mut key);
+    key
+}
+
+/// Build an HTTP response from a cached entry, counting the hit on it.
+pub fn build_cached_response(cached: &CachedResponse, cache: &CacheLayer) -> Response<Body> {
+    let mut response = entry_response(cached);
+    append_cache_headers(response.headers_mut(), "HIT", cache);
+    response
+}
+
+/// The response for a hit on `cached`, counting the hit, before colander's
+/// policy and mode headers are added.
+///
+/// Starts from a clone of the entry's `HeaderMap` instead of going through
+/// `Response::builder()`, and uses static names/values for colander's own
+/// headers, so the stored headers are never parsed or copied on a hit: each
+/// value is a ref-counted `Bytes`.
+///
+/// `Age` is how long the entry has been cached and `X-Cache-Hits` how many
+/// times it has been served (this hit included), as Varnish and Squid do.
+pub(crate) fn entry_response(cached: &CachedResponse) -> Response<Body> {
+    let hits = cached.record_hit();
+    let mut response = Response::new(Body::from(cached.body.clone()));
+    // RESP-inserted entries carry status 0; serve those as plain 200s
+    *response.status_mut() = StatusCode::from_u16(cached.status).unwrap_or(StatusCode::OK);
+
+    let headers = response.headers_mut();
+    *headers = cached.headers.clone();
+    headers.reserve(5);
+    headers.insert(AGE, HeaderValue::from(cached.age().as_secs()));
+    // The stored Date would be as old as the entry; RFC 9110 §6.6.1 wants the
+    // time this message was generated
+    if let Ok(date) = HeaderValue::from_str(&httpdate::fmt_http_date(SystemTime::now())) {
+        headers.insert(DATE, date);
+    }
+    headers.insert(
+        HeaderName::from_static("x-cache-hits"),
+        HeaderValue::from(hits),
+    );
+
+    response
+}
+
+/// Finish the response to a request answered from `cached`: `HEAD` gets the
+/// entry's headers with its body's length and no body, and `GET` has its
+/// `Range` honored.
+pub(crate) fn serve_cached(
+    method: &Method,
+    headers: &HeaderMap,
+    cached: &CachedResponse,
+    mut response: Response<Body>,
+) -> Response<Body> {
+    if method == Method::HEAD {
+        response
+            .headers_mut()
+            .insert(CONTENT_LENGTH, HeaderValue::from(cached.body.len()));
+        *response.body_mut() = Body::empty();
+        return response;
+    }
+    range::serve_range(headers, cached, response)
+}
+
+/// The upstream headers `[cache.headers]` lets us keep with an entry.
+pub(crate) fn stored_headers(config: &StoredHeadersConfig, headers: &HeaderMap) -> HeaderMap {
+    let listed = |names: &[String], name: &HeaderName| {
+        names.iter().any(|n| n.eq_ignore_ascii_case(name.as_str()))
+    };
+    headers
+        .iter()
+        .filter(|(name, _)| {
+            if *name == AGE || *name == DATE {
+                return false;
+            }
+            listed(&config.keep, name)
+                || ((config.allow.is_empty() || listed(&config.allow, name))
+                    && !listed(&config.deny, name))
+        })
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect()
+}
+
+/// Add colander's `X-Cache`, `X-Cache-Policy`, and `X-Mode` headers.
+pub(crate) fn append_cache_headers(
+    headers: &mut HeaderMap,
+    status: &'static str,
+    cache: &CacheLayer,
+) {
+    headers.insert(
+        HeaderName::from_static("x-cache"),
+        HeaderValue::from_static(status),
+    );
+    headers.insert(
+        HeaderName::from_static("x-cache-policy"),
+        HeaderValue::from_static(cache.primary_name()),
+    );
+    headers.insert(
+        HeaderName::from_static("x-mode"),
+        HeaderValue::from_static(if cache.is_demo_mode() {
+            "demo"
+        } else {
+            "bench"
+        }),
+    );
+}
+
+/// `503` with a `Retry-After` matching when the open circuit next probes.
+pub(crate) fn circuit_open_response(circuit: &CircuitBreaker) -> Response<Body> {
+    let retry_after = circuit.retry_after().as_secs_f64().ceil().max(1.0) as u64;
+    Response::builder()
+        .status(StatusCode::SERVICE_UNAVAILABLE)
+        .header(RETRY_AFTER, retry_after)
+        .body(Body::from("Service Unavailable"))
+        .unwrap()
+}
+
+/// `503` for a request that waited too long for an upstream slot.
+fn upstream_busy_response() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::SERVICE_UNAVAILABLE)
+        .header(RETRY_AFTER, 1)
+        .body(Body::from("Service Unavailable"))
+        .unwrap()
+}
+
+/// Parsed `Cache-Control` of an upstream response. No header (or an
+/// unreadable one) means cacheable with no explicit lifetime.
+pub(crate) fn cache_control(headers: &HeaderMap) -> CacheControl {
+    match headers.get("cache-control").and_then(|cc| cc.to_str().ok()) {
+        Some(cc) => parse_cache_control(cc),
+        None => CacheControl {
+            cacheable: true,
+            max_age: None,
+            must_revalidate: false,
+        },
     }
-    // No Cache-Control header — cacheable by default
-    true
 }
 
-/// Extract TTL from Cache-Control header.
-fn extract_ttl(headers: &HeaderMap) -> Option<std::time::Duration> {
-    if let Some(cc) = headers.get("cache-control") {
-        if let Ok(cc_str) = cc.to_str() {
-            return parse_cache_control(cc_str).max_age;
+/// Corrected initial age of a response (RFC 9111 §4.2.3): the larger of what
+/// its `Date` implies and what its `Age` says plus our round trip.
+pub(crate) fn initial_age(
+    headers: &HeaderMap,
+    request_time: SystemTime,
+    response_time: SystemTime,
+) -> Duration {
+    let age_value = headers
+        .get(AGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map_or(Duration::ZERO, Duration::from_secs);
+    let apparent_age = headers
+        .get(DATE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| httpdate::parse_http_date(v).ok())
+        .and_then(|date| response_time.duration_since(date).ok())
+        .unwrap_or(Duration::ZERO);
+    let response_delay = response_time
+        .duration_since(request_time)
+        .unwrap_or(Duration::ZERO);
+
+    apparent_age.max(age_value + response_delay)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{CircuitBreakerConfig, Config, ListenerRole};
+    use crate::server::Server;
+    use axum::body::Body;
+    use axum::extract::Path;
+    use axum::http::{header, Request, StatusCode};
+    use axum::routing::get;
+    use axum::Router;
+    use hyper_util::client::legacy::Client;
+    use hyper_util::rt::TokioExecutor;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn open_circuit_never_serves_must_revalidate_entries_stale() {
+        // `/strict` must be revalidated once expired, `/loose` needn't; both
+        // fail with a 500 once `down` is set
+        let down = Arc::new(AtomicBool::new(false));
+        let origin = {
+            let down = Arc::clone(&down);
+            Router::new().route(
+                "/{name}",
+                get(move |Path(name): Path<String>| async move {
+                    if down.load(Ordering::Relaxed) {
+                        return (
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            [(header::CACHE_CONTROL, "")],
+                        );
+                    }
+                    let cc = match name.as_str() {
+                        "strict" => "max-age=1, must-revalidate",
+                        _ => "max-age=1",
+                    };
+                    (StatusCode::OK, [(header::CACHE_CONTROL, cc)])
+                }),
+            )
+        };
+        let tcp = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let origin_addr = tcp.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(tcp, origin).await });
+
+        let mut config = Config::default_config();
+        config.server.listen_addr = "127.0.0.1:0".into();
+        config.server.metrics_addr = "127.0.0.1:0".into();
+        config.upstream.url = format!("http://{origin_addr}");
+        config.upstream.circuit_breaker = CircuitBreakerConfig {
+            enabled: true,
+            min_requests: 1,
+            cool_down_ms: 60_000,
+            ..config.upstream.circuit_breaker.clone()
+        };
+        let server = Server::start(config).await.unwrap();
+        let addr = server.handle().addr(ListenerRole::Proxy).unwrap();
+
+        let client = Client::builder(TokioExecutor::new()).build_http::<Body>();
+        let fetch = |path: &str| {
+            let req = Request::get(format!("http://{addr}/{path}"))
+                .body(Body::empty())
+                .unwrap();
+            let resp = client.request(req);
+            async move {
+                let resp = resp.await.unwrap();
+                let cache = resp.headers().get("x-cache").cloned();
+                (resp.status(), cache)
+            }
+        };
+        assert_eq!(fetch("strict").await.0, StatusCode::OK);
+        assert_eq!(fetch("loose").await.0, StatusCode::OK);
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+
+        down.store(true, Ordering::Relaxed);
+        let mut tries = 0;
+        while fetch("boom").await.0 != StatusCode::SERVICE_UNAVAILABLE {
+            tries += 1;
+            assert!(tries < 20, "circuit never opened");
         }
+
+        let (status, cache) = fetch("loose").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(cache.unwrap(), "STALE");
+        assert_eq!(fetch("strict").await.0, StatusCode::SERVICE_UNAVAILABLE);
+        server.handle().shutdown();
     }
-    None
 }
@@ -0,0 +1,351 @@
+use bytes::Bytes;
+use parking_lot::RwLock;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// Messages a subscriber can fall behind by before new ones are dropped.
+const SUBSCRIBER_BUFFER: usize = 1024;
+
+/// Redis-style publish/subscribe hub shared by every RESP connection. The
+/// cache publishes keyspace events to it (see `keyevent`).
+#[derive(Default)]
+pub struct PubSub {
+    subscribers: RwLock<HashMap<u64, Subscriber>>,
+    next_id: AtomicU64,
+    /// Subscribers with at least one channel or pattern, so publishers can
+    /// skip building messages nobody would receive.
+    listening: AtomicUsize,
+}
+
+struct Subscriber {
+    channels: HashSet<Bytes>,
+    patterns: HashSet<Bytes>,
+    tx: mpsc::Sender<Message>,
+}
+
+/// A published message as delivered to one subscriber.
+#[derive(Debug, Clone)]
+pub struct Message {
+    /// The pattern it matched, for `PSUBSCRIBE` deliveries.
+    pub pattern: Option<Bytes>,
+    pub channel: Bytes,
+    pub payload: Bytes,
+}
+
+impl PubSub {
+    /// Register a connection as a (so far channel-less) subscriber.
+    pub fn register(self: &Arc<Self>) -> Subscription {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = mpsc::channel(SUBSCRIBER_BUFFER);
+        self.subscribers.write().insert(
+            id,
+            Subscriber {
+                channels: HashSet::new(),
+                patterns: HashSet::new(),
+                tx,
+            },
+        );
+        Subscription {
+            hub: Arc::clone(self),
+            id,
+            rx,
+        }
+    }
+
+    /// Deliver `payload` to everyone subscribed to `channel`, directly or by
+    /// pattern. Returns how many deliveries were made, like `PUBLISH`.
+    pub fn publish(&self, channel: &[u8], payload: Bytes) -> usize {
+        if !self.has_subscribers() {
+            return 0;
+        }
+        let channel = Bytes::copy_from_slice(channel);
+        let mut delivered = 0;
+        for subscriber in self.subscribers.read().values() {
+            let tx = &subscriber.tx;
+            let mut deliver = |pattern: Option<&Bytes>| {
+                let message = Message {
+                    pattern: pattern.cloned(),
+                    channel: channel.clone(),
+                    payload: payload.clone(),
+                };
+                // A subscriber that can't keep up loses messages rather than
+                // stalling the publisher (often a request handler)
+                if tx.try_send(message).is_ok() {
+                    delivered += 1;
+                } else {
+                    ::metrics::counter!("colander_pubsub_dropped_total").increment(1);
+                }
+            };
+            if subscriber.channels.contains(&channel) {
+                deliver(None);
+            }
+            for pattern in &subscriber.patterns {
+                if glob_match(pattern, &channel) {
+                    deliver(Some(pattern));
+                }
+            }
+        }
+        delivered
+    }
+
+    /// Publish a keyspace event the way Redis does: the key on
+    /// `__keyevent@0__:<event>`. Free when nobody is subscribed.
+    pub fn keyevent(&self, event: &str, key: &str) {
+        if self.has_subscribers() {
+            let channel = format!("__keyevent@0__:{event}");
+            self.publish(channel.as_bytes(), Bytes::copy_from_slice(key.as_bytes()));
+        }
+    }
+
+    pub fn has_subscribers(&self) -> bool {
+        self.listening.load(Ordering::Relaxed) > 0
+    }
+
+    /// Apply `change` to subscriber `id`, keeping `listening` in step.
+    /// Returns its channel + pattern count afterwards.
+    fn update(&self, id: u64, change: impl FnOnce(&mut Subscriber)) -> usize {
+        let mut subscribers = self.subscribers.write();
+        let Some(subscriber) = subscribers.get_mut(&id) else {
+            return 0;
+        };
+        let before = subscriber.channels.len() + subscriber.patterns.len();
+        change(subscriber);
+        let after = subscriber.channels.len() + subscriber.patterns.len();
+        if before == 0 && after > 0 {
+            self.listening.fetch_add(1, Ordering::Relaxed);
+        } else if before > 0 && after == 0 {
+            self.listening.fetch_sub(1, Ordering::Relaxed);
+        }
+        after
+    }
+}
+
+/// One connection's subscriptions. Unregisters itself when dropped.
+pub struct Subscription {
+    hub: Arc<PubSub>,
+    id: u64,
+    rx: mpsc::Receiver<Message>,
+}
+
+impl Subscription {
+    /// Subscribe to `channel`; returns the connection's subscription count.
+    pub fn subscribe(&self, channel: Bytes) -> usize {
+        self.hub.update(self.id, |s| {
+            s.channels.insert(channel);
+        })
+    }
+
+    pub fn unsubscribe(&self, channel: &[u8]) -> usize {
+        self.hub.update(self.id, |s| {
+            s.channels.remove(channel);
+        })
+    }
+
+    pub fn psubscribe(&self, pattern: Bytes) -> usize {
+        self.hub.update(self.id, |s| {
+            s.patterns.insert(pattern);
+        })
+    }
+
+    pub fn punsubscribe(&self, pattern: &[u8]) -> usize {
+        self.hub.update(self.id, |s| {
+            s.patterns.remove(pattern);
+        })
+    }
+
+    /// Channels subscribed to by name.
+    pub fn channels(&self) -> Vec<Bytes> {
+        self.hub
+            .subscribers
+            .read()
+            .get(&self.id)
+            .map(|s| s.channels.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn patterns(&self) -> Vec<Bytes> {
+        self.hub
+            .subscribers
+            .read()
+            .get(&self.id)
+            .map(|s| s.patterns.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Total channels and patterns subscribed to.
+    pub fn count(&self) -> usize {
+        self.hub.update(self.id, |_| {})
+    }
+
+    /// The next message for this connection.
+    pub async fn recv(&mut self) -> Option<Message> {
+        self.rx.recv().await
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.hub.update(self.id, |s| {
+            s.channels.clear();
+            s.patterns.clear();
+        });
+        self.hub.subscribers.write().remove(&self.id);
+    }
+}
+
+/// Redis glob matching for `PSUBSCRIBE`: `*`, `?`, `[abc]`, `[^a-z]` and
+/// `\` to escape.
+pub(crate) fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Where to resume after the last `*` if the rest fails to match
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        let step = match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, t));
+                p += 1;
+                continue;
+            }
+            Some(b'?') => Some(p + 1),
+            Some(b'[') => match_class(pattern, p, text[t]),
+            Some(b'\\') if p + 1 < pattern.len() => (pattern[p + 1] == text[t]).then_some(p + 2),
+            Some(&c) => (c == text[t]).then_some(p + 1),
+            None => None,
+        };
+        match (step, backtrack) {
+            (Some(next), _) => {
+                p = next;
+                t += 1;
+            }
+            (None, Some((star, from))) => {
+                p = star + 1;
+                t = from + 1;
+                backtrack = Some((star, from + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Match `c` against the `[...]` class starting at `pattern[start]`,
+/// returning the index just past it on a match.
+fn match_class(pattern: &[u8], start: usize, c: u8) -> Option<usize> {
+    let mut i = start + 1;
+    let negate = pattern.get(i) == Some(&b'^');
+    if negate {
+        i += 1;
+    }
+    let mut matched = false;
+    while i < pattern.len() && pattern[i] != b']' {
+        if pattern[i] == b'\\' && i + 1 < pattern.len() {
+            i += 1;
+            matched |= pattern[i] == c;
+            i += 1;
+        } else if i + 2 < pattern.len() && pattern[i + 1] == b'-' && pattern[i + 2] != b']' {
+            let (lo, hi) = (
+                pattern[i].min(pattern[i + 2]),
+                pattern[i].max(pattern[i + 2]),
+            );
+            matched |= (lo..=hi).contains(&c);
+            i += 3;
+        } else {
+            matched |= pattern[i] == c;
+            i += 1;
+        }
+    }
+    // An unclosed class runs to the end of the pattern, as in Redis
+    let end = (i + 1).min(pattern.len());
+    (matched != negate).then_some(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache_layer::CacheLayer;
+    use colander_cache::traits::InsertSource;
+    use std::time::Duration;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        glob_match(pattern.as_bytes(), text.as_bytes())
+    }
+
+    #[test]
+    fn star_matches_any_run() {
+        assert!(matches("*", ""));
+        assert!(matches("*", "anything"));
+        assert!(matches("__keyevent@0__:*", "__keyevent@0__:set"));
+        assert!(matches("a*c", "ac"));
+        assert!(matches("a*c", "abbbc"));
+        assert!(matches("a*b*c", "axxbyyc"));
+        // Needs backtracking past the first `b`
+        assert!(matches("*bc", "abcbc"));
+        assert!(!matches("a*c", "abcd"));
+        assert!(!matches("__keyevent@0__:*", "__keyspace@0__:set"));
+    }
+
+    #[test]
+    fn question_mark_matches_one_byte() {
+        assert!(matches("h?llo", "hello"));
+        assert!(matches("h?llo", "hallo"));
+        assert!(!matches("h?llo", "hllo"));
+        assert!(!matches("h?llo", "heello"));
+        assert!(!matches("?", ""));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(matches("h[ae]llo", "hello"));
+        assert!(matches("h[ae]llo", "hallo"));
+        assert!(!matches("h[ae]llo", "hillo"));
+        assert!(matches("h[^e]llo", "hallo"));
+        assert!(!matches("h[^e]llo", "hello"));
+        assert!(matches("h[a-b]llo", "hbllo"));
+        assert!(!matches("h[a-b]llo", "hcllo"));
+        // Reversed ranges work too, as in Redis
+        assert!(matches("h[b-a]llo", "hallo"));
+        // A trailing `-` is a literal
+        assert!(matches("[a-]", "-"));
+        assert!(matches("[\\]]", "]"));
+    }
+
+    #[test]
+    fn escapes_match_literally() {
+        assert!(matches("a\\*b", "a*b"));
+        assert!(!matches("a\\*b", "axb"));
+        assert!(matches("a\\?", "a?"));
+        assert!(!matches("a\\?", "ab"));
+        assert!(matches("\\[x]", "[x]"));
+        // A trailing backslash is a literal
+        assert!(matches("a\\", "a\\"));
+    }
+
+    #[test]
+    fn evictions_are_published() {
+        let pubsub = Arc::new(PubSub::default());
+        let mut subscription = pubsub.register();
+        subscription.psubscribe(Bytes::from_static(b"__keyevent@0__:*"));
+        let cache = CacheLayer::new("fifo", &[], 64, Duration::from_secs(60), 1024)
+            .with_keyspace_events(Arc::clone(&pubsub));
+        // Few enough that sets and evictions fit the subscriber's buffer
+        for i in 0..300 {
+            cache
+                .insert_raw(format!("k{i}"), Bytes::new(), None, InsertSource::Resp)
+                .unwrap();
+        }
+
+        let mut events: HashMap<Bytes, usize> = HashMap::new();
+        while let Ok(message) = subscription.rx.try_recv() {
+            *events.entry(message.channel).or_default() += 1;
+        }
+        let count = |event: &'static str| {
+            let channel = format!("__keyevent@0__:{event}");
+            events.get(channel.as_bytes()).copied().unwrap_or(0)
+        };
+        assert_eq!(count("set"), 300);
+        assert!(count("evicted") > 0);
+        assert_eq!(count("evicted"), 300 - cache.primary_stats().current_size);
+    }
+}
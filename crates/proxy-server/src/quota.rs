@@ -0,0 +1,132 @@
+use crate::config::QuotaConfig;
+use crate::http_key::matches;
+use colander_cache::sharded::{KeyQuotas, Quota, QuotaUsage};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// `[[cache.quotas]]` applied to HTTP cache keys. A path quota is one group
+/// named by its pattern; a tenant quota gives every tenant it matches a
+/// group of its own, named `tenant=<name>`. RESP and memcached keys have no
+/// quota.
+#[derive(Debug)]
+pub struct HttpQuotas {
+    quotas: Vec<QuotaConfig>,
+}
+
+impl HttpQuotas {
+    pub fn new(quotas: Vec<QuotaConfig>) -> Self {
+        Self { quotas }
+    }
+
+    /// `usage` from the cache, with each group's configured share.
+    pub fn report(&self, usage: HashMap<String, QuotaUsage>) -> BTreeMap<String, QuotaReport> {
+        usage
+            .into_iter()
+            .filter_map(|(group, usage)| {
+                let max_share = self.config_of(&group)?.max_share;
+                let report = QuotaReport {
+                    entries: usage.entries,
+                    evictions: usage.evictions,
+                    max_share,
+                };
+                Some((group, report))
+            })
+            .collect()
+    }
+
+    /// Publish `usage` as `colander_quota_entries` and
+    /// `colander_quota_evictions_total`, labelled by quota rather than
+    /// group, so tenants don't each get a series.
+    pub fn export(&self, usage: &HashMap<String, QuotaUsage>) {
+        let mut totals: BTreeMap<String, QuotaUsage> = self
+            .quotas
+            .iter()
+            .map(|quota| (label(quota), QuotaUsage::default()))
+            .collect();
+        for (group, usage) in usage {
+            if let Some(total) = self
+                .config_of(group)
+                .and_then(|q| totals.get_mut(&label(q)))
+            {
+                total.entries += usage.entries;
+                total.evictions += usage.evictions;
+            }
+        }
+        for (quota, total) in totals {
+            ::metrics::gauge!("colander_quota_entries", "quota" => quota.clone())
+                .set(total.entries as f64);
+            ::metrics::counter!("colander_quota_evictions_total", "quota" => quota)
+                .absolute(total.evictions);
+        }
+    }
+
+    /// The quota a group was made for.
+    fn config_of(&self, group: &str) -> Option<&QuotaConfig> {
+        match group.strip_prefix("tenant=") {
+            Some(tenant) => self
+                .quotas
+                .iter()
+                .find(|q| q.tenant.as_deref().is_some_and(|p| matches(p, tenant))),
+            None => self
+                .quotas
+                .iter()
+                .find(|q| q.path.as_deref() == Some(group)),
+        }
+    }
+}
+
+impl KeyQuotas for HttpQuotas {
+    fn quota(&self, key: &str) -> Option<Quota> {
+        let (path, tenant) = parse(key)?;
+        self.quotas.iter().find_map(|quota| {
+            let group = match (&quota.path, &quota.tenant, tenant) {
+                (Some(pattern), _, _) if matches(pattern, path) => pattern.clone(),
+                (_, Some(pattern), Some(tenant)) if matches(pattern, tenant) => {
+                    format!("tenant={tenant}")
+                }
+                _ => return None,
+            };
+            Some(Quota {
+                group,
+                max_share: quota.max_share,
+            })
+        })
+    }
+}
+
+/// The `colander_quota_*` label of a quota: its path pattern, or
+/// `tenant=` and its tenant pattern.
+fn label(quota: &QuotaConfig) -> String {
+    match (&quota.path, &quota.tenant) {
+        (Some(path), _) => path.clone(),
+        (None, Some(tenant)) => format!("tenant={tenant}"),
+        (None, None) => String::new(),
+    }
+}
+
+/// The path and tenant of an HTTP cache key such as
+/// `GET:/a?b=1#tenant=acme#user=...`. Absolute-form keys
+/// (`GET:http://host/a`) give the path after the authority.
+fn parse(key: &str) -> Option<(&str, Option<&str>)> {
+    let rest = key.strip_prefix("GET:")?;
+    let mut parts = rest.split('#');
+    let target = parts.next()?;
+    let tenant = parts.find_map(|part| part.strip_prefix("tenant="));
+    let target = match target.split_once("://") {
+        Some((_, after_scheme)) if !target.starts_with('/') => {
+            &after_scheme[after_scheme.find('/')?..]
+        }
+        _ => target,
+    };
+    let path = target.split('?').next().unwrap_or(target);
+    Some((path, tenant))
+}
+
+/// One quota group in `/api/stats`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct QuotaReport {
+    pub entries: usize,
+    /// Entries evicted to keep the group within its quota.
+    pub evictions: u64,
+    pub max_share: f64,
+}
@@ -0,0 +1,233 @@
+use axum::body::Body;
+use axum::http::header::{CONTENT_LENGTH, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use axum::http::{HeaderMap, HeaderValue, Response, StatusCode};
+use colander_cache::traits::CachedResponse;
+
+/// What a `Range` header asks of a body of known length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    /// No usable range: serve the whole body with `200`.
+    Full,
+    /// Bytes `start..=end` (RFC 9110 ranges are inclusive).
+    Partial { start: u64, end: u64 },
+    /// Starts past the end of the body: `416`.
+    Unsatisfiable,
+}
+
+/// Parse a `Range` value against a body of `len` bytes.
+///
+/// Only a single `bytes` range is honored. Multiple ranges would need a
+/// `multipart/byteranges` body, so like malformed ranges they're ignored,
+/// which RFC 9110 §14.2 allows.
+pub fn parse(value: &str, len: u64) -> ByteRange {
+    let Some((unit, spec)) = value.split_once('=') else {
+        return ByteRange::Full;
+    };
+    if !unit.trim().eq_ignore_ascii_case("bytes") || spec.contains(',') {
+        return ByteRange::Full;
+    }
+    let Some((first, last)) = spec.trim().split_once('-') else {
+        return ByteRange::Full;
+    };
+    let (first, last) = (first.trim(), last.trim());
+
+    if first.is_empty() {
+        // Suffix range: the final `n` bytes
+        return match last.parse::<u64>() {
+            Ok(0) => ByteRange::Unsatisfiable,
+            Ok(_) if len == 0 => ByteRange::Unsatisfiable,
+            Ok(n) => ByteRange::Partial {
+                start: len.saturating_sub(n),
+                end: len - 1,
+            },
+            Err(_) => ByteRange::Full,
+        };
+    }
+
+    let Ok(start) = first.parse::<u64>() else {
+        return ByteRange::Full;
+    };
+    let end = if last.is_empty() {
+        u64::MAX
+    } else {
+        match last.parse::<u64>() {
+            Ok(end) if end >= start => end,
+            _ => return ByteRange::Full,
+        }
+    };
+    if start >= len {
+        return ByteRange::Unsatisfiable;
+    }
+    ByteRange::Partial {
+        start,
+        end: end.min(len - 1),
+    }
+}
+
+/// Turn a cache hit into a partial response if the request asked for a
+/// range of it, per RFC 9110 §14. `response` is the full hit as built from
+/// `cached`; it's returned unchanged when there's no applicable range.
+pub fn serve_range(
+    request: &HeaderMap,
+    cached: &CachedResponse,
+    mut response: Response<Body>,
+) -> Response<Body> {
+    let Some(range) = request.get(RANGE).and_then(|v| v.to_str().ok()) else {
+        return response;
+    };
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+    // A stale validator means the client's earlier bytes came from another
+    // version: send it the whole current one
+    if let Some(validator) = request.get(IF_RANGE) {
+        if !if_range_matches(validator, response.headers()) {
+            return response;
+        }
+    }
+
+    let len = cached.body.len() as u64;
+    let headers = response.headers_mut();
+    match parse(range, len) {
+        ByteRange::Full => response,
+        ByteRange::Partial { start, end } => {
+            headers.insert(
+                CONTENT_RANGE,
+                HeaderValue::from_str(&format!("bytes {start}-{end}/{len}")).unwrap(),
+            );
+            headers.insert(CONTENT_LENGTH, HeaderValue::from(end - start + 1));
+            *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+            *response.body_mut() = Body::from(cached.body.slice(start as usize..=end as usize));
+            ::metrics::counter!("colander_range_hits_total").increment(1);
+            response
+        }
+        ByteRange::Unsatisfiable => {
+            headers.insert(
+                CONTENT_RANGE,
+                HeaderValue::from_str(&format!("bytes */{len}")).unwrap(),
+            );
+            headers.remove(CONTENT_LENGTH);
+            *response.status_mut() = StatusCode::RANGE_NOT_SATISFIABLE;
+            *response.body_mut() = Body::empty();
+            response
+        }
+    }
+}
+
+/// `If-Range` holds either an entity tag, compared strongly, or a date,
+/// which must match `Last-Modified` exactly.
+fn if_range_matches(validator: &HeaderValue, headers: &HeaderMap) -> bool {
+    let validator = validator.as_bytes();
+    if validator.starts_with(b"W/") {
+        return false;
+    }
+    let current = if validator.starts_with(b"\"") {
+        headers.get(ETAG)
+    } else {
+        headers.get(LAST_MODIFIED)
+    };
+    current.is_some_and(|v| v.as_bytes() == validator && !v.as_bytes().starts_with(b"W/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ranges_against_the_body_length() {
+        use ByteRange::{Full, Partial, Unsatisfiable};
+        let cases = [
+            ("bytes=0-99", 1000, Partial { start: 0, end: 99 }),
+            (
+                "bytes=500-",
+                1000,
+                Partial {
+                    start: 500,
+                    end: 999,
+                },
+            ),
+            ("BYTES = 1-1", 1000, Partial { start: 1, end: 1 }),
+            // Suffix ranges: the final n bytes, all of them if n is longer
+            (
+                "bytes=-100",
+                1000,
+                Partial {
+                    start: 900,
+                    end: 999,
+                },
+            ),
+            ("bytes=-5000", 1000, Partial { start: 0, end: 999 }),
+            ("bytes=-0", 1000, Unsatisfiable),
+            // Start at or past the end
+            ("bytes=1000-", 1000, Unsatisfiable),
+            ("bytes=1000-2000", 1000, Unsatisfiable),
+            ("bytes=5000-", 1000, Unsatisfiable),
+            // End past the end is cut to it
+            (
+                "bytes=900-5000",
+                1000,
+                Partial {
+                    start: 900,
+                    end: 999,
+                },
+            ),
+            (
+                "bytes=999-999",
+                1000,
+                Partial {
+                    start: 999,
+                    end: 999,
+                },
+            ),
+            // Empty body: nothing to serve a range of
+            ("bytes=0-", 0, Unsatisfiable),
+            ("bytes=0-0", 0, Unsatisfiable),
+            ("bytes=-1", 0, Unsatisfiable),
+            // Multiple ranges, other units and malformed ranges: whole body
+            ("bytes=0-1,5-9", 1000, Full),
+            ("bytes=-1, -2", 1000, Full),
+            ("items=0-1", 1000, Full),
+            ("bytes=9-1", 1000, Full),
+            ("bytes=a-b", 1000, Full),
+            ("bytes=-x", 1000, Full),
+            ("bytes=5", 1000, Full),
+            ("0-99", 1000, Full),
+        ];
+        for (value, len, expected) in cases {
+            assert_eq!(parse(value, len), expected, "{value} of {len} bytes");
+        }
+    }
+
+    #[test]
+    fn if_range_needs_a_strong_match() {
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, HeaderValue::from_static("\"v1\""));
+        headers.insert(
+            LAST_MODIFIED,
+            HeaderValue::from_static("Wed, 21 Oct 2026 07:28:00 GMT"),
+        );
+        let mut weak = HeaderMap::new();
+        weak.insert(ETAG, HeaderValue::from_static("W/\"v1\""));
+
+        let cases = [
+            ("\"v1\"", &headers, true),
+            ("\"v2\"", &headers, false),
+            // Weak tags never match, on either side
+            ("W/\"v1\"", &headers, false),
+            ("W/\"v1\"", &weak, false),
+            ("\"v1\"", &weak, false),
+            // Dates compare with Last-Modified exactly
+            ("Wed, 21 Oct 2026 07:28:00 GMT", &headers, true),
+            ("Wed, 21 Oct 2026 07:28:01 GMT", &headers, false),
+            ("Wed, 21 Oct 2026 07:28:00 GMT", &weak, false),
+        ];
+        for (validator, current, expected) in cases {
+            let validator = HeaderValue::from_static(validator);
+            assert_eq!(
+                if_range_matches(&validator, current),
+                expected,
+                "If-Range {validator:?} against {current:?}"
+            );
+        }
+    }
+}
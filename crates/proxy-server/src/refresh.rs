@@ -0,0 +1,103 @@
+use crate::config::{Config, RefreshAheadConfig};
+use crate::http_key::{self, HttpKey};
+use crate::proxy::{cache_key, proxy_handler, AppState};
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{Method, Request, StatusCode, Uri};
+use colander_cache::traits::{CachedResponse, InsertSource};
+use parking_lot::Mutex;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Refresh-ahead bookkeeping: which keys are being refetched, so a hot
+/// entry gets one refresh rather than one per hit, and how many have been
+/// started.
+#[derive(Debug, Default)]
+pub struct RefreshAhead {
+    in_flight: Mutex<HashSet<String>>,
+    started: AtomicU64,
+}
+
+impl RefreshAhead {
+    /// Refresh-ahead fetches started since startup.
+    pub fn count(&self) -> u64 {
+        self.started.load(Ordering::Relaxed)
+    }
+}
+
+/// Settings for `path` after `[[cache.refresh_ahead.routes]]` overrides:
+/// `(min_hits, window_percent)`, or `None` if it isn't refreshed.
+fn settings(config: &RefreshAheadConfig, path: &str) -> Option<(u64, f64)> {
+    let route = config
+        .routes
+        .iter()
+        .find(|route| http_key::matches(&route.path, path));
+    let enabled = route.and_then(|r| r.enabled).unwrap_or(config.enabled);
+    enabled.then(|| {
+        (
+            route.and_then(|r| r.min_hits).unwrap_or(config.min_hits),
+            route
+                .and_then(|r| r.window_percent)
+                .unwrap_or(config.window_percent),
+        )
+    })
+}
+
+/// After serving `cached` as a hit for `uri`, refetch it in the background
+/// if it's hot (`min_hits`) and close to expiry (`window_percent` of its
+/// TTL left), so the next request after it would have expired still hits.
+///
+/// The refetch goes through the proxy handler tagged
+/// `InsertSource::Refresh`, which skips the lookup and replaces the entry
+/// like any fill. A key already being refreshed isn't fetched again. A
+/// `tenant`'s entry is refetched with its `[cache.tenant]` header.
+pub fn maybe_refresh(
+    state: &Arc<AppState>,
+    config: &Config,
+    uri: &Uri,
+    tenant: Option<&str>,
+    cached: &CachedResponse,
+) {
+    let refresh = &config.cache.refresh_ahead;
+    if !refresh.enabled && refresh.routes.is_empty() {
+        return;
+    }
+    let Some((min_hits, window_percent)) = settings(refresh, uri.path()) else {
+        return;
+    };
+    let window = cached.ttl.mul_f64(window_percent / 100.0);
+    if cached.hits.get() < min_hits || cached.ttl.saturating_sub(cached.age()) > window {
+        return;
+    }
+
+    let keys = HttpKey::new(&config.cache.key).with_tenant(tenant);
+    let key = cache_key(&keys, &Method::GET, uri);
+    if !state.refresh.in_flight.lock().insert(key.clone()) {
+        return;
+    }
+    state.refresh.started.fetch_add(1, Ordering::Relaxed);
+    ::metrics::counter!("colander_refreshes_total").increment(1);
+
+    let state = Arc::clone(state);
+    let uri = uri.clone();
+    let tenant = tenant.map(|t| (config.cache.tenant.header.clone(), t.to_string()));
+    tokio::spawn(async move {
+        let mut req = Request::get(uri).extension(InsertSource::Refresh);
+        if let Some((header, tenant)) = tenant {
+            req = req.header(header, tenant);
+        }
+        let status = match req.body(Body::empty()) {
+            Ok(req) => proxy_handler(State(Arc::clone(&state)), req).await.status(),
+            Err(_) => StatusCode::BAD_REQUEST,
+        };
+        if status == StatusCode::OK {
+            tracing::debug!(key = %key, "refreshed ahead of expiry");
+        } else {
+            // The old entry stays until it expires; the next hit tries again
+            tracing::debug!(key = %key, status = status.as_u16(), "refresh-ahead failed");
+            ::metrics::counter!("colander_refresh_failures_total").increment(1);
+        }
+        state.refresh.in_flight.lock().remove(&key);
+    });
+}
@@ -1,27 +1,231 @@
+use crate::build_info;
+use crate::cache_layer::{CacheLayer, CacheMode, IncrError, InsertError};
+use crate::config::{self, RespConfig};
+use crate::listeners::constant_time_eq;
 use crate::proxy::AppState;
+use crate::pubsub::{glob_match, Message, Subscription};
 use bytes::Bytes;
+use colander_cache::traits::{CachedResponse, InsertSource};
 use redis_protocol::resp2::types::BytesFrame;
+use std::sync::Arc;
 use std::time::Duration;
 
-/// Dispatch a RESP2 frame (expected to be an Array of bulk strings) to the appropriate handler.
-pub fn dispatch(frame: &BytesFrame, state: &AppState) -> BytesFrame {
+/// Redis's own reply for writes against a read-only replica, so clients
+/// that already handle it (e.g. by failing over) do the right thing.
+const READONLY: &str = "READONLY You can't write against a read only replica.";
+
+const NOAUTH: &str = "NOAUTH Authentication required.";
+
+/// Commands a read-only connection may not run. `PUBLISH` is among them
+/// since subscribers act on keyspace events, which anyone publishing could
+/// forge.
+const WRITE_COMMANDS: &[&str] = &[
+    "SET", "SETNX", "SETEX", "GETSET", "GETDEL", "APPEND", "MSET", "INCR", "INCRBY", "DECR",
+    "DECRBY", "DEL", "FLUSHALL", "FLUSHDB", "PUBLISH",
+];
+
+/// Parameters `CONFIG GET`/`CONFIG SET` expose, named as in `config.toml`
+/// (`mode` being the runtime demo/bench toggle).
+const CONFIG_PARAMS: &[&str] = &[
+    "default_ttl_seconds",
+    "max_body_size_bytes",
+    "max_memory_bytes",
+    "mode",
+];
+
+/// Per-connection state: authentication (see `[resp] password`) and
+/// pub/sub subscriptions.
+pub struct Session {
+    password: Option<String>,
+    read_only_password: Option<String>,
+    /// False until a successful `AUTH` when a password is configured.
+    authenticated: bool,
+    /// Authenticated with `read_only_password`.
+    read_only: bool,
+    /// Present while subscribed to at least one channel or pattern.
+    subscription: Option<Subscription>,
+}
+
+impl Session {
+    /// Start a connection under the passwords configured when it opened.
+    pub fn new(config: &RespConfig) -> Self {
+        Self {
+            password: config.password.clone(),
+            read_only_password: config.read_only_password.clone(),
+            authenticated: config.password.is_none() && config.read_only_password.is_none(),
+            read_only: false,
+            subscription: None,
+        }
+    }
+
+    pub fn is_subscribed(&self) -> bool {
+        self.subscription.is_some()
+    }
+
+    /// The connection's subscriptions, if it's in subscribed mode.
+    pub fn subscription(&mut self) -> Option<&mut Subscription> {
+        self.subscription.as_mut()
+    }
+
+    /// (P)SUBSCRIBE and (P)UNSUBSCRIBE, which confirm each channel or
+    /// pattern with its own reply. Unsubscribing with no arguments drops
+    /// every channel (or pattern).
+    fn pubsub(
+        &mut self,
+        cmd: &str,
+        args: &[BytesFrame],
+        state: &AppState,
+        replies: &mut Vec<BytesFrame>,
+    ) {
+        let kind = Bytes::from(cmd.to_lowercase());
+        if args.is_empty() && matches!(cmd, "SUBSCRIBE" | "PSUBSCRIBE") {
+            replies.push(error_frame(&format!(
+                "ERR wrong number of arguments for '{}' command",
+                cmd.to_lowercase()
+            )));
+            return;
+        }
+        let subscription = self
+            .subscription
+            .get_or_insert_with(|| state.pubsub.register());
+        let names: Vec<Bytes> = match (cmd, args.is_empty()) {
+            ("UNSUBSCRIBE", true) => subscription.channels(),
+            ("PUNSUBSCRIBE", true) => subscription.patterns(),
+            _ => args.iter().map(bulk_to_bytes).collect(),
+        };
+        if names.is_empty() {
+            replies.push(BytesFrame::Array(vec![
+                BytesFrame::BulkString(kind.clone()),
+                BytesFrame::Null,
+                BytesFrame::Integer(subscription.count() as i64),
+            ]));
+        }
+        for name in names {
+            let count = match cmd {
+                "SUBSCRIBE" => subscription.subscribe(name.clone()),
+                "PSUBSCRIBE" => subscription.psubscribe(name.clone()),
+                "UNSUBSCRIBE" => subscription.unsubscribe(&name),
+                _ => subscription.punsubscribe(&name),
+            };
+            replies.push(BytesFrame::Array(vec![
+                BytesFrame::BulkString(kind.clone()),
+                BytesFrame::BulkString(name),
+                BytesFrame::Integer(count as i64),
+            ]));
+        }
+        if subscription.count() == 0 {
+            self.subscription = None;
+        }
+    }
+
+    /// `AUTH password` or `AUTH default password`. A failed attempt leaves
+    /// the connection as it was.
+    fn auth(&mut self, args: &[BytesFrame]) -> BytesFrame {
+        let presented = match args {
+            [password] => bulk_to_bytes(password),
+            [user, password] if bulk_to_string(user) == "default" => bulk_to_bytes(password),
+            [_, _] => return wrong_pass(),
+            _ => return error_frame("ERR wrong number of arguments for 'AUTH' command"),
+        };
+        if self.password.is_none() && self.read_only_password.is_none() {
+            return error_frame(
+                "ERR AUTH <password> called without any password configured for the default user. \
+                 Are you sure your configuration is correct?",
+            );
+        }
+        let matches = |configured: &Option<String>| {
+            configured
+                .as_ref()
+                .is_some_and(|p| constant_time_eq(p.as_bytes(), &presented))
+        };
+        if matches(&self.password) {
+            self.read_only = false;
+        } else if matches(&self.read_only_password) {
+            self.read_only = true;
+        } else {
+            return wrong_pass();
+        }
+        self.authenticated = true;
+        BytesFrame::SimpleString("OK".into())
+    }
+}
+
+fn wrong_pass() -> BytesFrame {
+    ::metrics::counter!("colander_resp_auth_failures_total").increment(1);
+    error_frame("WRONGPASS invalid username-password pair or user is disabled.")
+}
+
+/// Dispatch a RESP2 frame (expected to be an Array of bulk strings) to the
+/// appropriate handler, pushing its replies (one per channel for the
+/// subscribe commands, otherwise exactly one) onto `replies`.
+pub fn dispatch(
+    frame: &BytesFrame,
+    state: &AppState,
+    session: &mut Session,
+    replies: &mut Vec<BytesFrame>,
+) {
     let args = match frame {
         BytesFrame::Array(arr) => arr,
-        _ => return error_frame("ERR expected array"),
+        _ => return replies.push(error_frame("ERR expected array")),
     };
 
     if args.is_empty() {
-        return error_frame("ERR empty command");
+        return replies.push(error_frame("ERR empty command"));
     }
 
     let cmd = match &args[0] {
         BytesFrame::BulkString(b) => String::from_utf8_lossy(b).to_uppercase(),
-        _ => return error_frame("ERR invalid command format"),
+        _ => return replies.push(error_frame("ERR invalid command format")),
     };
 
-    let cache = state.cache.load();
+    if cmd == "AUTH" {
+        return replies.push(session.auth(&args[1..]));
+    }
+    if !session.authenticated {
+        return replies.push(error_frame(NOAUTH));
+    }
+    if session.read_only {
+        // CONFIG SET and RESETSTAT change server state; GET is a read
+        let config_write = (cmd == "CONFIG")
+            .then(|| args.get(1).map(bulk_to_string).unwrap_or_default())
+            .map(|sub| sub.to_lowercase())
+            .filter(|sub| sub == "set" || sub == "resetstat");
+        if WRITE_COMMANDS.contains(&cmd.as_str()) || config_write.is_some() {
+            let name = match config_write {
+                Some(sub) => format!("config|{sub}"),
+                None => cmd.to_lowercase(),
+            };
+            return replies.push(error_frame(&format!(
+                "NOPERM this user has no permissions to run the '{name}' command"
+            )));
+        }
+    }
 
     match cmd.as_str() {
+        "SUBSCRIBE" | "PSUBSCRIBE" | "UNSUBSCRIBE" | "PUNSUBSCRIBE" => {
+            session.pubsub(&cmd, &args[1..], state, replies)
+        }
+        // Subscribed connections only receive messages, as in Redis
+        "PING" if session.subscription.is_some() => {
+            let payload = args.get(1).map(bulk_to_bytes).unwrap_or_default();
+            replies.push(BytesFrame::Array(vec![
+                BytesFrame::BulkString(Bytes::from_static(b"pong")),
+                BytesFrame::BulkString(payload),
+            ]));
+        }
+        _ if session.subscription.is_some() => replies.push(error_frame(&format!(
+            "ERR Can't execute '{}': only (P)SUBSCRIBE / (P)UNSUBSCRIBE / PING are allowed in this context",
+            cmd.to_lowercase()
+        ))),
+        _ => replies.push(execute(&cmd, args, state)),
+    }
+}
+
+/// Run a regular (non-subscribe) command.
+fn execute(cmd: &str, args: &[BytesFrame], state: &AppState) -> BytesFrame {
+    let cache = state.cache.load();
+
+    match cmd {
         "PING" => BytesFrame::SimpleString("PONG".into()),
         "COMMAND" => BytesFrame::SimpleString("OK".into()),
         "GET" => {
@@ -31,7 +235,10 @@ pub fn dispatch(frame: &BytesFrame, state: &AppState) -> BytesFrame {
             let key = bulk_to_string(&args[1]);
             let lookup = cache.get(&key);
             match lookup.value {
-                Some(cached) => BytesFrame::BulkString(cached.body.clone()),
+                Some(cached) => {
+                    cached.record_hit();
+                    BytesFrame::BulkString(cached.body.clone())
+                }
                 None => BytesFrame::Null,
             }
         }
@@ -39,16 +246,189 @@ pub fn dispatch(frame: &BytesFrame, state: &AppState) -> BytesFrame {
             if args.len() < 3 {
                 return error_frame("ERR wrong number of arguments for 'SET' command");
             }
+            if cache.is_read_only() {
+                return error_frame(READONLY);
+            }
             let key = bulk_to_string(&args[1]);
             let value = bulk_to_bytes(&args[2]);
             let ttl = parse_set_options(&args[3..]);
-            cache.insert_raw(key, value, ttl);
+            if let Err(e) = cache.insert_raw(key, value, ttl, InsertSource::Resp) {
+                return insert_error(e);
+            }
+            BytesFrame::SimpleString("OK".into())
+        }
+        "SETNX" => {
+            if args.len() != 3 {
+                return error_frame("ERR wrong number of arguments for 'SETNX' command");
+            }
+            if cache.is_read_only() {
+                return error_frame(READONLY);
+            }
+            let key = bulk_to_string(&args[1]);
+            match cache.insert_raw_if_absent(&key, bulk_to_bytes(&args[2]), InsertSource::Resp) {
+                Ok(stored) => BytesFrame::Integer(stored.into()),
+                Err(e) => insert_error(e),
+            }
+        }
+        "SETEX" => {
+            if args.len() != 4 {
+                return error_frame("ERR wrong number of arguments for 'SETEX' command");
+            }
+            if cache.is_read_only() {
+                return error_frame(READONLY);
+            }
+            let secs = match bulk_to_string(&args[2]).parse::<u64>() {
+                Ok(secs) if secs > 0 => secs,
+                Ok(_) => return error_frame("ERR invalid expire time in 'setex' command"),
+                Err(_) => return error_frame("ERR value is not an integer or out of range"),
+            };
+            let key = bulk_to_string(&args[1]);
+            let stored = cache.insert_raw(
+                key,
+                bulk_to_bytes(&args[3]),
+                Some(Duration::from_secs(secs)),
+                InsertSource::Resp,
+            );
+            if let Err(e) = stored {
+                return insert_error(e);
+            }
+            BytesFrame::SimpleString("OK".into())
+        }
+        "GETSET" => {
+            if args.len() != 3 {
+                return error_frame("ERR wrong number of arguments for 'GETSET' command");
+            }
+            if cache.is_read_only() {
+                return error_frame(READONLY);
+            }
+            let key = bulk_to_string(&args[1]);
+            // Like Redis, the new value doesn't keep the old one's TTL
+            match cache.replace_raw(&key, bulk_to_bytes(&args[2]), InsertSource::Resp) {
+                Ok(Some(old)) => BytesFrame::BulkString(old),
+                Ok(None) => BytesFrame::Null,
+                Err(e) => insert_error(e),
+            }
+        }
+        "GETDEL" => {
+            if args.len() != 2 {
+                return error_frame("ERR wrong number of arguments for 'GETDEL' command");
+            }
+            if cache.is_read_only() {
+                return error_frame(READONLY);
+            }
+            let key = bulk_to_string(&args[1]);
+            match cache.take(&key) {
+                Some(cached) => BytesFrame::BulkString(cached.body.clone()),
+                None => BytesFrame::Null,
+            }
+        }
+        "APPEND" => {
+            if args.len() != 3 {
+                return error_frame("ERR wrong number of arguments for 'APPEND' command");
+            }
+            if cache.is_read_only() {
+                return error_frame(READONLY);
+            }
+            let key = bulk_to_string(&args[1]);
+            match cache.append_raw(&key, &bulk_to_bytes(&args[2]), InsertSource::Resp) {
+                Ok(len) => BytesFrame::Integer(len as i64),
+                Err(e) => insert_error(e),
+            }
+        }
+        "INCR" | "DECR" | "INCRBY" | "DECRBY" => {
+            let by = cmd.ends_with("BY");
+            if args.len() != if by { 3 } else { 2 } {
+                return error_frame(&format!(
+                    "ERR wrong number of arguments for '{}' command",
+                    cmd.to_lowercase()
+                ));
+            }
+            let delta = if by {
+                match bulk_to_string(&args[2]).parse::<i64>() {
+                    Ok(n) => n,
+                    Err(_) => return error_frame("ERR value is not an integer or out of range"),
+                }
+            } else {
+                1
+            };
+            let delta = if cmd.starts_with("DECR") {
+                match delta.checked_neg() {
+                    Some(n) => n,
+                    None => return error_frame("ERR decrement would overflow"),
+                }
+            } else {
+                delta
+            };
+            match cache.incr_by(&bulk_to_string(&args[1]), delta, InsertSource::Resp) {
+                Ok(n) => BytesFrame::Integer(n),
+                Err(IncrError::NotAnInteger) => {
+                    error_frame("ERR value is not an integer or out of range")
+                }
+                Err(IncrError::Overflow) => {
+                    error_frame("ERR increment or decrement would overflow")
+                }
+                Err(IncrError::ReadOnly) => error_frame(READONLY),
+            }
+        }
+        "STRLEN" => {
+            if args.len() != 2 {
+                return error_frame("ERR wrong number of arguments for 'STRLEN' command");
+            }
+            let len = live_entry(&cache, &bulk_to_string(&args[1])).map_or(0, |e| e.body.len());
+            BytesFrame::Integer(len as i64)
+        }
+        "TYPE" => {
+            if args.len() != 2 {
+                return error_frame("ERR wrong number of arguments for 'TYPE' command");
+            }
+            // Every cached value is a string
+            let kind = match live_entry(&cache, &bulk_to_string(&args[1])) {
+                Some(_) => "string",
+                None => "none",
+            };
+            BytesFrame::SimpleString(kind.into())
+        }
+        "MGET" => {
+            if args.len() < 2 {
+                return error_frame("ERR wrong number of arguments for 'MGET' command");
+            }
+            let keys: Vec<String> = args[1..].iter().map(bulk_to_string).collect();
+            let values = cache
+                .get_many(&keys)
+                .into_iter()
+                .map(|value| match value {
+                    Some(cached) => {
+                        cached.record_hit();
+                        BytesFrame::BulkString(cached.body.clone())
+                    }
+                    None => BytesFrame::Null,
+                })
+                .collect();
+            BytesFrame::Array(values)
+        }
+        "MSET" => {
+            if args.len() < 3 || args.len().is_multiple_of(2) {
+                return error_frame("ERR wrong number of arguments for 'MSET' command");
+            }
+            if cache.is_read_only() {
+                return error_frame(READONLY);
+            }
+            let entries = args[1..]
+                .chunks_exact(2)
+                .map(|pair| (bulk_to_string(&pair[0]), bulk_to_bytes(&pair[1])))
+                .collect();
+            if let Err(e) = cache.insert_raw_many(entries, None, InsertSource::Resp) {
+                return insert_error(e);
+            }
             BytesFrame::SimpleString("OK".into())
         }
         "DEL" => {
             if args.len() < 2 {
                 return error_frame("ERR wrong number of arguments for 'DEL' command");
             }
+            if cache.is_read_only() {
+                return error_frame(READONLY);
+            }
             let mut count: i64 = 0;
             for arg in &args[1..] {
                 let key = bulk_to_string(arg);
@@ -72,10 +452,304 @@ pub fn dispatch(frame: &BytesFrame, state: &AppState) -> BytesFrame {
                 None => BytesFrame::Integer(-2),
             }
         }
+        "EXISTS" => {
+            if args.len() < 2 {
+                return error_frame("ERR wrong number of arguments for 'EXISTS' command");
+            }
+            // Repeated keys count each time, as in Redis
+            let count = args[1..]
+                .iter()
+                .filter(|arg| live_entry(&cache, &bulk_to_string(arg)).is_some())
+                .count();
+            BytesFrame::Integer(count as i64)
+        }
+        "DBSIZE" => BytesFrame::Integer(cache.primary_stats().current_size as i64),
+        "FLUSHALL" | "FLUSHDB" => {
+            // ASYNC/SYNC are accepted and ignored: clearing is always synchronous
+            if cache.is_read_only() {
+                return error_frame(READONLY);
+            }
+            cache.clear();
+            BytesFrame::SimpleString("OK".into())
+        }
+        "INFO" => {
+            let sections: Vec<String> = args[1..].iter().map(bulk_to_string).collect();
+            BytesFrame::BulkString(Bytes::from(info(state, &cache, &sections)))
+        }
+        "CONFIG" => {
+            let sub = args.get(1).map(bulk_to_string).unwrap_or_default();
+            match sub.to_uppercase().as_str() {
+                "GET" if args.len() >= 3 => config_get(&args[2..], state, &cache),
+                "SET" if args.len() >= 4 && args.len().is_multiple_of(2) => {
+                    config_set(&args[2..], state)
+                }
+                "RESETSTAT" if args.len() == 2 => {
+                    cache.reset_stats();
+                    BytesFrame::SimpleString("OK".into())
+                }
+                "GET" | "SET" | "RESETSTAT" => error_frame(&format!(
+                    "ERR wrong number of arguments for 'config|{}' command",
+                    sub.to_lowercase()
+                )),
+                _ => error_frame(&format!(
+                    "ERR unknown subcommand '{sub}'. Try CONFIG GET, CONFIG SET or CONFIG RESETSTAT."
+                )),
+            }
+        }
+        "PUBLISH" => {
+            if args.len() != 3 {
+                return error_frame("ERR wrong number of arguments for 'PUBLISH' command");
+            }
+            let delivered = state
+                .pubsub
+                .publish(&bulk_to_bytes(&args[1]), bulk_to_bytes(&args[2]));
+            BytesFrame::Integer(delivered as i64)
+        }
+        "HOTKEYS" => {
+            // HOTKEYS [count] — flat key/count pairs, like ZRANGE ... WITHSCORES
+            let count = match args.get(1) {
+                Some(arg) => match bulk_to_string(arg).parse::<usize>() {
+                    Ok(n) => n,
+                    Err(_) => return error_frame("ERR value is not an integer or out of range"),
+                },
+                None => 10,
+            };
+            let mut out = Vec::new();
+            for (key, hits) in cache.hot_keys(count) {
+                out.push(BytesFrame::BulkString(Bytes::from(key)));
+                out.push(BytesFrame::Integer(hits as i64));
+            }
+            BytesFrame::Array(out)
+        }
         other => error_frame(&format!("ERR unknown command '{other}'")),
     }
 }
 
+/// `CONFIG GET pattern [pattern ...]`: flat name/value pairs for every
+/// exposed parameter matching a glob, as strings.
+fn config_get(patterns: &[BytesFrame], state: &AppState, cache: &CacheLayer) -> BytesFrame {
+    let config = state.config.load();
+    let mut out = Vec::new();
+    for &name in CONFIG_PARAMS {
+        let wanted = patterns
+            .iter()
+            .any(|p| glob_match(&bulk_to_bytes(p).to_ascii_lowercase(), name.as_bytes()));
+        if !wanted {
+            continue;
+        }
+        let value = match name {
+            "default_ttl_seconds" => config.cache.default_ttl_seconds.to_string(),
+            "max_body_size_bytes" => config.cache.max_body_size_bytes.to_string(),
+            "max_memory_bytes" => config.cache.max_memory_bytes.to_string(),
+            _ => if cache.is_demo_mode() {
+                "demo"
+            } else {
+                "bench"
+            }
+            .to_string(),
+        };
+        out.push(BytesFrame::BulkString(Bytes::from_static(name.as_bytes())));
+        out.push(BytesFrame::BulkString(Bytes::from(value)));
+    }
+    BytesFrame::Array(out)
+}
+
+/// `CONFIG SET name value [name value ...]`: all or nothing. Config
+/// parameters go through the same apply path as a `config.toml` reload,
+/// and stay in effect until the file next sets them or the process restarts.
+fn config_set(pairs: &[BytesFrame], state: &AppState) -> BytesFrame {
+    let _guard = state.config_lock.lock();
+    let old = state.config.load_full();
+    let mut new = (*old).clone();
+    let mut mode = None;
+    for pair in pairs.chunks_exact(2) {
+        let name = bulk_to_string(&pair[0]).to_lowercase();
+        let value = bulk_to_string(&pair[1]);
+        let invalid = || {
+            error_frame(&format!(
+                "ERR CONFIG SET failed (possibly related to argument '{name}') - invalid value '{value}'"
+            ))
+        };
+        match name.as_str() {
+            "default_ttl_seconds" => match value.parse() {
+                Ok(secs) => new.cache.default_ttl_seconds = secs,
+                Err(_) => return invalid(),
+            },
+            "max_body_size_bytes" => match value.parse() {
+                Ok(bytes) => new.cache.max_body_size_bytes = bytes,
+                Err(_) => return invalid(),
+            },
+            "max_memory_bytes" => match value.parse() {
+                Ok(bytes) => new.cache.max_memory_bytes = bytes,
+                Err(_) => return invalid(),
+            },
+            "mode" => match value.to_lowercase().as_str() {
+                "demo" => mode = Some(CacheMode::Demo),
+                "bench" => mode = Some(CacheMode::Bench),
+                _ => return invalid(),
+            },
+            _ => {
+                return error_frame(&format!(
+                    "ERR Unknown option or number of arguments for CONFIG SET - '{name}'"
+                ))
+            }
+        }
+    }
+    if let Err(e) = new.validate() {
+        return error_frame(&format!("ERR CONFIG SET failed - {e}"));
+    }
+    if new.cache.max_memory_bytes != old.cache.max_memory_bytes && state.cache.load().is_read_only()
+    {
+        return error_frame(READONLY);
+    }
+    let applied = config::apply_changes(&old, &new, &state.cache, &state.upstreams);
+    state.config.store(Arc::new(applied));
+    if let Some(mode) = mode {
+        state.cache.load().set_mode(mode);
+    }
+    BytesFrame::SimpleString("OK".into())
+}
+
+/// How a published message reaches a subscriber: `message channel payload`,
+/// or `pmessage pattern channel payload` for pattern subscriptions.
+pub fn message_frame(message: Message) -> BytesFrame {
+    let mut frame = Vec::with_capacity(4);
+    match message.pattern {
+        Some(pattern) => {
+            frame.push(BytesFrame::BulkString(Bytes::from_static(b"pmessage")));
+            frame.push(BytesFrame::BulkString(pattern));
+        }
+        None => frame.push(BytesFrame::BulkString(Bytes::from_static(b"message"))),
+    }
+    frame.push(BytesFrame::BulkString(message.channel));
+    frame.push(BytesFrame::BulkString(message.payload));
+    BytesFrame::Array(frame)
+}
+
+/// A Redis-style `INFO` block: `# Section` headers followed by `field:value`
+/// lines, limited to `sections` unless that's empty or `all`/`default`/`everything`.
+fn info(state: &AppState, cache: &CacheLayer, sections: &[String]) -> String {
+    let wanted = |name: &str| {
+        sections.is_empty()
+            || sections.iter().any(|s| {
+                s.eq_ignore_ascii_case(name)
+                    || ["all", "default", "everything"]
+                        .iter()
+                        .any(|all| s.eq_ignore_ascii_case(all))
+            })
+    };
+    let stats = cache.primary_stats();
+    let lookups = stats.hits + stats.misses;
+    let hit_rate = if lookups > 0 {
+        stats.hits as f64 / lookups as f64
+    } else {
+        0.0
+    };
+    let uptime = state.started_at.elapsed().as_secs();
+
+    let mut out = String::new();
+    let mut section = |name: &str, fields: Vec<(&str, String)>| {
+        if !wanted(name) {
+            return;
+        }
+        if !out.is_empty() {
+            out.push_str("\r\n");
+        }
+        out.push_str(&format!("# {name}\r\n"));
+        for (field, value) in fields {
+            out.push_str(&format!("{field}:{value}\r\n"));
+        }
+    };
+    section(
+        "Server",
+        vec![
+            ("colander_version", build_info::VERSION.to_string()),
+            ("colander_git_sha1", build_info::GIT_HASH.to_string()),
+            ("process_id", std::process::id().to_string()),
+            ("uptime_in_seconds", uptime.to_string()),
+            ("uptime_in_days", (uptime / 86_400).to_string()),
+        ],
+    );
+    let rss = rss_bytes().unwrap_or(0);
+    let used = cache.memory_bytes();
+    let max = cache.max_memory();
+    section(
+        "Memory",
+        vec![
+            ("used_memory", used.to_string()),
+            ("used_memory_human", human_bytes(used as u64)),
+            ("used_memory_rss", rss.to_string()),
+            ("used_memory_rss_human", human_bytes(rss)),
+            ("maxmemory", max.to_string()),
+            ("maxmemory_human", human_bytes(max as u64)),
+        ],
+    );
+    section(
+        "Stats",
+        vec![
+            ("keyspace_hits", stats.hits.to_string()),
+            ("keyspace_misses", stats.misses.to_string()),
+            ("hit_rate", format!("{hit_rate:.4}")),
+            ("evicted_keys", stats.evictions.to_string()),
+            (
+                "oversize_rejections",
+                cache.oversize_rejections().to_string(),
+            ),
+        ],
+    );
+    let mut cache_fields = vec![("eviction_policy", cache.primary_name().to_string())];
+    let comparisons = cache.comparison_names();
+    if !comparisons.is_empty() {
+        cache_fields.push(("comparison_policy", comparisons.join(",")));
+    }
+    cache_fields.extend([
+        ("capacity", cache.capacity().to_string()),
+        (
+            "mode",
+            if cache.is_demo_mode() {
+                "demo"
+            } else {
+                "bench"
+            }
+            .to_string(),
+        ),
+        ("read_only", u8::from(cache.is_read_only()).to_string()),
+    ]);
+    section("Cache", cache_fields);
+    // Every entry has a TTL, so all keys count as expiring
+    section(
+        "Keyspace",
+        vec![("db0", format!("keys={0},expires={0}", stats.current_size))],
+    );
+    out
+}
+
+/// Resident set size of this process, where the OS reports it.
+/// `bytes` in mebibytes, as `INFO` shows sizes.
+fn human_bytes(bytes: u64) -> String {
+    format!("{:.2}M", bytes as f64 / (1024.0 * 1024.0))
+}
+
+fn rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+/// The unexpired entry for `key`, without counting a lookup or promoting it.
+fn live_entry(cache: &CacheLayer, key: &str) -> Option<Arc<CachedResponse>> {
+    cache.peek(key).filter(|entry| !entry.is_expired())
+}
+
+/// The reply to a write `insert_raw` refused.
+fn insert_error(e: InsertError) -> BytesFrame {
+    match e {
+        InsertError::TooLarge => error_frame("ERR value is larger than max_body_size_bytes"),
+        InsertError::ReadOnly => error_frame(READONLY),
+    }
+}
+
 fn error_frame(msg: &str) -> BytesFrame {
     BytesFrame::Error(msg.into())
 }
@@ -109,3 +783,130 @@ fn parse_set_options(args: &[BytesFrame]) -> Option<Duration> {
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{Config, ListenerRole};
+    use crate::server::Server;
+    use std::collections::HashSet;
+    use std::net::SocketAddr;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpStream;
+
+    async fn start() -> (Server, SocketAddr) {
+        let mut config = Config::default_config();
+        config.server.listen_addr = "127.0.0.1:0".into();
+        config.server.metrics_addr = "127.0.0.1:0".into();
+        config.resp.listen_addr = "127.0.0.1:0".into();
+        config.resp.enabled = true;
+        let server = Server::start(config).await.unwrap();
+        let addr = server.handle().addr(ListenerRole::Resp).unwrap();
+        (server, addr)
+    }
+
+    /// Send `commands` pipelined on one connection and return each reply:
+    /// a status, integer or bulk string as text, or `None` for a null.
+    async fn pipeline(addr: SocketAddr, commands: Vec<Vec<String>>) -> Vec<Option<String>> {
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (read, mut write) = stream.into_split();
+        let mut out = Vec::new();
+        for args in &commands {
+            out.extend(format!("*{}\r\n", args.len()).bytes());
+            for arg in args {
+                out.extend(format!("${}\r\n{arg}\r\n", arg.len()).bytes());
+            }
+        }
+        write.write_all(&out).await.unwrap();
+
+        let mut lines = BufReader::new(read).lines();
+        let mut replies = Vec::with_capacity(commands.len());
+        while replies.len() < commands.len() {
+            let line = lines.next_line().await.unwrap().unwrap();
+            let reply = match line.split_at(1) {
+                ("+" | ":", text) => Some(text.to_string()),
+                ("$", "-1") => None,
+                ("$", _) => Some(lines.next_line().await.unwrap().unwrap()),
+                _ => panic!("unexpected reply {line:?}"),
+            };
+            replies.push(reply);
+        }
+        replies
+    }
+
+    /// Run `commands(client)` on two connections at once.
+    async fn race(
+        addr: SocketAddr,
+        commands: impl Fn(usize) -> Vec<Vec<String>>,
+    ) -> [Vec<Option<String>>; 2] {
+        let (a, b) = tokio::join!(
+            tokio::spawn(pipeline(addr, commands(0))),
+            tokio::spawn(pipeline(addr, commands(1))),
+        );
+        [a.unwrap(), b.unwrap()]
+    }
+
+    fn command(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    const ROUNDS: usize = 2000;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn racing_connections_see_atomic_writes() {
+        let (server, addr) = start().await;
+
+        // SETNX: exactly one connection stores each key
+        let replies = race(addr, |c| {
+            (0..ROUNDS)
+                .map(|i| command(&["SETNX", &format!("nx:{i}"), &c.to_string()]))
+                .collect()
+        })
+        .await;
+        let stored = replies
+            .iter()
+            .flatten()
+            .filter(|r| r.as_deref() == Some("1"));
+        assert_eq!(stored.count(), ROUNDS);
+
+        // APPEND: no append is lost, and each sees a distinct length
+        let replies = race(addr, |_| {
+            (0..ROUNDS)
+                .map(|_| command(&["APPEND", "log", "x"]))
+                .collect()
+        })
+        .await;
+        let lengths: HashSet<_> = replies.iter().flatten().flatten().collect();
+        assert_eq!(lengths.len(), 2 * ROUNDS);
+        let get = pipeline(addr, vec![command(&["GET", "log"])]).await;
+        assert_eq!(get[0].as_ref().map(String::len), Some(2 * ROUNDS));
+
+        // GETSET: every value written is handed back exactly once, by the
+        // write that replaced it or by the final GET
+        let replies = race(addr, |c| {
+            (0..ROUNDS)
+                .map(|i| command(&["GETSET", "swap", &format!("{c}-{i}")]))
+                .collect()
+        })
+        .await;
+        let get = pipeline(addr, vec![command(&["GET", "swap"])]).await;
+        let seen: Vec<_> = replies.iter().flatten().chain(&get).flatten().collect();
+        assert_eq!(seen.len(), 2 * ROUNDS);
+        assert_eq!(seen.iter().collect::<HashSet<_>>().len(), 2 * ROUNDS);
+
+        // GETDEL: each value is deleted and returned once
+        let sets = (0..ROUNDS)
+            .map(|i| command(&["SET", &format!("del:{i}"), "v"]))
+            .collect();
+        pipeline(addr, sets).await;
+        let replies = race(addr, |_| {
+            (0..ROUNDS)
+                .map(|i| command(&["GETDEL", &format!("del:{i}")]))
+                .collect()
+        })
+        .await;
+        assert_eq!(replies.iter().flatten().flatten().count(), ROUNDS);
+
+        server.handle().shutdown();
+        server.join().await;
+    }
+}
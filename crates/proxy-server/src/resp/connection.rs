@@ -1,19 +1,55 @@
 use super::cmd;
 use crate::proxy::AppState;
-use bytes::BytesMut;
-use redis_protocol::resp2::decode::decode_bytes;
-use redis_protocol::resp2::encode::encode_bytes;
+use crate::pubsub::Message;
+use bytes::{Bytes, BytesMut};
+use redis_protocol::resp2::decode::decode_bytes_mut;
+use redis_protocol::resp2::encode::extend_encode;
 use redis_protocol::resp2::types::BytesFrame;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Longest inline command line, as in Redis.
+const MAX_INLINE_BYTES: usize = 64 * 1024;
+
+/// Handle a single RESP client connection: read frames, dispatch commands,
+/// write responses, and forward published messages while subscribed.
+pub async fn handle_connection<S>(mut stream: S, state: &AppState)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let config = state.config.load();
+    let max_frame = config.resp.max_frame_bytes;
+    let idle_timeout = (config.resp.idle_timeout_secs > 0)
+        .then(|| Duration::from_secs(config.resp.idle_timeout_secs));
+    let mut session = cmd::Session::new(&config.resp);
+    drop(config);
 
-/// Handle a single RESP client connection: read frames, dispatch commands, write responses.
-pub async fn handle_connection(mut stream: TcpStream, state: &AppState) {
     let mut buf = BytesMut::with_capacity(4096);
+    let mut replies = Vec::new();
 
     loop {
-        // Read data from client
-        match stream.read_buf(&mut buf).await {
+        // Read data from client, or relay a message to a subscriber.
+        // Subscribers are expected to sit quietly, so they never time out
+        let idle = idle_timeout.filter(|_| !session.is_subscribed());
+        let read = tokio::select! {
+            read = stream.read_buf(&mut buf) => read,
+            Some(message) = next_message(&mut session) => {
+                let mut out = BytesMut::new();
+                if extend_encode(&mut out, &cmd::message_frame(message), false).is_err()
+                    || stream.write_all(&out).await.is_err()
+                {
+                    return;
+                }
+                continue;
+            }
+            _ = tokio::time::sleep(idle.unwrap_or_default()), if idle.is_some() => {
+                tracing::debug!("RESP client idle, closing");
+                ::metrics::counter!("colander_resp_connections_closed_total", "reason" => "idle")
+                    .increment(1);
+                return;
+            }
+        };
+        match read {
             Ok(0) => break, // EOF
             Ok(_) => {}
             Err(e) => {
@@ -22,36 +58,52 @@ pub async fn handle_connection(mut stream: TcpStream, state: &AppState) {
             }
         }
 
-        // Try to decode complete frames from the buffer
-        loop {
-            // clone().freeze() gives us &Bytes without copying the data
-            let (frame, consumed) = match decode_bytes(&buf.clone().freeze()) {
-                Ok(Some((frame, consumed))) => (frame, consumed),
-                Ok(None) => break, // Need more data
-                Err(e) => {
+        // Decode every complete frame in the buffer and answer them with a
+        // single write, so pipelined commands cost one syscall per batch.
+        // Frames are split off `buf` without copying
+        let mut out = BytesMut::new();
+        // Ends with whether the unfinished command left in `buf` is too big
+        let too_large = loop {
+            let frame = match decode_frame(&mut buf, max_frame) {
+                Decoded::Frame(frame) => frame,
+                Decoded::Blank => continue,
+                Decoded::Incomplete { too_large } => break too_large,
+                Decoded::Invalid(e) => {
                     tracing::debug!(error = %e, "RESP decode error");
                     let err_frame = BytesFrame::Error("ERR protocol error".into());
-                    let mut out = BytesMut::new();
                     // false = don't encode integers as bulk strings (standard RESP2)
-                    if encode_bytes(&mut out, &err_frame, false).is_ok() {
+                    if extend_encode(&mut out, &err_frame, false).is_ok() {
                         let _ = stream.write_all(&out).await;
                     }
                     return;
                 }
             };
 
-            // Advance the buffer past the consumed bytes
-            let _ = buf.split_to(consumed);
-
             // Dispatch the command
-            let response = cmd::dispatch(&frame, state);
+            cmd::dispatch(&frame, state, &mut session, &mut replies);
+            if !encode_replies(&mut out, &mut replies) {
+                return;
+            }
+        };
 
-            // Encode and send the response
-            let mut out = BytesMut::new();
-            if let Err(e) = encode_bytes(&mut out, &response, false) {
-                tracing::debug!(error = %e, "RESP encode error");
-                break;
+        // A partial command this big won't fit; refuse it rather than
+        // buffering without bound
+        if too_large {
+            tracing::debug!(
+                buffered = buf.len(),
+                max_frame,
+                "RESP request too large, closing"
+            );
+            ::metrics::counter!("colander_resp_connections_closed_total", "reason" => "too_large")
+                .increment(1);
+            let err_frame = BytesFrame::Error("ERR Protocol error: request too large".into());
+            if extend_encode(&mut out, &err_frame, false).is_ok() {
+                let _ = stream.write_all(&out).await;
             }
+            return;
+        }
+
+        if !out.is_empty() {
             if let Err(e) = stream.write_all(&out).await {
                 tracing::debug!(error = %e, "RESP write error");
                 return;
@@ -59,3 +111,75 @@ pub async fn handle_connection(mut stream: TcpStream, state: &AppState) {
         }
     }
 }
+
+/// What `decode_frame` found at the start of a connection's buffer.
+#[derive(Debug)]
+pub enum Decoded {
+    /// A whole command, split off the buffer.
+    Frame(BytesFrame),
+    /// A blank inline line, consumed.
+    Blank,
+    /// The rest of the command hasn't arrived yet; nothing was consumed.
+    /// `too_large` if what's buffered is already over the limit, so it
+    /// isn't worth waiting for.
+    Incomplete { too_large: bool },
+    /// Bytes that aren't RESP. The connection can't recover from them.
+    Invalid(String),
+}
+
+/// Split the next command off `buf`: a RESP array, or anything else as an
+/// inline command line, as sent by telnet and simple health checkers.
+/// Frames are split off without copying. `max_frame` bounds an unfinished
+/// RESP command, and `MAX_INLINE_BYTES` an unfinished inline one.
+pub fn decode_frame(buf: &mut BytesMut, max_frame: usize) -> Decoded {
+    if buf.first().is_some_and(|&b| b != b'*') {
+        let Some(end) = buf.iter().position(|&b| b == b'\n') else {
+            return Decoded::Incomplete {
+                too_large: buf.len() > MAX_INLINE_BYTES,
+            };
+        };
+        let line = buf.split_to(end + 1).freeze();
+        return match inline_frame(&line.slice(..end)) {
+            Some(frame) => Decoded::Frame(frame),
+            None => Decoded::Blank,
+        };
+    }
+    match decode_bytes_mut(buf) {
+        Ok(Some((frame, _, _))) => Decoded::Frame(frame),
+        Ok(None) => Decoded::Incomplete {
+            too_large: buf.len() > max_frame,
+        },
+        Err(e) => Decoded::Invalid(e.to_string()),
+    }
+}
+
+/// Append `replies` to `out`, emptying it. False if one couldn't be encoded.
+fn encode_replies(out: &mut BytesMut, replies: &mut Vec<BytesFrame>) -> bool {
+    for response in replies.drain(..) {
+        if let Err(e) = extend_encode(out, &response, false) {
+            tracing::debug!(error = %e, "RESP encode error");
+            return false;
+        }
+    }
+    true
+}
+
+/// An inline command line (without its `\n`) as the array of bulk strings
+/// a client library would have sent. Arguments are split on whitespace;
+/// quoting isn't supported. `None` for a blank line.
+fn inline_frame(line: &Bytes) -> Option<BytesFrame> {
+    let args: Vec<BytesFrame> = line
+        .split(|b| b.is_ascii_whitespace())
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| BytesFrame::BulkString(line.slice_ref(arg)))
+        .collect();
+    (!args.is_empty()).then_some(BytesFrame::Array(args))
+}
+
+/// The next message for a subscribed connection; never resolves otherwise.
+async fn next_message(session: &mut cmd::Session) -> Option<Message> {
+    match session.subscription() {
+        Some(subscription) => subscription.recv().await,
+        None => std::future::pending().await,
+    }
+}
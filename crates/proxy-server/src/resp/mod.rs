@@ -1,23 +1,34 @@
 mod cmd;
 mod connection;
 
+pub use connection::{decode_frame, Decoded};
+
 use crate::proxy::AppState;
+use crate::tls::{self, ReloadableTls};
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio::net::TcpListener;
 use tokio_util::sync::CancellationToken;
 
-/// Run the RESP2 server on the given address, sharing the same cache as the HTTP proxy.
-pub async fn run_resp_server(addr: &str, state: Arc<AppState>, shutdown: CancellationToken) {
-    let listener = match TcpListener::bind(addr).await {
-        Ok(l) => {
-            tracing::info!(addr = %addr, "RESP server listening");
-            l
-        }
-        Err(e) => {
-            tracing::error!(error = %e, addr = %addr, "failed to bind RESP server");
-            return;
-        }
-    };
+/// Redis's reply to clients over `maxclients`.
+const TOO_MANY_CLIENTS: &[u8] = b"-ERR max number of clients reached\r\n";
+
+/// Run the RESP2 server on a bound listener, sharing the same cache as the HTTP proxy.
+///
+/// At most `[resp] max_connections` clients are served at once; others get
+/// an error as soon as they connect.
+pub async fn run_resp_server(
+    listener: TcpListener,
+    tls: Option<ReloadableTls>,
+    state: Arc<AppState>,
+    shutdown: CancellationToken,
+) {
+    if let Ok(addr) = listener.local_addr() {
+        tracing::info!(addr = %addr, tls = tls.is_some(), "RESP server listening");
+    }
+
+    let open = Arc::new(AtomicUsize::new(0));
 
     loop {
         tokio::select! {
@@ -28,10 +39,30 @@ pub async fn run_resp_server(addr: &str, state: Arc<AppState>, shutdown: Cancell
             result = listener.accept() => {
                 match result {
                     Ok((stream, peer)) => {
+                        let limit = state.config.load().resp.max_connections;
+                        let Some(slot) = ConnectionSlot::claim(&open, limit) else {
+                            tracing::warn!(peer = %peer, limit, "RESP connection limit reached");
+                            ::metrics::counter!("colander_resp_connections_rejected_total").increment(1);
+                            // Best effort: the socket is non-blocking and its send buffer
+                            // empty, so this never stalls accepting. TLS clients just see
+                            // the close
+                            if let (None, Ok(mut stream)) = (&tls, stream.into_std()) {
+                                let _ = stream.write(TOO_MANY_CLIENTS);
+                            }
+                            continue;
+                        };
                         let state = Arc::clone(&state);
+                        let tls = tls.as_ref().map(ReloadableTls::acceptor);
                         tokio::spawn(async move {
+                            let _slot = slot;
                             tracing::debug!(peer = %peer, "RESP client connected");
-                            connection::handle_connection(stream, &state).await;
+                            match tls {
+                                Some(acceptor) => match tls::handshake(&acceptor, stream).await {
+                                    Ok(stream) => connection::handle_connection(stream, &state).await,
+                                    Err(e) => tracing::debug!(error = %e, peer = %peer, "RESP TLS handshake failed"),
+                                },
+                                None => connection::handle_connection(stream, &state).await,
+                            }
                             tracing::debug!(peer = %peer, "RESP client disconnected");
                         });
                     }
@@ -43,3 +74,24 @@ pub async fn run_resp_server(addr: &str, state: Arc<AppState>, shutdown: Cancell
         }
     }
 }
+
+/// One of a listener's `max_connections`, released when the connection ends.
+struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl ConnectionSlot {
+    fn claim(open: &Arc<AtomicUsize>, limit: usize) -> Option<Self> {
+        open.fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+            (n < limit).then_some(n + 1)
+        })
+        .ok()?;
+        ::metrics::gauge!("colander_resp_connections_active").increment(1.0);
+        Some(Self(Arc::clone(open)))
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+        ::metrics::gauge!("colander_resp_connections_active").decrement(1.0);
+    }
+}
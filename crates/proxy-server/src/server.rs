@@ -0,0 +1,372 @@
+use crate::build_info;
+use crate::cache_layer::CacheLayer;
+use crate::config::{self, Config, ConfigErrors, ListenerRole};
+use crate::drain::{self, DrainOptions};
+use crate::export;
+use crate::invalidation::{self, InvalidationBus};
+use crate::listeners::{self, ListenerContext};
+use crate::metrics::{metrics_broadcaster, LatencyRecorder, MetricsSnapshot, MetricsState};
+use crate::plugin::Plugins;
+use crate::proxy::{AppState, OriginFetcher};
+use crate::pubsub::PubSub;
+use crate::shed::LoadShedder;
+use crate::tls::TlsReloader;
+use crate::upstream::{self, UpstreamPool};
+use crate::warmup::{self, WarmupProgress};
+use arc_swap::ArcSwap;
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use std::net::SocketAddr;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+/// A complete colander instance running in-process: every configured
+/// listener (proxy, admin, metrics, RESP, memcached) plus the background
+/// tasks behind them (metrics broadcast, health checks, outlier detection,
+/// warmup).
+///
+/// The `proxy-server` binary is a thin wrapper that adds a config file,
+/// file watching and signal handling. Other applications can embed the same
+/// stack, e.g. on port 0 in integration tests:
+///
+/// ```no_run
+/// # async fn run() {
+/// use proxy_server::config::{Config, ListenerRole};
+/// use proxy_server::server::Server;
+///
+/// let mut config = Config::default_config();
+/// config.server.listen_addr = "127.0.0.1:0".into();
+/// config.server.metrics_addr = "127.0.0.1:0".into();
+/// config.resp.enabled = false;
+///
+/// let server = Server::start(config).await.unwrap();
+/// let proxy = server.handle().addr(ListenerRole::Proxy).unwrap();
+/// // ... send requests to `proxy`, inspect `server.handle().cache()` ...
+/// server.handle().shutdown();
+/// server.join().await;
+/// # }
+/// ```
+///
+/// Prometheus metrics are process-wide: the first server installs the
+/// global recorder and later ones share it.
+pub struct Server {
+    handle: ServerHandle,
+    tasks: JoinSet<()>,
+}
+
+/// Why `Server::start` couldn't bring the instance up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StartError(pub String);
+
+impl std::fmt::Display for StartError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for StartError {}
+
+/// Cheap, cloneable access to a running `Server`.
+#[derive(Clone)]
+pub struct ServerHandle {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    state: Arc<AppState>,
+    metrics: MetricsState,
+    addrs: Vec<(Vec<ListenerRole>, SocketAddr)>,
+    tls: TlsReloader,
+}
+
+impl Server {
+    /// Build the cache and upstream pool from `config`, bind every listener
+    /// and start serving.
+    ///
+    /// Fails if a listener can't be bound or its TLS material loaded, or the
+    /// `[metrics_export]` file opened. Nothing is left running then.
+    pub async fn start(config: Config) -> Result<Self, StartError> {
+        Self::start_with(config, None).await
+    }
+
+    /// `start`, filling misses from `origin` instead of the `[upstream]`
+    /// backends (WebSocket and event-stream requests still go to those).
+    pub async fn start_with_origin(
+        config: Config,
+        origin: Arc<dyn OriginFetcher>,
+    ) -> Result<Self, StartError> {
+        Self::start_with(config, Some(origin)).await
+    }
+
+    async fn start_with(
+        config: Config,
+        origin: Option<Arc<dyn OriginFetcher>>,
+    ) -> Result<Self, StartError> {
+        let prom_handle = prometheus_handle();
+        let plugins = Plugins::load(&config.cache.plugins).map_err(StartError)?;
+
+        let pubsub = Arc::new(PubSub::default());
+        let (invalidations, invalidation_rx) = InvalidationBus::new();
+        let cache = CacheLayer::from_config(&config.cache, config.cache.capacity)
+            .with_keyspace_events(Arc::clone(&pubsub))
+            .with_invalidation_bus(Arc::new(invalidations));
+        let client = upstream::build_client(&config.upstream);
+
+        let mut upstreams = UpstreamPool::new(&config.upstream.effective_backends());
+        if config.upstream.timeout_ms > 0 {
+            upstreams =
+                upstreams.with_request_timeout(Duration::from_millis(config.upstream.timeout_ms));
+        }
+        if config.upstream.outlier_detection.enabled {
+            upstreams = upstreams.with_outlier_detection(config.upstream.outlier_detection.clone());
+        }
+        if config.upstream.circuit_breaker.enabled {
+            upstreams = upstreams.with_circuit_breaker(config.upstream.circuit_breaker.clone());
+        }
+        let concurrency = &config.upstream.concurrency;
+        if concurrency.max_in_flight > 0 {
+            upstreams = upstreams.with_concurrency_limit(
+                concurrency.max_in_flight,
+                Duration::from_millis(concurrency.queue_timeout_ms),
+            );
+        }
+        let upstreams = Arc::new(upstreams);
+
+        let shutdown = CancellationToken::new();
+        let started_at = Instant::now();
+
+        let state = Arc::new(AppState {
+            cache: ArcSwap::from_pointee(cache),
+            client,
+            upstreams: Arc::clone(&upstreams),
+            latency: LatencyRecorder::new(),
+            ready: AtomicBool::new(false),
+            config: ArcSwap::from_pointee(config.clone()),
+            config_lock: Default::default(),
+            draining: AtomicBool::new(false),
+            shutdown: shutdown.clone(),
+            shedder: LoadShedder::default(),
+            started_at,
+            pubsub,
+            warmup: WarmupProgress::default(),
+            refresh: Default::default(),
+            tenants: Default::default(),
+            origin,
+            plugins,
+        });
+
+        let (metrics_tx, _) = broadcast::channel::<MetricsSnapshot>(64);
+        let metrics_state = MetricsState::new(Arc::clone(&state), metrics_tx);
+
+        let listener_configs = config.effective_listeners();
+
+        tracing::info!(
+            version = build_info::VERSION,
+            git_hash = build_info::GIT_HASH,
+            listeners = listener_configs.len(),
+            upstreams = upstreams.status().len(),
+            upstream_timeout_ms = config.upstream.timeout_ms,
+            policy = %config.cache.eviction_policy,
+            comparison = ?config.cache.comparison_policy,
+            capacity = config.cache.capacity,
+            "colander proxy starting"
+        );
+
+        // Bind every listener before serving any, so a bad address fails fast
+        let mut bound = Vec::with_capacity(listener_configs.len());
+        for listener in listener_configs {
+            bound.push(listeners::bind(listener).await.map_err(StartError)?);
+        }
+        export::spawn_metrics_export(&config.metrics_export, &metrics_state.tx, shutdown.clone())
+            .map_err(StartError)?;
+        tokio::spawn(metrics_broadcaster(
+            metrics_state.clone(),
+            started_at,
+            shutdown.clone(),
+        ));
+
+        // Hot reload swaps certificates under running TLS listeners
+        let mut tls = TlsReloader::default();
+        for listener in &bound {
+            if let Some(reloadable) = listener.tls() {
+                tls.register(listener.addr(), reloadable.clone());
+            }
+        }
+        let addrs = bound
+            .iter()
+            .map(|l| (l.roles().to_vec(), l.local_addr()))
+            .collect();
+
+        let ctx = ListenerContext {
+            state: Arc::clone(&state),
+            metrics: metrics_state.clone(),
+            prom_handle,
+            shutdown: shutdown.clone(),
+        };
+
+        let mut tasks = JoinSet::new();
+        for listener in bound {
+            listeners::spawn(listener, &ctx, &mut tasks);
+        }
+
+        if config.upstream.health_check.enabled {
+            tokio::spawn(upstream::run_health_checks(
+                Arc::clone(&upstreams),
+                state.client.clone(),
+                config.upstream.health_check.clone(),
+                shutdown.clone(),
+            ));
+        }
+
+        tokio::spawn(upstream::run_outlier_detection(
+            Arc::clone(&upstreams),
+            shutdown.clone(),
+        ));
+
+        tokio::spawn(invalidation::run(
+            Arc::clone(&state),
+            invalidation_rx,
+            shutdown.clone(),
+        ));
+
+        #[cfg(feature = "soak")]
+        tokio::spawn(crate::soak::run_soak_checks(
+            Arc::clone(&state),
+            config.soak.clone(),
+            shutdown.clone(),
+        ));
+        #[cfg(not(feature = "soak"))]
+        if config.soak != config::SoakConfig::default() {
+            tracing::warn!("[soak] is set but this build lacks the soak feature; ignoring it");
+        }
+
+        // Listeners are already serving; /readyz flips once the cache is warm
+        tokio::spawn(warmup::warm_then_ready(
+            Arc::clone(&state),
+            config.warmup.clone(),
+        ));
+
+        Ok(Self {
+            handle: ServerHandle {
+                shared: Arc::new(Shared {
+                    state,
+                    metrics: metrics_state,
+                    addrs,
+                    tls,
+                }),
+            },
+            tasks,
+        })
+    }
+
+    pub fn handle(&self) -> &ServerHandle {
+        &self.handle
+    }
+
+    /// Wait until every listener has shut down (see `ServerHandle::shutdown`
+    /// and `ServerHandle::drain`). Spawn this to run the server in the
+    /// background.
+    pub async fn join(mut self) {
+        while let Some(result) = self.tasks.join_next().await {
+            if let Err(e) = result {
+                tracing::error!(error = %e, "listener task failed");
+            }
+        }
+        tracing::info!("colander proxy shut down");
+    }
+}
+
+impl ServerHandle {
+    /// Shared state behind every handler.
+    pub fn state(&self) -> &Arc<AppState> {
+        &self.shared.state
+    }
+
+    /// The cache layer currently in use. Policy changes swap in a new one,
+    /// so fetch it again rather than holding on to it.
+    pub fn cache(&self) -> Arc<CacheLayer> {
+        self.shared.state.cache.load_full()
+    }
+
+    pub fn metrics(&self) -> &MetricsState {
+        &self.shared.metrics
+    }
+
+    /// Every listener's roles and the address it actually bound (useful
+    /// with port 0).
+    pub fn addrs(&self) -> &[(Vec<ListenerRole>, SocketAddr)] {
+        &self.shared.addrs
+    }
+
+    /// Address of the first listener serving `role`.
+    pub fn addr(&self, role: ListenerRole) -> Option<SocketAddr> {
+        self.shared
+            .addrs
+            .iter()
+            .find(|(roles, _)| roles.contains(&role))
+            .map(|&(_, addr)| addr)
+    }
+
+    /// Apply a new config the way a `config.toml` edit would: safe changes
+    /// take effect, restart-only ones and those a read-only cache refuses
+    /// are logged and skipped. A config that fails `Config::validate` is
+    /// refused outright.
+    pub fn reload(&self, new_config: Config) -> Result<(), ConfigErrors> {
+        new_config.validate()?;
+        let state = &self.shared.state;
+        let _guard = state.config_lock.lock();
+        let old = state.config.load();
+        let applied = config::diff_and_apply(
+            &old,
+            &new_config,
+            &state.cache,
+            &state.upstreams,
+            &self.shared.tls,
+        );
+        state.config.store(Arc::new(applied));
+        Ok(())
+    }
+
+    /// Start a graceful drain in the background (see `drain`). Returns
+    /// false if one is already under way.
+    pub fn drain(&self, options: DrainOptions) -> bool {
+        let state = &self.shared.state;
+        if !drain::begin(state) {
+            return false;
+        }
+        tokio::spawn(drain::drain(Arc::clone(state), options));
+        true
+    }
+
+    /// Stop accepting connections right away; listeners finish their
+    /// in-flight requests and `Server::join` returns.
+    pub fn shutdown(&self) {
+        self.shared.state.shutdown.cancel();
+    }
+
+    /// The token that closes every listener when cancelled.
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shared.state.shutdown.clone()
+    }
+}
+
+/// The process-wide Prometheus recorder, installed by the first server.
+fn prometheus_handle() -> PrometheusHandle {
+    static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+    HANDLE
+        .get_or_init(|| {
+            let recorder = PrometheusBuilder::new()
+                .set_quantiles(&[0.5, 0.9, 0.99])
+                .expect("valid quantiles")
+                .build_recorder();
+            let handle = recorder.handle();
+            if ::metrics::set_global_recorder(recorder).is_err() {
+                tracing::warn!("another metrics recorder is installed; /metrics will be empty");
+            }
+            handle
+        })
+        .clone()
+}
@@ -0,0 +1,73 @@
+use crate::proxy::AppState;
+use axum::extract::{Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Counts proxy requests in flight and turns away the excess.
+///
+/// Shedding happens before the cache lookup, so an overloaded instance spends
+/// almost nothing on requests it can't serve well and accepted requests keep
+/// their latency.
+#[derive(Debug, Default)]
+pub struct LoadShedder {
+    in_flight: AtomicUsize,
+    shed: AtomicU64,
+}
+
+/// Holds one in-flight slot; released on drop, including when the client
+/// disconnects mid-request.
+pub struct InFlight<'a>(&'a LoadShedder);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl LoadShedder {
+    /// Take a slot, or `None` if `limit` are already taken. A `limit` of 0
+    /// means unlimited; requests are still counted.
+    pub fn try_acquire(&self, limit: usize) -> Option<InFlight<'_>> {
+        let previous = self.in_flight.fetch_add(1, Ordering::Relaxed);
+        let slot = InFlight(self);
+        if limit > 0 && previous >= limit {
+            self.shed.fetch_add(1, Ordering::Relaxed);
+            return None; // dropping `slot` gives it back
+        }
+        Some(slot)
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// Requests rejected since startup.
+    pub fn shed_count(&self) -> u64 {
+        self.shed.load(Ordering::Relaxed)
+    }
+}
+
+/// Middleware for the proxy role: answer `503` with `Retry-After` when
+/// `[load_shedding] max_in_flight` requests are already being served.
+pub async fn shed_load(State(state): State<Arc<AppState>>, req: Request, next: Next) -> Response {
+    let config = state.config.load();
+    let limits = &config.load_shedding;
+    let Some(_slot) = state.shedder.try_acquire(limits.max_in_flight) else {
+        ::metrics::counter!("colander_requests_shed_total").increment(1);
+        tracing::debug!(
+            max_in_flight = limits.max_in_flight,
+            "overloaded, request shed"
+        );
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(header::RETRY_AFTER, limits.retry_after_secs.to_string())],
+            "Service Unavailable",
+        )
+            .into_response();
+    };
+    drop(config);
+    next.run(req).await
+}
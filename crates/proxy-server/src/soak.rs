@@ -0,0 +1,105 @@
+//! Soak mode (`--features soak`): periodically re-verify cache invariants on
+//! the live process so slow arena/hand corruption surfaces as a counter and a
+//! log line instead of a wrong response days later.
+
+use crate::cache_layer::CacheLayer;
+use crate::config::SoakConfig;
+use crate::proxy::AppState;
+use colander_cache::traits::CacheStats;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+/// Stats from the previous pass, tied to the layer they came from: a policy
+/// swap replaces the layer and `reset_stats` zeroes it, and either one
+/// legitimately starts the counters over.
+struct Previous {
+    layer: Arc<CacheLayer>,
+    /// `stats_generation` while these were read, or `None` if a reset
+    /// raced the read.
+    generation: Option<u64>,
+    primary: CacheStats,
+    comparisons: Vec<(&'static str, CacheStats)>,
+}
+
+/// Check invariants every `interval_ms` until shutdown.
+///
+/// Every pass verifies, for the primary and comparison caches:
+/// - structure: each shard's index matches its arena, lists are well linked,
+///   occupancy is within capacity, the SIEVE hand points at a live node
+/// - TTL math: no entry claims to have been inserted in the future
+/// - stats: hits, misses and evictions never go backwards, except across a
+///   policy swap or a stats reset, which start a new baseline
+///
+/// Violations are logged and counted in `colander_soak_violations_total`;
+/// nothing is repaired, so the evidence stays in place for a dump.
+pub async fn run_soak_checks(
+    state: Arc<AppState>,
+    config: SoakConfig,
+    shutdown: CancellationToken,
+) {
+    let interval = Duration::from_millis(config.interval_ms.max(100));
+    let mut previous: Option<Previous> = None;
+    tracing::info!(
+        interval_ms = interval.as_millis() as u64,
+        "soak checks started"
+    );
+
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = shutdown.cancelled() => return,
+        }
+
+        let layer = state.cache.load_full();
+        let checked = Arc::clone(&layer);
+        let structural = tokio::task::spawn_blocking(move || checked.check_invariants())
+            .await
+            .unwrap_or_else(|e| vec![format!("check panicked: {e}")]);
+        for violation in structural {
+            report("structure", &violation);
+        }
+
+        let generation = layer.stats_generation();
+        let primary = layer.primary_stats();
+        let comparisons = layer.comparison_stats();
+        let current = Previous {
+            generation: (layer.stats_generation() == generation).then_some(generation),
+            primary,
+            comparisons,
+            layer,
+        };
+        if let Some(prev) = previous.as_ref().filter(|p| {
+            Arc::ptr_eq(&p.layer, &current.layer)
+                && p.generation.is_some()
+                && p.generation == current.generation
+        }) {
+            check_monotonic("primary", &prev.primary, &current.primary);
+            for ((name, before), (_, after)) in prev.comparisons.iter().zip(&current.comparisons) {
+                check_monotonic(&format!("comparison {name}"), before, after);
+            }
+        }
+        previous = Some(current);
+    }
+}
+
+fn check_monotonic(cache: &str, before: &CacheStats, after: &CacheStats) {
+    let counters = [
+        ("hits", before.hits, after.hits),
+        ("misses", before.misses, after.misses),
+        ("evictions", before.evictions, after.evictions),
+    ];
+    for (name, before, after) in counters {
+        if after < before {
+            report(
+                "stats_monotonic",
+                &format!("{cache} {name} went from {before} to {after}"),
+            );
+        }
+    }
+}
+
+fn report(check: &'static str, detail: &str) {
+    tracing::error!(check, detail, "soak invariant violated");
+    ::metrics::counter!("colander_soak_violations_total", "check" => check).increment(1);
+}
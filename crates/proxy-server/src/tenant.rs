@@ -0,0 +1,137 @@
+use crate::config::TenantConfig;
+use axum::http::HeaderMap;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Longest tenant name taken from a request; longer ones count as absent.
+const MAX_TENANT_LEN: usize = 128;
+
+/// Label of the Prometheus series counting tenants past `max_tracked`.
+const UNTRACKED_LABEL: &str = "_untracked";
+
+/// The tenant a request names in `[cache.tenant] header`, if tenants are on
+/// and the value is usable. `#` is refused so a tenant can't pose as a
+/// `#user=` key suffix.
+pub fn tenant<'a>(config: &TenantConfig, headers: &'a HeaderMap) -> Option<&'a str> {
+    if config.header.is_empty() {
+        return None;
+    }
+    headers
+        .get(config.header.as_str())
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|t| !t.is_empty() && t.len() <= MAX_TENANT_LEN && !t.contains('#'))
+}
+
+/// Cache hits and misses of each tenant. The first `max_tracked` tenants
+/// seen get counters of their own; later ones share one, so a client
+/// inventing tenant names can't grow this (or `/metrics`) without bound.
+#[derive(Default)]
+pub struct TenantStats {
+    tenants: RwLock<HashMap<String, Counts>>,
+    untracked: Counts,
+}
+
+#[derive(Default)]
+struct Counts {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Counts {
+    fn record(&self, hit: bool) {
+        let counter = if hit { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> TenantHits {
+        TenantHits::new(
+            self.hits.load(Ordering::Relaxed),
+            self.misses.load(Ordering::Relaxed),
+        )
+    }
+}
+
+impl TenantStats {
+    /// Count a lookup by `tenant`.
+    pub fn record(&self, tenant: &str, hit: bool, max_tracked: usize) {
+        if let Some(counts) = self.tenants.read().get(tenant) {
+            counts.record(hit);
+            return;
+        }
+        let mut tenants = self.tenants.write();
+        if tenants.len() < max_tracked || tenants.contains_key(tenant) {
+            tenants.entry(tenant.to_string()).or_default().record(hit);
+        } else {
+            self.untracked.record(hit);
+        }
+    }
+
+    pub fn report(&self) -> TenantReport {
+        TenantReport {
+            tenants: self
+                .tenants
+                .read()
+                .iter()
+                .map(|(tenant, counts)| (tenant.clone(), counts.snapshot()))
+                .collect(),
+            untracked: self.untracked.snapshot(),
+        }
+    }
+
+    /// Forget every tenant, as `POST /api/stats/reset` does cache stats.
+    pub fn reset(&self) {
+        self.tenants.write().clear();
+        self.untracked.hits.store(0, Ordering::Relaxed);
+        self.untracked.misses.store(0, Ordering::Relaxed);
+    }
+
+    /// Publish the counts as `colander_tenant_hits_total` and
+    /// `colander_tenant_misses_total`.
+    pub fn export(&self) {
+        let report = self.report();
+        let series = report
+            .tenants
+            .iter()
+            .map(|(tenant, hits)| (tenant.as_str(), hits))
+            .chain([(UNTRACKED_LABEL, &report.untracked)]);
+        for (tenant, hits) in series {
+            ::metrics::counter!("colander_tenant_hits_total", "tenant" => tenant.to_string())
+                .absolute(hits.hits);
+            ::metrics::counter!("colander_tenant_misses_total", "tenant" => tenant.to_string())
+                .absolute(hits.misses);
+        }
+    }
+}
+
+/// Per-tenant cache hits for `/api/stats`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TenantReport {
+    pub tenants: BTreeMap<String, TenantHits>,
+    /// Tenants seen after `max_tracked` others, together.
+    pub untracked: TenantHits,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TenantHits {
+    pub hits: u64,
+    pub misses: u64,
+    pub hit_rate: f64,
+}
+
+impl TenantHits {
+    fn new(hits: u64, misses: u64) -> Self {
+        let total = hits + misses;
+        Self {
+            hits,
+            misses,
+            hit_rate: if total == 0 {
+                0.0
+            } else {
+                hits as f64 / total as f64
+            },
+        }
+    }
+}
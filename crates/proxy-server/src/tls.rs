@@ -0,0 +1,223 @@
+use crate::config::{ListenerConfig, TlsConfig};
+use arc_swap::ArcSwap;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+
+/// Handshakes that take longer than this are dropped so a slow client can't
+/// pin a task forever.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// ALPN ids for an HTTP listener that speaks HTTP/2 and HTTP/1.1.
+pub const ALPN_H2_HTTP1: &[&[u8]] = &[b"h2", b"http/1.1"];
+/// ALPN ids for an HTTP/1.1-only listener.
+pub const ALPN_HTTP1: &[&[u8]] = &[b"http/1.1"];
+
+/// Build a rustls server config from a PEM certificate chain and private
+/// key, offering `alpn` protocols in preference order (none for wire
+/// protocols). With `client_ca_path`, clients must present a certificate
+/// signed by one of those CAs.
+pub fn load_server_config(
+    config: &TlsConfig,
+    alpn: &[&[u8]],
+) -> Result<ServerConfig, Box<dyn std::error::Error>> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(&config.cert_path)?))
+        .collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        return Err(format!("no certificates found in {}", config.cert_path).into());
+    }
+
+    let key = rustls_pemfile::private_key(&mut BufReader::new(File::open(&config.key_path)?))?
+        .ok_or_else(|| format!("no private key found in {}", config.key_path))?;
+
+    // Pin the provider explicitly: other crates in the dependency graph may
+    // enable a second rustls backend, which breaks automatic selection.
+    let provider = Arc::new(tokio_rustls::rustls::crypto::ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()?;
+    let builder = match &config.client_ca_path {
+        Some(ca_path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in rustls_pemfile::certs(&mut BufReader::new(File::open(ca_path)?)) {
+                roots.add(cert?)?;
+            }
+            if roots.is_empty() {
+                return Err(format!("no CA certificates found in {ca_path}").into());
+            }
+            let verifier =
+                WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider).build()?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let mut server_config = builder.with_single_cert(certs, key)?;
+    server_config.alpn_protocols = alpn.iter().map(|p| p.to_vec()).collect();
+
+    Ok(server_config)
+}
+
+/// A listener's TLS settings, swappable while it keeps accepting.
+///
+/// Each handshake uses whatever config is current when it starts, so a
+/// reload affects new connections only; established ones keep theirs.
+#[derive(Clone)]
+pub struct ReloadableTls {
+    current: Arc<ArcSwap<ServerConfig>>,
+    alpn: &'static [&'static [u8]],
+}
+
+impl ReloadableTls {
+    pub fn load(
+        config: &TlsConfig,
+        alpn: &'static [&'static [u8]],
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            current: Arc::new(ArcSwap::from_pointee(load_server_config(config, alpn)?)),
+            alpn,
+        })
+    }
+
+    /// Re-read the certificate, key and client CA files. On error the
+    /// current config stays in place.
+    pub fn reload(&self, config: &TlsConfig) -> Result<(), Box<dyn std::error::Error>> {
+        self.current
+            .store(Arc::new(load_server_config(config, self.alpn)?));
+        Ok(())
+    }
+
+    /// An acceptor for the config in effect right now.
+    pub fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(self.current.load_full())
+    }
+}
+
+/// Every TLS listener's reload handle, keyed by listener address.
+#[derive(Clone, Default)]
+pub struct TlsReloader {
+    listeners: Vec<(String, ReloadableTls)>,
+}
+
+impl TlsReloader {
+    pub fn register(&mut self, addr: &str, tls: ReloadableTls) {
+        self.listeners.push((addr.to_string(), tls));
+    }
+
+    /// Re-read TLS files for every registered listener that still has TLS
+    /// configured in `listeners`, even when the paths are unchanged, so certs
+    /// rotated in place are picked up. Failures keep the old certificate.
+    pub fn reload(&self, listeners: &[ListenerConfig]) {
+        for (addr, tls) in &self.listeners {
+            let Some(config) = listeners
+                .iter()
+                .find(|l| &l.addr == addr)
+                .and_then(|l| l.tls.as_ref())
+            else {
+                continue;
+            };
+            match tls.reload(config) {
+                Ok(()) => {
+                    tracing::info!(addr = %addr, cert = %config.cert_path, "TLS certificate reloaded")
+                }
+                Err(e) => {
+                    tracing::error!(error = %e, addr = %addr, cert = %config.cert_path, key = %config.key_path, "TLS reload failed, keeping the current certificate")
+                }
+            }
+        }
+    }
+}
+
+/// Whether the client picked HTTP/2 during the handshake.
+pub fn negotiated_h2(stream: &TlsStream<TcpStream>) -> bool {
+    stream.get_ref().1.alpn_protocol() == Some(b"h2")
+}
+
+/// Perform a server-side TLS handshake with a bounded timeout.
+pub async fn handshake(
+    acceptor: &TlsAcceptor,
+    stream: TcpStream,
+) -> io::Result<TlsStream<TcpStream>> {
+    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+        Ok(result) => result,
+        Err(_) => Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "TLS handshake timed out",
+        )),
+    }
+}
+
+/// A TLS-terminating listener usable with `axum::serve`.
+///
+/// Handshakes run on their own tasks and completed streams are handed to
+/// `accept()` through a channel, so one stalled handshake never blocks the
+/// accept loop for other clients.
+pub struct TlsListener {
+    rx: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+    local_addr: SocketAddr,
+}
+
+impl TlsListener {
+    pub fn new(listener: TcpListener, tls: ReloadableTls) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let (tx, rx) = mpsc::channel(64);
+        tokio::spawn(accept_loop(listener, tls, tx));
+        Ok(Self { rx, local_addr })
+    }
+}
+
+async fn accept_loop(
+    listener: TcpListener,
+    tls: ReloadableTls,
+    tx: mpsc::Sender<(TlsStream<TcpStream>, SocketAddr)>,
+) {
+    loop {
+        let (stream, peer) = tokio::select! {
+            _ = tx.closed() => return,
+            result = listener.accept() => match result {
+                Ok(conn) => conn,
+                Err(e) => {
+                    tracing::error!(error = %e, "TLS accept error");
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    continue;
+                }
+            },
+        };
+
+        let acceptor = tls.acceptor();
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            match handshake(&acceptor, stream).await {
+                Ok(tls) => {
+                    let _ = tx.send((tls, peer)).await;
+                }
+                Err(e) => {
+                    tracing::debug!(error = %e, peer = %peer, "TLS handshake failed");
+                }
+            }
+        });
+    }
+}
+
+impl axum::serve::Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.rx.recv().await {
+            Some(conn) => conn,
+            // The accept loop only exits once we're dropped, so this never resolves
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
@@ -0,0 +1,228 @@
+use crate::forwarded;
+use crate::hop_by_hop;
+use crate::proxy::{append_cache_headers, circuit_open_response, AppState};
+use axum::body::Body;
+use axum::http::header::{ACCEPT, CONNECTION, HOST, UPGRADE};
+use axum::http::{HeaderMap, HeaderValue, Request, Response, StatusCode};
+use futures_util::StreamExt;
+use http_body_util::BodyExt;
+use hyper::upgrade::OnUpgrade;
+use hyper_util::rt::TokioIo;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// A request whose response can't be buffered, so it bypasses the cache and
+/// is relayed to upstream as it happens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Passthrough {
+    /// `Upgrade: websocket`; after upstream's `101` the two connections are
+    /// spliced together.
+    WebSocket,
+    /// `Accept: text/event-stream`; the response body is streamed.
+    EventStream,
+}
+
+impl Passthrough {
+    fn label(self) -> &'static str {
+        match self {
+            Passthrough::WebSocket => "websocket",
+            Passthrough::EventStream => "sse",
+        }
+    }
+}
+
+/// Detect requests that must not go through the collect-and-cache path.
+pub fn classify(headers: &HeaderMap) -> Option<Passthrough> {
+    let has_token = |name, token: &str| {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .any(|t| {
+                let t = t.trim();
+                // Accept values may carry parameters (`;q=0.9`)
+                let t = t.split(';').next().unwrap_or(t).trim();
+                t.eq_ignore_ascii_case(token)
+            })
+    };
+    if has_token(UPGRADE, "websocket") && has_token(CONNECTION, "upgrade") {
+        Some(Passthrough::WebSocket)
+    } else if has_token(ACCEPT, "text/event-stream") {
+        Some(Passthrough::EventStream)
+    } else {
+        None
+    }
+}
+
+/// Relay `req` to upstream without buffering or caching: a WebSocket
+/// handshake becomes a bidirectional tunnel, an event stream is streamed
+/// back as it arrives. Both end when the proxy shuts down.
+pub async fn forward(
+    state: &Arc<AppState>,
+    mut req: Request<Body>,
+    kind: Passthrough,
+) -> Response<Body> {
+    ::metrics::counter!("colander_passthrough_total", "kind" => kind.label()).increment(1);
+
+    if let Some(circuit) = state.upstreams.circuit().filter(|c| !c.allow()) {
+        ::metrics::counter!("colander_upstream_circuit_rejected_total").increment(1);
+        return circuit_open_response(circuit);
+    }
+
+    // Upgrades need the raw client connection, which HTTP/2 requests lack
+    let client_upgrade = match kind {
+        Passthrough::WebSocket => match req.extensions_mut().remove::<OnUpgrade>() {
+            Some(upgrade) => Some(upgrade),
+            None => {
+                return Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(Body::from("WebSocket upgrade requires HTTP/1.1"))
+                    .unwrap();
+            }
+        },
+        Passthrough::EventStream => None,
+    };
+
+    let (parts, body) = req.into_parts();
+    let backend = state.upstreams.select();
+    let upstream_uri = format!(
+        "{}{}",
+        backend.url,
+        parts
+            .uri
+            .path_and_query()
+            .map(|pq| pq.as_str())
+            .unwrap_or("/")
+    );
+
+    // Forward the client's end-to-end headers (the handshake needs its
+    // `Sec-WebSocket-*` ones), except Host, which is `[upstream]
+    // host_header`'s to decide. The upgrade itself is the one hop-by-hop
+    // request passed on.
+    let config = state.config.load();
+    let mut headers = parts.headers.clone();
+    hop_by_hop::strip(&mut headers);
+    headers.remove(HOST);
+    if kind == Passthrough::WebSocket {
+        headers.insert(CONNECTION, HeaderValue::from_static("upgrade"));
+        headers.insert(UPGRADE, HeaderValue::from_static("websocket"));
+    }
+    if let Some(host) = backend.host_header(config.upstream.host_header, &parts) {
+        headers.insert(HOST, host);
+    }
+    forwarded::set_headers(&config.upstream.forwarded, &parts, &mut headers);
+    let mut upstream_req = Request::builder().method(&parts.method).uri(&upstream_uri);
+    if let Some(out) = upstream_req.headers_mut() {
+        *out = headers;
+    }
+    let upstream_req = match upstream_req.body(body) {
+        Ok(r) => r,
+        Err(e) => {
+            tracing::error!(error = %e, "failed to build upstream request");
+            return Response::builder()
+                .status(StatusCode::BAD_GATEWAY)
+                .body(Body::from("Bad Gateway"))
+                .unwrap();
+        }
+    };
+
+    // The timeout covers the response head only; streams may run for hours
+    let upstream_start = Instant::now();
+    let request = state.client.request(upstream_req);
+    let result = match state.upstreams.request_timeout() {
+        Some(limit) => match tokio::time::timeout(limit, request).await {
+            Ok(result) => result,
+            Err(_) => {
+                tracing::warn!(upstream = %upstream_uri, "upstream passthrough timed out");
+                ::metrics::counter!("colander_upstream_timeouts_total").increment(1);
+                state
+                    .upstreams
+                    .record(&backend, upstream_start.elapsed(), false);
+                return Response::builder()
+                    .status(StatusCode::GATEWAY_TIMEOUT)
+                    .body(Body::from("Gateway Timeout"))
+                    .unwrap();
+            }
+        },
+        None => request.await,
+    };
+    let elapsed = upstream_start.elapsed();
+    let mut upstream_resp = match result {
+        Ok(resp) => resp,
+        Err(e) => {
+            tracing::error!(error = %e, upstream = %upstream_uri, "upstream passthrough failed");
+            state.upstreams.record(&backend, elapsed, false);
+            return Response::builder()
+                .status(StatusCode::BAD_GATEWAY)
+                .body(Body::from("Bad Gateway"))
+                .unwrap();
+        }
+    };
+    let status = upstream_resp.status();
+    state
+        .upstreams
+        .record(&backend, elapsed, !status.is_server_error());
+
+    let mut response = Response::builder().status(status);
+    let headers = response.headers_mut().unwrap();
+    *headers = upstream_resp.headers().clone();
+    hop_by_hop::strip(headers);
+    // A `101` has to say what it switched to
+    if status == StatusCode::SWITCHING_PROTOCOLS {
+        if let Some(upgrade) = upstream_resp.headers().get(UPGRADE) {
+            headers.insert(CONNECTION, HeaderValue::from_static("upgrade"));
+            headers.insert(UPGRADE, upgrade.clone());
+        }
+    }
+    append_cache_headers(headers, "BYPASS", &state.cache.load());
+
+    if let Some(client_upgrade) = client_upgrade {
+        if status == StatusCode::SWITCHING_PROTOCOLS {
+            let upstream_upgrade = hyper::upgrade::on(&mut upstream_resp);
+            tokio::spawn(splice(
+                client_upgrade,
+                upstream_upgrade,
+                state.shutdown.clone(),
+            ));
+            return response.body(Body::empty()).unwrap();
+        }
+        // Upstream refused the upgrade; pass its answer on
+    }
+
+    let shutdown = state.shutdown.clone().cancelled_owned();
+    let stream = upstream_resp
+        .into_body()
+        .into_data_stream()
+        .take_until(shutdown);
+    response.body(Body::from_stream(stream)).unwrap()
+}
+
+/// Copy bytes both ways between the upgraded client and upstream
+/// connections until either side closes or the proxy shuts down.
+async fn splice(
+    client: OnUpgrade,
+    upstream: OnUpgrade,
+    shutdown: tokio_util::sync::CancellationToken,
+) {
+    let (client, upstream) = match tokio::try_join!(client, upstream) {
+        Ok(pair) => pair,
+        Err(e) => {
+            tracing::debug!(error = %e, "websocket upgrade failed");
+            return;
+        }
+    };
+    let mut client = TokioIo::new(client);
+    let mut upstream = TokioIo::new(upstream);
+
+    ::metrics::gauge!("colander_websocket_tunnels_active").increment(1.0);
+    tokio::select! {
+        result = tokio::io::copy_bidirectional(&mut client, &mut upstream) => {
+            if let Err(e) = result {
+                tracing::debug!(error = %e, "websocket tunnel closed with error");
+            }
+        }
+        _ = shutdown.cancelled() => {}
+    }
+    ::metrics::gauge!("colander_websocket_tunnels_active").decrement(1.0);
+}
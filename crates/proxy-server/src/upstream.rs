@@ -0,0 +1,643 @@
+use crate::circuit::CircuitBreaker;
+use crate::config::{
+    BackendConfig, CircuitBreakerConfig, HealthCheckConfig, HostHeader, OutlierDetectionConfig,
+    UpstreamConfig, UpstreamHttp2,
+};
+use crate::forwarded;
+use crate::proxy::HttpClient;
+use arc_swap::ArcSwap;
+use axum::body::Body;
+use axum::http::header::HOST;
+use axum::http::request::Parts;
+use axum::http::{HeaderValue, Request};
+use hyper_rustls::HttpsConnectorBuilder;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio_util::sync::CancellationToken;
+
+/// Build the client used for every upstream request.
+///
+/// `https://` backends are verified against the system's root certificates
+/// and, with `http2 = "auto"`, pick HTTP/2 or HTTP/1.1 by ALPN. `http://`
+/// backends get HTTP/1.1 unless `http2 = "always"` forces h2c. Connecting
+/// gives up after `timeout_ms`.
+pub fn build_client(config: &UpstreamConfig) -> HttpClient {
+    // Same reasoning as `tls::load_acceptor`: pin the provider explicitly
+    let provider = Arc::new(tokio_rustls::rustls::crypto::ring::default_provider());
+    let tls = match HttpsConnectorBuilder::new().with_provider_and_native_roots(provider.clone()) {
+        Ok(builder) => builder,
+        Err(e) => {
+            tracing::warn!(error = %e, "no system root certificates; https upstreams will fail verification");
+            let tls_config = tokio_rustls::rustls::ClientConfig::builder_with_provider(provider)
+                .with_safe_default_protocol_versions()
+                .expect("ring supports the default TLS versions")
+                .with_root_certificates(tokio_rustls::rustls::RootCertStore::empty())
+                .with_no_client_auth();
+            HttpsConnectorBuilder::new().with_tls_config(tls_config)
+        }
+    }
+    .https_or_http();
+
+    let mut http = HttpConnector::new();
+    // HttpsConnector enforces the scheme itself
+    http.enforce_http(false);
+    if config.timeout_ms > 0 {
+        http.set_connect_timeout(Some(Duration::from_millis(config.timeout_ms)));
+    }
+    let connector = match config.http2 {
+        UpstreamHttp2::Auto => tls.enable_all_versions().wrap_connector(http),
+        UpstreamHttp2::Always => tls.enable_http2().wrap_connector(http),
+        UpstreamHttp2::Never => tls.enable_http1().wrap_connector(http),
+    };
+    Client::builder(TokioExecutor::new())
+        .http2_only(config.http2 == UpstreamHttp2::Always)
+        .build(connector)
+}
+
+/// Weight of the newest sample in the latency EWMA.
+const EWMA_ALPHA: f64 = 0.3;
+
+/// One origin server.
+pub struct Backend {
+    pub url: String,
+    pub tier: u32,
+    /// Fixed `Host` for every request to this backend.
+    pub host: Option<HeaderValue>,
+    healthy: AtomicBool,
+    consecutive_failures: AtomicU32,
+    consecutive_successes: AtomicU32,
+    /// Set while outlier detection has this backend out of rotation.
+    ejected: AtomicBool,
+    traffic: Mutex<TrafficStats>,
+}
+
+/// Live-traffic observations used by outlier detection.
+#[derive(Default)]
+struct TrafficStats {
+    ewma_latency_us: f64,
+    /// Requests and errors since the last analysis pass.
+    requests: u64,
+    errors: u64,
+    consecutive_errors: u32,
+    ejected_until: Option<Instant>,
+    ejections: u32,
+}
+
+impl Backend {
+    fn new(config: &BackendConfig) -> Self {
+        Self {
+            url: config.url.trim_end_matches('/').to_string(),
+            tier: config.tier,
+            host: config
+                .host
+                .as_deref()
+                .and_then(|h| HeaderValue::from_str(h).ok()),
+            // Optimistic until the first check says otherwise
+            healthy: AtomicBool::new(true),
+            consecutive_failures: AtomicU32::new(0),
+            consecutive_successes: AtomicU32::new(0),
+            ejected: AtomicBool::new(false),
+            traffic: Mutex::new(TrafficStats::default()),
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    pub fn is_ejected(&self) -> bool {
+        self.ejected.load(Ordering::Relaxed)
+    }
+
+    /// `Host` to send this backend for `client`'s request, or `None` to let
+    /// the HTTP client take it from the backend URL.
+    pub fn host_header(&self, mode: HostHeader, client: &Parts) -> Option<HeaderValue> {
+        if let Some(host) = &self.host {
+            return Some(host.clone());
+        }
+        match mode {
+            HostHeader::Upstream => None,
+            HostHeader::Preserve => {
+                forwarded::client_host(client).and_then(|host| HeaderValue::from_str(host).ok())
+            }
+        }
+    }
+}
+
+/// Point-in-time view of one backend for `/api/upstreams`.
+#[derive(Debug, Clone, Serialize)]
+pub struct BackendStatus {
+    pub url: String,
+    pub tier: u32,
+    pub healthy: bool,
+    pub ejected: bool,
+    pub ejections: u32,
+    pub latency_ewma_us: u64,
+}
+
+/// Upstream servers grouped into priority tiers.
+///
+/// Requests go to the lowest-numbered tier that has at least one healthy
+/// backend, round-robin within that tier. Backups (higher tiers) only see
+/// traffic while every backend in the tiers above them is down, and traffic
+/// fails back as soon as health checks bring a better tier back. If nothing is
+/// healthy we fail open to the top tier rather than refuse every request.
+///
+/// With outlier detection on, backends that are healthy but misbehaving
+/// (error bursts, latency far above their tier peers) are ejected from the
+/// round-robin for a while. Ejection never moves traffic to another tier.
+///
+/// A circuit breaker, if configured, sits in front of the whole pool (see
+/// `circuit`).
+///
+/// The backend list and request timeout can be swapped while serving (see
+/// `set_backends`); requests already under way finish on the backend they
+/// picked.
+///
+/// An optional concurrency limit caps how many requests are in flight to
+/// upstream at once; the rest wait their turn (see `acquire`).
+pub struct UpstreamPool {
+    backends: ArcSwap<Vec<Arc<Backend>>>,
+    active_tier: AtomicU32,
+    next: AtomicUsize,
+    outlier: Option<OutlierDetectionConfig>,
+    circuit: Option<CircuitBreaker>,
+    /// Milliseconds, 0 for none.
+    request_timeout_ms: AtomicU64,
+    limit: Option<ConcurrencyLimit>,
+}
+
+struct ConcurrencyLimit {
+    permits: Semaphore,
+    max: usize,
+    queue_timeout: Duration,
+    /// Requests currently waiting for a permit.
+    queued: AtomicUsize,
+}
+
+impl ConcurrencyLimit {
+    fn publish(&self) {
+        let in_flight = self.max - self.permits.available_permits();
+        ::metrics::gauge!("colander_upstream_in_flight").set(in_flight as f64);
+        ::metrics::gauge!("colander_upstream_queued")
+            .set(self.queued.load(Ordering::Relaxed) as f64);
+    }
+}
+
+/// Permission to send one upstream attempt, from `UpstreamPool::acquire`.
+/// Hold it until the response has been read; dropping it frees the slot.
+pub struct UpstreamSlot<'a> {
+    permit: Option<(SemaphorePermit<'a>, &'a ConcurrencyLimit)>,
+}
+
+impl Drop for UpstreamSlot<'_> {
+    fn drop(&mut self) {
+        if let Some((permit, limit)) = self.permit.take() {
+            drop(permit);
+            limit.publish();
+        }
+    }
+}
+
+/// Counts a request as queued for as long as it waits on the semaphore,
+/// including when the wait is cancelled.
+struct Queued<'a>(&'a ConcurrencyLimit);
+
+impl<'a> Queued<'a> {
+    fn enter(limit: &'a ConcurrencyLimit) -> Self {
+        limit.queued.fetch_add(1, Ordering::Relaxed);
+        limit.publish();
+        Self(limit)
+    }
+}
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        self.0.queued.fetch_sub(1, Ordering::Relaxed);
+        self.0.publish();
+    }
+}
+
+impl UpstreamPool {
+    pub fn new(configs: &[BackendConfig]) -> Self {
+        assert!(!configs.is_empty(), "at least one upstream is required");
+        let mut backends: Vec<Arc<Backend>> =
+            configs.iter().map(|c| Arc::new(Backend::new(c))).collect();
+        backends.sort_by_key(|b| b.tier);
+        let top = backends[0].tier;
+        Self {
+            backends: ArcSwap::from_pointee(backends),
+            active_tier: AtomicU32::new(top),
+            next: AtomicUsize::new(0),
+            outlier: None,
+            circuit: None,
+            request_timeout_ms: AtomicU64::new(0),
+            limit: None,
+        }
+    }
+
+    /// Replace the backend list. Backends whose URL, tier and `host` are
+    /// unchanged keep their health, ejection and latency state; new ones
+    /// start out healthy, like at startup.
+    pub fn set_backends(&self, configs: &[BackendConfig]) {
+        assert!(!configs.is_empty(), "at least one upstream is required");
+        let current = self.backends.load();
+        let mut backends: Vec<Arc<Backend>> = configs
+            .iter()
+            .map(|config| {
+                let fresh = Backend::new(config);
+                current
+                    .iter()
+                    .find(|b| b.url == fresh.url && b.tier == fresh.tier && b.host == fresh.host)
+                    .cloned()
+                    .unwrap_or_else(|| Arc::new(fresh))
+            })
+            .collect();
+        backends.sort_by_key(|b| b.tier);
+        self.backends.store(Arc::new(backends));
+        self.update_active_tier();
+    }
+
+    /// Track live request outcomes and eject outliers (see `run_outlier_detection`).
+    pub fn with_outlier_detection(mut self, config: OutlierDetectionConfig) -> Self {
+        self.outlier = Some(config);
+        self
+    }
+
+    /// Count request outcomes and stop calling upstream while most of them fail.
+    pub fn with_circuit_breaker(mut self, config: CircuitBreakerConfig) -> Self {
+        self.circuit = Some(CircuitBreaker::new(config));
+        self
+    }
+
+    pub fn circuit(&self) -> Option<&CircuitBreaker> {
+        self.circuit.as_ref()
+    }
+
+    /// Give up on a proxied request (every attempt included) after `timeout`.
+    pub fn with_request_timeout(self, timeout: Duration) -> Self {
+        self.set_request_timeout(Some(timeout));
+        self
+    }
+
+    /// Change the request timeout for requests that start from now on.
+    pub fn set_request_timeout(&self, timeout: Option<Duration>) {
+        let ms = timeout.map_or(0, |t| t.as_millis().max(1) as u64);
+        self.request_timeout_ms.store(ms, Ordering::Relaxed);
+    }
+
+    pub fn request_timeout(&self) -> Option<Duration> {
+        match self.request_timeout_ms.load(Ordering::Relaxed) {
+            0 => None,
+            ms => Some(Duration::from_millis(ms)),
+        }
+    }
+
+    /// Allow at most `max` upstream requests in flight at once. Others wait up
+    /// to `queue_timeout` for a slot.
+    pub fn with_concurrency_limit(mut self, max: usize, queue_timeout: Duration) -> Self {
+        self.limit = Some(ConcurrencyLimit {
+            permits: Semaphore::new(max),
+            max,
+            queue_timeout,
+            queued: AtomicUsize::new(0),
+        });
+        self
+    }
+
+    /// Wait for a slot to send one upstream attempt, giving up after the
+    /// queue timeout or at `deadline`, whichever comes first. `None` means
+    /// no slot freed up in time. Always succeeds at once without a limit.
+    pub async fn acquire(&self, deadline: Option<Instant>) -> Option<UpstreamSlot<'_>> {
+        let Some(limit) = &self.limit else {
+            return Some(UpstreamSlot { permit: None });
+        };
+        let permit = match limit.permits.try_acquire() {
+            Ok(permit) => permit,
+            Err(_) => {
+                let mut wait = limit.queue_timeout;
+                if let Some(deadline) = deadline {
+                    wait = wait.min(deadline.saturating_duration_since(Instant::now()));
+                }
+                let queued = Queued::enter(limit);
+                let acquired = tokio::time::timeout(wait, limit.permits.acquire()).await;
+                drop(queued);
+                match acquired {
+                    Ok(Ok(permit)) => permit,
+                    // Timed out (the semaphore itself is never closed)
+                    _ => {
+                        ::metrics::counter!("colander_upstream_queue_timeouts_total").increment(1);
+                        return None;
+                    }
+                }
+            }
+        };
+        limit.publish();
+        Some(UpstreamSlot {
+            permit: Some((permit, limit)),
+        })
+    }
+
+    /// Pick the backend for the next request.
+    pub fn select(&self) -> Arc<Backend> {
+        let backends = self.backends.load();
+        let tier = self.active_tier.load(Ordering::Relaxed);
+        let healthy = || {
+            backends
+                .iter()
+                .filter(move |b| b.tier == tier && b.is_healthy())
+        };
+
+        let mut candidates: Vec<&Arc<Backend>> = healthy().filter(|b| !b.is_ejected()).collect();
+        if candidates.is_empty() {
+            // Everything in the tier is ejected: ejection is advisory, so
+            // fall back to the healthy set rather than fail over
+            candidates = healthy().collect();
+        }
+        if candidates.is_empty() {
+            // Fail open: nothing healthy, so try the preferred tier anyway
+            return Arc::clone(&backends[0]);
+        }
+        let n = self.next.fetch_add(1, Ordering::Relaxed);
+        Arc::clone(candidates[n % candidates.len()])
+    }
+
+    /// Tier currently receiving traffic.
+    pub fn active_tier(&self) -> u32 {
+        self.active_tier.load(Ordering::Relaxed)
+    }
+
+    pub fn status(&self) -> Vec<BackendStatus> {
+        self.backends
+            .load()
+            .iter()
+            .map(|b| {
+                let traffic = b.traffic.lock();
+                BackendStatus {
+                    url: b.url.clone(),
+                    tier: b.tier,
+                    healthy: b.is_healthy(),
+                    ejected: b.is_ejected(),
+                    ejections: traffic.ejections,
+                    latency_ewma_us: traffic.ewma_latency_us as u64,
+                }
+            })
+            .collect()
+    }
+
+    /// Record the outcome of one proxied request. `ok` is false for connect
+    /// errors and 5xx responses. Feeds the circuit breaker and outlier
+    /// detection, when enabled.
+    pub fn record(&self, backend: &Backend, latency: Duration, ok: bool) {
+        if let Some(circuit) = &self.circuit {
+            circuit.record(ok);
+        }
+        let Some(config) = &self.outlier else {
+            return;
+        };
+
+        let eject = {
+            let mut traffic = backend.traffic.lock();
+            let sample = latency.as_micros() as f64;
+            traffic.ewma_latency_us = if traffic.ewma_latency_us == 0.0 {
+                sample
+            } else {
+                EWMA_ALPHA * sample + (1.0 - EWMA_ALPHA) * traffic.ewma_latency_us
+            };
+            traffic.requests += 1;
+            if ok {
+                traffic.consecutive_errors = 0;
+            } else {
+                traffic.errors += 1;
+                traffic.consecutive_errors += 1;
+            }
+            traffic.consecutive_errors >= config.consecutive_errors
+        };
+
+        if eject && !backend.is_ejected() {
+            self.try_eject(backend, "consecutive_errors", config);
+        }
+    }
+
+    /// One outlier-detection pass: restore backends whose ejection expired,
+    /// judge each tier's error rates and latencies, then reset the windows.
+    fn analyse(&self, config: &OutlierDetectionConfig) {
+        let backends = self.backends.load();
+        let now = Instant::now();
+        for backend in backends.iter() {
+            let mut traffic = backend.traffic.lock();
+            if backend.is_ejected() && traffic.ejected_until.is_some_and(|t| t <= now) {
+                traffic.ejected_until = None;
+                traffic.consecutive_errors = 0;
+                backend.ejected.store(false, Ordering::Relaxed);
+                tracing::info!(upstream = %backend.url, "upstream outlier restored");
+                ::metrics::gauge!("colander_upstream_ejected", "upstream" => backend.url.clone())
+                    .set(0.0);
+            }
+        }
+
+        let mut tiers: Vec<u32> = backends.iter().map(|b| b.tier).collect();
+        tiers.dedup();
+        for tier in tiers {
+            // Only backends with enough traffic this window are comparable
+            let observed: Vec<(&Backend, f64, f64)> = backends
+                .iter()
+                .filter(|b| b.tier == tier && !b.is_ejected())
+                .filter_map(|b| {
+                    let traffic = b.traffic.lock();
+                    (traffic.requests >= config.min_requests).then(|| {
+                        let error_rate = traffic.errors as f64 / traffic.requests as f64;
+                        (&**b, error_rate, traffic.ewma_latency_us)
+                    })
+                })
+                .collect();
+
+            let median_latency = median(observed.iter().map(|(_, _, l)| *l).collect());
+            for (backend, error_rate, latency) in observed {
+                if error_rate > config.error_rate_threshold {
+                    self.try_eject(backend, "error_rate", config);
+                } else if median_latency > 0.0 && latency > median_latency * config.latency_factor {
+                    self.try_eject(backend, "latency", config);
+                }
+            }
+        }
+
+        for backend in backends.iter() {
+            let mut traffic = backend.traffic.lock();
+            traffic.requests = 0;
+            traffic.errors = 0;
+        }
+    }
+
+    /// Eject `backend` unless its tier is already at `max_ejection_percent`.
+    fn try_eject(&self, backend: &Backend, reason: &'static str, config: &OutlierDetectionConfig) {
+        let backends = self.backends.load();
+        let in_tier = backends.iter().filter(|b| b.tier == backend.tier);
+        let total = in_tier.clone().count();
+        let ejected = in_tier.filter(|b| b.is_ejected()).count();
+        if (ejected + 1) * 100 > total * config.max_ejection_percent as usize {
+            tracing::debug!(upstream = %backend.url, reason, "outlier ejection skipped: tier at max ejection");
+            return;
+        }
+
+        let mut traffic = backend.traffic.lock();
+        traffic.ejections += 1;
+        let duration = Duration::from_millis(config.base_ejection_ms) * traffic.ejections;
+        traffic.ejected_until = Some(Instant::now() + duration);
+        backend.ejected.store(true, Ordering::Relaxed);
+
+        tracing::warn!(
+            upstream = %backend.url,
+            tier = backend.tier,
+            reason,
+            ejection_ms = duration.as_millis() as u64,
+            "upstream outlier ejected"
+        );
+        ::metrics::counter!("colander_upstream_ejections_total", "upstream" => backend.url.clone(), "reason" => reason)
+            .increment(1);
+        ::metrics::gauge!("colander_upstream_ejected", "upstream" => backend.url.clone()).set(1.0);
+    }
+
+    /// Feed one health-check result for `backend` through the
+    /// healthy/unhealthy thresholds, then re-evaluate the active tier.
+    fn report(&self, backend: &Backend, ok: bool, config: &HealthCheckConfig) {
+        if ok {
+            backend.consecutive_failures.store(0, Ordering::Relaxed);
+            let successes = backend
+                .consecutive_successes
+                .fetch_add(1, Ordering::Relaxed)
+                + 1;
+            if !backend.is_healthy() && successes >= config.healthy_threshold {
+                backend.healthy.store(true, Ordering::Relaxed);
+                tracing::info!(upstream = %backend.url, tier = backend.tier, "upstream healthy");
+            }
+        } else {
+            backend.consecutive_successes.store(0, Ordering::Relaxed);
+            let failures = backend.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+            if backend.is_healthy() && failures >= config.unhealthy_threshold {
+                backend.healthy.store(false, Ordering::Relaxed);
+                tracing::warn!(upstream = %backend.url, tier = backend.tier, "upstream unhealthy");
+            }
+        }
+        ::metrics::gauge!("colander_upstream_healthy", "upstream" => backend.url.clone())
+            .set(if backend.is_healthy() { 1.0 } else { 0.0 });
+
+        self.update_active_tier();
+    }
+
+    fn update_active_tier(&self) {
+        let backends = self.backends.load();
+        let best = backends
+            .iter()
+            .find(|b| b.is_healthy())
+            .map(|b| b.tier)
+            .unwrap_or(backends[0].tier);
+
+        let previous = self.active_tier.swap(best, Ordering::Relaxed);
+        if previous != best {
+            if best > previous {
+                tracing::warn!(
+                    from = previous,
+                    to = best,
+                    "upstream failover: switching tier"
+                );
+            } else {
+                tracing::info!(
+                    from = previous,
+                    to = best,
+                    "upstream fail-back: switching tier"
+                );
+            }
+            ::metrics::counter!("colander_upstream_tier_switches_total").increment(1);
+        }
+        ::metrics::gauge!("colander_upstream_active_tier").set(best as f64);
+    }
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    // Lower median, so with two backends the slow one is compared to the fast one
+    values[(values.len() - 1) / 2]
+}
+
+/// Periodically probe every backend and update pool health until shutdown.
+pub async fn run_health_checks(
+    pool: Arc<UpstreamPool>,
+    client: HttpClient,
+    config: HealthCheckConfig,
+    shutdown: CancellationToken,
+) {
+    let mut interval = tokio::time::interval(Duration::from_millis(config.interval_ms));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let timeout = Duration::from_millis(config.timeout_ms);
+
+    tracing::info!(
+        backends = pool.backends.load().len(),
+        interval_ms = config.interval_ms,
+        path = %config.path,
+        "upstream health checks started"
+    );
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => return,
+            _ = interval.tick() => {}
+        }
+
+        // Probe the list as it was when the round started; a reload
+        // meanwhile is picked up next round
+        let backends = pool.backends.load_full();
+        let probes = backends.iter().map(|backend| {
+            let uri = format!("{}{}", backend.url, config.path);
+            let client = client.clone();
+            async move {
+                let mut req = Request::get(&uri);
+                if let Some(host) = &backend.host {
+                    req = req.header(HOST, host);
+                }
+                let req = match req.body(Body::empty()) {
+                    Ok(req) => req,
+                    Err(_) => return false,
+                };
+                match tokio::time::timeout(timeout, client.request(req)).await {
+                    Ok(Ok(resp)) => resp.status().is_success(),
+                    _ => false,
+                }
+            }
+        });
+        let results = futures_util::future::join_all(probes).await;
+
+        for (backend, ok) in backends.iter().zip(results) {
+            pool.report(backend, ok, &config);
+        }
+    }
+}
+
+/// Periodically analyse live traffic and eject/restore outliers until shutdown.
+/// Does nothing unless the pool was built `with_outlier_detection`.
+pub async fn run_outlier_detection(pool: Arc<UpstreamPool>, shutdown: CancellationToken) {
+    let Some(config) = pool.outlier.clone() else {
+        return;
+    };
+    let mut interval = tokio::time::interval(Duration::from_millis(config.interval_ms));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    // The first tick fires immediately; skip it so the first window is full
+    interval.tick().await;
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => return,
+            _ = interval.tick() => pool.analyse(&config),
+        }
+    }
+}
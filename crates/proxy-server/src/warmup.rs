@@ -0,0 +1,267 @@
+use crate::config::WarmupConfig;
+use crate::proxy::{proxy_handler, AppState};
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{Request, StatusCode};
+use colander_cache::traits::InsertSource;
+use futures_util::stream::{self, StreamExt};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How often a running warmup logs its progress.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Outcome of a warmup run.
+#[derive(Debug, Default)]
+pub struct WarmupReport {
+    pub requested: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+}
+
+/// Where the startup warmup stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarmupState {
+    Running,
+    /// Every entry was fetched, successfully or not.
+    Done,
+    /// `timeout_secs` ran out first; the rest were skipped.
+    TimedOut,
+}
+
+/// Point-in-time view of the warmup for `/api/stats`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WarmupStatus {
+    pub state: WarmupState,
+    pub requested: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    /// Time spent so far, or in total once finished.
+    pub elapsed_ms: u64,
+}
+
+/// Progress of the startup warmup, shared with `/api/stats`. Empty until a
+/// warmup starts, so instances without one report nothing.
+#[derive(Debug, Default)]
+pub struct WarmupProgress {
+    inner: Mutex<Option<(WarmupStatus, Instant)>>,
+}
+
+impl WarmupProgress {
+    pub fn status(&self) -> Option<WarmupStatus> {
+        let inner = self.inner.lock();
+        let (status, started) = inner.as_ref()?;
+        let mut status = status.clone();
+        if status.state == WarmupState::Running {
+            status.elapsed_ms = started.elapsed().as_millis() as u64;
+        }
+        Some(status)
+    }
+
+    fn start(&self, requested: usize) {
+        let status = WarmupStatus {
+            state: WarmupState::Running,
+            requested,
+            succeeded: 0,
+            failed: 0,
+            elapsed_ms: 0,
+        };
+        *self.inner.lock() = Some((status, Instant::now()));
+    }
+
+    fn record(&self, ok: bool) {
+        if let Some((status, _)) = self.inner.lock().as_mut() {
+            if ok {
+                status.succeeded += 1;
+            } else {
+                status.failed += 1;
+            }
+        }
+    }
+
+    fn finish(&self, state: WarmupState) {
+        if let Some((status, started)) = self.inner.lock().as_mut() {
+            status.state = state;
+            status.elapsed_ms = started.elapsed().as_millis() as u64;
+        }
+    }
+}
+
+/// Extract request paths to prefetch from a warm file.
+///
+/// Each line may be any of:
+/// - a path: `/api/items/1`
+/// - an absolute URL: `http://host/api/items/1` (host is ignored)
+/// - a cache key as shown by `/api/hotkeys`: `GET:/api/items/1`
+/// - a common/combined access-log line: `... "GET /api/items/1 HTTP/1.1" ...`
+///
+/// Blank lines, `#` comments, non-GET log lines and duplicates are skipped.
+pub fn parse_warm_file(contents: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    contents
+        .lines()
+        .filter_map(parse_line)
+        .filter(|path| seen.insert(path.clone()))
+        .collect()
+}
+
+fn parse_line(line: &str) -> Option<String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    // Access log: the request line is the first quoted field
+    if let Some(start) = line.find('"') {
+        let rest = &line[start + 1..];
+        let request = &rest[..rest.find('"')?];
+        let mut parts = request.split_whitespace();
+        return match (parts.next(), parts.next()) {
+            (Some("GET"), Some(target)) => parse_target(target),
+            _ => None,
+        };
+    }
+
+    parse_target(line.strip_prefix("GET:").unwrap_or(line))
+}
+
+fn parse_target(target: &str) -> Option<String> {
+    if target.starts_with('/') {
+        return Some(target.to_string());
+    }
+    let uri: axum::http::Uri = target.parse().ok()?;
+    uri.scheme()?;
+    Some(
+        uri.path_and_query()
+            .map_or("/", |pq| pq.as_str())
+            .to_string(),
+    )
+}
+
+/// Read `path` and prefetch every entry (see `warm`).
+pub async fn warm_from_file(
+    state: Arc<AppState>,
+    path: &Path,
+    concurrency: usize,
+    deadline: Duration,
+) -> std::io::Result<WarmupReport> {
+    let contents = tokio::fs::read_to_string(path).await?;
+    tracing::info!(file = %path.display(), "warming cache from file");
+    Ok(warm(state, parse_warm_file(&contents), concurrency, deadline).await)
+}
+
+/// Prefetch every path through the proxy handler, at most `concurrency` at
+/// a time, giving up on whatever is left after `deadline`. Progress is
+/// logged every few seconds and kept in `AppState::warmup`.
+///
+/// Going through `proxy_handler` means warm fills obey exactly the same
+/// cacheability and TTL rules as live traffic; they're tagged
+/// `InsertSource::Warmup` so they can be told apart.
+pub async fn warm(
+    state: Arc<AppState>,
+    paths: Vec<String>,
+    concurrency: usize,
+    deadline: Duration,
+) -> WarmupReport {
+    let mut report = WarmupReport {
+        requested: paths.len(),
+        ..Default::default()
+    };
+
+    tracing::info!(entries = paths.len(), concurrency, "cache warmup started");
+    let start = Instant::now();
+    state.warmup.start(paths.len());
+
+    let fetches = stream::iter(paths)
+        .map(|path| {
+            let state = Arc::clone(&state);
+            async move {
+                let req = match Request::get(path.as_str())
+                    .extension(InsertSource::Warmup)
+                    .body(Body::empty())
+                {
+                    Ok(req) => req,
+                    Err(_) => return false,
+                };
+                let resp = proxy_handler(State(state), req).await;
+                resp.status() == StatusCode::OK
+            }
+        })
+        .buffer_unordered(concurrency.max(1));
+    let mut fetches = std::pin::pin!(fetches);
+
+    let mut progress = tokio::time::interval_at(
+        tokio::time::Instant::now() + PROGRESS_INTERVAL,
+        PROGRESS_INTERVAL,
+    );
+    let timed_out = tokio::time::timeout(deadline, async {
+        loop {
+            tokio::select! {
+                fetched = fetches.next() => match fetched {
+                    Some(ok) => {
+                        if ok {
+                            report.succeeded += 1;
+                        } else {
+                            report.failed += 1;
+                        }
+                        state.warmup.record(ok);
+                    }
+                    None => break,
+                },
+                _ = progress.tick() => tracing::info!(
+                    done = report.succeeded + report.failed,
+                    requested = report.requested,
+                    failed = report.failed,
+                    "cache warmup progress"
+                ),
+            }
+        }
+    })
+    .await
+    .is_err();
+    state.warmup.finish(if timed_out {
+        WarmupState::TimedOut
+    } else {
+        WarmupState::Done
+    });
+
+    tracing::info!(
+        requested = report.requested,
+        succeeded = report.succeeded,
+        failed = report.failed,
+        timed_out,
+        elapsed_ms = start.elapsed().as_millis() as u64,
+        "cache warmup finished"
+    );
+    report
+}
+
+/// Run the configured warmup (if any), then mark the instance ready.
+///
+/// `urls` and the lines of `file` are warmed together, duplicates once. An
+/// unreadable file is logged and skipped, leaving just `urls`.
+pub async fn warm_then_ready(state: Arc<AppState>, config: WarmupConfig) {
+    let mut seeds = config.urls.join("\n");
+    if let Some(file) = &config.file {
+        match tokio::fs::read_to_string(file).await {
+            Ok(contents) => {
+                seeds.push('\n');
+                seeds.push_str(&contents);
+            }
+            Err(e) => tracing::error!(error = %e, file = %file, "cache warmup file unreadable"),
+        }
+    }
+    let paths = parse_warm_file(&seeds);
+    if !paths.is_empty() {
+        let deadline = Duration::from_secs(config.timeout_secs);
+        warm(Arc::clone(&state), paths, config.concurrency, deadline).await;
+    }
+    state.ready.store(true, Ordering::Relaxed);
+    tracing::info!("instance ready");
+}
@@ -0,0 +1,121 @@
+use crate::bypass;
+use crate::cache_layer::CacheLayer;
+use crate::config::{BypassMode, Config, WriteMode};
+use crate::http_key::HttpKey;
+use crate::proxy::{cache_key, proxy_handler, AppState};
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{Method, Request, Uri};
+use colander_cache::traits::InsertSource;
+use std::sync::Arc;
+
+/// Drop the cached `GET`s a successful write through the proxy made stale,
+/// per `[cache.writes]`: the written URI's own entry, the writer's
+/// `per_user` copy of it, and every `invalidate` pattern. Exact keys are
+/// those of the writer's `tenant`; prefix patterns reach every tenant.
+///
+/// Removals go through `CacheLayer::remove` and `remove_prefix`, so with
+/// `[cluster] broadcast_invalidations` peers drop their copies too. Prefix
+/// patterns walk the whole cache, so they run on a blocking thread after
+/// the response has gone. In `refresh` mode the written URI is then
+/// refetched in the background, through the proxy handler like a warmup
+/// fill, tagged `InsertSource::Refresh`.
+pub fn after_write(
+    state: &Arc<AppState>,
+    cache: &Arc<CacheLayer>,
+    config: &Config,
+    method: &Method,
+    uri: &Uri,
+    tenant: Option<&str>,
+    credentials: Option<&str>,
+) {
+    let writes = &config.cache.writes;
+    if writes.mode == WriteMode::Off {
+        return;
+    }
+    let keys = HttpKey::new(&config.cache.key).with_tenant(tenant);
+    let key = cache_key(&keys, &Method::GET, uri);
+
+    let mut removed = usize::from(cache.remove(&key));
+    if let Some(credentials) =
+        credentials.filter(|_| config.cache.bypass.mode == BypassMode::PerUser)
+    {
+        removed += usize::from(cache.remove(&bypass::per_user_key(&key, credentials)));
+    }
+    let mut prefixes = Vec::new();
+    for pattern in &writes.invalidate {
+        // Patterns were checked by `Config::validate`
+        let Ok(target) = expand(pattern, uri.path()) else {
+            continue;
+        };
+        match target.strip_suffix('*') {
+            Some(prefix) => prefixes.push(keys.prefix(&Method::GET, uri, prefix)),
+            None => {
+                if let Some(uri) = with_path(uri, &target) {
+                    removed += usize::from(cache.remove(&cache_key(&keys, &Method::GET, &uri)));
+                }
+            }
+        }
+    }
+    tracing::debug!(key = %key, method = %method, removed, "write invalidated cached reads");
+    ::metrics::counter!("colander_write_invalidations_total").increment(removed as u64);
+
+    let scan = (!prefixes.is_empty()).then(|| {
+        let cache = Arc::clone(cache);
+        tokio::task::spawn_blocking(move || {
+            let removed: usize = prefixes.iter().map(|p| cache.remove_prefix(p)).sum();
+            tracing::debug!(
+                ?prefixes,
+                removed,
+                "write invalidated cached reads by prefix"
+            );
+            ::metrics::counter!("colander_write_invalidations_total").increment(removed as u64);
+        })
+    });
+
+    if writes.mode == WriteMode::Refresh && method != Method::DELETE {
+        let state = Arc::clone(state);
+        let uri = uri.clone();
+        let tenant = tenant.map(|t| (config.cache.tenant.header.clone(), t.to_string()));
+        tokio::spawn(async move {
+            // So the scan can't drop the refreshed entry
+            if let Some(scan) = scan {
+                let _ = scan.await;
+            }
+            let mut req = Request::get(uri).extension(InsertSource::Refresh);
+            if let Some((header, tenant)) = tenant {
+                req = req.header(header, tenant);
+            }
+            let Ok(req) = req.body(Body::empty()) else {
+                return;
+            };
+            let status = proxy_handler(State(state), req).await.status();
+            tracing::debug!(key = %key, status = status.as_u16(), "refreshed after write");
+        });
+    }
+}
+
+/// Fill in a `[cache.writes] invalidate` pattern for a write to `path`:
+/// `{path}` is the path itself, `{parent}` the path without its last
+/// segment (`/api/items/7` → `/api/items`).
+pub fn expand(pattern: &str, path: &str) -> Result<String, &'static str> {
+    let parent = match path.trim_end_matches('/').rsplit_once('/') {
+        Some(("", _)) | None => "/",
+        Some((parent, _)) => parent,
+    };
+    let target = pattern.replace("{path}", path).replace("{parent}", parent);
+    if target.contains(['{', '}']) {
+        return Err("has an unknown placeholder, use {path} or {parent}");
+    }
+    if !target.starts_with('/') {
+        return Err("must start with / or a placeholder");
+    }
+    Ok(target)
+}
+
+/// `uri` with its path and query replaced by `target`.
+fn with_path(uri: &Uri, target: &str) -> Option<Uri> {
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(target.parse().ok()?);
+    Uri::from_parts(parts).ok()
+}
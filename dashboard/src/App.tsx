@@ -4,6 +4,7 @@ import { ThroughputChart } from "./components/ThroughputChart";
 import { StatsCards } from "./components/StatsCards";
 import { AlphaSlider } from "./components/AlphaSlider";
 import { ModeToggle } from "./components/ModeToggle";
+import { PolicySelect } from "./components/PolicySelect";
 import { ConnectionStatus } from "./components/ConnectionStatus";
 
 function App() {
@@ -27,6 +28,7 @@ function App() {
               connected={connected}
               uptime={latest?.uptime_seconds ?? null}
             />
+            <PolicySelect latest={latest} />
             <ModeToggle latest={latest} />
           </div>
         </div>
@@ -8,24 +8,47 @@ import {
   Legend,
   ResponsiveContainer,
 } from "recharts";
-import type { MetricsSnapshot } from "../types";
+import type { MetricsSnapshot, PolicyMetrics } from "../types";
 
 interface Props {
   history: MetricsSnapshot[];
 }
 
+// Primary first, then one color per comparison policy
+const COLORS = ["#22d3ee", "#f472b6", "#a3e635", "#fbbf24", "#c084fc"];
+
+const percent = (rate: number) => +(rate * 100).toFixed(2);
+
+// The lifetime rate flattens out after warm-up; the 1m window tracks changes
+const recentRate = (m: PolicyMetrics) => m.windows?.["1m"] ?? m.hit_rate;
+
 export function HitRateChart({ history }: Props) {
-  const data = history.map((s, i) => ({
-    idx: i,
-    time: Math.floor(s.uptime_seconds),
-    sieve: +(s.primary.hit_rate * 100).toFixed(2),
-    lru: s.comparison ? +(s.comparison.hit_rate * 100).toFixed(2) : null,
-  }));
+  const data = history.map((s, i) => {
+    const point: Record<string, number | null> = {
+      idx: i,
+      time: Math.floor(s.uptime_seconds),
+      primary: percent(recentRate(s.primary)),
+    };
+    (s.comparisons ?? []).forEach((c, j) => {
+      point[`comparison${j}`] = percent(recentRate(c));
+    });
+    return point;
+  });
+  const latest = history[history.length - 1];
+  const series = latest
+    ? [
+        { key: "primary", name: latest.primary.name },
+        ...(latest.comparisons ?? []).map((c, j) => ({
+          key: `comparison${j}`,
+          name: c.name,
+        })),
+      ]
+    : [];
 
   return (
     <div className="rounded-xl bg-slate-800/50 p-4 border border-slate-700/50">
       <h2 className="text-sm font-medium text-slate-400 mb-3">
-        Hit Rate (%)
+        Hit Rate, last minute (%)
       </h2>
       <ResponsiveContainer width="100%" height={300}>
         <LineChart data={data}>
@@ -59,24 +82,18 @@ export function HitRateChart({ history }: Props) {
             }
           />
           <Legend />
-          <Line
-            type="monotone"
-            dataKey="sieve"
-            name="SIEVE"
-            stroke="#22d3ee"
-            strokeWidth={2}
-            dot={false}
-            isAnimationActive={false}
-          />
-          <Line
-            type="monotone"
-            dataKey="lru"
-            name="LRU"
-            stroke="#f472b6"
-            strokeWidth={2}
-            dot={false}
-            isAnimationActive={false}
-          />
+          {series.map(({ key, name }, i) => (
+            <Line
+              key={key}
+              type="monotone"
+              dataKey={key}
+              name={name}
+              stroke={COLORS[i % COLORS.length]}
+              strokeWidth={2}
+              dot={false}
+              isAnimationActive={false}
+            />
+          ))}
         </LineChart>
       </ResponsiveContainer>
     </div>
@@ -0,0 +1,60 @@
+import type { MetricsSnapshot } from "../types";
+
+interface Props {
+  latest: MetricsSnapshot | null;
+}
+
+const POLICIES = ["sieve", "lru", "fifo"];
+
+// Replay this many hot keys into the new cache so a swap doesn't start cold
+const WARM_KEYS = 1000;
+
+export function PolicySelect({ latest }: Props) {
+  const primary = latest?.primary.name.toLowerCase() || "sieve";
+  const comparison = latest?.comparison?.name.toLowerCase() || "none";
+
+  const update = async (body: { primary?: string; comparison?: string }) => {
+    try {
+      await fetch("/api/policy", {
+        method: "POST",
+        headers: { "Content-Type": "application/json" },
+        body: JSON.stringify({ ...body, warm_keys: WARM_KEYS }),
+      });
+    } catch {
+      // ignore
+    }
+  };
+
+  const selectClass =
+    "bg-slate-800 text-slate-200 border border-slate-700 rounded-lg px-2 py-2 text-sm";
+
+  return (
+    <div className="flex items-center gap-2 text-xs text-slate-400">
+      <select
+        value={primary}
+        onChange={(e) => update({ primary: e.target.value })}
+        className={selectClass}
+        title="Primary policy"
+      >
+        {POLICIES.map((p) => (
+          <option key={p} value={p}>
+            {p.toUpperCase()}
+          </option>
+        ))}
+      </select>
+      <span>vs</span>
+      <select
+        value={comparison}
+        onChange={(e) => update({ comparison: e.target.value })}
+        className={selectClass}
+        title="Comparison policy"
+      >
+        {[...POLICIES, "none"].map((p) => (
+          <option key={p} value={p}>
+            {p === "none" ? "None" : p.toUpperCase()}
+          </option>
+        ))}
+      </select>
+    </div>
+  );
+}
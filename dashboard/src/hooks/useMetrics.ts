@@ -17,7 +17,25 @@ export function useMetrics() {
     const ws = new WebSocket(`${protocol}//${window.location.host}/ws/metrics`);
     wsRef.current = ws;
 
-    ws.onopen = () => setConnected(true);
+    ws.onopen = () => {
+      setConnected(true);
+      // Back-fill what happened before we (re)connected
+      fetch("/api/history?window=300")
+        .then((res) => res.json())
+        .then(({ snapshots }: { snapshots: MetricsSnapshot[] }) =>
+          setHistory((prev) => {
+            const byTime = new Map<number, MetricsSnapshot>();
+            for (const s of [...snapshots, ...prev]) byTime.set(s.timestamp_ms, s);
+            const merged = [...byTime.values()].sort(
+              (a, b) => a.timestamp_ms - b.timestamp_ms,
+            );
+            return merged.slice(-MAX_HISTORY);
+          }),
+        )
+        .catch(() => {
+          // history is optional; live snapshots still arrive
+        });
+    };
 
     ws.onmessage = (event) => {
       try {
@@ -1,19 +1,64 @@
+/** Hit rates over the trailing 10 seconds, 1 minute and 5 minutes. */
+export interface WindowedHitRates {
+  "10s": number;
+  "1m": number;
+  "5m": number;
+}
+
+/** Lifetimes of evicted entries. */
+export interface EvictionMetrics {
+  mean_age_ms: number;
+  median_age_ms: number;
+  /** Share of evicted entries that were never hit. */
+  one_hit_wonder_ratio: number;
+}
+
+/** Work SIEVE's hand does per eviction, as in the SIEVE paper. */
+export interface ScanMetrics {
+  mean_scan_length: number;
+  visited_clears_per_eviction: number;
+  hand_wraps: number;
+}
+
 export interface PolicyMetrics {
   name: string;
+  /** Since startup. */
   hit_rate: number;
+  windows: WindowedHitRates;
+  eviction: EvictionMetrics;
+  /** SIEVE only. */
+  scan?: ScanMetrics;
   hits: number;
   misses: number;
   evictions: number;
   size: number;
   capacity: number;
+  /** Approximate heap footprint of the cached entries. */
+  memory_bytes: number;
+}
+
+export interface LatencyPercentiles {
+  count: number;
+  p50_us: number;
+  p90_us: number;
+  p99_us: number;
+}
+
+export interface LatencyMetrics {
+  hit: LatencyPercentiles;
+  miss: LatencyPercentiles;
+  upstream: LatencyPercentiles;
 }
 
 export interface MetricsSnapshot {
   timestamp_ms: number;
   window_ms: number;
   primary: PolicyMetrics;
+  /** First of `comparisons`. */
   comparison: PolicyMetrics | null;
+  comparisons: PolicyMetrics[];
   throughput_rps: number;
   uptime_seconds: number;
   mode: string;
+  latency: LatencyMetrics;
 }